@@ -1,4 +1,7 @@
-use rorm_sql::imr::Field;
+use clap::ValueEnum;
+use rorm_sql::imr::{
+    Annotation, DbType, Dialect, Field, ForeignKey, Identity, ModelAnnotation, StorageParameter,
+};
 use serde::{Deserialize, Serialize};
 
 /**
@@ -30,11 +33,341 @@ pub struct Migration {
     /// Migration this migration depends on
     pub dependency: String,
 
+    /// Migrations of other migration directories this migration depends on,
+    /// in the form namespace/id
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub external_dependencies: Vec<String>,
+
+    /// Leaves of other branches this merge migration joins, their branches are applied
+    /// in this order before the branch of the dependency
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub merges: Vec<String>,
+
     /// List of migrations this migration replaces
     pub replaces: Vec<String>,
 
+    /// Whether the migration is applied in a single transaction
+    #[serde(default = "default_atomic")]
+    #[serde(skip_serializing_if = "is_atomic")]
+    pub atomic: bool,
+
+    /// Time in milliseconds the statements of the migration wait for a lock, before the migration fails
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock_timeout_ms: Option<u64>,
+
+    /// How the names of the models were converted to the names of the tables and columns
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub naming_policy: Option<NamingPolicy>,
+
     /// The operations to execute
     pub operations: Vec<Operation>,
+
+    /// Condition on the data, which is checked before the migration is applied
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precondition: Option<Precondition>,
+
+    /// Author, creation time and description of the migration, not covered by any hash
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<AuditInfo>,
+}
+
+/**
+A condition on the data of the database, the `[Migration.Precondition]` section of a migration file.
+
+It is evaluated in the transaction of the migration, right before its operations.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Precondition {
+    /// Query returning a single boolean, e.g. `SELECT COUNT(*) = 0 FROM user WHERE email IS NULL`
+    pub sql: String,
+
+    /// What happens if the query returns false
+    #[serde(default)]
+    #[serde(skip_serializing_if = "PreconditionFailure::is_default")]
+    pub on_failure: PreconditionFailure,
+}
+
+/**
+How a migration whose precondition doesn't hold is treated
+*/
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreconditionFailure {
+    /// The migrate run fails
+    #[default]
+    Error,
+    /// The migration is recorded as skipped without applying its operations
+    Skip,
+}
+
+impl PreconditionFailure {
+    fn is_default(&self) -> bool {
+        *self == PreconditionFailure::Error
+    }
+}
+
+/**
+How make-migrations converts the names of models and fields to the names of tables and columns.
+
+Migrations without a recorded policy took the names as they are, see [NamingPolicy::Preserve].
+*/
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum NamingPolicy {
+    /// Words are lowercase and separated by underscores, e.g. `UserProfile` becomes `user_profile`
+    #[default]
+    #[clap(name = "snake_case")]
+    SnakeCase,
+    /// The names are used as they are
+    #[clap(name = "preserve")]
+    Preserve,
+    /// The names are lowercase without separators, e.g. `UserProfile` becomes `userprofile`
+    #[clap(name = "lowercase")]
+    Lowercase,
+}
+
+impl NamingPolicy {
+    /**
+    Converts the name of a model or field to the name of its table or column.
+
+    `name`: [&str]: The name in the models file.
+    */
+    pub fn convert(&self, name: &str) -> String {
+        match self {
+            NamingPolicy::Preserve => name.to_string(),
+            NamingPolicy::Lowercase => name.to_lowercase(),
+            NamingPolicy::SnakeCase => {
+                let chars: Vec<char> = name.chars().collect();
+                let mut converted = String::with_capacity(name.len());
+                for (index, c) in chars.iter().enumerate() {
+                    if c.is_uppercase() && index > 0 {
+                        // A word starts after a lowercase letter or digit and, within
+                        // an acronym like HTTPRequest, before the last uppercase letter
+                        let previous = chars[index - 1];
+                        let next_lowercase = chars.get(index + 1).is_some_and(|x| x.is_lowercase());
+                        if (previous.is_lowercase()
+                            || previous.is_ascii_digit()
+                            || (previous.is_uppercase() && next_lowercase))
+                            && !converted.ends_with('_')
+                        {
+                            converted.push('_');
+                        }
+                    }
+                    converted.extend(c.to_lowercase());
+                }
+                converted
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for NamingPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            NamingPolicy::SnakeCase => "snake_case",
+            NamingPolicy::Preserve => "preserve",
+            NamingPolicy::Lowercase => "lowercase",
+        })
+    }
+}
+
+/**
+The audit trail of a migration, the `[Migration.Meta]` section of its file.
+
+Editing it doesn't change the migration, so all hashes ignore it.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct AuditInfo {
+    /// The author of the migration, e.g. `Jane Doe <jane@example.com>`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+
+    /// The time the migration was created at in UTC, e.g. `2024-05-01T12:00:00Z`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+
+    /// A free-form description of the migration
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/**
+The presentation of a migration file, whose operations are skipped while reading
+*/
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct MigrationHeaderFile {
+    /// The metadata of the migration of the migration file
+    pub migration: MigrationHeader,
+}
+
+/**
+Representation of the metadata of a migration.
+
+The operations are only counted and not deserialized,
+so operations unknown to this version don't fail reading the metadata.
+*/
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct MigrationHeader {
+    /// Hash of the migration
+    pub hash: String,
+
+    /// Marks the migration initial state
+    pub initial: bool,
+
+    /// ID of the migration, derived from filename
+    #[serde(skip)]
+    pub id: String,
+
+    /// Migration this migration depends on
+    pub dependency: String,
+
+    /// Migrations of other migration directories this migration depends on,
+    /// in the form namespace/id
+    #[serde(default)]
+    pub external_dependencies: Vec<String>,
+
+    /// Leaves of other branches this merge migration joins
+    #[serde(default)]
+    pub merges: Vec<String>,
+
+    /// List of migrations this migration replaces
+    pub replaces: Vec<String>,
+
+    /// Whether the migration is applied in a single transaction
+    #[serde(default = "default_atomic")]
+    pub atomic: bool,
+
+    /// How the names of the models were converted to the names of the tables and columns
+    #[serde(default)]
+    pub naming_policy: Option<NamingPolicy>,
+
+    /// Number of operations of the migration
+    #[serde(rename = "Operations")]
+    #[serde(default)]
+    #[serde(deserialize_with = "count_operations")]
+    pub operation_count: usize,
+}
+
+/**
+The metadata shared by [Migration] and [MigrationHeader],
+used by checks which don't need the operations of a migration.
+*/
+pub trait MigrationMeta {
+    /// ID of the migration, derived from filename
+    fn id(&self) -> &str;
+    /// Migration this migration depends on
+    fn dependency(&self) -> &str;
+    /// Leaves of other branches this merge migration joins
+    fn merges(&self) -> &[String];
+    /// Marks the migration initial state
+    fn initial(&self) -> bool;
+    /// List of migrations this migration replaces
+    fn replaces(&self) -> &[String];
+    /// Hash of the migration
+    fn hash(&self) -> &str;
+    /// How the names of the models were converted, `None` if the migration doesn't record it
+    fn naming_policy(&self) -> Option<NamingPolicy>;
+}
+
+macro_rules! impl_migration_meta {
+    ($type:ty) => {
+        impl MigrationMeta for $type {
+            fn id(&self) -> &str {
+                self.id.as_str()
+            }
+            fn dependency(&self) -> &str {
+                self.dependency.as_str()
+            }
+            fn merges(&self) -> &[String] {
+                &self.merges
+            }
+            fn initial(&self) -> bool {
+                self.initial
+            }
+            fn replaces(&self) -> &[String] {
+                &self.replaces
+            }
+            fn hash(&self) -> &str {
+                self.hash.as_str()
+            }
+            fn naming_policy(&self) -> Option<NamingPolicy> {
+                self.naming_policy
+            }
+        }
+    };
+}
+
+impl_migration_meta!(Migration);
+impl_migration_meta!(MigrationHeader);
+
+impl<T: MigrationMeta> MigrationMeta for &T {
+    fn id(&self) -> &str {
+        (*self).id()
+    }
+    fn dependency(&self) -> &str {
+        (*self).dependency()
+    }
+    fn merges(&self) -> &[String] {
+        (*self).merges()
+    }
+    fn initial(&self) -> bool {
+        (*self).initial()
+    }
+    fn replaces(&self) -> &[String] {
+        (*self).replaces()
+    }
+    fn hash(&self) -> &str {
+        (*self).hash()
+    }
+    fn naming_policy(&self) -> Option<NamingPolicy> {
+        (*self).naming_policy()
+    }
+}
+
+/**
+Counts the elements of a list without deserializing them.
+*/
+fn count_operations<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    struct CountVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for CountVisitor {
+        type Value = usize;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "a list of operations")
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<usize, A::Error> {
+            let mut count = 0;
+            while seq.next_element::<serde::de::IgnoredAny>()?.is_some() {
+                count += 1;
+            }
+            Ok(count)
+        }
+    }
+
+    deserializer.deserialize_seq(CountVisitor)
+}
+
+fn default_atomic() -> bool {
+    true
+}
+
+fn is_atomic(atomic: &bool) -> bool {
+    *atomic
 }
 
 /**
@@ -49,6 +382,10 @@ pub enum Operation {
         name: String,
         /// List of fields associated to the model
         fields: Vec<Field>,
+        /// List of annotations of the model
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        annotations: Vec<ModelAnnotation>,
     },
 
     #[serde(rename_all = "PascalCase")]
@@ -57,12 +394,21 @@ pub enum Operation {
         old: String,
         /// New name of the model
         new: String,
+        /// Whether the auto-named constraints and indexes of the table are renamed to the new prefix,
+        /// only supported by postgres
+        #[serde(default)]
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        rename_constraints: bool,
     },
 
     #[serde(rename_all = "PascalCase")]
     DeleteModel {
         /// Name of the model
         name: String,
+        /// Whether objects depending on the table, e.g. foreign keys referencing it, are dropped as well
+        #[serde(default)]
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        cascade: bool,
     },
 
     #[serde(rename_all = "PascalCase")]
@@ -85,11 +431,550 @@ pub enum Operation {
         new: String,
     },
 
+    #[serde(rename_all = "PascalCase")]
+    RenameFields {
+        /// Name of the table the columns live in
+        table_name: String,
+
+        /// Pairs of old and new names, all old names refer to the columns before the operation
+        renames: Vec<(String, String)>,
+    },
+
     #[serde(rename_all = "PascalCase")]
     DeleteField {
         /// Name of the model
         model: String,
         /// Name of the field to delete
         name: String,
+        /// Whether objects depending on the column, e.g. foreign keys referencing it, are dropped as well
+        #[serde(default)]
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        cascade: bool,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    AlterField {
+        /// Name of the model
+        model: String,
+        /// How the type of the field changes, only widening changes are applied without --allow-unsafe
+        change: TypeChange,
+        /// The new definition of the field, its name is left unchanged
+        field: Field,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    CreateIndex {
+        /// Name of the model the index is created on
+        model: String,
+        /// Name of the index
+        name: String,
+        /// Columns of the index
+        columns: Vec<String>,
+        /// Whether the index is unique
+        #[serde(default)]
+        unique: bool,
+        /// Whether the index is built without locking out writes.
+        /// Only supported by postgres and requires a migration which is not atomic.
+        #[serde(default)]
+        concurrently: bool,
+        /// Kind of the index
+        #[serde(default)]
+        #[serde(skip_serializing_if = "IndexKind::is_default")]
+        kind: IndexKind,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    DropIndex {
+        /// Name of the model the index is defined on
+        model: String,
+        /// Name of the index
+        name: String,
+        /// Columns of the index in index order, used to identify its declaration
+        columns: Vec<String>,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    RenameIndex {
+        /// Name of the model the index is defined on
+        model: String,
+        /// Old name of the index
+        old: String,
+        /// New name of the index
+        new: String,
+        /// Columns of the index.
+        /// Required on dialects which recreate the index instead of renaming it.
+        #[serde(default)]
+        columns: Vec<String>,
+        /// Whether the index is unique
+        #[serde(default)]
+        unique: bool,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    RenameConstraint {
+        /// Name of the model the constraint is defined on
+        model: String,
+        /// Old name of the constraint
+        old: String,
+        /// New name of the constraint
+        new: String,
+        /// Definition of the constraint without its name, e.g. `UNIQUE (a, b)`.
+        /// Required on dialects which recreate the constraint instead of renaming it.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        definition: Option<String>,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    CreateForeignKey {
+        /// Name of the model the foreign key is defined on
+        model: String,
+        /// Name of the constraint
+        name: String,
+        /// Name of the referencing field
+        column: String,
+        /// The referenced field
+        foreign_key: ForeignKey,
+        /// Don't check the existing rows, only supported by postgres.
+        /// Check them later using [Operation::ValidateConstraint].
+        #[serde(default)]
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        not_valid: bool,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    CreateCheck {
+        /// Name of the model the check constraint is defined on
+        model: String,
+        /// Name of the constraint
+        name: String,
+        /// The SQL expression every row has to satisfy, e.g. `age >= 0`
+        expression: String,
+        /// Don't check the existing rows, only supported by postgres.
+        /// Check them later using [Operation::ValidateConstraint].
+        #[serde(default)]
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        not_valid: bool,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    ValidateConstraint {
+        /// Name of the model the constraint is defined on
+        model: String,
+        /// Name of the constraint
+        name: String,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    DropConstraint {
+        /// Name of the model the constraint is defined on
+        model: String,
+        /// Name of the constraint
+        name: String,
+        /// Name of the referencing field, if the constraint is a foreign key
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        column: Option<String>,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    SetSequence {
+        /// Name of the model
+        model: String,
+        /// Name of the auto increment field
+        column: String,
+        /// Value the next inserted row receives
+        value: i64,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    AddIdentity {
+        /// Name of the model
+        model: String,
+        /// Name of the integer field to turn into an identity column
+        column: String,
+        /// How the values of the column are generated
+        identity: Identity,
+        /// Whether the field is auto_increment, whose serial sequence is replaced.
+        /// Only used by postgres.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        from_serial: bool,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    SetIdentity {
+        /// Name of the model
+        model: String,
+        /// Name of the identity field
+        column: String,
+        /// How the values of the column are generated
+        identity: Identity,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    DropIdentity {
+        /// Name of the model
+        model: String,
+        /// Name of the identity field
+        column: String,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    Truncate {
+        /// Name of the model
+        model: String,
+        /// Whether tables referencing the model are truncated as well.
+        /// Only supported by postgres.
+        #[serde(default)]
+        cascade: bool,
+        /// Whether auto increment fields are reset
+        #[serde(default)]
+        restart_identity: bool,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    SetTablespace {
+        /// Name of the model
+        model: String,
+        /// New tablespace of the model, the default tablespace is used if not set
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tablespace: Option<String>,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    SetStorageParameters {
+        /// Name of the model
+        model: String,
+        /// Storage parameters to set
+        #[serde(default)]
+        set: Vec<StorageParameter>,
+        /// Names of the storage parameters to reset to their defaults
+        #[serde(default)]
+        reset: Vec<String>,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    SetCharset {
+        /// Name of the model
+        model: String,
+        /// New character set of the model, the configured one is used if not set.
+        /// Only supported by mysql.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        charset: Option<String>,
+        /// New collation of the model, the default of the character set is used if not set
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        collation: Option<String>,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    CreatePartition {
+        /// Name of the partitioned model
+        model: String,
+        /// Name of the partition
+        name: String,
+        /// The rows of the partition, e.g. `FOR VALUES FROM ('2024-01-01') TO ('2024-02-01')` or `DEFAULT`
+        bound: String,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    AttachPartition {
+        /// Name of the partitioned model
+        model: String,
+        /// Name of the existing table to attach
+        partition: String,
+        /// The rows of the partition, e.g. `FOR VALUES IN ('eu')` or `DEFAULT`
+        bound: String,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    DetachPartition {
+        /// Name of the partitioned model
+        model: String,
+        /// Name of the partition, which is kept as standalone table
+        partition: String,
     },
+
+    #[serde(rename_all = "PascalCase")]
+    SetInherit {
+        /// Name of the model
+        model: String,
+        /// Name of the parent model, whose columns the model already has.
+        /// Only supported by postgres.
+        parent: String,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    NoInherit {
+        /// Name of the model
+        model: String,
+        /// Name of the parent model, the inherited columns are kept
+        parent: String,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    CreateUnique {
+        /// Name of the model
+        model: String,
+        /// Name of the unique index
+        name: String,
+        /// Columns whose combination of values is unique
+        columns: Vec<String>,
+        /// Null values collide with each other, only supported by postgres 15+
+        #[serde(default)]
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        nulls_not_distinct: bool,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    DropUnique {
+        /// Name of the model
+        model: String,
+        /// Name of the unique index
+        name: String,
+        /// Columns of the unique index, used to identify its declaration
+        columns: Vec<String>,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    AlterEnum {
+        /// Name of the model
+        model: String,
+        /// The field of type choices with its values after the change
+        field: Field,
+        /// The change of the values
+        action: EnumAction,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    CreateExtension {
+        /// Name of the extension, e.g. `uuid-ossp`
+        name: String,
+        /// Whether an already installed extension is skipped
+        #[serde(default)]
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        if_not_exists: bool,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    DropExtension {
+        /// Name of the extension
+        name: String,
+        /// Whether a missing extension is skipped
+        #[serde(default)]
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        if_exists: bool,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    RunSql {
+        /// The SQL to execute, e.g. to backfill data
+        sql: String,
+        /// Seconds after which the SQL is aborted and its migration rolled back
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        timeout_seconds: Option<u64>,
+    },
+
+    #[serde(rename_all = "PascalCase")]
+    Only {
+        /// Dialects the operation is applied on
+        dialects: Vec<Dialect>,
+        /// The operation to apply
+        operation: Box<Operation>,
+    },
+}
+
+/**
+The kind of an index
+*/
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum IndexKind {
+    /// A regular index
+    #[default]
+    Index,
+    /// A full-text search index, not supported by sqlite
+    FullText,
+}
+
+impl IndexKind {
+    fn is_default(&self) -> bool {
+        *self == IndexKind::Index
+    }
+}
+
+/**
+The change of the values of an enum, i.e. a field of type choices
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "Type")]
+pub enum EnumAction {
+    /// Adds a value
+    #[serde(rename_all = "PascalCase")]
+    Add {
+        /// The new value
+        value: String,
+    },
+    /// Renames a value, the rows using the old value are updated
+    #[serde(rename_all = "PascalCase")]
+    Rename {
+        /// Old value
+        old: String,
+        /// New value
+        new: String,
+    },
+}
+
+/**
+The classification of a changed column type
+*/
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TypeChange {
+    /// Every value of the old type can be represented by the new type
+    Widening,
+    /// The new type is of the same kind, but values of the old type may not fit
+    Narrowing,
+    /// The values have to be converted to the new type
+    Incompatible,
+}
+
+impl TypeChange {
+    /**
+    Classifies the change of the type of a field.
+
+    Integers widen to larger integers of the same signedness and unsigned integers
+    to larger signed ones, float widens to double and varchar, citext, char and varbinary widen
+    to a larger max_length. The reverse of those and other changes between integers are narrowing,
+    any other change of the type, including the length of a binary_fixed, is incompatible.
+
+    `None` is returned if neither the type nor its max_length changed.
+
+    `old`: [&Field]: State of the field resulting from the existing migrations.
+    `new`: [&Field]: Current state of the field.
+    */
+    pub fn classify(old: &Field, new: &Field) -> Option<TypeChange> {
+        // Signedness and number of bits of integer types
+        let integer = |db_type: &DbType| match db_type {
+            DbType::Int8 => Some((true, 8)),
+            DbType::Int16 => Some((true, 16)),
+            DbType::Int32 => Some((true, 32)),
+            DbType::Int64 => Some((true, 64)),
+            DbType::UInt8 => Some((false, 8)),
+            DbType::UInt16 => Some((false, 16)),
+            DbType::UInt32 => Some((false, 32)),
+            DbType::UInt64 => Some((false, 64)),
+            _ => None,
+        };
+        let max_length = |field: &Field| {
+            field.annotations.iter().find_map(|x| match x {
+                Annotation::MaxLength(l) => Some(*l),
+                _ => None,
+            })
+        };
+        let widening = |widening: bool| {
+            Some(if widening {
+                TypeChange::Widening
+            } else {
+                TypeChange::Narrowing
+            })
+        };
+
+        match (&old.db_type, &new.db_type) {
+            (DbType::Float, DbType::Double) => Some(TypeChange::Widening),
+            (DbType::Double, DbType::Float) => Some(TypeChange::Narrowing),
+            (DbType::VarChar, DbType::VarChar)
+            | (DbType::Citext, DbType::Citext)
+            | (DbType::Char, DbType::Char)
+            | (DbType::VarBinary, DbType::VarBinary) => {
+                match (max_length(old), max_length(new)) {
+                    (Some(old), Some(new)) if old != new => widening(new > old),
+                    // A missing max_length is unbounded
+                    (Some(_), None) => Some(TypeChange::Widening),
+                    (None, Some(_)) => Some(TypeChange::Narrowing),
+                    _ => None,
+                }
+            }
+            // Fixed length binaries are padded or truncated, which changes their values
+            (DbType::Binary, DbType::Binary) if max_length(old) != max_length(new) => {
+                Some(TypeChange::Incompatible)
+            }
+            (old_type, new_type) => match (integer(old_type), integer(new_type)) {
+                (Some(old), Some(new)) if old != new => {
+                    widening(new.1 > old.1 && (old.0 == new.0 || new.0))
+                }
+                (Some(_), Some(_)) => None,
+                _ if std::mem::discriminant(old_type) == std::mem::discriminant(new_type) => None,
+                _ => Some(TypeChange::Incompatible),
+            },
+        }
+    }
+
+    /**
+    Checks, if the change can lose data or fail on existing rows.
+    */
+    pub fn is_unsafe(&self) -> bool {
+        *self != TypeChange::Widening
+    }
+}
+
+impl std::fmt::Display for TypeChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TypeChange::Widening => "widening",
+                TypeChange::Narrowing => "narrowing",
+                TypeChange::Incompatible => "incompatible",
+            }
+        )
+    }
+}
+
+impl Operation {
+    /**
+    Returns the type of the operation as written to the migration files, e.g. `CreateModel`.
+    The type of the inner operation is returned for operations restricted to some dialects.
+    */
+    pub fn type_name(&self) -> String {
+        serde_json::to_value(self.inner())
+            .ok()
+            .and_then(|x| x.get("Type")?.as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+
+    /**
+    Returns the operation without its dialect filters.
+    */
+    pub fn inner(&self) -> &Operation {
+        match self {
+            Operation::Only { operation, .. } => operation.inner(),
+            operation => operation,
+        }
+    }
+
+    /**
+    Returns the operation to apply on the dialect, if it isn't filtered out.
+
+    `dialect`: [Dialect]: The dialect the operation is applied on.
+    */
+    pub fn for_dialect(&self, dialect: Dialect) -> Option<&Operation> {
+        match self {
+            Operation::Only {
+                dialects,
+                operation,
+            } => {
+                if dialects.contains(&dialect) {
+                    operation.for_dialect(dialect)
+                } else {
+                    None
+                }
+            }
+            operation => Some(operation),
+        }
+    }
 }
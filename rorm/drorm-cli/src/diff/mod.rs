@@ -0,0 +1,180 @@
+use anyhow::anyhow;
+
+use crate::declaration::{Migration, Operation};
+use crate::make_migrations::operation_to_diff_line;
+use crate::migrate::sql_builder::sorted_operations;
+use crate::utils::fold::fold_operations;
+use crate::utils::migrations::get_existing_migrations;
+use crate::utils::output::{info, paint_diff_line};
+
+/**
+Options for showing the changes between two migrations
+*/
+pub struct DiffOptions {
+    /// Directory, migrations exist in
+    pub migration_dir: String,
+
+    /// If set, the subdirectories of the migration directory are searched as well
+    pub recursive: bool,
+
+    /// Id of the migration to start after
+    pub from: String,
+
+    /// Id of the last migration to include
+    pub to: String,
+}
+
+/**
+Helper function to find a migration by its id or its number, e.g. `0003`.
+
+`migrations`: [&\[Migration\]]: The migrations to search.
+`id`: [&str]: The id or number of the migration.
+*/
+fn find_migration(migrations: &[Migration], id: &str) -> anyhow::Result<usize> {
+    if let Some(index) = migrations.iter().position(|x| x.id == id) {
+        return Ok(index);
+    }
+
+    let prefix = format!("{}_", id);
+    let matching: Vec<usize> = migrations
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.id.starts_with(prefix.as_str()))
+        .map(|(index, _)| index)
+        .collect();
+    match matching[..] {
+        [index] => Ok(index),
+        [] => Err(anyhow!("Migration {} does not exist", id)),
+        _ => Err(anyhow!(
+            "Migration {} is ambiguous, it matches {}",
+            id,
+            matching
+                .iter()
+                .map(|x| migrations[*x].id.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        )),
+    }
+}
+
+/**
+Returns the folded operations of the migrations after `from` up to and including `to`.
+
+`migrations`: [&\[Migration\]]: The migrations ordered by their dependency chain.
+`from`: [&str]: Id or number of the migration to start after.
+`to`: [&str]: Id or number of the last migration to include.
+*/
+pub fn diff_operations(
+    migrations: &[Migration],
+    from: &str,
+    to: &str,
+) -> anyhow::Result<Vec<Operation>> {
+    let start = find_migration(migrations, from)?;
+    let end = find_migration(migrations, to)?;
+    if start > end {
+        return Err(anyhow!(
+            "Migration {} is not an ancestor of migration {}",
+            migrations[start].id,
+            migrations[end].id
+        ));
+    }
+
+    Ok(fold_operations(
+        migrations[start + 1..=end]
+            .iter()
+            .flat_map(sorted_operations)
+            .cloned()
+            .collect(),
+    ))
+}
+
+/**
+Prints the combined changes of a range of migrations.
+*/
+pub fn run_diff(options: DiffOptions) -> anyhow::Result<()> {
+    let migrations = get_existing_migrations(options.migration_dir.as_str(), options.recursive)?;
+    let operations = diff_operations(&migrations, options.from.as_str(), options.to.as_str())?;
+
+    if operations.is_empty() {
+        info!(
+            "No changes between {} and {}",
+            options.from.as_str(),
+            options.to.as_str()
+        );
+    }
+    for operation in &operations {
+        println!("{}", paint_diff_line(&operation_to_diff_line(operation)));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{DbType, Field};
+
+    use crate::declaration::Operation;
+    use crate::diff::diff_operations;
+    use crate::make_migrations::operation_to_diff_line;
+    use crate::utils::test_helpers::migration;
+
+    #[test]
+    fn range() {
+        let migrations = vec![
+            migration(
+                "0001_initial",
+                "",
+                vec![Operation::CreateModel {
+                    name: "user".to_string(),
+                    fields: vec![],
+                    annotations: vec![],
+                }],
+            ),
+            migration(
+                "0002_age",
+                "0001_initial",
+                vec![Operation::CreateField {
+                    model: "user".to_string(),
+                    field: Field {
+                        name: "age".to_string(),
+                        db_type: DbType::Int32,
+                        annotations: vec![],
+                        source_defined_at: None,
+                    },
+                }],
+            ),
+            migration(
+                "0003_drop_age",
+                "0002_age",
+                vec![
+                    Operation::DeleteField {
+                        model: "user".to_string(),
+                        name: "age".to_string(),
+                        cascade: false,
+                    },
+                    Operation::RenameModel {
+                        old: "user".to_string(),
+                        new: "member".to_string(),
+                        rename_constraints: false,
+                    },
+                ],
+            ),
+        ];
+        let lines = |from, to| {
+            diff_operations(&migrations, from, to).map(|x| {
+                x.iter()
+                    .map(operation_to_diff_line)
+                    .collect::<Vec<String>>()
+            })
+        };
+
+        assert_eq!(
+            lines("0001", "0003_drop_age").unwrap(),
+            vec!["~ model user -> member"]
+        );
+        assert_eq!(lines("0001", "0002").unwrap(), vec!["+ user.age"]);
+        assert!(lines("0002", "0002").unwrap().is_empty());
+        assert!(lines("0003", "0001").is_err());
+        assert!(lines("0004", "0001").is_err());
+    }
+}
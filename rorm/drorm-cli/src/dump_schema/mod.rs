@@ -0,0 +1,178 @@
+use rorm_sql::imr::InternalModelFormat;
+use rorm_sql::DBImpl;
+
+use crate::declaration::{Migration, Operation};
+use crate::make_migrations::split_foreign_keys;
+use crate::migrate::config::DatabaseDriver;
+use crate::migrate::sql_builder::{
+    migration_to_statements, redact_sensitive_defaults, BuildOptions,
+};
+use crate::utils::migrations::{convert_migrations_to_internal_models, get_existing_migrations};
+use crate::utils::output::info;
+
+/**
+Options for printing the schema resulting from the migrations
+*/
+pub struct DumpSchemaOptions {
+    /// Directory, migrations exist in
+    pub migration_dir: String,
+
+    /// If set, the subdirectories of the migration directory are searched as well
+    pub recursive: bool,
+
+    /// Dialect to generate the SQL for
+    pub dialect: DatabaseDriver,
+}
+
+/**
+Returns a migration creating the models from scratch.
+
+The models are ordered by their name and their foreign keys
+are created after all tables, so the result is deterministic.
+
+`models`: [&InternalModelFormat]: The models to create.
+*/
+pub fn schema_migration(models: &InternalModelFormat) -> Migration {
+    let mut sorted: Vec<_> = models.models.iter().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut operations = vec![];
+    let mut foreign_keys = vec![];
+    for model in sorted {
+        let (fields, mut constraints) = split_foreign_keys(model.name.as_str(), &model.fields);
+        operations.push(Operation::CreateModel {
+            name: model.name.clone(),
+            fields,
+            annotations: model.annotations.clone(),
+        });
+        foreign_keys.append(&mut constraints);
+    }
+    operations.append(&mut foreign_keys);
+
+    Migration {
+        hash: "".to_string(),
+        initial: true,
+        id: "schema".to_string(),
+        dependency: "".to_string(),
+        external_dependencies: vec![],
+        merges: vec![],
+        replaces: vec![],
+        atomic: true,
+        operations,
+        precondition: None,
+        lock_timeout_ms: None,
+        naming_policy: None,
+        meta: None,
+    }
+}
+
+/**
+Returns the statements creating the schema resulting from the migrations.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`migrations`: [&\[Migration\]]: The migrations ordered by their dependency chain.
+*/
+pub fn schema_statements(db_impl: DBImpl, migrations: &[Migration]) -> anyhow::Result<Vec<String>> {
+    let migration = schema_migration(&convert_migrations_to_internal_models(migrations)?);
+    Ok(
+        migration_to_statements(db_impl, &migration, &BuildOptions::default())?
+            .into_iter()
+            .map(|x| redact_sensitive_defaults(&migration, x.as_str()))
+            .collect(),
+    )
+}
+
+/**
+Prints the statements creating the schema resulting from the migrations without a database.
+*/
+pub fn run_dump_schema(options: DumpSchemaOptions) -> anyhow::Result<()> {
+    let migrations = get_existing_migrations(options.migration_dir.as_str(), options.recursive)?;
+    let statements = schema_statements(options.dialect.db_impl(), &migrations)?;
+
+    if statements.is_empty() {
+        info!("The migrations don't create any model");
+    }
+    for statement in statements {
+        println!("{}", statement);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{Annotation, DbType, Field, ForeignKey, ReferentialAction};
+    use rorm_sql::DBImpl;
+
+    use crate::declaration::Operation;
+    use crate::dump_schema::schema_statements;
+    use crate::utils::test_helpers::migration;
+
+    fn field(name: &str, annotations: Vec<Annotation>) -> Field {
+        Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations,
+            source_defined_at: None,
+        }
+    }
+
+    #[test]
+    fn resolved_schema() {
+        let migrations = vec![
+            migration(
+                "0001_initial",
+                "",
+                vec![
+                    Operation::CreateModel {
+                        name: "user".to_string(),
+                        fields: vec![field("id", vec![Annotation::PrimaryKey])],
+                        annotations: vec![],
+                    },
+                    Operation::CreateModel {
+                        name: "post".to_string(),
+                        fields: vec![field("id", vec![Annotation::PrimaryKey])],
+                        annotations: vec![],
+                    },
+                ],
+            ),
+            migration(
+                "0002_author",
+                "0001_initial",
+                vec![Operation::CreateField {
+                    model: "post".to_string(),
+                    field: field(
+                        "author",
+                        vec![Annotation::ForeignKey(ForeignKey {
+                            table_name: "user".to_string(),
+                            column_name: "id".to_string(),
+                            deferrable: false,
+                            initially_deferred: false,
+                            on_delete: ReferentialAction::Cascade,
+                            on_update: ReferentialAction::NoAction,
+                        })],
+                    ),
+                }],
+            ),
+            migration(
+                "0003_account",
+                "0002_author",
+                vec![Operation::RenameModel {
+                    old: "user".to_string(),
+                    new: "account".to_string(),
+                    rename_constraints: false,
+                }],
+            ),
+        ];
+
+        // Models are ordered by name, foreign keys follow the tables and the renamed model
+        assert_eq!(
+            schema_statements(DBImpl::Postgres, &migrations).unwrap(),
+            vec![
+                "CREATE TABLE account (id BIGINT PRIMARY KEY);",
+                "CREATE TABLE post (id BIGINT PRIMARY KEY,author BIGINT);",
+                "ALTER TABLE post ADD CONSTRAINT post_author_fkey FOREIGN KEY (author) REFERENCES account (id) ON DELETE CASCADE;",
+            ]
+        );
+    }
+}
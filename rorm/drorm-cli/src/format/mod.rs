@@ -0,0 +1,215 @@
+use std::collections::HashSet;
+use std::fs::{read_to_string, write};
+
+use anyhow::{anyhow, Context};
+
+use crate::utils::migrations::{convert_str_to_migration, migration_file_to_toml, migration_files};
+use crate::utils::output::{info, paint, Style};
+
+/**
+Options for formatting migration files
+*/
+pub struct FormatOptions {
+    /// Directory, migrations exist in
+    pub migration_dir: String,
+
+    /// If set, the subdirectories of the migration directory are searched as well
+    pub recursive: bool,
+
+    /// If set, the files are only checked and nothing is written
+    pub check: bool,
+}
+
+/**
+Formats the content of a migration file canonically, i.e. as make-migrations writes it.
+
+The canonical form is checked to describe the same migration as the content.
+Comments can't be kept by the serializer, so files containing them are refused.
+
+`file_name`: [&str]: Name of the migration file.
+`content`: [&str]: Content of the migration file.
+*/
+pub fn canonical_migration(file_name: &str, content: &str) -> anyhow::Result<String> {
+    let file = convert_str_to_migration(file_name, content)?;
+    let canonical = migration_file_to_toml(&file)?;
+    if canonical == content {
+        return Ok(canonical);
+    }
+
+    // Lines of multi-line strings, e.g. of raw SQL, are kept in the canonical form
+    let kept: HashSet<&str> = canonical.lines().collect();
+    if let Some(comment) = content
+        .lines()
+        .find(|x| x.trim_start().starts_with('#') && !kept.contains(x))
+    {
+        return Err(anyhow!(
+            "{} contains the comment {:?}, which would be lost. Remove it to format the file",
+            file_name,
+            comment.trim()
+        ));
+    }
+
+    let reformatted = convert_str_to_migration(file_name, canonical.as_str())?;
+    let value =
+        |x| toml::Value::try_from(x).with_context(|| "Error while comparing the canonical form");
+    if value(&file)? != value(&reformatted)? {
+        return Err(anyhow!(
+            "The canonical form of {} describes another migration",
+            file_name
+        ));
+    }
+
+    Ok(canonical)
+}
+
+/**
+Formats the migration files canonically or, with `check`, reports the files which aren't.
+
+Every file which isn't formatted canonically is reported, so CI can point out all of them.
+Files which can't be formatted, e.g. as they contain comments, are reported as well.
+In both cases, the run fails.
+
+`options`: [FormatOptions]: The options of the run.
+*/
+pub fn run_format(options: FormatOptions) -> anyhow::Result<()> {
+    let files = migration_files(options.migration_dir.as_str(), options.recursive)?;
+    let mut problems = vec![];
+    let mut unformatted = 0;
+
+    for file in &files {
+        let path = file.path();
+        let file_name = file.file_name().to_string_lossy().to_string();
+        let content = read_to_string(&path)
+            .with_context(|| format!("Error occurred while reading {}", path.display()))?;
+
+        let canonical = match canonical_migration(file_name.as_str(), content.as_str()) {
+            Ok(canonical) => canonical,
+            Err(err) => {
+                problems.push(format!("{} can't be formatted: {:#}", path.display(), err));
+                continue;
+            }
+        };
+        if canonical == content {
+            continue;
+        }
+
+        unformatted += 1;
+        if options.check {
+            problems.push(format!("{} is not formatted canonically", path.display()));
+        } else {
+            write(&path, canonical)
+                .with_context(|| format!("Couldn't write migration file {}", path.display()))?;
+            info!("Formatted {}", path.display());
+        }
+    }
+
+    if problems.is_empty() {
+        match (options.check, unformatted) {
+            (false, 1..) => info!(
+                "Formatted {} of {} migration files in {}",
+                unformatted,
+                files.len(),
+                options.migration_dir
+            ),
+            _ => info!(
+                "All {} migration files in {} are formatted canonically",
+                files.len(),
+                options.migration_dir
+            ),
+        }
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{}", paint(Style::Error, problem));
+    }
+
+    Err(anyhow!(
+        "Found {} migration files in {}, which are not formatted canonically{}",
+        problems.len(),
+        options.migration_dir,
+        match options.check {
+            true => ", run drorm fmt to format them",
+            false => "",
+        }
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{canonical_migration, run_format, FormatOptions};
+    use crate::utils::migrations::read_migrations;
+
+    const HAND_WRITTEN: &str = r#"[Migration]
+Initial = true
+Hash = "123"
+Replaces = []
+Dependency = ""
+
+[[Migration.Operations]]
+Type   = "CreateModel"
+Name   = "user"
+Fields = [{ Name = "id", Type = "int64", Annotations = [{ Type = "primary_key" }] }]
+
+[[Migration.Operations]]
+Type = "RunSql"
+Sql = """
+INSERT INTO user (id) VALUES (1);
+# not a comment of the file
+"""
+"#;
+
+    #[test]
+    fn canonical_form() {
+        let canonical = canonical_migration("0001_initial.toml", HAND_WRITTEN).unwrap();
+        assert_ne!(canonical, HAND_WRITTEN);
+        assert!(canonical.starts_with("[Migration]\nHash = '123'\nInitial = true\n"));
+
+        // The canonical form is stable and describes the same migration
+        assert_eq!(
+            canonical_migration("0001_initial.toml", canonical.as_str()).unwrap(),
+            canonical
+        );
+        assert!(canonical.contains("# not a comment of the file"));
+
+        let commented =
+            HAND_WRITTEN.replace("Initial = true", "# The first migration\nInitial = true");
+        let err = canonical_migration("0001_initial.toml", commented.as_str()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("contains the comment \"# The first migration\""));
+
+        assert!(canonical_migration("0001_initial.toml", "[Migration]").is_err());
+    }
+
+    #[test]
+    fn check_and_format() {
+        let dir = std::env::temp_dir().join(format!("drorm_format_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("0001_initial.toml");
+        std::fs::write(&path, HAND_WRITTEN).unwrap();
+        let options = |check| FormatOptions {
+            migration_dir: dir.to_str().unwrap().to_string(),
+            recursive: false,
+            check,
+        };
+        let migrations = || {
+            let migrations = read_migrations(dir.to_str().unwrap(), false).unwrap();
+            serde_json::to_value(&migrations).unwrap()
+        };
+        let before = migrations();
+
+        // Checking reports the file without changing it
+        assert!(run_format(options(true)).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), HAND_WRITTEN);
+
+        // Formatting only changes the formatting, not the migration
+        run_format(options(false)).unwrap();
+        assert_ne!(std::fs::read_to_string(&path).unwrap(), HAND_WRITTEN);
+        assert_eq!(migrations(), before);
+        run_format(options(true)).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
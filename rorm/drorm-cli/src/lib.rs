@@ -0,0 +1,18 @@
+/*!
+Library interface of the drorm migration tool.
+
+The [migrate::migrator::Migrator] can be used to apply migrations
+from within an application, e.g. with migrations embedded into the binary.
+*/
+
+pub mod declaration;
+pub mod diff;
+pub mod dump_schema;
+pub mod format;
+pub mod make_migrations;
+pub mod merge_migrations;
+pub mod migrate;
+pub mod repair;
+pub mod squash_migrations;
+pub mod utils;
+pub mod validate;
@@ -0,0 +1,747 @@
+use std::fs::write;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use ordered_float::OrderedFloat;
+use rorm_sql::imr::{
+    Annotation, DbType, DefaultValue, Field, ForeignKey, InternalModelFormat, Model,
+    ReferentialAction,
+};
+use rorm_sql::DBImpl;
+use sqlx::any::{AnyPool, AnyRow};
+use sqlx::{query, Row};
+
+use crate::make_migrations::MakeMigrationsOptions;
+use crate::migrate::config::{deserialize_db_conf, DatabaseConfig};
+use crate::migrate::connect;
+use crate::utils::migrations::get_existing_migration_headers;
+use crate::utils::output::info;
+use crate::utils::question::question;
+
+/**
+A column as it is defined in the database.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct IntrospectedColumn {
+    /// Name of the column
+    pub name: String,
+    /// Type of the column as reported by the database
+    pub data_type: String,
+    /// Whether the column allows null
+    pub nullable: bool,
+    /// Default expression of the column as reported by the database
+    pub default: Option<String>,
+    /// Whether the default is an expression instead of a literal
+    pub default_is_expression: bool,
+    /// Maximum length of character columns
+    pub max_length: Option<i64>,
+    /// Whether the column is part of the primary key
+    pub primary_key: bool,
+    /// Whether the column is filled from a sequence
+    pub auto_increment: bool,
+    /// Whether the column has a single column unique constraint
+    pub unique: bool,
+}
+
+/**
+A foreign key constraint as it is defined in the database.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntrospectedForeignKey {
+    /// Name of the referencing column
+    pub column: String,
+    /// The referenced table and column with the actions of the constraint
+    pub foreign_key: ForeignKey,
+}
+
+/**
+Helper method to convert the type of a column to its [DbType].
+
+`db_impl`: [DBImpl]: The database implementation the type originates from.
+`data_type`: [&str]: Type of the column as reported by the database.
+*/
+pub fn db_type_from_sql(db_impl: DBImpl, data_type: &str) -> Option<DbType> {
+    let data_type = data_type.trim().to_lowercase();
+
+    match db_impl {
+        DBImpl::SQLite => {
+            // Declared types are arbitrary, so the affinity rules of sqlite are applied
+            // after checking for the names of well known types
+            let base = data_type.split('(').next().unwrap_or_default().trim();
+            match base {
+                "boolean" | "bool" => Some(DbType::Boolean),
+                "date" => Some(DbType::Date),
+                "datetime" => Some(DbType::Datetime),
+                "timestamp" => Some(DbType::Timestamp),
+                "time" => Some(DbType::Time),
+                "float" => Some(DbType::Float),
+                _ if base.contains("int") => Some(DbType::Int64),
+                _ if base.contains("char") || base.contains("clob") || base.contains("text") => {
+                    Some(DbType::VarChar)
+                }
+                _ if base.contains("blob") || base.is_empty() => Some(DbType::VarBinary),
+                _ if base.contains("real") || base.contains("floa") || base.contains("doub") => {
+                    Some(DbType::Double)
+                }
+                _ => None,
+            }
+        }
+        DBImpl::Postgres => match data_type.as_str() {
+            "character varying" | "text" => Some(DbType::VarChar),
+            "character" => Some(DbType::Char),
+            "bytea" => Some(DbType::VarBinary),
+            "smallint" => Some(DbType::Int16),
+            "integer" => Some(DbType::Int32),
+            "bigint" => Some(DbType::Int64),
+            "real" => Some(DbType::Float),
+            "double precision" => Some(DbType::Double),
+            "boolean" => Some(DbType::Boolean),
+            "date" => Some(DbType::Date),
+            "timestamp without time zone" | "timestamp with time zone" => Some(DbType::Datetime),
+            "time without time zone" | "time with time zone" => Some(DbType::Time),
+            "uuid" => Some(DbType::Uuid),
+            "interval" => Some(DbType::Interval),
+            // Types of extensions are reported by their name
+            "citext" => Some(DbType::Citext),
+            _ => None,
+        },
+        DBImpl::MySQL => {
+            // Booleans are stored as tinyint(1)
+            if data_type == "tinyint(1)" {
+                return Some(DbType::Boolean);
+            }
+
+            let unsigned = data_type.ends_with(" unsigned");
+            let base = data_type.split(['(', ' ']).next().unwrap_or_default();
+            match (base, unsigned) {
+                ("tinyint", false) => Some(DbType::Int8),
+                ("tinyint", true) => Some(DbType::UInt8),
+                ("smallint", false) => Some(DbType::Int16),
+                ("smallint", true) => Some(DbType::UInt16),
+                ("int", false) => Some(DbType::Int32),
+                ("int", true) => Some(DbType::UInt32),
+                ("bigint", false) => Some(DbType::Int64),
+                ("bigint", true) => Some(DbType::UInt64),
+                ("float", _) => Some(DbType::Float),
+                ("double", _) => Some(DbType::Double),
+                ("char", _) => Some(DbType::Char),
+                ("varchar" | "tinytext" | "text" | "mediumtext" | "longtext", _) => {
+                    Some(DbType::VarChar)
+                }
+                ("binary", _) => Some(DbType::Binary),
+                ("varbinary" | "tinyblob" | "blob" | "mediumblob" | "longblob", _) => {
+                    Some(DbType::VarBinary)
+                }
+                ("date", _) => Some(DbType::Date),
+                ("datetime", _) => Some(DbType::Datetime),
+                ("timestamp", _) => Some(DbType::Timestamp),
+                ("time", _) => Some(DbType::Time),
+                ("enum", _) => Some(DbType::Choices),
+                ("set", _) => Some(DbType::Set),
+                _ => None,
+            }
+        }
+    }
+}
+
+/**
+Helper method to parse the choices of a MySQL enum or set type.
+
+`data_type`: [&str]: Type of the column, e.g. `enum('a','b')`.
+*/
+fn parse_choices(data_type: &str) -> Vec<String> {
+    data_type
+        .split_once('(')
+        .and_then(|(_, choices)| choices.strip_suffix(')'))
+        .unwrap_or_default()
+        .split(',')
+        .map(|x| x.trim().trim_matches('\'').replace("''", "'"))
+        .collect()
+}
+
+/**
+Helper method to convert the default expression of a column to a [DefaultValue].
+
+Returns [None] if the expression is not a literal.
+
+`db_impl`: [DBImpl]: The database implementation the expression originates from.
+`db_type`: [&DbType]: Type of the column.
+`default`: [&str]: Default expression as reported by the database.
+*/
+pub fn parse_default(db_impl: DBImpl, db_type: &DbType, default: &str) -> Option<DefaultValue> {
+    let mut default = default.trim();
+
+    // Postgres adds a cast to literals, e.g. 'foo'::character varying
+    if db_impl == DBImpl::Postgres {
+        if let Some((value, _)) = default.rsplit_once("::") {
+            default = value;
+        }
+    }
+
+    let quoted = default.len() >= 2 && default.starts_with('\'') && default.ends_with('\'');
+    let value = if quoted {
+        default[1..default.len() - 1].replace("''", "'")
+    } else {
+        // MySQL reports string defaults without quotes
+        if default.eq_ignore_ascii_case("null") {
+            return None;
+        }
+        default.to_string()
+    };
+
+    match db_type {
+        DbType::Int8
+        | DbType::Int16
+        | DbType::Int32
+        | DbType::Int64
+        | DbType::UInt8
+        | DbType::UInt16
+        | DbType::UInt32
+        | DbType::UInt64 => value.parse().ok().map(DefaultValue::Integer),
+        DbType::Float | DbType::Double => value
+            .parse()
+            .ok()
+            .map(|x| DefaultValue::Float(OrderedFloat(x))),
+        DbType::Boolean => match value.to_lowercase().as_str() {
+            "true" | "t" | "1" => Some(DefaultValue::Boolean(true)),
+            "false" | "f" | "0" => Some(DefaultValue::Boolean(false)),
+            _ => None,
+        },
+        // Intervals are reported as literals by postgres, e.g. '1 day'::interval
+        DbType::Interval if quoted => Some(DefaultValue::String(value)),
+        DbType::Interval => value.parse().ok().map(DefaultValue::Integer),
+        DbType::VarChar | DbType::Char | DbType::Choices | DbType::Set
+            if quoted || db_impl == DBImpl::MySQL =>
+        {
+            Some(DefaultValue::String(value))
+        }
+        _ => None,
+    }
+}
+
+/**
+Helper method to convert an introspected column to a field.
+
+Parts of the column which can't be represented are returned as warnings.
+
+`db_impl`: [DBImpl]: The database implementation the column originates from.
+`table`: [&str]: Name of the table of the column.
+`column`: [&IntrospectedColumn]: The column to convert.
+*/
+pub fn column_to_field(
+    db_impl: DBImpl,
+    table: &str,
+    column: &IntrospectedColumn,
+) -> anyhow::Result<(Field, Vec<String>)> {
+    let db_type = db_type_from_sql(db_impl, column.data_type.as_str()).ok_or_else(|| {
+        anyhow!(
+            "Type {} of column {}.{} is not supported",
+            column.data_type,
+            table,
+            column.name
+        )
+    })?;
+
+    let mut annotations = vec![];
+    let mut warnings = vec![];
+
+    if column.primary_key {
+        annotations.push(Annotation::PrimaryKey);
+    }
+    if column.auto_increment {
+        annotations.push(Annotation::AutoIncrement);
+    }
+    if column.unique && !column.primary_key {
+        annotations.push(Annotation::Unique);
+    }
+    if !column.nullable {
+        annotations.push(Annotation::NotNull);
+    }
+    // Only VARBINARY has a length, the BLOB types don't
+    let varbinary = matches!(db_type, DbType::VarBinary)
+        && column.data_type.to_lowercase().starts_with("varbinary(");
+    if matches!(db_type, DbType::Char | DbType::Binary) || varbinary {
+        if let Some(max_length) = column.max_length {
+            annotations.push(Annotation::MaxLength(max_length as i32));
+        }
+    }
+    if matches!(db_type, DbType::VarChar) {
+        match column.max_length {
+            Some(max_length) => annotations.push(Annotation::MaxLength(max_length as i32)),
+            None => warnings.push(format!(
+                "Column {}.{} has no maximum length, which is required on MySQL",
+                table, column.name
+            )),
+        }
+    }
+    if matches!(db_type, DbType::Choices | DbType::Set) {
+        annotations.push(Annotation::Choices(parse_choices(
+            column.data_type.as_str(),
+        )));
+    }
+
+    // Sequences are represented by the auto increment annotation
+    if let Some(default) = column
+        .default
+        .as_ref()
+        .filter(|d| !column.auto_increment && !d.eq_ignore_ascii_case("null"))
+    {
+        // Expressions are kept as they are, so they are only valid for the introspected dialect
+        match parse_default(db_impl, &db_type, default.as_str())
+            .filter(|_| !column.default_is_expression)
+        {
+            Some(value) => annotations.push(Annotation::DefaultValue(value)),
+            None if matches!(db_type, DbType::Uuid)
+                && default.eq_ignore_ascii_case("gen_random_uuid()") =>
+            {
+                annotations.push(Annotation::DefaultUuid)
+            }
+            None => annotations.push(Annotation::ServerDefault(default.clone())),
+        }
+    }
+
+    Ok((
+        Field {
+            name: column.name.clone(),
+            db_type,
+            annotations,
+            source_defined_at: None,
+        },
+        warnings,
+    ))
+}
+
+/**
+Helper method to retrieve the names of all tables in the database.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`pool`: [&AnyPool]: Pool to introspect.
+*/
+pub(crate) async fn tables(db_impl: DBImpl, pool: &AnyPool) -> anyhow::Result<Vec<String>> {
+    let q = match db_impl {
+        DBImpl::SQLite => "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name;",
+        DBImpl::Postgres => "SELECT table_name::TEXT FROM information_schema.tables WHERE table_schema = current_schema() AND table_type = 'BASE TABLE' ORDER BY table_name;",
+        DBImpl::MySQL => "SELECT CAST(table_name AS CHAR) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_type = 'BASE TABLE' ORDER BY table_name;",
+    };
+
+    query(q)
+        .map(|x: AnyRow| x.get(0))
+        .fetch_all(pool)
+        .await
+        .with_context(|| "Couldn't retrieve the tables of the database")
+}
+
+/**
+Helper method to retrieve the columns of a table.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`pool`: [&AnyPool]: Pool to introspect.
+`table`: [&str]: Name of the table.
+*/
+pub(crate) async fn columns(
+    db_impl: DBImpl,
+    pool: &AnyPool,
+    table: &str,
+) -> anyhow::Result<Vec<IntrospectedColumn>> {
+    let columns = match db_impl {
+        DBImpl::SQLite => {
+            let sql: String =
+                query("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?;")
+                    .bind(table)
+                    .map(|x: AnyRow| x.get(0))
+                    .fetch_one(pool)
+                    .await?;
+            let auto_increment = sql.to_uppercase().contains("AUTOINCREMENT");
+
+            let unique: Vec<String> = query(
+                "SELECT ii.name FROM pragma_index_list(?) AS il, pragma_index_info(il.name) AS ii WHERE il.\"unique\" = 1 AND il.origin = 'u' AND (SELECT COUNT(*) FROM pragma_index_info(il.name)) = 1;",
+            )
+            .bind(table)
+            .map(|x: AnyRow| x.get(0))
+            .fetch_all(pool)
+            .await?;
+
+            query("SELECT name, type, \"notnull\", dflt_value, pk FROM pragma_table_info(?) ORDER BY cid;")
+                .bind(table)
+                .map(|x: AnyRow| {
+                    let name: String = x.get(0);
+                    let data_type: String = x.get(1);
+                    let primary_key = x.get::<i64, _>(4) > 0;
+                    // The declared length of character types is not enforced, but kept
+                    let max_length = data_type
+                        .split_once('(')
+                        .and_then(|(_, l)| l.trim_end_matches(')').trim().parse().ok());
+                    IntrospectedColumn {
+                        unique: unique.contains(&name),
+                        name,
+                        max_length,
+                        data_type,
+                        // Primary keys can't be null, but sqlite does not report it
+                        nullable: x.get::<i64, _>(2) == 0 && !primary_key,
+                        default: x.get(3),
+                        default_is_expression: false,
+                        auto_increment: auto_increment && primary_key,
+                        primary_key,
+                    }
+                })
+                .fetch_all(pool)
+                .await?
+        }
+        DBImpl::Postgres => {
+            let constraints: Vec<(String, String)> = query(
+                "SELECT kcu.column_name::TEXT, tc.constraint_type::TEXT FROM information_schema.table_constraints AS tc JOIN information_schema.key_column_usage AS kcu ON tc.constraint_name = kcu.constraint_name AND tc.table_schema = kcu.table_schema WHERE tc.table_schema = current_schema() AND tc.table_name = $1 AND tc.constraint_type IN ('PRIMARY KEY', 'UNIQUE') AND (SELECT COUNT(*) FROM information_schema.key_column_usage AS k WHERE k.constraint_name = tc.constraint_name AND k.table_schema = tc.table_schema) = 1;",
+            )
+            .bind(table)
+            .map(|x: AnyRow| (x.get(0), x.get(1)))
+            .fetch_all(pool)
+            .await?;
+            let has = |name: &str, constraint_type: &str| {
+                constraints
+                    .iter()
+                    .any(|(n, t)| n == name && t == constraint_type)
+            };
+
+            query("SELECT column_name::TEXT, (CASE WHEN data_type = 'USER-DEFINED' THEN udt_name ELSE data_type END)::TEXT, is_nullable::TEXT, column_default::TEXT, character_maximum_length::BIGINT, is_identity::TEXT FROM information_schema.columns WHERE table_schema = current_schema() AND table_name = $1 ORDER BY ordinal_position;")
+                .bind(table)
+                .map(|x: AnyRow| {
+                    let name: String = x.get(0);
+                    let default: Option<String> = x.get(3);
+                    IntrospectedColumn {
+                        data_type: x.get(1),
+                        nullable: x.get::<String, _>(2) == "YES",
+                        max_length: x.get(4),
+                        primary_key: has(name.as_str(), "PRIMARY KEY"),
+                        auto_increment: x.get::<String, _>(5) == "YES"
+                            || default.as_ref().is_some_and(|d| d.starts_with("nextval(")),
+                        unique: has(name.as_str(), "UNIQUE"),
+                        default,
+                        default_is_expression: false,
+                        name,
+                    }
+                })
+                .fetch_all(pool)
+                .await?
+        }
+        DBImpl::MySQL => {
+            query("SELECT CAST(column_name AS CHAR), CAST(column_type AS CHAR), CAST(is_nullable AS CHAR), CAST(column_default AS CHAR), CAST(character_maximum_length AS SIGNED), CAST(column_key AS CHAR), CAST(extra AS CHAR) FROM information_schema.columns WHERE table_schema = DATABASE() AND table_name = ? ORDER BY ordinal_position;")
+                .bind(table)
+                .map(|x: AnyRow| {
+                    let column_key: String = x.get(5);
+                    let extra = x.get::<String, _>(6).to_lowercase();
+                    IntrospectedColumn {
+                        name: x.get(0),
+                        data_type: x.get(1),
+                        nullable: x.get::<String, _>(2) == "YES",
+                        default: x.get(3),
+                        // Generated defaults are expressions like CURRENT_TIMESTAMP
+                        default_is_expression: extra.contains("default_generated"),
+                        max_length: x.get(4),
+                        primary_key: column_key == "PRI",
+                        auto_increment: extra.contains("auto_increment"),
+                        unique: column_key == "UNI",
+                    }
+                })
+                .fetch_all(pool)
+                .await?
+        }
+    };
+
+    Ok(columns)
+}
+
+/**
+Helper method to retrieve the single column foreign keys of a table.
+
+Whether a constraint is deferrable is not retrieved.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`pool`: [&AnyPool]: Pool to introspect.
+`table`: [&str]: Name of the table.
+*/
+pub(crate) async fn foreign_keys(
+    db_impl: DBImpl,
+    pool: &AnyPool,
+    table: &str,
+) -> anyhow::Result<Vec<IntrospectedForeignKey>> {
+    let q = match db_impl {
+        // A reference without column implicitly references the primary key
+        DBImpl::SQLite => "SELECT fk.\"from\", fk.\"table\", COALESCE(fk.\"to\", (SELECT name FROM pragma_table_info(fk.\"table\") WHERE pk = 1)), fk.on_delete, fk.on_update FROM pragma_foreign_key_list(?) AS fk ORDER BY fk.\"from\";",
+        DBImpl::Postgres => "SELECT kcu.column_name::TEXT, ccu.table_name::TEXT, ccu.column_name::TEXT, rc.delete_rule::TEXT, rc.update_rule::TEXT FROM information_schema.referential_constraints AS rc JOIN information_schema.key_column_usage AS kcu ON rc.constraint_name = kcu.constraint_name AND rc.constraint_schema = kcu.constraint_schema JOIN information_schema.constraint_column_usage AS ccu ON rc.constraint_name = ccu.constraint_name AND rc.constraint_schema = ccu.constraint_schema WHERE kcu.table_schema = current_schema() AND kcu.table_name = $1 ORDER BY kcu.column_name;",
+        DBImpl::MySQL => "SELECT CAST(kcu.column_name AS CHAR), CAST(kcu.referenced_table_name AS CHAR), CAST(kcu.referenced_column_name AS CHAR), CAST(rc.delete_rule AS CHAR), CAST(rc.update_rule AS CHAR) FROM information_schema.referential_constraints AS rc JOIN information_schema.key_column_usage AS kcu ON rc.constraint_name = kcu.constraint_name AND rc.constraint_schema = kcu.constraint_schema AND rc.table_name = kcu.table_name WHERE rc.constraint_schema = DATABASE() AND rc.table_name = ? ORDER BY kcu.column_name;",
+    };
+
+    let rows: Vec<(String, String, String, String, String)> = query(q)
+        .bind(table)
+        .map(|x: AnyRow| (x.get(0), x.get(1), x.get(2), x.get(3), x.get(4)))
+        .fetch_all(pool)
+        .await?;
+
+    rows.into_iter()
+        .map(|(column, table_name, column_name, on_delete, on_update)| {
+            let action = |action: &str| {
+                ReferentialAction::from_sql(action).ok_or_else(|| {
+                    anyhow!(
+                        "Unknown action {} of the foreign key on {}.{}",
+                        action,
+                        table,
+                        column
+                    )
+                })
+            };
+            Ok(IntrospectedForeignKey {
+                foreign_key: ForeignKey {
+                    table_name,
+                    column_name,
+                    deferrable: false,
+                    initially_deferred: false,
+                    on_delete: action(on_delete.as_str())?,
+                    on_update: action(on_update.as_str())?,
+                },
+                column,
+            })
+        })
+        .collect()
+}
+
+/**
+Introspects all tables of a database.
+
+The last migration table is excluded.
+Parts of the schema which can't be represented are printed as warnings.
+
+`db_conf`: [&DatabaseConfig]: The database configuration to use.
+*/
+pub async fn introspect_database(db_conf: &DatabaseConfig) -> anyhow::Result<InternalModelFormat> {
+    let db_impl = db_conf.driver.db_impl();
+    let pool = connect(db_conf).await?;
+
+    let mut models = vec![];
+    for table in tables(db_impl, &pool).await? {
+        if table == db_conf.last_migration_table_name {
+            continue;
+        }
+
+        let foreign_keys = foreign_keys(db_impl, &pool, table.as_str())
+            .await
+            .with_context(|| format!("Couldn't retrieve the foreign keys of table {}", table))?;
+        let mut fields = vec![];
+        for column in columns(db_impl, &pool, table.as_str())
+            .await
+            .with_context(|| format!("Couldn't retrieve the columns of table {}", table))?
+        {
+            let (mut field, warnings) = column_to_field(db_impl, table.as_str(), &column)?;
+            for warning in warnings {
+                info!("Warning: {}", warning);
+            }
+            field.annotations.extend(
+                foreign_keys
+                    .iter()
+                    .filter(|x| x.column == field.name)
+                    .map(|x| Annotation::ForeignKey(x.foreign_key.clone())),
+            );
+            fields.push(field);
+        }
+
+        models.push(Model {
+            name: table,
+            fields,
+            annotations: vec![],
+            source_defined_at: None,
+        });
+    }
+
+    Ok(InternalModelFormat { models })
+}
+
+/**
+Writes the models file from the schema of an existing database.
+
+Returns whether the models file has been written.
+
+`options`: [&MakeMigrationsOptions]: Options of the make-migrations tool.
+`database_config`: [&str]: Path to the database configuration file.
+*/
+pub async fn write_models_from_database(
+    options: &MakeMigrationsOptions,
+    database_config: &str,
+) -> anyhow::Result<bool> {
+    let db_conf_path = Path::new(database_config);
+    if !db_conf_path.exists() {
+        return Err(anyhow!(
+            "Couldn't find the database configuration file {}",
+            database_config
+        ));
+    }
+    let db_conf = deserialize_db_conf(db_conf_path)?;
+
+    if Path::new(options.migration_dir.as_str()).exists()
+        && !get_existing_migration_headers(options.migration_dir.as_str(), options.recursive)?
+            .is_empty()
+    {
+        return Err(anyhow!(
+            "Migrations already exist in {}, the initial migration can only be generated into an empty migration directory",
+            options.migration_dir
+        ));
+    }
+
+    let models_file = match options.models_files.as_slice() {
+        [models_file] => models_file,
+        _ => {
+            return Err(anyhow!(
+                "The models of the database are written into a single models file, pass exactly one --models-file"
+            ))
+        }
+    };
+    if Path::new(models_file.as_str()).exists()
+        && !options.non_interactive
+        && !question(
+            format!(
+                "Models file {} already exists, do you want to overwrite it?",
+                models_file
+            )
+            .as_str(),
+        )?
+    {
+        info!("Aborted, no migration has been written.");
+        return Ok(false);
+    }
+
+    let internal_models = introspect_database(&db_conf).await?;
+    info!(
+        "Introspected {} tables from the database",
+        internal_models.models.len()
+    );
+
+    write(
+        models_file.as_str(),
+        serde_json::to_string_pretty(&internal_models)
+            .with_context(|| "Couldn't serialize internal models")?,
+    )
+    .with_context(|| format!("Couldn't write models file {}", models_file))?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{Annotation, DbType, DefaultValue};
+    use rorm_sql::DBImpl;
+
+    use crate::make_migrations::introspect::{
+        column_to_field, db_type_from_sql, parse_default, IntrospectedColumn,
+    };
+
+    #[test]
+    fn types() {
+        assert!(matches!(
+            db_type_from_sql(DBImpl::MySQL, "tinyint(1)"),
+            Some(DbType::Boolean)
+        ));
+        assert!(matches!(
+            db_type_from_sql(DBImpl::MySQL, "bigint unsigned"),
+            Some(DbType::UInt64)
+        ));
+        assert!(matches!(
+            db_type_from_sql(DBImpl::Postgres, "character varying"),
+            Some(DbType::VarChar)
+        ));
+        assert!(matches!(
+            db_type_from_sql(DBImpl::SQLite, "UNSIGNED BIG INT"),
+            Some(DbType::Int64)
+        ));
+        assert!(matches!(
+            db_type_from_sql(DBImpl::Postgres, "character"),
+            Some(DbType::Char)
+        ));
+        assert!(matches!(
+            db_type_from_sql(DBImpl::MySQL, "binary(16)"),
+            Some(DbType::Binary)
+        ));
+        assert!(db_type_from_sql(DBImpl::Postgres, "jsonb").is_none());
+    }
+
+    #[test]
+    fn defaults() {
+        assert!(matches!(
+            parse_default(DBImpl::Postgres, &DbType::VarChar, "'it''s'::character varying"),
+            Some(DefaultValue::String(s)) if s == "it's"
+        ));
+        assert!(matches!(
+            parse_default(DBImpl::Postgres, &DbType::Int32, "'-1'::integer"),
+            Some(DefaultValue::Integer(-1))
+        ));
+        assert!(matches!(
+            parse_default(DBImpl::MySQL, &DbType::VarChar, "foo"),
+            Some(DefaultValue::String(s)) if s == "foo"
+        ));
+        assert!(matches!(
+            parse_default(DBImpl::Postgres, &DbType::Interval, "'1 day'::interval"),
+            Some(DefaultValue::String(s)) if s == "1 day"
+        ));
+        assert!(parse_default(DBImpl::SQLite, &DbType::Datetime, "CURRENT_TIMESTAMP").is_none());
+        assert!(parse_default(DBImpl::Postgres, &DbType::VarChar, "upper('a')").is_none());
+    }
+
+    #[test]
+    fn column() {
+        let (field, warnings) = column_to_field(
+            DBImpl::Postgres,
+            "user",
+            &IntrospectedColumn {
+                name: "id".to_string(),
+                data_type: "bigint".to_string(),
+                default: Some("nextval('user_id_seq'::regclass)".to_string()),
+                primary_key: true,
+                auto_increment: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(warnings.is_empty());
+        assert!(matches!(
+            field.annotations.as_slice(),
+            [
+                Annotation::PrimaryKey,
+                Annotation::AutoIncrement,
+                Annotation::NotNull
+            ]
+        ));
+
+        let (field, warnings) = column_to_field(
+            DBImpl::SQLite,
+            "user",
+            &IntrospectedColumn {
+                name: "created".to_string(),
+                data_type: "TEXT".to_string(),
+                nullable: true,
+                default: Some("CURRENT_TIMESTAMP".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(matches!(
+            field.annotations.as_slice(),
+            [Annotation::ServerDefault(e)] if e == "CURRENT_TIMESTAMP"
+        ));
+
+        // Fixed length columns keep their length
+        let (field, warnings) = column_to_field(
+            DBImpl::MySQL,
+            "user",
+            &IntrospectedColumn {
+                name: "uuid".to_string(),
+                data_type: "binary(16)".to_string(),
+                max_length: Some(16),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(warnings.is_empty());
+        assert!(matches!(field.db_type, DbType::Binary));
+        assert!(matches!(
+            field.annotations.as_slice(),
+            [Annotation::NotNull, Annotation::MaxLength(16)]
+        ));
+    }
+}
@@ -1,60 +1,521 @@
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+pub mod introspect;
+pub mod naming;
+pub mod watch;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
 use std::fs::{create_dir_all, read_to_string};
-use std::hash::{Hash, Hasher};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Context};
+use clap::ValueEnum;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use rorm_sql::imr::{Field, InternalModelFormat, Model};
+use rorm_sql::imr::{
+    Annotation, DbType, Dialect, Field, Identity, InternalModelFormat, Model, ModelAnnotation,
+    Persistence, StorageParameter,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 
-use crate::declaration::{Migration, Operation};
+use crate::declaration::{
+    AuditInfo, EnumAction, IndexKind, Migration, NamingPolicy, Operation, TypeChange,
+};
+use crate::make_migrations::naming::{
+    apply_naming_policy, naming_policy_renames, recorded_naming_policy,
+};
 use crate::utils::migrations::{
     convert_migration_to_file, convert_migrations_to_internal_models, get_existing_migrations,
+    rename_references,
 };
+use crate::utils::output::{info, paint_diff_line};
+use crate::utils::question::question;
+use crate::validate::referential_action_problems;
 
 pub static RE_ALLOWED_NAME: Lazy<Regex> = Lazy::new(|| Regex::new(r#"^[\d\w]+$"#).unwrap());
 
 /// Options struct for [run_make_migrations]
 #[derive(Debug)]
 pub struct MakeMigrationsOptions {
-    /// Path to internal model file
-    pub models_file: String,
+    /// Paths to the internal model files, their models are combined
+    pub models_files: Vec<String>,
     /// Path to the migration directory
     pub migration_dir: String,
+    /// If set, the subdirectories of the migration directory are searched as well
+    pub recursive: bool,
     /// Alternative name of the migration
     pub name: Option<String>,
     /// If set, no questions are gonna be asked
     pub non_interactive: bool,
     /// If set, all warnings are suppressed
     pub warnings_disabled: bool,
+    /// If set, the detected changes are printed before the migration is written
+    pub verbose_diff: bool,
+    /// If set, the summary of the written migration is printed as JSON
+    pub json: bool,
+    /// If set, the operations of the migration are printed instead of writing it
+    pub dry_run: bool,
+    /// If set, long histories are also reported in non-interactive mode
+    pub squash_check: bool,
+    /// Number of migrations after which squashing them is suggested
+    pub squash_threshold: usize,
+    /// If set, deleted models and fields drop the objects depending on them
+    pub cascade: bool,
+    /// If set, renamed models also rename their auto-named constraints and indexes
+    pub rename_constraints: bool,
+    /// If set, `created_at` and `updated_at` fields get their timestamps from the database
+    pub auto_timestamps: bool,
+    /// How the number of the new migration is chosen
+    pub id_scheme: IdScheme,
+    /// Number of digits sequential ids are padded to
+    pub id_width: usize,
+    /// Description stored in the audit trail of the new migration
+    pub description: Option<String>,
+    /// If set, this command is run to regenerate the models files before they are read
+    pub models_command: Option<String>,
+    /// How the names of models and fields are converted, the recorded policy of the
+    /// existing migrations if not set, see [naming::recorded_naming_policy]
+    pub naming_policy: Option<NamingPolicy>,
+}
+
+/**
+How the number at the beginning of a new migration id is chosen.
+
+The order migrations are applied in is determined by their dependencies, not by their ids.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum IdScheme {
+    /// The number of the last migration plus one, e.g. `0003`
+    #[default]
+    #[clap(name = "sequential")]
+    Sequential,
+    /// The current time in UTC, e.g. `20240131093000`.
+    /// Migrations created independently rarely get the same id.
+    #[clap(name = "timestamp")]
+    Timestamp,
+}
+
+/**
+Renames passed to [make_migrations] in addition to the `rename_from` annotations of the models.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct RenameMap {
+    /// New names of models by their old names
+    pub models: BTreeMap<String, String>,
+    /// New names of fields by the new name of their model and their old name
+    pub fields: BTreeMap<(String, String), String>,
+}
+
+impl RenameMap {
+    /**
+    Returns the renames as operations, the models before the fields like [take_renames].
+    */
+    fn operations(&self) -> Vec<Operation> {
+        self.models
+            .iter()
+            .map(|(old, new)| Operation::RenameModel {
+                old: old.clone(),
+                new: new.clone(),
+                rename_constraints: false,
+            })
+            .chain(
+                self.fields
+                    .iter()
+                    .map(|((model, old), new)| Operation::RenameField {
+                        table_name: model.clone(),
+                        old: old.clone(),
+                        new: new.clone(),
+                    }),
+            )
+            .collect()
+    }
+}
+
+/**
+The result of a make-migrations run.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct MakeMigrationsResult {
+    /// Path of the written migration, `None` if nothing changed or the migration was declined
+    pub written: Option<PathBuf>,
+    /// Operations of the generated migration
+    pub operations: Vec<Operation>,
+    /// Warnings about the generated migration, e.g. about lost data
+    pub warnings: Vec<String>,
+}
+
+/**
+Number of the operations of one type in a migration, see [MigrationSummary].
+*/
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct OperationCount {
+    /// Type of the operations as written to the migration files, see [Operation::type_name]
+    #[serde(rename = "Type")]
+    pub operation_type: String,
+    /// Number of the operations
+    pub count: usize,
+}
+
+/**
+Summary of a written migration, printed as single line after writing it:
+`Wrote 0008_add_orders.toml: 1 CreateModel, 2 CreateField, 1 CreateIndex`
+*/
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct MigrationSummary {
+    /// File name of the written migration
+    pub file: String,
+    /// Number of the operations of each type, in the order the types first occur
+    pub operations: Vec<OperationCount>,
+}
+
+impl MigrationSummary {
+    /**
+    Counts the operations of a written migration.
+
+    `path`: [&Path]: Path of the written migration file.
+    `operations`: [&\[Operation\]]: Operations of the migration.
+    */
+    pub fn new(path: &Path, operations: &[Operation]) -> Self {
+        let mut counts: Vec<OperationCount> = vec![];
+        for operation_type in operations.iter().map(Operation::type_name) {
+            match counts
+                .iter_mut()
+                .find(|x| x.operation_type == operation_type)
+            {
+                Some(count) => count.count += 1,
+                None => counts.push(OperationCount {
+                    operation_type,
+                    count: 1,
+                }),
+            }
+        }
+        MigrationSummary {
+            file: path
+                .file_name()
+                .map(|x| x.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            operations: counts,
+        }
+    }
+}
+
+impl Display for MigrationSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Wrote {}: {}",
+            self.file,
+            self.operations
+                .iter()
+                .map(|x| format!("{} {}", x.count, x.operation_type))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+/**
+A migration generated by [generate_migration], which has not been written yet.
+*/
+struct GeneratedMigration {
+    migration: Migration,
+    /// Warnings about the operations, except the ones about lost data
+    warnings: Vec<String>,
+    /// Number of migrations including the new one
+    migration_count: usize,
+}
+
+/**
+Formats seconds since the unix epoch as `YYYYMMDDhhmmss` in UTC.
+
+`seconds`: [u64]: Seconds since the unix epoch.
+*/
+pub fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    // Converts days since the epoch to a date of the proleptic gregorian calendar
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/**
+Helper function to read the author of a new migration.
+
+`DRORM_AUTHOR` is used if set, otherwise `user.name` and `user.email`
+of the git configuration. `None` is returned if neither is set.
+*/
+fn migration_author() -> Option<String> {
+    if let Ok(author) = std::env::var("DRORM_AUTHOR") {
+        if !author.trim().is_empty() {
+            return Some(author.trim().to_string());
+        }
+    }
+
+    let git_config = |key: &str| {
+        std::process::Command::new("git")
+            .args(["config", key])
+            .output()
+            .ok()
+            .filter(|x| x.status.success())
+            .map(|x| String::from_utf8_lossy(&x.stdout).trim().to_string())
+            .filter(|x| !x.is_empty())
+    };
+    match (git_config("user.name"), git_config("user.email")) {
+        (Some(name), Some(email)) => Some(format!("{} <{}>", name, email)),
+        (Some(name), None) => Some(name),
+        (None, Some(email)) => Some(email),
+        (None, None) => None,
+    }
+}
+
+/**
+Creates the audit trail of a new migration.
+
+`description`: [Option<&str>]: Free-form description of the migration.
+`now`: [SystemTime]: The time the migration is created at.
+*/
+pub fn audit_info(description: Option<&str>, now: SystemTime) -> AuditInfo {
+    let t = format_timestamp(now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs());
+    AuditInfo {
+        author: migration_author(),
+        created_at: Some(format!(
+            "{}-{}-{}T{}:{}:{}Z",
+            &t[0..4],
+            &t[4..6],
+            &t[6..8],
+            &t[8..10],
+            &t[10..12],
+            &t[12..14]
+        )),
+        description: description.map(str::to_string),
+    }
+}
+
+/// Timestamp ids have fourteen digits, so sequential ids have to be shorter
+const TIMESTAMP_WIDTH: usize = 14;
+
+/**
+Returns the number at the beginning of the id of the next migration.
+
+With [IdScheme::Sequential] the number is padded to `width` digits,
+it only gets longer if it doesn't fit.
+With [IdScheme::Timestamp] the number is newer than the last migration,
+even if the clock is behind or several migrations are created within a second.
+
+`scheme`: [IdScheme]: How the number is chosen.
+`width`: [usize]: Number of digits sequential numbers are padded to.
+`last`: [Option<&Migration>]: The last migration of the chain, if any exists.
+`now`: [SystemTime]: The current time.
+*/
+pub fn next_migration_number(
+    scheme: IdScheme,
+    width: usize,
+    last: Option<&Migration>,
+    now: SystemTime,
+) -> anyhow::Result<String> {
+    if width == 0 || width >= TIMESTAMP_WIDTH {
+        return Err(anyhow!(
+            "The id width has to be between 1 and {}, ids with {} digits are timestamps",
+            TIMESTAMP_WIDTH - 1,
+            TIMESTAMP_WIDTH
+        ));
+    }
+
+    let last_number = match last {
+        None => None,
+        Some(migration) => {
+            let prefix = migration.id.split('_').next().unwrap_or_default();
+            Some((
+                prefix.len(),
+                prefix.parse::<u64>().with_context(|| {
+                    format!(
+                        "Failed converting name of migration {} to int",
+                        migration.id
+                    )
+                })?,
+            ))
+        }
+    };
+
+    match scheme {
+        IdScheme::Sequential => match last_number {
+            None => Ok(format!("{:0width$}", 1, width = width)),
+            Some((TIMESTAMP_WIDTH, _)) => Err(anyhow!(
+                "The last migration {} has a timestamp id, use --id-scheme timestamp",
+                last.unwrap().id
+            )),
+            Some((_, number)) => Ok(format!("{:0width$}", number + 1, width = width)),
+        },
+        IdScheme::Timestamp => {
+            let seconds = now
+                .duration_since(UNIX_EPOCH)
+                .with_context(|| "The system time is before the unix epoch")?
+                .as_secs();
+            let timestamp = format_timestamp(seconds);
+            match last_number {
+                Some((TIMESTAMP_WIDTH, number)) if timestamp.parse::<u64>()? <= number => {
+                    Ok((number + 1).to_string())
+                }
+                _ => Ok(timestamp),
+            }
+        }
+    }
+}
+
+/**
+Returns a warning, if sequential ids don't fit into the configured width.
+
+Migrations are ordered numerically, but files of ids with
+different widths don't sort by name, e.g. in a file browser.
+
+`existing`: [&\[Migration\]]: The existing migrations.
+`number`: [&str]: The number of the new migration.
+`width`: [usize]: Number of digits sequential numbers are padded to.
+*/
+pub fn id_width_warning(existing: &[Migration], number: &str, width: usize) -> Option<String> {
+    let mut exceeding: Vec<&str> = existing
+        .iter()
+        .map(|x| x.id.split('_').next().unwrap_or_default())
+        .chain(std::iter::once(number))
+        .filter(|x| x.len() > width && x.len() != TIMESTAMP_WIDTH)
+        .collect();
+    exceeding.dedup();
+
+    (!exceeding.is_empty()).then(|| {
+        format!(
+            "The migration numbers {} exceed the id width of {} digits. Their files don't sort by name anymore, increase --id-width.",
+            exceeding.join(", "),
+            width
+        )
+    })
+}
+
+/**
+Returns the number of a migration following the highest number of the migrations,
+e.g. of a squash.
+
+Timestamp ids are followed by a timestamp, sequential ids by the next number
+with the same width.
+
+`migrations`: [&\[Migration\]]: The existing migrations.
+`now`: [SystemTime]: The current time.
+*/
+pub fn following_number(migrations: &[Migration], now: SystemTime) -> anyhow::Result<String> {
+    let number = |x: &&Migration| {
+        let prefix = x.id.split('_').next().unwrap_or_default();
+        (prefix.len(), prefix.parse::<u64>().unwrap_or_default())
+    };
+    let last = migrations.iter().max_by_key(number);
+    let (scheme, width) = match last.map(|x| number(&x).0) {
+        Some(TIMESTAMP_WIDTH) => (IdScheme::Timestamp, 4),
+        Some(width) if width > 0 => (IdScheme::Sequential, width),
+        _ => (IdScheme::Sequential, 4),
+    };
+    next_migration_number(scheme, width, last, now)
+}
+
+/**
+Runs the command regenerating the models files, e.g. `cargo run --features rorm-main`.
+
+The command is run by the shell in the current directory and has to write all models files.
+Its standard output is captured and only printed if it fails, its errors are shown as they occur,
+e.g. the progress of cargo.
+
+`command`: [&str]: The command to run.
+`models_files`: [&\[String\]]: The models files the command has to write.
+*/
+pub fn regenerate_models(command: &str, models_files: &[String]) -> anyhow::Result<()> {
+    let modified = |path: &String| std::fs::metadata(path).and_then(|x| x.modified()).ok();
+    let before: Vec<Option<SystemTime>> = models_files.iter().map(modified).collect();
+
+    info!("Regenerating the models with {}", command);
+    let mut shell = match cfg!(windows) {
+        true => Command::new("cmd"),
+        false => Command::new("sh"),
+    };
+    let output = shell
+        .arg(if cfg!(windows) { "/C" } else { "-c" })
+        .arg(command)
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Couldn't run the models command {}", command))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "The models command {} failed with {}{}",
+            command,
+            output.status,
+            match output.stdout.is_empty() {
+                true => "".to_string(),
+                false => format!(":\n{}", String::from_utf8_lossy(&output.stdout).trim_end()),
+            }
+        ));
+    }
+
+    for (models_file, before) in models_files.iter().zip(before) {
+        match modified(models_file) {
+            Some(after) if before != Some(after) => {}
+            _ => {
+                return Err(anyhow!(
+                    "The models command {} didn't write the models file {}",
+                    command,
+                    models_file
+                ))
+            }
+        }
+    }
+    Ok(())
 }
 
 /**
 Checks the options
 */
 pub fn check_options(options: &MakeMigrationsOptions) -> anyhow::Result<()> {
-    let models_file = Path::new(options.models_file.as_str());
-    if !models_file.exists() || !models_file.is_file() {
-        return Err(anyhow!("Models file does not exist"));
+    if options.models_files.is_empty() {
+        return Err(anyhow!("No models file was given"));
+    }
+    for models_file in &options.models_files {
+        let path = Path::new(models_file.as_str());
+        if !path.exists() || !path.is_file() {
+            return Err(anyhow!("Models file {} does not exist", models_file));
+        }
     }
 
     let migration_dir = Path::new(options.migration_dir.as_str());
     if migration_dir.is_file() {
         return Err(anyhow!("Migration directory cannot be created, is a file"));
     }
-    if !migration_dir.exists() {
+    // Nothing is written in a dry run
+    if !migration_dir.exists() && !options.dry_run {
         create_dir_all(migration_dir).with_context(|| "Couldn't create migration directory")?;
     }
 
-    match &options.name {
-        Some(name) => {
-            if !RE_ALLOWED_NAME.is_match(name.as_str()) {
-                return Err(anyhow!("Custom migration name is not allowed"));
-            }
+    if let Some(name) = &options.name {
+        if !RE_ALLOWED_NAME.is_match(name.as_str()) {
+            return Err(anyhow!("Custom migration name is not allowed"));
         }
-        None => {}
     }
     Ok(())
 }
@@ -74,202 +535,4114 @@ pub fn get_internal_models(models_file: &str) -> anyhow::Result<InternalModelFor
 }
 
 /**
-Runs the make-migrations tool
+Combines the internal models of several files.
+
+The models keep the order of the files, so a single file is returned unchanged.
+Model names have to be unique across all files.
+
+`models`: [Vec<(&str, InternalModelFormat)>]: Pairs of the path of a models file and its models.
 */
-pub fn run_make_migrations(options: MakeMigrationsOptions) -> anyhow::Result<()> {
-    check_options(&options).with_context(|| "Error while checking options")?;
+pub fn merge_internal_models(
+    models: Vec<(&str, InternalModelFormat)>,
+) -> anyhow::Result<InternalModelFormat> {
+    let mut sources: HashMap<String, &str> = HashMap::new();
+    let mut merged = InternalModelFormat { models: vec![] };
+    for (models_file, internal_models) in models {
+        for model in internal_models.models {
+            if let Some(other) = sources.insert(model.name.clone(), models_file) {
+                return Err(anyhow!(
+                    "Model {} is defined in {} and {}, model names have to be unique across all models files",
+                    model.name,
+                    other,
+                    models_file
+                ));
+            }
+            merged.models.push(model);
+        }
+    }
+    Ok(merged)
+}
 
-    let internal_models = get_internal_models(&options.models_file.as_str())
-        .with_context(|| "Couldn't retrieve internal model files.")?;
+/**
+A helper function to retrieve the combined internal models of several files.
 
-    let existing_migrations = get_existing_migrations(&options.migration_dir.as_str())
-        .with_context(|| "An error occurred while deserializing migrations")?;
+`models_files`: [&\[String\]]: The paths to the models files.
+*/
+pub fn get_merged_internal_models(models_files: &[String]) -> anyhow::Result<InternalModelFormat> {
+    let mut models = vec![];
+    for models_file in models_files {
+        models.push((
+            models_file.as_str(),
+            get_internal_models(models_file.as_str())
+                .with_context(|| format!("Couldn't read models file {}", models_file))?,
+        ));
+    }
+    merge_internal_models(models)
+}
 
-    let mut hasher = DefaultHasher::new();
-    internal_models.hash(&mut hasher);
-    let h = hasher.finish();
+/// Version of the hashes of [models_hash], which prefixes them, increased on changes of the hash
+pub const MODELS_HASH_VERSION: &str = "v2";
 
-    if existing_migrations.len() != 0 {
-        let last_migration = &existing_migrations[existing_migrations.len() - 1];
+/**
+Checks whether a hash was calculated by a version of drorm before [MODELS_HASH_VERSION].
 
-        // If hash matches with the one of the current models, exiting
-        if (&last_migration).hash == (&h).to_string() {
-            println!("No changes - nothing to do.");
-            return Ok(());
-        }
+These hashes are numbers calculated from the memory layout of the models,
+which changes between versions of drorm, so they can't be recomputed and aren't compared.
 
-        let constructed = convert_migrations_to_internal_models(&existing_migrations)?;
+`hash`: [&str]: The hash of a migration.
+*/
+pub fn is_legacy_hash(hash: &str) -> bool {
+    hash.parse::<u64>().is_ok()
+}
 
-        let mut last_id: u16 = last_migration.id[..4]
-            .parse()
-            .with_context(|| "Failed converting name of migration to int")?;
-        last_id += 1;
+/**
+Checks whether a hash is a hash of [models_hash] or a legacy hash, see [is_legacy_hash].
 
-        let name = match options.name {
-            None => format!("{:04}_placeholder", last_id),
-            Some(n) => format!("{:04}_{}", last_id, n),
-        };
+`hash`: [&str]: The hash of a migration.
+*/
+pub fn is_valid_hash(hash: &str) -> bool {
+    is_legacy_hash(hash)
+        || hash
+            .strip_prefix(MODELS_HASH_VERSION)
+            .and_then(|x| x.strip_prefix(':'))
+            .is_some_and(|x| x.len() == 64 && x.chars().all(|y| y.is_ascii_hexdigit()))
+}
 
-        let mut op: Vec<Operation> = vec![];
+/**
+Helper function to bring the JSON representation of models into a canonical form.
 
-        let old_lookup: HashMap<String, &Model> = constructed
-            .models
-            .iter()
-            .map(|x| (x.name.clone(), x))
-            .collect();
+The keys of objects are sorted, and nulls and empty lists and objects are removed,
+so an optional property added to the representation doesn't change the hash.
 
-        let new_lookup: HashMap<String, &Model> = internal_models
-            .models
-            .iter()
-            .map(|x| (x.name.clone(), x))
-            .collect();
+`value`: [serde_json::Value]: The JSON representation to normalize.
+*/
+fn canonical_json(value: serde_json::Value) -> serde_json::Value {
+    let is_empty = |x: &serde_json::Value| match x {
+        serde_json::Value::Null => true,
+        serde_json::Value::Array(x) => x.is_empty(),
+        serde_json::Value::Object(x) => x.is_empty(),
+        _ => false,
+    };
+    match value {
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(canonical_json).collect())
+        }
+        serde_json::Value::Object(values) => {
+            let mut values: Vec<(String, serde_json::Value)> = values
+                .into_iter()
+                .map(|(key, value)| (key, canonical_json(value)))
+                .filter(|(_, value)| !is_empty(value))
+                .collect();
+            values.sort_by(|a, b| a.0.cmp(&b.0));
+            serde_json::Value::Object(values.into_iter().collect())
+        }
+        value => value,
+    }
+}
 
-        let mut new_models: Vec<&Model> = vec![];
-        let mut deleted_models: Vec<&Model> = vec![];
+/**
+Helper function to calculate the hash of the internal models, which is stored in migrations.
 
-        let mut new_fields: HashMap<String, Vec<&Field>> = HashMap::new();
-        let mut deleted_fields: HashMap<String, Vec<&Field>> = HashMap::new();
+The order of models, fields, annotations and the columns of a unique together,
+their source locations and rename hints are no change of the schema.
+So the hash is calculated from the models and fields sorted by name and their annotations sorted,
+without their sources and rename hints.
+It's the hex encoded SHA-256 of their canonical JSON representation, see [canonical_json],
+prefixed with [MODELS_HASH_VERSION], so it doesn't depend on their layout in memory.
+It's shared by the generator and the checks of the hashes, which replay the operations.
 
-        // Check if any new models exist
-        internal_models.models.iter().for_each(|x| {
-            if !old_lookup.iter().any(|(a, _)| x.name == *a) {
-                new_models.push(x);
+`internal_models`: [&InternalModelFormat]: The models to hash.
+*/
+pub fn models_hash(internal_models: &InternalModelFormat) -> String {
+    let mut normalized = internal_models.clone();
+    normalized.models.sort_by(|a, b| a.name.cmp(&b.name));
+    for model in normalized.models.iter_mut() {
+        model.source_defined_at = None;
+        model
+            .annotations
+            .retain(|x| !matches!(x, ModelAnnotation::RenameFrom(_)));
+        for annotation in model.annotations.iter_mut() {
+            if let ModelAnnotation::UniqueTogether(columns) = annotation {
+                columns.sort();
             }
-        });
+        }
+        model
+            .annotations
+            .sort_by_cached_key(|x| serde_json::to_string(x).unwrap_or_default());
+        model.fields.sort_by(|a, b| a.name.cmp(&b.name));
+        for field in model.fields.iter_mut() {
+            field.source_defined_at = None;
+            field
+                .annotations
+                .retain(|x| !matches!(x, Annotation::RenameFrom(_)));
+            field
+                .annotations
+                .sort_by_cached_key(|x| serde_json::to_string(x).unwrap_or_default());
+        }
+    }
+
+    // The models contain no maps with non-string keys, so serializing them can't fail
+    let json = serde_json::to_value(&normalized)
+        .map(canonical_json)
+        .unwrap_or_default();
+    format!(
+        "{}:{:x}",
+        MODELS_HASH_VERSION,
+        Sha256::digest(json.to_string().as_bytes())
+    )
+}
+
+/**
+Helper function to convert an operation into a human-readable line of a diff.
 
-        // Check if any old model got deleted
-        constructed.models.iter().for_each(|x| {
-            if !new_lookup.iter().any(|(a, _)| x.name == *a) {
-                deleted_models.push(x);
+`operation`: [&Operation]: The operation to convert.
+*/
+pub fn operation_to_diff_line(operation: &Operation) -> String {
+    match operation {
+        Operation::CreateModel { name, .. } => format!("+ model {}", name),
+        Operation::RenameModel { old, new, .. } => format!("~ model {} -> {}", old, new),
+        Operation::DeleteModel { name, cascade } => {
+            format!(
+                "- model {}{}",
+                name,
+                if *cascade { " (cascade)" } else { "" }
+            )
+        }
+        Operation::CreateField { model, field } => format!("+ {}.{}", model, field.name),
+        Operation::RenameField {
+            table_name,
+            old,
+            new,
+        } => format!("~ {}.{} -> {}.{}", table_name, old, table_name, new),
+        Operation::RenameFields {
+            table_name,
+            renames,
+        } => format!(
+            "~ {}",
+            renames
+                .iter()
+                .map(|(old, new)| format!("{}.{} -> {}.{}", table_name, old, table_name, new))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        Operation::AlterField {
+            model,
+            field,
+            change,
+        } => format!(
+            "~ type {}.{} = {:?}{} ({})",
+            model,
+            field.name,
+            field.db_type,
+            field
+                .annotations
+                .iter()
+                .find_map(|x| match x {
+                    Annotation::MaxLength(l) => Some(format!("({})", l)),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+            change
+        ),
+        Operation::AlterEnum {
+            model,
+            field,
+            action,
+        } => match action {
+            EnumAction::Add { value } => format!("~ enum {}.{} + {}", model, field.name, value),
+            EnumAction::Rename { old, new } => {
+                format!("~ enum {}.{} {} -> {}", model, field.name, old, new)
             }
-        });
+        },
+        Operation::DeleteField {
+            model,
+            name,
+            cascade,
+        } => format!(
+            "- {}.{}{}",
+            model,
+            name,
+            if *cascade { " (cascade)" } else { "" }
+        ),
+        Operation::CreateIndex { model, name, .. } => format!("+ index {}.{}", model, name),
+        Operation::RenameIndex {
+            model, old, new, ..
+        } => {
+            format!("~ index {}.{} -> {}.{}", model, old, model, new)
+        }
+        Operation::RenameConstraint {
+            model, old, new, ..
+        } => {
+            format!("~ constraint {}.{} -> {}.{}", model, old, model, new)
+        }
+        Operation::CreateForeignKey { model, name, .. } => {
+            format!("+ foreign key {}.{}", model, name)
+        }
+        Operation::CreateCheck { model, name, .. } => format!("+ check {}.{}", model, name),
+        Operation::ValidateConstraint { model, name } => {
+            format!("~ validate constraint {}.{}", model, name)
+        }
+        Operation::DropConstraint { model, name, .. } => {
+            format!("- constraint {}.{}", model, name)
+        }
+        Operation::SetSequence {
+            model,
+            column,
+            value,
+        } => format!("~ sequence {}.{} = {}", model, column, value),
+        Operation::AddIdentity { model, column, .. } => {
+            format!("+ identity {}.{}", model, column)
+        }
+        Operation::SetIdentity {
+            model,
+            column,
+            identity,
+        } => format!(
+            "~ identity {}.{} = {}",
+            model,
+            column,
+            match identity {
+                Identity::Always => "always",
+                Identity::ByDefault => "by default",
+            }
+        ),
+        Operation::DropIdentity { model, column } => format!("- identity {}.{}", model, column),
+        Operation::Truncate { model, .. } => format!("~ truncate {}", model),
+        Operation::RunSql { sql, .. } => {
+            let line = sql.trim().lines().next().unwrap_or_default();
+            match line.char_indices().nth(60) {
+                Some((index, _)) => format!("~ run sql {}...", &line[..index]),
+                None => format!("~ run sql {}", line),
+            }
+        }
+        Operation::SetTablespace { model, tablespace } => format!(
+            "~ tablespace {} = {}",
+            model,
+            tablespace.as_deref().unwrap_or("default")
+        ),
+        Operation::SetStorageParameters { model, .. } => {
+            format!("~ storage parameters {}", model)
+        }
+        Operation::SetCharset {
+            model,
+            charset,
+            collation,
+        } => format!(
+            "~ charset {} = {}{}",
+            model,
+            charset.as_deref().unwrap_or("default"),
+            collation
+                .as_ref()
+                .map(|x| format!(" collate {}", x))
+                .unwrap_or_default()
+        ),
+        Operation::CreatePartition { model, name, .. } => {
+            format!("+ partition {}.{}", model, name)
+        }
+        Operation::AttachPartition {
+            model, partition, ..
+        } => format!("+ attach partition {}.{}", model, partition),
+        Operation::DetachPartition { model, partition } => {
+            format!("- detach partition {}.{}", model, partition)
+        }
+        Operation::SetInherit { model, parent } => format!("+ inherit {} from {}", model, parent),
+        Operation::NoInherit { model, parent } => {
+            format!("- inherit {} from {}", model, parent)
+        }
+        Operation::CreateUnique {
+            model,
+            columns,
+            nulls_not_distinct,
+            ..
+        } => format!(
+            "+ unique {}({}){}",
+            model,
+            columns.join(", "),
+            if *nulls_not_distinct {
+                " nulls not distinct"
+            } else {
+                ""
+            }
+        ),
+        Operation::DropUnique { model, columns, .. } => {
+            format!("- unique {}({})", model, columns.join(", "))
+        }
+        Operation::DropIndex { model, name, .. } => format!("- index {}.{}", model, name),
+        Operation::CreateExtension { name, .. } => format!("+ extension {}", name),
+        Operation::DropExtension { name, .. } => format!("- extension {}", name),
+        Operation::Only {
+            dialects,
+            operation,
+        } => format!(
+            "{} (only {})",
+            operation_to_diff_line(operation),
+            dialects
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    }
+}
 
-        // Iterate over all models, that are in the constructed
-        // as well as in the new internal models
-        internal_models
-            .models
-            .iter()
-            .filter(|x| old_lookup.get(x.name.as_str()).is_some())
-            .for_each(|x| {
-                // Check if a new field has been added
-                x.fields.iter().for_each(|y| {
-                    if !old_lookup[x.name.as_str()]
-                        .fields
-                        .iter()
-                        .any(|z| z.name == y.name)
-                    {
-                        if new_fields.get(x.name.as_str()).is_none() {
-                            new_fields.insert(x.name.clone(), vec![]);
-                        }
-                        new_fields.get_mut(x.name.as_str()).unwrap().push(y);
-                    }
-                });
+/**
+Helper method to move the foreign keys of new fields into separate operations.
 
-                // Check if a existing field got deleted
-                old_lookup[x.name.as_str()].fields.iter().for_each(|y| {
-                    if !x.fields.iter().any(|z| z.name == y.name) {
-                        if deleted_fields.get(x.name.as_str()).is_none() {
-                            deleted_fields.insert(x.name.clone(), vec![]);
-                        }
-                        deleted_fields.get_mut(x.name.as_str()).unwrap().push(y);
-                    }
-                });
-            });
+The constraints are created after all tables of a migration,
+so models can reference each other.
 
-        // Create migration operations for new models
-        new_models.iter().for_each(|x| {
-            op.push(Operation::CreateModel {
-                name: x.name.clone(),
-                fields: x.fields.clone(),
+`model`: [&str]: Name of the model the fields belong to.
+`fields`: [&\[Field\]]: The new fields of the model.
+*/
+pub fn split_foreign_keys(model: &str, fields: &[Field]) -> (Vec<Field>, Vec<Operation>) {
+    let mut operations = vec![];
+    let fields = fields
+        .iter()
+        .map(|field| {
+            let mut field = field.clone();
+            let column = field.name.clone();
+            let only = field_dialects(&field).cloned();
+            field.annotations.retain(|annotation| match annotation {
+                Annotation::ForeignKey(foreign_key) => {
+                    operations.push(restrict(
+                        Operation::CreateForeignKey {
+                            model: model.to_string(),
+                            name: format!("{}_{}_fkey", model, column),
+                            column: column.clone(),
+                            foreign_key: foreign_key.clone(),
+                            not_valid: false,
+                        },
+                        only.as_ref(),
+                    ));
+                    false
+                }
+                _ => true,
             });
-            println!("Created model {}", x.name);
-        });
+            field
+        })
+        .collect();
+    (fields, operations)
+}
 
-        // Create migration operations for deleted models
-        deleted_models.iter().for_each(|x| {
-            op.push(Operation::DeleteModel {
-                name: x.name.clone(),
-            });
-            println!("Deleted model {}", x.name);
-        });
+/**
+Helper method to create the full-text indexes of new fields.
 
-        // Create migration operations for new fields in existing models
-        new_fields.iter().for_each(|(x, y)| {
-            y.iter().for_each(|z| {
-                op.push(Operation::CreateField {
-                    model: "".to_string(),
-                    field: (*z).clone(),
-                });
-                println!("Added field {} to model {}", z.name, x);
-            })
-        });
+The `fulltext` annotation is kept on the field, the index is named `<model>_<field>_fulltext_idx`.
 
-        // Create migration operations for deleted fields in existing models
-        deleted_fields.iter().for_each(|(x, y)| {
-            y.iter().for_each(|z| {
-                op.push(Operation::DeleteField {
-                    model: x.clone(),
-                    name: z.name.clone(),
-                });
-                println!("Deleted field {} from model {}", z.name, x);
+`model`: [&str]: Name of the model the fields belong to.
+`fields`: [&\[Field\]]: The new fields of the model.
+*/
+pub fn full_text_indexes(model: &str, fields: &[Field]) -> Vec<Operation> {
+    fields
+        .iter()
+        .filter(|x| {
+            x.annotations
+                .iter()
+                .any(|y| matches!(y, Annotation::FullText))
+        })
+        .map(|x| {
+            restrict(
+                Operation::CreateIndex {
+                    model: model.to_string(),
+                    name: format!("{}_{}_fulltext_idx", model, x.name),
+                    columns: vec![x.name.clone()],
+                    unique: false,
+                    concurrently: false,
+                    kind: IndexKind::FullText,
+                },
+                field_dialects(x),
+            )
+        })
+        .collect()
+}
+
+/**
+Helper function to extract the names of the models a model inherits from.
+*/
+fn parents(model: &Model) -> Vec<&String> {
+    model
+        .annotations
+        .iter()
+        .filter_map(|x| match x {
+            ModelAnnotation::Inherits(parent) => Some(parent),
+            _ => None,
+        })
+        .collect()
+}
+
+/**
+Orders new models, so models are created after the models they inherit from.
+
+Models inheriting from each other are kept in their order, creating them fails.
+
+`models`: [Vec<&Model>]: The new models.
+*/
+pub fn parents_first(mut models: Vec<&Model>) -> Vec<&Model> {
+    let mut ordered = vec![];
+    while !models.is_empty() {
+        let index = models
+            .iter()
+            .position(|x| {
+                !parents(x)
+                    .into_iter()
+                    .any(|parent| models.iter().any(|y| y.name == *parent))
             })
-        });
+            .unwrap_or(0);
+        ordered.push(models.remove(index));
+    }
+    ordered
+}
 
-        let new_migration = Migration {
-            hash: h.to_string(),
-            initial: false,
-            id: name.clone(),
-            dependency: last_migration.id.clone(),
-            replaces: vec![],
-            operations: op,
-        };
+/**
+Creates the operations to migrate the models a model inherits from.
 
-        // Write migration to disk
-        let path = Path::new(options.migration_dir.as_str()).join(format!("{}.toml", name));
-        convert_migration_to_file(new_migration, &path)
-            .with_context(|| "Error occurred while converting migration to file")?;
-    } else {
-        // New migration must be generated as no migration exists
+`old`: [&Model]: State of the model resulting from the existing migrations.
+`new`: [&Model]: Current state of the model.
+*/
+pub fn inherit_operations(old: &Model, new: &Model) -> Vec<Operation> {
+    let (old_parents, new_parents) = (parents(old), parents(new));
+    let mut operations: Vec<Operation> = old_parents
+        .iter()
+        .filter(|x| !new_parents.contains(x))
+        .map(|x| Operation::NoInherit {
+            model: new.name.clone(),
+            parent: x.to_string(),
+        })
+        .collect();
+    operations.extend(
+        new_parents
+            .iter()
+            .filter(|x| !old_parents.contains(x))
+            .map(|x| Operation::SetInherit {
+                model: new.name.clone(),
+                parent: x.to_string(),
+            }),
+    );
+    operations
+}
 
-        let name = match options.name {
-            None => "0001_initial".to_string(),
-            Some(n) => format!("0001_{}", n),
-        };
+/**
+Helper method to get the annotations of a new model's table.
 
-        let new_migration = Migration {
-            hash: h.to_string(),
-            initial: true,
-            id: name.clone(),
-            dependency: "".to_string(),
-            replaces: vec![],
-            operations: internal_models
-                .models
-                .iter()
-                .map(|x| {
-                    let o = Operation::CreateModel {
-                        name: x.name.clone(),
-                        fields: x
-                            .fields
-                            .iter()
-                            .map(|y| Field {
-                                name: y.name.clone(),
-                                db_type: y.db_type.clone(),
-                                annotations: y.annotations.clone(),
-                                source_defined_at: None,
-                            })
-                            .collect(),
-                    };
-                    println!("Created model {}", x.name);
-                    return o;
-                })
-                .collect(),
-        };
+The indexes of the model are created by their own operations after the table.
 
-        // Write migration to disk
-        let path = Path::new(options.migration_dir.as_str()).join(format!("{}.toml", name));
-        convert_migration_to_file(new_migration, &path)
-            .with_context(|| "Error occurred while converting migration to file")?;
-    }
+`model`: [&Model]: The new model.
+*/
+fn table_annotations(model: &Model) -> Vec<ModelAnnotation> {
+    model
+        .annotations
+        .iter()
+        .filter(|x| !matches!(x, ModelAnnotation::Index(_)))
+        .cloned()
+        .collect()
+}
 
-    Ok(())
+/**
+Returns the name of the unique index of columns declared unique together.
+
+The index is named `<model>_<columns>_key` with the columns sorted by their name,
+so reordering the columns of a declaration keeps its name.
+
+`model`: [&str]: Name of the model.
+`columns`: [&\[String\]]: The columns declared unique together.
+*/
+pub fn unique_together_name(model: &str, columns: &[String]) -> String {
+    let mut columns = columns.to_vec();
+    columns.sort();
+    format!("{}_{}_key", model, columns.join("_"))
+}
+
+/**
+Returns the name of an index managed by make-migrations.
+
+The index is named `<model>_<columns>_idx` with the columns in index order.
+
+`model`: [&str]: Name of the model.
+`columns`: [&\[String\]]: The columns of the index.
+*/
+pub fn index_name(model: &str, columns: &[String]) -> String {
+    format!("{}_{}_idx", model, columns.join("_"))
+}
+
+/**
+Checks that the columns declared unique together or indexed exist in their models.
+
+`internal_models`: [&InternalModelFormat]: The current models.
+*/
+pub fn check_unique_together(internal_models: &InternalModelFormat) -> anyhow::Result<()> {
+    for model in &internal_models.models {
+        for annotation in &model.annotations {
+            let (columns, declared) = match annotation {
+                ModelAnnotation::UniqueTogether(columns) => (columns, "declared unique together"),
+                ModelAnnotation::Index(columns) => (columns, "indexed"),
+                _ => continue,
+            };
+            if let Some(column) = columns
+                .iter()
+                .find(|x| !model.fields.iter().any(|y| y.name == **x))
+            {
+                return Err(anyhow!(
+                    "Field {} of model {} is {}, but does not exist",
+                    column,
+                    model.name,
+                    declared
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+Checks that the referencing fields of foreign keys can take the values their actions set them to,
+see [referential_action_problems].
+
+`internal_models`: [&InternalModelFormat]: The current models.
+*/
+pub fn check_referential_actions(internal_models: &InternalModelFormat) -> anyhow::Result<()> {
+    for model in &internal_models.models {
+        for field in &model.fields {
+            for annotation in &field.annotations {
+                if let Annotation::ForeignKey(foreign_key) = annotation {
+                    if let Some(problem) =
+                        referential_action_problems(model.name.as_str(), field, foreign_key)
+                            .into_iter()
+                            .next()
+                    {
+                        return Err(anyhow!(problem));
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+Removes the `rename_from` annotations from the models and returns the declared renames.
+
+Model renames are returned before field renames, fields are referenced by their new model name.
+The annotations are only hints, so removing them after the migration is written is no change.
+
+`internal_models`: [&mut InternalModelFormat]: The current models.
+*/
+pub fn take_renames(internal_models: &mut InternalModelFormat) -> Vec<Operation> {
+    let mut models = vec![];
+    let mut fields = vec![];
+    for model in internal_models.models.iter_mut() {
+        model.annotations.retain(|x| match x {
+            ModelAnnotation::RenameFrom(old) => {
+                models.push(Operation::RenameModel {
+                    old: old.clone(),
+                    new: model.name.clone(),
+                    rename_constraints: false,
+                });
+                false
+            }
+            _ => true,
+        });
+
+        for field in model.fields.iter_mut() {
+            let mut old = None;
+            field.annotations.retain(|x| match x {
+                Annotation::RenameFrom(name) => {
+                    old = Some(name.clone());
+                    false
+                }
+                _ => true,
+            });
+            if let Some(old) = old {
+                fields.push(restrict(
+                    Operation::RenameField {
+                        table_name: model.name.clone(),
+                        old,
+                        new: field.name.clone(),
+                    },
+                    field_dialects(field),
+                ));
+            }
+        }
+    }
+    models.append(&mut fields);
+    models
+}
+
+/**
+Annotates the timestamp fields following the naming convention of `--auto-timestamps`.
+
+A field named `created_at` gets [Annotation::AutoCreateTime] and a field named
+`updated_at` gets [Annotation::AutoUpdateTime] if it is a `datetime` or `timestamp`
+and has none of the annotations `auto_create_time`, `auto_update_time`,
+`default`, `server_default` and `client_default`.
+
+`internal_models`: [&mut InternalModelFormat]: The current models.
+*/
+pub fn apply_auto_timestamps(internal_models: &mut InternalModelFormat) {
+    for model in internal_models.models.iter_mut() {
+        for field in model.fields.iter_mut() {
+            let annotation = match field.name.as_str() {
+                "created_at" => Annotation::AutoCreateTime,
+                "updated_at" => Annotation::AutoUpdateTime,
+                _ => continue,
+            };
+            if !matches!(field.db_type, DbType::Datetime | DbType::Timestamp) {
+                continue;
+            }
+            let annotated = field.annotations.iter().any(|x| {
+                matches!(
+                    x,
+                    Annotation::AutoCreateTime
+                        | Annotation::AutoUpdateTime
+                        | Annotation::DefaultValue(_)
+                        | Annotation::ServerDefault(_)
+                        | Annotation::ClientDefault
+                )
+            });
+            if !annotated {
+                field.annotations.push(annotation);
+            }
+        }
+    }
+}
+
+/**
+Applies the renames of [take_renames] to the state resulting from the existing migrations.
+
+Renames which have already been applied, e.g. as the annotation hasn't been removed yet, are skipped.
+Returns the operations of the remaining renames.
+
+`constructed`: [&mut InternalModelFormat]: State resulting from the existing migrations.
+`renames`: [Vec<Operation>]: The declared renames.
+*/
+pub fn apply_renames(
+    constructed: &mut InternalModelFormat,
+    renames: Vec<Operation>,
+) -> anyhow::Result<Vec<Operation>> {
+    let mut operations = vec![];
+    for rename in renames {
+        match rename.inner() {
+            Operation::RenameModel { old, new, .. } => {
+                let exists = |name: &String| constructed.models.iter().any(|x| x.name == *name);
+                match (exists(old), exists(new)) {
+                    (true, false) => {}
+                    (false, true) => continue,
+                    (true, true) => {
+                        return Err(anyhow!(
+                            "Model {} is renamed from {}, but both models exist",
+                            new,
+                            old
+                        ))
+                    }
+                    (false, false) => {
+                        return Err(anyhow!(
+                            "Model {} is renamed from {}, which does not exist",
+                            new,
+                            old
+                        ))
+                    }
+                }
+
+                for model in constructed.models.iter_mut() {
+                    if model.name == *old {
+                        model.name = new.clone();
+                    }
+                }
+                rename_references(&mut constructed.models, old, new, &[]);
+                info!("Renamed model {} to {}", old, new);
+            }
+            Operation::RenameField {
+                table_name,
+                old,
+                new,
+            } => {
+                let model = constructed
+                    .models
+                    .iter_mut()
+                    .find(|x| x.name == *table_name)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Field {}.{} is renamed from {}, but model {} does not exist yet",
+                            table_name,
+                            new,
+                            old,
+                            table_name
+                        )
+                    })?;
+                let exists = |name: &String| model.fields.iter().any(|x| x.name == *name);
+                match (exists(old), exists(new)) {
+                    (true, false) => {}
+                    (false, true) => continue,
+                    (true, true) => {
+                        return Err(anyhow!(
+                            "Field {}.{} is renamed from {}, but both fields exist",
+                            table_name,
+                            new,
+                            old
+                        ))
+                    }
+                    (false, false) => {
+                        return Err(anyhow!(
+                            "Field {}.{} is renamed from {}, which does not exist",
+                            table_name,
+                            new,
+                            old
+                        ))
+                    }
+                }
+
+                for field in model.fields.iter_mut() {
+                    if field.name == *old {
+                        field.name = new.clone();
+                    }
+                }
+                rename_references(
+                    &mut constructed.models,
+                    table_name,
+                    table_name,
+                    &[(old.clone(), new.clone())],
+                );
+                info!("Renamed field {} of model {} to {}", old, table_name, new);
+            }
+            _ => unreachable!("take_renames only returns renames"),
+        }
+        operations.push(rename);
+    }
+    Ok(coalesce_renames(operations))
+}
+
+/**
+Combines the field renames of a model into a single [Operation::RenameFields].
+
+The renames are applied one after another, so a field renamed twice is renamed
+from its first to its last name. Renames restricted to some dialects are kept as they are.
+
+`operations`: [Vec<Operation>]: The renames in the order they are applied.
+*/
+pub fn coalesce_renames(operations: Vec<Operation>) -> Vec<Operation> {
+    let mut coalesced: Vec<Operation> = vec![];
+    for operation in operations {
+        let (table_name, old, new) = match operation {
+            Operation::RenameField {
+                table_name,
+                old,
+                new,
+            } => (table_name, old, new),
+            operation => {
+                coalesced.push(operation);
+                continue;
+            }
+        };
+
+        let existing = coalesced.iter_mut().find(|x| {
+            matches!(
+                x,
+                Operation::RenameField { table_name: t, .. }
+                    | Operation::RenameFields { table_name: t, .. } if *t == table_name
+            )
+        });
+        let existing = match existing {
+            Some(existing) => existing,
+            None => {
+                coalesced.push(Operation::RenameField {
+                    table_name,
+                    old,
+                    new,
+                });
+                continue;
+            }
+        };
+
+        let mut renames = match existing {
+            Operation::RenameField { old, new, .. } => vec![(old.clone(), new.clone())],
+            Operation::RenameFields { renames, .. } => renames.clone(),
+            _ => unreachable!("Only renames are combined"),
+        };
+        match renames.iter_mut().find(|(_, x)| *x == old) {
+            Some(rename) => rename.1 = new,
+            None => renames.push((old, new)),
+        }
+        renames.retain(|(old, new)| old != new);
+        *existing = Operation::RenameFields {
+            table_name,
+            renames,
+        };
+    }
+    coalesced
+}
+
+/**
+Helper function to extract the dialects a field is restricted to.
+*/
+fn field_dialects(field: &Field) -> Option<&Vec<Dialect>> {
+    field.annotations.iter().find_map(|x| match x {
+        Annotation::Only(dialects) => Some(dialects),
+        _ => None,
+    })
+}
+
+/**
+Helper function to restrict an operation to the given dialects.
+
+`operation`: [Operation]: The operation to restrict.
+`dialects`: [Option<&Vec<Dialect>>]: The dialects to restrict to, the operation is returned unchanged if not set.
+*/
+fn restrict(operation: Operation, dialects: Option<&Vec<Dialect>>) -> Operation {
+    match dialects {
+        None => operation,
+        Some(dialects) => Operation::Only {
+            dialects: dialects.clone(),
+            operation: Box::new(operation),
+        },
+    }
+}
+
+/**
+Helper function to extract the tablespace of a model.
+*/
+fn tablespace(model: &Model) -> Option<&String> {
+    model.annotations.iter().find_map(|x| match x {
+        ModelAnnotation::Tablespace(t) => Some(t),
+        _ => None,
+    })
+}
+
+/**
+Helper function to extract the storage parameters of a model.
+*/
+fn storage_parameters(model: &Model) -> Vec<&StorageParameter> {
+    model
+        .annotations
+        .iter()
+        .filter_map(|x| match x {
+            ModelAnnotation::StorageParameter(p) => Some(p),
+            _ => None,
+        })
+        .collect()
+}
+
+/**
+Helper function to extract the character set and collation of a model.
+*/
+fn charset(model: &Model) -> (Option<&String>, Option<&String>) {
+    (
+        model.annotations.iter().find_map(|x| match x {
+            ModelAnnotation::Charset(c) => Some(c),
+            _ => None,
+        }),
+        model.annotations.iter().find_map(|x| match x {
+            ModelAnnotation::Collation(c) => Some(c),
+            _ => None,
+        }),
+    )
+}
+
+/**
+Creates the operations to migrate the tablespace, storage parameters and character set of a model.
+
+`old`: [&Model]: State of the model resulting from the existing migrations.
+`new`: [&Model]: Current state of the model.
+*/
+pub fn storage_operations(old: &Model, new: &Model) -> Vec<Operation> {
+    let mut operations = vec![];
+
+    if tablespace(old) != tablespace(new) {
+        operations.push(Operation::SetTablespace {
+            model: new.name.clone(),
+            tablespace: tablespace(new).cloned(),
+        });
+    }
+
+    let old_parameters = storage_parameters(old);
+    let new_parameters = storage_parameters(new);
+    let set: Vec<StorageParameter> = new_parameters
+        .iter()
+        .filter(|x| !old_parameters.contains(x))
+        .map(|x| (*x).clone())
+        .collect();
+    let reset: Vec<String> = old_parameters
+        .iter()
+        .filter(|x| !new_parameters.iter().any(|y| y.name == x.name))
+        .map(|x| x.name.clone())
+        .collect();
+    if !set.is_empty() || !reset.is_empty() {
+        operations.push(Operation::SetStorageParameters {
+            model: new.name.clone(),
+            set,
+            reset,
+        });
+    }
+
+    if charset(old) != charset(new) {
+        let (charset, collation) = charset(new);
+        operations.push(Operation::SetCharset {
+            model: new.name.clone(),
+            charset: charset.cloned(),
+            collation: collation.cloned(),
+        });
+    }
+
+    operations
+}
+
+/**
+Helper function to extract the column sets declared unique together, keyed by their sorted columns.
+*/
+fn unique_together(model: &Model) -> Vec<(Vec<&String>, &Vec<String>)> {
+    model
+        .annotations
+        .iter()
+        .filter_map(|x| match x {
+            ModelAnnotation::UniqueTogether(columns) => {
+                let mut key: Vec<&String> = columns.iter().collect();
+                key.sort();
+                Some((key, columns))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/**
+Helper function to resolve the names of a model and its columns before the renames of a migration.
+
+`new`: [&Model]: Current state of the model.
+`renames`: [&\[Operation\]]: The renames of the migration.
+*/
+fn previous_names<'a>(
+    new: &'a Model,
+    renames: &'a [Operation],
+) -> (&'a String, impl Fn(&String) -> String + 'a) {
+    let model = renames
+        .iter()
+        .find_map(|x| match x.inner() {
+            Operation::RenameModel {
+                old, new: renamed, ..
+            } if *renamed == new.name => Some(old),
+            _ => None,
+        })
+        .unwrap_or(&new.name);
+    let column = |name: &String| {
+        renames
+            .iter()
+            .flat_map(|x| match x.inner() {
+                Operation::RenameField {
+                    table_name,
+                    old,
+                    new: renamed,
+                } if *table_name == new.name => vec![(old, renamed)],
+                Operation::RenameFields {
+                    table_name,
+                    renames: pairs,
+                } if *table_name == new.name => pairs.iter().map(|(x, y)| (x, y)).collect(),
+                _ => vec![],
+            })
+            .find_map(|(old, renamed)| (renamed == name).then(|| old.clone()))
+            .unwrap_or_else(|| name.clone())
+    };
+    (model, column)
+}
+
+/**
+Helper function to rename a kept index or unique constraint, whose columns are renamed.
+
+The renames of the migration are applied before, so the index is renamed on the new model.
+Its old name starts with the old name of the model, unless postgres renamed it with the
+constraints of a renamed model, so the rename is restricted to the dialects in this case.
+
+`new`: [&Model]: Current state of the model.
+`model`: [&str]: Name of the model before the renames.
+`renames`: [&\[Operation\]]: The renames of the migration.
+`previous`: [&\[String\]]: Columns of the index before the renames.
+`columns`: [&\[String\]]: Columns of the index after the renames.
+`unique`: [bool]: Whether the index is a unique constraint declared unique together.
+*/
+fn rename_index_operations(
+    new: &Model,
+    model: &str,
+    renames: &[Operation],
+    previous: &[String],
+    columns: &[String],
+    unique: bool,
+) -> Vec<Operation> {
+    let name = if unique {
+        unique_together_name
+    } else {
+        index_name
+    };
+    let rename = |old: &str| Operation::RenameIndex {
+        model: new.name.clone(),
+        old: name(old, previous),
+        new: name(new.name.as_str(), columns),
+        columns: columns.to_vec(),
+        unique,
+    };
+    let constraints_renamed = renames.iter().any(|x| {
+        matches!(
+            x.inner(),
+            Operation::RenameModel { new: renamed, rename_constraints: true, .. }
+                if *renamed == new.name
+        )
+    });
+    if constraints_renamed {
+        vec![
+            restrict(rename(new.name.as_str()), Some(&vec![Dialect::Postgres])),
+            restrict(rename(model), Some(&vec![Dialect::SQLite, Dialect::MySQL])),
+        ]
+    } else {
+        vec![rename(model)]
+    }
+}
+
+/**
+Creates the operations to migrate the columns declared unique together of a model.
+
+Declarations are compared by their set of columns, so reordering the columns
+of a declaration is no change. Unique constraints are dropped before the renames of
+the migration are applied, so dropped constraints refer to the names before the renames.
+Kept constraints with a renamed column are renamed after it, see [rename_index_operations].
+
+`old`: [&Model]: State of the model resulting from the existing migrations, with the renames applied.
+`new`: [&Model]: Current state of the model.
+`renames`: [&\[Operation\]]: The renames of the migration.
+*/
+pub fn unique_together_operations(
+    old: &Model,
+    new: &Model,
+    renames: &[Operation],
+) -> Vec<Operation> {
+    let old_unique = unique_together(old);
+    let new_unique = unique_together(new);
+    let (model, column) = previous_names(new, renames);
+
+    let mut operations = vec![];
+    for (key, columns) in &old_unique {
+        if !new_unique.iter().any(|(x, _)| x == key) {
+            let columns: Vec<String> = columns.iter().map(&column).collect();
+            operations.push(Operation::DropUnique {
+                model: model.clone(),
+                name: unique_together_name(model.as_str(), &columns),
+                columns,
+            });
+        }
+    }
+    for (key, columns) in &new_unique {
+        if !old_unique.iter().any(|(x, _)| x == key) {
+            operations.push(Operation::CreateUnique {
+                model: new.name.clone(),
+                name: unique_together_name(new.name.as_str(), columns),
+                columns: (*columns).clone(),
+                nulls_not_distinct: false,
+            });
+        } else {
+            let previous: Vec<String> = columns.iter().map(&column).collect();
+            if previous != **columns {
+                operations.extend(rename_index_operations(
+                    new, model, renames, &previous, columns, true,
+                ));
+            }
+        }
+    }
+    operations
+}
+
+/**
+Collects the indexes declared by a model, in index order.
+
+The indexes of the `indexes` model annotation come first, followed by the
+`index` annotations of the fields. Fields sharing the name of an index form a
+composite index ordered by their priority, which defaults to 10.
+Indexes with the same columns in the same order are only returned once.
+
+`model`: [&Model]: The model to collect the indexes of.
+*/
+pub fn model_indexes(model: &Model) -> Vec<Vec<String>> {
+    let mut indexes: Vec<Vec<String>> = managed_indexes(model).into_iter().cloned().collect();
+
+    let mut composite: Vec<(&String, Vec<(i32, &String)>)> = vec![];
+    for field in &model.fields {
+        for annotation in &field.annotations {
+            match annotation {
+                Annotation::Index(None) => indexes.push(vec![field.name.clone()]),
+                Annotation::Index(Some(value)) => {
+                    let entry = (value.priority.unwrap_or(10), &field.name);
+                    match composite.iter_mut().find(|(x, _)| **x == value.name) {
+                        Some((_, fields)) => fields.push(entry),
+                        None => composite.push((&value.name, vec![entry])),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    for (_, mut fields) in composite {
+        // The sort is stable, so fields of the same priority keep their order
+        fields.sort_by_key(|(priority, _)| *priority);
+        indexes.push(fields.into_iter().map(|(_, x)| x.clone()).collect());
+    }
+
+    let mut unique: Vec<Vec<String>> = vec![];
+    for index in indexes {
+        if !unique.contains(&index) {
+            unique.push(index);
+        }
+    }
+    unique
+}
+
+/**
+Helper function to extract the indexes managed by make-migrations from the state of a model.
+*/
+fn managed_indexes(model: &Model) -> Vec<&Vec<String>> {
+    model
+        .annotations
+        .iter()
+        .filter_map(|x| match x {
+            ModelAnnotation::Index(columns) => Some(columns),
+            _ => None,
+        })
+        .collect()
+}
+
+/**
+Creates the operations creating the indexes of a new model.
+
+`model`: [&Model]: The new model.
+*/
+pub fn create_index_operations(model: &Model) -> Vec<Operation> {
+    model_indexes(model)
+        .into_iter()
+        .map(|columns| Operation::CreateIndex {
+            model: model.name.clone(),
+            name: index_name(model.name.as_str(), &columns),
+            columns,
+            unique: false,
+            concurrently: false,
+            kind: IndexKind::Index,
+        })
+        .collect()
+}
+
+/**
+Creates the operations to migrate the indexes of a model.
+
+Indexes are compared by their columns in index order, so reordering the columns
+of an index replaces it. Like unique constraints, indexes are dropped before the renames of
+the migration are applied, so dropped indexes refer to the names before the renames.
+Kept indexes with a renamed column are renamed after it, see [rename_index_operations].
+
+`old`: [&Model]: State of the model resulting from the existing migrations, with the renames applied.
+`new`: [&Model]: Current state of the model.
+`renames`: [&\[Operation\]]: The renames of the migration.
+*/
+pub fn index_operations(old: &Model, new: &Model, renames: &[Operation]) -> Vec<Operation> {
+    let old_indexes = managed_indexes(old);
+    let new_indexes = model_indexes(new);
+    let (model, column) = previous_names(new, renames);
+
+    let mut operations = vec![];
+    for columns in &old_indexes {
+        if !new_indexes.contains(columns) {
+            let columns: Vec<String> = columns.iter().map(&column).collect();
+            operations.push(Operation::DropIndex {
+                model: model.clone(),
+                name: index_name(model.as_str(), &columns),
+                columns,
+            });
+        }
+    }
+    operations.extend(
+        create_index_operations(new)
+            .into_iter()
+            .filter(|x| match x {
+                Operation::CreateIndex { columns, .. } => !old_indexes.contains(&columns),
+                _ => false,
+            }),
+    );
+    for columns in old_indexes.iter().filter(|x| new_indexes.contains(x)) {
+        let previous: Vec<String> = columns.iter().map(&column).collect();
+        if previous != **columns {
+            operations.extend(rename_index_operations(
+                new, model, renames, &previous, columns, false,
+            ));
+        }
+    }
+    operations
+}
+
+/**
+Returns a note, if the persistence of a model changed.
+
+The persistence can't be changed by a migration, the table has to be rebuilt instead.
+
+`old`: [&Model]: State of the model resulting from the existing migrations.
+`new`: [&Model]: Current state of the model.
+*/
+pub fn persistence_note(old: &Model, new: &Model) -> Option<String> {
+    let persistence = |model: &Model| match model.annotations.iter().find_map(|x| match x {
+        ModelAnnotation::Persistence(p) => Some(*p),
+        _ => None,
+    }) {
+        None => "logged",
+        Some(Persistence::Unlogged) => "unlogged",
+        Some(Persistence::Temporary) => "temporary",
+    };
+
+    let (from, to) = (persistence(old), persistence(new));
+    (from != to).then(|| {
+        format!(
+            "Model {} changed from {} to {}, which is not migrated. Delete and create the model again to rebuild its table",
+            new.name, from, to
+        )
+    })
+}
+
+/**
+Returns a note, if the partitioning of a model changed.
+
+Existing tables can't be partitioned or change their partitioning, the table has to be rebuilt instead.
+
+`old`: [&Model]: State of the model resulting from the existing migrations.
+`new`: [&Model]: Current state of the model.
+*/
+pub fn partition_note(old: &Model, new: &Model) -> Option<String> {
+    let partition_by = |model: &Model| {
+        model.annotations.iter().find_map(|x| match x {
+            ModelAnnotation::PartitionBy(p) => Some(p.clone()),
+            _ => None,
+        })
+    };
+
+    (partition_by(old) != partition_by(new)).then(|| {
+        format!(
+            "Partitioning of model {} changed, which is not migrated. Delete and create the model again to rebuild its table",
+            new.name
+        )
+    })
+}
+
+/**
+Helper function to extract the identity of a field.
+*/
+fn identity(field: &Field) -> Option<Identity> {
+    field.annotations.iter().find_map(|x| match x {
+        Annotation::Identity(i) => Some(*i),
+        _ => None,
+    })
+}
+
+/**
+Creates the operation to migrate the identity of a field, which exists in both states.
+
+`model`: [&str]: Name of the model the field belongs to.
+`old`: [&Field]: State of the field resulting from the existing migrations.
+`new`: [&Field]: Current state of the field.
+*/
+pub fn identity_operation(model: &str, old: &Field, new: &Field) -> Option<Operation> {
+    let operation = match (identity(old), identity(new)) {
+        (None, Some(identity)) => Operation::AddIdentity {
+            model: model.to_string(),
+            column: new.name.clone(),
+            identity,
+            from_serial: old
+                .annotations
+                .iter()
+                .any(|x| matches!(x, Annotation::AutoIncrement)),
+        },
+        (Some(old), Some(identity)) if old != identity => Operation::SetIdentity {
+            model: model.to_string(),
+            column: new.name.clone(),
+            identity,
+        },
+        (Some(_), None) => Operation::DropIdentity {
+            model: model.to_string(),
+            column: new.name.clone(),
+        },
+        _ => return None,
+    };
+    Some(restrict(operation, field_dialects(new)))
+}
+
+/// Functions of postgres extensions, which are detected in server defaults, and their extensions
+const EXTENSION_FUNCTIONS: &[(&str, &str)] = &[
+    ("uuid_generate_v1", "uuid-ossp"),
+    ("uuid_generate_v1mc", "uuid-ossp"),
+    ("uuid_generate_v3", "uuid-ossp"),
+    ("uuid_generate_v4", "uuid-ossp"),
+    ("uuid_generate_v5", "uuid-ossp"),
+    ("crypt", "pgcrypto"),
+    ("gen_salt", "pgcrypto"),
+    ("gen_random_bytes", "pgcrypto"),
+    ("digest", "pgcrypto"),
+    ("hmac", "pgcrypto"),
+];
+
+/**
+Returns the postgres extensions the server defaults of the models require, e.g.
+`uuid-ossp` for `uuid_generate_v4()`. Fields restricted to other dialects are skipped.
+
+`models`: [&InternalModelFormat]: The models to check.
+*/
+pub fn required_extensions(models: &InternalModelFormat) -> Vec<&'static str> {
+    let mut extensions = vec![];
+    for field in models.models.iter().flat_map(|x| x.fields.iter()) {
+        if field_dialects(field).is_some_and(|x| !x.contains(&Dialect::Postgres)) {
+            continue;
+        }
+        for annotation in &field.annotations {
+            let expression = match annotation {
+                Annotation::ServerDefault(expression) => expression.to_lowercase(),
+                _ => continue,
+            };
+            for (function, extension) in EXTENSION_FUNCTIONS {
+                // The function name must not be the end of another identifier
+                let called = expression
+                    .match_indices(format!("{}(", function).as_str())
+                    .any(|(index, _)| {
+                        !expression[..index]
+                            .ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+                    });
+                if called && !extensions.contains(extension) {
+                    extensions.push(*extension);
+                }
+            }
+        }
+    }
+    extensions.sort();
+    extensions
+}
+
+/**
+Creates the operations to create the postgres extensions required by the models,
+which haven't been created by the existing migrations, see [required_extensions].
+
+The extensions are created with `IF NOT EXISTS`, as they may have been installed
+by an administrator, and are restricted to postgres.
+
+`existing`: [&\[Migration\]]: The existing migrations.
+`models`: [&InternalModelFormat]: Current state of the models.
+*/
+pub fn extension_operations(
+    existing: &[Migration],
+    models: &InternalModelFormat,
+) -> Vec<Operation> {
+    let mut created = vec![];
+    for operation in existing.iter().flat_map(|x| x.operations.iter()) {
+        match operation.inner() {
+            Operation::CreateExtension { name, .. } => created.push(name.as_str()),
+            Operation::DropExtension { name, .. } => created.retain(|x| x != name),
+            _ => {}
+        }
+    }
+
+    required_extensions(models)
+        .into_iter()
+        .filter(|x| !created.contains(x))
+        .map(|x| {
+            restrict(
+                Operation::CreateExtension {
+                    name: x.to_string(),
+                    if_not_exists: true,
+                },
+                Some(&vec![Dialect::Postgres]),
+            )
+        })
+        .collect()
+}
+
+/**
+Creates the operation to migrate the type of a field, which exists in both states.
+
+Only the type and the max_length of the field are changed,
+its other annotations are kept as they are in the old state.
+
+`model`: [&str]: Name of the model the field belongs to.
+`old`: [&Field]: State of the field resulting from the existing migrations.
+`new`: [&Field]: Current state of the field.
+*/
+pub fn type_operation(model: &str, old: &Field, new: &Field) -> Option<Operation> {
+    let change = TypeChange::classify(old, new)?;
+
+    let mut field = old.clone();
+    field.db_type = new.db_type.clone();
+    let max_length = new
+        .annotations
+        .iter()
+        .find(|x| matches!(x, Annotation::MaxLength(_)));
+    // The max_length is replaced in place to keep the order of the annotations
+    match (
+        field
+            .annotations
+            .iter()
+            .position(|x| matches!(x, Annotation::MaxLength(_))),
+        max_length,
+    ) {
+        (Some(index), Some(max_length)) => field.annotations[index] = max_length.clone(),
+        (Some(index), None) => {
+            field.annotations.remove(index);
+        }
+        (None, Some(max_length)) => field.annotations.push(max_length.clone()),
+        (None, None) => {}
+    }
+
+    Some(restrict(
+        Operation::AlterField {
+            model: model.to_string(),
+            field,
+            change,
+        },
+        field_dialects(new),
+    ))
+}
+
+/**
+Helper function to get the choices of a field of type choices.
+
+`field`: [&Field]: The field to get the choices of.
+*/
+fn enum_choices(field: &Field) -> Option<&Vec<String>> {
+    if !matches!(field.db_type, DbType::Choices) {
+        return None;
+    }
+    field.annotations.iter().find_map(|x| match x {
+        Annotation::Choices(c) => Some(c),
+        _ => None,
+    })
+}
+
+/**
+Creates the operations to migrate the choices of a field of type choices, which exists in both states.
+
+A value replaced at the same position by a value which didn't exist before is renamed,
+other new values are added. Removed values are reported by [enum_note].
+Every operation carries the field with the values after it has been applied.
+
+`model`: [&str]: Name of the model the field belongs to.
+`old`: [&Field]: State of the field resulting from the existing migrations.
+`new`: [&Field]: Current state of the field.
+*/
+pub fn enum_operations(model: &str, old: &Field, new: &Field) -> Vec<Operation> {
+    let (old_values, new_values) = match (enum_choices(old), enum_choices(new)) {
+        (Some(old_values), Some(new_values)) => (old_values, new_values),
+        _ => return vec![],
+    };
+
+    let mut actions = vec![];
+    let mut values = old_values.clone();
+    for (index, value) in old_values.iter().enumerate() {
+        match new_values.get(index) {
+            Some(renamed) if !new_values.contains(value) && !old_values.contains(renamed) => {
+                values[index] = renamed.clone();
+                actions.push((
+                    EnumAction::Rename {
+                        old: value.clone(),
+                        new: renamed.clone(),
+                    },
+                    values.clone(),
+                ));
+            }
+            _ => {}
+        }
+    }
+    for value in new_values {
+        if !values.contains(value) {
+            values.push(value.clone());
+            actions.push((
+                EnumAction::Add {
+                    value: value.clone(),
+                },
+                values.clone(),
+            ));
+        }
+    }
+
+    actions
+        .into_iter()
+        .map(|(action, values)| {
+            let mut field = old.clone();
+            for annotation in &mut field.annotations {
+                if let Annotation::Choices(c) = annotation {
+                    *c = values.clone();
+                }
+            }
+            restrict(
+                Operation::AlterEnum {
+                    model: model.to_string(),
+                    field,
+                    action,
+                },
+                field_dialects(new),
+            )
+        })
+        .collect()
+}
+
+/**
+Returns a note, if values were removed from the choices of a field.
+
+Removing a value would fail for the rows using it, so it isn't migrated.
+
+`model`: [&str]: Name of the model the field belongs to.
+`old`: [&Field]: State of the field resulting from the existing migrations.
+`new`: [&Field]: Current state of the field.
+*/
+pub fn enum_note(model: &str, old: &Field, new: &Field) -> Option<String> {
+    let (old_values, new_values) = (enum_choices(old)?, enum_choices(new)?);
+    let renamed = enum_operations(model, old, new)
+        .into_iter()
+        .filter_map(|x| match x.inner() {
+            Operation::AlterEnum {
+                action: EnumAction::Rename { old, .. },
+                ..
+            } => Some(old.clone()),
+            _ => None,
+        })
+        .collect::<Vec<String>>();
+    let removed = old_values
+        .iter()
+        .filter(|x| !new_values.contains(x) && !renamed.contains(x))
+        .map(String::as_str)
+        .collect::<Vec<&str>>();
+
+    (!removed.is_empty()).then(|| {
+        format!(
+            "The choices {} of field {}.{} were removed, which is not migrated. Update the rows using them with RunSql",
+            removed.join(", "),
+            model,
+            new.name
+        )
+    })
+}
+
+/**
+Returns the message printed for a detected change.
+
+Renames and foreign keys are part of other changes, so they have no message.
+
+`operation`: [&Operation]: The operation of the change.
+*/
+pub fn change_message(operation: &Operation) -> Option<String> {
+    match operation.inner() {
+        Operation::CreateModel { name, .. } => Some(format!("Created model {}", name)),
+        Operation::DeleteModel { name, .. } => Some(format!("Deleted model {}", name)),
+        Operation::CreateField { model, field } => {
+            Some(format!("Added field {} to model {}", field.name, model))
+        }
+        Operation::DeleteField { model, name, .. } => {
+            Some(format!("Deleted field {} from model {}", name, model))
+        }
+        Operation::AlterField { model, field, .. } => Some(format!(
+            "Changed type of field {} of model {}",
+            field.name, model
+        )),
+        Operation::AlterEnum { model, field, .. } => Some(format!(
+            "Changed choices of field {} of model {}",
+            field.name, model
+        )),
+        Operation::AddIdentity { model, column, .. }
+        | Operation::SetIdentity { model, column, .. }
+        | Operation::DropIdentity { model, column } => Some(format!(
+            "Changed identity of field {} of model {}",
+            column, model
+        )),
+        Operation::SetTablespace { model, .. }
+        | Operation::SetStorageParameters { model, .. }
+        | Operation::SetCharset { model, .. } => {
+            Some(format!("Changed storage options of model {}", model))
+        }
+        Operation::CreateUnique { model, .. } | Operation::DropUnique { model, .. } => {
+            Some(format!("Changed unique constraints of model {}", model))
+        }
+        Operation::SetInherit { model, parent } => {
+            Some(format!("Model {} inherits from {}", model, parent))
+        }
+        Operation::NoInherit { model, parent } => Some(format!(
+            "Model {} no longer inherits from {}",
+            model, parent
+        )),
+        // Full-text indexes are part of their fields
+        Operation::CreateIndex {
+            model,
+            name,
+            kind: IndexKind::Index,
+            ..
+        } => Some(format!("Created index {} of model {}", name, model)),
+        Operation::DropIndex { model, name, .. } => {
+            Some(format!("Dropped index {} of model {}", name, model))
+        }
+        Operation::CreateExtension { name, .. } => Some(format!("Created extension {}", name)),
+        Operation::DropExtension { name, .. } => Some(format!("Dropped extension {}", name)),
+        _ => None,
+    }
+}
+
+/**
+Returns a warning, if the operation can lose data.
+
+`operation`: [&Operation]: The operation to check.
+*/
+pub fn data_loss_warning(operation: &Operation) -> Option<String> {
+    match operation.inner() {
+        Operation::DeleteModel { name, cascade } => Some(format!(
+            "Deleting model {} drops the table {} including all of its data{}",
+            name,
+            name,
+            if *cascade {
+                " and the objects depending on it"
+            } else {
+                ""
+            }
+        )),
+        Operation::DeleteField {
+            model,
+            name,
+            cascade,
+        } => Some(format!(
+            "Deleting field {}.{} drops the column {} including all of its data{}",
+            model,
+            name,
+            name,
+            if *cascade {
+                " and the objects depending on it"
+            } else {
+                ""
+            }
+        )),
+        Operation::AlterField {
+            model,
+            field,
+            change,
+        } if change.is_unsafe() => Some(format!(
+            "Changing the type of field {}.{} is {}, existing values may be lost or fail to convert. Applying it requires migrate --allow-unsafe",
+            model, field.name, change
+        )),
+        _ => None,
+    }
+}
+
+/**
+Returns a warning, if the operation adds a not null field whose default is only known to the orm.
+
+Client defaults are not emitted in the DDL, so the database can't fill the column of existing rows.
+
+`operation`: [&Operation]: The operation to check.
+*/
+pub fn client_default_warning(operation: &Operation) -> Option<String> {
+    match operation {
+        Operation::CreateField { model, field } => {
+            let has = |f: fn(&Annotation) -> bool| field.annotations.iter().any(f);
+            (has(|x| matches!(x, Annotation::NotNull))
+                && has(|x| matches!(x, Annotation::ClientDefault))
+                && !has(|x| {
+                    matches!(
+                        x,
+                        Annotation::DefaultValue(_)
+                            | Annotation::ServerDefault(_)
+                            | Annotation::AutoCreateTime
+                    )
+                }))
+            .then(|| {
+                format!(
+                    "Field {}.{} is not null but only has a client default, adding it fails for tables with existing rows. Consider a server_default.",
+                    model, field.name
+                )
+            })
+        }
+        _ => None,
+    }
+}
+
+/**
+Returns a warning suggesting to squash migrations, if the history has grown too long.
+
+The check is skipped in non-interactive mode, unless it has been enabled explicitly.
+
+`options`: [&MakeMigrationsOptions]: The options of the current run.
+`migration_count`: [usize]: Number of migrations, including the one that has been written.
+*/
+pub fn squash_warning(options: &MakeMigrationsOptions, migration_count: usize) -> Option<String> {
+    if options.warnings_disabled || (options.non_interactive && !options.squash_check) {
+        return None;
+    }
+
+    (migration_count > options.squash_threshold).then(|| {
+        format!(
+            "There are {} migrations, exceeding the threshold of {}. Consider squashing them with drorm squash-migrations.",
+            migration_count, options.squash_threshold
+        )
+    })
+}
+
+/**
+Prints warnings for operations of a migration that can lose data and asks
+for confirmation of each, if required.
+
+Returns whether the migration should be written.
+
+`options`: [&MakeMigrationsOptions]: The options of the current run.
+`migration`: [&Migration]: The migration that should be written.
+*/
+pub fn confirm_data_loss(
+    options: &MakeMigrationsOptions,
+    migration: &Migration,
+) -> anyhow::Result<bool> {
+    for warning in migration.operations.iter().filter_map(data_loss_warning) {
+        if options.non_interactive {
+            if !options.warnings_disabled {
+                info!("WARNING: {}", warning);
+            }
+            continue;
+        }
+
+        info!("WARNING: {}", warning);
+        if !question("Do you want to include this operation in the migration?")? {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/**
+Prints the summary of the changes of a migration and asks for confirmation, if required.
+
+Returns whether the migration should be written.
+
+`options`: [&MakeMigrationsOptions]: The options of the current run.
+`migration`: [&Migration]: The migration that should be written.
+*/
+pub fn confirm_migration(
+    options: &MakeMigrationsOptions,
+    migration: &Migration,
+) -> anyhow::Result<bool> {
+    if !options.verbose_diff {
+        return Ok(true);
+    }
+
+    info!("Detected changes for migration {}:", migration.id);
+    migration
+        .operations
+        .iter()
+        .for_each(|x| info!("    {}", paint_diff_line(&operation_to_diff_line(x))));
+
+    if options.non_interactive {
+        return Ok(true);
+    }
+
+    question("Do you want to write this migration?")
+}
+
+/**
+Detects the operations migrating the old state of the models to the new one.
+
+Renames are applied first, so the remaining changes are detected against the new names.
+Returns the operations and the warnings and notes about the changes.
+The postgres extensions required by the new models are not created, see [extension_operations].
+
+`old`: [InternalModelFormat]: The old state of the models, e.g. resulting from the existing migrations.
+`new`: [&InternalModelFormat]: The new state of the models.
+`renames`: [Vec<Operation>]: Declared renames of models and fields, see [apply_renames].
+`cascade`: [bool]: Whether deleted models and fields drop the objects depending on them.
+`rename_constraints`: [bool]: Whether renamed models rename their auto-named constraints.
+*/
+pub fn diff_models(
+    mut old: InternalModelFormat,
+    new: &InternalModelFormat,
+    renames: Vec<Operation>,
+    cascade: bool,
+    rename_constraints: bool,
+) -> anyhow::Result<(Vec<Operation>, Vec<String>)> {
+    let mut warnings = vec![];
+    // Renames are applied first, so the remaining changes are detected against the new names
+    let mut op: Vec<Operation> = apply_renames(&mut old, renames)?;
+    if rename_constraints {
+        for operation in op.iter_mut() {
+            if let Operation::RenameModel {
+                rename_constraints, ..
+            } = operation
+            {
+                *rename_constraints = true;
+            }
+        }
+    }
+    let applied_renames = op.clone();
+
+    let old_lookup: HashMap<String, &Model> =
+        old.models.iter().map(|x| (x.name.clone(), x)).collect();
+
+    let new_lookup: HashMap<String, &Model> =
+        new.models.iter().map(|x| (x.name.clone(), x)).collect();
+
+    let mut new_models: Vec<&Model> = vec![];
+    let mut deleted_models: Vec<&Model> = vec![];
+
+    let mut new_fields: HashMap<String, Vec<&Field>> = HashMap::new();
+    let mut deleted_fields: HashMap<String, Vec<&Field>> = HashMap::new();
+
+    // Check if any new models exist
+    new.models.iter().for_each(|x| {
+        if !old_lookup.iter().any(|(a, _)| x.name == *a) {
+            new_models.push(x);
+        }
+    });
+
+    // Check if any old model got deleted
+    old.models.iter().for_each(|x| {
+        if !new_lookup.iter().any(|(a, _)| x.name == *a) {
+            deleted_models.push(x);
+        }
+    });
+
+    // Iterate over all models, that are in the old
+    // as well as in the new internal models
+    new.models
+        .iter()
+        .filter(|x| old_lookup.contains_key(x.name.as_str()))
+        .for_each(|x| {
+            // Check if a new field has been added
+            x.fields.iter().for_each(|y| {
+                if !old_lookup[x.name.as_str()]
+                    .fields
+                    .iter()
+                    .any(|z| z.name == y.name)
+                {
+                    new_fields.entry(x.name.clone()).or_default().push(y);
+                }
+            });
+
+            // Check if a existing field got deleted
+            old_lookup[x.name.as_str()].fields.iter().for_each(|y| {
+                if !x.fields.iter().any(|z| z.name == y.name) {
+                    deleted_fields.entry(x.name.clone()).or_default().push(y);
+                }
+            });
+        });
+
+    // Create migration operations for new models
+    let mut foreign_keys = vec![];
+    parents_first(new_models).iter().for_each(|x| {
+        let (fields, mut operations) = split_foreign_keys(x.name.as_str(), &x.fields);
+        op.push(Operation::CreateModel {
+            name: x.name.clone(),
+            fields,
+            annotations: table_annotations(x),
+        });
+        foreign_keys.append(&mut operations);
+        foreign_keys.append(&mut full_text_indexes(x.name.as_str(), &x.fields));
+        foreign_keys.append(&mut create_index_operations(x));
+    });
+
+    // Create migration operations for deleted models
+    deleted_models.iter().for_each(|x| {
+        op.push(Operation::DeleteModel {
+            name: x.name.clone(),
+            cascade,
+        });
+    });
+
+    // Create migration operations for new fields in existing models
+    new_fields.iter().for_each(|(x, y)| {
+        y.iter().for_each(|z| {
+            let (mut fields, mut operations) =
+                split_foreign_keys(x.as_str(), std::slice::from_ref(*z));
+            let operation = Operation::CreateField {
+                model: x.clone(),
+                field: fields.remove(0),
+            };
+            foreign_keys.append(&mut operations);
+            foreign_keys.append(&mut full_text_indexes(x.as_str(), std::slice::from_ref(*z)));
+            if let Some(warning) = client_default_warning(&operation) {
+                warnings.push(warning);
+            }
+            op.push(restrict(operation, field_dialects(z)));
+        })
+    });
+
+    // Create migration operations for deleted fields in existing models
+    deleted_fields.iter().for_each(|(x, y)| {
+        y.iter().for_each(|z| {
+            op.push(restrict(
+                Operation::DeleteField {
+                    model: x.clone(),
+                    name: z.name.clone(),
+                    cascade,
+                },
+                field_dialects(z),
+            ));
+        })
+    });
+
+    // Foreign keys and indexes are created after all new models and fields exist
+    op.append(&mut foreign_keys);
+
+    // Create migration operations for changed types and identities of existing fields
+    new.models
+        .iter()
+        .filter(|x| old_lookup.contains_key(x.name.as_str()))
+        .for_each(|x| {
+            for field in &x.fields {
+                if let Some(old) = old_lookup[x.name.as_str()]
+                    .fields
+                    .iter()
+                    .find(|y| y.name == field.name)
+                {
+                    op.extend(type_operation(x.name.as_str(), old, field));
+                    op.extend(enum_operations(x.name.as_str(), old, field));
+                    warnings.extend(enum_note(x.name.as_str(), old, field));
+                    op.extend(identity_operation(x.name.as_str(), old, field));
+                }
+            }
+        });
+
+    // Create migration operations for changed tablespaces, storage parameters, parents, unique columns and indexes
+    new.models
+        .iter()
+        .filter(|x| old_lookup.contains_key(x.name.as_str()))
+        .for_each(|x| {
+            warnings.extend(persistence_note(old_lookup[x.name.as_str()], x));
+            warnings.extend(partition_note(old_lookup[x.name.as_str()], x));
+            storage_operations(old_lookup[x.name.as_str()], x)
+                .into_iter()
+                .for_each(|y| {
+                    op.push(y);
+                });
+            op.append(&mut inherit_operations(old_lookup[x.name.as_str()], x));
+            op.append(&mut unique_together_operations(
+                old_lookup[x.name.as_str()],
+                x,
+                &applied_renames,
+            ));
+            op.append(&mut index_operations(
+                old_lookup[x.name.as_str()],
+                x,
+                &applied_renames,
+            ));
+        });
+
+    Ok((op, warnings))
+}
+
+/**
+Generates the next migration from the models file, without writing it.
+
+`None` is returned, if the models haven't changed since the last migration.
+
+`options`: [&MakeMigrationsOptions]: The options of the run.
+`renames`: [&RenameMap]: Renames in addition to the `rename_from` annotations of the models.
+*/
+fn generate_migration(
+    options: &MakeMigrationsOptions,
+    renames: &RenameMap,
+) -> anyhow::Result<Option<GeneratedMigration>> {
+    if let Some(command) = &options.models_command {
+        regenerate_models(command.as_str(), &options.models_files)?;
+    }
+    check_options(options).with_context(|| "Error while checking options")?;
+
+    let mut internal_models = get_merged_internal_models(&options.models_files)
+        .with_context(|| "Couldn't retrieve internal model files.")?;
+
+    let existing_migrations = match Path::new(options.migration_dir.as_str()).exists() {
+        true => get_existing_migrations(options.migration_dir.as_str(), options.recursive)
+            .with_context(|| "An error occurred while deserializing migrations")?,
+        false => vec![],
+    };
+
+    // The names are converted before anything else refers to them, e.g. the timestamp fields
+    let recorded = recorded_naming_policy(&existing_migrations);
+    let naming_policy = options.naming_policy.or(recorded).unwrap_or_default();
+    let models_files = internal_models.clone();
+    apply_naming_policy(naming_policy, &mut internal_models);
+
+    if options.auto_timestamps {
+        apply_auto_timestamps(&mut internal_models);
+    }
+    check_unique_together(&internal_models)?;
+    check_referential_actions(&internal_models)?;
+    let mut declared = take_renames(&mut internal_models);
+    declared.append(&mut renames.operations());
+    let mut renames = declared;
+    let mut warnings = vec![];
+
+    let h = models_hash(&internal_models);
+
+    if !existing_migrations.is_empty() {
+        let last_migration = &existing_migrations[existing_migrations.len() - 1];
+
+        // If hash matches with the one of the current models, exiting
+        if last_migration.hash == h {
+            return Ok(None);
+        }
+
+        let constructed = convert_migrations_to_internal_models(&existing_migrations)?;
+
+        if let Some(recorded) = recorded.filter(|x| *x != naming_policy) {
+            let policy_renames =
+                naming_policy_renames(recorded, naming_policy, &models_files, &constructed);
+            if !policy_renames.models.is_empty() || !policy_renames.fields.is_empty() {
+                warnings.push(format!(
+                    "The naming policy changed from {} to {}, which renames {} tables and {} columns",
+                    recorded,
+                    naming_policy,
+                    policy_renames.models.len(),
+                    policy_renames.fields.len()
+                ));
+            }
+            // The tables and columns are renamed before the declared renames refer to them
+            renames.splice(0..0, policy_renames.operations());
+        }
+
+        let number = next_migration_number(
+            options.id_scheme,
+            options.id_width,
+            Some(last_migration),
+            SystemTime::now(),
+        )?;
+        if let Some(warning) =
+            id_width_warning(&existing_migrations, number.as_str(), options.id_width)
+        {
+            warnings.push(warning);
+        }
+        let name = match &options.name {
+            None => format!("{}_placeholder", number),
+            Some(n) => format!("{}_{}", number, n),
+        };
+
+        let (mut op, mut diff_warnings) = diff_models(
+            constructed,
+            &internal_models,
+            renames,
+            options.cascade,
+            options.rename_constraints,
+        )?;
+        op.splice(
+            0..0,
+            extension_operations(&existing_migrations, &internal_models),
+        );
+        warnings.append(&mut diff_warnings);
+
+        // Fields are compared by name, so e.g. reordered fields are no change.
+        // Changes without operations, which are reported as notes, are still written.
+        if op.is_empty() && warnings.is_empty() {
+            return Ok(None);
+        }
+
+        let new_migration = Migration {
+            hash: h,
+            initial: false,
+            id: name.clone(),
+            dependency: last_migration.id.clone(),
+            external_dependencies: vec![],
+            merges: vec![],
+            replaces: vec![],
+            atomic: true,
+            operations: op,
+            precondition: None,
+            lock_timeout_ms: None,
+            naming_policy: Some(naming_policy),
+            meta: Some(audit_info(
+                options.description.as_deref(),
+                SystemTime::now(),
+            )),
+        };
+
+        Ok(Some(GeneratedMigration {
+            migration: new_migration,
+            warnings,
+            migration_count: existing_migrations.len() + 1,
+        }))
+    } else {
+        // New migration must be generated as no migration exists
+
+        let number =
+            next_migration_number(options.id_scheme, options.id_width, None, SystemTime::now())?;
+        let name = match &options.name {
+            None => format!("{}_initial", number),
+            Some(n) => format!("{}_{}", number, n),
+        };
+
+        let new_migration = Migration {
+            hash: h,
+            initial: true,
+            id: name.clone(),
+            dependency: "".to_string(),
+            external_dependencies: vec![],
+            merges: vec![],
+            replaces: vec![],
+            atomic: true,
+            operations: create_models_operations(&internal_models),
+            precondition: None,
+            lock_timeout_ms: None,
+            naming_policy: Some(naming_policy),
+            meta: Some(audit_info(
+                options.description.as_deref(),
+                SystemTime::now(),
+            )),
+        };
+
+        Ok(Some(GeneratedMigration {
+            migration: new_migration,
+            warnings,
+            migration_count: 1,
+        }))
+    }
+}
+
+/**
+Creates the operations of an initial migration, which creates the models from scratch.
+
+The extensions required by the models are created first, followed by the models,
+parents first. Foreign keys and indexes are created after all models exist.
+
+`internal_models`: [&InternalModelFormat]: The models to create.
+*/
+pub fn create_models_operations(internal_models: &InternalModelFormat) -> Vec<Operation> {
+    let mut operations = extension_operations(&[], internal_models);
+    let mut foreign_keys = vec![];
+    for x in parents_first(internal_models.models.iter().collect()) {
+        let (fields, mut split) = split_foreign_keys(x.name.as_str(), &x.fields);
+        operations.push(Operation::CreateModel {
+            name: x.name.clone(),
+            fields: fields
+                .into_iter()
+                .map(|y| Field {
+                    source_defined_at: None,
+                    ..y
+                })
+                .collect(),
+            annotations: table_annotations(x),
+        });
+        foreign_keys.append(&mut split);
+        foreign_keys.append(&mut full_text_indexes(x.name.as_str(), &x.fields));
+        foreign_keys.append(&mut create_index_operations(x));
+    }
+    // Foreign keys and indexes are created after all models exist
+    operations.append(&mut foreign_keys);
+    operations
+}
+
+/**
+Detects the changes between two models files, instead of between the existing migrations
+and the models files.
+
+The options are applied to both files, e.g. the naming policy, which defaults to snake_case.
+The `rename_from` annotations of the new models are taken into account.
+Returns the operations and the warnings and notes about the changes, see [diff_models].
+
+`options`: [&MakeMigrationsOptions]: The options of the run.
+`from_models`: [&str]: Path to the models file of the old state.
+`to_models`: [&str]: Path to the models file of the new state.
+*/
+pub fn diff_models_files(
+    options: &MakeMigrationsOptions,
+    from_models: &str,
+    to_models: &str,
+) -> anyhow::Result<(Vec<Operation>, Vec<String>)> {
+    let naming_policy = options.naming_policy.unwrap_or_default();
+    let read = |models_file: &str| {
+        let mut internal_models = get_internal_models(models_file)
+            .with_context(|| format!("Couldn't retrieve models file {}", models_file))?;
+        apply_naming_policy(naming_policy, &mut internal_models);
+        if options.auto_timestamps {
+            apply_auto_timestamps(&mut internal_models);
+        }
+        check_unique_together(&internal_models)?;
+        check_referential_actions(&internal_models)?;
+        anyhow::Ok(internal_models)
+    };
+    let mut old = read(from_models)?;
+    let mut new = read(to_models)?;
+
+    // Leftover rename hints of the old state are no change
+    take_renames(&mut old);
+    let renames = take_renames(&mut new);
+    let required = required_extensions(&old);
+
+    let (mut operations, warnings) = diff_models(
+        old,
+        &new,
+        renames,
+        options.cascade,
+        options.rename_constraints,
+    )?;
+    let mut extensions = extension_operations(&[], &new);
+    extensions.retain(|x| match x.inner() {
+        Operation::CreateExtension { name, .. } => !required.contains(&name.as_str()),
+        _ => true,
+    });
+    operations.splice(0..0, extensions);
+
+    Ok((operations, warnings))
+}
+
+/**
+Prints the detected changes of a migration and its warnings, e.g. for `--dry-run`.
+
+`options`: [&MakeMigrationsOptions]: The options of the run.
+`title`: [&str]: What the changes are of, printed before them.
+`operations`: [&\[Operation\]]: The detected operations.
+`warnings`: [&\[String\]]: The warnings about the changes.
+*/
+fn print_dry_run(
+    options: &MakeMigrationsOptions,
+    title: &str,
+    operations: &[Operation],
+    warnings: &[String],
+) {
+    println!("{}", title);
+    for operation in operations {
+        println!(
+            "    {}",
+            paint_diff_line(&operation_to_diff_line(operation))
+        );
+    }
+    if !options.warnings_disabled {
+        for warning in warnings {
+            info!("Warning: {}", warning);
+        }
+    }
+}
+
+/**
+Prints the changes between two models files without reading or writing migrations,
+see [diff_models_files].
+
+`options`: [&MakeMigrationsOptions]: The options of the run.
+`from_models`: [&str]: Path to the models file of the old state.
+`to_models`: [&str]: Path to the models file of the new state.
+*/
+pub fn run_diff_models_files(
+    options: &MakeMigrationsOptions,
+    from_models: &str,
+    to_models: &str,
+) -> anyhow::Result<Vec<Operation>> {
+    let (operations, mut warnings) = diff_models_files(options, from_models, to_models)?;
+    if operations.is_empty() && warnings.is_empty() {
+        info!("No changes - nothing to do.");
+        return Ok(operations);
+    }
+
+    warnings.extend(operations.iter().filter_map(data_loss_warning));
+    print_dry_run(
+        options,
+        format!("Changes from {} to {}:", from_models, to_models).as_str(),
+        &operations,
+        &warnings,
+    );
+    Ok(operations)
+}
+
+/**
+Writes a generated migration to the migration directory.
+
+`options`: [&MakeMigrationsOptions]: The options of the run.
+`generated`: [GeneratedMigration]: The migration to write.
+`warnings`: [Vec<String>]: The warnings of the migration, the squash warning is added to them.
+*/
+fn write_migration(
+    options: &MakeMigrationsOptions,
+    generated: GeneratedMigration,
+    mut warnings: Vec<String>,
+) -> anyhow::Result<MakeMigrationsResult> {
+    let path =
+        Path::new(options.migration_dir.as_str()).join(format!("{}.toml", generated.migration.id));
+    let operations = generated.migration.operations.clone();
+    convert_migration_to_file(generated.migration, &path)
+        .with_context(|| "Error occurred while converting migration to file")?;
+
+    if let Some(warning) = squash_warning(options, generated.migration_count) {
+        warnings.push(warning);
+    }
+    Ok(MakeMigrationsResult {
+        written: Some(path),
+        operations,
+        warnings,
+    })
+}
+
+/**
+Generates and writes the next migration without asking any questions.
+
+This is the library interface of the make-migrations tool, e.g. for build scripts.
+Nothing is printed, the warnings are returned instead, including the warnings about lost data.
+Migrations which lose data are written nonetheless, check the warnings or operations before
+applying them.
+
+`options`: [&MakeMigrationsOptions]: The options of the run, the interactive options are ignored.
+`renames`: [&RenameMap]: Renames in addition to the `rename_from` annotations of the models.
+*/
+pub fn make_migrations(
+    options: &MakeMigrationsOptions,
+    renames: &RenameMap,
+) -> anyhow::Result<MakeMigrationsResult> {
+    let generated = match generate_migration(options, renames)? {
+        None => return Ok(MakeMigrationsResult::default()),
+        Some(generated) => generated,
+    };
+
+    let mut warnings = generated.warnings.clone();
+    warnings.extend(
+        generated
+            .migration
+            .operations
+            .iter()
+            .filter_map(data_loss_warning),
+    );
+    write_migration(options, generated, warnings)
+}
+
+/**
+Runs the make-migrations tool.
+
+Prints the detected changes and warnings and asks for confirmation, unless it's non-interactive.
+
+`options`: [MakeMigrationsOptions]: The options of the run.
+*/
+pub fn run_make_migrations(options: MakeMigrationsOptions) -> anyhow::Result<MakeMigrationsResult> {
+    let generated = match generate_migration(&options, &RenameMap::default())? {
+        None => {
+            info!("No changes - nothing to do.");
+            return Ok(MakeMigrationsResult::default());
+        }
+        Some(generated) => generated,
+    };
+
+    generated
+        .migration
+        .operations
+        .iter()
+        .filter_map(change_message)
+        .for_each(|x| info!("{}", x));
+    if !options.warnings_disabled {
+        for warning in &generated.warnings {
+            info!("Warning: {}", warning);
+        }
+    }
+
+    let mut warnings = generated.warnings.clone();
+    warnings.extend(
+        generated
+            .migration
+            .operations
+            .iter()
+            .filter_map(data_loss_warning),
+    );
+    if options.dry_run {
+        print_dry_run(
+            &options,
+            format!(
+                "Changes of migration {}, not written:",
+                generated.migration.id
+            )
+            .as_str(),
+            &generated.migration.operations,
+            &warnings[generated.warnings.len()..],
+        );
+        return Ok(MakeMigrationsResult {
+            written: None,
+            operations: generated.migration.operations,
+            warnings,
+        });
+    }
+    if !confirm_data_loss(&options, &generated.migration)?
+        || !confirm_migration(&options, &generated.migration)?
+    {
+        info!("Aborted, no migration has been written.");
+        return Ok(MakeMigrationsResult {
+            written: None,
+            operations: generated.migration.operations,
+            warnings,
+        });
+    }
+
+    let count = warnings.len();
+    let result = write_migration(&options, generated, warnings)?;
+    if let Some(path) = &result.written {
+        let summary = MigrationSummary::new(path, &result.operations);
+        if options.json {
+            println!(
+                "{}",
+                serde_json::to_string(&summary).with_context(|| "Couldn't serialize summary")?
+            );
+        } else {
+            info!("{}", summary);
+        }
+    }
+    for warning in &result.warnings[count..] {
+        info!("Warning: {}", warning);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{
+        Annotation, DbType, Dialect, Field, ForeignKey, Identity, IndexValue, InternalModelFormat,
+        Model, ModelAnnotation, PartitionBy, PartitionStrategy, Persistence, ReferentialAction,
+        Source, StorageParameter,
+    };
+
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::declaration::{
+        EnumAction, IndexKind, Migration, NamingPolicy, Operation, TypeChange,
+    };
+    use crate::make_migrations::{
+        apply_auto_timestamps, apply_renames, check_referential_actions, client_default_warning,
+        coalesce_renames, create_index_operations, data_loss_warning, diff_models_files, enum_note,
+        enum_operations, extension_operations, format_timestamp, full_text_indexes,
+        id_width_warning, identity_operation, index_operations, inherit_operations, is_legacy_hash,
+        is_valid_hash, make_migrations, merge_internal_models, model_indexes, models_hash,
+        next_migration_number, operation_to_diff_line, parents_first, partition_note,
+        persistence_note, required_extensions, run_make_migrations, split_foreign_keys,
+        squash_warning, storage_operations, take_renames, type_operation, unique_together_name,
+        unique_together_operations, IdScheme, MakeMigrationsOptions, MakeMigrationsResult,
+        MigrationSummary, RenameMap,
+    };
+    use crate::utils::migrations::convert_str_to_migration;
+
+    #[test]
+    fn data_loss() {
+        assert!(data_loss_warning(&Operation::DeleteModel {
+            name: "user".to_string(),
+            cascade: false,
+        })
+        .is_some());
+        assert!(data_loss_warning(&Operation::DeleteField {
+            model: "user".to_string(),
+            name: "age".to_string(),
+            cascade: false,
+        })
+        .is_some());
+        assert!(data_loss_warning(&Operation::RenameModel {
+            old: "user".to_string(),
+            new: "account".to_string(),
+            rename_constraints: false,
+        })
+        .is_none());
+    }
+
+    #[test]
+    fn foreign_keys() {
+        let foreign_key = ForeignKey {
+            table_name: "user".to_string(),
+            column_name: "id".to_string(),
+            deferrable: true,
+            initially_deferred: false,
+            on_delete: ReferentialAction::NoAction,
+            on_update: ReferentialAction::NoAction,
+        };
+        let fields = vec![Field {
+            name: "owner".to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![
+                Annotation::NotNull,
+                Annotation::ForeignKey(foreign_key.clone()),
+            ],
+            source_defined_at: None,
+        }];
+
+        let (fields, operations) = split_foreign_keys("post", &fields);
+        assert!(matches!(fields[0].annotations[..], [Annotation::NotNull]));
+        match &operations[..] {
+            [Operation::CreateForeignKey {
+                model,
+                name,
+                column,
+                foreign_key: f,
+                ..
+            }] => {
+                assert_eq!(model, "post");
+                assert_eq!(name, "post_owner_fkey");
+                assert_eq!(column, "owner");
+                assert_eq!(*f, foreign_key);
+            }
+            _ => panic!("Expected a single CreateForeignKey operation"),
+        }
+
+        // Foreign keys of fields restricted to some dialects are restricted as well
+        let fields = vec![Field {
+            name: "owner".to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![
+                Annotation::Only(vec![Dialect::Postgres]),
+                Annotation::ForeignKey(foreign_key),
+            ],
+            source_defined_at: None,
+        }];
+        let (_, operations) = split_foreign_keys("post", &fields);
+        assert_eq!(
+            operations
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<_>>(),
+            vec!["+ foreign key post.post_owner_fkey (only postgres)"]
+        );
+    }
+
+    #[test]
+    fn full_text() {
+        let field = |name: &str, annotations| Field {
+            name: name.to_string(),
+            db_type: DbType::VarChar,
+            annotations,
+            source_defined_at: None,
+        };
+        let fields = vec![
+            field("title", vec![Annotation::FullText]),
+            field("slug", vec![]),
+            field(
+                "body",
+                vec![
+                    Annotation::FullText,
+                    Annotation::Only(vec![Dialect::Postgres]),
+                ],
+            ),
+        ];
+
+        let operations = full_text_indexes("post", &fields);
+        assert_eq!(
+            operations
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<String>>(),
+            vec![
+                "+ index post.post_title_fulltext_idx",
+                "+ index post.post_body_fulltext_idx (only postgres)"
+            ]
+        );
+        assert!(matches!(
+            operations[0],
+            Operation::CreateIndex {
+                kind: IndexKind::FullText,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn client_default() {
+        let create_field = |annotations| Operation::CreateField {
+            model: "user".to_string(),
+            field: Field {
+                name: "token".to_string(),
+                db_type: DbType::VarChar,
+                annotations,
+                source_defined_at: None,
+            },
+        };
+
+        assert!(client_default_warning(&create_field(vec![
+            Annotation::ClientDefault,
+            Annotation::NotNull
+        ]))
+        .is_some());
+        assert!(client_default_warning(&create_field(vec![
+            Annotation::ClientDefault,
+            Annotation::NotNull,
+            Annotation::ServerDefault("gen_random_uuid()".to_string())
+        ]))
+        .is_none());
+        assert!(client_default_warning(&create_field(vec![Annotation::ClientDefault])).is_none());
+    }
+
+    #[test]
+    fn auto_timestamps() {
+        let field = |name: &str, db_type, annotations| Field {
+            name: name.to_string(),
+            db_type,
+            annotations,
+            source_defined_at: None,
+        };
+        let mut models = InternalModelFormat {
+            models: vec![Model {
+                name: "post".to_string(),
+                fields: vec![
+                    field("created_at", DbType::Datetime, vec![Annotation::NotNull]),
+                    field("updated_at", DbType::Timestamp, vec![]),
+                    field("published_at", DbType::Datetime, vec![]),
+                    field("created_at_", DbType::Datetime, vec![]),
+                    field(
+                        "updated_at",
+                        DbType::Datetime,
+                        vec![Annotation::ServerDefault("now()".to_string())],
+                    ),
+                    field("created_at", DbType::Int64, vec![]),
+                ],
+                annotations: vec![],
+                source_defined_at: None,
+            }],
+        };
+        apply_auto_timestamps(&mut models);
+
+        let annotations: Vec<_> = models.models[0]
+            .fields
+            .iter()
+            .map(|x| x.annotations.as_slice())
+            .collect();
+        assert!(matches!(
+            annotations[0],
+            [Annotation::NotNull, Annotation::AutoCreateTime]
+        ));
+        assert!(matches!(annotations[1], [Annotation::AutoUpdateTime]));
+        // Other names, explicit defaults and other types are kept
+        assert!(annotations[2].is_empty());
+        assert!(annotations[3].is_empty());
+        assert!(matches!(annotations[4], [Annotation::ServerDefault(_)]));
+        assert!(annotations[5].is_empty());
+    }
+
+    #[test]
+    fn identity_changes() {
+        let field = |annotations| Field {
+            name: "id".to_string(),
+            db_type: DbType::Int64,
+            annotations,
+            source_defined_at: None,
+        };
+        let serial = field(vec![Annotation::PrimaryKey, Annotation::AutoIncrement]);
+        let always = field(vec![
+            Annotation::PrimaryKey,
+            Annotation::Identity(Identity::Always),
+        ]);
+        let by_default = field(vec![
+            Annotation::PrimaryKey,
+            Annotation::Identity(Identity::ByDefault),
+        ]);
+
+        assert!(identity_operation("user", &serial, &serial).is_none());
+        assert!(identity_operation("user", &always, &always).is_none());
+        assert!(matches!(
+            identity_operation("user", &serial, &always),
+            Some(Operation::AddIdentity {
+                identity: Identity::Always,
+                from_serial: true,
+                ..
+            })
+        ));
+        assert!(matches!(
+            identity_operation("user", &always, &by_default),
+            Some(Operation::SetIdentity {
+                identity: Identity::ByDefault,
+                ..
+            })
+        ));
+        assert!(matches!(
+            identity_operation("user", &always, &serial),
+            Some(Operation::DropIdentity { .. })
+        ));
+    }
+
+    #[test]
+    fn type_changes() {
+        let field = |db_type, annotations| Field {
+            name: "age".to_string(),
+            db_type,
+            annotations,
+            source_defined_at: None,
+        };
+        let varchar = |l| field(DbType::VarChar, vec![Annotation::MaxLength(l)]);
+        let classify = |old: &Field, new: &Field| TypeChange::classify(old, new);
+
+        // Widening
+        for (old, new) in [
+            (DbType::Int32, DbType::Int64),
+            (DbType::Int8, DbType::Int16),
+            (DbType::UInt16, DbType::UInt32),
+            (DbType::UInt32, DbType::Int64),
+            (DbType::Float, DbType::Double),
+        ] {
+            assert_eq!(
+                classify(&field(old, vec![]), &field(new, vec![])),
+                Some(TypeChange::Widening)
+            );
+        }
+        assert_eq!(
+            classify(&varchar(100), &varchar(255)),
+            Some(TypeChange::Widening)
+        );
+        assert_eq!(
+            classify(&varchar(100), &field(DbType::VarChar, vec![])),
+            Some(TypeChange::Widening)
+        );
+
+        // Narrowing
+        for (old, new) in [
+            (DbType::Int64, DbType::Int32),
+            (DbType::UInt64, DbType::UInt8),
+            (DbType::Int32, DbType::UInt64),
+            (DbType::UInt32, DbType::Int32),
+            (DbType::Double, DbType::Float),
+        ] {
+            assert_eq!(
+                classify(&field(old, vec![]), &field(new, vec![])),
+                Some(TypeChange::Narrowing)
+            );
+        }
+        assert_eq!(
+            classify(&varchar(255), &varchar(100)),
+            Some(TypeChange::Narrowing)
+        );
+
+        // Incompatible
+        for (old, new) in [
+            (DbType::VarChar, DbType::Int64),
+            (DbType::Int64, DbType::Double),
+            (DbType::Boolean, DbType::Int8),
+            (DbType::Datetime, DbType::Timestamp),
+        ] {
+            assert_eq!(
+                classify(&field(old, vec![]), &field(new, vec![])),
+                Some(TypeChange::Incompatible)
+            );
+        }
+
+        // Fixed length text is padded, fixed length binaries change their values
+        let fixed = |db_type, l| field(db_type, vec![Annotation::MaxLength(l)]);
+        assert_eq!(
+            classify(&fixed(DbType::Char, 2), &fixed(DbType::Char, 3)),
+            Some(TypeChange::Widening)
+        );
+        assert_eq!(
+            classify(&fixed(DbType::Binary, 16), &fixed(DbType::Binary, 20)),
+            Some(TypeChange::Incompatible)
+        );
+
+        // Unchanged
+        assert_eq!(classify(&varchar(100), &varchar(100)), None);
+        assert_eq!(
+            classify(&field(DbType::Int64, vec![]), &field(DbType::Int64, vec![])),
+            None
+        );
+
+        // Only the type and max_length are changed, the max_length is replaced in place
+        let old = field(
+            DbType::VarChar,
+            vec![Annotation::MaxLength(100), Annotation::NotNull],
+        );
+        let operation = type_operation("user", &old, &varchar(255)).unwrap();
+        match &operation {
+            Operation::AlterField {
+                model,
+                field,
+                change,
+            } => {
+                assert_eq!(model, "user");
+                assert_eq!(*change, TypeChange::Widening);
+                assert!(matches!(
+                    field.annotations.as_slice(),
+                    [Annotation::MaxLength(255), Annotation::NotNull]
+                ));
+            }
+            _ => panic!("expected an alter field operation"),
+        }
+        assert_eq!(
+            operation_to_diff_line(&operation),
+            "~ type user.age = VarChar(255) (widening)"
+        );
+        assert!(data_loss_warning(&operation).is_none());
+        assert!(type_operation("user", &old, &old).is_none());
+
+        let operation = type_operation("user", &varchar(255), &varchar(100)).unwrap();
+        assert!(data_loss_warning(&operation)
+            .unwrap()
+            .contains("--allow-unsafe"));
+    }
+
+    #[test]
+    fn inheritance() {
+        let model = |name: &str, parents: &[&str]| Model {
+            name: name.to_string(),
+            fields: vec![],
+            annotations: parents
+                .iter()
+                .map(|x| ModelAnnotation::Inherits(x.to_string()))
+                .collect(),
+            source_defined_at: None,
+        };
+
+        // Parents are created first
+        let (employee, person, manager) = (
+            model("employee", &["person"]),
+            model("person", &[]),
+            model("manager", &["employee"]),
+        );
+        assert_eq!(
+            parents_first(vec![&manager, &employee, &person])
+                .iter()
+                .map(|x| x.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["person", "employee", "manager"]
+        );
+
+        let operations = inherit_operations(
+            &model("employee", &["person"]),
+            &model("employee", &["account"]),
+        );
+        assert_eq!(
+            operations
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<_>>(),
+            vec![
+                "- inherit employee from person",
+                "+ inherit employee from account"
+            ]
+        );
+        assert!(inherit_operations(&employee, &employee).is_empty());
+    }
+
+    #[test]
+    fn storage_changes() {
+        let model = |annotations| Model {
+            name: "log".to_string(),
+            fields: vec![],
+            annotations,
+            source_defined_at: None,
+        };
+        let fillfactor = |value: &str| {
+            ModelAnnotation::StorageParameter(StorageParameter {
+                name: "fillfactor".to_string(),
+                value: value.to_string(),
+            })
+        };
+
+        assert!(storage_operations(
+            &model(vec![fillfactor("70")]),
+            &model(vec![fillfactor("70")])
+        )
+        .is_empty());
+
+        let operations = storage_operations(
+            &model(vec![fillfactor("70")]),
+            &model(vec![
+                ModelAnnotation::Tablespace("fast".to_string()),
+                fillfactor("80"),
+            ]),
+        );
+        assert!(matches!(
+            &operations[..],
+            [
+                Operation::SetTablespace { tablespace: Some(t), .. },
+                Operation::SetStorageParameters { set, reset, .. },
+            ] if t == "fast" && set.len() == 1 && set[0].value == "80" && reset.is_empty()
+        ));
+
+        let operations = storage_operations(&model(vec![fillfactor("70")]), &model(vec![]));
+        assert!(matches!(
+            &operations[..],
+            [Operation::SetStorageParameters { set, reset, .. }]
+                if set.is_empty() && reset == &vec!["fillfactor".to_string()]
+        ));
+
+        let operations = storage_operations(
+            &model(vec![ModelAnnotation::Charset("latin1".to_string())]),
+            &model(vec![ModelAnnotation::Collation("utf8mb4_bin".to_string())]),
+        );
+        assert!(matches!(
+            &operations[..],
+            [Operation::SetCharset { charset: None, collation: Some(c), .. }] if c == "utf8mb4_bin"
+        ));
+    }
+
+    #[test]
+    fn persistence_changes() {
+        let model = |annotations| Model {
+            name: "cache".to_string(),
+            fields: vec![],
+            annotations,
+            source_defined_at: None,
+        };
+        let unlogged = model(vec![ModelAnnotation::Persistence(Persistence::Unlogged)]);
+
+        assert!(persistence_note(&unlogged, &unlogged).is_none());
+        assert!(persistence_note(&model(vec![]), &model(vec![])).is_none());
+        assert!(persistence_note(&model(vec![]), &unlogged)
+            .unwrap()
+            .contains("from logged to unlogged"));
+
+        let partitioned = model(vec![ModelAnnotation::PartitionBy(PartitionBy {
+            strategy: PartitionStrategy::Range,
+            columns: vec!["created_at".to_string()],
+        })]);
+        assert!(partition_note(&partitioned, &partitioned).is_none());
+        assert!(partition_note(&model(vec![]), &partitioned).is_some());
+    }
+
+    #[test]
+    fn extension_changes() {
+        let field = |name: &str, annotations| Field {
+            name: name.to_string(),
+            db_type: DbType::VarChar,
+            annotations,
+            source_defined_at: None,
+        };
+        let models = InternalModelFormat {
+            models: vec![Model {
+                name: "user".to_string(),
+                fields: vec![
+                    field(
+                        "id",
+                        vec![Annotation::ServerDefault("UUID_GENERATE_V4()".to_string())],
+                    ),
+                    field(
+                        "password",
+                        vec![Annotation::ServerDefault(
+                            "crypt('secret', gen_salt('bf'))".to_string(),
+                        )],
+                    ),
+                    // Not a call of digest
+                    field(
+                        "hash",
+                        vec![Annotation::ServerDefault("md5_digest('')".to_string())],
+                    ),
+                    field(
+                        "token",
+                        vec![
+                            Annotation::ServerDefault("gen_random_bytes(16)".to_string()),
+                            Annotation::Only(vec![Dialect::SQLite]),
+                        ],
+                    ),
+                ],
+                source_defined_at: None,
+                annotations: vec![],
+            }],
+        };
+        assert_eq!(required_extensions(&models), vec!["pgcrypto", "uuid-ossp"]);
+
+        let existing = Migration {
+            hash: "".to_string(),
+            initial: true,
+            id: "0001_initial".to_string(),
+            dependency: "".to_string(),
+            external_dependencies: vec![],
+            merges: vec![],
+            replaces: vec![],
+            atomic: true,
+            operations: vec![Operation::CreateExtension {
+                name: "pgcrypto".to_string(),
+                if_not_exists: false,
+            }],
+            precondition: None,
+            lock_timeout_ms: None,
+            naming_policy: None,
+            meta: None,
+        };
+        let operations = extension_operations(&[existing], &models);
+        assert!(matches!(
+            operations.as_slice(),
+            [Operation::Only { dialects, operation }]
+                if dialects == &vec![Dialect::Postgres]
+                && matches!(
+                    operation.as_ref(),
+                    Operation::CreateExtension { name, if_not_exists: true } if name == "uuid-ossp"
+                )
+        ));
+        assert_eq!(
+            operation_to_diff_line(&operations[0]),
+            "+ extension uuid-ossp (only postgres)"
+        );
+    }
+
+    #[test]
+    fn unique_together_changes() {
+        let model = |unique: &[&[&str]]| Model {
+            name: "member".to_string(),
+            fields: vec![],
+            annotations: unique
+                .iter()
+                .map(|x| ModelAnnotation::UniqueTogether(x.iter().map(|y| y.to_string()).collect()))
+                .collect(),
+            source_defined_at: None,
+        };
+
+        // Reordering the columns of a declaration is no change
+        assert!(unique_together_operations(
+            &model(&[&["user", "group"]]),
+            &model(&[&["group", "user"]]),
+            &[]
+        )
+        .is_empty());
+        assert_eq!(
+            unique_together_name("member", &["user".to_string(), "group".to_string()]),
+            "member_group_user_key"
+        );
+
+        let operations = unique_together_operations(
+            &model(&[&["user", "group"]]),
+            &model(&[&["user", "role"]]),
+            &[],
+        );
+        assert_eq!(
+            operations
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<_>>(),
+            vec![
+                "- unique member(user, group)",
+                "+ unique member(user, role)"
+            ]
+        );
+        assert!(matches!(
+            &operations[1],
+            Operation::CreateUnique { name, columns, .. }
+                if name == "member_role_user_key" && columns[0] == "user"
+        ));
+
+        // Dropped constraints are dropped before the renames of the migration
+        let renames = vec![
+            Operation::RenameModel {
+                old: "membership".to_string(),
+                new: "member".to_string(),
+                rename_constraints: false,
+            },
+            Operation::RenameField {
+                table_name: "member".to_string(),
+                old: "team".to_string(),
+                new: "group".to_string(),
+            },
+        ];
+        assert!(matches!(
+            unique_together_operations(&model(&[&["user", "group"]]), &model(&[]), &renames).as_slice(),
+            [Operation::DropUnique { model, name, columns }]
+                if model == "membership" && name == "membership_team_user_key" && columns[1] == "team"
+        ));
+
+        // Kept constraints are renamed with their columns
+        assert!(matches!(
+            unique_together_operations(
+                &model(&[&["user", "group"]]),
+                &model(&[&["user", "group"]]),
+                &renames[1..]
+            )
+            .as_slice(),
+            [Operation::RenameIndex { model, old, new, columns, unique: true }]
+                if model == "member" && old == "member_team_user_key"
+                    && new == "member_group_user_key" && columns[1] == "group"
+        ));
+
+        // The constraint of a renamed model keeps the old model name
+        assert!(matches!(
+            unique_together_operations(
+                &model(&[&["user", "group"]]),
+                &model(&[&["user", "group"]]),
+                &renames
+            )
+            .as_slice(),
+            [Operation::RenameIndex { model, old, new, .. }]
+                if model == "member" && old == "membership_team_user_key"
+                    && new == "member_group_user_key"
+        ));
+
+        // unless postgres renamed it with the constraints of the model
+        let mut renames = renames;
+        renames[0] = Operation::RenameModel {
+            old: "membership".to_string(),
+            new: "member".to_string(),
+            rename_constraints: true,
+        };
+        assert!(matches!(
+            unique_together_operations(
+                &model(&[&["user", "group"]]),
+                &model(&[&["user", "group"]]),
+                &renames
+            )
+            .as_slice(),
+            [
+                Operation::Only { dialects: postgres, operation: first },
+                Operation::Only { dialects: others, operation: second },
+            ] if postgres == &[Dialect::Postgres]
+                && others == &[Dialect::SQLite, Dialect::MySQL]
+                && matches!(&**first, Operation::RenameIndex { old, .. } if old == "member_team_user_key")
+                && matches!(&**second, Operation::RenameIndex { old, .. } if old == "membership_team_user_key")
+        ));
+    }
+
+    #[test]
+    fn index_changes() {
+        let model = |indexes: &[&[&str]], field_index: bool| Model {
+            name: "event".to_string(),
+            fields: vec![Field {
+                name: "email".to_string(),
+                db_type: DbType::VarChar,
+                annotations: if field_index {
+                    vec![Annotation::Index(None)]
+                } else {
+                    vec![]
+                },
+                source_defined_at: None,
+            }],
+            annotations: indexes
+                .iter()
+                .map(|x| ModelAnnotation::Index(x.iter().map(|y| y.to_string()).collect()))
+                .collect(),
+            source_defined_at: None,
+        };
+
+        // The index of the field duplicates the declared one
+        let created =
+            create_index_operations(&model(&[&["email"], &["tenant_id", "created_at"]], true));
+        assert_eq!(
+            created
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<_>>(),
+            vec![
+                "+ index event.event_email_idx",
+                "+ index event.event_tenant_id_created_at_idx"
+            ]
+        );
+
+        // The state only has the indexes created by make-migrations
+        assert!(matches!(
+            index_operations(&model(&[], true), &model(&[], true), &[]).as_slice(),
+            [Operation::CreateIndex { name, columns, unique: false, .. }]
+                if name == "event_email_idx" && columns == &["email"]
+        ));
+        assert!(index_operations(&model(&[&["email"]], false), &model(&[], true), &[]).is_empty());
+
+        // Reordering the columns replaces the index
+        let operations = index_operations(
+            &model(&[&["email"], &["tenant_id", "created_at"]], false),
+            &model(&[&["created_at", "tenant_id"]], false),
+            &[],
+        );
+        assert_eq!(
+            operations
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<_>>(),
+            vec![
+                "- index event.event_email_idx",
+                "- index event.event_tenant_id_created_at_idx",
+                "+ index event.event_created_at_tenant_id_idx"
+            ]
+        );
+        assert!(matches!(
+            &operations[1],
+            Operation::DropIndex { columns, .. } if columns == &["tenant_id", "created_at"]
+        ));
+
+        // Kept indexes are renamed with their columns
+        let renames = vec![Operation::RenameField {
+            table_name: "event".to_string(),
+            old: "mail".to_string(),
+            new: "email".to_string(),
+        }];
+        assert_eq!(
+            index_operations(&model(&[&["email"]], false), &model(&[], true), &renames)
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<_>>(),
+            vec!["~ index event.event_mail_idx -> event.event_email_idx"]
+        );
+
+        // The index of a renamed model keeps the old model name
+        let renames = vec![
+            Operation::RenameModel {
+                old: "log".to_string(),
+                new: "event".to_string(),
+                rename_constraints: false,
+            },
+            renames[0].clone(),
+        ];
+        assert!(matches!(
+            index_operations(&model(&[&["email"]], false), &model(&[], true), &renames).as_slice(),
+            [Operation::RenameIndex { model, old, new, .. }]
+                if model == "event" && old == "log_mail_idx" && new == "event_email_idx"
+        ));
+    }
+
+    #[test]
+    fn composite_field_indexes() {
+        let field = |name: &str, index: &str, priority: Option<i32>| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![Annotation::Index(Some(IndexValue {
+                name: index.to_string(),
+                priority,
+            }))],
+            source_defined_at: None,
+        };
+        let model = Model {
+            name: "event".to_string(),
+            fields: vec![
+                field("created_at", "time", Some(20)),
+                field("tenant_id", "time", None),
+                field("kind", "kind", Some(1)),
+            ],
+            annotations: vec![],
+            source_defined_at: None,
+        };
+        assert_eq!(
+            model_indexes(&model),
+            vec![
+                vec!["tenant_id".to_string(), "created_at".to_string()],
+                vec!["kind".to_string()]
+            ]
+        );
+    }
+
+    #[test]
+    fn squash_check() {
+        let options = |non_interactive: bool, warnings_disabled: bool, squash_check: bool| {
+            MakeMigrationsOptions {
+                models_files: vec![],
+                migration_dir: "".to_string(),
+                recursive: false,
+                name: None,
+                non_interactive,
+                warnings_disabled,
+                verbose_diff: false,
+                json: false,
+                dry_run: false,
+                squash_check,
+                squash_threshold: 50,
+                cascade: false,
+                rename_constraints: false,
+                auto_timestamps: false,
+                id_scheme: IdScheme::Sequential,
+                id_width: 4,
+                description: None,
+                models_command: None,
+                naming_policy: None,
+            }
+        };
+
+        assert!(squash_warning(&options(false, false, false), 50).is_none());
+        assert!(squash_warning(&options(false, false, false), 51).is_some());
+        assert!(squash_warning(&options(false, true, false), 51).is_none());
+        // Only reported in CI, if enabled explicitly
+        assert!(squash_warning(&options(true, false, false), 51).is_none());
+        assert!(squash_warning(&options(true, false, true), 51).is_some());
+        assert!(squash_warning(&options(true, true, true), 51).is_none());
+    }
+
+    #[test]
+    fn rename_from() {
+        let field = |name: &str, annotations| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations,
+            source_defined_at: None,
+        };
+        let model = |name: &str, fields, annotations| Model {
+            name: name.to_string(),
+            fields,
+            annotations,
+            source_defined_at: None,
+        };
+
+        let constructed = InternalModelFormat {
+            models: vec![model(
+                "user",
+                vec![field("id", vec![]), field("age", vec![])],
+                vec![],
+            )],
+        };
+        let mut internal_models = InternalModelFormat {
+            models: vec![model(
+                "account",
+                vec![
+                    field("id", vec![]),
+                    field("years", vec![Annotation::RenameFrom("age".to_string())]),
+                ],
+                vec![ModelAnnotation::RenameFrom("user".to_string())],
+            )],
+        };
+
+        let renames = take_renames(&mut internal_models);
+        assert!(internal_models.models[0].annotations.is_empty());
+        assert!(internal_models.models[0].fields[1].annotations.is_empty());
+
+        let mut state = constructed.clone();
+        let operations = apply_renames(&mut state, renames.clone()).unwrap();
+        assert_eq!(
+            operations
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<String>>(),
+            vec!["~ model user -> account", "~ account.age -> account.years"]
+        );
+        assert_eq!(state.models[0].name, "account");
+        assert_eq!(state.models[0].fields[1].name, "years");
+
+        // Already applied renames are skipped
+        assert!(apply_renames(&mut state, renames).unwrap().is_empty());
+
+        let mut state = constructed;
+        assert!(apply_renames(
+            &mut state,
+            vec![Operation::RenameField {
+                table_name: "user".to_string(),
+                old: "name".to_string(),
+                new: "username".to_string(),
+            }]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn coalesce_field_renames() {
+        let rename = |table_name: &str, old: &str, new: &str| Operation::RenameField {
+            table_name: table_name.to_string(),
+            old: old.to_string(),
+            new: new.to_string(),
+        };
+
+        let operations = coalesce_renames(vec![
+            Operation::RenameModel {
+                old: "user".to_string(),
+                new: "account".to_string(),
+                rename_constraints: false,
+            },
+            rename("account", "firstName", "first_name"),
+            rename("post", "id", "post_id"),
+            rename("account", "lastName", "last_name"),
+            rename("account", "first_name", "given_name"),
+            Operation::Only {
+                dialects: vec![Dialect::Postgres],
+                operation: Box::new(rename("account", "geo", "location")),
+            },
+        ]);
+        assert_eq!(
+            operations
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<String>>(),
+            vec![
+                "~ model user -> account",
+                "~ account.firstName -> account.given_name, account.lastName -> account.last_name",
+                "~ post.id -> post.post_id",
+                "~ account.geo -> account.location (only postgres)",
+            ]
+        );
+    }
+
+    #[test]
+    fn migration_ids() {
+        let migration = |id: &str| Migration {
+            hash: "".to_string(),
+            initial: false,
+            id: id.to_string(),
+            dependency: "".to_string(),
+            external_dependencies: vec![],
+            merges: vec![],
+            replaces: vec![],
+            atomic: true,
+            operations: vec![],
+            precondition: None,
+            lock_timeout_ms: None,
+            naming_policy: None,
+            meta: None,
+        };
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+
+        assert_eq!(format_timestamp(0), "19700101000000");
+        assert_eq!(format_timestamp(951827696), "20000229123456");
+        assert_eq!(format_timestamp(1706693400), "20240131093000");
+
+        let next = |scheme, last: Option<&str>| {
+            next_migration_number(scheme, 4, last.map(migration).as_ref(), at(1706693400))
+        };
+        assert_eq!(next(IdScheme::Sequential, None).unwrap(), "0001");
+        assert_eq!(
+            next(IdScheme::Sequential, Some("0009_placeholder")).unwrap(),
+            "0010"
+        );
+        assert!(next(IdScheme::Sequential, Some("20240131093000_placeholder")).is_err());
+
+        assert_eq!(next(IdScheme::Timestamp, None).unwrap(), "20240131093000");
+        assert_eq!(
+            next(IdScheme::Timestamp, Some("0009_placeholder")).unwrap(),
+            "20240131093000"
+        );
+        // Ids of the same second or of a clock running behind stay ordered
+        assert_eq!(
+            next(IdScheme::Timestamp, Some("20240131093000_initial")).unwrap(),
+            "20240131093001"
+        );
+
+        let last = migration("9999_placeholder");
+        let padded =
+            |width| next_migration_number(IdScheme::Sequential, width, Some(&last), at(0)).unwrap();
+        assert_eq!(padded(6), "010000");
+        assert_eq!(padded(4), "10000");
+        assert!(next_migration_number(IdScheme::Sequential, 14, None, at(0)).is_err());
+
+        assert!(id_width_warning(std::slice::from_ref(&last), "010000", 6).is_none());
+        assert!(id_width_warning(std::slice::from_ref(&last), "10000", 4)
+            .unwrap()
+            .contains("10000"));
+        assert!(id_width_warning(&[last], "20240131093000", 2)
+            .unwrap()
+            .contains("9999 "));
+    }
+
+    #[test]
+    fn library_interface() {
+        let dir = std::env::temp_dir().join(format!("drorm_library_{}", std::process::id()));
+        let models_file = dir.join("models.json");
+        let migration_dir = dir.join("migrations");
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = MakeMigrationsOptions {
+            models_files: vec![models_file.to_str().unwrap().to_string()],
+            migration_dir: migration_dir.to_str().unwrap().to_string(),
+            recursive: false,
+            name: None,
+            non_interactive: false,
+            warnings_disabled: false,
+            verbose_diff: false,
+            json: false,
+            dry_run: false,
+            squash_check: false,
+            squash_threshold: 50,
+            cascade: false,
+            rename_constraints: false,
+            auto_timestamps: false,
+            id_scheme: IdScheme::Sequential,
+            id_width: 4,
+            description: Some("Create the users".to_string()),
+            models_command: None,
+            naming_policy: None,
+        };
+        let write_models = |model: &str, field: &str| {
+            let models = InternalModelFormat {
+                models: vec![Model {
+                    name: model.to_string(),
+                    fields: vec![Field {
+                        name: field.to_string(),
+                        db_type: DbType::Int64,
+                        annotations: vec![Annotation::PrimaryKey],
+                        source_defined_at: None,
+                    }],
+                    annotations: vec![],
+                    source_defined_at: None,
+                }],
+            };
+            std::fs::write(&models_file, serde_json::to_string(&models).unwrap()).unwrap();
+        };
+
+        write_models("user", "id");
+        let result = make_migrations(&options, &RenameMap::default()).unwrap();
+        let written = result.written.unwrap();
+        assert!(written.ends_with("0001_initial.toml"));
+        assert_eq!(result.operations.len(), 1);
+        let content = std::fs::read_to_string(&written).unwrap();
+        let meta = convert_str_to_migration("0001_initial.toml", content.as_str())
+            .unwrap()
+            .migration
+            .meta
+            .unwrap();
+        assert_eq!(meta.description.as_deref(), Some("Create the users"));
+        assert!(meta.created_at.unwrap().ends_with('Z'));
+
+        // Unchanged models write nothing
+        let result = make_migrations(&options, &RenameMap::default()).unwrap();
+        assert!(result.written.is_none() && result.operations.is_empty());
+
+        // Renames are taken from the map instead of a prompt
+        write_models("account", "uid");
+        let mut renames = RenameMap::default();
+        renames
+            .models
+            .insert("user".to_string(), "account".to_string());
+        renames
+            .fields
+            .insert(("account".to_string(), "id".to_string()), "uid".to_string());
+        let result = make_migrations(&options, &renames).unwrap();
+        assert!(result.written.is_some());
+        assert_eq!(
+            result
+                .operations
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<String>>(),
+            vec!["~ model user -> account", "~ account.id -> account.uid"]
+        );
+        assert!(result.warnings.is_empty());
+
+        // Without the map, the rename is a deletion, which is reported as a warning
+        write_models("person", "uid");
+        let result = make_migrations(&options, &RenameMap::default()).unwrap();
+        assert!(result.written.is_some());
+        assert!(result.warnings.iter().any(|x| x.contains("account")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn naming_policies() {
+        let dir = std::env::temp_dir().join(format!("drorm_naming_{}", std::process::id()));
+        let models_file = dir.join("models.json");
+        let migration_dir = dir.join("migrations");
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = |naming_policy: Option<NamingPolicy>| MakeMigrationsOptions {
+            models_files: vec![models_file.to_str().unwrap().to_string()],
+            migration_dir: migration_dir.to_str().unwrap().to_string(),
+            recursive: false,
+            name: None,
+            non_interactive: true,
+            warnings_disabled: false,
+            verbose_diff: false,
+            json: false,
+            dry_run: false,
+            squash_check: false,
+            squash_threshold: 50,
+            cascade: false,
+            rename_constraints: false,
+            auto_timestamps: false,
+            id_scheme: IdScheme::Sequential,
+            id_width: 4,
+            description: None,
+            models_command: None,
+            naming_policy,
+        };
+        let field = |name: &str| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+        let models = InternalModelFormat {
+            models: vec![Model {
+                name: "UserProfile".to_string(),
+                fields: vec![field("id"), field("displayName")],
+                annotations: vec![],
+                source_defined_at: None,
+            }],
+        };
+        std::fs::write(&models_file, serde_json::to_string(&models).unwrap()).unwrap();
+        let diff = |result: &MakeMigrationsResult| {
+            result
+                .operations
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<String>>()
+        };
+
+        // New migration directories use snake_case, which is recorded
+        let result = make_migrations(&options(None), &RenameMap::default()).unwrap();
+        let content = std::fs::read_to_string(result.written.unwrap()).unwrap();
+        let migration = convert_str_to_migration("0001_initial.toml", content.as_str())
+            .unwrap()
+            .migration;
+        assert_eq!(migration.naming_policy, Some(NamingPolicy::SnakeCase));
+        assert!(matches!(
+            &migration.operations[0],
+            Operation::CreateModel { name, fields, .. }
+                if name == "user_profile" && fields[1].name == "display_name"
+        ));
+
+        // The recorded policy is kept
+        let result = make_migrations(&options(None), &RenameMap::default()).unwrap();
+        assert!(result.written.is_none());
+
+        // Changing the policy renames the existing tables and columns
+        let result = make_migrations(
+            &options(Some(NamingPolicy::Preserve)),
+            &RenameMap::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            diff(&result),
+            vec![
+                "~ model user_profile -> UserProfile",
+                "~ UserProfile.display_name -> UserProfile.displayName"
+            ]
+        );
+        assert!(result.warnings[0].contains("changed from snake_case to preserve"));
+        let result = make_migrations(&options(None), &RenameMap::default()).unwrap();
+        assert!(result.written.is_none());
+
+        let result = make_migrations(
+            &options(Some(NamingPolicy::Lowercase)),
+            &RenameMap::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            diff(&result),
+            vec![
+                "~ model UserProfile -> userprofile",
+                "~ userprofile.displayName -> userprofile.displayname"
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reordered_fields() {
+        let dir = std::env::temp_dir().join(format!("drorm_reordered_{}", std::process::id()));
+        let models_file = dir.join("models.json");
+        let migration_dir = dir.join("migrations");
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = MakeMigrationsOptions {
+            models_files: vec![models_file.to_str().unwrap().to_string()],
+            migration_dir: migration_dir.to_str().unwrap().to_string(),
+            recursive: false,
+            name: None,
+            non_interactive: true,
+            warnings_disabled: false,
+            verbose_diff: false,
+            json: false,
+            dry_run: false,
+            squash_check: false,
+            squash_threshold: 50,
+            cascade: false,
+            rename_constraints: false,
+            auto_timestamps: false,
+            id_scheme: IdScheme::Sequential,
+            id_width: 4,
+            description: None,
+            models_command: None,
+            naming_policy: None,
+        };
+        let models = |names: &[&str]| InternalModelFormat {
+            models: vec![Model {
+                name: "user".to_string(),
+                fields: names
+                    .iter()
+                    .enumerate()
+                    .map(|(line, name)| Field {
+                        name: name.to_string(),
+                        db_type: DbType::Int64,
+                        annotations: vec![],
+                        source_defined_at: Some(Source {
+                            file: "models.rs".to_string(),
+                            line,
+                            column: 4,
+                        }),
+                    })
+                    .collect(),
+                annotations: vec![],
+                source_defined_at: None,
+            }],
+        };
+        let write_models = |names: &[&str]| {
+            std::fs::write(&models_file, serde_json::to_string(&models(names)).unwrap()).unwrap();
+        };
+
+        assert_eq!(
+            models_hash(&models(&["id", "age"])),
+            models_hash(&models(&["age", "id"]))
+        );
+        assert_ne!(
+            models_hash(&models(&["id", "age"])),
+            models_hash(&models(&["id", "years"]))
+        );
+        let mut hinted = models(&["id", "years"]);
+        hinted.models[0].fields[1]
+            .annotations
+            .push(Annotation::RenameFrom("age".to_string()));
+        hinted.models[0]
+            .annotations
+            .push(ModelAnnotation::RenameFrom("person".to_string()));
+        assert_eq!(models_hash(&hinted), models_hash(&models(&["id", "years"])));
+        let mut reordered = models(&["id", "years"]);
+        reordered.models[0].fields[1].annotations = vec![Annotation::NotNull, Annotation::Unique];
+        reordered.models[0].annotations = vec![
+            ModelAnnotation::Index(vec!["years".to_string()]),
+            ModelAnnotation::UniqueTogether(vec!["id".to_string(), "years".to_string()]),
+        ];
+        let mut canonical = models(&["id", "years"]);
+        canonical.models[0].fields[1].annotations = vec![Annotation::Unique, Annotation::NotNull];
+        canonical.models[0].annotations = vec![
+            ModelAnnotation::UniqueTogether(vec!["years".to_string(), "id".to_string()]),
+            ModelAnnotation::Index(vec!["years".to_string()]),
+        ];
+        assert_eq!(models_hash(&reordered), models_hash(&canonical));
+
+        // The hash is calculated from the JSON representation, not the layout in memory
+        assert_eq!(
+            models_hash(&models(&["id"])),
+            "v2:004f9b0d943b53f08ebc730ed083f6ba0a4e411beb6cee0bcd4e2d5f6c450cfa"
+        );
+        assert!(is_valid_hash(models_hash(&models(&["id"])).as_str()));
+        assert!(is_legacy_hash("2053104884920620157"));
+
+        write_models(&["id", "age"]);
+        let result = make_migrations(&options, &RenameMap::default()).unwrap();
+        assert!(result.written.is_some());
+
+        write_models(&["age", "id"]);
+        let result = make_migrations(&options, &RenameMap::default()).unwrap();
+        assert!(result.written.is_none() && result.operations.is_empty());
+        assert_eq!(std::fs::read_dir(&migration_dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn auto_models() {
+        let dir = std::env::temp_dir().join(format!("drorm_auto_models_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let models_file = dir.join("models.json");
+        let generated = dir.join("generated.json");
+        let models = InternalModelFormat {
+            models: vec![Model {
+                name: "user".to_string(),
+                fields: vec![Field {
+                    name: "id".to_string(),
+                    db_type: DbType::Int64,
+                    annotations: vec![Annotation::PrimaryKey],
+                    source_defined_at: None,
+                }],
+                annotations: vec![],
+                source_defined_at: None,
+            }],
+        };
+        std::fs::write(&generated, serde_json::to_string(&models).unwrap()).unwrap();
+        let options = |command: String| MakeMigrationsOptions {
+            models_files: vec![models_file.to_str().unwrap().to_string()],
+            migration_dir: dir.join("migrations").to_str().unwrap().to_string(),
+            recursive: false,
+            name: None,
+            non_interactive: true,
+            warnings_disabled: false,
+            verbose_diff: false,
+            json: false,
+            dry_run: false,
+            squash_check: false,
+            squash_threshold: 50,
+            cascade: false,
+            rename_constraints: false,
+            auto_timestamps: false,
+            id_scheme: IdScheme::Sequential,
+            id_width: 4,
+            description: None,
+            models_command: Some(command),
+            naming_policy: None,
+        };
+
+        let err = make_migrations(
+            &options("echo broken; exit 3".to_string()),
+            &RenameMap::default(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("failed") && err.contains("broken"));
+
+        let err = make_migrations(&options("true".to_string()), &RenameMap::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("didn't write the models file"));
+
+        let copy = format!("cp {} {}", generated.display(), models_file.display());
+        let result = make_migrations(&options(copy), &RenameMap::default()).unwrap();
+        assert!(result.written.is_some());
+
+        // A stale models file isn't used
+        let err = make_migrations(&options("true".to_string()), &RenameMap::default()).unwrap_err();
+        assert!(err.to_string().contains("didn't write the models file"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merge_models() {
+        let models = |names: &[&str]| InternalModelFormat {
+            models: names
+                .iter()
+                .map(|x| Model {
+                    name: x.to_string(),
+                    fields: vec![],
+                    annotations: vec![],
+                    source_defined_at: None,
+                })
+                .collect(),
+        };
+        let names = |x: &InternalModelFormat| {
+            x.models
+                .iter()
+                .map(|y| y.name.clone())
+                .collect::<Vec<String>>()
+        };
+
+        let merged = merge_internal_models(vec![("a.json", models(&["user", "post"]))]).unwrap();
+        assert_eq!(names(&merged), vec!["user", "post"]);
+
+        // The models keep the order of the files
+        let merged = merge_internal_models(vec![
+            ("a.json", models(&["user"])),
+            ("b.json", models(&["post", "comment"])),
+        ])
+        .unwrap();
+        assert_eq!(names(&merged), vec!["user", "post", "comment"]);
+
+        let err = merge_internal_models(vec![
+            ("a.json", models(&["user"])),
+            ("b.json", models(&["post", "user"])),
+        ])
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("user") && err.contains("a.json") && err.contains("b.json"));
+    }
+
+    #[test]
+    fn enum_changes() {
+        let field = |choices: &[&str]| Field {
+            name: "color".to_string(),
+            db_type: DbType::Choices,
+            annotations: vec![
+                Annotation::NotNull,
+                Annotation::Choices(choices.iter().map(|x| x.to_string()).collect()),
+            ],
+            source_defined_at: None,
+        };
+        let actions = |old: &[&str], new: &[&str]| {
+            enum_operations("user", &field(old), &field(new))
+                .into_iter()
+                .map(|x| match x {
+                    Operation::AlterEnum { action, field, .. } => (action, field),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<(EnumAction, Field)>>()
+        };
+
+        let changed = actions(&["red", "grey"], &["red", "gray", "teal"]);
+        assert_eq!(changed.len(), 2);
+        assert_eq!(
+            changed[0].0,
+            EnumAction::Rename {
+                old: "grey".to_string(),
+                new: "gray".to_string()
+            }
+        );
+        assert_eq!(
+            changed[1].0,
+            EnumAction::Add {
+                value: "teal".to_string()
+            }
+        );
+        // Every operation carries the values after it
+        assert!(matches!(
+            &changed[0].1.annotations[1],
+            Annotation::Choices(c) if *c == ["red", "gray"]
+        ));
+        assert!(matches!(
+            &changed[1].1.annotations[1],
+            Annotation::Choices(c) if *c == ["red", "gray", "teal"]
+        ));
+        assert_eq!(
+            operation_to_diff_line(&Operation::AlterEnum {
+                model: "user".to_string(),
+                field: changed[0].1.clone(),
+                action: changed[0].0.clone(),
+            }),
+            "~ enum user.color grey -> gray"
+        );
+
+        // Reordered values are no change
+        assert!(actions(&["red", "teal"], &["teal", "red"]).is_empty());
+        assert!(enum_note("user", &field(&["red", "teal"]), &field(&["teal", "red"])).is_none());
+
+        // Removed values are only noted
+        assert!(actions(&["red", "teal"], &["red"]).is_empty());
+        assert!(
+            enum_note("user", &field(&["red", "teal"]), &field(&["red"]))
+                .unwrap()
+                .contains("teal")
+        );
+    }
+
+    #[test]
+    fn diff_two_models_files() {
+        let dir = std::env::temp_dir().join(format!("drorm_models_diff_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let migration_dir = dir.join("migrations");
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = MakeMigrationsOptions {
+            models_files: vec![dir.join("new.json").to_str().unwrap().to_string()],
+            migration_dir: migration_dir.to_str().unwrap().to_string(),
+            recursive: false,
+            name: None,
+            non_interactive: true,
+            warnings_disabled: false,
+            verbose_diff: false,
+            json: false,
+            dry_run: true,
+            squash_check: false,
+            squash_threshold: 50,
+            cascade: false,
+            rename_constraints: false,
+            auto_timestamps: false,
+            id_scheme: IdScheme::Sequential,
+            id_width: 4,
+            description: None,
+            models_command: None,
+            naming_policy: None,
+        };
+        let field = |name: &str, db_type, annotations| Field {
+            name: name.to_string(),
+            db_type,
+            annotations,
+            source_defined_at: None,
+        };
+        let model = |name: &str, fields| Model {
+            name: name.to_string(),
+            fields,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+        let write_models = |file: &str, models| {
+            let path = dir.join(file);
+            std::fs::write(
+                &path,
+                serde_json::to_string(&InternalModelFormat { models }).unwrap(),
+            )
+            .unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let id = || field("id", DbType::Int64, vec![Annotation::PrimaryKey]);
+        let name = |max_length| {
+            field(
+                "name",
+                DbType::VarChar,
+                vec![Annotation::MaxLength(max_length), Annotation::NotNull],
+            )
+        };
+
+        let old = write_models(
+            "old.json",
+            vec![
+                model(
+                    "user",
+                    vec![id(), name(255), field("age", DbType::Int32, vec![])],
+                ),
+                model("post", vec![id()]),
+            ],
+        );
+        let new = write_models(
+            "new.json",
+            vec![
+                model(
+                    "user",
+                    vec![id(), name(512), field("email", DbType::VarChar, vec![])],
+                ),
+                model("comment", vec![id()]),
+            ],
+        );
+
+        let (operations, warnings) = diff_models_files(&options, &old, &new).unwrap();
+        assert_eq!(
+            operations
+                .iter()
+                .map(operation_to_diff_line)
+                .collect::<Vec<String>>(),
+            vec![
+                "+ model comment",
+                "- model post",
+                "+ user.email",
+                "- user.age",
+                "~ type user.name = VarChar(512) (widening)"
+            ]
+        );
+        assert!(warnings.is_empty());
+
+        // Diffing a file with itself is no change
+        let (operations, _) = diff_models_files(&options, &new, &new).unwrap();
+        assert!(operations.is_empty());
+
+        // A dry run neither writes the migration nor creates the migration directory
+        let result = run_make_migrations(options).unwrap();
+        assert!(result.written.is_none());
+        assert!(matches!(
+            &result.operations[0],
+            Operation::CreateModel { .. }
+        ));
+        assert!(!migration_dir.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn referential_actions() {
+        let models = |on_delete, annotations: Vec<Annotation>| InternalModelFormat {
+            models: vec![Model {
+                name: "post".to_string(),
+                fields: vec![Field {
+                    name: "author".to_string(),
+                    db_type: DbType::Int64,
+                    annotations: [
+                        annotations,
+                        vec![Annotation::ForeignKey(ForeignKey {
+                            table_name: "user".to_string(),
+                            column_name: "id".to_string(),
+                            deferrable: false,
+                            initially_deferred: false,
+                            on_delete,
+                            on_update: ReferentialAction::NoAction,
+                        })],
+                    ]
+                    .concat(),
+                    source_defined_at: None,
+                }],
+                annotations: vec![],
+                source_defined_at: None,
+            }],
+        };
+
+        let err = check_referential_actions(&models(
+            ReferentialAction::SetNull,
+            vec![Annotation::NotNull],
+        ))
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Field post.author is not nullable, but its foreign key sets it to NULL on delete"
+        );
+        assert!(check_referential_actions(&models(ReferentialAction::SetNull, vec![])).is_ok());
+
+        let err =
+            check_referential_actions(&models(ReferentialAction::SetDefault, vec![])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Field post.author has no default, but its foreign key sets it to its default on delete"
+        );
+        assert!(check_referential_actions(&models(
+            ReferentialAction::SetDefault,
+            vec![Annotation::ServerDefault("0".to_string())]
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn migration_summary() {
+        let field = |name: &str| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+        let create_field = |name: &str| Operation::CreateField {
+            model: "order".to_string(),
+            field: field(name),
+        };
+        let operations = vec![
+            Operation::CreateModel {
+                name: "order".to_string(),
+                fields: vec![field("id")],
+                annotations: vec![],
+            },
+            create_field("amount"),
+            Operation::Only {
+                dialects: vec![Dialect::Postgres],
+                operation: Box::new(create_field("region")),
+            },
+        ];
+
+        let summary =
+            MigrationSummary::new(Path::new("migrations/0008_add_orders.toml"), &operations);
+        assert_eq!(
+            summary.to_string(),
+            "Wrote 0008_add_orders.toml: 1 CreateModel, 2 CreateField"
+        );
+        assert_eq!(
+            serde_json::to_string(&summary).unwrap(),
+            r#"{"File":"0008_add_orders.toml","Operations":[{"Type":"CreateModel","Count":1},{"Type":"CreateField","Count":2}]}"#
+        );
+    }
 }
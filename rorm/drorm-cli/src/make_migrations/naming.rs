@@ -0,0 +1,267 @@
+use rorm_sql::imr::{Annotation, InternalModelFormat, ModelAnnotation};
+
+use crate::declaration::{MigrationMeta, NamingPolicy};
+use crate::make_migrations::RenameMap;
+
+/**
+Resolves the naming policy the existing migrations were generated with.
+
+Every migration uses the policy of the last migration before it, which records one.
+Migrations created before the policy was recorded took the names as they are.
+`None` is returned if there are no migrations.
+
+`migrations`: [IntoIterator<Item = &MigrationMeta>]: The migrations ordered by their dependency chain.
+*/
+pub fn recorded_naming_policy<'a, M: MigrationMeta + 'a>(
+    migrations: impl IntoIterator<Item = &'a M>,
+) -> Option<NamingPolicy> {
+    let mut recorded = None;
+    for migration in migrations {
+        recorded = Some(
+            migration
+                .naming_policy()
+                .unwrap_or(recorded.unwrap_or(NamingPolicy::Preserve)),
+        );
+    }
+    recorded
+}
+
+/**
+Converts the names of the models and fields with a naming policy.
+
+The names referring to models or fields are converted as well,
+i.e. foreign keys, unique and index columns, partition columns and previous names.
+
+`policy`: [NamingPolicy]: The policy to apply.
+`internal_models`: [&mut InternalModelFormat]: The models as read from the models files.
+*/
+pub fn apply_naming_policy(policy: NamingPolicy, internal_models: &mut InternalModelFormat) {
+    if policy == NamingPolicy::Preserve {
+        return;
+    }
+    let convert = |names: &mut Vec<String>| {
+        for name in names.iter_mut() {
+            *name = policy.convert(name);
+        }
+    };
+
+    for model in internal_models.models.iter_mut() {
+        model.name = policy.convert(&model.name);
+        for annotation in model.annotations.iter_mut() {
+            match annotation {
+                ModelAnnotation::RenameFrom(name) | ModelAnnotation::Inherits(name) => {
+                    *name = policy.convert(name)
+                }
+                ModelAnnotation::PartitionBy(partition_by) => convert(&mut partition_by.columns),
+                ModelAnnotation::UniqueTogether(columns) | ModelAnnotation::Index(columns) => {
+                    convert(columns)
+                }
+                _ => {}
+            }
+        }
+
+        for field in model.fields.iter_mut() {
+            field.name = policy.convert(&field.name);
+            for annotation in field.annotations.iter_mut() {
+                match annotation {
+                    Annotation::RenameFrom(name) => *name = policy.convert(name),
+                    Annotation::ForeignKey(foreign_key) => {
+                        foreign_key.table_name = policy.convert(&foreign_key.table_name);
+                        foreign_key.column_name = policy.convert(&foreign_key.column_name);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/**
+Derives the renames of the tables and columns caused by changing the naming policy.
+
+Only models and fields which exist in the state of the existing migrations are renamed,
+new ones are created with the new names anyway.
+
+`recorded`: [NamingPolicy]: The policy of the existing migrations.
+`policy`: [NamingPolicy]: The new policy.
+`internal_models`: [&InternalModelFormat]: The models as read from the models files.
+`constructed`: [&InternalModelFormat]: State resulting from the existing migrations.
+*/
+pub fn naming_policy_renames(
+    recorded: NamingPolicy,
+    policy: NamingPolicy,
+    internal_models: &InternalModelFormat,
+    constructed: &InternalModelFormat,
+) -> RenameMap {
+    let mut renames = RenameMap::default();
+    if recorded == policy {
+        return renames;
+    }
+
+    for model in &internal_models.models {
+        let (old, new) = (recorded.convert(&model.name), policy.convert(&model.name));
+        let existing = match constructed.models.iter().find(|x| x.name == old) {
+            Some(existing) => existing,
+            None => continue,
+        };
+        if old != new {
+            renames.models.insert(old, new.clone());
+        }
+
+        for field in &model.fields {
+            let (old, new_field) = (recorded.convert(&field.name), policy.convert(&field.name));
+            if old != new_field && existing.fields.iter().any(|x| x.name == old) {
+                renames.fields.insert((new.clone(), old), new_field);
+            }
+        }
+    }
+    renames
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{
+        Annotation, DbType, Field, ForeignKey, InternalModelFormat, Model, ModelAnnotation,
+    };
+
+    use crate::declaration::NamingPolicy;
+    use crate::make_migrations::naming::{apply_naming_policy, naming_policy_renames};
+
+    fn models() -> InternalModelFormat {
+        let field = |name: &str, annotations: Vec<Annotation>| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations,
+            source_defined_at: None,
+        };
+        InternalModelFormat {
+            models: vec![Model {
+                name: "UserProfile".to_string(),
+                fields: vec![
+                    field("id", vec![Annotation::PrimaryKey]),
+                    field("displayName", vec![]),
+                    field(
+                        "HTTPSessionId",
+                        vec![Annotation::ForeignKey(ForeignKey {
+                            table_name: "HTTPSession".to_string(),
+                            column_name: "id".to_string(),
+                            deferrable: false,
+                            initially_deferred: false,
+                            on_delete: Default::default(),
+                            on_update: Default::default(),
+                        })],
+                    ),
+                ],
+                annotations: vec![ModelAnnotation::UniqueTogether(vec![
+                    "displayName".to_string(),
+                    "HTTPSessionId".to_string(),
+                ])],
+                source_defined_at: None,
+            }],
+        }
+    }
+
+    fn names(models: &InternalModelFormat) -> Vec<String> {
+        let model = &models.models[0];
+        let mut names = vec![model.name.clone()];
+        names.extend(model.fields.iter().map(|x| x.name.clone()));
+        names
+    }
+
+    #[test]
+    fn convert() {
+        assert_eq!(
+            NamingPolicy::SnakeCase.convert("UserProfile"),
+            "user_profile"
+        );
+        assert_eq!(
+            NamingPolicy::SnakeCase.convert("HTTPRequest"),
+            "http_request"
+        );
+        assert_eq!(NamingPolicy::SnakeCase.convert("userID"), "user_id");
+        assert_eq!(
+            NamingPolicy::SnakeCase.convert("Oauth2Token"),
+            "oauth2_token"
+        );
+        assert_eq!(NamingPolicy::SnakeCase.convert("created_at"), "created_at");
+        assert_eq!(
+            NamingPolicy::SnakeCase.convert("Already_Snake"),
+            "already_snake"
+        );
+        assert_eq!(
+            NamingPolicy::Lowercase.convert("UserProfile"),
+            "userprofile"
+        );
+        assert_eq!(NamingPolicy::Preserve.convert("UserProfile"), "UserProfile");
+    }
+
+    #[test]
+    fn snake_case() {
+        let mut models = models();
+        apply_naming_policy(NamingPolicy::SnakeCase, &mut models);
+        assert_eq!(
+            names(&models),
+            vec!["user_profile", "id", "display_name", "http_session_id"]
+        );
+
+        // References are converted with the names they refer to
+        let model = &models.models[0];
+        assert!(matches!(
+            &model.fields[2].annotations[0],
+            Annotation::ForeignKey(x) if x.table_name == "http_session" && x.column_name == "id"
+        ));
+        assert!(matches!(
+            &model.annotations[0],
+            ModelAnnotation::UniqueTogether(x) if x == &["display_name", "http_session_id"]
+        ));
+    }
+
+    #[test]
+    fn lowercase() {
+        let mut models = models();
+        apply_naming_policy(NamingPolicy::Lowercase, &mut models);
+        assert_eq!(
+            names(&models),
+            vec!["userprofile", "id", "displayname", "httpsessionid"]
+        );
+    }
+
+    #[test]
+    fn preserve() {
+        let mut models = models();
+        apply_naming_policy(NamingPolicy::Preserve, &mut models);
+        assert_eq!(
+            names(&models),
+            vec!["UserProfile", "id", "displayName", "HTTPSessionId"]
+        );
+    }
+
+    #[test]
+    fn policy_change_renames() {
+        let models = models();
+        let mut constructed = models.clone();
+        apply_naming_policy(NamingPolicy::Preserve, &mut constructed);
+
+        let renames = naming_policy_renames(
+            NamingPolicy::Preserve,
+            NamingPolicy::SnakeCase,
+            &models,
+            &constructed,
+        );
+        assert_eq!(renames.models["UserProfile"], "user_profile");
+        assert_eq!(
+            renames.fields[&("user_profile".to_string(), "displayName".to_string())],
+            "display_name"
+        );
+        // Unchanged names aren't renamed
+        assert_eq!(renames.fields.len(), 2);
+
+        let unchanged = naming_policy_renames(
+            NamingPolicy::SnakeCase,
+            NamingPolicy::SnakeCase,
+            &models,
+            &constructed,
+        );
+        assert!(unchanged.models.is_empty() && unchanged.fields.is_empty());
+    }
+}
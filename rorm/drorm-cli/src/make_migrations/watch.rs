@@ -0,0 +1,199 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use tokio::signal;
+use tokio::time::{sleep, Instant};
+
+use crate::make_migrations::{
+    data_loss_warning, generate_migration, print_dry_run, MakeMigrationsOptions, RenameMap,
+};
+use crate::utils::output::{info, paint, Style};
+
+/**
+Interval in which the watched paths are checked for changes
+*/
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/**
+Time the watched paths have to stay unchanged after a change, before the pending changes are printed.
+
+Tools writing the models files, e.g. a build, often write several times in a row.
+*/
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/**
+Modification time and size of every watched file, `None` for missing files
+*/
+type Snapshot = Vec<(PathBuf, Option<(SystemTime, u64)>)>;
+
+/**
+Takes a snapshot of the watched paths.
+
+Directories are walked recursively, their files are sorted by path.
+
+`paths`: [&\[PathBuf\]]: The files and directories to watch.
+*/
+fn snapshot(paths: &[PathBuf]) -> Snapshot {
+    let mut files = vec![];
+    let mut stack: Vec<PathBuf> = paths.iter().rev().cloned().collect();
+    while let Some(path) = stack.pop() {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = match std::fs::read_dir(&path) {
+                Ok(entries) => entries.filter_map(|x| x.ok()).map(|x| x.path()).collect(),
+                Err(_) => vec![],
+            };
+            entries.sort();
+            stack.extend(entries.into_iter().rev());
+        } else {
+            let state = std::fs::metadata(&path)
+                .and_then(|x| Ok((x.modified()?, x.len())))
+                .ok();
+            files.push((path, state));
+        }
+    }
+    files
+}
+
+/**
+Prints the changes the next migration would consist of, without writing it.
+
+`options`: [&MakeMigrationsOptions]: The options of the run, the models command is run if set.
+*/
+pub fn print_pending_changes(options: &MakeMigrationsOptions) -> anyhow::Result<()> {
+    let generated = match generate_migration(options, &RenameMap::default())? {
+        None => {
+            info!("No changes - nothing to do.");
+            return Ok(());
+        }
+        Some(generated) => generated,
+    };
+
+    let mut warnings = generated.warnings.clone();
+    warnings.extend(
+        generated
+            .migration
+            .operations
+            .iter()
+            .filter_map(data_loss_warning),
+    );
+    print_dry_run(
+        options,
+        format!(
+            "Pending changes of migration {}, not written:",
+            generated.migration.id
+        )
+        .as_str(),
+        &generated.migration.operations,
+        &warnings,
+    );
+    Ok(())
+}
+
+/**
+Watches the models files and prints the pending changes whenever they change.
+
+Nothing is written, writing the migration stays an explicit run of make-migrations.
+Changes are only printed after the watched paths stopped changing for [DEBOUNCE].
+Errors, e.g. of a models file which is written right now, are printed and watching continues.
+With a models command, the command is run before every check, which is triggered by changes
+to the additional watch paths, e.g. the sources of the models.
+
+The watch stops on Ctrl-C.
+
+`options`: [MakeMigrationsOptions]: The options of the run, `dry_run` is implied.
+`watch_paths`: [&\[String\]]: Files and directories to watch in addition to the models files.
+*/
+pub async fn run_watch(
+    options: MakeMigrationsOptions,
+    watch_paths: &[String],
+) -> anyhow::Result<()> {
+    let options = MakeMigrationsOptions {
+        dry_run: true,
+        ..options
+    };
+    let paths: Vec<PathBuf> = options
+        .models_files
+        .iter()
+        .chain(watch_paths)
+        .map(PathBuf::from)
+        .collect();
+    let check = |options: &MakeMigrationsOptions| {
+        if let Err(err) = print_pending_changes(options) {
+            println!("{}", paint(Style::Error, format!("Error: {:#}", err)));
+        }
+    };
+
+    info!(
+        "Watching {} for changes, press Ctrl-C to stop",
+        paths
+            .iter()
+            .map(|x| x.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    check(&options);
+
+    // The listener is kept, so an interrupt during a check is not lost
+    let ctrl_c = signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut last = snapshot(&paths);
+    let mut changed_at = None;
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c => {
+                info!("Stopped watching");
+                return Ok(());
+            }
+            _ = sleep(POLL_INTERVAL) => {}
+        }
+
+        let current = snapshot(&paths);
+        if current != last {
+            last = current;
+            changed_at = Some(Instant::now());
+            continue;
+        }
+        if changed_at.is_some_and(|x: Instant| x.elapsed() >= DEBOUNCE) {
+            changed_at = None;
+            println!();
+            check(&options);
+            // The models command rewrites the models files, which isn't a change of its own
+            last = snapshot(&paths);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::make_migrations::watch::snapshot;
+
+    #[test]
+    fn snapshot_detects_changes() {
+        let dir = std::env::temp_dir().join(format!("drorm_watch_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        let models_file = dir.join(".models.json");
+        let source = dir.join("src").join("models.rs");
+        std::fs::write(&models_file, "{}").unwrap();
+        std::fs::write(&source, "").unwrap();
+        let paths: Vec<PathBuf> = vec![models_file.clone(), dir.join("src")];
+
+        let before = snapshot(&paths);
+        let files: Vec<&PathBuf> = before.iter().map(|(x, _)| x).collect();
+        assert_eq!(files, vec![&models_file, &source]);
+        assert_eq!(before, snapshot(&paths));
+
+        // A change of the size is detected, even within the resolution of the modification time
+        std::fs::write(&source, "struct User;").unwrap();
+        assert_ne!(before, snapshot(&paths));
+
+        // Missing files are part of the snapshot, so creating them is a change as well
+        std::fs::remove_file(&models_file).unwrap();
+        let missing = snapshot(&paths);
+        assert!(matches!(missing.first(), Some((path, None)) if *path == models_file));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,335 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context};
+
+use crate::declaration::Migration;
+use crate::make_migrations::naming::recorded_naming_policy;
+use crate::make_migrations::{audit_info, following_number, models_hash};
+use crate::utils::migrations::{
+    convert_migration_to_file, convert_migrations_to_internal_models, leaf_migrations,
+    order_migrations, read_migrations, replaced_migrations,
+};
+use crate::utils::output::{info, paint, Style};
+use crate::utils::question::ask;
+use crate::validate::validate_operations;
+
+/**
+Options for merging the branches of migrations
+*/
+pub struct MergeOptions {
+    /// Directory, migrations exist in
+    pub migration_dir: String,
+
+    /// If set, the subdirectories of the migration directory are searched as well
+    pub recursive: bool,
+
+    /// If set, the branches are merged in the order of their numbers without asking
+    pub non_interactive: bool,
+}
+
+/**
+Returns the leaves of the branches of the migrations, which need to be merged.
+
+Migrations replaced by another migration of the list are left out.
+A single chain has a single leaf, which doesn't need to be merged.
+
+`migrations`: [&\[Migration\]]: The migrations to check.
+*/
+pub fn unmerged_leaves(migrations: &[Migration]) -> Vec<&Migration> {
+    let replaced = replaced_migrations(migrations);
+    let active: Vec<&Migration> = migrations
+        .iter()
+        .filter(|x| !replaced.contains(&x.id))
+        .collect();
+    leaf_migrations(&active).into_iter().copied().collect()
+}
+
+/**
+Helper function to parse the order of the branches chosen by the user.
+
+The answer lists the numbers of the branches, starting at 1, separated by spaces or commas.
+An empty answer keeps the proposed order.
+
+`answer`: [&str]: The answer of the user.
+`count`: [usize]: The number of branches.
+*/
+fn parse_order(answer: &str, count: usize) -> anyhow::Result<Vec<usize>> {
+    if answer.is_empty() {
+        return Ok((0..count).collect());
+    }
+
+    let mut order = vec![];
+    for number in answer
+        .split(|x: char| x == ',' || x.is_whitespace())
+        .filter(|x| !x.is_empty())
+    {
+        let index = match number.parse::<usize>() {
+            Ok(number) if (1..=count).contains(&number) => number - 1,
+            _ => {
+                return Err(anyhow!(
+                    "{} is not the number of a branch, expected 1 to {}",
+                    number,
+                    count
+                ))
+            }
+        };
+        if order.contains(&index) {
+            return Err(anyhow!("Branch {} is listed twice", number));
+        }
+        order.push(index);
+    }
+    if order.len() != count {
+        return Err(anyhow!("Expected the order of all {} branches", count));
+    }
+    Ok(order)
+}
+
+/**
+Creates the merge migration joining the branches of the given leaves.
+
+The merge migration has no operations. It depends on the last leaf and merges the others,
+the branches are applied in the order of the leaves, see [order_migrations].
+The merged chain is checked to be in order and for conflicts of the branches,
+e.g. a field created by one branch on a model deleted by the other one.
+
+`migrations`: [&\[Migration\]]: The existing migrations.
+`leaves`: [&\[&str\]]: The ids of the leaves in the order their branches are applied in.
+`now`: [SystemTime]: The current time, used for the id and the audit trail.
+*/
+pub fn merge_migration(
+    migrations: &[Migration],
+    leaves: &[&str],
+    now: SystemTime,
+) -> anyhow::Result<Migration> {
+    let (dependency, merges) = match leaves.split_last() {
+        Some((dependency, merges)) if !merges.is_empty() => (dependency, merges),
+        _ => return Err(anyhow!("At least two branches are needed for a merge")),
+    };
+
+    let mut merge = Migration {
+        hash: "".to_string(),
+        initial: false,
+        id: format!("{}_merge", following_number(migrations, now)?),
+        dependency: dependency.to_string(),
+        external_dependencies: vec![],
+        merges: merges.iter().map(|x| x.to_string()).collect(),
+        replaces: vec![],
+        atomic: true,
+        operations: vec![],
+        precondition: None,
+        lock_timeout_ms: None,
+        naming_policy: recorded_naming_policy(migrations),
+        meta: Some(audit_info(
+            Some(format!("Merge of the branches of {}", leaves.join(", ")).as_str()),
+            now,
+        )),
+    };
+
+    let mut merged = migrations.to_vec();
+    merged.push(merge.clone());
+    let chain = order_migrations(merged).with_context(|| "The branches can't be merged")?;
+    let problems = validate_operations(&chain.iter().collect::<Vec<_>>());
+    if !problems.is_empty() {
+        return Err(anyhow!(
+            "The merged branches conflict, resolve the conflicts with a migration of one of the branches before merging them:\n{}",
+            problems.join("\n")
+        ));
+    }
+    merge.hash = models_hash(&convert_migrations_to_internal_models(&chain)?);
+
+    Ok(merge)
+}
+
+/**
+Merges the branches of the migrations of a migration directory with a new merge migration.
+
+The user is asked for the order the branches are applied in, unless `non_interactive` is set.
+Then the branches are applied in the order of the numbers of their leaves.
+
+`options`: [MergeOptions]: The options of the run.
+*/
+pub fn run_merge_migrations(options: MergeOptions) -> anyhow::Result<()> {
+    let migrations = read_migrations(options.migration_dir.as_str(), options.recursive)?;
+    let mut leaves: Vec<&str> = unmerged_leaves(&migrations)
+        .iter()
+        .map(|x| x.id.as_str())
+        .collect();
+    if leaves.len() < 2 {
+        info!("No branches to merge - nothing to do.");
+        return Ok(());
+    }
+
+    if !options.non_interactive {
+        info!("Found {} branches ending at:", leaves.len());
+        for (index, leaf) in leaves.iter().enumerate() {
+            info!("    {}) {}", index + 1, leaf);
+        }
+        let proposed: Vec<String> = (1..=leaves.len()).map(|x| x.to_string()).collect();
+        let order = loop {
+            let answer = ask(format!(
+                "In which order should the branches be applied? Enter their numbers [{}]",
+                proposed.join(" ")
+            )
+            .as_str())?;
+            match parse_order(answer.as_str(), leaves.len()) {
+                Ok(order) => break order,
+                Err(err) => println!("{}", paint(Style::Error, err.to_string())),
+            }
+        };
+        leaves = order.into_iter().map(|x| leaves[x]).collect();
+    }
+
+    let merge = merge_migration(&migrations, &leaves, SystemTime::now())
+        .with_context(|| format!("Couldn't merge the migrations in {}", options.migration_dir))?;
+    let path = Path::new(options.migration_dir.as_str()).join(format!("{}.toml", merge.id));
+    if path.exists() {
+        return Err(anyhow!("Migration file {} exists already", path.display()));
+    }
+    let id = merge.id.clone();
+    convert_migration_to_file(merge, &path)?;
+
+    let migrations = read_migrations(options.migration_dir.as_str(), options.recursive)?;
+    let remaining: Vec<&str> = unmerged_leaves(&migrations)
+        .iter()
+        .map(|x| x.id.as_str())
+        .collect();
+    if remaining != [id.as_str()] {
+        return Err(anyhow!(
+            "Wrote {}, but the migrations end at {} instead of the merge migration",
+            path.display(),
+            remaining.join(", ")
+        ));
+    }
+    info!(
+        "Merged the branches of {} into {}",
+        leaves.join(", "),
+        path.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use rorm_sql::imr::{DbType, Field};
+
+    use crate::declaration::{Migration, Operation};
+    use crate::merge_migrations::{
+        merge_migration, parse_order, run_merge_migrations, unmerged_leaves, MergeOptions,
+    };
+    use crate::utils::migrations::{convert_migration_to_file, get_existing_migrations};
+    use crate::utils::test_helpers::migration;
+
+    fn create_field(name: &str) -> Operation {
+        Operation::CreateField {
+            model: "user".to_string(),
+            field: Field {
+                name: name.to_string(),
+                db_type: DbType::Int32,
+                annotations: vec![],
+                source_defined_at: None,
+            },
+        }
+    }
+
+    fn branches() -> Vec<Migration> {
+        vec![
+            migration(
+                "0001_initial",
+                "",
+                vec![Operation::CreateModel {
+                    name: "user".to_string(),
+                    fields: vec![],
+                    annotations: vec![],
+                }],
+            ),
+            migration("0002_age", "0001_initial", vec![create_field("age")]),
+            migration("0002_email", "0001_initial", vec![create_field("email")]),
+            migration("0003_name", "0002_email", vec![create_field("name")]),
+        ]
+    }
+
+    #[test]
+    fn merge() {
+        let migrations = branches();
+        let leaves: Vec<&str> = unmerged_leaves(&migrations)
+            .iter()
+            .map(|x| x.id.as_str())
+            .collect();
+        assert_eq!(leaves, vec!["0002_age", "0003_name"]);
+
+        let merge = merge_migration(&migrations, &leaves, SystemTime::now()).unwrap();
+        assert_eq!(merge.id, "0004_merge");
+        assert_eq!(merge.dependency, "0003_name");
+        assert_eq!(merge.merges, vec!["0002_age"]);
+        assert!(merge.operations.is_empty());
+        assert!(!merge.hash.is_empty());
+
+        let mut merged = migrations.clone();
+        merged.push(merge);
+        assert_eq!(unmerged_leaves(&merged).len(), 1);
+
+        // Conflicting branches can't be merged
+        let mut conflicting = migrations.clone();
+        conflicting[1].operations = vec![Operation::DeleteModel {
+            name: "user".to_string(),
+            cascade: false,
+        }];
+        assert!(merge_migration(&conflicting, &leaves, SystemTime::now()).is_err());
+
+        assert!(merge_migration(&migrations, &leaves[..1], SystemTime::now()).is_err());
+    }
+
+    #[test]
+    fn order() {
+        assert_eq!(parse_order("", 3).unwrap(), vec![0, 1, 2]);
+        assert_eq!(parse_order("2 3,1", 3).unwrap(), vec![1, 2, 0]);
+        assert!(parse_order("1 2", 3).is_err());
+        assert!(parse_order("1 1 2", 3).is_err());
+        assert!(parse_order("1 2 4", 3).is_err());
+        assert!(parse_order("a", 1).is_err());
+    }
+
+    #[test]
+    fn merge_directory() {
+        let dir = std::env::temp_dir().join(format!("drorm_merge_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for migration in branches() {
+            let path = dir.join(format!("{}.toml", migration.id));
+            convert_migration_to_file(migration, &path).unwrap();
+        }
+        let options = || MergeOptions {
+            migration_dir: dir.to_str().unwrap().to_string(),
+            recursive: false,
+            non_interactive: true,
+        };
+        let ids = || {
+            get_existing_migrations(dir.to_str().unwrap(), false)
+                .map(|x| x.into_iter().map(|x| x.id).collect::<Vec<_>>())
+        };
+        assert!(ids().is_err());
+
+        // The branches are applied in the order of the numbers of their leaves
+        run_merge_migrations(options()).unwrap();
+        assert_eq!(
+            ids().unwrap(),
+            vec![
+                "0001_initial",
+                "0002_age",
+                "0002_email",
+                "0003_name",
+                "0004_merge"
+            ]
+        );
+
+        // Nothing is left to merge
+        run_merge_migrations(options()).unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 5);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,484 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use rorm_sql::DBImpl;
+use serde::{Deserialize, Serialize};
+use sqlx::any::{AnyPool, AnyRow};
+use sqlx::{query, Row};
+
+use crate::declaration::Migration;
+use crate::migrate::config::DatabaseConfig;
+use crate::migrate::{
+    bookkeeping_column_exists, bookkeeping_table_exists, connect, create_bookkeeping_table,
+};
+use crate::utils::output::{info, paint_diff_line};
+use crate::utils::question::question;
+
+/// Version of the format of [AppliedDump], increased on breaking changes
+pub const APPLIED_DUMP_VERSION: u32 = 1;
+
+/**
+The rows of the last migration table, written by `migrate --dump-applied`
+and read by `migrate --restore-applied`.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct AppliedDump {
+    /// Version of the format, see [APPLIED_DUMP_VERSION]
+    pub version: u32,
+    /// Name of the last migration table the rows were read from
+    pub table: String,
+    /// The recorded migrations, oldest first
+    pub migrations: Vec<AppliedMigration>,
+}
+
+/**
+A row of the last migration table.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct AppliedMigration {
+    /// Id of the row, which orders the recorded migrations
+    pub row: u64,
+    /// Id of the migration
+    pub migration: String,
+    /// Time the migration was recorded at, as formatted by the database
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recorded_at: Option<String>,
+    /// Whether the migration was skipped by its precondition
+    #[serde(default)]
+    pub skipped: bool,
+    /// Hash recorded with the migration, or the hash of its file when the rows were dumped
+    /// for rows recorded by an older version
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/**
+Helper method to build the query reading all rows of the last migration table.
+
+All columns are converted to text, so they are read the same way on all dialects.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`last_migration_table_name`: [&str]: Name of the last migration table.
+`hash`: [bool]: Whether the table has the column of hashes, which older versions didn't create.
+*/
+fn select_applied(db_impl: DBImpl, last_migration_table_name: &str, hash: bool) -> String {
+    let text = match db_impl {
+        DBImpl::SQLite | DBImpl::Postgres => "TEXT",
+        DBImpl::MySQL => "CHAR",
+    };
+    format!(
+        "SELECT CAST(id AS {0}), CAST(updated_at AS {0}), migration_name, CAST(skipped AS {0}), {1} FROM {2} ORDER BY id ASC;",
+        text,
+        if hash { "hash" } else { "NULL" },
+        last_migration_table_name
+    )
+}
+
+/**
+Helper method to build the statement which restores a row of the last migration table.
+
+The time it was recorded at is kept, the id of the row is assigned by the database.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`last_migration_table_name`: [&str]: Name of the last migration table.
+*/
+fn insert_applied(db_impl: DBImpl, last_migration_table_name: &str) -> String {
+    match db_impl {
+        DBImpl::SQLite | DBImpl::MySQL => format!(
+            "INSERT INTO {} (migration_name, skipped, hash, updated_at) VALUES (?, ?, ?, COALESCE(?, CURRENT_TIMESTAMP));",
+            last_migration_table_name
+        ),
+        DBImpl::Postgres => format!(
+            "INSERT INTO {} (migration_name, skipped, hash, updated_at) VALUES ($1, $2, $3, COALESCE(CAST($4 AS TIMESTAMP), CURRENT_TIMESTAMP));",
+            last_migration_table_name
+        ),
+    }
+}
+
+/**
+Reads the rows of the last migration table, no row is returned if it doesn't exist.
+
+Rows recorded by an older version have no hash.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`pool`: [&AnyPool]: The connection pool.
+`last_migration_table_name`: [&str]: Name of the last migration table.
+*/
+pub async fn read_applied(
+    db_impl: DBImpl,
+    pool: &AnyPool,
+    last_migration_table_name: &str,
+) -> anyhow::Result<Vec<AppliedMigration>> {
+    if !bookkeeping_table_exists(db_impl, pool, last_migration_table_name).await? {
+        return Ok(vec![]);
+    }
+
+    let hash = bookkeeping_column_exists(db_impl, pool, last_migration_table_name, "hash").await?;
+    let rows: Vec<AnyRow> =
+        query(select_applied(db_impl, last_migration_table_name, hash).as_str())
+            .fetch_all(pool)
+            .await
+            .with_context(|| "Couldn't read the rows of the last migration table")?;
+
+    rows.into_iter()
+        .map(|x| {
+            let row: String = x.get(0);
+            Ok(AppliedMigration {
+                row: row
+                    .parse()
+                    .with_context(|| format!("Invalid id {} in last migration table", row))?,
+                migration: x.get(2),
+                recorded_at: x.get(1),
+                skipped: matches!(x.get::<String, _>(3).as_str(), "1" | "true"),
+                hash: x.get(4),
+            })
+        })
+        .collect()
+}
+
+/**
+Replaces the rows of the last migration table in a single transaction.
+
+The table is created, if it doesn't exist. No other table is changed.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`pool`: [&AnyPool]: The connection pool.
+`last_migration_table_name`: [&str]: Name of the last migration table.
+`migrations`: [&\[AppliedMigration\]]: The rows to write, oldest first.
+*/
+pub async fn write_applied(
+    db_impl: DBImpl,
+    pool: &AnyPool,
+    last_migration_table_name: &str,
+    migrations: &[AppliedMigration],
+) -> anyhow::Result<()> {
+    create_bookkeeping_table(db_impl, pool, last_migration_table_name).await?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Couldn't start a transaction")?;
+    query(format!("DELETE FROM {};", last_migration_table_name).as_str())
+        .execute(&mut tx)
+        .await
+        .with_context(|| "Couldn't clear the last migration table")?;
+    for migration in migrations {
+        query(insert_applied(db_impl, last_migration_table_name).as_str())
+            .bind(migration.migration.as_str())
+            .bind(migration.skipped)
+            .bind(migration.hash.clone())
+            .bind(migration.recorded_at.clone())
+            .execute(&mut tx)
+            .await
+            .with_context(|| {
+                format!(
+                    "Couldn't record migration {} in migration table",
+                    migration.migration
+                )
+            })?;
+    }
+    tx.commit()
+        .await
+        .with_context(|| "Couldn't commit the changes of the migration table")
+}
+
+/**
+Returns warnings about the rows of a dump, which don't match the migrations on disk.
+
+`dump`: [&AppliedDump]: The dump to restore.
+`migrations`: [&\[Migration\]]: The migrations of the migration directories.
+*/
+pub fn restore_warnings(dump: &AppliedDump, migrations: &[Migration]) -> Vec<String> {
+    let mut warnings = vec![];
+    for applied in &dump.migrations {
+        match migrations
+            .iter()
+            .find(|x| x.id == applied.migration || x.replaces.contains(&applied.migration))
+        {
+            None => warnings.push(format!(
+                "Migration {} of the dump doesn't exist in the migration directories",
+                applied.migration
+            )),
+            Some(migration) => {
+                if migration.id == applied.migration
+                    && applied.hash.as_ref().is_some_and(|x| *x != migration.hash)
+                {
+                    warnings.push(format!(
+                        "Migration {} has been changed since the dump was taken",
+                        applied.migration
+                    ));
+                }
+            }
+        }
+    }
+    warnings
+}
+
+/**
+Writes the rows of the last migration table to a file.
+
+Rows without a recorded hash get the hash of the migration on disk, to detect changed
+migrations on restore.
+
+`db_conf`: [&DatabaseConfig]: The database configuration to use.
+`migrations`: [&\[Migration\]]: The migrations of the migration directories.
+`path`: [&str]: Path of the file to write.
+*/
+pub async fn dump_applied(
+    db_conf: &DatabaseConfig,
+    migrations: &[Migration],
+    path: &str,
+) -> anyhow::Result<()> {
+    let table = db_conf.last_migration_table_name.as_str();
+    let pool = connect(db_conf).await?;
+    let mut applied = read_applied(db_conf.driver.db_impl(), &pool, table).await?;
+    pool.close().await;
+
+    for row in applied.iter_mut() {
+        if row.hash.is_none() {
+            row.hash = migrations
+                .iter()
+                .find(|x| x.id == row.migration)
+                .map(|x| x.hash.clone());
+        }
+    }
+    let dump = AppliedDump {
+        version: APPLIED_DUMP_VERSION,
+        table: table.to_string(),
+        migrations: applied,
+    };
+    std::fs::write(
+        path,
+        serde_json::to_string_pretty(&dump).with_context(|| "Couldn't serialize dump")?,
+    )
+    .with_context(|| format!("Couldn't write dump file {}", path))?;
+
+    info!(
+        "Dumped {} recorded migrations of {} to {}",
+        dump.migrations.len(),
+        table,
+        path
+    );
+    Ok(())
+}
+
+/**
+Rewrites the last migration table to match a file written by [dump_applied].
+
+The changes are printed and confirmed, unless it's non-interactive.
+Only the last migration table is changed, the schema is never touched.
+
+`db_conf`: [&DatabaseConfig]: The database configuration to use.
+`migrations`: [&\[Migration\]]: The migrations of the migration directories.
+`path`: [&str]: Path of the dump file.
+`non_interactive`: [bool]: Rewrite the last migration table without asking.
+*/
+pub async fn restore_applied(
+    db_conf: &DatabaseConfig,
+    migrations: &[Migration],
+    path: &str,
+    non_interactive: bool,
+) -> anyhow::Result<()> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read dump file {}", path))?;
+    let dump: AppliedDump = serde_json::from_str(content.as_str())
+        .with_context(|| format!("Couldn't parse dump file {}", path))?;
+    if dump.version != APPLIED_DUMP_VERSION {
+        return Err(anyhow::anyhow!(
+            "Dump file {} has version {}, only version {} is supported",
+            path,
+            dump.version,
+            APPLIED_DUMP_VERSION
+        ));
+    }
+
+    for warning in restore_warnings(&dump, migrations) {
+        info!("Warning: {}", warning);
+    }
+
+    let db_impl = db_conf.driver.db_impl();
+    let table = db_conf.last_migration_table_name.as_str();
+    let pool = connect(db_conf).await?;
+    let recorded: Vec<String> = read_applied(db_impl, &pool, table)
+        .await?
+        .into_iter()
+        .map(|x| x.migration)
+        .collect();
+    let restored: Vec<String> = dump
+        .migrations
+        .iter()
+        .map(|x| x.migration.clone())
+        .collect();
+
+    let (recorded_set, restored_set): (HashSet<&String>, HashSet<&String>) =
+        (recorded.iter().collect(), restored.iter().collect());
+    for id in restored.iter().filter(|x| !recorded_set.contains(x)) {
+        info!("{}", paint_diff_line(&format!("+ record {}", id)));
+    }
+    for id in recorded.iter().filter(|x| !restored_set.contains(x)) {
+        info!("{}", paint_diff_line(&format!("- forget {}", id)));
+    }
+    if recorded == restored {
+        info!(
+            "The last migration table {} already matches {}",
+            table, path
+        );
+        pool.close().await;
+        return Ok(());
+    }
+
+    if !non_interactive
+        && !question(
+            format!("Do you want to rewrite the last migration table {}?", table).as_str(),
+        )?
+    {
+        info!("Aborted, the last migration table has not been changed.");
+        pool.close().await;
+        return Ok(());
+    }
+
+    write_applied(db_impl, &pool, table, &dump.migrations).await?;
+    pool.close().await;
+
+    info!(
+        "Restored {} recorded migrations of {} from {}",
+        dump.migrations.len(),
+        table,
+        path
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::DBImpl;
+    use sqlx::Executor;
+
+    use crate::declaration::Migration;
+    use crate::migrate::applied::{
+        read_applied, restore_warnings, write_applied, AppliedDump, APPLIED_DUMP_VERSION,
+    };
+    use crate::migrate::config::{DatabaseConfig, DatabaseDriver};
+    use crate::migrate::{connect, create_bookkeeping_table};
+
+    #[tokio::test]
+    async fn dump_and_restore() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_applied_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+        let table = db_conf.last_migration_table_name.as_str();
+        let pool = connect(&db_conf).await.unwrap();
+
+        // Nothing is recorded without the table
+        assert!(read_applied(DBImpl::SQLite, &pool, table)
+            .await
+            .unwrap()
+            .is_empty());
+
+        create_bookkeeping_table(DBImpl::SQLite, &pool, table)
+            .await
+            .unwrap();
+        pool.execute("INSERT INTO _drorm__last_migration (migration_name) VALUES ('0001_initial'), ('0002_user_age');")
+            .await
+            .unwrap();
+        pool.execute("UPDATE _drorm__last_migration SET skipped = TRUE WHERE migration_name = '0002_user_age';")
+            .await
+            .unwrap();
+        let dumped = read_applied(DBImpl::SQLite, &pool, table).await.unwrap();
+        assert_eq!(
+            dumped
+                .iter()
+                .map(|x| (x.migration.as_str(), x.skipped))
+                .collect::<Vec<_>>(),
+            vec![("0001_initial", false), ("0002_user_age", true)]
+        );
+        assert!(dumped.iter().all(|x| x.recorded_at.is_some()));
+
+        // Another migration is recorded after the dump and forgotten by the restore
+        pool.execute("INSERT INTO _drorm__last_migration (migration_name) VALUES ('0003_post');")
+            .await
+            .unwrap();
+        write_applied(DBImpl::SQLite, &pool, table, &dumped)
+            .await
+            .unwrap();
+        let restored = read_applied(DBImpl::SQLite, &pool, table).await.unwrap();
+        assert_eq!(
+            restored
+                .iter()
+                .map(|x| (&x.migration, x.skipped, &x.recorded_at))
+                .collect::<Vec<_>>(),
+            dumped
+                .iter()
+                .map(|x| (&x.migration, x.skipped, &x.recorded_at))
+                .collect::<Vec<_>>()
+        );
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn warnings() {
+        let migration = |id: &str, hash: &str| Migration {
+            hash: hash.to_string(),
+            initial: false,
+            id: id.to_string(),
+            dependency: "".to_string(),
+            external_dependencies: vec![],
+            merges: vec![],
+            replaces: vec![],
+            atomic: true,
+            operations: vec![],
+            precondition: None,
+            lock_timeout_ms: None,
+            naming_policy: None,
+            meta: None,
+        };
+        let dump: AppliedDump = serde_json::from_str(
+            r#"{
+                "Version": 1,
+                "Table": "_drorm__last_migration",
+                "Migrations": [
+                    {"Row": 1, "Migration": "0001_initial", "Hash": "a"},
+                    {"Row": 2, "Migration": "0002_user_age", "Hash": "b"},
+                    {"Row": 3, "Migration": "0003_post"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(dump.version, APPLIED_DUMP_VERSION);
+
+        let warnings = restore_warnings(
+            &dump,
+            &[
+                migration("0001_initial", "a"),
+                migration("0002_user_age", "c"),
+            ],
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "Migration 0002_user_age has been changed since the dump was taken",
+                "Migration 0003_post of the dump doesn't exist in the migration directories"
+            ]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::declaration::TypeChange;
+use crate::migrate::config::DatabaseDriver;
+
+/// Any error type, used for errors which are only reported and not matched on
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/**
+Errors of the library interface of the migrate tool.
+
+The variants distinguish the failure categories,
+e.g. to retry on connection errors but fail fast on SQL errors.
+*/
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    /// The migration files could not be read or parsed
+    #[error("Couldn't read migrations from {location}")]
+    Parse {
+        location: String,
+        #[source]
+        source: BoxError,
+    },
+
+    /// A migration uses an operation which is not known to this version, e.g. it was written by a newer version
+    #[error("Migration {migration} uses operation '{operation}', which this version of drorm doesn't support. Upgrade drorm to apply it.")]
+    UnsupportedOperation {
+        migration: String,
+        operation: String,
+    },
+
+    /// The migrations don't form a valid history, e.g. a dependency is missing
+    #[error("Invalid migration history")]
+    DependencyChain(#[source] BoxError),
+
+    /// An operation is invalid or not supported by the dialect
+    #[error("Couldn't generate the SQL of migration {migration}")]
+    Build {
+        migration: String,
+        #[source]
+        source: BoxError,
+    },
+
+    /// The database could not be connected to
+    #[error("Couldn't connect to the database")]
+    Connection(#[source] sqlx::Error),
+
+    /// A statement of a migration failed
+    #[error("Error while applying migration {migration}{}", .operation.as_ref().map(|x| format!(", operation {}", x)).unwrap_or_default())]
+    Sql {
+        migration: String,
+        /// The failed operation in the form of a diff line, e.g. `+ user.age`
+        operation: Option<String>,
+        statement: String,
+        #[source]
+        source: sqlx::Error,
+    },
+
+    /// The rows of a table rebuilt on SQLite violate its foreign keys
+    #[error("Migration {migration} was rolled back: rows of table {table} violate its foreign keys after rebuilding tables")]
+    ForeignKeyViolation { migration: String, table: String },
+
+    /// A statement of a migration took longer than the statement timeout
+    #[error("Statement of migration {migration} didn't finish within {} seconds", .timeout.as_secs())]
+    Timeout {
+        migration: String,
+        statement: String,
+        timeout: Duration,
+    },
+
+    /// A statement of a migration couldn't acquire a lock within the lock timeout of the migration
+    #[error("Migration {migration} couldn't acquire a lock within {}ms, retry later", .lock_timeout.as_millis())]
+    LockTimeout {
+        migration: String,
+        statement: String,
+        lock_timeout: Duration,
+        #[source]
+        source: sqlx::Error,
+    },
+
+    /// The migrate run was interrupted, e.g. by Ctrl-C
+    #[error("Interrupted while applying migration {migration}, {}", if *.atomic {
+        "it was rolled back and not recorded as applied"
+    } else {
+        "it is not atomic, so the statements executed before the interrupt are not rolled back"
+    })]
+    Interrupted { migration: String, atomic: bool },
+
+    /// The last migration table could not be created, read or written
+    #[error("Error while accessing the last migration table {table}")]
+    Bookkeeping {
+        table: String,
+        #[source]
+        source: BoxError,
+    },
+
+    /// The precondition of a migration doesn't hold
+    #[error("Migration {migration} was not applied: its precondition {sql:?} doesn't hold. Set OnFailure = \"skip\" to record it as skipped instead.")]
+    PreconditionFailed { migration: String, sql: String },
+
+    /// The dialect override does not match the configured driver
+    #[error("Refusing to apply migrations: the dialect override {dialect_override:?} does not match the configured driver {driver:?}. Use --dry-run to only print the generated SQL.")]
+    DialectMismatch {
+        dialect_override: DatabaseDriver,
+        driver: DatabaseDriver,
+    },
+
+    /// The pending migrations can't be applied in a single transaction
+    #[error("Refusing to apply the migrations in a single transaction: {reason}. Use --transaction-per-migration instead.")]
+    SingleTransaction { reason: String },
+
+    /// The last applied migration is not part of the migrations
+    #[error(
+        r#"Last applied migration {migration} was not found in current migrations.
+
+Can not proceed any further without damaging data.
+To correct, run `drorm repair`, empty the {table} table or reset the whole database.
+To continue after the latest recorded migration which exists, use --ignore-missing-dependency."#
+    )]
+    UnknownAppliedMigration { migration: String, table: String },
+
+    /// The pending migrations don't match the expected plan
+    #[error("Refusing to apply migrations: the plan hash {actual} does not match the expected plan hash {expected}. Review the plan with --print-plan.")]
+    PlanHashMismatch { expected: String, actual: String },
+
+    /// The hashes of migrations don't match their operations or the hashes recorded when applying them
+    #[error("Refusing to apply migrations with mismatched hashes:\n{}", .mismatches.join("\n"))]
+    HashMismatch { mismatches: Vec<String> },
+
+    /// An exported plan file is damaged, modified or of an unsupported version
+    #[error("Invalid plan file: {reason}")]
+    InvalidPlanFile { reason: String },
+
+    /// A migration replaces other migrations, of which only some have been applied
+    #[error("Migration {migration} replaces migrations, of which {} have not been applied. Apply them with the replaced migration files before switching to {migration}.", .missing.join(", "))]
+    PartiallyReplaced {
+        migration: String,
+        missing: Vec<String>,
+    },
+
+    /// A pending migration narrows or converts the type of a field
+    #[error("Refusing to apply migration {migration}: changing the type of field {model}.{field} is {change}, existing values may be lost or fail to convert. Review the change and pass --allow-unsafe to apply it.")]
+    UnsafeTypeChange {
+        migration: String,
+        model: String,
+        field: String,
+        change: TypeChange,
+    },
+
+    /// A rollback reverses more migrations than have been applied
+    #[error("Can't roll back {count} migrations, only {applied} have been applied")]
+    RollbackCount { count: usize, applied: usize },
+
+    /// Migrations failed while the remaining ones were applied, see [crate::migrate::migrator::Migrator::keep_going]
+    #[error("{}", failure_report(.failures, .skipped))]
+    Failures {
+        /// The failed migrations and their errors in the order they were applied
+        failures: Vec<(String, MigrationError)>,
+        /// Migrations which were not applied, as they depend on a failed migration
+        skipped: Vec<String>,
+    },
+}
+
+/**
+Helper function to list the failed and skipped migrations of [MigrationError::Failures].
+
+`failures`: [&\[(String, MigrationError)\]]: The failed migrations and their errors.
+`skipped`: [&\[String\]]: The skipped migrations.
+*/
+fn failure_report(failures: &[(String, MigrationError)], skipped: &[String]) -> String {
+    let mut report = format!("{} migrations failed:", failures.len());
+    for (migration, err) in failures {
+        let mut message = err.to_string();
+        let mut source = std::error::Error::source(err);
+        while let Some(err) = source {
+            message.push_str(format!(": {}", err).as_str());
+            source = err.source();
+        }
+        report.push_str(format!("\n    {}: {}", migration, message).as_str());
+    }
+    if !skipped.is_empty() {
+        report.push_str(
+            format!(
+                "\nSkipped as they depend on failed migrations: {}",
+                skipped.join(", ")
+            )
+            .as_str(),
+        );
+    }
+    report
+}
@@ -0,0 +1,560 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use rorm_sql::DBImpl;
+use sqlx::any::{AnyPool, AnyRow};
+use sqlx::{query, Row};
+
+use crate::declaration::Migration;
+use crate::make_migrations::{is_legacy_hash, models_hash};
+use crate::migrate::{bookkeeping_column_exists, bookkeeping_table_exists};
+use crate::utils::migrations::convert_migrations_to_internal_models;
+
+/**
+Recomputes the hash of a migration from its operations.
+
+The hash of a migration is the hash of the models resulting from it, see [models_hash],
+so the migrations of its directory are replayed up to and including it.
+Migrations of other directories are no part of its models and are left out,
+as are migrations of other branches, which haven't been merged into it yet.
+
+`migrations`: [&\[Migration\]]: The ordered migrations.
+`index`: [usize]: Index of the migration to recompute the hash of.
+*/
+pub fn recompute_hash(migrations: &[Migration], index: usize) -> anyhow::Result<String> {
+    let namespace = |migration: &Migration| {
+        migration
+            .id
+            .split_once('/')
+            .map(|(namespace, _)| namespace.to_string())
+    };
+    let own = namespace(&migrations[index]);
+
+    // The migrations the migration depends on, directly or through a merge
+    let lookup: HashMap<&str, &Migration> = migrations[..=index]
+        .iter()
+        .map(|x| (x.id.as_str(), x))
+        .collect();
+    let mut ancestors: HashSet<&str> = HashSet::new();
+    let mut pending = vec![migrations[index].id.as_str()];
+    while let Some(id) = pending.pop() {
+        if !ancestors.insert(id) {
+            continue;
+        }
+        if let Some(migration) = lookup.get(id) {
+            pending.push(migration.dependency.as_str());
+            pending.extend(migration.merges.iter().map(String::as_str));
+        }
+    }
+
+    let replayed: Vec<Migration> = migrations[..=index]
+        .iter()
+        .filter(|x| namespace(x) == own && ancestors.contains(x.id.as_str()))
+        .cloned()
+        .collect();
+
+    Ok(models_hash(&convert_migrations_to_internal_models(
+        &replayed,
+    )?))
+}
+
+/**
+Reads the hashes recorded in the last migration table, oldest first.
+
+No hash is returned if the table or its column of hashes don't exist.
+Migrations recorded by an older version have no hash.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`pool`: [&AnyPool]: The connection pool.
+`last_migration_table_name`: [&str]: Name of the last migration table.
+*/
+pub async fn recorded_hashes(
+    db_impl: DBImpl,
+    pool: &AnyPool,
+    last_migration_table_name: &str,
+) -> anyhow::Result<Vec<(String, Option<String>)>> {
+    if !bookkeeping_table_exists(db_impl, pool, last_migration_table_name).await?
+        || !bookkeeping_column_exists(db_impl, pool, last_migration_table_name, "hash").await?
+    {
+        return Ok(vec![]);
+    }
+
+    query(
+        format!(
+            "SELECT migration_name, hash FROM {} ORDER BY id ASC;",
+            last_migration_table_name
+        )
+        .as_str(),
+    )
+    .map(|x: AnyRow| (x.get(0), x.get(1)))
+    .fetch_all(pool)
+    .await
+    .with_context(|| "Couldn't read the hashes of the last migration table")
+}
+
+/**
+Compares the hashes of the migrations with their operations and the last migration table.
+
+The hash of a pending migration has to match the hash recomputed from its operations.
+The hash of an applied migration has to match the hash recorded when applying it.
+Migrations without a hash, e.g. written by hand, and rows without one are not compared.
+Legacy hashes of pending migrations can't be recomputed and are not compared either,
+see [is_legacy_hash].
+
+`migrations`: [&\[Migration\]]: The ordered migrations.
+`pending`: [&\[&Migration\]]: The pending migrations.
+`recorded`: [&\[(String, Option<String>)\]]: The recorded migrations and their hashes.
+*/
+pub fn hash_mismatches(
+    migrations: &[Migration],
+    pending: &[&Migration],
+    recorded: &[(String, Option<String>)],
+) -> Vec<String> {
+    let mut mismatches = vec![];
+
+    for migration in pending
+        .iter()
+        .filter(|x| !x.hash.is_empty() && !is_legacy_hash(&x.hash))
+    {
+        let index = match migrations.iter().position(|x| x.id == migration.id) {
+            Some(index) => index,
+            None => continue,
+        };
+        // Models which can't be replayed on their own, e.g. referring to another directory,
+        // have no hash to compare with
+        if let Ok(hash) = recompute_hash(migrations, index) {
+            if hash != migration.hash {
+                mismatches.push(format!(
+                    "Pending migration {} has the hash {}, but its operations result in the hash {}",
+                    migration.id, migration.hash, hash
+                ));
+            }
+        }
+    }
+
+    for (id, hash) in recorded {
+        let migration = migrations.iter().find(|x| x.id == *id);
+        if let (Some(hash), Some(migration)) = (hash, migration) {
+            if migration.hash.is_empty() || *hash == migration.hash {
+                continue;
+            }
+            mismatches.push(format!(
+                "Migration {} was applied with the hash {}, but its file has the hash {}",
+                id, hash, migration.hash
+            ));
+        }
+    }
+
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{Annotation, DbType, Field, InternalModelFormat, Model, ModelAnnotation};
+    use sqlx::any::AnyRow;
+    use sqlx::{query, Row};
+
+    use crate::declaration::Operation;
+    use crate::make_migrations::{
+        make_migrations, IdScheme, MakeMigrationsOptions, RenameMap, MODELS_HASH_VERSION,
+    };
+    use crate::migrate::config::{DatabaseConfig, DatabaseDriver};
+    use crate::migrate::connect;
+    use crate::migrate::error::MigrationError;
+    use crate::migrate::hashes::{hash_mismatches, recompute_hash};
+    use crate::migrate::migrator::Migrator;
+    use crate::utils::migrations::{convert_str_to_migration, read_migrations};
+
+    const CREATE_USER: &str = r#"[Migration]
+Hash = ""
+Initial = true
+Dependency = ""
+Replaces = []
+
+[[Migration.Operations]]
+Type = "CreateModel"
+Name = "user"
+
+[[Migration.Operations.Fields]]
+Name = "id"
+Type = "int64"
+Annotations = [{ Type = "primary_key" }]
+"#;
+
+    #[test]
+    fn pending_mismatch() {
+        let dir = std::env::temp_dir().join(format!("drorm_hashes_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let models_file = dir.join("models.json");
+        let migration_dir = dir.join("migrations");
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = MakeMigrationsOptions {
+            models_files: vec![models_file.to_str().unwrap().to_string()],
+            migration_dir: migration_dir.to_str().unwrap().to_string(),
+            recursive: false,
+            name: None,
+            non_interactive: true,
+            warnings_disabled: false,
+            verbose_diff: false,
+            json: false,
+            dry_run: false,
+            squash_check: false,
+            squash_threshold: 50,
+            cascade: false,
+            rename_constraints: false,
+            auto_timestamps: false,
+            id_scheme: IdScheme::Sequential,
+            id_width: 4,
+            description: None,
+            models_command: None,
+            naming_policy: None,
+        };
+        let field = |name: &str, db_type, annotations| Field {
+            name: name.to_string(),
+            db_type,
+            annotations,
+            source_defined_at: None,
+        };
+        let write_models = |fields: Vec<Field>| {
+            let models = InternalModelFormat {
+                models: vec![Model {
+                    name: "user".to_string(),
+                    fields,
+                    annotations: vec![],
+                    source_defined_at: None,
+                }],
+            };
+            std::fs::write(&models_file, serde_json::to_string(&models).unwrap()).unwrap();
+        };
+
+        let id = field("id", DbType::Int64, vec![Annotation::PrimaryKey]);
+        write_models(vec![id.clone()]);
+        make_migrations(&options, &RenameMap::default()).unwrap();
+        write_models(vec![
+            id,
+            field(
+                "name",
+                DbType::VarChar,
+                vec![Annotation::MaxLength(255), Annotation::NotNull],
+            ),
+        ]);
+        make_migrations(&options, &RenameMap::default()).unwrap();
+
+        // The hashes of the generator are recomputed from the operations
+        let mut migrations = read_migrations(migration_dir.to_str().unwrap(), false).unwrap();
+        assert_eq!(migrations.len(), 2);
+        for (index, migration) in migrations.iter().enumerate() {
+            assert_eq!(recompute_hash(&migrations, index).unwrap(), migration.hash);
+        }
+        let pending: Vec<_> = migrations.iter().collect();
+        assert!(hash_mismatches(&migrations, &pending, &[]).is_empty());
+
+        // Editing the operations of a migration changes the models it results in
+        if let Operation::CreateField { field, .. } = &mut migrations[1].operations[0] {
+            field
+                .annotations
+                .retain(|x| !matches!(x, Annotation::NotNull));
+        }
+        let pending: Vec<_> = migrations.iter().collect();
+        let mismatches = hash_mismatches(&migrations, &pending, &[]);
+        assert_eq!(mismatches.len(), 1);
+        assert!(mismatches[0].starts_with("Pending migration 0002_"));
+
+        // A hash written by hand isn't compared
+        migrations[1].hash = String::new();
+        let pending: Vec<_> = migrations.iter().collect();
+        assert!(hash_mismatches(&migrations, &pending, &[]).is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn strict_hash_after_make_migrations() {
+        let dir = std::env::temp_dir().join(format!("drorm_strict_hash_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let models_file = dir.join("models.json");
+        let migration_dir = dir.join("migrations");
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = MakeMigrationsOptions {
+            models_files: vec![models_file.to_str().unwrap().to_string()],
+            migration_dir: migration_dir.to_str().unwrap().to_string(),
+            recursive: false,
+            name: None,
+            non_interactive: true,
+            warnings_disabled: true,
+            verbose_diff: false,
+            json: false,
+            dry_run: false,
+            squash_check: false,
+            squash_threshold: 50,
+            cascade: false,
+            rename_constraints: false,
+            auto_timestamps: false,
+            id_scheme: IdScheme::Sequential,
+            id_width: 4,
+            description: None,
+            models_command: None,
+            naming_policy: None,
+        };
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: dir.join("db.sqlite3").to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+        let field = |name: &str, annotations| Field {
+            name: name.to_string(),
+            db_type: DbType::VarChar,
+            annotations,
+            source_defined_at: None,
+        };
+        let id = Field {
+            name: "id".to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![Annotation::PrimaryKey],
+            source_defined_at: None,
+        };
+        let write_models = |fields: Vec<Field>, annotations: Vec<ModelAnnotation>| {
+            let models = InternalModelFormat {
+                models: vec![Model {
+                    name: "user".to_string(),
+                    fields: [vec![id.clone()], fields].concat(),
+                    annotations,
+                    source_defined_at: None,
+                }],
+            };
+            std::fs::write(&models_file, serde_json::to_string(&models).unwrap()).unwrap();
+        };
+        let make_and_apply = || async {
+            let result = make_migrations(&options, &RenameMap::default()).unwrap();
+            assert!(result.written.is_some());
+            Migrator::from_dir(migration_dir.to_str().unwrap())
+                .unwrap()
+                .strict_hash()
+                .apply(&db_conf)
+                .await
+                .unwrap();
+        };
+
+        write_models(
+            vec![field(
+                "name",
+                vec![Annotation::NotNull, Annotation::MaxLength(255)],
+            )],
+            vec![],
+        );
+        make_and_apply().await;
+
+        // AlterField
+        write_models(
+            vec![field(
+                "name",
+                vec![Annotation::MaxLength(500), Annotation::NotNull],
+            )],
+            vec![],
+        );
+        make_and_apply().await;
+
+        // RenameField, the hint left in the models is no change
+        write_models(
+            vec![field(
+                "username",
+                vec![
+                    Annotation::RenameFrom("name".to_string()),
+                    Annotation::MaxLength(500),
+                    Annotation::NotNull,
+                ],
+            )],
+            vec![],
+        );
+        make_and_apply().await;
+        write_models(
+            vec![field(
+                "username",
+                vec![Annotation::NotNull, Annotation::MaxLength(500)],
+            )],
+            vec![],
+        );
+        let result = make_migrations(&options, &RenameMap::default()).unwrap();
+        assert!(result.written.is_none());
+
+        // unique_together
+        write_models(
+            vec![
+                field(
+                    "username",
+                    vec![Annotation::NotNull, Annotation::MaxLength(500)],
+                ),
+                field("email", vec![Annotation::MaxLength(500)]),
+            ],
+            vec![ModelAnnotation::UniqueTogether(vec![
+                "username".to_string(),
+                "email".to_string(),
+            ])],
+        );
+        make_and_apply().await;
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merged_branches() {
+        let branch = |id: &str, dependency: &str, field: &str| {
+            let mut migration = convert_str_to_migration(
+                format!("{}.toml", id).as_str(),
+                format!(
+                    "[Migration]\nHash = \"\"\nInitial = false\nDependency = \"{}\"\nReplaces = []\n\n\
+                    [[Migration.Operations]]\nType = \"CreateField\"\nModel = \"user\"\n\
+                    Field = {{ Name = \"{}\", Type = \"int32\", Annotations = [] }}\n",
+                    dependency, field
+                )
+                .as_str(),
+            )
+            .unwrap()
+            .migration;
+            migration.id = id.to_string();
+            migration
+        };
+        let initial = convert_str_to_migration("0001_initial.toml", CREATE_USER)
+            .unwrap()
+            .migration;
+        let mut merge = branch("0003_merge", "0002_age", "");
+        merge.operations.clear();
+        merge.merges = vec!["0002_email".to_string()];
+        let migrations = vec![
+            initial.clone(),
+            branch("0002_email", "0001_initial", "email"),
+            branch("0002_age", "0001_initial", "age"),
+            merge,
+        ];
+
+        // A branch results in its own models, the merge migration in the models of both
+        let age = vec![initial, migrations[2].clone()];
+        assert_eq!(
+            recompute_hash(&migrations, 2).unwrap(),
+            recompute_hash(&age, 1).unwrap()
+        );
+        assert_ne!(
+            recompute_hash(&migrations, 3).unwrap(),
+            recompute_hash(&migrations, 2).unwrap()
+        );
+        assert_ne!(
+            recompute_hash(&migrations, 3).unwrap(),
+            recompute_hash(&migrations, 1).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn legacy_hashes() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_legacy_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        // Written by make-migrations of the version before the hashes were versioned
+        Migrator::from_embedded(&[(
+            "0001_initial.toml",
+            include_str!("../../tests/legacy/migrations/0001_initial.toml"),
+        )])
+        .unwrap()
+        .strict_hash()
+        .apply(&db_conf)
+        .await
+        .unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn applied_mismatch() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_hashes_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        let migration = convert_str_to_migration("0001_initial.toml", CREATE_USER)
+            .unwrap()
+            .migration;
+        let hash = recompute_hash(&[migration], 0).unwrap();
+        let file = CREATE_USER.replace("Hash = \"\"", format!("Hash = \"{}\"", hash).as_str());
+        let edited_hash = format!("{}:{}", MODELS_HASH_VERSION, "0".repeat(64));
+        let edited = CREATE_USER.replace(
+            "Hash = \"\"",
+            format!("Hash = \"{}\"", edited_hash).as_str(),
+        );
+
+        // A pending migration with a mismatched hash is refused
+        assert!(matches!(
+            Migrator::from_embedded(&[("0001_initial.toml", edited.as_str())])
+                .unwrap()
+                .strict_hash()
+                .apply(&db_conf)
+                .await,
+            Err(MigrationError::HashMismatch { mismatches })
+                if mismatches[0].starts_with("Pending migration 0001_initial")
+        ));
+
+        Migrator::from_embedded(&[("0001_initial.toml", file.as_str())])
+            .unwrap()
+            .strict_hash()
+            .apply(&db_conf)
+            .await
+            .unwrap();
+        let pool = connect(&db_conf).await.unwrap();
+        let recorded: Option<String> = query("SELECT hash FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(recorded, Some(hash.clone()));
+        pool.close().await;
+
+        // Editing the file after applying it is only a warning without strict hashes
+        let migrator = || Migrator::from_embedded(&[("0001_initial.toml", edited.as_str())]);
+        migrator().unwrap().apply(&db_conf).await.unwrap();
+        assert!(matches!(
+            migrator().unwrap().strict_hash().apply(&db_conf).await,
+            Err(MigrationError::HashMismatch { mismatches })
+                if mismatches == vec![format!(
+                    "Migration 0001_initial was applied with the hash {}, but its file has the hash {}",
+                    hash, edited_hash
+                )]
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
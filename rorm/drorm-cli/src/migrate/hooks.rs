@@ -0,0 +1,130 @@
+use std::future::Future;
+use std::process::Command;
+
+use anyhow::{anyhow, Context};
+
+use crate::migrate::config::DatabaseConfig;
+use crate::utils::output::info;
+
+/**
+Runs a hook of the database configuration.
+
+The command is run by the shell with the working directory of the CLI,
+its output is passed through.
+
+`name`: [&str]: Name of the hook, used in messages.
+`command`: [&str]: The shell command to run.
+*/
+pub fn run_hook(name: &str, command: &str) -> anyhow::Result<()> {
+    info!("Running the {} hook {}", name, command);
+    let mut shell = match cfg!(windows) {
+        true => Command::new("cmd"),
+        false => Command::new("sh"),
+    };
+    let status = shell
+        .arg(if cfg!(windows) { "/C" } else { "-c" })
+        .arg(command)
+        .status()
+        .with_context(|| format!("Couldn't run the {} hook {}", name, command))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "The {} hook {} failed with {}",
+            name,
+            command,
+            status
+        ));
+    }
+    Ok(())
+}
+
+/**
+Applies migrations between the hooks of the database configuration.
+
+The pre migrate hook runs before anything is applied, if it fails the migrations aren't applied.
+The post migrate hook only runs if applying succeeded.
+Both hooks are optional.
+
+`db_conf`: [&DatabaseConfig]: The database configuration with the hooks.
+`apply`: [F]: Applies the migrations.
+*/
+pub async fn with_hooks<F>(db_conf: &DatabaseConfig, apply: F) -> anyhow::Result<()>
+where
+    F: Future<Output = anyhow::Result<()>>,
+{
+    if let Some(command) = &db_conf.pre_migrate_command {
+        run_hook("pre migrate", command.as_str())
+            .with_context(|| "Aborted before applying any migration")?;
+    }
+
+    apply.await?;
+
+    if let Some(command) = &db_conf.post_migrate_command {
+        run_hook("post migrate", command.as_str())
+            .with_context(|| "The migrations have been applied")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use crate::migrate::config::{DatabaseConfig, DatabaseDriver};
+    use crate::migrate::hooks::with_hooks;
+
+    #[tokio::test]
+    async fn hooks() {
+        let log = std::env::temp_dir().join(format!("drorm_hooks_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let read_log = || std::fs::read_to_string(&log).unwrap_or_default();
+        let echo = |text: &str| format!("echo {} >> '{}'", text, log.display());
+        let db_conf = |pre: Option<String>, post: Option<String>| DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: pre,
+            post_migrate_command: post,
+            analyze: false,
+        };
+
+        // The pre hook has run when applying starts, the post hook after it succeeded
+        let conf = db_conf(Some(echo("pre")), Some(echo("post")));
+        with_hooks(&conf, async {
+            assert_eq!(read_log(), "pre\n");
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(read_log(), "pre\npost\n");
+
+        // A failed run skips the post hook
+        std::fs::remove_file(&log).unwrap();
+        assert!(with_hooks(&conf, async { Err(anyhow!("failed")) })
+            .await
+            .is_err());
+        assert_eq!(read_log(), "pre\n");
+
+        // A failing pre hook aborts with its exit code, before anything is applied
+        std::fs::remove_file(&log).unwrap();
+        let conf = db_conf(Some("exit 3".to_string()), Some(echo("post")));
+        let err = with_hooks(&conf, async {
+            std::fs::write(&log, "applied").unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("exit status: 3"));
+        assert_eq!(read_log(), "");
+
+        // Hooks are opt-in
+        with_hooks(&db_conf(None, None), async { Ok(()) })
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,2817 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::Context;
+use rorm_sql::DBImpl;
+use sqlx::any::{AnyPool, AnyRow};
+use sqlx::{query, Connection, Row};
+use tokio::signal;
+
+use crate::declaration::{Migration, Operation};
+use crate::make_migrations::operation_to_diff_line;
+use crate::migrate::config::{DatabaseConfig, DatabaseDriver};
+use crate::migrate::error::MigrationError;
+use crate::migrate::hashes::{hash_mismatches, recorded_hashes};
+use crate::migrate::plan::{
+    plan_hash, ExportedMigration, Plan, PlanFile, PlannedMigration, PLAN_VERSION,
+};
+use crate::migrate::rebuild::{rebuilt_tables, TableState};
+use crate::migrate::rollback::{down_operations, rollback_targets};
+use crate::migrate::server::detect_server;
+use crate::migrate::sql_builder::{
+    affected_tables, analyze_statements, migration_to_sql, operation_statements,
+    redact_sensitive_defaults, BuildOptions,
+};
+use crate::migrate::status::{MigrationStatus, Status, STATUS_VERSION};
+use crate::migrate::{
+    apply_in_transaction, apply_migration, apply_transaction, bookkeeping_table_exists, connect,
+    create_bookkeeping_table, print_failed_sql, rebuild_connection, report_applied, Outcome,
+};
+use crate::utils::migrations::{
+    convert_migrations_to_internal_models, merge_migration_dirs, order_migrations,
+    read_embedded_migrations, read_migrations, replaced_migrations, MigrationDir,
+};
+use crate::utils::output::{info, paint, Style};
+
+/**
+Applies a set of migrations on a database.
+
+This is the library interface of the migrate tool.
+The migrations can either be read from a directory or embedded into the binary:
+
+```ignore
+use drorm_cli::migrate::migrator::Migrator;
+
+static MIGRATIONS: &[(&str, &str)] = &[
+    ("0001_initial.toml", include_str!("../migrations/0001_initial.toml")),
+    ("0002_user_age.toml", include_str!("../migrations/0002_user_age.toml")),
+];
+
+Migrator::from_embedded(MIGRATIONS)?.apply(&db_conf).await?;
+```
+*/
+pub struct Migrator {
+    migrations: Vec<Migration>,
+    replaced: Vec<Migration>,
+    dry_run: bool,
+    dialect_override: Option<DatabaseDriver>,
+    no_bookkeeping: bool,
+    statement_timeout: Option<Duration>,
+    idempotent: bool,
+    continue_on_error: bool,
+    ignore_missing_dependency: bool,
+    expected_plan_hash: Option<String>,
+    allow_unsafe: bool,
+    keep_going: bool,
+    single_transaction: bool,
+    retries: u32,
+    show_sql_on_error: bool,
+    strict_hash: bool,
+    analyze: bool,
+}
+
+impl Migrator {
+    /**
+    Creates a migrator from validated migrations.
+
+    `migrations`: [Vec<Migration>]: The ordered migrations without the replaced ones.
+    `replaced`: [Vec<Migration>]: The replaced migrations whose files have been kept,
+    see [Migrator::pending].
+    */
+    fn new(migrations: Vec<Migration>, replaced: Vec<Migration>) -> Result<Self, MigrationError> {
+        // Validates the operations against the state resulting from the previous migrations
+        convert_migrations_to_internal_models(&migrations)
+            .map_err(|err| MigrationError::DependencyChain(err.into()))?;
+
+        Ok(Migrator {
+            migrations,
+            replaced,
+            dry_run: false,
+            dialect_override: None,
+            no_bookkeeping: false,
+            statement_timeout: None,
+            idempotent: false,
+            continue_on_error: false,
+            ignore_missing_dependency: false,
+            expected_plan_hash: None,
+            allow_unsafe: false,
+            keep_going: false,
+            single_transaction: false,
+            retries: 0,
+            show_sql_on_error: false,
+            strict_hash: false,
+            analyze: false,
+        })
+    }
+
+    /**
+    Creates a migrator from the migrations in the given directory.
+
+    `migration_dir`: [&str]: Directory, migrations exist in.
+    */
+    pub fn from_dir(migration_dir: &str) -> Result<Self, MigrationError> {
+        let migrations =
+            read_migrations(migration_dir, false).map_err(|err| parse_error(migration_dir, err))?;
+
+        let dir = MigrationDir {
+            namespace: String::new(),
+            path: migration_dir.to_string(),
+        };
+        Self::from_read(vec![(dir, migrations)])
+    }
+
+    /**
+    Creates a migrator from the migrations of multiple directories.
+
+    The directories are given as `namespace=path` or `path`, see [MigrationDir::parse].
+    If more than one directory is given, the ids of the migrations are prefixed
+    with the namespace of their directory, see [merge_migration_dirs].
+
+    `migration_dirs`: [&\[&str\]]: Directories, migrations exist in.
+    */
+    pub fn from_dirs(migration_dirs: &[&str]) -> Result<Self, MigrationError> {
+        Self::read_dirs(migration_dirs, false)
+    }
+
+    /**
+    Creates a migrator from the migrations of multiple directories and their subdirectories.
+
+    The migrations of the subdirectories are flattened into the history of their directory,
+    see [Migrator::from_dirs].
+
+    `migration_dirs`: [&\[&str\]]: Directories, migrations exist in.
+    */
+    pub fn from_dirs_recursive(migration_dirs: &[&str]) -> Result<Self, MigrationError> {
+        Self::read_dirs(migration_dirs, true)
+    }
+
+    /**
+    Reads the migrations of multiple directories.
+
+    `migration_dirs`: [&\[&str\]]: Directories, migrations exist in.
+    `recursive`: [bool]: Whether the subdirectories are searched as well.
+    */
+    fn read_dirs(migration_dirs: &[&str], recursive: bool) -> Result<Self, MigrationError> {
+        let mut dirs = vec![];
+        for dir in migration_dirs {
+            let dir = MigrationDir::parse(dir);
+            let migrations = read_migrations(dir.path.as_str(), recursive)
+                .map_err(|err| parse_error(&dir.path, err))?;
+            dirs.push((dir, migrations));
+        }
+
+        Self::from_read(dirs)
+    }
+
+    /**
+    Creates a migrator from migrations embedded into the binary.
+
+    `files`: [&\[(&str, &str)\]]: Pairs of file name and content of the migrations.
+    */
+    pub fn from_embedded(files: &[(&str, &str)]) -> Result<Self, MigrationError> {
+        let migrations = read_embedded_migrations(files)
+            .map_err(|err| parse_error("embedded migrations", err))?;
+
+        let dir = MigrationDir {
+            namespace: String::new(),
+            path: "embedded".to_string(),
+        };
+        Self::from_read(vec![(dir, migrations)])
+    }
+
+    /**
+    Creates a migrator from the read, but not yet ordered migrations of directories.
+
+    `dirs`: [Vec<(MigrationDir, Vec<Migration>)>]: The directories and their migrations.
+    */
+    fn from_read(dirs: Vec<(MigrationDir, Vec<Migration>)>) -> Result<Self, MigrationError> {
+        let namespaced = dirs.len() > 1;
+        let mut ordered = vec![];
+        let mut replaced = vec![];
+        for (dir, migrations) in dirs {
+            let ids = replaced_migrations(&migrations);
+            let (kept, migrations): (Vec<Migration>, Vec<Migration>) =
+                migrations.into_iter().partition(|x| ids.contains(&x.id));
+            // The ids are prefixed like the ones of the migrations replacing them
+            replaced.extend(kept.into_iter().map(|mut migration| {
+                if namespaced {
+                    migration.id = format!("{}/{}", dir.namespace, migration.id);
+                    if !migration.dependency.is_empty() {
+                        migration.dependency =
+                            format!("{}/{}", dir.namespace, migration.dependency);
+                    }
+                }
+                migration
+            }));
+
+            let migrations = order_migrations(migrations)
+                .with_context(|| format!("Invalid migrations in {}", dir.path))
+                .map_err(|err| MigrationError::DependencyChain(err.into()))?;
+            ordered.push((dir, migrations));
+        }
+
+        Self::new(
+            merge_migration_dirs(ordered)
+                .map_err(|err| MigrationError::DependencyChain(err.into()))?,
+            replaced,
+        )
+    }
+
+    /**
+    Creates a migrator applying exactly the plan of an exported plan file.
+
+    The migrations are applied with the dialect of the plan, applying them on a database
+    of another driver fails with [MigrationError::DialectMismatch].
+    If the pending migrations of the database differ from the plan, e.g. because
+    a migration has been applied since the plan was exported, nothing is applied,
+    see [Migrator::expect_plan_hash].
+
+    `plan`: [&PlanFile]: The exported plan, see [Migrator::export_plan].
+    */
+    pub fn from_plan(plan: &PlanFile) -> Result<Self, MigrationError> {
+        let migrations = plan.migrations();
+        let ids = replaced_migrations(&migrations);
+        let (replaced, migrations) = migrations.into_iter().partition(|x| ids.contains(&x.id));
+        Ok(Self::new(migrations, replaced)?
+            .dialect_override(plan.dialect.into())
+            .expect_plan_hash(plan.plan_hash.as_str()))
+    }
+
+    /**
+    The migrations of the migrator in the order they are applied.
+    */
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /**
+    Only print the SQL of pending migrations instead of applying them.
+    */
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    /**
+    Generate the SQL for the given dialect instead of the configured driver.
+
+    Applying migrations with an override that does not match the configured driver is refused.
+    */
+    pub fn dialect_override(mut self, driver: DatabaseDriver) -> Self {
+        self.dialect_override = Some(driver);
+        self
+    }
+
+    /**
+    Apply all migrations without creating or using the last migration table.
+    */
+    pub fn no_bookkeeping(mut self) -> Self {
+        self.no_bookkeeping = true;
+        self
+    }
+
+    /**
+    Abort a migration if one of its statements takes longer than the given duration.
+
+    `duration`: [Duration]: Maximum time a single statement may take.
+    */
+    pub fn statement_timeout(mut self, duration: Duration) -> Self {
+        self.statement_timeout = Some(duration);
+        self
+    }
+
+    /**
+    Skip objects which already exist or have already been removed.
+
+    Allows re-running migrations, which have been applied partially or by hand.
+    Postgres uses IF [NOT] EXISTS where possible, other dialects
+    skip statements which fail because they have already been applied.
+    */
+    pub fn idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+
+    /**
+    Skip failed operations instead of aborting the migration.
+
+    Every skipped operation is logged and the migration is recorded as applied nonetheless.
+    This is only meant for debugging large migrations, e.g. squashes, on development databases.
+    */
+    pub fn continue_on_error(mut self) -> Self {
+        self.continue_on_error = true;
+        self
+    }
+
+    /**
+    Continue after the latest recorded migration which exists, if the last recorded one doesn't.
+
+    This only handles migrations which have been recorded, but whose files have been removed,
+    e.g. after a bad merge. Their changes are neither reverted nor applied again,
+    so a loud warning is printed. `drorm repair` corrects the last migration table instead.
+    */
+    pub fn ignore_missing_dependency(mut self) -> Self {
+        self.ignore_missing_dependency = true;
+        self
+    }
+
+    /**
+    Refuse to apply the migrations, unless the hash of the plan matches.
+
+    Guards against migrations which are added between approving a plan and applying it.
+
+    `hash`: [&str]: The plan hash of the approved plan, see [Plan::plan_hash].
+    */
+    pub fn expect_plan_hash(mut self, hash: &str) -> Self {
+        self.expected_plan_hash = Some(hash.to_string());
+        self
+    }
+
+    /**
+    Apply type changes of fields which may lose data.
+
+    Narrowing and incompatible changes, see [TypeChange], are refused otherwise.
+    Widening changes are always applied.
+    */
+    pub fn allow_unsafe(mut self) -> Self {
+        self.allow_unsafe = true;
+        self
+    }
+
+    /**
+    Continue with the remaining migrations if a migration fails.
+
+    Migrations depending on a failed migration are skipped, as are the ones depending on them,
+    e.g. the later migrations of its directory. Migrations of other directories
+    which don't depend on it are still applied, each in its own transaction.
+    All failures are reported at the end as [MigrationError::Failures].
+    An interrupt still stops the run immediately.
+
+    By default, the migrate run stops at the first failed migration.
+    */
+    pub fn keep_going(mut self) -> Self {
+        self.keep_going = true;
+        self
+    }
+
+    /**
+    Apply all pending migrations and their bookkeeping in a single transaction.
+
+    A failure rolls back all migrations of the run, not only the failed one.
+    This requires transactional DDL, so it is refused on MySQL, which commits DDL implicitly,
+    and for migrations which aren't atomic. It can't be combined with [Migrator::keep_going].
+
+    By default, every migration is applied in its own transaction.
+    */
+    pub fn single_transaction(mut self) -> Self {
+        self.single_transaction = true;
+        self
+    }
+
+    /**
+    Retry the transaction of a migration, if it is aborted by a concurrent transaction.
+
+    Postgres and MySQL abort transactions on serialization failures and deadlocks,
+    e.g. when migrating a live database. The aborted transaction is rolled back and
+    applied again up to `retries` times, waiting 100ms before the first retry and twice
+    as long before every further one. Other errors fail immediately.
+    With [Migrator::single_transaction], the whole transaction is retried.
+
+    By default, aborted transactions are not retried.
+
+    `retries`: [u32]: Maximum number of retries of a transaction.
+    */
+    pub fn retry(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /**
+    Print the SQL of a migration, if it fails, followed by the statement which failed.
+
+    Nothing is printed for migrations which are applied successfully, so the output stays
+    as quiet as without the SQL. With [Migrator::single_transaction], the SQL of the
+    migration the failed statement belongs to is printed.
+
+    By default, only the error is reported.
+    */
+    pub fn show_sql_on_error(mut self) -> Self {
+        self.show_sql_on_error = true;
+        self
+    }
+
+    /**
+    Refuse to apply the migrations, if the hash of a migration doesn't match.
+
+    The hash of every pending migration is recomputed from its operations, the hash of
+    every applied migration is compared with the hash recorded when applying it.
+    This catches migrations which are edited before and after they are applied.
+
+    By default, mismatched hashes are only reported as warnings.
+    */
+    pub fn strict_hash(mut self) -> Self {
+        self.strict_hash = true;
+        self
+    }
+
+    /**
+    Refresh the planner statistics of the tables changed by a migration, once it is committed.
+
+    Rebuilt tables and new indexes have no statistics until the database collects them
+    on its own, e.g. by autovacuum, so queries may be planned badly in the meantime.
+    The tables changed by the operations of the migration are analyzed outside its
+    transaction, raw SQL is not taken into account. A failure is reported as warning,
+    as the migration has been applied already.
+
+    By default, the statistics are left to the database.
+    */
+    pub fn analyze(mut self) -> Self {
+        self.analyze = true;
+        self
+    }
+
+    /**
+    Applies the pending migrations on the configured database.
+
+    `db_conf`: [&DatabaseConfig]: The database configuration to use.
+    */
+    pub async fn apply(&self, db_conf: &DatabaseConfig) -> Result<(), MigrationError> {
+        // The dialect of the connection, used for the bookkeeping statements
+        let conn_impl = db_conf.driver.db_impl();
+
+        // The dialect the migrations are converted to
+        let db_impl = match &self.dialect_override {
+            None => conn_impl,
+            Some(driver) => {
+                if !self.dry_run && driver.db_impl() != conn_impl {
+                    return Err(MigrationError::DialectMismatch {
+                        dialect_override: driver.clone(),
+                        driver: db_conf.driver.clone(),
+                    });
+                }
+                driver.db_impl()
+            }
+        };
+        if self.single_transaction && !self.dry_run {
+            if conn_impl == DBImpl::MySQL {
+                return Err(MigrationError::SingleTransaction {
+                    reason: "MySQL commits DDL statements implicitly".to_string(),
+                });
+            }
+            if self.keep_going {
+                return Err(MigrationError::SingleTransaction {
+                    reason: "failed migrations can't be skipped with keep going".to_string(),
+                });
+            }
+        }
+
+        let pool = connect(db_conf).await?;
+        let last_migration_table_name = db_conf.last_migration_table_name.as_str();
+        // The server only determines the statements of its own dialect
+        let server = match db_impl == conn_impl {
+            true => detect_server(conn_impl, &pool).await,
+            false => None,
+        };
+
+        // A dry run must not modify the database, so the table is only checked for
+        let applied = self
+            .applied(conn_impl, &pool, last_migration_table_name, !self.dry_run)
+            .await?;
+        let pending = self.pending(&applied, last_migration_table_name)?;
+        if let Some(expected) = &self.expected_plan_hash {
+            let actual = plan_hash(&pending);
+            if *expected != actual {
+                pool.close().await;
+                return Err(MigrationError::PlanHashMismatch {
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+        if let Err(err) = self
+            .check_hashes(conn_impl, &pool, last_migration_table_name, &pending)
+            .await
+        {
+            pool.close().await;
+            return Err(err);
+        }
+        if !self.dry_run && !self.allow_unsafe {
+            if let Err(err) = unsafe_type_changes(db_impl, &pending) {
+                pool.close().await;
+                return Err(err);
+            }
+        }
+        let options = BuildOptions {
+            idempotent: self.idempotent,
+            charset: db_conf.charset.clone(),
+            collation: db_conf.collation.clone(),
+            state: None,
+            server,
+        };
+        if !applied.is_empty() && pending.is_empty() {
+            info!(
+                "{}",
+                paint(Style::Success, "All migration have already been applied.")
+            );
+        }
+
+        if !pending.is_empty() {
+            info!(
+                "Apply order: {}",
+                pending
+                    .iter()
+                    .map(|x| x.id.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(" -> ")
+            );
+        }
+
+        if self.single_transaction && !self.dry_run {
+            let result = self
+                .apply_single_transaction(
+                    db_impl,
+                    &pending,
+                    &pool,
+                    last_migration_table_name,
+                    &options,
+                )
+                .await;
+            if result.is_err() && !pending.is_empty() {
+                info!(
+                    "{}",
+                    paint(
+                        Style::Warning,
+                        format!(
+                            "The transaction was rolled back, none of the {} pending migrations has been applied",
+                            pending.len()
+                        )
+                    )
+                );
+            }
+            pool.close().await;
+            return result;
+        }
+
+        // Ids of failed and skipped migrations, including the ones they replace
+        let mut blocked: HashSet<&str> = HashSet::new();
+        let mut failures = vec![];
+        let mut skipped = vec![];
+        for migration in pending {
+            if std::iter::once(&migration.dependency)
+                .chain(migration.merges.iter())
+                .chain(migration.external_dependencies.iter())
+                .any(|x| blocked.contains(x.as_str()))
+            {
+                info!(
+                    "{}",
+                    paint(
+                        Style::Warning,
+                        format!(
+                            "Skipping migration {} as it depends on a failed migration",
+                            migration.id
+                        )
+                    )
+                );
+                skipped.push(migration.id.clone());
+                blocked.insert(migration.id.as_str());
+                blocked.extend(migration.replaces.iter().map(String::as_str));
+                continue;
+            }
+
+            match self
+                .apply_one(
+                    db_impl,
+                    migration,
+                    &pool,
+                    last_migration_table_name,
+                    &options,
+                )
+                .await
+            {
+                Ok(()) => {}
+                Err(err @ MigrationError::Interrupted { .. }) => return Err(err),
+                Err(err) if self.keep_going => {
+                    info!(
+                        "{}",
+                        paint(
+                            Style::Error,
+                            format!("Migration {} failed: {}", migration.id, err)
+                        )
+                    );
+                    failures.push((migration.id.clone(), err));
+                    blocked.insert(migration.id.as_str());
+                    blocked.extend(migration.replaces.iter().map(String::as_str));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        if !failures.is_empty() {
+            return Err(MigrationError::Failures { failures, skipped });
+        }
+        Ok(())
+    }
+
+    /**
+    Helper method to apply or, in a dry run, print a single pending migration.
+
+    `db_impl`: [DBImpl]: The dialect the migration is converted to.
+    `migration`: [&Migration]: The migration to apply.
+    `pool`: [&AnyPool]: The connection pool.
+    `last_migration_table_name`: [&str]: Name of the last migration table.
+    `options`: [&BuildOptions]: The options of the generated SQL.
+    */
+    async fn apply_one(
+        &self,
+        db_impl: DBImpl,
+        migration: &Migration,
+        pool: &AnyPool,
+        last_migration_table_name: &str,
+        options: &BuildOptions,
+    ) -> Result<(), MigrationError> {
+        let options = BuildOptions {
+            state: table_state(db_impl, &self.preceding(migration), migration)?,
+            ..options.clone()
+        };
+        if self.dry_run {
+            println!("-- {}", migration.id.as_str());
+            if let Some(precondition) = &migration.precondition {
+                println!("-- precondition: {}", precondition.sql);
+            }
+            println!(
+                "{}",
+                redact_sensitive_defaults(
+                    migration,
+                    migration_to_sql(db_impl, migration, &options)?.as_str()
+                )
+            );
+            if self.analyze {
+                for statement in analyze_statements(db_impl, &affected_tables(db_impl, migration)) {
+                    println!("{}", statement);
+                }
+            }
+        } else {
+            // Dropping the apply future on interrupt drops its transaction,
+            // so neither the migration nor its bookkeeping is committed
+            tokio::select! {
+                result = apply_migration(
+                    db_impl,
+                    migration,
+                    pool,
+                    (!self.no_bookkeeping).then_some(last_migration_table_name),
+                    self.statement_timeout,
+                    &options,
+                    self.continue_on_error,
+                    self.retries,
+                ) => {
+                    if let Err(err) = &result {
+                        if self.show_sql_on_error {
+                            print_failed_sql(
+                                migration,
+                                &operation_statements(db_impl, migration, &options)?,
+                                err,
+                            );
+                        }
+                    }
+                    result?
+                },
+                _ = signal::ctrl_c() => {
+                    return Err(MigrationError::Interrupted {
+                        migration: migration.id.clone(),
+                        atomic: migration.atomic,
+                    });
+                }
+            }
+            if self.analyze {
+                analyze_tables(db_impl, pool, &affected_tables(db_impl, migration)).await;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+    Helper method to apply the pending migrations in a single transaction.
+
+    `db_impl`: [DBImpl]: The dialect the migrations are converted to.
+    `pending`: [&\[&Migration\]]: The migrations to apply.
+    `pool`: [&AnyPool]: The connection pool.
+    `last_migration_table_name`: [&str]: Name of the last migration table.
+    `options`: [&BuildOptions]: The options of the generated SQL.
+    */
+    async fn apply_single_transaction(
+        &self,
+        db_impl: DBImpl,
+        pending: &[&Migration],
+        pool: &AnyPool,
+        last_migration_table_name: &str,
+        options: &BuildOptions,
+    ) -> Result<(), MigrationError> {
+        if let Some(migration) = pending.iter().find(|x| !x.atomic) {
+            return Err(MigrationError::SingleTransaction {
+                reason: format!("migration {} is not atomic", migration.id),
+            });
+        }
+        let first = match pending.first() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+
+        let mut statements = vec![];
+        for migration in pending {
+            let options = BuildOptions {
+                state: table_state(db_impl, &self.preceding(migration), migration)?,
+                ..options.clone()
+            };
+            statements.push(operation_statements(db_impl, migration, &options)?);
+        }
+
+        // A retry starts a new transaction, which applies all migrations again
+        let result = apply_transaction(db_impl, first, self.retries, || {
+            self.apply_pending_in_transaction(
+                db_impl,
+                pending,
+                &statements,
+                pool,
+                last_migration_table_name,
+            )
+        })
+        .await;
+        let outcomes = match result {
+            Ok(outcomes) => outcomes,
+            Err(err) => {
+                if self.show_sql_on_error {
+                    if let MigrationError::Sql { migration, .. }
+                    | MigrationError::LockTimeout { migration, .. } = &err
+                    {
+                        if let Some(index) = pending.iter().position(|x| x.id == *migration) {
+                            print_failed_sql(pending[index], &statements[index], &err);
+                        }
+                    }
+                }
+                return Err(err);
+            }
+        };
+
+        let mut tables: Vec<String> = vec![];
+        for ((migration, operations), outcome) in pending.iter().zip(&statements).zip(outcomes) {
+            if let Outcome::Applied(skipped) = outcome {
+                report_applied(migration, skipped, operations.len());
+                for table in affected_tables(db_impl, migration) {
+                    if !tables.contains(&table) {
+                        tables.push(table);
+                    }
+                }
+            }
+        }
+        if self.analyze {
+            analyze_tables(db_impl, pool, &tables).await;
+        }
+        Ok(())
+    }
+
+    /**
+    Helper method to apply the pending migrations in one transaction, which is committed.
+
+    The outcomes of the migrations are returned in the order of `pending`.
+
+    `db_impl`: [DBImpl]: The dialect the migrations are converted to.
+    `pending`: [&\[&Migration\]]: The migrations to apply, must not be empty.
+    `statements`: [&\[Vec<(&Operation, Vec<String>)>\]]: The statements of the operations of every migration.
+    `pool`: [&AnyPool]: The connection pool.
+    `last_migration_table_name`: [&str]: Name of the last migration table.
+    */
+    async fn apply_pending_in_transaction(
+        &self,
+        db_impl: DBImpl,
+        pending: &[&Migration],
+        statements: &[Vec<(&Operation, Vec<String>)>],
+        pool: &AnyPool,
+        last_migration_table_name: &str,
+    ) -> Result<Vec<Outcome>, MigrationError> {
+        // The foreign keys are disabled for the whole transaction, if any table is rebuilt
+        let rebuilds =
+            db_impl == DBImpl::SQLite && pending.iter().any(|x| !rebuilt_tables(x).is_empty());
+        let mut rebuild_conn = None;
+        let mut tx = if rebuilds {
+            rebuild_conn
+                .insert(rebuild_connection(pool, pending[0]).await?)
+                .begin()
+                .await
+                .map_err(MigrationError::Connection)?
+        } else {
+            pool.begin().await.map_err(MigrationError::Connection)?
+        };
+
+        let mut outcomes = vec![];
+        for (migration, operations) in pending.iter().zip(statements) {
+            // Dropping the transaction on interrupt rolls back all migrations
+            let outcome = tokio::select! {
+                result = apply_in_transaction(
+                    &mut tx,
+                    db_impl,
+                    migration,
+                    operations,
+                    (!self.no_bookkeeping).then_some(last_migration_table_name),
+                    self.statement_timeout,
+                    self.idempotent,
+                    self.continue_on_error,
+                    rebuilds,
+                ) => result?,
+                _ = signal::ctrl_c() => {
+                    return Err(MigrationError::Interrupted {
+                        migration: migration.id.clone(),
+                        atomic: true,
+                    });
+                }
+            };
+            outcomes.push(outcome);
+        }
+
+        tx.commit().await.map_err(|source| MigrationError::Sql {
+            migration: pending[pending.len() - 1].id.clone(),
+            operation: None,
+            statement: "COMMIT".to_string(),
+            source,
+        })?;
+        if let Some(conn) = rebuild_conn {
+            let _ = conn.close().await;
+        }
+
+        Ok(outcomes)
+    }
+
+    /**
+    Resolves the pending migrations without applying them.
+
+    The database is not modified, the last migration table is only read if it exists.
+
+    `db_conf`: [&DatabaseConfig]: The database configuration to use.
+    */
+    pub async fn plan(&self, db_conf: &DatabaseConfig) -> Result<Plan, MigrationError> {
+        let conn_impl = db_conf.driver.db_impl();
+        let db_impl = self
+            .dialect_override
+            .as_ref()
+            .map_or(conn_impl, |x| x.db_impl());
+
+        let pool = connect(db_conf).await?;
+        let last_migration_table_name = db_conf.last_migration_table_name.as_str();
+        let applied = self
+            .applied(conn_impl, &pool, last_migration_table_name, false)
+            .await?;
+        let pending = self.pending(&applied, last_migration_table_name)?;
+        pool.close().await;
+
+        Ok(Plan {
+            version: PLAN_VERSION,
+            dialect: db_impl.into(),
+            last_applied: applied.last().cloned(),
+            plan_hash: plan_hash(&pending),
+            migrations: pending
+                .into_iter()
+                .map(|x| PlannedMigration::new(x, db_impl.into()))
+                .collect(),
+        })
+    }
+
+    /**
+    Resolves which migrations have been applied, without modifying the database.
+
+    Unlike [Migrator::plan], recorded migrations which don't exist anymore are
+    listed instead of being refused.
+
+    `db_conf`: [&DatabaseConfig]: The database configuration to use.
+    */
+    pub async fn status(&self, db_conf: &DatabaseConfig) -> Result<Status, MigrationError> {
+        let conn_impl = db_conf.driver.db_impl();
+        let pool = connect(db_conf).await?;
+        let applied = self
+            .applied(
+                conn_impl,
+                &pool,
+                db_conf.last_migration_table_name.as_str(),
+                false,
+            )
+            .await?;
+        pool.close().await;
+
+        let recorded: HashSet<&String> = applied.iter().collect();
+        Ok(Status {
+            version: STATUS_VERSION,
+            dialect: conn_impl.into(),
+            last_applied: applied.last().cloned(),
+            migrations: self
+                .migrations
+                .iter()
+                .map(|x| MigrationStatus {
+                    id: x.id.clone(),
+                    applied: recorded.contains(&x.id)
+                        || (!x.replaces.is_empty()
+                            && x.replaces.iter().all(|y| recorded.contains(y))),
+                })
+                .collect(),
+            hidden: 0,
+            unknown: applied
+                .iter()
+                .filter(|id| {
+                    !self
+                        .migrations
+                        .iter()
+                        .chain(&self.replaced)
+                        .any(|x| x.id == **id || x.replaces.contains(id))
+                })
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /**
+    Resolves the pending migrations for an export, which is applied later with [Migrator::from_plan].
+
+    The database is not modified, the last migration table is only read if it exists.
+
+    `db_conf`: [&DatabaseConfig]: The database configuration to use.
+    */
+    pub async fn export_plan(&self, db_conf: &DatabaseConfig) -> Result<PlanFile, MigrationError> {
+        let plan = self.plan(db_conf).await?;
+        Ok(PlanFile {
+            dialect: plan.dialect,
+            last_applied: plan.last_applied,
+            migrations: self
+                .replaced
+                .iter()
+                .chain(&self.migrations)
+                .map(|x| ExportedMigration {
+                    id: x.id.clone(),
+                    migration: x.clone(),
+                })
+                .collect(),
+            pending: plan.migrations.into_iter().map(|x| x.id).collect(),
+            plan_hash: plan.plan_hash,
+        })
+    }
+
+    /**
+    Prints the SQL reverting the most recently applied migrations without executing it.
+
+    The migrations are resolved from the last migration table, which is only read.
+    Migrations containing operations which can't be reversed are reported as irreversible
+    with the first of these operations.
+
+    `db_conf`: [&DatabaseConfig]: The database configuration to use.
+    `count`: [usize]: Number of migrations to revert.
+    */
+    pub async fn preview_rollback(
+        &self,
+        db_conf: &DatabaseConfig,
+        count: usize,
+    ) -> Result<(), MigrationError> {
+        let conn_impl = db_conf.driver.db_impl();
+        let db_impl = self
+            .dialect_override
+            .as_ref()
+            .map_or(conn_impl, |x| x.db_impl());
+
+        let pool = connect(db_conf).await?;
+        let last_migration_table_name = db_conf.last_migration_table_name.as_str();
+        let server = match db_impl == conn_impl {
+            true => detect_server(conn_impl, &pool).await,
+            false => None,
+        };
+        let applied = self
+            .applied(conn_impl, &pool, last_migration_table_name, false)
+            .await?;
+        pool.close().await;
+        let targets =
+            rollback_targets(&self.migrations, &applied, count, last_migration_table_name)?;
+        let options = BuildOptions {
+            idempotent: self.idempotent,
+            charset: db_conf.charset.clone(),
+            collation: db_conf.collation.clone(),
+            state: None,
+            server,
+        };
+
+        if targets.is_empty() {
+            info!("No migration has been applied, there is nothing to roll back.");
+        } else {
+            info!(
+                "Rollback order: {}",
+                targets
+                    .iter()
+                    .map(|x| x.id.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(" -> ")
+            );
+        }
+
+        for migration in targets {
+            let index = self
+                .migrations
+                .iter()
+                .position(|x| x.id == migration.id)
+                .unwrap_or_default();
+            let down = down_operations(&self.migrations[..index], migration).map_err(|err| {
+                MigrationError::Build {
+                    migration: migration.id.clone(),
+                    source: err.into(),
+                }
+            })?;
+
+            println!("-- {}", migration.id.as_str());
+            match down {
+                Ok(operations) if operations.is_empty() => {
+                    println!("-- no operations to reverse")
+                }
+                Ok(operations) => {
+                    let down = Migration {
+                        operations,
+                        ..migration.clone()
+                    };
+                    let options = BuildOptions {
+                        state: table_state(db_impl, &self.migrations[..=index], &down)?,
+                        ..options.clone()
+                    };
+                    println!(
+                        "{}",
+                        redact_sensitive_defaults(
+                            &down,
+                            migration_to_sql(db_impl, &down, &options)?.as_str()
+                        )
+                    );
+                }
+                Err(operation) => {
+                    println!("-- irreversible: {}", operation_to_diff_line(&operation))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+    Fetches the applied migrations from the last migration table, oldest first.
+
+    Without bookkeeping, no migration is applied.
+
+    `conn_impl`: [DBImpl]: The dialect of the connection.
+    `pool`: [&AnyPool]: The connection pool.
+    `table`: [&str]: Name of the last migration table.
+    `create`: [bool]: Create the last migration table if it doesn't exist.
+    */
+    async fn applied(
+        &self,
+        conn_impl: DBImpl,
+        pool: &AnyPool,
+        table: &str,
+        create: bool,
+    ) -> Result<Vec<String>, MigrationError> {
+        let bookkeeping_error = |err: anyhow::Error| MigrationError::Bookkeeping {
+            table: table.to_string(),
+            source: err.into(),
+        };
+        let table_exists = if self.no_bookkeeping {
+            false
+        } else if create {
+            create_bookkeeping_table(conn_impl, pool, table)
+                .await
+                .map_err(bookkeeping_error)?;
+            true
+        } else {
+            bookkeeping_table_exists(conn_impl, pool, table)
+                .await
+                .map_err(bookkeeping_error)?
+        };
+        if !table_exists {
+            return Ok(vec![]);
+        }
+
+        query(format!("SELECT migration_name FROM {} ORDER BY id ASC;", table).as_str())
+            .map(|x: AnyRow| x.get(0))
+            .fetch_all(pool)
+            .await
+            .with_context(|| {
+                "Couldn't fetch information about successful migrations from migration table"
+            })
+            .map_err(bookkeeping_error)
+    }
+
+    /**
+    Compares the hashes of the migrations, see [hash_mismatches].
+
+    Mismatches are refused with [Migrator::strict_hash] and reported as warnings otherwise.
+
+    `conn_impl`: [DBImpl]: The dialect of the connection.
+    `pool`: [&AnyPool]: The connection pool.
+    `table`: [&str]: Name of the last migration table.
+    `pending`: [&\[&Migration\]]: The pending migrations.
+    */
+    async fn check_hashes(
+        &self,
+        conn_impl: DBImpl,
+        pool: &AnyPool,
+        table: &str,
+        pending: &[&Migration],
+    ) -> Result<(), MigrationError> {
+        let recorded = match self.no_bookkeeping {
+            true => vec![],
+            false => recorded_hashes(conn_impl, pool, table)
+                .await
+                .map_err(|err| MigrationError::Bookkeeping {
+                    table: table.to_string(),
+                    source: err.into(),
+                })?,
+        };
+        let mismatches = hash_mismatches(&self.migrations, pending, &recorded);
+        if self.strict_hash && !mismatches.is_empty() {
+            return Err(MigrationError::HashMismatch { mismatches });
+        }
+        for mismatch in mismatches {
+            info!(
+                "{}",
+                paint(Style::Warning, format!("Warning: {}", mismatch))
+            );
+        }
+        Ok(())
+    }
+
+    /**
+    The migrations preceding a migration, whose state it is applied on.
+
+    A replaced migration, which is applied instead of its squash, is preceded by
+    the migrations before the squash and the replaced migrations before it.
+
+    `migration`: [&Migration]: The migration to apply.
+    */
+    fn preceding(&self, migration: &Migration) -> Cow<'_, [Migration]> {
+        if let Some(index) = self.migrations.iter().position(|x| x.id == migration.id) {
+            return Cow::Borrowed(&self.migrations[..index]);
+        }
+
+        let squash = match self
+            .migrations
+            .iter()
+            .position(|x| x.replaces.contains(&migration.id))
+        {
+            Some(squash) => squash,
+            None => return Cow::Borrowed(&[]),
+        };
+        let replaces = &self.migrations[squash].replaces;
+        let index = replaces
+            .iter()
+            .position(|x| *x == migration.id)
+            .unwrap_or_default();
+        Cow::Owned(
+            self.migrations[..squash]
+                .iter()
+                .chain(
+                    replaces[..index]
+                        .iter()
+                        .filter_map(|id| self.replaced.iter().find(|x| x.id == *id)),
+                )
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /**
+    Resolves the pending migrations in the order they are applied in.
+
+    A migration replacing other migrations, e.g. a squash, counts as applied
+    if all of the migrations it replaces have been applied.
+    If only some of them have been applied, the remaining ones are applied instead
+    of the squash, given their files have been kept.
+
+    `applied`: [&\[String\]]: The applied migrations, oldest first.
+    `table`: [&str]: Name of the last migration table, used for errors.
+    */
+    fn pending(&self, applied: &[String], table: &str) -> Result<Vec<&Migration>, MigrationError> {
+        let exists = |id: &String| {
+            self.migrations
+                .iter()
+                .any(|x| x.id == *id || x.replaces.contains(id))
+        };
+        if let Some(id) = applied.last() {
+            // If last applied migration could not be found in existing migrations,
+            // abort as there's no way to determine what to do next
+            if !exists(id) {
+                let error = MigrationError::UnknownAppliedMigration {
+                    migration: id.clone(),
+                    table: table.to_string(),
+                };
+                if !self.ignore_missing_dependency {
+                    return Err(error);
+                }
+
+                // Without any existing recorded migration, everything would be applied again
+                let ancestor = applied.iter().rposition(exists).ok_or(error)?;
+                let missing: Vec<&str> = applied[ancestor + 1..]
+                    .iter()
+                    .filter(|x| !exists(x))
+                    .map(String::as_str)
+                    .collect();
+                info!(
+                    "Warning: The recorded migrations {} don't exist anymore, continuing after {}, the latest recorded migration which exists. \
+                    It is assumed that their changes don't conflict with the pending migrations, they are neither reverted nor applied again. \
+                    If this assumption is wrong, the pending migrations may fail or leave the database in an inconsistent state. \
+                    Run `drorm repair` to correct the {} table.",
+                    missing.join(", "),
+                    applied[ancestor],
+                    table
+                );
+            }
+        }
+
+        // Migrations of multiple directories are merged, so a new migration
+        // of one directory may be ordered before already applied ones
+        let applied: HashSet<&String> = applied.iter().collect();
+        let mut pending = vec![];
+        for migration in &self.migrations {
+            if applied.contains(&migration.id) {
+                continue;
+            }
+
+            let missing: Vec<String> = migration
+                .replaces
+                .iter()
+                .filter(|x| !applied.contains(x))
+                .cloned()
+                .collect();
+            if migration.replaces.is_empty() || missing.len() == migration.replaces.len() {
+                pending.push(migration);
+            } else if !missing.is_empty() {
+                let kept: Vec<&Migration> = missing
+                    .iter()
+                    .filter_map(|id| self.replaced.iter().find(|x| x.id == *id))
+                    .collect();
+                if kept.len() == missing.len() {
+                    pending.extend(kept);
+                    continue;
+                }
+
+                return Err(MigrationError::PartiallyReplaced {
+                    migration: migration.id.clone(),
+                    missing,
+                });
+            }
+        }
+        Ok(pending)
+    }
+}
+
+/**
+Helper function to convert an error while reading migrations.
+
+Migrations using an operation which is unknown to this version keep their own error
+to be distinguishable from malformed files.
+
+`location`: [&str]: The location the migrations were read from.
+`err`: [anyhow::Error]: The error while reading the migrations.
+*/
+fn parse_error(location: &str, err: anyhow::Error) -> MigrationError {
+    match err.downcast::<MigrationError>() {
+        Ok(err) => err,
+        Err(err) => MigrationError::Parse {
+            location: location.to_string(),
+            source: err.into(),
+        },
+    }
+}
+
+/**
+Derives the state of the tables before a migration, if it rebuilds tables on SQLite.
+
+`db_impl`: [DBImpl]: The dialect the migration is applied on.
+`previous`: [&\[Migration\]]: The migrations applied before the migration.
+`migration`: [&Migration]: The migration to apply.
+*/
+fn table_state(
+    db_impl: DBImpl,
+    previous: &[Migration],
+    migration: &Migration,
+) -> Result<Option<TableState>, MigrationError> {
+    if db_impl != DBImpl::SQLite || rebuilt_tables(migration).is_empty() {
+        return Ok(None);
+    }
+    TableState::from_migrations(previous)
+        .map(Some)
+        .map_err(|err| MigrationError::Build {
+            migration: migration.id.clone(),
+            source: err.into(),
+        })
+}
+
+/**
+Checks the pending migrations for type changes of fields, which may lose data.
+
+`db_impl`: [DBImpl]: The dialect the migrations are applied on.
+`pending`: [&\[&Migration\]]: The pending migrations.
+*/
+pub(crate) fn unsafe_type_changes(
+    db_impl: DBImpl,
+    pending: &[&Migration],
+) -> Result<(), MigrationError> {
+    for migration in pending {
+        for operation in &migration.operations {
+            if let Some(Operation::AlterField {
+                model,
+                field,
+                change,
+            }) = operation.for_dialect(db_impl.into())
+            {
+                if change.is_unsafe() {
+                    return Err(MigrationError::UnsafeTypeChange {
+                        migration: migration.id.clone(),
+                        model: model.clone(),
+                        field: field.name.clone(),
+                        change: *change,
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+Helper method to refresh the planner statistics of tables, see [Migrator::analyze].
+
+Failures are printed as warnings, the remaining tables are analyzed anyway.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`pool`: [&AnyPool]: The connection pool.
+`tables`: [&\[String\]]: The tables to analyze.
+*/
+async fn analyze_tables(db_impl: DBImpl, pool: &AnyPool, tables: &[String]) {
+    for (table, statement) in tables.iter().zip(analyze_statements(db_impl, tables)) {
+        if let Err(err) = query(statement.as_str()).execute(pool).await {
+            info!(
+                "{}",
+                paint(
+                    Style::Warning,
+                    format!("Couldn't refresh the statistics of {}: {}", table, err)
+                )
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{Annotation, DbType, Dialect, Field};
+    use rorm_sql::DBImpl;
+    use sqlx::any::AnyRow;
+    use sqlx::{query, Executor, Row};
+
+    use crate::declaration::{Migration, Operation, TypeChange};
+    use crate::migrate::config::{DatabaseConfig, DatabaseDriver};
+    use crate::migrate::error::MigrationError;
+    use crate::migrate::migrator::{unsafe_type_changes, Migrator};
+    use crate::migrate::plan::PlanFile;
+    use crate::migrate::sql_builder::BuildOptions;
+    use crate::migrate::{apply_migration, connect};
+
+    const INITIAL: &str = r#"[Migration]
+Hash = ""
+Initial = true
+Dependency = ""
+Replaces = []
+Operations = []
+"#;
+
+    const SECOND: &str = r#"[Migration]
+Hash = ""
+Initial = false
+Dependency = "0001_initial"
+Replaces = []
+Operations = []
+"#;
+
+    #[test]
+    fn from_embedded() {
+        let migrator = Migrator::from_embedded(&[
+            ("0002_second.toml", SECOND),
+            ("0001_initial.toml", INITIAL),
+        ])
+        .unwrap();
+
+        let ids: Vec<&str> = migrator
+            .migrations()
+            .iter()
+            .map(|x| x.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["0001_initial", "0002_second"]);
+    }
+
+    #[test]
+    fn ignore_missing_dependency() {
+        let migrator = Migrator::from_embedded(&[
+            ("0001_initial.toml", INITIAL),
+            ("0002_second.toml", SECOND),
+        ])
+        .unwrap();
+        let applied = |x: &[&str]| x.iter().map(|y| y.to_string()).collect::<Vec<String>>();
+        let pending = |migrator: &Migrator, x: &[&str]| {
+            migrator
+                .pending(&applied(x), "_drorm__last_migration")
+                .map(|y| y.iter().map(|z| z.id.clone()).collect::<Vec<String>>())
+        };
+
+        assert!(matches!(
+            pending(&migrator, &["0001_initial", "0002_gone"]),
+            Err(MigrationError::UnknownAppliedMigration { migration, .. }) if migration == "0002_gone"
+        ));
+
+        let migrator = migrator.ignore_missing_dependency();
+        // Pending migrations are still applied
+        assert_eq!(
+            pending(&migrator, &["0001_initial", "0002_gone"]).unwrap(),
+            vec!["0002_second"]
+        );
+        assert!(
+            pending(&migrator, &["0001_initial", "0002_second", "0003_gone"])
+                .unwrap()
+                .is_empty()
+        );
+        // Without an existing recorded migration everything would be applied again
+        assert!(matches!(
+            pending(&migrator, &["0001_gone"]),
+            Err(MigrationError::UnknownAppliedMigration { .. })
+        ));
+    }
+
+    #[test]
+    fn from_embedded_error_names_file() {
+        let err = Migrator::from_embedded(&[("0001_initial.toml", "[Migration]")])
+            .err()
+            .unwrap();
+        assert!(matches!(err, MigrationError::Parse { .. }));
+        assert!(format!("{:#}", anyhow::Error::from(err)).contains("0001_initial.toml"));
+
+        let err = Migrator::from_embedded(&[("initial.toml", INITIAL)])
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", anyhow::Error::from(err)).contains("initial.toml"));
+    }
+
+    #[test]
+    fn from_embedded_unsupported_operation() {
+        let err = Migrator::from_embedded(&[(
+            "0001_initial.toml",
+            r#"[Migration]
+Hash = ""
+Initial = true
+Dependency = ""
+Replaces = []
+
+[[Migration.Operations]]
+Type = "CreateMaterializedView"
+Model = "user"
+"#,
+        )])
+        .err()
+        .unwrap();
+        assert!(matches!(
+            &err,
+            MigrationError::UnsupportedOperation { migration, operation }
+                if migration == "0001_initial" && operation == "CreateMaterializedView"
+        ));
+    }
+
+    #[test]
+    fn from_embedded_error_kinds() {
+        let err = Migrator::from_embedded(&[("0002_second.toml", SECOND)])
+            .err()
+            .unwrap();
+        assert!(matches!(err, MigrationError::DependencyChain(_)));
+    }
+
+    const CREATE_MODELS: &str = r#"[Migration]
+Hash = ""
+Initial = true
+Dependency = ""
+Replaces = []
+
+[[Migration.Operations]]
+Type = "CreateModel"
+Name = "bar"
+
+[[Migration.Operations.Fields]]
+Name = "id"
+Type = "int64"
+Annotations = [{ Type = "primary_key" }]
+
+[[Migration.Operations]]
+Type = "CreateModel"
+Name = "foo"
+
+[[Migration.Operations.Fields]]
+Name = "id"
+Type = "int64"
+Annotations = [{ Type = "primary_key" }]
+"#;
+
+    #[tokio::test]
+    async fn analyze_after_commit() {
+        for single_transaction in [false, true] {
+            let path = std::env::temp_dir().join(format!(
+                "drorm_analyze_{}_{}.sqlite3",
+                single_transaction,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_file(&path);
+            let db_conf = DatabaseConfig {
+                driver: DatabaseDriver::SQLite,
+                name: path.to_str().unwrap().to_string(),
+                host: "".to_string(),
+                port: 0,
+                user: "".to_string(),
+                password: "".to_string(),
+                last_migration_table_name: "_drorm__last_migration".to_string(),
+                charset: None,
+                collation: None,
+                pre_migrate_command: None,
+                post_migrate_command: None,
+                analyze: false,
+            };
+
+            let mut migrator = Migrator::from_embedded(&[("0001_initial.toml", CREATE_MODELS)])
+                .unwrap()
+                .analyze();
+            if single_transaction {
+                migrator = migrator.single_transaction();
+            }
+            migrator.apply(&db_conf).await.unwrap();
+
+            // SQLite keeps the statistics in sqlite_stat1, which ANALYZE creates
+            let pool = connect(&db_conf).await.unwrap();
+            let tables: i64 =
+                query("SELECT COUNT(*) FROM sqlite_master WHERE name = 'sqlite_stat1';")
+                    .map(|x: AnyRow| x.get(0))
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+            assert_eq!(tables, 1);
+            pool.close().await;
+
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_migration_is_rolled_back() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_rollback_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        let pool = connect(&db_conf).await.unwrap();
+        pool.execute("CREATE TABLE foo (id INTEGER);")
+            .await
+            .unwrap();
+
+        // Printing the SQL of the failed migration doesn't change the error
+        let migrator = Migrator::from_embedded(&[("0001_initial.toml", CREATE_MODELS)])
+            .unwrap()
+            .show_sql_on_error();
+        assert!(matches!(
+            migrator.apply(&db_conf).await,
+            Err(MigrationError::Sql { migration, operation, .. })
+                if migration == "0001_initial" && operation.as_deref() == Some("+ model foo")
+        ));
+
+        let tables: i64 = query("SELECT COUNT(*) FROM sqlite_master WHERE name = 'bar';")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(tables, 0);
+        let applied: i64 = query("SELECT COUNT(*) FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, 0);
+
+        pool.execute("DROP TABLE foo;").await.unwrap();
+        migrator.apply(&db_conf).await.unwrap();
+        let applied: i64 = query("SELECT COUNT(*) FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, 1);
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    const DELETE_FIELD: &str = r#"[Migration]
+Hash = ""
+Initial = false
+Dependency = "0002_second"
+Replaces = []
+
+[[Migration.Operations]]
+Type = "DeleteField"
+Model = "foo"
+Name = "age"
+
+[[Migration.Operations]]
+Type = "DeleteModel"
+Name = "bar"
+"#;
+
+    #[tokio::test]
+    async fn idempotent_delete_of_absent_column() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_absent_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        Migrator::from_embedded(&[
+            ("0001_initial.toml", CREATE_MODELS),
+            ("0002_second.toml", CREATE_FIELD),
+        ])
+        .unwrap()
+        .apply(&db_conf)
+        .await
+        .unwrap();
+
+        // The destructive changes have already been applied by hand
+        let pool = connect(&db_conf).await.unwrap();
+        pool.execute("ALTER TABLE foo DROP COLUMN age; DROP TABLE bar;")
+            .await
+            .unwrap();
+
+        Migrator::from_embedded(&[
+            ("0001_initial.toml", CREATE_MODELS),
+            ("0002_second.toml", CREATE_FIELD),
+            ("0003_delete.toml", DELETE_FIELD),
+        ])
+        .unwrap()
+        .idempotent()
+        .apply(&db_conf)
+        .await
+        .unwrap();
+        let applied: i64 = query("SELECT COUNT(*) FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, 3);
+        query("SELECT id FROM foo;").execute(&pool).await.unwrap();
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    const FAILING: &str = r#"[Migration]
+Hash = ""
+Initial = false
+Dependency = "0002_second"
+Replaces = []
+
+[[Migration.Operations]]
+Type = "RunSql"
+Sql = "INSERT INTO missing VALUES (1);"
+"#;
+
+    #[tokio::test]
+    async fn single_transaction_rolls_back_all() {
+        let dir = std::env::temp_dir().join(format!("drorm_single_tx_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_conf = |name: &str| DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: dir.join(name).to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+        let migrator = || {
+            Migrator::from_embedded(&[
+                ("0001_initial.toml", CREATE_MODELS),
+                ("0002_second.toml", CREATE_FIELD),
+                ("0003_failing.toml", FAILING),
+            ])
+            .unwrap()
+        };
+        let state = |db_conf: DatabaseConfig| async move {
+            let pool = connect(&db_conf).await.unwrap();
+            let tables: i64 = query("SELECT COUNT(*) FROM sqlite_master WHERE name = 'foo';")
+                .map(|x: AnyRow| x.get(0))
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            let applied: i64 = query("SELECT COUNT(*) FROM _drorm__last_migration;")
+                .map(|x: AnyRow| x.get(0))
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            pool.close().await;
+            (tables, applied)
+        };
+
+        // Only the failed migration is rolled back
+        let per_migration = db_conf("per_migration.sqlite3");
+        assert!(matches!(
+            migrator().apply(&per_migration).await,
+            Err(MigrationError::Sql { migration, .. }) if migration == "0003_failing"
+        ));
+        assert_eq!(state(per_migration).await, (1, 2));
+
+        // All migrations are rolled back
+        let single = db_conf("single.sqlite3");
+        assert!(matches!(
+            migrator().single_transaction().apply(&single).await,
+            Err(MigrationError::Sql { migration, .. }) if migration == "0003_failing"
+        ));
+        assert_eq!(state(single.clone()).await, (0, 0));
+
+        let migrator = Migrator::from_embedded(&[
+            ("0001_initial.toml", CREATE_MODELS),
+            ("0002_second.toml", CREATE_FIELD),
+        ])
+        .unwrap();
+        migrator.single_transaction().apply(&single).await.unwrap();
+        assert_eq!(state(single).await, (1, 2));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn single_transaction_refused_on_mysql() {
+        let db_conf = DatabaseConfig::from_url("mysql://root@localhost:1/app").unwrap();
+        let err = Migrator::from_embedded(&[("0001_initial.toml", INITIAL)])
+            .unwrap()
+            .single_transaction()
+            .apply(&db_conf)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, MigrationError::SingleTransaction { .. }));
+    }
+
+    const PRECONDITION: &str = r#"[Migration]
+Hash = ""
+Initial = true
+Dependency = ""
+Replaces = []
+
+[Migration.Precondition]
+Sql = "SELECT COUNT(*) > 0 FROM flag"
+
+[[Migration.Operations]]
+Type = "CreateModel"
+Name = "bar"
+
+[[Migration.Operations.Fields]]
+Name = "id"
+Type = "int64"
+Annotations = [{ Type = "primary_key" }]
+"#;
+
+    #[tokio::test]
+    async fn precondition_gates_migration() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_precondition_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        let pool = connect(&db_conf).await.unwrap();
+        pool.execute("CREATE TABLE flag (id INTEGER);")
+            .await
+            .unwrap();
+        let count = |sql: &'static str| {
+            let pool = pool.clone();
+            async move {
+                query(sql)
+                    .map(|x: AnyRow| x.get::<i64, _>(0))
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let migrator = Migrator::from_embedded(&[("0001_initial.toml", PRECONDITION)]).unwrap();
+        assert!(matches!(
+            migrator.apply(&db_conf).await,
+            Err(MigrationError::PreconditionFailed { migration, .. }) if migration == "0001_initial"
+        ));
+        assert_eq!(
+            count("SELECT COUNT(*) FROM _drorm__last_migration;").await,
+            0
+        );
+
+        let skipping = PRECONDITION.replace("FROM flag\"", "FROM flag\"\nOnFailure = \"skip\"");
+        let migrator = Migrator::from_embedded(&[("0001_initial.toml", &skipping)]).unwrap();
+        migrator.apply(&db_conf).await.unwrap();
+        assert_eq!(
+            count("SELECT COUNT(*) FROM _drorm__last_migration WHERE skipped;").await,
+            1
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM sqlite_master WHERE name = 'bar';").await,
+            0
+        );
+
+        pool.execute("DELETE FROM _drorm__last_migration; INSERT INTO flag VALUES (1);")
+            .await
+            .unwrap();
+        migrator.apply(&db_conf).await.unwrap();
+        assert_eq!(
+            count("SELECT COUNT(*) FROM _drorm__last_migration WHERE NOT skipped;").await,
+            1
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM sqlite_master WHERE name = 'bar';").await,
+            1
+        );
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    const LOCKED: &str = r#"[Migration]
+Hash = ""
+Initial = false
+Dependency = "0001_initial"
+Replaces = []
+LockTimeoutMs = 100
+
+[[Migration.Operations]]
+Type = "CreateModel"
+Name = "bar"
+
+[[Migration.Operations.Fields]]
+Name = "id"
+Type = "int64"
+Annotations = [{ Type = "primary_key" }]
+"#;
+
+    #[tokio::test]
+    async fn lock_timeout_fails_fast() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_lock_timeout_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+        let migrations = [("0001_initial.toml", INITIAL), ("0002_second.toml", LOCKED)];
+        Migrator::from_embedded(&migrations[..1])
+            .unwrap()
+            .apply(&db_conf)
+            .await
+            .unwrap();
+
+        // Another connection holds the write lock of the database
+        let pool = connect(&db_conf).await.unwrap();
+        let mut holder = pool.acquire().await.unwrap();
+        holder.execute("BEGIN IMMEDIATE;").await.unwrap();
+
+        let migrator = Migrator::from_embedded(&migrations).unwrap();
+        let err = migrator.apply(&db_conf).await.err().unwrap();
+        assert!(matches!(
+            &err,
+            MigrationError::LockTimeout { migration, lock_timeout, .. }
+                if migration == "0002_second" && lock_timeout.as_millis() == 100
+        ));
+        assert!(err.to_string().ends_with("retry later"));
+
+        holder.execute("ROLLBACK;").await.unwrap();
+        drop(holder);
+        migrator.apply(&db_conf).await.unwrap();
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    const CREATE_FIELD: &str = r#"[Migration]
+Hash = ""
+Initial = false
+Dependency = "0001_initial"
+Replaces = []
+
+[[Migration.Operations]]
+Type = "CreateField"
+Model = "foo"
+
+[Migration.Operations.Field]
+Name = "age"
+Type = "int64"
+Annotations = []
+"#;
+
+    #[tokio::test]
+    async fn keep_going_applies_independent_migrations() {
+        let dir = std::env::temp_dir().join(format!("drorm_keep_going_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (namespace, files) in [
+            (
+                "a",
+                vec![
+                    ("0001_initial.toml", CREATE_MODELS),
+                    ("0002_second.toml", SECOND),
+                ],
+            ),
+            ("b", vec![("0001_initial.toml", INITIAL)]),
+        ] {
+            std::fs::create_dir_all(dir.join(namespace)).unwrap();
+            for (name, content) in files {
+                std::fs::write(dir.join(namespace).join(name), content).unwrap();
+            }
+        }
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: dir.join("db.sqlite3").to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        let pool = connect(&db_conf).await.unwrap();
+        pool.execute("CREATE TABLE foo (id INTEGER);")
+            .await
+            .unwrap();
+        let dirs: Vec<String> = ["a", "b"]
+            .iter()
+            .map(|x| format!("{}={}", x, dir.join(x).to_str().unwrap()))
+            .collect();
+        let dirs: Vec<&str> = dirs.iter().map(String::as_str).collect();
+        let applied = || async {
+            query("SELECT migration_name FROM _drorm__last_migration ORDER BY id;")
+                .map(|x: AnyRow| x.get::<String, _>(0))
+                .fetch_all(&pool)
+                .await
+                .unwrap()
+        };
+
+        // By default, the run stops at the first failed migration
+        let migrator = Migrator::from_dirs(&dirs).unwrap();
+        assert!(matches!(
+            migrator.apply(&db_conf).await,
+            Err(MigrationError::Sql { migration, .. }) if migration == "a/0001_initial"
+        ));
+        assert!(applied().await.is_empty());
+
+        let migrator = Migrator::from_dirs(&dirs).unwrap().keep_going();
+        match migrator.apply(&db_conf).await {
+            Err(MigrationError::Failures { failures, skipped }) => {
+                assert_eq!(failures.len(), 1);
+                assert_eq!(failures[0].0, "a/0001_initial");
+                assert_eq!(skipped, vec!["a/0002_second"]);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(applied().await, vec!["b/0001_initial"]);
+
+        pool.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn idempotent_skips_applied_objects() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_idempotent_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        // Partially applied by hand
+        let pool = connect(&db_conf).await.unwrap();
+        pool.execute("CREATE TABLE foo (id INTEGER PRIMARY KEY, age INTEGER);")
+            .await
+            .unwrap();
+
+        let migrations = [
+            ("0001_initial.toml", CREATE_MODELS),
+            ("0002_foo_age.toml", CREATE_FIELD),
+        ];
+        assert!(matches!(
+            Migrator::from_embedded(&migrations)
+                .unwrap()
+                .apply(&db_conf)
+                .await,
+            Err(MigrationError::Sql { migration, .. }) if migration == "0001_initial"
+        ));
+
+        Migrator::from_embedded(&migrations)
+            .unwrap()
+            .idempotent()
+            .apply(&db_conf)
+            .await
+            .unwrap();
+        let applied: i64 = query("SELECT COUNT(*) FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, 2);
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn continue_on_error_skips_failed_operations() {
+        let path = std::env::temp_dir().join(format!(
+            "drorm_continue_on_error_{}.sqlite3",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        let pool = connect(&db_conf).await.unwrap();
+        pool.execute("CREATE TABLE foo (id INTEGER);")
+            .await
+            .unwrap();
+
+        Migrator::from_embedded(&[("0001_initial.toml", CREATE_MODELS)])
+            .unwrap()
+            .continue_on_error()
+            .apply(&db_conf)
+            .await
+            .unwrap();
+
+        let tables: i64 = query("SELECT COUNT(*) FROM sqlite_master WHERE name = 'bar';")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(tables, 1);
+        let applied: i64 = query("SELECT COUNT(*) FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, 1);
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    const SQUASHED: &str = r#"[Migration]
+Hash = ""
+Initial = true
+Dependency = ""
+Replaces = ["0001_initial", "0002_second"]
+
+[[Migration.Operations]]
+Type = "CreateModel"
+Name = "bar"
+
+[[Migration.Operations.Fields]]
+Name = "id"
+Type = "int64"
+Annotations = [{ Type = "primary_key" }]
+"#;
+
+    const THIRD: &str = r#"[Migration]
+Hash = ""
+Initial = false
+Dependency = "0002_squashed"
+Replaces = []
+Atomic = false
+Operations = []
+"#;
+
+    const FOURTH: &str = r#"[Migration]
+Hash = ""
+Initial = false
+Dependency = "0003_third"
+Replaces = []
+Operations = []
+"#;
+
+    #[tokio::test]
+    async fn plan_is_aware_of_replaced_migrations() {
+        let path = std::env::temp_dir().join(format!("drorm_plan_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+        let squashed = [("0002_squashed.toml", SQUASHED), ("0003_third.toml", THIRD)];
+
+        // Nothing applied, the squash replaces the original migrations
+        let plan = Migrator::from_embedded(&squashed)
+            .unwrap()
+            .plan(&db_conf)
+            .await
+            .unwrap();
+        assert_eq!(plan.last_applied, None);
+        let ids: Vec<&str> = plan.migrations.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, vec!["0002_squashed", "0003_third"]);
+        assert_eq!(plan.migrations[0].name, "squashed");
+        assert_eq!(plan.migrations[0].operations, 1);
+        assert!(!plan.migrations[1].atomic);
+
+        // The plan must not create the last migration table
+        let pool = connect(&db_conf).await.unwrap();
+        let tables: i64 =
+            query("SELECT COUNT(*) FROM sqlite_master WHERE name = '_drorm__last_migration';")
+                .map(|x: AnyRow| x.get(0))
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(tables, 0);
+
+        // Only some of the replaced migrations are applied
+        Migrator::from_embedded(&[("0001_initial.toml", INITIAL)])
+            .unwrap()
+            .apply(&db_conf)
+            .await
+            .unwrap();
+        assert!(matches!(
+            Migrator::from_embedded(&squashed).unwrap().plan(&db_conf).await,
+            Err(MigrationError::PartiallyReplaced { migration, missing })
+                if migration == "0002_squashed" && missing == vec!["0002_second".to_string()]
+        ));
+
+        // The kept replaced migrations are applied instead of the squash
+        let kept = Migrator::from_embedded(&[
+            ("0001_initial.toml", INITIAL),
+            ("0002_second.toml", SECOND),
+            ("0002_squashed.toml", SQUASHED),
+            ("0003_third.toml", THIRD),
+        ])
+        .unwrap();
+        let plan = kept.plan(&db_conf).await.unwrap();
+        let ids: Vec<&str> = plan.migrations.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, vec!["0002_second", "0003_third"]);
+        let exported = kept.export_plan(&db_conf).await.unwrap();
+        let plan = Migrator::from_plan(&exported)
+            .unwrap()
+            .plan(&db_conf)
+            .await
+            .unwrap();
+        let ids: Vec<&str> = plan.migrations.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, vec!["0002_second", "0003_third"]);
+
+        // All replaced migrations are applied
+        Migrator::from_embedded(&[("0001_initial.toml", INITIAL), ("0002_second.toml", SECOND)])
+            .unwrap()
+            .apply(&db_conf)
+            .await
+            .unwrap();
+        let plan = Migrator::from_embedded(&squashed)
+            .unwrap()
+            .plan(&db_conf)
+            .await
+            .unwrap();
+        assert_eq!(plan.last_applied.as_deref(), Some("0002_second"));
+        let ids: Vec<&str> = plan.migrations.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, vec!["0003_third"]);
+
+        let json = serde_json::to_value(&plan).unwrap();
+        assert_eq!(json["Version"], 1);
+        assert_eq!(json["Dialect"], "sqlite");
+        assert_eq!(json["LastApplied"], "0002_second");
+        assert_eq!(json["Migrations"][0]["Id"], "0003_third");
+        assert_eq!(json["Migrations"][0]["Atomic"], false);
+        assert_eq!(json["PlanHash"], plan.plan_hash.as_str());
+
+        // A migration added after the plan was approved changes the hash
+        let added = Migrator::from_embedded(&[
+            ("0002_squashed.toml", SQUASHED),
+            ("0003_third.toml", THIRD),
+            ("0004_fourth.toml", FOURTH),
+        ])
+        .unwrap();
+        assert_ne!(
+            added.plan(&db_conf).await.unwrap().plan_hash,
+            plan.plan_hash
+        );
+        assert!(matches!(
+            added.expect_plan_hash(plan.plan_hash.as_str()).apply(&db_conf).await,
+            Err(MigrationError::PlanHashMismatch { expected, .. }) if expected == plan.plan_hash
+        ));
+        let applied: i64 = query("SELECT COUNT(*) FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, 2);
+        Migrator::from_embedded(&squashed)
+            .unwrap()
+            .expect_plan_hash(plan.plan_hash.as_str())
+            .apply(&db_conf)
+            .await
+            .unwrap();
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn status_lists_unknown_migrations() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_status_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+        Migrator::from_embedded(&[("0001_initial.toml", INITIAL), ("0002_second.toml", SECOND)])
+            .unwrap()
+            .apply(&db_conf)
+            .await
+            .unwrap();
+
+        // The squash counts as applied, the migrations it replaces aren't unknown
+        let status = Migrator::from_embedded(&[
+            ("0002_squashed.toml", SQUASHED),
+            ("0003_third.toml", THIRD),
+        ])
+        .unwrap()
+        .status(&db_conf)
+        .await
+        .unwrap();
+        assert_eq!(status.last_applied.as_deref(), Some("0002_second"));
+        let applied: Vec<(&str, bool)> = status
+            .migrations
+            .iter()
+            .map(|x| (x.id.as_str(), x.applied))
+            .collect();
+        assert_eq!(
+            applied,
+            vec![("0002_squashed", true), ("0003_third", false)]
+        );
+        assert!(status.unknown.is_empty());
+
+        let status = Migrator::from_embedded(&[("0001_initial.toml", INITIAL)])
+            .unwrap()
+            .status(&db_conf)
+            .await
+            .unwrap()
+            .since_applied();
+        assert_eq!(status.migrations.len(), 1);
+        assert_eq!(status.unknown, vec!["0002_second".to_string()]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn sqlite_strict_tables_accept_all_types() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_strict_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        let types = [
+            "varchar",
+            "varbinary",
+            "int8",
+            "int16",
+            "int32",
+            "int64",
+            "uint8",
+            "uint16",
+            "uint32",
+            "uint64",
+            "float_number",
+            "double_number",
+            "boolean",
+            "date",
+            "datetime",
+            "timestamp",
+            "time",
+            "choices",
+            "set",
+        ];
+        let mut migration = INITIAL.replace(
+            "Operations = []",
+            "[[Migration.Operations]]\nType = \"CreateModel\"\nName = \"all_types\"\n",
+        );
+        for db_type in types {
+            migration.push_str(
+                format!(
+                    "\n[[Migration.Operations.Fields]]\nName = \"{0}_field\"\nType = \"{0}\"\nAnnotations = [{{ Type = \"choices\", Value = [\"a\"] }}]\n",
+                    db_type
+                )
+                .as_str(),
+            );
+        }
+
+        Migrator::from_embedded(&[("0001_initial.toml", migration.as_str())])
+            .unwrap()
+            .apply(&db_conf)
+            .await
+            .unwrap();
+
+        let pool = connect(&db_conf).await.unwrap();
+        pool.execute("INSERT INTO all_types (varbinary_field) VALUES (x'00ff');")
+            .await
+            .unwrap();
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    const ALTER_FIELD: &str = r#"[Migration]
+Hash = ""
+Initial = false
+Dependency = "0001_initial"
+Replaces = []
+
+[[Migration.Operations]]
+Type = "AlterField"
+Model = "foo"
+Change = "narrowing"
+
+[Migration.Operations.Field]
+Name = "id"
+Type = "int32"
+Annotations = [{ Type = "primary_key" }]
+"#;
+
+    #[tokio::test]
+    async fn unsafe_type_changes_are_refused() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_unsafe_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        let migrations = [
+            ("0001_initial.toml", CREATE_MODELS),
+            ("0002_foo_id.toml", ALTER_FIELD),
+        ];
+        assert!(matches!(
+            Migrator::from_embedded(&migrations)
+                .unwrap()
+                .apply(&db_conf)
+                .await,
+            Err(MigrationError::UnsafeTypeChange { migration, model, field, .. })
+                if migration == "0002_foo_id" && model == "foo" && field == "id"
+        ));
+
+        // Nothing is applied, as the migrations are checked upfront
+        let pool = connect(&db_conf).await.unwrap();
+        let applied: i64 = query("SELECT COUNT(*) FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, 0);
+
+        Migrator::from_embedded(&migrations)
+            .unwrap()
+            .allow_unsafe()
+            .apply(&db_conf)
+            .await
+            .unwrap();
+        let applied: i64 = query("SELECT COUNT(*) FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, 2);
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn narrowed_max_length_is_unsafe() {
+        let migration = |change| Migration {
+            hash: "".to_string(),
+            initial: false,
+            id: "0002_name".to_string(),
+            dependency: "0001_initial".to_string(),
+            external_dependencies: vec![],
+            merges: vec![],
+            replaces: vec![],
+            atomic: true,
+            operations: vec![Operation::AlterField {
+                model: "user".to_string(),
+                field: Field {
+                    name: "name".to_string(),
+                    db_type: DbType::VarChar,
+                    annotations: vec![Annotation::MaxLength(100)],
+                    source_defined_at: None,
+                },
+                change,
+            }],
+            precondition: None,
+            lock_timeout_ms: None,
+            naming_policy: None,
+            meta: None,
+        };
+
+        let widening = migration(TypeChange::Widening);
+        assert!(unsafe_type_changes(DBImpl::Postgres, &[&widening]).is_ok());
+
+        let narrowing = migration(TypeChange::Narrowing);
+        for db_impl in [DBImpl::SQLite, DBImpl::Postgres, DBImpl::MySQL] {
+            assert!(matches!(
+                unsafe_type_changes(db_impl, &[&narrowing]),
+                Err(MigrationError::UnsafeTypeChange {
+                    change: TypeChange::Narrowing,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn exported_plan_is_applied() {
+        let path = std::env::temp_dir().join(format!(
+            "drorm_exported_plan_{}.sqlite3",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        let plan = Migrator::from_embedded(&[("0001_initial.toml", CREATE_MODELS)])
+            .unwrap()
+            .export_plan(&db_conf)
+            .await
+            .unwrap();
+        assert_eq!(plan.pending, vec!["0001_initial"]);
+        let plan = PlanFile::from_bytes(&plan.to_bytes()).unwrap();
+
+        // The plan is bound to its dialect
+        let postgres = PlanFile {
+            dialect: Dialect::Postgres,
+            ..plan.clone()
+        };
+        assert!(matches!(
+            Migrator::from_plan(&postgres)
+                .unwrap()
+                .apply(&db_conf)
+                .await,
+            Err(MigrationError::DialectMismatch { .. })
+        ));
+
+        Migrator::from_plan(&plan)
+            .unwrap()
+            .apply(&db_conf)
+            .await
+            .unwrap();
+        let pool = connect(&db_conf).await.unwrap();
+        let applied: i64 = query("SELECT COUNT(*) FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, 1);
+        pool.close().await;
+
+        // Nothing is pending anymore, so the plan doesn't match the database
+        assert!(matches!(
+            Migrator::from_plan(&plan).unwrap().apply(&db_conf).await,
+            Err(MigrationError::PlanHashMismatch { .. })
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn concurrent_migrators_apply_once() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_concurrent_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+        let first = Migrator::from_embedded(&[("0001_initial.toml", CREATE_MODELS)]).unwrap();
+        let second = Migrator::from_embedded(&[("0001_initial.toml", CREATE_MODELS)]).unwrap();
+
+        let (a, b) = tokio::join!(first.apply(&db_conf), second.apply(&db_conf));
+        a.unwrap();
+        b.unwrap();
+
+        // A migrator which read the pending migrations before the other one committed
+        let pool = connect(&db_conf).await.unwrap();
+        let migration = &first.migrations[0];
+        apply_migration(
+            DBImpl::SQLite,
+            migration,
+            &pool,
+            Some("_drorm__last_migration"),
+            None,
+            &BuildOptions::default(),
+            false,
+            0,
+        )
+        .await
+        .unwrap();
+
+        let applied: i64 = query("SELECT COUNT(*) FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, 1);
+        pool.close().await;
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    const REFERENCED_MODELS: &str = r#"[Migration]
+Hash = ""
+Initial = false
+Dependency = "0001_initial"
+Replaces = []
+
+[[Migration.Operations]]
+Type = "CreateModel"
+Name = "baz"
+
+[[Migration.Operations.Fields]]
+Name = "id"
+Type = "int64"
+Annotations = [{ Type = "primary_key" }]
+
+[[Migration.Operations.Fields]]
+Name = "foo"
+Type = "int64"
+Annotations = []
+
+[[Migration.Operations]]
+Type = "CreateForeignKey"
+Model = "baz"
+Name = "baz_foo_fkey"
+Column = "foo"
+ForeignKey = { TableName = "foo", ColumnName = "id", OnDelete = "cascade" }
+
+[[Migration.Operations]]
+Type = "CreateIndex"
+Model = "foo"
+Name = "foo_id_idx"
+Columns = ["id"]
+"#;
+
+    const REBUILD: &str = r#"[Migration]
+Hash = ""
+Initial = false
+Dependency = "0002_baz"
+Replaces = []
+
+[[Migration.Operations]]
+Type = "CreateField"
+Model = "foo"
+
+[Migration.Operations.Field]
+Name = "bar"
+Type = "int64"
+Annotations = []
+
+[[Migration.Operations]]
+Type = "CreateForeignKey"
+Model = "foo"
+Name = "foo_bar_fkey"
+Column = "bar"
+ForeignKey = { TableName = "bar", ColumnName = "id" }
+
+[[Migration.Operations]]
+Type = "CreateCheck"
+Model = "foo"
+Name = "foo_id_check"
+Expression = "id > 0"
+"#;
+
+    #[tokio::test]
+    async fn sqlite_rebuild_keeps_rows() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_rebuild_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        Migrator::from_embedded(&[
+            ("0001_initial.toml", CREATE_MODELS),
+            ("0002_baz.toml", REFERENCED_MODELS),
+        ])
+        .unwrap()
+        .apply(&db_conf)
+        .await
+        .unwrap();
+        let pool = connect(&db_conf).await.unwrap();
+        pool.execute("INSERT INTO bar (id) VALUES (1); INSERT INTO foo (id) VALUES (1); INSERT INTO baz (id, foo) VALUES (1, 1);")
+            .await
+            .unwrap();
+        pool.close().await;
+
+        Migrator::from_embedded(&[
+            ("0001_initial.toml", CREATE_MODELS),
+            ("0002_baz.toml", REFERENCED_MODELS),
+            ("0003_rebuild.toml", REBUILD),
+        ])
+        .unwrap()
+        .apply(&db_conf)
+        .await
+        .unwrap();
+
+        let pool = connect(&db_conf).await.unwrap();
+        let count = |sql: &'static str| {
+            let pool = pool.clone();
+            async move {
+                query(sql)
+                    .map(|x: AnyRow| x.get::<i64, _>(0))
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+        // The rows of the table and the rows referencing it are kept
+        assert_eq!(count("SELECT COUNT(*) FROM foo;").await, 1);
+        assert_eq!(count("SELECT COUNT(*) FROM baz;").await, 1);
+        assert_eq!(
+            count("SELECT COUNT(*) FROM sqlite_master WHERE name = 'foo_id_idx';").await,
+            1
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM sqlite_master WHERE name LIKE '%drorm_old%' OR sql LIKE '%drorm_old%';").await,
+            0
+        );
+        // The new constraints are enforced
+        assert!(pool
+            .execute("INSERT INTO foo (id) VALUES (-1);")
+            .await
+            .is_err());
+        assert!(pool
+            .execute("INSERT INTO foo (id, bar) VALUES (2, 2);")
+            .await
+            .is_err());
+        // The foreign key of the referencing table still refers to the rebuilt table
+        pool.execute("DELETE FROM foo;").await.unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM baz;").await, 0);
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,197 +1,2475 @@
+pub mod applied;
 pub mod config;
+pub mod error;
+pub mod hashes;
+pub mod hooks;
+pub mod migrator;
+pub mod plan;
+pub mod rebuild;
+pub mod rollback;
+pub mod server;
 pub mod sql_builder;
+pub mod status;
 
+use std::fs::create_dir_all;
+use std::future::Future;
 use std::path::Path;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context};
-use rorm_sql::imr::{Annotation, DbType};
+use rorm_sql::alter_table::SQLAlterTableOperation;
+use rorm_sql::create_column::SQLCreateColumn;
+use rorm_sql::imr::{Annotation, DbType, DefaultValue, Dialect};
 use rorm_sql::DBImpl;
-use sqlx::sqlite::{SqliteConnectOptions, SqliteRow};
-use sqlx::{query, Row, SqlitePool};
+use sqlx::any::{Any, AnyConnectOptions, AnyConnection, AnyPool, AnyRow};
+use sqlx::mysql::{MySqlConnectOptions, MySqlDatabaseError};
+use sqlx::postgres::PgConnectOptions;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{query, Connection, Executor, Row};
+use tokio::time::{interval_at, sleep, timeout, Instant};
 
-use crate::declaration::Migration;
-use crate::migrate::config::DatabaseDriver::SQLite;
-use crate::migrate::config::{create_db_config, deserialize_db_conf, DatabaseDriver};
-use crate::migrate::sql_builder::migration_to_sql;
-use crate::utils::migrations::get_existing_migrations;
+use crate::declaration::{Migration, Operation, PreconditionFailure};
+use crate::make_migrations::operation_to_diff_line;
+use crate::migrate::applied::{dump_applied, restore_applied};
+use crate::migrate::config::{load_db_conf, DatabaseConfig, DatabaseDriver};
+use crate::migrate::error::MigrationError;
+use crate::migrate::hooks::with_hooks;
+use crate::migrate::migrator::{unsafe_type_changes, Migrator};
+use crate::migrate::plan::PlanFile;
+use crate::migrate::rebuild::rebuilt_tables;
+use crate::migrate::server::detect_server;
+use crate::migrate::sql_builder::{
+    lock_timeout_statements, migration_to_sql, operation_statements, redact_sensitive_defaults,
+    BuildOptions,
+};
+use crate::utils::migrations::convert_str_to_migration;
+use crate::utils::output::{info, paint, set_color, verbosity, ColorChoice, Style};
 
 /**
 Options for running migrations
 */
 pub struct MigrateOptions {
-    /// Directory, migrations exist in
-    pub migration_dir: String,
+    /// Directories, migrations exist in, given as `namespace=path` or `path`
+    pub migration_dirs: Vec<String>,
+
+    /// If set, the subdirectories of the migration directories are searched as well
+    pub recursive: bool,
 
     /// Path to the database configuration file
     pub database_config: String,
+
+    /// Url of the database, used instead of the configuration file
+    pub database_url: Option<String>,
+
+    /// If set, the SQL of pending migrations is printed instead of applied
+    pub dry_run: bool,
+
+    /// Dialect to generate the SQL for instead of the configured driver
+    pub dialect_override: Option<DatabaseDriver>,
+
+    /// If set, the last migration table is neither created nor used
+    /// and all migrations are applied
+    pub no_bookkeeping: bool,
+
+    /// Seconds after which a single statement is aborted
+    pub timeout: Option<u64>,
+
+    /// If set, objects which already exist or have already been removed are skipped
+    pub idempotent: bool,
+
+    /// If set, failed operations are skipped instead of aborting the migration
+    pub continue_on_error: bool,
+
+    /// If set, migrate continues after the latest recorded migration which exists,
+    /// if the last recorded migration doesn't exist anymore
+    pub ignore_missing_dependency: bool,
+
+    /// If set, the pending migrations are printed instead of applied
+    pub print_plan: bool,
+
+    /// If set, the migrations and whether they have been applied are printed
+    /// instead of applied, see [Migrator::status]
+    pub status: bool,
+
+    /// If set, [MigrateOptions::status] only lists the last applied and the pending migrations
+    pub since_applied: bool,
+
+    /// If set, [MigrateOptions::status] only lists this number of the most recent migrations
+    pub last: Option<usize>,
+
+    /// If set, the plan or the status is printed as JSON
+    pub json: bool,
+
+    /// If set, the database is created before migrating, if it doesn't exist
+    pub create_db: bool,
+
+    /// If set, the migrations are only applied if the hash of the plan matches
+    pub expect_plan_hash: Option<String>,
+
+    /// If set, the SQL reverting this number of applied migrations is printed,
+    /// requires [MigrateOptions::dry_run]
+    pub rollback: Option<usize>,
+
+    /// If set, type changes of fields which may lose data are applied
+    pub allow_unsafe: bool,
+
+    /// If set, the remaining migrations are applied after a migration failed,
+    /// see [Migrator::keep_going]
+    pub keep_going: bool,
+
+    /// If set, all pending migrations are applied in a single transaction,
+    /// see [Migrator::single_transaction]
+    pub single_transaction: bool,
+
+    /// Number of times a transaction aborted by a concurrent transaction is retried,
+    /// see [Migrator::retry]
+    pub retry: u32,
+
+    /// If set, the SQL of a failed migration is printed, see [Migrator::show_sql_on_error]
+    pub show_sql_on_error: bool,
+
+    /// If set, mismatched hashes of migrations are refused instead of warned about,
+    /// see [Migrator::strict_hash]
+    pub strict_hash: bool,
+
+    /// If set, the statistics of the tables changed by a migration are refreshed,
+    /// see [Migrator::analyze]
+    pub analyze: bool,
+
+    /// If set, only the migration of this file is applied, without resolving its
+    /// dependencies or checking the last migration table, see [apply_migration_file]
+    pub file: Option<String>,
+
+    /// If set, the migration applied by [MigrateOptions::file] is recorded in the last migration table
+    pub record: bool,
+
+    /// If set, the resolved plan is written to this file instead of applied, see [Migrator::export_plan]
+    pub export_plan: Option<String>,
+
+    /// If set, exactly the plan of this exported plan file is applied
+    /// instead of the migrations of [MigrateOptions::migration_dirs], see [Migrator::from_plan]
+    pub apply_plan: Option<String>,
+
+    /// If set, the rows of the last migration table are written to this file instead of
+    /// applying migrations, see [dump_applied]
+    pub dump_applied: Option<String>,
+
+    /// If set, the last migration table is rewritten to match this file written by
+    /// [MigrateOptions::dump_applied] instead of applying migrations, see [restore_applied]
+    pub restore_applied: Option<String>,
+
+    /// If set, the last migration table is restored without asking
+    pub non_interactive: bool,
 }
 
 /**
-Helper method to apply one migration. Writes also to last migration table.
+Helper method to connect to the database described by the configuration.
 
-`migration`: [&Migration]: Reference to the migration to apply.
-`pool`: [&SqlitePool]: Pool to apply the migration onto.
+The database connected to is printed with `-v`.
+
+`db_conf`: [&DatabaseConfig]: The database configuration to use.
+*/
+pub async fn connect(db_conf: &DatabaseConfig) -> Result<AnyPool, MigrationError> {
+    if verbosity() >= 1 {
+        match db_conf.driver {
+            DatabaseDriver::SQLite => info!("Connecting to SQLite database {}", db_conf.name),
+            _ => info!(
+                "Connecting to {:?} database {} on {}:{} as {}",
+                db_conf.driver.db_impl(),
+                db_conf.name,
+                db_conf.host,
+                db_conf.port,
+                db_conf.user
+            ),
+        }
+    }
+
+    let options: AnyConnectOptions = match db_conf.driver {
+        DatabaseDriver::SQLite => SqliteConnectOptions::default()
+            .create_if_missing(true)
+            .filename(db_conf.name.as_str())
+            .into(),
+        DatabaseDriver::Postgres => PgConnectOptions::new()
+            .host(db_conf.host.as_str())
+            .port(db_conf.port)
+            .username(db_conf.user.as_str())
+            .password(db_conf.password.as_str())
+            .database(db_conf.name.as_str())
+            .into(),
+        DatabaseDriver::MySQL => MySqlConnectOptions::new()
+            .host(db_conf.host.as_str())
+            .port(db_conf.port)
+            .username(db_conf.user.as_str())
+            .password(db_conf.password.as_str())
+            .database(db_conf.name.as_str())
+            .into(),
+    };
+
+    AnyPool::connect_with(options)
+        .await
+        .map_err(MigrationError::Connection)
+}
+
+/**
+Helper method to build the statement which creates the configured database.
+
+MySQL uses the configured character set and collation, falling back to the
+defaults of new tables. Postgres uses the configured character set as encoding.
+`None` is returned on SQLite, whose database is created with its file.
+
+`db_conf`: [&DatabaseConfig]: The database configuration to use.
+*/
+pub fn create_database_statement(db_conf: &DatabaseConfig) -> Option<String> {
+    match db_conf.driver {
+        DatabaseDriver::SQLite => None,
+        DatabaseDriver::Postgres => Some(match &db_conf.charset {
+            None => format!("CREATE DATABASE \"{}\";", db_conf.name.replace('"', "\"\"")),
+            // Only template0 can be copied with a different encoding
+            Some(charset) => format!(
+                "CREATE DATABASE \"{}\" ENCODING '{}' TEMPLATE template0;",
+                db_conf.name.replace('"', "\"\""),
+                charset.replace('\'', "''")
+            ),
+        }),
+        DatabaseDriver::MySQL => {
+            let (charset, collation) = match (&db_conf.charset, &db_conf.collation) {
+                (None, None) => ("utf8mb4", Some("utf8mb4_unicode_ci")),
+                (None, Some(collation)) => ("utf8mb4", Some(collation.as_str())),
+                (Some(charset), collation) => (charset.as_str(), collation.as_deref()),
+            };
+            Some(format!(
+                "CREATE DATABASE IF NOT EXISTS `{}` CHARACTER SET {}{};",
+                db_conf.name.replace('`', "``"),
+                charset,
+                collation
+                    .map(|x| format!(" COLLATE {}", x))
+                    .unwrap_or_default()
+            ))
+        }
+    }
+}
+
+/**
+Helper method to create the configured database, if it doesn't exist.
+
+Postgres and MySQL are connected to without the database, i.e. to the
+maintenance database `postgres` and the server respectively.
+On SQLite the parent directory of the database file is created.
+
+`db_conf`: [&DatabaseConfig]: The database configuration to use.
+*/
+pub async fn create_database(db_conf: &DatabaseConfig) -> anyhow::Result<()> {
+    let (options, exists_query): (AnyConnectOptions, &str) = match db_conf.driver {
+        DatabaseDriver::SQLite => {
+            if let Some(parent) = Path::new(db_conf.name.as_str()).parent() {
+                if !parent.as_os_str().is_empty() {
+                    create_dir_all(parent).with_context(|| {
+                        format!("Couldn't create the directory {:?} of the database", parent)
+                    })?;
+                }
+            }
+            return Ok(());
+        }
+        DatabaseDriver::Postgres => (
+            PgConnectOptions::new()
+                .host(db_conf.host.as_str())
+                .port(db_conf.port)
+                .username(db_conf.user.as_str())
+                .password(db_conf.password.as_str())
+                .database("postgres")
+                .into(),
+            "SELECT 1 FROM pg_database WHERE datname = $1;",
+        ),
+        DatabaseDriver::MySQL => (
+            MySqlConnectOptions::new()
+                .host(db_conf.host.as_str())
+                .port(db_conf.port)
+                .username(db_conf.user.as_str())
+                .password(db_conf.password.as_str())
+                .into(),
+            "SELECT 1 FROM information_schema.SCHEMATA WHERE SCHEMA_NAME = ?;",
+        ),
+    };
+
+    let mut conn = AnyConnection::connect_with(&options)
+        .await
+        .with_context(|| {
+            format!(
+                "Couldn't connect to the server of database {} to create it",
+                db_conf.name
+            )
+        })?;
+
+    // Checked first, so the privilege to create databases is only required if it's missing
+    let exists = query(exists_query)
+        .bind(db_conf.name.as_str())
+        .fetch_optional(&mut conn)
+        .await
+        .with_context(|| format!("Couldn't check if database {} exists", db_conf.name))?
+        .is_some();
+    if !exists {
+        // Checked above, the statement is only missing on SQLite
+        let statement = create_database_statement(db_conf).unwrap_or_default();
+        if let Err(err) = conn.execute(statement.as_str()).await {
+            let denied = err.as_database_error().is_some_and(|x| {
+                x.code().is_some_and(|code| code == "42501")
+                    || x.try_downcast_ref::<MySqlDatabaseError>()
+                        .is_some_and(|y| matches!(y.number(), 1044 | 1227))
+            });
+            if denied {
+                return Err(anyhow!(
+                    "User {} is not allowed to create databases. Create the database {} manually or grant the privilege to create databases.",
+                    db_conf.user,
+                    db_conf.name
+                ));
+            }
+            return Err(err).with_context(|| format!("Couldn't create database {}", db_conf.name));
+        }
+        info!("Created database {}", db_conf.name);
+    }
+
+    conn.close()
+        .await
+        .with_context(|| "Couldn't close the connection to the server")?;
+    Ok(())
+}
+
+/**
+Helper method to build the statement which records an applied migration.
+
+The insert is idempotent, recording a migration twice does not fail.
+The name and the hash of the migration are bound to the statement.
+
+`db_impl`: [DBImpl]: The database implementation to use.
 `last_migration_table_name`: [&str]: Name of the table to insert successful applied migrations into.
 */
-pub async fn apply_migration_sqlite(
-    migration: &Migration,
-    pool: &SqlitePool,
+pub fn bookkeeping_insert(db_impl: DBImpl, last_migration_table_name: &str) -> String {
+    match db_impl {
+        DBImpl::SQLite => format!(
+            "INSERT INTO {} (migration_name, hash) VALUES (?, ?) ON CONFLICT (migration_name) DO NOTHING;",
+            last_migration_table_name
+        ),
+        DBImpl::Postgres => format!(
+            "INSERT INTO {} (migration_name, hash) VALUES ($1, $2) ON CONFLICT (migration_name) DO NOTHING;",
+            last_migration_table_name
+        ),
+        DBImpl::MySQL => format!(
+            "INSERT IGNORE INTO {} (migration_name, hash) VALUES (?, ?);",
+            last_migration_table_name
+        ),
+    }
+}
+
+/**
+Helper method to build the statement which locks the last migration table
+for the migrators racing to apply the same migrations.
+
+Postgres can't lock rows which don't exist yet, so the table is locked
+against concurrent writes. SQLite takes its write lock, which is held until the
+end of the transaction. MySQL locks the rows and gaps read by [bookkeeping_locking_read].
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`last_migration_table_name`: [&str]: Name of the table to lock.
+*/
+pub fn bookkeeping_lock(db_impl: DBImpl, last_migration_table_name: &str) -> Option<String> {
+    match db_impl {
+        DBImpl::SQLite => Some(format!(
+            "DELETE FROM {} WHERE 1 = 0;",
+            last_migration_table_name
+        )),
+        DBImpl::Postgres => Some(format!(
+            "LOCK TABLE {} IN SHARE ROW EXCLUSIVE MODE;",
+            last_migration_table_name
+        )),
+        DBImpl::MySQL => None,
+    }
+}
+
+/**
+Helper method to build the statement which re-reads whether a migration is recorded,
+after [bookkeeping_lock] has been executed in the same transaction.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`last_migration_table_name`: [&str]: Name of the table to read from.
+*/
+pub fn bookkeeping_locking_read(db_impl: DBImpl, last_migration_table_name: &str) -> String {
+    match db_impl {
+        DBImpl::SQLite => format!(
+            "SELECT migration_name FROM {} WHERE migration_name = ?;",
+            last_migration_table_name
+        ),
+        DBImpl::Postgres => format!(
+            "SELECT migration_name FROM {} WHERE migration_name = $1 FOR UPDATE;",
+            last_migration_table_name
+        ),
+        DBImpl::MySQL => format!(
+            "SELECT migration_name FROM {} WHERE migration_name = ? FOR UPDATE;",
+            last_migration_table_name
+        ),
+    }
+}
+
+/**
+Helper method to build the statement which records a migration skipped by its precondition.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`last_migration_table_name`: [&str]: Name of the table to insert the skipped migration into.
+*/
+pub fn bookkeeping_insert_skipped(db_impl: DBImpl, last_migration_table_name: &str) -> String {
+    match db_impl {
+        DBImpl::SQLite => format!(
+            "INSERT INTO {} (migration_name, hash, skipped) VALUES (?, ?, TRUE) ON CONFLICT (migration_name) DO NOTHING;",
+            last_migration_table_name
+        ),
+        DBImpl::Postgres => format!(
+            "INSERT INTO {} (migration_name, hash, skipped) VALUES ($1, $2, TRUE) ON CONFLICT (migration_name) DO NOTHING;",
+            last_migration_table_name
+        ),
+        DBImpl::MySQL => format!(
+            "INSERT IGNORE INTO {} (migration_name, hash, skipped) VALUES (?, ?, TRUE);",
+            last_migration_table_name
+        ),
+    }
+}
+
+/**
+Helper method to build the statement which removes a recorded migration.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`last_migration_table_name`: [&str]: Name of the table to remove the migration from.
+*/
+pub fn bookkeeping_delete(db_impl: DBImpl, last_migration_table_name: &str) -> String {
+    match db_impl {
+        DBImpl::SQLite | DBImpl::MySQL => format!(
+            "DELETE FROM {} WHERE migration_name = ?;",
+            last_migration_table_name
+        ),
+        DBImpl::Postgres => format!(
+            "DELETE FROM {} WHERE migration_name = $1;",
+            last_migration_table_name
+        ),
+    }
+}
+
+/**
+Helper method to check whether the last migration table exists.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`pool`: [&AnyPool]: Pool to check.
+`last_migration_table_name`: [&str]: Name of the table to check for.
+*/
+pub async fn bookkeeping_table_exists(
+    db_impl: DBImpl,
+    pool: &AnyPool,
+    last_migration_table_name: &str,
+) -> anyhow::Result<bool> {
+    let q = match db_impl {
+        DBImpl::SQLite => "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?;",
+        DBImpl::Postgres => "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = $1;",
+        DBImpl::MySQL => "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = ?;",
+    };
+
+    let count: i64 = query(q)
+        .bind(last_migration_table_name)
+        .map(|x: AnyRow| x.get(0))
+        .fetch_one(pool)
+        .await
+        .with_context(|| "Couldn't check for the last migration table")?;
+
+    Ok(count > 0)
+}
+
+/**
+Helper method to build the column of the last migration table,
+which marks the migrations skipped by their precondition.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`last_migration_table_name`: [&str]: Name of the last migration table.
+*/
+fn skipped_column(db_impl: DBImpl, last_migration_table_name: &str) -> SQLCreateColumn {
+    db_impl.create_column(
+        last_migration_table_name,
+        "skipped",
+        DbType::Boolean,
+        vec![
+            Annotation::NotNull,
+            Annotation::DefaultValue(DefaultValue::Boolean(false)),
+        ],
+    )
+}
+
+/**
+Helper method to build the column of the last migration table,
+which holds the hash of the migrations at the time they were recorded.
+
+Migrations recorded by an older version have no hash.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`last_migration_table_name`: [&str]: Name of the last migration table.
+*/
+fn hash_column(db_impl: DBImpl, last_migration_table_name: &str) -> SQLCreateColumn {
+    db_impl.create_column(
+        last_migration_table_name,
+        "hash",
+        DbType::VarChar,
+        vec![Annotation::MaxLength(255)],
+    )
+}
+
+/**
+Helper method to check, if a column of the last migration table exists.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`pool`: [&AnyPool]: Pool to check the table in.
+`last_migration_table_name`: [&str]: Name of the last migration table.
+`column`: [&str]: Name of the column to check for.
+*/
+pub async fn bookkeeping_column_exists(
+    db_impl: DBImpl,
+    pool: &AnyPool,
+    last_migration_table_name: &str,
+    column: &str,
+) -> anyhow::Result<bool> {
+    let column_exists = match db_impl {
+        DBImpl::SQLite => "SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?;",
+        DBImpl::Postgres => "SELECT COUNT(*) FROM information_schema.columns WHERE table_schema = current_schema() AND table_name = $1 AND column_name = $2;",
+        DBImpl::MySQL => "SELECT COUNT(*) FROM information_schema.columns WHERE table_schema = DATABASE() AND table_name = ? AND column_name = ?;",
+    };
+    let count: i64 = query(column_exists)
+        .bind(last_migration_table_name)
+        .bind(column)
+        .map(|x: AnyRow| x.get(0))
+        .fetch_one(pool)
+        .await
+        .with_context(|| "Couldn't check for the columns of the last migration table")?;
+    Ok(count > 0)
+}
+
+/**
+Helper method to create the last migration table, if it doesn't exist yet.
+
+It also adds the columns of skipped migrations and hashes and the unique index on
+the migration name to tables which were created by an older version.
+The index isn't created if a migration is recorded more than once, an error is returned instead.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`pool`: [&AnyPool]: Pool to create the table in.
+`last_migration_table_name`: [&str]: Name of the table to create.
+*/
+pub async fn create_bookkeeping_table(
+    db_impl: DBImpl,
+    pool: &AnyPool,
     last_migration_table_name: &str,
 ) -> anyhow::Result<()> {
-    let q = migration_to_sql(DBImpl::SQLite, migration)?;
-    query(q.as_str())
-        .execute(pool)
+    let create_table = db_impl
+        .create_table(last_migration_table_name)
+        .add_column(db_impl.create_column(
+            last_migration_table_name,
+            "id",
+            DbType::UInt64,
+            vec![
+                Annotation::NotNull,
+                Annotation::PrimaryKey,
+                Annotation::AutoIncrement,
+            ],
+        ))
+        // Rows are never updated, so setting the time on creation is sufficient
+        .add_column(db_impl.create_column(
+            last_migration_table_name,
+            "updated_at",
+            DbType::Datetime,
+            vec![Annotation::AutoCreateTime],
+        ))
+        .add_column(db_impl.create_column(
+            last_migration_table_name,
+            "migration_name",
+            DbType::VarChar,
+            vec![Annotation::MaxLength(255), Annotation::NotNull],
+        ))
+        .add_column(skipped_column(db_impl, last_migration_table_name))
+        .add_column(hash_column(db_impl, last_migration_table_name))
+        .if_not_exists()
+        .build()
+        .with_context(|| "Error while creating last migration table")?;
+
+    pool.execute(create_table.as_str())
         .await
-        .with_context(|| format!("Error while applying migration {}", migration.id))?;
-    query(
+        .with_context(|| "Couldn't create internal last migration table")?;
+
+    // Tables created by an older version lack the columns of skipped migrations and hashes
+    let columns = [
+        (
+            "skipped",
+            skipped_column(db_impl, last_migration_table_name),
+        ),
+        ("hash", hash_column(db_impl, last_migration_table_name)),
+    ];
+    for (name, column) in columns {
+        if bookkeeping_column_exists(db_impl, pool, last_migration_table_name, name).await? {
+            continue;
+        }
+        let add_column = db_impl
+            .alter_table(
+                last_migration_table_name,
+                SQLAlterTableOperation::AddColumn { operation: column },
+            )
+            .build()
+            .with_context(|| {
+                format!(
+                    "Error while adding the {} column to last migration table",
+                    name
+                )
+            })?;
+        pool.execute(add_column.as_str()).await.with_context(|| {
+            format!(
+                "Couldn't add the {} column to internal last migration table",
+                name
+            )
+        })?;
+    }
+
+    let index_name = format!("{}_migration_name_index", last_migration_table_name);
+    let mut create_index = db_impl
+        .create_index(index_name.as_str(), last_migration_table_name)
+        .unique()
+        .add_column("migration_name");
+
+    match db_impl {
+        DBImpl::SQLite | DBImpl::Postgres => {
+            create_index = create_index.if_not_exists();
+        }
+        // MySQL can't create indexes conditionally, so check for its existence
+        DBImpl::MySQL => {
+            let count: i64 = query(
+                "SELECT COUNT(*) FROM information_schema.statistics WHERE table_schema = DATABASE() AND table_name = ? AND index_name = ?;",
+            )
+            .bind(last_migration_table_name)
+            .bind(index_name.as_str())
+            .map(|x: AnyRow| x.get(0))
+            .fetch_one(pool)
+            .await
+            .with_context(|| "Couldn't check for the index of the last migration table")?;
+
+            if count > 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    // Tables of an older version may record a migration twice, which violates the index
+    let duplicates: Vec<(String, i64)> = query(
         format!(
-            "INSERT INTO {} (migration_name) VALUES (?);",
+            "SELECT migration_name, COUNT(*) FROM {} GROUP BY migration_name HAVING COUNT(*) > 1 ORDER BY migration_name;",
             last_migration_table_name
         )
         .as_str(),
     )
-    .bind(migration.id.as_str())
-    .execute(pool)
+    .map(|x: AnyRow| (x.get(0), x.get(1)))
+    .fetch_all(pool)
     .await
-    .with_context(|| {
-        format!(
-            "Error while inserting applied migration {} into last migration table",
-            last_migration_table_name
-        )
-    })?;
+    .with_context(|| "Couldn't check for duplicate rows of the last migration table")?;
+    if !duplicates.is_empty() {
+        return Err(anyhow!(
+            "The last migration table {} records migrations more than once: {}. \
+            Its unique index on the migration name can't be created, \
+            delete all but the row with the lowest id of each of them and migrate again.",
+            last_migration_table_name,
+            duplicates
+                .iter()
+                .map(|(name, count)| format!("{} ({} rows)", name, count))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    pool.execute(
+        create_index
+            .build()
+            .with_context(|| "Error while creating index of last migration table")?
+            .as_str(),
+    )
+    .await
+    .with_context(|| "Couldn't create index of internal last migration table")?;
 
-    println!("Applied migration {}", migration.id.as_str());
     Ok(())
 }
 
 /**
-Applies migrations on the given database
+Helper method to execute a single statement of a migration.
+
+`executor`: [Executor]: Connection or transaction to execute the statement on.
+`migration`: [&Migration]: The migration the statement belongs to.
+`operation`: [Option<&Operation>]: The operation the statement belongs to.
+`statement`: [&str]: The statement to execute.
+`statement_timeout`: [Option<Duration>]: Time after which the statement is aborted.
 */
-pub async fn run_migrate(options: MigrateOptions) -> anyhow::Result<()> {
-    let db_conf_path = Path::new(options.database_config.as_str());
+async fn execute_statement<'c, E>(
+    executor: E,
+    migration: &Migration,
+    operation: Option<&Operation>,
+    statement: &str,
+    statement_timeout: Option<Duration>,
+) -> Result<(), MigrationError>
+where
+    E: Executor<'c, Database = Any>,
+{
+    let result = match statement_timeout {
+        None => executor.execute(statement).await,
+        Some(duration) => timeout(duration, executor.execute(statement))
+            .await
+            .map_err(|_| MigrationError::Timeout {
+                migration: migration.id.clone(),
+                statement: redact_sensitive_defaults(migration, statement),
+                timeout: duration,
+            })?,
+    };
 
-    if !&db_conf_path.exists() {
-        println!("Couldn't find the database configuration file, creating one and exiting");
-        create_db_config(&db_conf_path)?;
-        return Ok(());
+    result.map(|_| ()).map_err(|source| MigrationError::Sql {
+        migration: migration.id.clone(),
+        operation: operation.map(operation_to_diff_line),
+        statement: redact_sensitive_defaults(migration, statement),
+        source,
+    })
+}
+
+/**
+Interval in which the progress of long running SQL operations is reported
+*/
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/**
+Helper method to periodically report the progress of a statement while it is executed.
+
+`future`: [Future]: The execution of the statement.
+`migration`: [&Migration]: The migration the statement belongs to.
+*/
+async fn report_progress<F: Future>(future: F, migration: &Migration) -> F::Output {
+    tokio::pin!(future);
+    let start = Instant::now();
+    let mut ticks = interval_at(start + PROGRESS_INTERVAL, PROGRESS_INTERVAL);
+    loop {
+        tokio::select! {
+            output = &mut future => return output,
+            _ = ticks.tick() => info!(
+                "Running SQL operation of migration {} (elapsed {}s)...",
+                migration.id,
+                start.elapsed().as_secs()
+            ),
+        }
+    }
+}
+
+/**
+Helper method to check, if a statement failed because it has already been applied.
+
+Used for dialects, which don't support IF [NOT] EXISTS on all statements.
+Postgres skips those statements in the generated SQL instead.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`err`: [&MigrationError]: The error of the failed statement.
+*/
+fn already_applied(db_impl: DBImpl, err: &MigrationError) -> bool {
+    let source = match err {
+        MigrationError::Sql { source, .. } => source,
+        _ => return false,
+    };
+    let db_err = match source.as_database_error() {
+        Some(db_err) => db_err,
+        None => return false,
+    };
+
+    match db_impl {
+        DBImpl::SQLite => {
+            let message = db_err.message();
+            [
+                "already exists",
+                "duplicate column name",
+                "no such column",
+                "no such table",
+            ]
+            .iter()
+            .any(|x| message.contains(x))
+        }
+        // Table, column, key or foreign key already exists,
+        // can't drop a missing column or key, unknown column on rename
+        DBImpl::MySQL => db_err
+            .try_downcast_ref::<MySqlDatabaseError>()
+            .is_some_and(|x| matches!(x.number(), 1050 | 1054 | 1060 | 1061 | 1091 | 1826)),
+        DBImpl::Postgres => false,
     }
+}
 
-    let db_conf = deserialize_db_conf(&db_conf_path)?;
+/**
+Helper method to record an applied migration in the last migration table.
 
-    let existing_migrations = get_existing_migrations(options.migration_dir.as_str())
-        .with_context(|| "Couldn't retrieve existing migrations")?;
+`executor`: [Executor]: Connection or transaction to execute the insert on.
+`db_impl`: [DBImpl]: The database implementation to use.
+`migration`: [&Migration]: The applied migration.
+`last_migration_table_name`: [&str]: Name of the table to insert successful applied migrations into.
+`skipped`: [bool]: Whether the migration was skipped by its precondition.
+*/
+async fn record_migration<'c, E>(
+    executor: E,
+    db_impl: DBImpl,
+    migration: &Migration,
+    last_migration_table_name: &str,
+    skipped: bool,
+) -> Result<(), MigrationError>
+where
+    E: Executor<'c, Database = Any>,
+{
+    let insert = match skipped {
+        false => bookkeeping_insert(db_impl, last_migration_table_name),
+        true => bookkeeping_insert_skipped(db_impl, last_migration_table_name),
+    };
+    query(insert.as_str())
+        .bind(migration.id.as_str())
+        .bind(migration.hash.as_str())
+        .execute(executor)
+        .await
+        .map_err(|source| MigrationError::Bookkeeping {
+            table: last_migration_table_name.to_string(),
+            source: source.into(),
+        })?;
+    Ok(())
+}
 
-    match db_conf.driver {
-        SQLite => {
-            let pool = SqlitePool::connect_with(
-                SqliteConnectOptions::default()
-                    .create_if_missing(true)
-                    .filename(db_conf.name),
-            )
-            .await
-            .with_context(|| "Couldn't initialize pool connection")?;
-
-            query(
-                DBImpl::SQLite
-                    .create_table(db_conf.last_migration_table_name.as_str())
-                    .add_column(DBImpl::SQLite.create_column(
-                        db_conf.last_migration_table_name.as_str(),
-                        "id",
-                        DbType::UInt64,
-                        vec![
-                            Annotation::NotNull,
-                            Annotation::PrimaryKey,
-                            Annotation::AutoIncrement,
-                        ],
-                    ))
-                    .add_column(DBImpl::SQLite.create_column(
-                        db_conf.last_migration_table_name.as_str(),
-                        "updated_at",
-                        DbType::VarChar,
-                        vec![Annotation::AutoUpdateTime],
-                    ))
-                    .add_column(DBImpl::SQLite.create_column(
-                        db_conf.last_migration_table_name.as_str(),
-                        "migration_name",
-                        DbType::VarChar,
-                        vec![Annotation::NotNull],
-                    ))
-                    .if_not_exists()
-                    .build()
-                    .with_context(|| "Error while creating last migration table")?
-                    .as_str(),
-            )
-            .execute(&pool)
-            .await
-            .with_context(|| "Couldn't create internal last migration table")?;
+/**
+Helper method to re-read whether a migration has been recorded by a concurrent migrate run.
 
-            let last_migration: Option<String> = query(
-                format!(
-                    "SELECT migration_name FROM {} ORDER BY id DESC LIMIT 1;",
-                    &db_conf.last_migration_table_name
-                )
-                .as_str(),
-            )
-            .map(|x: SqliteRow| x.get(0))
-            .fetch_optional(&pool)
-            .await
-            .with_context(|| {
-                "Couldn't fetch information about successful migrations from migration table"
-            })?;
+The read locks the last migration table until the end of the transaction, so a
+concurrent run waits for the migration to be committed and skips it afterwards,
+instead of applying it a second time.
 
-            match last_migration {
-                None => {
-                    // Apply all migrations
-                    for migration in &existing_migrations {
-                        apply_migration_sqlite(
-                            migration,
-                            &pool,
-                            db_conf.last_migration_table_name.as_str(),
-                        )
-                        .await?;
-                    }
-                }
-                Some(id) => {
-                    // Search for last applied migration
-                    if existing_migrations.iter().any(|x| x.id == id) {
-                        let mut apply = false;
-                        for (idx, migration) in existing_migrations.iter().enumerate() {
-                            if apply {
-                                apply_migration_sqlite(
-                                    migration,
-                                    &pool,
-                                    db_conf.last_migration_table_name.as_str(),
-                                )
-                                .await?;
-                                continue;
-                            }
-
-                            if migration.id == id {
-                                apply = true;
-
-                                if idx == existing_migrations.len() - 1 {
-                                    println!("All migration have already been applied.");
-                                }
-                            }
-                        }
-                    } else {
-                        // If last applied migration could not be found in existing migrations,
-                        // panic as there's no way to determine what to do next
-                        return Err(anyhow!(
-                            r#"Last applied migration {} was not found in current migrations.
- 
-Can not proceed any further without damaging data.
-To correct, empty the {} table or reset the whole database."#,
-                            id.as_str(),
-                            db_conf.last_migration_table_name.as_str()
-                        ));
+`conn`: [&mut AnyConnection]: Transaction the migration is applied in.
+`db_impl`: [DBImpl]: The database implementation to use.
+`migration`: [&Migration]: The migration to check.
+`last_migration_table_name`: [&str]: Name of the last migration table.
+*/
+async fn recorded_concurrently(
+    conn: &mut AnyConnection,
+    db_impl: DBImpl,
+    migration: &Migration,
+    last_migration_table_name: &str,
+) -> Result<bool, MigrationError> {
+    let bookkeeping = |source: sqlx::Error| MigrationError::Bookkeeping {
+        table: last_migration_table_name.to_string(),
+        source: source.into(),
+    };
+
+    if let Some(lock) = bookkeeping_lock(db_impl, last_migration_table_name) {
+        conn.execute(lock.as_str())
+            .await
+            .map_err(|source| match migration.lock_timeout_ms {
+                Some(millis) if lock_not_acquired(db_impl, &source) => {
+                    MigrationError::LockTimeout {
+                        migration: migration.id.clone(),
+                        statement: lock.clone(),
+                        lock_timeout: Duration::from_millis(millis),
+                        source,
                     }
                 }
-            }
+                _ => bookkeeping(source),
+            })?;
+    }
+    let recorded = query(bookkeeping_locking_read(db_impl, last_migration_table_name).as_str())
+        .bind(migration.id.as_str())
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(bookkeeping)?;
+    Ok(recorded.is_some())
+}
+
+/**
+Helper method to evaluate the precondition of a migration.
+
+`Ok(false)` is returned if the migration is skipped. A precondition which doesn't hold
+and isn't skipped is returned as [MigrationError::PreconditionFailed].
+
+`conn`: [&mut AnyConnection]: Connection or transaction the migration is applied on.
+`migration`: [&Migration]: The migration to check.
+*/
+async fn check_precondition(
+    conn: &mut AnyConnection,
+    migration: &Migration,
+) -> Result<bool, MigrationError> {
+    let precondition = match &migration.precondition {
+        None => return Ok(true),
+        Some(precondition) => precondition,
+    };
+    let sql_error = |source| MigrationError::Sql {
+        migration: migration.id.clone(),
+        operation: None,
+        statement: precondition.sql.clone(),
+        source,
+    };
+
+    let row = query(precondition.sql.as_str())
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(sql_error)?;
+    // MySQL and SQLite return the results of comparisons as integers
+    let holds = match row.try_get::<bool, _>(0) {
+        Ok(holds) => holds,
+        Err(_) => row.try_get::<i64, _>(0).map_err(sql_error)? != 0,
+    };
+    if holds {
+        return Ok(true);
+    }
+
+    match precondition.on_failure {
+        PreconditionFailure::Error => Err(MigrationError::PreconditionFailed {
+            migration: migration.id.clone(),
+            sql: precondition.sql.clone(),
+        }),
+        PreconditionFailure::Skip => {
+            info!(
+                "{}",
+                paint(
+                    Style::Warning,
+                    format!(
+                        "Skipping migration {} as its precondition doesn't hold",
+                        migration.id
+                    )
+                )
+            );
+            Ok(false)
         }
-        DatabaseDriver::Postgres => {}
-        DatabaseDriver::MySQL => {}
     }
+}
 
-    Ok(())
+/**
+The lookup of a column on mysql, which has no `DROP COLUMN IF EXISTS`
+*/
+const MYSQL_COLUMN_EXISTS: &str = "SELECT COUNT(*) FROM information_schema.columns WHERE table_schema = DATABASE() AND table_name = ? AND column_name = ?;";
+
+/**
+Helper method to check whether the column deleted by an operation is already absent on mysql.
+
+MySQL has no `DROP COLUMN IF EXISTS`, so idempotent migrations look the column up
+before dropping it. The column is absent as well, if its table doesn't exist.
+
+`conn`: [&mut AnyConnection]: Connection or transaction to execute the lookup on.
+`migration`: [&Migration]: The migration the operation belongs to.
+`operation`: [&Operation]: The operation to check, all operations but `DeleteField` are present.
+*/
+async fn deleted_column_absent(
+    conn: &mut AnyConnection,
+    migration: &Migration,
+    operation: &Operation,
+) -> Result<bool, MigrationError> {
+    let (model, name) = match operation.inner() {
+        Operation::DeleteField { model, name, .. } => (model, name),
+        _ => return Ok(false),
+    };
+
+    let count: i64 = query(MYSQL_COLUMN_EXISTS)
+        .bind(model.as_str())
+        .bind(name.as_str())
+        .map(|x: AnyRow| x.get(0))
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|source| MigrationError::Sql {
+            migration: migration.id.clone(),
+            operation: Some(operation_to_diff_line(operation)),
+            statement: MYSQL_COLUMN_EXISTS.to_string(),
+            source,
+        })?;
+    Ok(count == 0)
+}
+
+/**
+Helper method to drop the foreign keys depending on a model or field deleted with cascade on mysql.
+
+MySQL ignores CASCADE and refuses to drop referenced tables and columns used in
+foreign keys, so the foreign keys referencing the table or column and the
+foreign keys of the column itself are looked up and dropped first.
+
+`conn`: [&mut AnyConnection]: Connection or transaction to execute the statements on.
+`migration`: [&Migration]: The migration the operation belongs to.
+`operation`: [&Operation]: The operation to drop the dependent foreign keys of.
+`statement_timeout`: [Option<Duration>]: Time after which a single statement is aborted.
+*/
+async fn drop_dependent_foreign_keys(
+    conn: &mut AnyConnection,
+    migration: &Migration,
+    operation: &Operation,
+    statement_timeout: Option<Duration>,
+) -> Result<(), MigrationError> {
+    let (lookup, table, column) = match operation.inner() {
+        Operation::DeleteModel {
+            name,
+            cascade: true,
+        } => (
+            query("SELECT DISTINCT CAST(table_name AS CHAR), CAST(constraint_name AS CHAR) FROM information_schema.key_column_usage WHERE table_schema = DATABASE() AND referenced_table_name = ? AND table_name <> ?;")
+                .bind(name.as_str())
+                .bind(name.as_str()),
+            name,
+            None,
+        ),
+        Operation::DeleteField {
+            model,
+            name,
+            cascade: true,
+        } => (
+            query("SELECT DISTINCT CAST(table_name AS CHAR), CAST(constraint_name AS CHAR) FROM information_schema.key_column_usage WHERE table_schema = DATABASE() AND referenced_table_name IS NOT NULL AND ((referenced_table_name = ? AND referenced_column_name = ?) OR (table_name = ? AND column_name = ?));")
+                .bind(model.as_str())
+                .bind(name.as_str())
+                .bind(model.as_str())
+                .bind(name.as_str()),
+            model,
+            Some(name),
+        ),
+        _ => return Ok(()),
+    };
+
+    let foreign_keys: Vec<(String, String)> = lookup
+        .map(|x: AnyRow| (x.get(0), x.get(1)))
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|source| MigrationError::Sql {
+            migration: migration.id.clone(),
+            operation: Some(operation_to_diff_line(operation)),
+            statement: "SELECT ... FROM information_schema.key_column_usage".to_string(),
+            source,
+        })?;
+
+    for (foreign_key_table, name) in foreign_keys {
+        info!(
+            "Dropping foreign key {} of table {}, which depends on {}",
+            name,
+            foreign_key_table,
+            match column {
+                None => table.to_string(),
+                Some(column) => format!("{}.{}", table, column),
+            }
+        );
+        execute_statement(
+            &mut *conn,
+            migration,
+            Some(operation),
+            format!(
+                "ALTER TABLE {} DROP FOREIGN KEY {};",
+                foreign_key_table, name
+            )
+            .as_str(),
+            statement_timeout,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/**
+Helper method to execute the statements of one operation of a migration.
+
+`conn`: [&mut AnyConnection]: Connection or transaction to execute the statements on.
+`db_impl`: [DBImpl]: The database implementation to use.
+`migration`: [&Migration]: The migration the operation belongs to.
+`operation`: [&Operation]: The operation to execute.
+`statements`: [&[String]]: The statements of the operation.
+`statement_timeout`: [Option<Duration>]: Time after which a single statement is aborted.
+`idempotent`: [bool]: Skip statements which fail because they have already been applied.
+*/
+async fn execute_operation(
+    conn: &mut AnyConnection,
+    db_impl: DBImpl,
+    migration: &Migration,
+    operation: &Operation,
+    statements: &[String],
+    statement_timeout: Option<Duration>,
+    idempotent: bool,
+) -> Result<(), MigrationError> {
+    // Raw SQL may run for long, e.g. while backfilling data, so its progress is reported
+    let (statement_timeout, progress) = match operation.inner() {
+        Operation::RunSql {
+            timeout_seconds: Some(seconds),
+            ..
+        } => {
+            let mut deadline = Duration::from_secs(*seconds);
+            // The native statement timeout of postgres aborts first with a proper error
+            if db_impl == DBImpl::Postgres {
+                deadline += Duration::from_secs(1);
+            }
+            (Some(deadline), true)
+        }
+        Operation::RunSql { .. } => (statement_timeout, true),
+        _ => (statement_timeout, false),
+    };
+
+    // The operation has no statements, if it's not applied on this dialect
+    if db_impl == DBImpl::MySQL && !statements.is_empty() {
+        if idempotent && deleted_column_absent(conn, migration, operation).await? {
+            info!(
+                "Warning: skipped operation {} of migration {} as the column doesn't exist",
+                operation_to_diff_line(operation),
+                migration.id
+            );
+            return Ok(());
+        }
+        drop_dependent_foreign_keys(conn, migration, operation, statement_timeout).await?;
+    }
+
+    for statement in statements {
+        let execution = execute_statement(
+            &mut *conn,
+            migration,
+            Some(operation),
+            statement.as_str(),
+            statement_timeout,
+        );
+        let result = if progress {
+            report_progress(execution, migration).await
+        } else {
+            execution.await
+        };
+        match result {
+            Err(err) if idempotent && already_applied(db_impl, &err) => {
+                info!(
+                    "Warning: skipped statement of migration {} as it has already been applied: {}",
+                    migration.id, err
+                );
+            }
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+/**
+Outcome of applying a migration in a transaction, see [apply_in_transaction].
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// The operations have been executed, the given number of failed operations was skipped
+    Applied(usize),
+    /// The precondition doesn't hold, the migration has been recorded as skipped
+    Skipped,
+    /// A concurrent migrate run has applied the migration, nothing has been executed
+    AppliedConcurrently,
+}
+
+/**
+Helper method to log an operation skipped by `continue_on_error`.
+
+`migration`: [&Migration]: The migration the operation belongs to.
+`operation`: [&Operation]: The failed operation.
+`err`: [&MigrationError]: The error of the operation.
+*/
+fn log_skipped_operation(migration: &Migration, operation: &Operation, err: &MigrationError) {
+    info!(
+        "Warning: skipped operation {} of migration {}: {}",
+        operation_to_diff_line(operation),
+        migration.id,
+        std::error::Error::source(err).map_or_else(|| err.to_string(), |x| x.to_string())
+    );
+}
+
+/**
+Helper method to apply an atomic migration in an open transaction, which isn't committed.
+
+The migration and its bookkeeping are executed in the transaction,
+so it can be shared by several migrations, e.g. with `--single-transaction`.
+The lock timeout of the migration is set for the whole transaction body, including the
+lock of the last migration table, and reset afterwards.
+
+`conn`: [&mut AnyConnection]: The transaction to apply the migration in.
+`db_impl`: [DBImpl]: The database implementation to use.
+`migration`: [&Migration]: Reference to the migration to apply.
+`operations`: [&\[(&Operation, Vec<String>)\]]: The statements of the operations of the migration.
+`last_migration_table_name`: [Option<&str>]: Name of the table to insert successful applied migrations into.
+`statement_timeout`: [Option<Duration>]: Time after which a single statement is aborted.
+`idempotent`: [bool]: Skip statements which fail because they have already been applied.
+`continue_on_error`: [bool]: Skip failed operations instead of aborting the migration.
+`check_foreign_keys`: [bool]: Check the foreign keys afterwards, as they are disabled on SQLite.
+*/
+#[allow(clippy::too_many_arguments)]
+async fn apply_in_transaction(
+    conn: &mut AnyConnection,
+    db_impl: DBImpl,
+    migration: &Migration,
+    operations: &[(&Operation, Vec<String>)],
+    last_migration_table_name: Option<&str>,
+    statement_timeout: Option<Duration>,
+    idempotent: bool,
+    continue_on_error: bool,
+    check_foreign_keys: bool,
+) -> Result<Outcome, MigrationError> {
+    let lock_timeout = lock_timeout_statements(db_impl, migration);
+    if let Some((set, _)) = &lock_timeout {
+        execute_statement(&mut *conn, migration, None, set, None).await?;
+    }
+
+    let result = apply_locked_in_transaction(
+        conn,
+        db_impl,
+        migration,
+        operations,
+        last_migration_table_name,
+        statement_timeout,
+        idempotent,
+        continue_on_error,
+        check_foreign_keys,
+    )
+    .await;
+
+    // MySQL and SQLite set the timeout for the session, which is kept by the pooled connection
+    if let Some((_, reset)) = &lock_timeout {
+        match &result {
+            Ok(_) => execute_statement(&mut *conn, migration, None, reset, None).await?,
+            Err(_) => {
+                let _ = conn.execute(reset.as_str()).await;
+            }
+        }
+    }
+    result
+}
+
+/**
+Applies the migration in the transaction of [apply_in_transaction], after its lock timeout has been set.
+*/
+#[allow(clippy::too_many_arguments)]
+async fn apply_locked_in_transaction(
+    conn: &mut AnyConnection,
+    db_impl: DBImpl,
+    migration: &Migration,
+    operations: &[(&Operation, Vec<String>)],
+    last_migration_table_name: Option<&str>,
+    statement_timeout: Option<Duration>,
+    idempotent: bool,
+    continue_on_error: bool,
+    check_foreign_keys: bool,
+) -> Result<Outcome, MigrationError> {
+    // Postgres aborts the transaction on errors, so failed operations are rolled back
+    // to a savepoint. MySQL commits DDL implicitly, which releases all savepoints.
+    let savepoints = continue_on_error && db_impl != DBImpl::MySQL;
+
+    // The pending migrations are read before applying them, so another migrate run
+    // may have applied the migration in the meantime
+    if let Some(last_migration_table_name) = last_migration_table_name {
+        if recorded_concurrently(conn, db_impl, migration, last_migration_table_name).await? {
+            info!(
+                "{}",
+                paint(
+                    Style::Warning,
+                    format!(
+                        "Skipping migration {} as it has been applied concurrently",
+                        migration.id
+                    )
+                )
+            );
+            return Ok(Outcome::AppliedConcurrently);
+        }
+    }
+
+    if !check_precondition(conn, migration).await? {
+        if let Some(last_migration_table_name) = last_migration_table_name {
+            record_migration(
+                &mut *conn,
+                db_impl,
+                migration,
+                last_migration_table_name,
+                true,
+            )
+            .await?;
+        }
+        return Ok(Outcome::Skipped);
+    }
+
+    let mut skipped = 0;
+    for (operation, statements) in operations {
+        if savepoints {
+            execute_statement(
+                &mut *conn,
+                migration,
+                Some(operation),
+                "SAVEPOINT drorm_operation;",
+                None,
+            )
+            .await?;
+        }
+
+        match execute_operation(
+            conn,
+            db_impl,
+            migration,
+            operation,
+            statements,
+            statement_timeout,
+            idempotent,
+        )
+        .await
+        .map_err(|err| lock_timeout_error(db_impl, migration, err))
+        {
+            Err(err) if continue_on_error => {
+                if savepoints {
+                    execute_statement(
+                        &mut *conn,
+                        migration,
+                        Some(operation),
+                        "ROLLBACK TO SAVEPOINT drorm_operation;",
+                        None,
+                    )
+                    .await?;
+                }
+                log_skipped_operation(migration, operation, &err);
+                skipped += 1;
+            }
+            result => result?,
+        }
+    }
+
+    if check_foreign_keys {
+        // The rows copied by the rebuilds weren't checked against the foreign keys
+        let violations: Vec<String> = query("PRAGMA foreign_key_check;")
+            .map(|x: AnyRow| x.get::<String, _>(0))
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|source| MigrationError::Sql {
+                migration: migration.id.clone(),
+                operation: None,
+                statement: "PRAGMA foreign_key_check;".to_string(),
+                source,
+            })?;
+        if let Some(table) = violations.into_iter().next() {
+            return Err(MigrationError::ForeignKeyViolation {
+                migration: migration.id.clone(),
+                table,
+            });
+        }
+    }
+
+    if let Some(last_migration_table_name) = last_migration_table_name {
+        record_migration(
+            &mut *conn,
+            db_impl,
+            migration,
+            last_migration_table_name,
+            false,
+        )
+        .await?;
+    }
+    Ok(Outcome::Applied(skipped))
+}
+
+/**
+SQLSTATEs of transactions aborted by a concurrent transaction, which may succeed when retried:
+serialization failure, reported by MySQL for deadlocks as well, and deadlock on Postgres.
+*/
+const RETRYABLE_STATES: [&str; 2] = ["40001", "40P01"];
+
+/**
+Delay before the first retry of an aborted transaction, it is doubled for every further retry
+*/
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/**
+Helper method to check, if a statement failed because its transaction was aborted
+by a concurrent transaction, e.g. a serialization failure or a deadlock.
+
+SQLite doesn't abort transactions, a locked database fails after the busy timeout instead.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`err`: [&MigrationError]: The error of the failed transaction.
+*/
+fn retryable(db_impl: DBImpl, err: &MigrationError) -> bool {
+    let source = match err {
+        MigrationError::Sql { source, .. } if db_impl != DBImpl::SQLite => source,
+        _ => return false,
+    };
+    source
+        .as_database_error()
+        .and_then(|x| x.code())
+        .is_some_and(|x| RETRYABLE_STATES.contains(&x.as_ref()))
+}
+
+/**
+Helper method to check, if a statement failed because it couldn't acquire a lock within the lock timeout.
+
+Postgres fails with lock_not_available and MySQL with a lock wait timeout.
+SQLite reports the database as busy after the busy timeout.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`source`: [&sqlx::Error]: The error of the failed statement.
+*/
+fn lock_not_acquired(db_impl: DBImpl, source: &sqlx::Error) -> bool {
+    source.as_database_error().is_some_and(|x| match db_impl {
+        DBImpl::Postgres => x.code().is_some_and(|code| code == "55P03"),
+        DBImpl::MySQL => x
+            .try_downcast_ref::<MySqlDatabaseError>()
+            .is_some_and(|y| y.number() == 1205),
+        // The primary result code SQLITE_BUSY of the extended result codes
+        DBImpl::SQLite => x
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .is_some_and(|code| code & 0xff == 5),
+    })
+}
+
+/**
+Helper method to report a statement, which couldn't acquire a lock within the lock timeout of its migration.
+
+Other errors and errors of migrations without a lock timeout are returned unchanged.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`migration`: [&Migration]: The migration the statement belongs to.
+`err`: [MigrationError]: The error of the failed statement.
+*/
+fn lock_timeout_error(
+    db_impl: DBImpl,
+    migration: &Migration,
+    err: MigrationError,
+) -> MigrationError {
+    let lock_timeout = match migration.lock_timeout_ms {
+        Some(millis) => Duration::from_millis(millis),
+        None => return err,
+    };
+    match err {
+        MigrationError::Sql {
+            migration,
+            statement,
+            source,
+            ..
+        } if lock_not_acquired(db_impl, &source) => MigrationError::LockTimeout {
+            migration,
+            statement,
+            lock_timeout,
+            source,
+        },
+        err => err,
+    }
+}
+
+/**
+Applies a migration transaction, which is retried if it is aborted by a concurrent transaction.
+
+`apply` has to roll back its transaction on errors, which happens when the transaction is dropped.
+It is called again up to `retries` times, with an exponential backoff,
+if it failed with a serialization failure or a deadlock.
+Other errors are returned immediately.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`migration`: [&Migration]: The migration applied by the transaction, used for the output.
+`retries`: [u32]: Maximum number of retries.
+`apply`: [FnMut]: Runs the transaction.
+*/
+pub async fn apply_transaction<F, Fut, T>(
+    db_impl: DBImpl,
+    migration: &Migration,
+    retries: u32,
+    mut apply: F,
+) -> Result<T, MigrationError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, MigrationError>>,
+{
+    let mut attempt = 0;
+    loop {
+        match apply().await {
+            Err(err) if attempt < retries && retryable(db_impl, &err) => {
+                let backoff = RETRY_BACKOFF * 2u32.pow(attempt);
+                attempt += 1;
+                info!(
+                    "Warning: the transaction of migration {} was aborted by a concurrent transaction, retrying in {}ms ({}/{}): {}",
+                    migration.id,
+                    backoff.as_millis(),
+                    attempt,
+                    retries,
+                    std::error::Error::source(&err).map_or_else(|| err.to_string(), |x| x.to_string())
+                );
+                sleep(backoff).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/**
+Prints the SQL of a failed migration and the statement which failed.
+
+The statement is only known for errors of the database, e.g. not for a failed connection.
+Both are printed with the defaults of sensitive fields redacted.
+
+`migration`: [&Migration]: The failed migration.
+`operations`: [&\[(&Operation, Vec<String>)\]]: The statements of the operations of the migration.
+`err`: [&MigrationError]: The error the migration failed with.
+*/
+pub fn print_failed_sql(
+    migration: &Migration,
+    operations: &[(&Operation, Vec<String>)],
+    err: &MigrationError,
+) {
+    println!("-- SQL of failed migration {}", migration.id.as_str());
+    for statement in operations.iter().flat_map(|(_, x)| x) {
+        println!("{}", redact_sensitive_defaults(migration, statement));
+    }
+    match err {
+        MigrationError::Sql { statement, .. }
+        | MigrationError::Timeout { statement, .. }
+        | MigrationError::LockTimeout { statement, .. } => {
+            println!("-- failed statement");
+            println!("{}", statement);
+        }
+        _ => {}
+    }
+}
+
+/**
+Helper method to print the result of an applied migration.
+
+`migration`: [&Migration]: The applied migration.
+`skipped`: [usize]: Number of failed operations, which were skipped.
+`operations`: [usize]: Number of operations of the migration.
+*/
+fn report_applied(migration: &Migration, skipped: usize, operations: usize) {
+    if skipped > 0 {
+        info!(
+            "Warning: skipped {} of {} operations of migration {}, it is recorded as applied nonetheless",
+            skipped,
+            operations,
+            migration.id
+        );
+    }
+
+    info!(
+        "{}",
+        paint(
+            Style::Success,
+            format!("Applied migration {}", migration.id.as_str())
+        )
+    );
+}
+
+/**
+Helper method to disable the foreign keys on a SQLite connection, which isn't returned into the pool.
+
+Rebuilding a table on SQLite drops it, which must neither execute the actions of the
+foreign keys referencing it nor rename them. Foreign keys can't be disabled within
+a transaction, so they are disabled before it is started.
+
+`pool`: [&AnyPool]: Pool to take the connection from.
+`migration`: [&Migration]: The migration rebuilding the tables, used for errors.
+*/
+async fn rebuild_connection(
+    pool: &AnyPool,
+    migration: &Migration,
+) -> Result<AnyConnection, MigrationError> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(MigrationError::Connection)?
+        .detach();
+    execute_statement(
+        &mut conn,
+        migration,
+        None,
+        "PRAGMA foreign_keys = OFF;",
+        None,
+    )
+    .await?;
+    Ok(conn)
+}
+
+/**
+Helper method to apply an atomic migration in its own transaction, which is committed.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`migration`: [&Migration]: Reference to the migration to apply.
+`pool`: [&AnyPool]: Pool to apply the migration onto.
+`operations`: [&\[(&Operation, Vec<String>)\]]: The statements of the operations of the migration.
+`last_migration_table_name`: [Option<&str>]: Name of the table to insert successful applied migrations into.
+`statement_timeout`: [Option<Duration>]: Time after which a single statement is aborted.
+`idempotent`: [bool]: Skip statements which fail because they have already been applied.
+`continue_on_error`: [bool]: Skip failed operations instead of aborting the migration.
+*/
+#[allow(clippy::too_many_arguments)]
+async fn apply_atomic(
+    db_impl: DBImpl,
+    migration: &Migration,
+    pool: &AnyPool,
+    operations: &[(&Operation, Vec<String>)],
+    last_migration_table_name: Option<&str>,
+    statement_timeout: Option<Duration>,
+    idempotent: bool,
+    continue_on_error: bool,
+) -> Result<Outcome, MigrationError> {
+    let rebuilds = db_impl == DBImpl::SQLite && !rebuilt_tables(migration).is_empty();
+    let mut rebuild_conn = None;
+    let mut tx = if rebuilds {
+        rebuild_conn
+            .insert(rebuild_connection(pool, migration).await?)
+            .begin()
+            .await
+            .map_err(MigrationError::Connection)?
+    } else {
+        pool.begin().await.map_err(MigrationError::Connection)?
+    };
+
+    let outcome = apply_in_transaction(
+        &mut tx,
+        db_impl,
+        migration,
+        operations,
+        last_migration_table_name,
+        statement_timeout,
+        idempotent,
+        continue_on_error,
+        rebuilds,
+    )
+    .await?;
+    if outcome == Outcome::AppliedConcurrently {
+        drop(tx);
+    } else {
+        tx.commit().await.map_err(|source| MigrationError::Sql {
+            migration: migration.id.clone(),
+            operation: None,
+            statement: "COMMIT".to_string(),
+            source,
+        })?;
+    }
+    if let Some(conn) = rebuild_conn {
+        let _ = conn.close().await;
+    }
+    Ok(outcome)
+}
+
+/**
+Helper method to apply one migration. Writes also to last migration table, if given.
+
+Atomic migrations and their bookkeeping are executed in one transaction,
+which is only committed if all statements succeeded.
+If the returned future is dropped, e.g. on interrupt, the transaction is rolled back.
+A transaction aborted by a concurrent transaction is retried, see [apply_transaction].
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`migration`: [&Migration]: Reference to the migration to apply.
+`pool`: [&AnyPool]: Pool to apply the migration onto.
+`last_migration_table_name`: [Option<&str>]: Name of the table to insert successful applied migrations into.
+`statement_timeout`: [Option<Duration>]: Time after which a single statement is aborted.
+`options`: [&BuildOptions]: Options for generating the SQL of the migration.
+`continue_on_error`: [bool]: Skip failed operations instead of aborting the migration.
+`retries`: [u32]: Number of times an aborted transaction is retried.
+*/
+#[allow(clippy::too_many_arguments)]
+pub async fn apply_migration(
+    db_impl: DBImpl,
+    migration: &Migration,
+    pool: &AnyPool,
+    last_migration_table_name: Option<&str>,
+    statement_timeout: Option<Duration>,
+    options: &BuildOptions,
+    continue_on_error: bool,
+    retries: u32,
+) -> Result<(), MigrationError> {
+    let operations = operation_statements(db_impl, migration, options)?;
+    let idempotent = options.idempotent;
+
+    let skipped = if migration.atomic {
+        let outcome = apply_transaction(db_impl, migration, retries, || {
+            apply_atomic(
+                db_impl,
+                migration,
+                pool,
+                &operations,
+                last_migration_table_name,
+                statement_timeout,
+                idempotent,
+                continue_on_error,
+            )
+        })
+        .await?;
+
+        match outcome {
+            Outcome::Applied(skipped) => skipped,
+            Outcome::Skipped | Outcome::AppliedConcurrently => return Ok(()),
+        }
+    } else {
+        // Statements like CREATE INDEX CONCURRENTLY can't be executed in a transaction block,
+        // so every statement is sent on its own
+        let mut conn = pool.acquire().await.map_err(MigrationError::Connection)?;
+
+        if !check_precondition(&mut conn, migration).await? {
+            if let Some(last_migration_table_name) = last_migration_table_name {
+                record_migration(
+                    &mut *conn,
+                    db_impl,
+                    migration,
+                    last_migration_table_name,
+                    true,
+                )
+                .await?;
+            }
+            return Ok(());
+        }
+
+        // The lock timeout is set for the session, so it is reset before the connection
+        // is returned into the pool
+        let lock_timeout = lock_timeout_statements(db_impl, migration);
+        if let Some((set, _)) = &lock_timeout {
+            execute_statement(&mut *conn, migration, None, set, None).await?;
+        }
+
+        let mut skipped = 0;
+        for (idx, (operation, statements)) in operations.iter().enumerate() {
+            if let Err(err) = execute_operation(
+                &mut conn,
+                db_impl,
+                migration,
+                operation,
+                statements,
+                statement_timeout,
+                idempotent,
+            )
+            .await
+            .map_err(|err| lock_timeout_error(db_impl, migration, err))
+            {
+                if continue_on_error {
+                    log_skipped_operation(migration, operation, &err);
+                    skipped += 1;
+                    continue;
+                }
+                if idx > 0 {
+                    info!(
+                        "Warning: migration {} is not atomic, the {} operations executed before the failure are not rolled back",
+                        migration.id, idx
+                    );
+                }
+                if db_impl == DBImpl::Postgres {
+                    for operation in &migration.operations {
+                        if let Some(Operation::CreateIndex {
+                            name,
+                            concurrently: true,
+                            ..
+                        }) = operation.for_dialect(Dialect::Postgres)
+                        {
+                            info!(
+                                "Warning: a failed concurrent build leaves the index {} INVALID, drop it before retrying",
+                                name
+                            );
+                        }
+                    }
+                }
+                if let Some((_, reset)) = &lock_timeout {
+                    let _ = conn.execute(reset.as_str()).await;
+                }
+                return Err(err);
+            }
+        }
+
+        if let Some((_, reset)) = &lock_timeout {
+            execute_statement(&mut *conn, migration, None, reset, None).await?;
+        }
+
+        if let Some(last_migration_table_name) = last_migration_table_name {
+            record_migration(
+                &mut *conn,
+                db_impl,
+                migration,
+                last_migration_table_name,
+                false,
+            )
+            .await?;
+        }
+        skipped
+    };
+
+    report_applied(migration, skipped, operations.len());
+    Ok(())
+}
+
+/**
+Applies the migration of a single file, e.g. to reproduce a failure on a throwaway database.
+
+The dependencies of the migration are neither resolved nor applied and the last migration
+table is only written to, if `record` is set. The migration is applied in a transaction,
+unless it sets `Atomic = false`. Tables can't be rebuilt on SQLite, as the state of
+the previous migrations is unknown.
+
+`db_conf`: [&DatabaseConfig]: The database configuration to use.
+`path`: [&str]: Path to the migration file.
+`options`: [&MigrateOptions]: Options of the run, e.g. whether to print the SQL instead.
+*/
+pub async fn apply_migration_file(
+    db_conf: &DatabaseConfig,
+    path: &str,
+    options: &MigrateOptions,
+) -> anyhow::Result<()> {
+    let file_name = Path::new(path)
+        .file_name()
+        .and_then(|x| x.to_str())
+        .ok_or_else(|| anyhow!("{} is not a migration file", path))?;
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read migration file {}", path))?;
+    let migration = convert_str_to_migration(file_name, content.as_str())?.migration;
+
+    info!(
+        "Warning: applying migration {} from {} without resolving its dependencies{}",
+        migration.id,
+        path,
+        if options.record {
+            ""
+        } else {
+            " or recording it"
+        }
+    );
+
+    let conn_impl = db_conf.driver.db_impl();
+    let db_impl = match &options.dialect_override {
+        None => conn_impl,
+        Some(driver) => {
+            if !options.dry_run && driver.db_impl() != conn_impl {
+                return Err(MigrationError::DialectMismatch {
+                    dialect_override: driver.clone(),
+                    driver: db_conf.driver.clone(),
+                }
+                .into());
+            }
+            driver.db_impl()
+        }
+    };
+    let mut build_options = BuildOptions {
+        idempotent: options.idempotent,
+        charset: db_conf.charset.clone(),
+        collation: db_conf.collation.clone(),
+        state: None,
+        server: None,
+    };
+
+    if options.dry_run {
+        println!("-- {}", migration.id.as_str());
+        if let Some(precondition) = &migration.precondition {
+            println!("-- precondition: {}", precondition.sql);
+        }
+        println!(
+            "{}",
+            redact_sensitive_defaults(
+                &migration,
+                migration_to_sql(db_impl, &migration, &build_options)?.as_str()
+            )
+        );
+        return Ok(());
+    }
+    if !options.allow_unsafe {
+        unsafe_type_changes(db_impl, &[&migration])?;
+    }
+
+    let pool = connect(db_conf).await?;
+    build_options.server = detect_server(db_impl, &pool).await;
+    let last_migration_table_name = db_conf.last_migration_table_name.as_str();
+    if options.record {
+        create_bookkeeping_table(db_impl, &pool, last_migration_table_name)
+            .await
+            .map_err(|err| MigrationError::Bookkeeping {
+                table: last_migration_table_name.to_string(),
+                source: err.into(),
+            })?;
+    }
+
+    let result = apply_migration(
+        db_impl,
+        &migration,
+        &pool,
+        options.record.then_some(last_migration_table_name),
+        options.timeout.map(Duration::from_secs),
+        &build_options,
+        options.continue_on_error,
+        options.retry,
+    )
+    .await;
+    pool.close().await;
+    if let Err(err) = &result {
+        if options.show_sql_on_error {
+            print_failed_sql(
+                &migration,
+                &operation_statements(db_impl, &migration, &build_options)?,
+                err,
+            );
+        }
+    }
+    Ok(result?)
+}
+
+/**
+Reads a plan file exported by [Migrator::export_plan], see [PlanFile::from_bytes].
+
+The checksum and plan hash of the file only detect accidental changes, as they can be
+recomputed. So with an expected plan hash, e.g. of the approved plan, the plan hash of the file
+has to match it, which refuses a rewritten plan with a consistent checksum and plan hash.
+
+`path`: [&str]: Path of the plan file.
+`expected_plan_hash`: [Option<&str>]: The plan hash of the approved plan, if any.
+*/
+pub fn read_plan_file(path: &str, expected_plan_hash: Option<&str>) -> anyhow::Result<PlanFile> {
+    let bytes = std::fs::read(path).with_context(|| format!("Couldn't read plan file {}", path))?;
+    let plan = PlanFile::from_bytes(&bytes)?;
+    if let Some(expected) = expected_plan_hash.filter(|x| *x != plan.plan_hash) {
+        return Err(MigrationError::PlanHashMismatch {
+            expected: expected.to_string(),
+            actual: plan.plan_hash,
+        }
+        .into());
+    }
+    Ok(plan)
+}
+
+/**
+Applies migrations on the given database
+*/
+pub async fn run_migrate(options: MigrateOptions) -> anyhow::Result<()> {
+    let db_conf = match load_db_conf(
+        Path::new(options.database_config.as_str()),
+        options.database_url.as_deref(),
+    )? {
+        Some(db_conf) => db_conf,
+        None => return Ok(()),
+    };
+    if options.create_db {
+        create_database(&db_conf).await?;
+    }
+    if let Some(path) = &options.file {
+        let apply = apply_migration_file(&db_conf, path.as_str(), &options);
+        return match options.dry_run {
+            true => apply.await,
+            false => with_hooks(&db_conf, apply).await,
+        };
+    }
+
+    let mut migrator = match &options.apply_plan {
+        Some(path) => Migrator::from_plan(&read_plan_file(
+            path.as_str(),
+            options.expect_plan_hash.as_deref(),
+        )?)?,
+        None => {
+            let migration_dirs: Vec<&str> =
+                options.migration_dirs.iter().map(String::as_str).collect();
+            match options.recursive {
+                true => Migrator::from_dirs_recursive(&migration_dirs)?,
+                false => Migrator::from_dirs(&migration_dirs)?,
+            }
+        }
+    };
+    if options.dry_run {
+        migrator = migrator.dry_run();
+    }
+    if let Some(driver) = options.dialect_override {
+        migrator = migrator.dialect_override(driver);
+    }
+    if options.no_bookkeeping {
+        migrator = migrator.no_bookkeeping();
+    }
+    if let Some(seconds) = options.timeout {
+        migrator = migrator.statement_timeout(Duration::from_secs(seconds));
+    }
+    if options.idempotent {
+        migrator = migrator.idempotent();
+    }
+    if options.continue_on_error {
+        migrator = migrator.continue_on_error();
+    }
+    if options.ignore_missing_dependency {
+        migrator = migrator.ignore_missing_dependency();
+    }
+    if let Some(hash) = &options.expect_plan_hash {
+        migrator = migrator.expect_plan_hash(hash.as_str());
+    }
+    if options.allow_unsafe {
+        migrator = migrator.allow_unsafe();
+    }
+    if options.keep_going {
+        migrator = migrator.keep_going();
+    }
+    if options.single_transaction {
+        migrator = migrator.single_transaction();
+    }
+    if options.retry > 0 {
+        migrator = migrator.retry(options.retry);
+    }
+    if options.show_sql_on_error {
+        migrator = migrator.show_sql_on_error();
+    }
+    if options.strict_hash {
+        migrator = migrator.strict_hash();
+    }
+    if options.analyze || db_conf.analyze {
+        migrator = migrator.analyze();
+    }
+
+    if let Some(count) = options.rollback {
+        return Ok(migrator.preview_rollback(&db_conf, count).await?);
+    }
+
+    if let Some(path) = &options.dump_applied {
+        return dump_applied(&db_conf, migrator.migrations(), path.as_str()).await;
+    }
+    if let Some(path) = &options.restore_applied {
+        return restore_applied(
+            &db_conf,
+            migrator.migrations(),
+            path.as_str(),
+            options.non_interactive,
+        )
+        .await;
+    }
+
+    if let Some(path) = &options.export_plan {
+        let plan = migrator.export_plan(&db_conf).await?;
+        std::fs::write(path, plan.to_bytes())
+            .with_context(|| format!("Couldn't write plan file {}", path))?;
+        info!(
+            "Exported plan {} with {} pending migrations to {}",
+            plan.plan_hash,
+            plan.pending.len(),
+            path
+        );
+        return Ok(());
+    }
+
+    if options.print_plan {
+        let plan = migrator.plan(&db_conf).await?;
+        if options.json {
+            set_color(ColorChoice::Never);
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&plan).with_context(|| "Couldn't serialize plan")?
+            );
+        } else {
+            println!("{}", plan);
+        }
+        return Ok(());
+    }
+
+    if options.status {
+        let mut status = migrator.status(&db_conf).await?;
+        if options.since_applied {
+            status = status.since_applied();
+        }
+        if let Some(count) = options.last {
+            status = status.last(count);
+        }
+        if options.json {
+            set_color(ColorChoice::Never);
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&status)
+                    .with_context(|| "Couldn't serialize status")?
+            );
+        } else {
+            println!("{}", status);
+        }
+        return Ok(());
+    }
+
+    if options.dry_run {
+        return Ok(migrator.apply(&db_conf).await?);
+    }
+    with_hooks(&db_conf, async { Ok(migrator.apply(&db_conf).await?) }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::cell::Cell;
+    use std::error::Error;
+    use std::fmt::{Display, Formatter};
+
+    use rorm_sql::DBImpl;
+    use sqlx::any::AnyRow;
+    use sqlx::error::DatabaseError;
+    use sqlx::{query, Executor, Row};
+
+    use crate::declaration::Migration;
+    use crate::migrate::config::{DatabaseConfig, DatabaseDriver};
+    use crate::migrate::error::MigrationError;
+    use crate::migrate::migrator::Migrator;
+    use crate::migrate::plan::plan_hash;
+    use crate::migrate::{
+        apply_migration_file, apply_transaction, bookkeeping_insert, bookkeeping_lock,
+        bookkeeping_locking_read, bookkeeping_table_exists, connect, create_bookkeeping_table,
+        create_database_statement, read_plan_file, MigrateOptions,
+    };
+
+    /// Database error with a SQLSTATE, as reported by postgres and mysql
+    #[derive(Debug)]
+    struct StateError(&'static str);
+
+    impl Display for StateError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "error with SQLSTATE {}", self.0)
+        }
+    }
+
+    impl Error for StateError {}
+
+    impl DatabaseError for StateError {
+        fn message(&self) -> &str {
+            "could not serialize access due to concurrent update"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.0))
+        }
+
+        fn as_error(&self) -> &(dyn Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn Error + Send + Sync + 'static> {
+            self
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_aborted_transaction() {
+        let migration = Migration {
+            hash: "".to_string(),
+            initial: false,
+            id: "0002_test".to_string(),
+            dependency: "0001_initial".to_string(),
+            external_dependencies: vec![],
+            merges: vec![],
+            replaces: vec![],
+            atomic: true,
+            operations: vec![],
+            precondition: None,
+            lock_timeout_ms: None,
+            naming_policy: None,
+            meta: None,
+        };
+        let failure = |state: &'static str| MigrationError::Sql {
+            migration: migration.id.clone(),
+            operation: None,
+            statement: "COMMIT".to_string(),
+            source: sqlx::Error::Database(Box::new(StateError(state))),
+        };
+        // Fails with the given state until the given attempt
+        let attempts = Cell::new(0);
+        let apply = |state: &'static str, succeeds: u32| {
+            let attempts = &attempts;
+            let failure = &failure;
+            move || async move {
+                attempts.set(attempts.get() + 1);
+                match attempts.get() < succeeds {
+                    true => Err(failure(state)),
+                    false => Ok(attempts.get()),
+                }
+            }
+        };
+
+        // A serialization failure is retried with a new transaction
+        let result = apply_transaction(DBImpl::Postgres, &migration, 3, apply("40001", 2)).await;
+        assert_eq!(result.unwrap(), 2);
+
+        attempts.set(0);
+        let result = apply_transaction(DBImpl::MySQL, &migration, 3, apply("40001", 3)).await;
+        assert_eq!(result.unwrap(), 3);
+
+        // The retries are limited
+        attempts.set(0);
+        let result = apply_transaction(DBImpl::Postgres, &migration, 1, apply("40P01", 5)).await;
+        assert!(matches!(result, Err(MigrationError::Sql { .. })));
+        assert_eq!(attempts.get(), 2);
+
+        // Other errors fail immediately
+        attempts.set(0);
+        let result = apply_transaction(DBImpl::Postgres, &migration, 3, apply("42P01", 2)).await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+
+        attempts.set(0);
+        let result = apply_transaction(DBImpl::SQLite, &migration, 3, apply("40001", 2)).await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn bookkeeping_read_is_locked() {
+        assert_eq!(
+            bookkeeping_lock(DBImpl::Postgres, "_drorm__last_migration").unwrap(),
+            "LOCK TABLE _drorm__last_migration IN SHARE ROW EXCLUSIVE MODE;"
+        );
+        assert_eq!(
+            bookkeeping_lock(DBImpl::MySQL, "_drorm__last_migration"),
+            None
+        );
+        assert_eq!(
+            bookkeeping_locking_read(DBImpl::MySQL, "_drorm__last_migration"),
+            "SELECT migration_name FROM _drorm__last_migration WHERE migration_name = ? FOR UPDATE;"
+        );
+        // SQLite has no row locks, the read is covered by the write lock
+        assert_eq!(
+            bookkeeping_locking_read(DBImpl::SQLite, "_drorm__last_migration"),
+            "SELECT migration_name FROM _drorm__last_migration WHERE migration_name = ?;"
+        );
+    }
+
+    #[test]
+    fn bookkeeping_insert_is_upsert() {
+        assert_eq!(
+            bookkeeping_insert(DBImpl::SQLite, "_drorm__last_migration"),
+            "INSERT INTO _drorm__last_migration (migration_name, hash) VALUES (?, ?) ON CONFLICT (migration_name) DO NOTHING;"
+        );
+        assert_eq!(
+            bookkeeping_insert(DBImpl::Postgres, "_drorm__last_migration"),
+            "INSERT INTO _drorm__last_migration (migration_name, hash) VALUES ($1, $2) ON CONFLICT (migration_name) DO NOTHING;"
+        );
+        assert_eq!(
+            bookkeeping_insert(DBImpl::MySQL, "_drorm__last_migration"),
+            "INSERT IGNORE INTO _drorm__last_migration (migration_name, hash) VALUES (?, ?);"
+        );
+    }
+
+    #[test]
+    fn create_database() {
+        let mut db_conf = DatabaseConfig::from_url("mysql://root@localhost/app").unwrap();
+        assert_eq!(
+            create_database_statement(&db_conf).unwrap(),
+            "CREATE DATABASE IF NOT EXISTS `app` CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci;"
+        );
+        db_conf.charset = Some("latin1".to_string());
+        assert_eq!(
+            create_database_statement(&db_conf).unwrap(),
+            "CREATE DATABASE IF NOT EXISTS `app` CHARACTER SET latin1;"
+        );
+
+        db_conf.driver = DatabaseDriver::Postgres;
+        db_conf.name = "my\"app".to_string();
+        assert_eq!(
+            create_database_statement(&db_conf).unwrap(),
+            "CREATE DATABASE \"my\"\"app\" ENCODING 'latin1' TEMPLATE template0;"
+        );
+        db_conf.charset = None;
+        assert_eq!(
+            create_database_statement(&db_conf).unwrap(),
+            "CREATE DATABASE \"my\"\"app\";"
+        );
+
+        db_conf.driver = DatabaseDriver::SQLite;
+        assert_eq!(create_database_statement(&db_conf), None);
+    }
+
+    #[tokio::test]
+    async fn apply_single_file() {
+        let dir = std::env::temp_dir().join(format!("drorm_file_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // The dependency doesn't exist, it isn't resolved
+        let file = dir.join("0007_debug.toml");
+        std::fs::write(
+            &file,
+            r#"[Migration]
+Hash = ""
+Initial = false
+Dependency = "0006_missing"
+Replaces = []
+
+[[Migration.Operations]]
+Type = "CreateModel"
+Name = "foo"
+
+[[Migration.Operations.Fields]]
+Name = "id"
+Type = "int64"
+Annotations = [{ Type = "primary_key" }]
+"#,
+        )
+        .unwrap();
+
+        let db_conf = |name: &str| DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: dir.join(name).to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+        let options = |record| MigrateOptions {
+            migration_dirs: vec![],
+            recursive: false,
+            database_config: "".to_string(),
+            database_url: None,
+            dry_run: false,
+            dialect_override: None,
+            no_bookkeeping: false,
+            timeout: None,
+            idempotent: false,
+            continue_on_error: false,
+            ignore_missing_dependency: false,
+            print_plan: false,
+            status: false,
+            since_applied: false,
+            last: None,
+            json: false,
+            create_db: false,
+            expect_plan_hash: None,
+            rollback: None,
+            allow_unsafe: false,
+            keep_going: false,
+            single_transaction: false,
+            retry: 0,
+            show_sql_on_error: false,
+            strict_hash: false,
+            analyze: false,
+            file: Some(file.to_str().unwrap().to_string()),
+            record,
+            export_plan: None,
+            apply_plan: None,
+            dump_applied: None,
+            restore_applied: None,
+            non_interactive: false,
+        };
+        let path = file.to_str().unwrap();
+
+        let unrecorded = db_conf("unrecorded.sqlite3");
+        apply_migration_file(&unrecorded, path, &options(false))
+            .await
+            .unwrap();
+        let pool = connect(&unrecorded).await.unwrap();
+        query("SELECT id FROM foo;").execute(&pool).await.unwrap();
+        assert!(
+            !bookkeeping_table_exists(DBImpl::SQLite, &pool, "_drorm__last_migration")
+                .await
+                .unwrap()
+        );
+        pool.close().await;
+
+        let recorded = db_conf("recorded.sqlite3");
+        apply_migration_file(&recorded, path, &options(true))
+            .await
+            .unwrap();
+        let pool = connect(&recorded).await.unwrap();
+        let applied: Vec<String> = query("SELECT migration_name FROM _drorm__last_migration;")
+            .map(|x: AnyRow| x.get(0))
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, vec!["0007_debug"]);
+        pool.close().await;
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn rewritten_plan_file() {
+        let dir = std::env::temp_dir().join(format!("drorm_plan_file_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: dir.join("db.sqlite3").to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+        let plan = Migrator::from_embedded(&[(
+            "0001_initial.toml",
+            r#"[Migration]
+Hash = ""
+Initial = true
+Dependency = ""
+Replaces = []
+
+[[Migration.Operations]]
+Type = "CreateModel"
+Name = "foo"
+
+[[Migration.Operations.Fields]]
+Name = "id"
+Type = "int64"
+Annotations = [{ Type = "primary_key" }]
+"#,
+        )])
+        .unwrap()
+        .export_plan(&db_conf)
+        .await
+        .unwrap();
+        let approved = plan.plan_hash.clone();
+
+        // The operations are rewritten with a consistent plan hash and checksum
+        let mut rewritten = plan.clone();
+        rewritten.migrations[0].migration.operations = vec![];
+        rewritten.plan_hash = plan_hash(&rewritten.migrations().iter().collect::<Vec<_>>());
+        let path = dir.join("plan.bin");
+        std::fs::write(&path, rewritten.to_bytes()).unwrap();
+        let path = path.to_str().unwrap();
+        assert!(read_plan_file(path, None).is_ok());
+        assert!(matches!(
+            read_plan_file(path, Some(approved.as_str()))
+                .unwrap_err()
+                .downcast::<MigrationError>(),
+            Ok(MigrationError::PlanHashMismatch { expected, .. }) if expected == approved
+        ));
+
+        std::fs::write(dir.join("plan.bin"), plan.to_bytes()).unwrap();
+        assert!(read_plan_file(path, Some(approved.as_str())).is_ok());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn duplicate_bookkeeping_rows() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_duplicates_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+        let table = db_conf.last_migration_table_name.as_str();
+        let pool = connect(&db_conf).await.unwrap();
+
+        // A table of an older version without the unique index
+        pool.execute("CREATE TABLE _drorm__last_migration (id INTEGER PRIMARY KEY AUTOINCREMENT, updated_at TEXT, migration_name VARCHAR(255) NOT NULL);")
+            .await
+            .unwrap();
+        pool.execute("INSERT INTO _drorm__last_migration (migration_name) VALUES ('0001_initial'), ('0002_user_age'), ('0001_initial');")
+            .await
+            .unwrap();
+        let err = create_bookkeeping_table(DBImpl::SQLite, &pool, table)
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("records migrations more than once: 0001_initial (2 rows)."));
+
+        pool.execute("DELETE FROM _drorm__last_migration WHERE id = 3;")
+            .await
+            .unwrap();
+        create_bookkeeping_table(DBImpl::SQLite, &pool, table)
+            .await
+            .unwrap();
+        assert!(pool
+            .execute("INSERT INTO _drorm__last_migration (migration_name) VALUES ('0001_initial');")
+            .await
+            .is_err());
+
+        pool.close().await;
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,396 @@
+use std::fmt::{Display, Formatter};
+
+use rorm_sql::imr::Dialect;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::declaration::Migration;
+use crate::migrate::error::MigrationError;
+use crate::utils::output::{paint, Style};
+
+/// Version of the JSON representation of [Plan], increased on breaking changes
+pub const PLAN_VERSION: u32 = 1;
+
+/// Bytes every file written by [PlanFile::to_bytes] starts with
+pub const PLAN_FILE_MAGIC: &[u8; 10] = b"DRORMPLAN\0";
+
+/// Version of the format of [PlanFile], increased on breaking changes
+pub const PLAN_FILE_VERSION: u32 = 1;
+
+/**
+The ordered list of migrations, which are applied by the next migrate run.
+*/
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct Plan {
+    /// Version of the representation, see [PLAN_VERSION]
+    pub version: u32,
+    /// The dialect the migrations are applied with
+    pub dialect: Dialect,
+    /// The last migration recorded as applied
+    pub last_applied: Option<String>,
+    /// The pending migrations in the order they are applied in
+    pub migrations: Vec<PlannedMigration>,
+    /// Hash of the pending migrations, see [plan_hash]
+    pub plan_hash: String,
+}
+
+/**
+Calculates the hash of a plan, which changes if a pending migration is added,
+removed, reordered or modified, e.g. its operations, precondition or lock timeout.
+
+The hash is the hex encoded SHA-256 of the ids and the JSON of the migrations.
+The hash of the models and the audit trail of a migration are left out,
+so editing them doesn't change the plan.
+
+`migrations`: [&\[&Migration\]]: The pending migrations in the order they are applied in.
+*/
+pub fn plan_hash(migrations: &[&Migration]) -> String {
+    let mut hasher = Sha256::new();
+    for migration in migrations {
+        // Operations contain no maps with non-string keys, so serializing them can't fail
+        let mut value = serde_json::to_value(migration).unwrap_or_default();
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("Hash");
+            fields.remove("Meta");
+        }
+        let migration_json = value.to_string();
+        // The length prefixes keep the boundaries unambiguous
+        for part in [migration.id.as_str(), migration_json.as_str()] {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part.as_bytes());
+        }
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/**
+A resolved plan exported to a file, which can be applied later, e.g. from another network.
+
+Besides the pending migrations, it contains the migrations ordered before them,
+as tables rebuilt on SQLite need the state resulting from the previous migrations.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct PlanFile {
+    /// The dialect the plan is applied with
+    pub dialect: Dialect,
+    /// The last migration recorded as applied when the plan was exported
+    pub last_applied: Option<String>,
+    /// All migrations in the order they are applied in
+    pub migrations: Vec<ExportedMigration>,
+    /// Ids of the pending migrations in the order they are applied in
+    pub pending: Vec<String>,
+    /// Hash of the pending migrations, see [plan_hash]
+    pub plan_hash: String,
+}
+
+/**
+A migration of a [PlanFile].
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct ExportedMigration {
+    /// ID of the migration including its namespace, which is not part of [Migration]
+    pub id: String,
+    /// The migration
+    pub migration: Migration,
+}
+
+impl PlanFile {
+    /**
+    The migrations of the plan in the order they are applied in.
+    */
+    pub fn migrations(&self) -> Vec<Migration> {
+        self.migrations
+            .iter()
+            .map(|x| Migration {
+                id: x.id.clone(),
+                ..x.migration.clone()
+            })
+            .collect()
+    }
+
+    /**
+    Serializes the plan.
+
+    The file starts with [PLAN_FILE_MAGIC], followed by [PLAN_FILE_VERSION]
+    as big endian u32 and the SHA-256 of the payload. The payload is the plan
+    as JSON, which makes up the rest of the file.
+    */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Operations contain no maps with non-string keys, so serializing them can't fail
+        let payload = serde_json::to_vec(self).unwrap_or_default();
+
+        let mut bytes = PLAN_FILE_MAGIC.to_vec();
+        bytes.extend(PLAN_FILE_VERSION.to_be_bytes());
+        bytes.extend(Sha256::digest(&payload));
+        bytes.extend(payload);
+        bytes
+    }
+
+    /**
+    Deserializes a plan written by [PlanFile::to_bytes].
+
+    The file is refused if it is of another version, or if the checksum of its payload
+    or its plan hash doesn't match, e.g. because the file has been modified.
+
+    `bytes`: [&\[u8\]]: Content of the plan file.
+    */
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MigrationError> {
+        let invalid = |reason: &str| MigrationError::InvalidPlanFile {
+            reason: reason.to_string(),
+        };
+
+        let bytes = bytes
+            .strip_prefix(PLAN_FILE_MAGIC.as_slice())
+            .ok_or_else(|| invalid("not a plan file exported by drorm"))?;
+        if bytes.len() < 36 {
+            return Err(invalid("the file is truncated"));
+        }
+        let (version, bytes) = bytes.split_at(4);
+        let version = u32::from_be_bytes([version[0], version[1], version[2], version[3]]);
+        if version != PLAN_FILE_VERSION {
+            return Err(invalid(
+                format!(
+                    "the file has version {}, this version of drorm supports version {}",
+                    version, PLAN_FILE_VERSION
+                )
+                .as_str(),
+            ));
+        }
+        let (checksum, payload) = bytes.split_at(32);
+        if Sha256::digest(payload).as_slice() != checksum {
+            return Err(invalid(
+                "the checksum doesn't match, the file has been modified or damaged",
+            ));
+        }
+
+        let plan: PlanFile = serde_json::from_slice(payload)
+            .map_err(|err| invalid(format!("couldn't parse the plan: {}", err).as_str()))?;
+        let migrations = plan.migrations();
+        let pending =
+            plan.pending
+                .iter()
+                .map(|id| {
+                    migrations.iter().find(|x| x.id == *id).ok_or_else(|| {
+                        invalid(format!("pending migration {} is missing", id).as_str())
+                    })
+                })
+                .collect::<Result<Vec<&Migration>, MigrationError>>()?;
+        if plan_hash(&pending) != plan.plan_hash {
+            return Err(invalid(
+                "the plan hash doesn't match the pending migrations, they have been modified",
+            ));
+        }
+        Ok(plan)
+    }
+}
+
+/**
+A pending migration of a [Plan].
+*/
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct PlannedMigration {
+    /// ID of the migration including its namespace, e.g. `users/0002_add_email`
+    pub id: String,
+    /// Name of the migration without its namespace and number, e.g. `add_email`
+    pub name: String,
+    /// Number of operations applied on the dialect
+    pub operations: usize,
+    /// Whether the migration is applied in a single transaction
+    pub atomic: bool,
+    /// The migrations replaced by this migration
+    pub replaces: Vec<String>,
+    /// The description of the migration's audit trail
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl PlannedMigration {
+    /**
+    Summarizes a migration for the given dialect.
+
+    `migration`: [&Migration]: The pending migration.
+    `dialect`: [Dialect]: The dialect the migration is applied with.
+    */
+    pub fn new(migration: &Migration, dialect: Dialect) -> Self {
+        let id = migration.id.rsplit('/').next().unwrap_or_default();
+        PlannedMigration {
+            id: migration.id.clone(),
+            name: id.split_once('_').map_or(id, |(_, name)| name).to_string(),
+            operations: migration
+                .operations
+                .iter()
+                .filter(|x| x.for_dialect(dialect).is_some())
+                .count(),
+            atomic: migration.atomic,
+            replaces: migration.replaces.clone(),
+            description: migration.meta.as_ref().and_then(|x| x.description.clone()),
+        }
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.last_applied {
+            None => writeln!(
+                f,
+                "No migration has been applied to the {} database",
+                self.dialect
+            )?,
+            Some(id) => writeln!(
+                f,
+                "Last applied migration on the {} database: {}",
+                self.dialect, id
+            )?,
+        }
+
+        writeln!(f, "Plan hash: {}", self.plan_hash)?;
+
+        if self.migrations.is_empty() {
+            return write!(f, "{}", paint(Style::Success, "No pending migrations"));
+        }
+
+        write!(f, "Pending migrations:")?;
+        for migration in &self.migrations {
+            write!(
+                f,
+                "\n    {} ({} operations{}",
+                paint(Style::Warning, &migration.id),
+                migration.operations,
+                if migration.atomic { "" } else { ", not atomic" }
+            )?;
+            if !migration.replaces.is_empty() {
+                write!(f, ", replaces {}", migration.replaces.join(", "))?;
+            }
+            write!(f, ")")?;
+            if let Some(description) = &migration.description {
+                write!(f, ": {}", description)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::Dialect;
+
+    use crate::declaration::{AuditInfo, Migration, Operation, Precondition, PreconditionFailure};
+    use crate::migrate::error::MigrationError;
+    use crate::migrate::plan::{
+        plan_hash, ExportedMigration, PlanFile, PlannedMigration, PLAN_FILE_MAGIC,
+    };
+
+    fn plan_file() -> PlanFile {
+        let migration = Migration {
+            hash: "".to_string(),
+            initial: true,
+            id: "0001_initial".to_string(),
+            dependency: "".to_string(),
+            external_dependencies: vec![],
+            merges: vec![],
+            replaces: vec![],
+            atomic: true,
+            operations: vec![Operation::RunSql {
+                sql: "SELECT 1;".to_string(),
+                timeout_seconds: None,
+            }],
+            precondition: None,
+            lock_timeout_ms: None,
+            naming_policy: None,
+            meta: None,
+        };
+        PlanFile {
+            dialect: Dialect::Postgres,
+            last_applied: None,
+            plan_hash: plan_hash(&[&migration]),
+            pending: vec![migration.id.clone()],
+            migrations: vec![ExportedMigration {
+                id: migration.id.clone(),
+                migration,
+            }],
+        }
+    }
+
+    #[test]
+    fn audit_info_is_not_hashed() {
+        let plan = plan_file();
+        let mut migration = plan.migrations[0].migration.clone();
+        migration.meta = Some(AuditInfo {
+            author: Some("Jane Doe <jane@example.com>".to_string()),
+            created_at: Some("2024-05-01T12:00:00Z".to_string()),
+            description: Some("Check the connection".to_string()),
+        });
+        migration.hash = "v2:0".to_string();
+        assert_eq!(plan_hash(&[&migration]), plan.plan_hash);
+        assert_eq!(
+            PlannedMigration::new(&migration, Dialect::Postgres)
+                .description
+                .as_deref(),
+            Some("Check the connection")
+        );
+    }
+
+    #[test]
+    fn whole_migration_is_hashed() {
+        let plan = plan_file();
+        let mut migration = plan.migrations[0].migration.clone();
+        migration.precondition = Some(Precondition {
+            sql: "SELECT COUNT(*) = 0 FROM foo;".to_string(),
+            on_failure: PreconditionFailure::Error,
+        });
+        assert_ne!(plan_hash(&[&migration]), plan.plan_hash);
+
+        let mut migration = plan.migrations[0].migration.clone();
+        migration.atomic = false;
+        assert_ne!(plan_hash(&[&migration]), plan.plan_hash);
+
+        let mut migration = plan.migrations[0].migration.clone();
+        migration.lock_timeout_ms = Some(1000);
+        assert_ne!(plan_hash(&[&migration]), plan.plan_hash);
+    }
+
+    #[test]
+    fn plan_file_round_trip() {
+        let plan = plan_file();
+        let bytes = plan.to_bytes();
+        assert!(bytes.starts_with(PLAN_FILE_MAGIC));
+
+        let read = PlanFile::from_bytes(&bytes).unwrap();
+        assert_eq!(read.dialect, Dialect::Postgres);
+        assert_eq!(read.plan_hash, plan.plan_hash);
+        assert_eq!(read.migrations()[0].id, "0001_initial");
+    }
+
+    #[test]
+    fn modified_plan_file_is_refused() {
+        let invalid = |bytes: &[u8]| {
+            matches!(
+                PlanFile::from_bytes(bytes),
+                Err(MigrationError::InvalidPlanFile { .. })
+            )
+        };
+        let bytes = plan_file().to_bytes();
+
+        assert!(invalid(b"SELECT 1;"));
+        assert!(invalid(&bytes[..20]));
+
+        let mut newer = bytes.clone();
+        newer[13] = 2;
+        assert!(invalid(&newer));
+
+        // Changing the statement without updating the checksum
+        let mut tampered = bytes.clone();
+        let position = tampered.windows(8).position(|x| x == b"SELECT 1").unwrap();
+        tampered[position + 7] = b'2';
+        assert!(invalid(&tampered));
+
+        // Changing the statement and the checksum, but not the plan hash
+        let mut plan = plan_file();
+        plan.migrations[0].migration.operations = vec![];
+        assert!(invalid(&plan.to_bytes()));
+    }
+}
@@ -0,0 +1,707 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context};
+use rorm_sql::alter_table::SQLAlterTableOperation;
+use rorm_sql::imr::{Dialect, Field, InternalModelFormat, Model, ModelAnnotation};
+use rorm_sql::DBImpl;
+
+use crate::declaration::{IndexKind, Migration, Operation, TypeChange};
+use crate::make_migrations::{index_name, split_foreign_keys, unique_together_name};
+use crate::migrate::sql_builder::{on_dialect, sorted_operations};
+use crate::utils::migrations::convert_migrations_to_internal_models;
+
+/**
+State of the tables before a migration.
+
+SQLite can't add constraints to existing tables or change the types of their columns,
+so these tables are rebuilt from their state, see [rebuilt_tables].
+*/
+#[derive(Clone, Debug)]
+pub struct TableState {
+    /// The models resulting from the previous migrations
+    pub models: InternalModelFormat,
+    /// The indexes and checks created by the previous migrations on SQLite
+    pub constraints: Vec<Operation>,
+}
+
+impl TableState {
+    /**
+    Derives the state of the tables from the migrations applied before.
+
+    `migrations`: [&\[Migration\]]: The previous migrations ordered by their dependency chain.
+    */
+    pub fn from_migrations(migrations: &[Migration]) -> anyhow::Result<Self> {
+        let mut constraints = vec![];
+        for migration in migrations {
+            for operation in sorted_operations(migration) {
+                if let Some(operation) = operation.for_dialect(Dialect::SQLite) {
+                    replay_constraints(&mut constraints, operation);
+                }
+            }
+        }
+
+        Ok(TableState {
+            models: convert_migrations_to_internal_models(migrations)?,
+            constraints,
+        })
+    }
+}
+
+/**
+Helper function to apply an operation to the indexes and checks of the tables.
+
+`constraints`: [&mut Vec<Operation>]: The indexes and checks to update.
+`operation`: [&Operation]: The operation to apply.
+*/
+fn replay_constraints(constraints: &mut Vec<Operation>, operation: &Operation) {
+    match operation {
+        // Full-text indexes are ignored on SQLite
+        Operation::CreateIndex { kind, .. } if *kind != IndexKind::FullText => {
+            constraints.push(operation.clone())
+        }
+        Operation::CreateCheck { .. } => constraints.push(operation.clone()),
+        // Columns unique together and indexes are kept as the indexes they are created as
+        Operation::CreateModel {
+            name, annotations, ..
+        } => {
+            for annotation in annotations {
+                let (index, columns, unique) = match annotation {
+                    ModelAnnotation::UniqueTogether(columns) => {
+                        (unique_together_name(name.as_str(), columns), columns, true)
+                    }
+                    ModelAnnotation::Index(columns) => {
+                        (index_name(name.as_str(), columns), columns, false)
+                    }
+                    _ => continue,
+                };
+                constraints.push(Operation::CreateIndex {
+                    model: name.clone(),
+                    name: index,
+                    columns: columns.clone(),
+                    unique,
+                    concurrently: false,
+                    kind: IndexKind::Index,
+                });
+            }
+        }
+        Operation::CreateUnique {
+            model,
+            name,
+            columns,
+            ..
+        } => constraints.push(Operation::CreateIndex {
+            model: model.clone(),
+            name: name.clone(),
+            columns: columns.clone(),
+            unique: true,
+            concurrently: false,
+            kind: IndexKind::Index,
+        }),
+        Operation::DropUnique {
+            model,
+            name: dropped,
+            ..
+        }
+        | Operation::DropIndex {
+            model,
+            name: dropped,
+            ..
+        } => constraints.retain(|x| {
+            !matches!(x, Operation::CreateIndex { model: index_model, name, .. }
+                if index_model == model && name == dropped)
+        }),
+        Operation::RenameIndex {
+            model, old, new, ..
+        } => {
+            for constraint in constraints.iter_mut() {
+                if let Operation::CreateIndex {
+                    model: index_model,
+                    name,
+                    ..
+                } = constraint
+                {
+                    if index_model == model && name == old {
+                        *name = new.clone();
+                    }
+                }
+            }
+        }
+        Operation::RenameModel { old, new, .. } => {
+            for constraint in constraints.iter_mut() {
+                if let Operation::CreateIndex { model, .. } | Operation::CreateCheck { model, .. } =
+                    constraint
+                {
+                    if model == old {
+                        *model = new.clone();
+                    }
+                }
+            }
+        }
+        Operation::DropConstraint {
+            model,
+            name: dropped,
+            ..
+        } => constraints.retain(|x| {
+            !matches!(x, Operation::CreateCheck { model: check_model, name, .. }
+                if check_model == model && name == dropped)
+        }),
+        Operation::DeleteModel { name, .. } => {
+            constraints.retain(|x| constraint_model(x) != name);
+        }
+        Operation::RenameField {
+            table_name,
+            old,
+            new,
+        } => rename_index_columns(constraints, table_name, &[(old.clone(), new.clone())]),
+        Operation::RenameFields {
+            table_name,
+            renames,
+        } => rename_index_columns(constraints, table_name, renames),
+        Operation::DeleteField { model, name, .. } => constraints.retain(|x| {
+            !matches!(x, Operation::CreateIndex { model: index_model, columns, .. }
+                if index_model == model && columns.contains(name))
+        }),
+        _ => {}
+    }
+}
+
+/**
+Helper function to rename the columns of the indexes of a table.
+
+`constraints`: [&mut \[Operation\]]: The indexes and checks to update.
+`table`: [&str]: Name of the table whose columns are renamed.
+`renames`: [&\[(String, String)\]]: Pairs of old and new names, applied at once.
+*/
+fn rename_index_columns(constraints: &mut [Operation], table: &str, renames: &[(String, String)]) {
+    for constraint in constraints.iter_mut() {
+        if let Operation::CreateIndex { model, columns, .. } = constraint {
+            if model == table {
+                for column in columns.iter_mut() {
+                    if let Some((_, new)) = renames.iter().find(|(old, _)| old == column) {
+                        *column = new.clone();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/**
+Helper function to get the table of an index or check.
+*/
+fn constraint_model(constraint: &Operation) -> &str {
+    match constraint {
+        Operation::CreateIndex { model, .. } | Operation::CreateCheck { model, .. } => {
+            model.as_str()
+        }
+        _ => "",
+    }
+}
+
+/**
+Returns the existing tables, which have to be rebuilt to apply a migration on SQLite.
+
+SQLite can't add foreign keys and checks to existing tables or change the types of
+their columns. All operations of the migration on such a table, which change its definition,
+are applied at once by a single rebuild of the table. Tables created by the
+migration include their constraints in their definition instead.
+
+`migration`: [&Migration]: The migration to apply.
+*/
+pub fn rebuilt_tables(migration: &Migration) -> Vec<&str> {
+    let created: Vec<&str> = migration
+        .operations
+        .iter()
+        .filter_map(|x| match x.for_dialect(Dialect::SQLite) {
+            Some(Operation::CreateModel { name, .. }) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let mut tables = vec![];
+    for operation in &migration.operations {
+        let table = match operation.for_dialect(Dialect::SQLite) {
+            Some(Operation::CreateForeignKey { model, .. })
+            | Some(Operation::CreateCheck { model, .. })
+            | Some(Operation::DropConstraint { model, .. }) => model,
+            Some(Operation::AlterField {
+                model,
+                field,
+                change: TypeChange::Incompatible,
+            }) if on_dialect(field, DBImpl::SQLite) => model,
+            _ => continue,
+        };
+        if !created.contains(&table.as_str()) && !tables.contains(&table.as_str()) {
+            tables.push(table.as_str());
+        }
+    }
+    tables
+}
+
+/**
+Returns the table of an operation, which is applied by a rebuild of the table.
+
+`operation`: [&Operation]: The operation on SQLite.
+*/
+pub(crate) fn rebuilt_table(operation: &Operation) -> Option<&str> {
+    match operation {
+        Operation::CreateField { model, .. }
+        | Operation::DeleteField { model, .. }
+        | Operation::AlterField { model, .. }
+        | Operation::CreateForeignKey { model, .. }
+        | Operation::CreateCheck { model, .. }
+        | Operation::DropConstraint { model, .. } => Some(model.as_str()),
+        _ => None,
+    }
+}
+
+/**
+Helper function to replay operations on the state of the tables.
+
+`state`: [&TableState]: The state before the operations.
+`operations`: [Vec<Operation>]: The operations to apply.
+*/
+fn replay(state: &TableState, operations: Vec<Operation>) -> anyhow::Result<InternalModelFormat> {
+    let migration = |operations| Migration {
+        hash: "".to_string(),
+        initial: false,
+        id: "".to_string(),
+        dependency: "".to_string(),
+        external_dependencies: vec![],
+        merges: vec![],
+        replaces: vec![],
+        atomic: true,
+        operations,
+        precondition: None,
+        lock_timeout_ms: None,
+        naming_policy: None,
+        meta: None,
+    };
+    let existing = state
+        .models
+        .models
+        .iter()
+        .map(|x| Operation::CreateModel {
+            name: x.name.clone(),
+            fields: x.fields.clone(),
+            annotations: x.annotations.clone(),
+        })
+        .collect();
+
+    convert_migrations_to_internal_models(&[migration(existing), migration(operations)])
+}
+
+/**
+Plans the rebuilds of the tables returned by [rebuilt_tables].
+
+Each table is rebuilt in place of the last of the operations it applies, all other
+operations it applies don't result in any statement. The returned map contains the
+position of this operation in the sorted operations and the statements of the rebuild.
+
+`migration`: [&Migration]: The migration to apply.
+`operations`: [&\[&Operation\]]: The operations of the migration sorted into their phases.
+`state`: [Option<&TableState>]: The state before the migration.
+*/
+pub(crate) fn rebuild_statements(
+    migration: &Migration,
+    operations: &[&Operation],
+    state: Option<&TableState>,
+) -> anyhow::Result<HashMap<usize, Vec<String>>> {
+    let mut rebuilds = HashMap::new();
+    let tables = rebuilt_tables(migration);
+    if tables.is_empty() {
+        return Ok(rebuilds);
+    }
+    if !migration.atomic {
+        return Err(anyhow!(
+            "Migration {} rebuilds table {} on SQLite, which requires Atomic = true",
+            migration.id,
+            tables[0]
+        ));
+    }
+    let state = state.ok_or_else(|| {
+        anyhow!(
+            "Migration {} rebuilds table {} on SQLite, which requires the state of the previous migrations",
+            migration.id,
+            tables[0]
+        )
+    })?;
+
+    let sqlite: Vec<Option<&Operation>> = operations
+        .iter()
+        .map(|x| x.for_dialect(Dialect::SQLite))
+        .collect();
+    for table in tables {
+        let applied: Vec<usize> = (0..sqlite.len())
+            .filter(|x| sqlite[*x].and_then(rebuilt_table) == Some(table))
+            .collect();
+        let (first, last) = (applied[0], applied[applied.len() - 1]);
+
+        for operation in sqlite[first..last].iter().flatten() {
+            if matches!(operation, Operation::RenameField { table_name, .. } | Operation::RenameFields { table_name, .. } if table_name == table)
+            {
+                return Err(anyhow!(
+                    "Migration {} renames fields of table {} between operations rebuilding the table on SQLite, move the renames into their own migration",
+                    migration.id,
+                    table
+                ));
+            }
+        }
+
+        // The operations applied by the rebuild have not been executed before it
+        let before = replay(
+            state,
+            (0..last)
+                .filter(|x| !applied.contains(x))
+                .filter_map(|x| sqlite[x].cloned())
+                .collect(),
+        )?;
+        let after = replay(
+            state,
+            sqlite[..=last].iter().flatten().cloned().cloned().collect(),
+        )?;
+        let mut constraints = state.constraints.clone();
+        for operation in sqlite[..=last].iter().flatten() {
+            replay_constraints(&mut constraints, operation);
+        }
+
+        let find = |models: &InternalModelFormat| {
+            models
+                .models
+                .iter()
+                .find(|x| x.name == table)
+                .cloned()
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Migration {} rebuilds table {}, which does not exist",
+                        migration.id,
+                        table
+                    )
+                })
+        };
+        let (old, new) = (find(&before)?, find(&after)?);
+
+        rebuilds.insert(
+            last,
+            table_rebuild(&old.fields, &new, &constraints).with_context(|| {
+                format!(
+                    "Could not build rebuild of table {} for migration {}",
+                    table, migration.id
+                )
+            })?,
+        );
+    }
+    Ok(rebuilds)
+}
+
+/**
+Helper function to build the statements rebuilding a table on SQLite.
+
+The existing table is renamed and its rows are copied into the new table.
+References of other tables are kept on the name of the table, as `legacy_alter_table`
+prevents renaming them, so they refer to the new table afterwards.
+Triggers are created after the rows have been copied, so they don't fire on the copy.
+
+`old`: [&\[Field\]]: The columns of the existing table.
+`new`: [&Model]: The new definition of the table.
+`constraints`: [&\[Operation\]]: The indexes and checks of all tables.
+*/
+fn table_rebuild(
+    old: &[Field],
+    new: &Model,
+    constraints: &[Operation],
+) -> anyhow::Result<Vec<String>> {
+    let db_impl = DBImpl::SQLite;
+    let table = new.name.as_str();
+    let temporary = format!("{}__drorm_old", table);
+
+    let fields: Vec<_> = new
+        .fields
+        .iter()
+        .filter(|x| on_dialect(x, db_impl))
+        .cloned()
+        .collect();
+    let (fields, foreign_keys) = split_foreign_keys(table, &fields);
+
+    let mut create_table = db_impl.create_table(table);
+    for annotation in &new.annotations {
+        if let ModelAnnotation::Persistence(p) = annotation {
+            create_table = create_table.persistence(*p);
+        }
+    }
+    for field in &fields {
+        create_table = create_table.add_column(db_impl.create_column(
+            table,
+            field.name.as_str(),
+            field.db_type.clone(),
+            field.annotations.clone(),
+        ));
+    }
+    for foreign_key in &foreign_keys {
+        if let Some(Operation::CreateForeignKey {
+            name,
+            column,
+            foreign_key,
+            ..
+        }) = foreign_key.for_dialect(Dialect::SQLite)
+        {
+            create_table = create_table.add_foreign_key(db_impl.foreign_key(
+                name.as_str(),
+                column.as_str(),
+                foreign_key.clone(),
+            ));
+        }
+    }
+    for constraint in constraints {
+        if let Operation::CreateCheck {
+            model,
+            name,
+            expression,
+            ..
+        } = constraint
+        {
+            if model == table {
+                create_table = create_table.add_check(name.as_str(), expression.as_str());
+            }
+        }
+    }
+    let (create_table, triggers) = create_table.build_with_triggers()?;
+
+    // Columns created by the rebuild get their defaults
+    let copied: Vec<&str> = fields
+        .iter()
+        .filter(|x| {
+            old.iter()
+                .any(|y| y.name == x.name && on_dialect(y, db_impl))
+        })
+        .map(|x| x.name.as_str())
+        .collect();
+
+    let mut statements = vec!["PRAGMA legacy_alter_table = ON;".to_string()];
+    statements.push(
+        db_impl
+            .alter_table(
+                table,
+                SQLAlterTableOperation::RenameTo {
+                    name: temporary.clone(),
+                },
+            )
+            .build()?,
+    );
+    statements.push(create_table);
+    if !copied.is_empty() {
+        statements.push(format!(
+            "INSERT INTO {} ({}) SELECT {} FROM {};",
+            table,
+            copied.join(", "),
+            copied.join(", "),
+            temporary
+        ));
+    }
+    statements.push(db_impl.drop_table(temporary.as_str()).build()?);
+    statements.extend(triggers);
+    for constraint in constraints {
+        if let Operation::CreateIndex {
+            model,
+            name,
+            columns,
+            unique,
+            ..
+        } = constraint
+        {
+            if model == table {
+                let mut create_index = db_impl.create_index(name.as_str(), table);
+                if *unique {
+                    create_index = create_index.unique();
+                }
+                for column in columns {
+                    create_index = create_index.add_column(column.as_str());
+                }
+                statements.push(create_index.build()?);
+            }
+        }
+    }
+    statements.push("PRAGMA legacy_alter_table = OFF;".to_string());
+
+    Ok(statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{
+        Annotation, DbType, Field, ForeignKey, InternalModelFormat, Model, ReferentialAction,
+    };
+    use rorm_sql::DBImpl;
+
+    use crate::declaration::{IndexKind, Migration, Operation, TypeChange};
+    use crate::migrate::rebuild::{rebuilt_tables, TableState};
+    use crate::migrate::sql_builder::{migration_to_sql, operation_statements, BuildOptions};
+    use crate::utils::test_helpers;
+
+    fn migration(operations: Vec<Operation>) -> Migration {
+        test_helpers::migration("0002_test", "0001_initial", operations)
+    }
+
+    fn field(name: &str, db_type: DbType, annotations: Vec<Annotation>) -> Field {
+        Field {
+            name: name.to_string(),
+            db_type,
+            annotations,
+            source_defined_at: None,
+        }
+    }
+
+    #[test]
+    fn single_rebuild() {
+        let state = TableState {
+            models: InternalModelFormat {
+                models: vec![Model {
+                    name: "user".to_string(),
+                    fields: vec![
+                        field("id", DbType::Int64, vec![Annotation::PrimaryKey]),
+                        field("age", DbType::Int64, vec![Annotation::NotNull]),
+                    ],
+                    annotations: vec![],
+                    source_defined_at: None,
+                }],
+            },
+            constraints: vec![Operation::CreateIndex {
+                model: "user".to_string(),
+                name: "user_age_idx".to_string(),
+                columns: vec!["age".to_string()],
+                unique: false,
+                concurrently: false,
+                kind: IndexKind::Index,
+            }],
+        };
+        let m = migration(vec![
+            Operation::CreateField {
+                model: "user".to_string(),
+                field: field("name", DbType::VarChar, vec![Annotation::MaxLength(255)]),
+            },
+            Operation::AlterField {
+                model: "user".to_string(),
+                field: field("age", DbType::VarChar, vec![Annotation::NotNull]),
+                change: TypeChange::Incompatible,
+            },
+            Operation::CreateCheck {
+                model: "user".to_string(),
+                name: "user_age_check".to_string(),
+                expression: "age <> ''".to_string(),
+                not_valid: false,
+            },
+        ]);
+        assert_eq!(rebuilt_tables(&m), vec!["user"]);
+
+        let options = BuildOptions {
+            state: Some(state),
+            ..Default::default()
+        };
+        let statements = operation_statements(DBImpl::SQLite, &m, &options).unwrap();
+        // The last operation rebuilds the table, the others are part of it
+        assert!(statements[0].1.is_empty() && statements[1].1.is_empty());
+        assert_eq!(
+            statements[2].1,
+            vec![
+                "PRAGMA legacy_alter_table = ON;",
+                "ALTER TABLE user RENAME TO user__drorm_old;",
+                "CREATE TABLE user (id INTEGER PRIMARY KEY,age TEXT NOT NULL,name TEXT,CONSTRAINT user_age_check CHECK (age <> '')) STRICT;",
+                "INSERT INTO user (id, age) SELECT id, age FROM user__drorm_old;",
+                "DROP TABLE user__drorm_old;",
+                "CREATE INDEX user_age_idx ON user (age);",
+                "PRAGMA legacy_alter_table = OFF;",
+            ]
+        );
+
+        // The state of the previous migrations is required
+        assert!(migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).is_err());
+        // Other dialects alter the table in place
+        assert!(
+            !migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default())
+                .unwrap()
+                .contains("RENAME")
+        );
+    }
+
+    #[test]
+    fn dropped_constraints() {
+        let state = TableState {
+            models: InternalModelFormat {
+                models: vec![Model {
+                    name: "user".to_string(),
+                    fields: vec![
+                        field("id", DbType::Int64, vec![Annotation::PrimaryKey]),
+                        field(
+                            "parent",
+                            DbType::Int64,
+                            vec![Annotation::ForeignKey(ForeignKey {
+                                table_name: "user".to_string(),
+                                column_name: "id".to_string(),
+                                deferrable: false,
+                                initially_deferred: false,
+                                on_delete: ReferentialAction::Restrict,
+                                on_update: ReferentialAction::Restrict,
+                            })],
+                        ),
+                    ],
+                    annotations: vec![],
+                    source_defined_at: None,
+                }],
+            },
+            constraints: vec![Operation::CreateCheck {
+                model: "user".to_string(),
+                name: "user_id_check".to_string(),
+                expression: "id > 0".to_string(),
+                not_valid: false,
+            }],
+        };
+        let m = migration(vec![
+            Operation::DropConstraint {
+                model: "user".to_string(),
+                name: "user_parent_fkey".to_string(),
+                column: Some("parent".to_string()),
+            },
+            Operation::DropConstraint {
+                model: "user".to_string(),
+                name: "user_id_check".to_string(),
+                column: None,
+            },
+        ]);
+        assert_eq!(rebuilt_tables(&m), vec!["user"]);
+
+        let options = BuildOptions {
+            state: Some(state),
+            ..Default::default()
+        };
+        let statements = operation_statements(DBImpl::SQLite, &m, &options).unwrap();
+        assert!(statements[1].1.contains(
+            &"CREATE TABLE user (id INTEGER PRIMARY KEY,parent INTEGER) STRICT;".to_string()
+        ));
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; ALTER TABLE user DROP CONSTRAINT user_parent_fkey; ALTER TABLE user DROP CONSTRAINT user_id_check; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn created_tables_are_not_rebuilt() {
+        let m = migration(vec![
+            Operation::CreateModel {
+                name: "user".to_string(),
+                fields: vec![field("id", DbType::Int64, vec![Annotation::PrimaryKey])],
+                annotations: vec![],
+            },
+            Operation::CreateCheck {
+                model: "user".to_string(),
+                name: "user_id_check".to_string(),
+                expression: "id > 0".to_string(),
+                not_valid: false,
+            },
+        ]);
+        assert!(rebuilt_tables(&m).is_empty());
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE TABLE user (id INTEGER PRIMARY KEY,CONSTRAINT user_id_check CHECK (id > 0)) STRICT; COMMIT;"
+        );
+    }
+}
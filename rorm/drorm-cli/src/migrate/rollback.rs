@@ -0,0 +1,585 @@
+use anyhow::anyhow;
+use rorm_sql::imr::{Annotation, InternalModelFormat, ModelAnnotation, StorageParameter};
+
+use crate::declaration::{EnumAction, IndexKind, Migration, Operation, TypeChange};
+use crate::migrate::error::MigrationError;
+use crate::migrate::sql_builder::sorted_operations;
+use crate::utils::migrations::convert_migrations_to_internal_models;
+
+/**
+Resolves the migrations a rollback reverses, the most recently applied one first.
+
+A migration replacing other migrations, e.g. a squash, is reversed as a whole
+if any of the migrations it replaces is recorded.
+
+`migrations`: [&\[Migration\]]: The migrations ordered by their dependency chain.
+`applied`: [&\[String\]]: The applied migrations in the order they have been recorded.
+`count`: [usize]: Number of migrations to reverse.
+`table`: [&str]: Name of the last migration table, used for errors.
+*/
+pub fn rollback_targets<'a>(
+    migrations: &'a [Migration],
+    applied: &[String],
+    count: usize,
+    table: &str,
+) -> Result<Vec<&'a Migration>, MigrationError> {
+    let mut targets: Vec<&Migration> = vec![];
+    for id in applied.iter().rev() {
+        if targets.len() == count {
+            break;
+        }
+        let migration = migrations
+            .iter()
+            .find(|x| x.id == *id)
+            .or_else(|| migrations.iter().find(|x| x.replaces.contains(id)))
+            .ok_or_else(|| MigrationError::UnknownAppliedMigration {
+                migration: id.clone(),
+                table: table.to_string(),
+            })?;
+        if !targets.iter().any(|x| x.id == migration.id) {
+            targets.push(migration);
+        }
+    }
+
+    if targets.len() < count {
+        return Err(MigrationError::RollbackCount {
+            count,
+            applied: targets.len(),
+        });
+    }
+    Ok(targets)
+}
+
+/**
+Derives the operations which revert a migration.
+
+The operations are derived from the state before each operation,
+e.g. a deleted field is created again with its previous definition.
+The data removed by the migration can't be restored.
+
+If any of the operations can't be reversed, e.g. full-text indexes and data changes,
+the first of them is returned as `Err`.
+
+`previous`: [&\[Migration\]]: The migrations applied before the migration.
+`migration`: [&Migration]: The migration to revert.
+*/
+pub fn down_operations(
+    previous: &[Migration],
+    migration: &Migration,
+) -> anyhow::Result<Result<Vec<Operation>, Operation>> {
+    let operations: Vec<Operation> = sorted_operations(migration).into_iter().cloned().collect();
+
+    let mut down = vec![];
+    for index in 0..operations.len() {
+        let mut chain = previous.to_vec();
+        chain.push(Migration {
+            operations: operations[..index].to_vec(),
+            ..migration.clone()
+        });
+        let state = convert_migrations_to_internal_models(&chain)?;
+
+        match reverse_operation(&state, &operations[index])? {
+            Some(operation) => down.push(operation),
+            None => return Ok(Err(operations[index].clone())),
+        }
+    }
+    down.reverse();
+    Ok(Ok(down))
+}
+
+/**
+Helper method to derive the operation reverting a single operation.
+
+`state`: [&InternalModelFormat]: The models before the operation.
+`operation`: [&Operation]: The operation to revert.
+*/
+fn reverse_operation(
+    state: &InternalModelFormat,
+    operation: &Operation,
+) -> anyhow::Result<Option<Operation>> {
+    let model = |name: &str| {
+        state
+            .models
+            .iter()
+            .find(|x| x.name == name)
+            .ok_or_else(|| anyhow!("Model {} doesn't exist", name))
+    };
+    let identity = |model_name: &str, column: &str| {
+        model(model_name)?
+            .fields
+            .iter()
+            .find(|x| x.name == column)
+            .ok_or_else(|| anyhow!("Field {}.{} doesn't exist", model_name, column))
+            .map(|field| {
+                field.annotations.iter().find_map(|x| match x {
+                    Annotation::Identity(identity) => Some(*identity),
+                    _ => None,
+                })
+            })
+    };
+
+    Ok(Some(match operation {
+        Operation::CreateModel { name, .. } => Operation::DeleteModel {
+            name: name.clone(),
+            cascade: false,
+        },
+        Operation::RenameModel {
+            old,
+            new,
+            rename_constraints,
+        } => Operation::RenameModel {
+            old: new.clone(),
+            new: old.clone(),
+            rename_constraints: *rename_constraints,
+        },
+        Operation::DeleteModel { name, .. } => {
+            let model = model(name)?;
+            Operation::CreateModel {
+                name: model.name.clone(),
+                fields: model.fields.clone(),
+                annotations: model.annotations.clone(),
+            }
+        }
+        Operation::CreateField { model, field } => Operation::DeleteField {
+            model: model.clone(),
+            name: field.name.clone(),
+            cascade: false,
+        },
+        Operation::RenameField {
+            table_name,
+            old,
+            new,
+        } => Operation::RenameField {
+            table_name: table_name.clone(),
+            old: new.clone(),
+            new: old.clone(),
+        },
+        Operation::RenameFields {
+            table_name,
+            renames,
+        } => Operation::RenameFields {
+            table_name: table_name.clone(),
+            renames: renames
+                .iter()
+                .map(|(old, new)| (new.clone(), old.clone()))
+                .collect(),
+        },
+        Operation::DeleteField {
+            model: model_name,
+            name,
+            ..
+        } => Operation::CreateField {
+            model: model_name.clone(),
+            field: model(model_name)?
+                .fields
+                .iter()
+                .find(|x| x.name == *name)
+                .ok_or_else(|| anyhow!("Field {}.{} doesn't exist", model_name, name))?
+                .clone(),
+        },
+        Operation::AlterField {
+            model: model_name,
+            field,
+            ..
+        } => {
+            let old = model(model_name)?
+                .fields
+                .iter()
+                .find(|x| x.name == field.name)
+                .ok_or_else(|| anyhow!("Field {}.{} doesn't exist", model_name, field.name))?;
+            match TypeChange::classify(field, old) {
+                Some(change) => Operation::AlterField {
+                    model: model_name.clone(),
+                    field: old.clone(),
+                    change,
+                },
+                None => return Ok(None),
+            }
+        }
+        // Full-text indexes aren't created on every dialect, so dropping them may fail
+        Operation::CreateIndex {
+            model,
+            name,
+            columns,
+            kind: IndexKind::Index,
+            ..
+        } => Operation::DropIndex {
+            model: model.clone(),
+            name: name.clone(),
+            columns: columns.clone(),
+        },
+        Operation::DropIndex {
+            model,
+            name,
+            columns,
+        } => Operation::CreateIndex {
+            model: model.clone(),
+            name: name.clone(),
+            columns: columns.clone(),
+            unique: false,
+            concurrently: false,
+            kind: IndexKind::Index,
+        },
+        Operation::RenameIndex {
+            model,
+            old,
+            new,
+            columns,
+            unique,
+        } => Operation::RenameIndex {
+            model: model.clone(),
+            old: new.clone(),
+            new: old.clone(),
+            columns: columns.clone(),
+            unique: *unique,
+        },
+        Operation::RenameConstraint {
+            model,
+            old,
+            new,
+            definition,
+        } => Operation::RenameConstraint {
+            model: model.clone(),
+            old: new.clone(),
+            new: old.clone(),
+            definition: definition.clone(),
+        },
+        // Added values may be used by rows already, so only renames are reversed
+        Operation::AlterEnum {
+            model: model_name,
+            field,
+            action: EnumAction::Rename { old, new },
+        } => Operation::AlterEnum {
+            model: model_name.clone(),
+            field: model(model_name)?
+                .fields
+                .iter()
+                .find(|x| x.name == field.name)
+                .ok_or_else(|| anyhow!("Field {}.{} doesn't exist", model_name, field.name))?
+                .clone(),
+            action: EnumAction::Rename {
+                old: new.clone(),
+                new: old.clone(),
+            },
+        },
+        Operation::CreateForeignKey {
+            model,
+            name,
+            column,
+            ..
+        } => Operation::DropConstraint {
+            model: model.clone(),
+            name: name.clone(),
+            column: Some(column.clone()),
+        },
+        Operation::CreateCheck { model, name, .. } => Operation::DropConstraint {
+            model: model.clone(),
+            name: name.clone(),
+            column: None,
+        },
+        // The serial sequence replaced by an identity isn't restored
+        Operation::AddIdentity {
+            model,
+            column,
+            from_serial: false,
+            ..
+        } => Operation::DropIdentity {
+            model: model.clone(),
+            column: column.clone(),
+        },
+        Operation::SetIdentity { model, column, .. } => match identity(model, column)? {
+            Some(identity) => Operation::SetIdentity {
+                model: model.clone(),
+                column: column.clone(),
+                identity,
+            },
+            None => return Ok(None),
+        },
+        Operation::DropIdentity { model, column } => match identity(model, column)? {
+            Some(identity) => Operation::AddIdentity {
+                model: model.clone(),
+                column: column.clone(),
+                identity,
+                from_serial: false,
+            },
+            None => return Ok(None),
+        },
+        Operation::SetTablespace {
+            model: model_name, ..
+        } => Operation::SetTablespace {
+            model: model_name.clone(),
+            tablespace: model(model_name)?.annotations.iter().find_map(|x| match x {
+                ModelAnnotation::Tablespace(tablespace) => Some(tablespace.clone()),
+                _ => None,
+            }),
+        },
+        Operation::SetCharset {
+            model: model_name, ..
+        } => {
+            let annotations = &model(model_name)?.annotations;
+            Operation::SetCharset {
+                model: model_name.clone(),
+                charset: annotations.iter().find_map(|x| match x {
+                    ModelAnnotation::Charset(charset) => Some(charset.clone()),
+                    _ => None,
+                }),
+                collation: annotations.iter().find_map(|x| match x {
+                    ModelAnnotation::Collation(collation) => Some(collation.clone()),
+                    _ => None,
+                }),
+            }
+        }
+        // Parameters which haven't been set before are reset to their defaults
+        Operation::SetStorageParameters {
+            model: model_name,
+            set,
+            reset,
+        } => {
+            let previous: Vec<StorageParameter> = model(model_name)?
+                .annotations
+                .iter()
+                .filter_map(|x| match x {
+                    ModelAnnotation::StorageParameter(parameter) => Some(parameter.clone()),
+                    _ => None,
+                })
+                .collect();
+            Operation::SetStorageParameters {
+                model: model_name.clone(),
+                set: previous
+                    .iter()
+                    .filter(|x| set.iter().any(|y| y.name == x.name) || reset.contains(&x.name))
+                    .cloned()
+                    .collect(),
+                reset: set
+                    .iter()
+                    .filter(|x| !previous.iter().any(|y| y.name == x.name))
+                    .map(|x| x.name.clone())
+                    .collect(),
+            }
+        }
+        // The bound of a detached partition isn't known, so only attaching is reversed
+        Operation::AttachPartition {
+            model, partition, ..
+        } => Operation::DetachPartition {
+            model: model.clone(),
+            partition: partition.clone(),
+        },
+        Operation::SetInherit { model, parent } => Operation::NoInherit {
+            model: model.clone(),
+            parent: parent.clone(),
+        },
+        Operation::NoInherit { model, parent } => Operation::SetInherit {
+            model: model.clone(),
+            parent: parent.clone(),
+        },
+        Operation::CreateUnique {
+            model,
+            name,
+            columns,
+            ..
+        } => Operation::DropUnique {
+            model: model.clone(),
+            name: name.clone(),
+            columns: columns.clone(),
+        },
+        // The models only record the columns of unique constraints, so the default
+        // distinct null values are restored
+        Operation::DropUnique {
+            model,
+            name,
+            columns,
+        } => Operation::CreateUnique {
+            model: model.clone(),
+            name: name.clone(),
+            columns: columns.clone(),
+            nulls_not_distinct: false,
+        },
+        // An extension created with IfNotExists may have been installed before
+        Operation::CreateExtension {
+            name,
+            if_not_exists: false,
+        } => Operation::DropExtension {
+            name: name.clone(),
+            if_exists: false,
+        },
+        Operation::DropExtension { name, .. } => Operation::CreateExtension {
+            name: name.clone(),
+            if_not_exists: true,
+        },
+        Operation::Only {
+            dialects,
+            operation,
+        } => match reverse_operation(state, operation)? {
+            Some(operation) => Operation::Only {
+                dialects: dialects.clone(),
+                operation: Box::new(operation),
+            },
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{DbType, Field, ForeignKey, Identity, ReferentialAction};
+
+    use crate::declaration::{Migration, Operation};
+    use crate::migrate::error::MigrationError;
+    use crate::migrate::rollback::{down_operations, rollback_targets};
+    use crate::utils::test_helpers;
+
+    fn migration(id: &str, replaces: &[&str], operations: Vec<Operation>) -> Migration {
+        Migration {
+            initial: false,
+            replaces: replaces.iter().map(|x| x.to_string()).collect(),
+            ..test_helpers::migration(id, "", operations)
+        }
+    }
+
+    fn field(name: &str) -> Field {
+        Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        }
+    }
+
+    #[test]
+    fn targets() {
+        let migrations = vec![
+            migration("0001_a", &[], vec![]),
+            migration("0002_squash", &["0002_b", "0003_c"], vec![]),
+            migration("0004_d", &[], vec![]),
+        ];
+        let applied: Vec<String> = ["0001_a", "0002_b", "0003_c", "0004_d"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let ids = |count: usize| {
+            rollback_targets(&migrations, &applied, count, "_drorm__last_migration")
+                .map(|x| x.iter().map(|y| y.id.as_str()).collect::<Vec<&str>>())
+        };
+
+        assert_eq!(ids(1).unwrap(), vec!["0004_d"]);
+        assert_eq!(ids(2).unwrap(), vec!["0004_d", "0002_squash"]);
+        assert_eq!(ids(3).unwrap(), vec!["0004_d", "0002_squash", "0001_a"]);
+        assert!(matches!(
+            ids(4),
+            Err(MigrationError::RollbackCount {
+                count: 4,
+                applied: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn down() {
+        let initial = migration(
+            "0001_a",
+            &[],
+            vec![Operation::CreateModel {
+                name: "user".to_string(),
+                fields: vec![field("id"), field("age")],
+                annotations: vec![],
+            }],
+        );
+        let second = migration(
+            "0002_b",
+            &[],
+            vec![
+                Operation::RenameModel {
+                    old: "user".to_string(),
+                    new: "account".to_string(),
+                    rename_constraints: false,
+                },
+                Operation::DeleteField {
+                    model: "user".to_string(),
+                    name: "age".to_string(),
+                    cascade: false,
+                },
+            ],
+        );
+
+        let down = down_operations(std::slice::from_ref(&initial), &second)
+            .unwrap()
+            .unwrap();
+        assert_eq!(down.len(), 2);
+        assert!(matches!(
+            &down[0],
+            Operation::RenameModel { old, new, .. } if old == "account" && new == "user"
+        ));
+        // The field is deleted before the model is renamed
+        assert!(matches!(
+            &down[1],
+            Operation::CreateField { model, field } if model == "user" && field.name == "age"
+        ));
+
+        let down = down_operations(&[], &initial).unwrap().unwrap();
+        assert!(matches!(
+            down.as_slice(),
+            [Operation::DeleteModel { name, .. }] if name == "user"
+        ));
+
+        let data = migration(
+            "0003_c",
+            &[],
+            vec![Operation::RunSql {
+                sql: "UPDATE user SET age = 0;".to_string(),
+                timeout_seconds: None,
+            }],
+        );
+        assert!(matches!(
+            down_operations(std::slice::from_ref(&initial), &data).unwrap(),
+            Err(Operation::RunSql { .. })
+        ));
+
+        let constraints = migration(
+            "0003_c",
+            &[],
+            vec![
+                Operation::CreateForeignKey {
+                    model: "user".to_string(),
+                    name: "user_age_fkey".to_string(),
+                    column: "age".to_string(),
+                    foreign_key: ForeignKey {
+                        table_name: "user".to_string(),
+                        column_name: "id".to_string(),
+                        deferrable: false,
+                        initially_deferred: false,
+                        on_delete: ReferentialAction::Restrict,
+                        on_update: ReferentialAction::Restrict,
+                    },
+                    not_valid: false,
+                },
+                Operation::CreateCheck {
+                    model: "user".to_string(),
+                    name: "user_age_check".to_string(),
+                    expression: "age >= 0".to_string(),
+                    not_valid: false,
+                },
+                Operation::AddIdentity {
+                    model: "user".to_string(),
+                    column: "id".to_string(),
+                    identity: Identity::Always,
+                    from_serial: false,
+                },
+            ],
+        );
+        let down = down_operations(std::slice::from_ref(&initial), &constraints)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            down.as_slice(),
+            [
+                Operation::DropIdentity { column: identity, .. },
+                Operation::DropConstraint { name: check, column: None, .. },
+                Operation::DropConstraint { name: foreign_key, column: Some(column), .. },
+            ] if identity == "id"
+                && check == "user_age_check"
+                && foreign_key == "user_age_fkey"
+                && column == "age"
+        ));
+    }
+}
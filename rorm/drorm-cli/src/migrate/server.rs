@@ -0,0 +1,172 @@
+use rorm_sql::DBImpl;
+use sqlx::any::{AnyPool, AnyRow};
+use sqlx::{query, Row};
+
+use crate::utils::output::{info, verbosity};
+
+/**
+The database server the migrations are applied on, detected when connecting.
+
+Some statements depend on the version of the server, e.g. renaming an index
+on MySQL. The SQL generation falls back to the statements supported by all versions,
+if the version is unknown.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// The dialect of the server
+    pub db_impl: DBImpl,
+    /// The version as reported by the server, e.g. `8.0.33` or `PostgreSQL 15.3 on x86_64-pc-linux-gnu, ..`
+    pub version: String,
+}
+
+impl ServerInfo {
+    /**
+    The version number of the server, e.g. `(8, 0, 33)`.
+
+    The first word starting with a digit is parsed, missing parts are 0.
+    `None` is returned if the version doesn't contain a version number.
+    */
+    pub fn version_number(&self) -> Option<(u64, u64, u64)> {
+        let word = self
+            .version
+            .split_whitespace()
+            .find(|x| x.starts_with(|c: char| c.is_ascii_digit()))?;
+        let mut parts = word.split('.').map(|x| {
+            x.chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>()
+                .parse::<u64>()
+                .unwrap_or_default()
+        });
+        Some((
+            parts.next()?,
+            parts.next().unwrap_or_default(),
+            parts.next().unwrap_or_default(),
+        ))
+    }
+
+    /**
+    Whether the server is at least of the given version, `false` if the version is unknown.
+
+    `version`: [(u64, u64, u64)]: The minimal version.
+    */
+    pub fn at_least(&self, version: (u64, u64, u64)) -> bool {
+        self.version_number().is_some_and(|x| x >= version)
+    }
+
+    /**
+    Whether the MySQL server is a MariaDB server, whose versions differ from MySQL.
+    */
+    pub fn is_mariadb(&self) -> bool {
+        self.db_impl == DBImpl::MySQL && self.version.contains("MariaDB")
+    }
+
+    /**
+    Whether the server supports `ALTER TABLE .. RENAME INDEX`, which is MySQL specific.
+    */
+    pub fn supports_rename_index(&self) -> bool {
+        match self.db_impl {
+            DBImpl::MySQL if self.is_mariadb() => self.at_least((10, 5, 2)),
+            DBImpl::MySQL => self.at_least((5, 7, 0)),
+            DBImpl::SQLite | DBImpl::Postgres => false,
+        }
+    }
+
+    /**
+    Whether `gen_random_uuid()` is built in, which postgres provides since version 13.
+    Older servers require the pgcrypto extension.
+    */
+    pub fn supports_gen_random_uuid(&self) -> bool {
+        match self.db_impl {
+            DBImpl::Postgres => self.at_least((13, 0, 0)),
+            DBImpl::SQLite | DBImpl::MySQL => false,
+        }
+    }
+
+    /**
+    Whether the server supports unique constraints with `NULLS NOT DISTINCT`, which is postgres specific.
+    */
+    pub fn supports_nulls_not_distinct(&self) -> bool {
+        match self.db_impl {
+            DBImpl::Postgres => self.at_least((15, 0, 0)),
+            DBImpl::SQLite | DBImpl::MySQL => false,
+        }
+    }
+}
+
+/**
+Detects the version of the server the pool is connected to.
+
+A failed detection is printed as warning, the SQL generation falls back to
+the statements supported by all versions then. The detected version is printed with `-vv`.
+
+`db_impl`: [DBImpl]: The dialect of the connection.
+`pool`: [&AnyPool]: Pool connected to the server.
+*/
+pub async fn detect_server(db_impl: DBImpl, pool: &AnyPool) -> Option<ServerInfo> {
+    let statement = match db_impl {
+        DBImpl::SQLite => "SELECT sqlite_version();",
+        DBImpl::Postgres | DBImpl::MySQL => "SELECT version();",
+    };
+
+    match query(statement)
+        .map(|x: AnyRow| x.get::<String, _>(0))
+        .fetch_one(pool)
+        .await
+    {
+        Ok(version) => {
+            if verbosity() >= 2 {
+                info!("Detected {:?} server version {}", db_impl, version);
+            }
+            Some(ServerInfo { db_impl, version })
+        }
+        Err(err) => {
+            info!(
+                "Warning: couldn't detect the server version, version dependent statements use their fallback: {}",
+                err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::DBImpl;
+
+    use crate::migrate::server::ServerInfo;
+
+    fn server(db_impl: DBImpl, version: &str) -> ServerInfo {
+        ServerInfo {
+            db_impl,
+            version: version.to_string(),
+        }
+    }
+
+    #[test]
+    fn version_detection() {
+        assert_eq!(
+            server(
+                DBImpl::Postgres,
+                "PostgreSQL 15.3 (Debian 15.3-1.pgdg120+1) on x86_64-pc-linux-gnu"
+            )
+            .version_number(),
+            Some((15, 3, 0))
+        );
+        assert_eq!(
+            server(DBImpl::SQLite, "3.42.0").version_number(),
+            Some((3, 42, 0))
+        );
+        assert_eq!(server(DBImpl::MySQL, "unknown").version_number(), None);
+
+        assert!(server(DBImpl::MySQL, "8.0.33").supports_rename_index());
+        assert!(!server(DBImpl::MySQL, "5.6.51-log").supports_rename_index());
+        assert!(server(DBImpl::MySQL, "10.6.12-MariaDB-1:10.6.12+maria~ubu2004").is_mariadb());
+        assert!(!server(DBImpl::MySQL, "10.4.28-MariaDB").supports_rename_index());
+        assert!(!server(DBImpl::Postgres, "PostgreSQL 15.3").supports_rename_index());
+        assert!(server(DBImpl::Postgres, "PostgreSQL 15.3").supports_nulls_not_distinct());
+        assert!(!server(DBImpl::Postgres, "PostgreSQL 14.8").supports_nulls_not_distinct());
+        assert!(server(DBImpl::Postgres, "PostgreSQL 13.11").supports_gen_random_uuid());
+        assert!(!server(DBImpl::Postgres, "PostgreSQL 12.15").supports_gen_random_uuid());
+    }
+}
@@ -1,24 +1,460 @@
-use anyhow::Context;
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context};
+use rorm_sql::alter_index::SQLAlterIndexOperation;
 use rorm_sql::alter_table::SQLAlterTableOperation;
+use rorm_sql::create_column::SQLAnnotation;
+use rorm_sql::imr::{Annotation, DbType, Dialect, Field, ModelAnnotation};
 use rorm_sql::DBImpl;
 
-use crate::declaration::{Migration, Operation};
+use crate::declaration::{EnumAction, IndexKind, Migration, Operation, TypeChange};
+use crate::make_migrations::{index_name, unique_together_name};
+use crate::migrate::error::MigrationError;
+use crate::migrate::rebuild::{rebuild_statements, rebuilt_table, rebuilt_tables, TableState};
+use crate::migrate::server::ServerInfo;
+use crate::utils::fold::model_of;
+use crate::utils::output::info;
 
 /**
-Helper method to convert a migration to a transaction string
+Options for converting migrations to SQL.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct BuildOptions {
+    /// Skip objects which already exist or have already been removed, where the dialect supports it
+    pub idempotent: bool,
+    /// Character set of new tables on mysql, unless set by the model
+    pub charset: Option<String>,
+    /// Collation of new tables on mysql, unless set by the model
+    pub collation: Option<String>,
+    /// State of the tables before the migration, required to rebuild tables on SQLite
+    pub state: Option<TableState>,
+    /// The server the migration is applied on, version dependent statements use
+    /// their fallback if not known
+    pub server: Option<ServerInfo>,
+}
+
+/**
+The phases operations of a migration are sorted into before rendering.
+
+Creating operations are rendered in the order of the phases, so every object
+exists before objects depending on it are created.
+Dropping operations are rendered before all creating operations in the reverse order.
+*/
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Database extensions
+    Extensions,
+    /// Enum types
+    Enums,
+    /// Tables
+    Tables,
+    /// Columns of existing tables
+    Columns,
+    /// Constraints, indexes and other properties of existing tables
+    Constraints,
+    /// Comments on tables and columns
+    Comments,
+}
+
+/**
+Helper method to get the phase of an operation and whether it drops an object.
+
+`operation`: [&Operation]: The operation to categorize.
+*/
+pub fn operation_phase(operation: &Operation) -> (Phase, bool) {
+    match operation {
+        Operation::CreateExtension { .. } => (Phase::Extensions, false),
+        // Extensions are dropped after the tables using them
+        Operation::DropExtension { .. } => (Phase::Extensions, true),
+        Operation::CreateModel { .. } | Operation::RenameModel { .. } => (Phase::Tables, false),
+        Operation::DeleteModel { .. } => (Phase::Tables, true),
+        Operation::CreateField { .. }
+        | Operation::RenameField { .. }
+        | Operation::RenameFields { .. }
+        | Operation::AlterField { .. }
+        | Operation::AlterEnum { .. }
+        // Partitions are created once their partitioned tables exist
+        | Operation::CreatePartition { .. } => (Phase::Columns, false),
+        Operation::DeleteField { .. } => (Phase::Columns, true),
+        Operation::CreateIndex { .. }
+        | Operation::RenameIndex { .. }
+        | Operation::RenameConstraint { .. }
+        | Operation::CreateForeignKey { .. }
+        | Operation::CreateCheck { .. }
+        | Operation::ValidateConstraint { .. }
+        | Operation::SetSequence { .. }
+        | Operation::AddIdentity { .. }
+        | Operation::SetIdentity { .. }
+        | Operation::DropIdentity { .. }
+        | Operation::Truncate { .. }
+        | Operation::SetTablespace { .. }
+        | Operation::SetStorageParameters { .. }
+        | Operation::SetCharset { .. }
+        | Operation::AttachPartition { .. }
+        | Operation::DetachPartition { .. }
+        // The columns of the parent have to exist before inheriting from it
+        | Operation::SetInherit { .. }
+        | Operation::CreateUnique { .. }
+        | Operation::RunSql { .. } => (Phase::Constraints, false),
+        // Unique constraints and indexes are dropped before their columns,
+        // parents are removed before their tables or columns are dropped
+        Operation::DropUnique { .. }
+        | Operation::DropIndex { .. }
+        | Operation::DropConstraint { .. }
+        | Operation::NoInherit { .. } => (Phase::Constraints, true),
+        Operation::Only { operation, .. } => operation_phase(operation),
+    }
+}
+
+/**
+Helper method to order a batch of renames into renames which can be applied one after another.
+
+All old names of the batch refer to the columns before the batch, so a rename to the
+old name of another rename is delayed until the other one has been applied. Cycles,
+e.g. swapping two columns, are broken by renaming one column to a temporary name first.
+
+`renames`: [&\[(String, String)\]]: Pairs of old and new names.
+*/
+pub fn ordered_renames(renames: &[(String, String)]) -> Vec<(String, String)> {
+    let mut pending: Vec<(String, String)> = renames
+        .iter()
+        .filter(|(old, new)| old != new)
+        .cloned()
+        .collect();
+
+    let mut ordered = vec![];
+    while !pending.is_empty() {
+        match pending
+            .iter()
+            .position(|(_, new)| !pending.iter().any(|(old, _)| old == new))
+        {
+            Some(index) => ordered.push(pending.remove(index)),
+            None => {
+                // Every pending rename is part of a cycle
+                let mut temporary = format!("{}__drorm_tmp", pending[0].0);
+                while renames
+                    .iter()
+                    .any(|(old, new)| *old == temporary || *new == temporary)
+                {
+                    temporary.push('_');
+                }
+                ordered.push((pending[0].0.clone(), temporary.clone()));
+                pending[0].0 = temporary;
+            }
+        }
+    }
+    ordered
+}
+
+/**
+Helper method to sort the operations of a migration into their phases.
+
+The sort is stable, operations of the same phase keep their authored order.
+
+`migration`: [&Migration]: Reference to the migration whose operations should be sorted.
+*/
+pub fn sorted_operations(migration: &Migration) -> Vec<&Operation> {
+    let mut operations: Vec<&Operation> = migration.operations.iter().collect();
+    operations.sort_by_key(|operation| match operation_phase(operation) {
+        // Drops come first, in the reverse order of the phases
+        (phase, true) => (false, Phase::Comments as u8 - phase as u8),
+        (phase, false) => (true, phase as u8),
+    });
+    operations
+}
+
+/**
+Helper method to convert a migration to a list of statements
+
+Each entry results from an operation of the migration and may consist of multiple SQL statements.
 
 `db_impl`: [DBImpl]: The database implementation to use.
 `migration`: [&Migration]: Reference to the migration that should be converted.
+`options`: [&BuildOptions]: Options for generating the SQL.
 */
-pub fn migration_to_sql(db_impl: DBImpl, migration: &Migration) -> anyhow::Result<String> {
-    let mut transaction = db_impl.start_transaction();
+pub fn migration_to_statements(
+    db_impl: DBImpl,
+    migration: &Migration,
+    options: &BuildOptions,
+) -> Result<Vec<String>, MigrationError> {
+    let statements = operation_statements(db_impl, migration, options)?
+        .into_iter()
+        .flat_map(|(_, statements)| statements);
 
-    for operation in &migration.operations {
-        match &operation {
-            Operation::CreateModel { name, fields } => {
+    Ok(match lock_timeout_statements(db_impl, migration) {
+        None => statements.collect(),
+        Some((set, reset)) => std::iter::once(set)
+            .chain(statements)
+            .chain(std::iter::once(reset))
+            .collect(),
+    })
+}
+
+/**
+Busy timeout of SQLite connections, which sqlx sets by default
+*/
+const SQLITE_BUSY_TIMEOUT_MS: u64 = 5000;
+
+/**
+Helper method to build the statements setting the lock timeout of a migration and resetting it afterwards.
+
+On Postgres, the lock timeout of an atomic migration is local to its transaction.
+MySQL only supports whole seconds, so the timeout is rounded up.
+SQLite has no lock timeout, the busy timeout of its connection is used instead.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`migration`: [&Migration]: The migration, whose lock timeout is set.
+*/
+pub fn lock_timeout_statements(db_impl: DBImpl, migration: &Migration) -> Option<(String, String)> {
+    let millis = migration.lock_timeout_ms?;
+    Some(match db_impl {
+        DBImpl::Postgres => {
+            let scope = if migration.atomic { "LOCAL " } else { "" };
+            (
+                format!("SET {}lock_timeout = '{}ms';", scope, millis),
+                format!("SET {}lock_timeout = DEFAULT;", scope),
+            )
+        }
+        DBImpl::MySQL => (
+            format!(
+                "SET SESSION lock_wait_timeout = {};",
+                millis.div_ceil(1000).max(1)
+            ),
+            "SET SESSION lock_wait_timeout = DEFAULT;".to_string(),
+        ),
+        DBImpl::SQLite => (
+            format!("PRAGMA busy_timeout = {};", millis),
+            format!("PRAGMA busy_timeout = {};", SQLITE_BUSY_TIMEOUT_MS),
+        ),
+    })
+}
+
+/**
+Helper method to determine the tables a migration changes, in the order they are changed first.
+
+Tables created by the migration are included, dropped tables are not.
+Tables which are renamed are returned by their new name.
+Raw SQL changes unknown tables and is ignored.
+
+`db_impl`: [DBImpl]: The database implementation to use, operations for other dialects are ignored.
+`migration`: [&Migration]: The migration, whose tables are returned.
+*/
+pub fn affected_tables(db_impl: DBImpl, migration: &Migration) -> Vec<String> {
+    let mut tables: Vec<String> = vec![];
+    for operation in sorted_operations(migration) {
+        let operation = match operation.for_dialect(db_impl.into()) {
+            Some(operation) => operation,
+            None => continue,
+        };
+        let table = match operation {
+            Operation::CreateModel { name, .. } => name.as_str(),
+            Operation::RenameModel { old, new, .. } => {
+                tables.retain(|x| x != old);
+                new.as_str()
+            }
+            Operation::DeleteModel { name, .. } => {
+                tables.retain(|x| x != name);
+                continue;
+            }
+            operation => match model_of(operation) {
+                Some(model) => model,
+                None => continue,
+            },
+        };
+        if !tables.iter().any(|x| x == table) {
+            tables.push(table.to_string());
+        }
+    }
+    tables
+}
+
+/**
+Helper method to build the statements refreshing the planner statistics of tables.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`tables`: [&\[String\]]: The tables to analyze.
+*/
+pub fn analyze_statements(db_impl: DBImpl, tables: &[String]) -> Vec<String> {
+    tables
+        .iter()
+        .map(|table| match db_impl {
+            DBImpl::Postgres | DBImpl::SQLite => format!("ANALYZE {};", table),
+            DBImpl::MySQL => format!("ANALYZE TABLE {};", table),
+        })
+        .collect()
+}
+
+/**
+Helper method to convert a migration to the statements of each of its operations
+
+The operations are returned in the order they are executed in.
+Some operations don't result in any statement on some dialects.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`migration`: [&Migration]: Reference to the migration that should be converted.
+`options`: [&BuildOptions]: Options for generating the SQL.
+*/
+pub fn operation_statements<'a>(
+    db_impl: DBImpl,
+    migration: &'a Migration,
+    options: &BuildOptions,
+) -> Result<Vec<(&'a Operation, Vec<String>)>, MigrationError> {
+    build_statements(db_impl, migration, options).map_err(|err| MigrationError::Build {
+        migration: migration.id.clone(),
+        source: err.into(),
+    })
+}
+
+/**
+Builds the statements of [operation_statements].
+*/
+fn build_statements<'a>(
+    db_impl: DBImpl,
+    migration: &'a Migration,
+    options: &BuildOptions,
+) -> anyhow::Result<Vec<(&'a Operation, Vec<String>)>> {
+    let mut operations = vec![];
+    let idempotent = options.idempotent;
+
+    let alter_table = |name: &str, operation| {
+        let alter_table = db_impl.alter_table(name, operation);
+        if idempotent {
+            alter_table.idempotent()
+        } else {
+            alter_table
+        }
+    };
+    // Postgres has no IF [NOT] EXISTS for some statements,
+    // so the errors of already applied statements are ignored instead
+    let ignore_errors = |statement: String, conditions: &[&str]| {
+        if idempotent && db_impl == DBImpl::Postgres {
+            postgres_ignore_errors(statement.as_str(), conditions)
+        } else {
+            statement
+        }
+    };
+
+    let sorted = sorted_operations(migration);
+    // SQLite applies some changes of existing tables by rebuilding them
+    let (rebuilt, mut rebuilds) = match db_impl {
+        DBImpl::SQLite => (
+            rebuilt_tables(migration),
+            rebuild_statements(migration, &sorted, options.state.as_ref())?,
+        ),
+        DBImpl::Postgres | DBImpl::MySQL => (vec![], HashMap::new()),
+    };
+
+    // Extensions required by the columns of the migration on postgres, created
+    // before the first operation using them unless the migration creates them itself
+    let mut extensions: Vec<RequiredExtension> = vec![];
+    if db_impl == DBImpl::Postgres {
+        // gen_random_uuid() is provided by pgcrypto before postgres 13,
+        // it's created unless the server is known to be newer
+        if !options
+            .server
+            .as_ref()
+            .is_some_and(|x| x.supports_gen_random_uuid())
+        {
+            extensions.push(("pgcrypto", |field| {
+                field
+                    .annotations
+                    .iter()
+                    .any(|x| matches!(x, Annotation::DefaultUuid))
+            }));
+        }
+        extensions.push(("citext", |field| matches!(field.db_type, DbType::Citext)));
+    }
+    extensions.retain(|(extension, _)| {
+        !migration.operations.iter().any(|x| {
+            matches!(x.for_dialect(Dialect::Postgres), Some(Operation::CreateExtension { name, .. }) if name == extension)
+        })
+    });
+
+    for (index, operation) in sorted.into_iter().enumerate() {
+        let mut statements: Vec<String> = vec![];
+
+        let filtered = match operation.for_dialect(db_impl.into()) {
+            None => {
+                operations.push((operation, statements));
+                continue;
+            }
+            Some(filtered) => filtered,
+        };
+
+        if db_impl == DBImpl::Postgres {
+            check_nulls_not_distinct(filtered, options.server.as_ref())?;
+        }
+        for (extension, _) in extensions
+            .iter()
+            .filter(|(_, required)| uses_field(filtered, *required))
+        {
+            statements.push(
+                db_impl
+                    .create_extension(extension)
+                    .if_not_exists()
+                    .build()
+                    .with_context(|| {
+                        format!(
+                            "Could not build create extension operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?,
+            );
+        }
+        extensions.retain(|(_, required)| !uses_field(filtered, *required));
+
+        if rebuilt_table(filtered).is_some_and(|x| rebuilt.contains(&x)) {
+            operations.push((operation, rebuilds.remove(&index).unwrap_or_default()));
+            continue;
+        }
+
+        match filtered {
+            Operation::CreateModel {
+                name,
+                fields,
+                annotations,
+            } => {
                 let mut create_table = db_impl.create_table(name.as_str());
+                if idempotent {
+                    create_table = create_table.if_not_exists();
+                }
+
+                let mut model_charset = (None, None);
+                for annotation in annotations {
+                    create_table = match annotation {
+                        ModelAnnotation::Tablespace(t) => create_table.tablespace(t.as_str()),
+                        ModelAnnotation::StorageParameter(p) => {
+                            create_table.add_storage_parameter(p.clone())
+                        }
+                        ModelAnnotation::Charset(c) => {
+                            model_charset.0 = Some(c);
+                            create_table
+                        }
+                        ModelAnnotation::Collation(c) => {
+                            model_charset.1 = Some(c);
+                            create_table
+                        }
+                        ModelAnnotation::Persistence(p) => create_table.persistence(*p),
+                        ModelAnnotation::PartitionBy(p) => create_table.partition_by(p.clone()),
+                        ModelAnnotation::Inherits(parent) => create_table.inherit(parent.as_str()),
+                        // Only a hint for make-migrations
+                        ModelAnnotation::RenameFrom(_) => create_table,
+                        // Created as indexes after the table
+                        ModelAnnotation::UniqueTogether(_) | ModelAnnotation::Index(_) => {
+                            create_table
+                        }
+                    };
+                }
+                let (charset, collation) =
+                    resolve_charset(model_charset.0, model_charset.1, options);
+                if let Some(charset) = charset {
+                    create_table = create_table.charset(charset.as_str());
+                }
+                if let Some(collation) = collation {
+                    create_table = create_table.collation(collation.as_str());
+                }
 
-                for field in fields {
+                for field in fields.iter().filter(|x| on_dialect(x, db_impl)) {
                     create_table = create_table.add_column(db_impl.create_column(
                         name.as_str(),
                         field.name.as_str(),
@@ -27,48 +463,233 @@ pub fn migration_to_sql(db_impl: DBImpl, migration: &Migration) -> anyhow::Resul
                     ));
                 }
 
-                transaction =
-                    transaction.add_statement(create_table.build().with_context(|| {
+                // SQLite can't add constraints to existing tables,
+                // so foreign keys and checks of new tables are part of their definition
+                if db_impl == DBImpl::SQLite {
+                    for operation in &migration.operations {
+                        match operation.for_dialect(Dialect::SQLite) {
+                            Some(Operation::CreateForeignKey {
+                                model,
+                                name: fk_name,
+                                column,
+                                foreign_key,
+                                ..
+                            }) if model == name => {
+                                create_table = create_table.add_foreign_key(db_impl.foreign_key(
+                                    fk_name.as_str(),
+                                    column.as_str(),
+                                    foreign_key.clone(),
+                                ));
+                            }
+                            Some(Operation::CreateCheck {
+                                model,
+                                name: check_name,
+                                expression,
+                                ..
+                            }) if model == name => {
+                                create_table = create_table
+                                    .add_check(check_name.as_str(), expression.as_str());
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+
+                for warning in [
+                    create_table.row_size_warning(),
+                    create_table.ignored_options_warning(),
+                    create_table.degraded_types_warning(),
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    info!("Warning: {}", warning);
+                }
+
+                statements.push(create_table.build().with_context(|| {
+                    format!(
+                        "Could not build create table operation for migration {}",
+                        migration.id.as_str()
+                    )
+                })?);
+
+                for annotation in annotations {
+                    let index = match annotation {
+                        ModelAnnotation::UniqueTogether(columns) => unique_together_index(
+                            db_impl,
+                            name.as_str(),
+                            unique_together_name(name.as_str(), columns).as_str(),
+                            columns,
+                            idempotent,
+                            false,
+                        ),
+                        ModelAnnotation::Index(columns) => {
+                            let mut create_index = db_impl.create_index(
+                                index_name(name.as_str(), columns).as_str(),
+                                name.as_str(),
+                            );
+                            if idempotent {
+                                create_index = create_index.if_not_exists();
+                            }
+                            for column in columns {
+                                create_index = create_index.add_column(column.as_str());
+                            }
+                            create_index.build()
+                        }
+                        _ => continue,
+                    };
+                    statements.push(index.with_context(|| {
                         format!(
-                            "Could not build create table operation for migration {}",
+                            "Could not build create index operation for migration {}",
                             migration.id.as_str()
                         )
                     })?);
+                }
             }
-            Operation::RenameModel { old, new } => {
-                transaction = transaction.add_statement(
-                    db_impl
-                        .alter_table(
-                            old.as_str(),
-                            SQLAlterTableOperation::RenameTo {
-                                name: new.to_string(),
+            Operation::RenameModel {
+                old,
+                new,
+                rename_constraints,
+            } => {
+                statements.push(
+                    alter_table(
+                        old.as_str(),
+                        SQLAlterTableOperation::RenameTo {
+                            name: new.to_string(),
+                        },
+                    )
+                    .build()
+                    .with_context(|| {
+                        format!(
+                            "Could not build rename table operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?,
+                );
+                if *rename_constraints && db_impl == DBImpl::Postgres {
+                    statements.push(postgres_rename_constraints(old.as_str(), new.as_str()));
+                }
+            }
+            Operation::DeleteModel { name, cascade } => {
+                let mut drop_table = db_impl.drop_table(name.as_str());
+                if idempotent {
+                    drop_table = drop_table.if_exists();
+                }
+                if *cascade {
+                    drop_table = drop_table.cascade();
+                }
+                statements.push(drop_table.build().with_context(|| {
+                    format!(
+                        "Could not build drop table operation for migration {}",
+                        migration.id.as_str()
+                    )
+                })?)
+            }
+            Operation::CreateField { model, field } if on_dialect(field, db_impl) => {
+                let create_column = db_impl.create_column(
+                    model.as_str(),
+                    field.name.as_str(),
+                    field.db_type.clone(),
+                    field.annotations.clone(),
+                );
+                if let Some(warning) = create_column.degraded_type_warning() {
+                    info!("Warning: {}", warning);
+                }
+                statements.push(
+                    alter_table(
+                        model.as_str(),
+                        SQLAlterTableOperation::AddColumn {
+                            operation: create_column,
+                        },
+                    )
+                    .build()
+                    .with_context(|| {
+                        format!(
+                            "Could not build add column operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?,
+                );
+            }
+            Operation::RenameField {
+                table_name,
+                old,
+                new,
+            } => statements.push(ignore_errors(
+                alter_table(
+                    table_name.as_str(),
+                    SQLAlterTableOperation::RenameColumnTo {
+                        column_name: old.to_string(),
+                        new_column_name: new.to_string(),
+                    },
+                )
+                .build()
+                .with_context(|| {
+                    format!(
+                        "Could not build rename field operation for migration {}",
+                        migration.id.as_str()
+                    )
+                })?,
+                &["undefined_column"],
+            )),
+            Operation::RenameFields {
+                table_name,
+                renames,
+            } => {
+                for (old, new) in ordered_renames(renames) {
+                    statements.push(ignore_errors(
+                        alter_table(
+                            table_name.as_str(),
+                            SQLAlterTableOperation::RenameColumnTo {
+                                column_name: old,
+                                new_column_name: new,
                             },
                         )
                         .build()
                         .with_context(|| {
                             format!(
-                                "Could not build rename table operation for migration {}",
+                                "Could not build rename fields operation for migration {}",
                                 migration.id.as_str()
                             )
                         })?,
-                );
+                        &["undefined_column"],
+                    ));
+                }
             }
-            Operation::DeleteModel { name } => {
-                transaction = transaction.add_statement(
-                    db_impl.drop_table(name.as_str()).build().with_context(|| {
+            Operation::DeleteField {
+                model,
+                name,
+                cascade,
+            } => {
+                statements.push(
+                    alter_table(
+                        model.as_str(),
+                        SQLAlterTableOperation::DropColumn {
+                            name: name.clone(),
+                            cascade: *cascade,
+                        },
+                    )
+                    .build()
+                    .with_context(|| {
                         format!(
-                            "Could not build drop table operation for migration {}",
+                            "Could not build drop column operation for migration {}",
                             migration.id.as_str()
                         )
                     })?,
-                )
+                );
             }
-            Operation::CreateField { model, field } => {
-                transaction = transaction.add_statement(
-                    db_impl
-                        .alter_table(
+            Operation::AlterField {
+                model,
+                field,
+                change,
+            } if on_dialect(field, db_impl) => {
+                // SQLite keeps the storage class of widened and narrowed types
+                // and doesn't enforce their lengths, so only incompatible changes reach the database
+                if db_impl != DBImpl::SQLite || *change == TypeChange::Incompatible {
+                    statements.push(
+                        alter_table(
                             model.as_str(),
-                            SQLAlterTableOperation::AddColumn {
+                            SQLAlterTableOperation::AlterColumnType {
                                 operation: db_impl.create_column(
                                     model.as_str(),
                                     field.name.as_str(),
@@ -80,58 +701,2449 @@ pub fn migration_to_sql(db_impl: DBImpl, migration: &Migration) -> anyhow::Resul
                         .build()
                         .with_context(|| {
                             format!(
-                                "Could not build add column operation for migration {}",
+                                "Could not build alter column operation for migration {}",
                                 migration.id.as_str()
                             )
                         })?,
-                );
+                    );
+                }
             }
-            Operation::RenameField {
-                table_name,
+            Operation::AlterEnum {
+                model,
+                field,
+                action,
+            } if on_dialect(field, db_impl) => {
+                let modify_column = |field: &Field| {
+                    alter_table(
+                        model.as_str(),
+                        SQLAlterTableOperation::AlterColumnType {
+                            operation: db_impl.create_column(
+                                model.as_str(),
+                                field.name.as_str(),
+                                field.db_type.clone(),
+                                field.annotations.clone(),
+                            ),
+                        },
+                    )
+                    .build()
+                    .with_context(|| {
+                        format!(
+                            "Could not build alter enum operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })
+                };
+
+                // Only MySQL has enum columns, the values are stored as strings otherwise
+                match action {
+                    EnumAction::Add { .. } => {
+                        if db_impl == DBImpl::MySQL {
+                            statements.push(modify_column(field)?);
+                        }
+                    }
+                    EnumAction::Rename { old, new } => {
+                        // The rows are updated while the column allows both values
+                        if db_impl == DBImpl::MySQL {
+                            let mut both = field.clone();
+                            for annotation in &mut both.annotations {
+                                if let Annotation::Choices(choices) = annotation {
+                                    choices.push(old.clone());
+                                }
+                            }
+                            statements.push(modify_column(&both)?);
+                        }
+                        statements.push(format!(
+                            "UPDATE {} SET {} = '{}' WHERE {} = '{}';",
+                            model,
+                            field.name,
+                            new.replace('\'', "''"),
+                            field.name,
+                            old.replace('\'', "''")
+                        ));
+                        if db_impl == DBImpl::MySQL {
+                            statements.push(modify_column(field)?);
+                        }
+                    }
+                }
+            }
+            Operation::CreateIndex {
+                model,
+                name,
+                columns,
+                unique,
+                concurrently,
+                kind,
+            } => {
+                if *kind == IndexKind::FullText && db_impl == DBImpl::SQLite {
+                    info!(
+                        "Warning: Full-text index {} of table {} is ignored on {:?}",
+                        name, model, db_impl
+                    );
+                    operations.push((operation, statements));
+                    continue;
+                }
+
+                let mut create_index = db_impl.create_index(name.as_str(), model.as_str());
+                if *kind == IndexKind::FullText {
+                    create_index = create_index.full_text();
+                }
+                if *unique {
+                    create_index = create_index.unique();
+                }
+                if idempotent {
+                    create_index = create_index.if_not_exists();
+                }
+                for column in columns {
+                    create_index = create_index.add_column(column.as_str());
+                }
+                if *concurrently && db_impl == DBImpl::Postgres {
+                    if migration.atomic {
+                        return Err(anyhow!(
+                            "Index {} of migration {} is created concurrently, which requires Atomic = false",
+                            name,
+                            migration.id.as_str()
+                        ));
+                    }
+                    create_index = create_index.concurrently();
+                }
+                statements.push(create_index.build().with_context(|| {
+                    format!(
+                        "Could not build create index operation for migration {}",
+                        migration.id.as_str()
+                    )
+                })?);
+            }
+            Operation::CreateUnique {
+                model,
+                name,
+                columns,
+                nulls_not_distinct,
+            } => statements.push(
+                unique_together_index(
+                    db_impl,
+                    model.as_str(),
+                    name.as_str(),
+                    columns,
+                    idempotent,
+                    *nulls_not_distinct,
+                )
+                .with_context(|| {
+                    format!(
+                        "Could not build create index operation for migration {}",
+                        migration.id.as_str()
+                    )
+                })?,
+            ),
+            Operation::DropUnique { model, name, .. }
+            | Operation::DropIndex { model, name, .. } => {
+                let mut drop_index = db_impl.drop_index(name.as_str(), model.as_str());
+                if idempotent {
+                    drop_index = drop_index.if_exists();
+                }
+                statements.push(drop_index.build().with_context(|| {
+                    format!(
+                        "Could not build drop index operation for migration {}",
+                        migration.id.as_str()
+                    )
+                })?);
+            }
+            Operation::RenameIndex {
+                model,
                 old,
                 new,
-            } => {
-                transaction = transaction.add_statement(
-                    db_impl
-                        .alter_table(
-                            table_name.as_str(),
-                            SQLAlterTableOperation::RenameColumnTo {
-                                column_name: old.to_string(),
-                                new_column_name: new.to_string(),
+                columns,
+                unique,
+            } => match db_impl {
+                DBImpl::Postgres => {
+                    let mut alter_index = db_impl.alter_index(
+                        old.as_str(),
+                        SQLAlterIndexOperation::RenameTo {
+                            name: new.to_string(),
+                        },
+                    );
+                    if idempotent {
+                        alter_index = alter_index.if_exists();
+                    }
+                    statements.push(alter_index.build().with_context(|| {
+                        format!(
+                            "Could not build rename index operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?);
+                }
+                DBImpl::MySQL
+                    if options
+                        .server
+                        .as_ref()
+                        .is_some_and(|x| x.supports_rename_index()) =>
+                {
+                    statements.push(
+                        alter_table(
+                            model.as_str(),
+                            SQLAlterTableOperation::RenameIndex {
+                                name: old.to_string(),
+                                new_name: new.to_string(),
                             },
                         )
                         .build()
                         .with_context(|| {
                             format!(
-                                "Could not build rename field operation for migration {}",
+                                "Could not build rename index operation for migration {}",
                                 migration.id.as_str()
                             )
                         })?,
-                )
-            }
-            Operation::DeleteField { model, name } => {
-                transaction = transaction.add_statement(
-                    db_impl
-                        .alter_table(
+                    );
+                }
+                // Indexes can't be renamed by older servers, so they are recreated with the new name
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    let mut drop_index = db_impl.drop_index(old.as_str(), model.as_str());
+                    if idempotent {
+                        drop_index = drop_index.if_exists();
+                    }
+                    statements.push(drop_index.build().with_context(|| {
+                        format!(
+                            "Could not build drop index operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?);
+
+                    let mut create_index = db_impl.create_index(new.as_str(), model.as_str());
+                    if *unique {
+                        create_index = create_index.unique();
+                    }
+                    if idempotent {
+                        create_index = create_index.if_not_exists();
+                    }
+                    for column in columns {
+                        create_index = create_index.add_column(column.as_str());
+                    }
+                    statements.push(create_index.build().with_context(|| {
+                        format!(
+                            "Could not build create index operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?);
+                }
+            },
+            Operation::RenameConstraint {
+                model,
+                old,
+                new,
+                definition,
+            } => match db_impl {
+                DBImpl::Postgres => {
+                    statements.push(ignore_errors(
+                        alter_table(
                             model.as_str(),
-                            SQLAlterTableOperation::DropColumn { name: name.clone() },
+                            SQLAlterTableOperation::RenameConstraintTo {
+                                constraint_name: old.to_string(),
+                                new_constraint_name: new.to_string(),
+                            },
                         )
                         .build()
                         .with_context(|| {
                             format!(
-                                "Could not build drop column operation for migration {}",
+                                "Could not build rename constraint operation for migration {}",
                                 migration.id.as_str()
                             )
                         })?,
-                );
-            }
-        }
-    }
+                        &["undefined_object"],
+                    ));
+                }
+                // Constraints can't be renamed, so they are recreated with the new name
+                DBImpl::MySQL => {
+                    let definition = definition.as_ref().ok_or_else(|| {
+                        anyhow!(
+                            "Constraint {} in migration {} requires a definition to be recreated on MySQL",
+                            old,
+                            migration.id.as_str()
+                        )
+                    })?;
+
+                    for operation in [
+                        SQLAlterTableOperation::DropConstraint { name: old.clone() },
+                        SQLAlterTableOperation::AddConstraint {
+                            name: new.clone(),
+                            definition: definition.clone(),
+                            not_valid: false,
+                        },
+                    ] {
+                        statements.push(
+                            alter_table(model.as_str(), operation)
+                                .build()
+                                .with_context(|| {
+                                    format!(
+                                        "Could not build recreate constraint operation for migration {}",
+                                        migration.id.as_str()
+                                    )
+                                })?,
+                        );
+                    }
+                }
+                DBImpl::SQLite => {
+                    return Err(anyhow!(
+                        "Could not rename constraint {} in migration {}: SQLite constraints are part of the table definition and can't be renamed",
+                        old,
+                        migration.id.as_str()
+                    ));
+                }
+            },
+            Operation::CreateForeignKey {
+                model,
+                name,
+                column,
+                foreign_key,
+                not_valid,
+            } => match db_impl {
+                DBImpl::Postgres | DBImpl::MySQL => {
+                    let foreign_key =
+                        db_impl.foreign_key(name.as_str(), column.as_str(), foreign_key.clone());
+                    if let Some(warning) = foreign_key.ignored_options_warning() {
+                        info!("Warning: {}", warning);
+                    }
 
-    Ok(transaction.finish().with_context(|| {
-        format!(
-            "Could not create transaction for migration {}",
-            migration.id.as_str()
-        )
-    })?)
+                    statements.push(ignore_errors(
+                        alter_table(
+                            model.as_str(),
+                            SQLAlterTableOperation::AddConstraint {
+                                name: name.clone(),
+                                definition: foreign_key.build(),
+                                not_valid: *not_valid,
+                            },
+                        )
+                        .build()
+                        .with_context(|| {
+                            format!(
+                                "Could not build create foreign key operation for migration {}",
+                                migration.id.as_str()
+                            )
+                        })?,
+                        &["duplicate_object"],
+                    ));
+                }
+                // Already rendered as part of the CREATE TABLE statement,
+                // foreign keys of existing tables are added by rebuilding the table
+                DBImpl::SQLite => {}
+            },
+            // Same as foreign keys, see above
+            Operation::CreateCheck { .. } if db_impl == DBImpl::SQLite => {}
+            Operation::CreateCheck {
+                model,
+                name,
+                expression,
+                not_valid,
+            } => {
+                statements.push(ignore_errors(
+                    alter_table(
+                        model.as_str(),
+                        SQLAlterTableOperation::AddConstraint {
+                            name: name.clone(),
+                            definition: format!("CHECK ({})", expression),
+                            not_valid: *not_valid,
+                        },
+                    )
+                    .build()
+                    .with_context(|| {
+                        format!(
+                            "Could not build create check operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?,
+                    &["duplicate_object"],
+                ));
+            }
+            Operation::ValidateConstraint { model, name } => match db_impl {
+                DBImpl::Postgres => {
+                    statements.push(
+                        alter_table(
+                            model.as_str(),
+                            SQLAlterTableOperation::ValidateConstraint { name: name.clone() },
+                        )
+                        .build()
+                        .with_context(|| {
+                            format!(
+                                "Could not build validate constraint operation for migration {}",
+                                migration.id.as_str()
+                            )
+                        })?,
+                    );
+                }
+                // Constraints have already been validated when they were added
+                DBImpl::SQLite | DBImpl::MySQL => {}
+            },
+            Operation::DropConstraint { model, name, .. } => match db_impl {
+                DBImpl::Postgres | DBImpl::MySQL => {
+                    statements.push(ignore_errors(
+                        alter_table(
+                            model.as_str(),
+                            SQLAlterTableOperation::DropConstraint { name: name.clone() },
+                        )
+                        .build()
+                        .with_context(|| {
+                            format!(
+                                "Could not build drop constraint operation for migration {}",
+                                migration.id.as_str()
+                            )
+                        })?,
+                        &["undefined_object"],
+                    ));
+                }
+                // Same as adding constraints, the table is rebuilt without the constraint
+                DBImpl::SQLite => {}
+            },
+            Operation::AddIdentity {
+                model,
+                column,
+                identity,
+                from_serial,
+            } => match db_impl {
+                DBImpl::Postgres => {
+                    let context = || {
+                        format!(
+                            "Could not build add identity operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    };
+                    let mut sql = vec![];
+                    if *from_serial {
+                        // The default of a serial column uses its own sequence
+                        sql.push(
+                            alter_table(
+                                model.as_str(),
+                                SQLAlterTableOperation::DropDefault {
+                                    column: column.clone(),
+                                },
+                            )
+                            .build()
+                            .with_context(context)?,
+                        );
+                        sql.push(format!("DROP SEQUENCE IF EXISTS {}_{}_seq;", model, column));
+                    }
+                    sql.push(
+                        alter_table(
+                            model.as_str(),
+                            SQLAlterTableOperation::AddIdentity {
+                                column: column.clone(),
+                                identity: *identity,
+                            },
+                        )
+                        .build()
+                        .with_context(context)?,
+                    );
+                    // The new sequence starts at 1, continue after the existing rows instead
+                    sql.push(format!(
+                        "SELECT setval(pg_get_serial_sequence('{}', '{}'), COALESCE(MAX({}), 0) + 1, false) FROM {};",
+                        model, column, column, model
+                    ));
+                    statements.push(sql.join(" "));
+                }
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    info!(
+                        "Warning: Identity of {}.{} is ignored on {:?}, declare the field as auto_increment instead",
+                        model, column, db_impl
+                    );
+                }
+            },
+            Operation::SetIdentity {
+                model,
+                column,
+                identity,
+            } => match db_impl {
+                DBImpl::Postgres => {
+                    statements.push(
+                        alter_table(
+                            model.as_str(),
+                            SQLAlterTableOperation::SetIdentity {
+                                column: column.clone(),
+                                identity: *identity,
+                            },
+                        )
+                        .build()
+                        .with_context(|| {
+                            format!(
+                                "Could not build set identity operation for migration {}",
+                                migration.id.as_str()
+                            )
+                        })?,
+                    );
+                }
+                // Explicit values are always accepted by auto increment columns
+                DBImpl::SQLite | DBImpl::MySQL => {}
+            },
+            Operation::DropIdentity { model, column } => match db_impl {
+                DBImpl::Postgres => {
+                    statements.push(
+                        alter_table(
+                            model.as_str(),
+                            SQLAlterTableOperation::DropIdentity {
+                                column: column.clone(),
+                            },
+                        )
+                        .build()
+                        .with_context(|| {
+                            format!(
+                                "Could not build drop identity operation for migration {}",
+                                migration.id.as_str()
+                            )
+                        })?,
+                    );
+                }
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    info!(
+                        "Warning: Dropping the identity of {}.{} is ignored on {:?}",
+                        model, column, db_impl
+                    );
+                }
+            },
+            Operation::SetTablespace { model, tablespace } => match db_impl {
+                DBImpl::Postgres => {
+                    statements.push(
+                        alter_table(
+                            model.as_str(),
+                            SQLAlterTableOperation::SetTablespace {
+                                name: tablespace
+                                    .clone()
+                                    .unwrap_or_else(|| "pg_default".to_string()),
+                            },
+                        )
+                        .build()
+                        .with_context(|| {
+                            format!(
+                                "Could not build set tablespace operation for migration {}",
+                                migration.id.as_str()
+                            )
+                        })?,
+                    );
+                }
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    info!(
+                        "Warning: Tablespace of table {} is ignored on {:?}",
+                        model, db_impl
+                    );
+                }
+            },
+            Operation::CreatePartition { model, name, bound } => {
+                statements.push(
+                    db_impl
+                        .create_table(name.as_str())
+                        .partition_of(model.as_str(), bound.as_str())
+                        .build()
+                        .with_context(|| {
+                            format!(
+                                "Could not build create partition operation for migration {}",
+                                migration.id.as_str()
+                            )
+                        })?,
+                );
+            }
+            Operation::AttachPartition {
+                model,
+                partition,
+                bound,
+            } => {
+                statements.push(
+                    alter_table(
+                        model.as_str(),
+                        SQLAlterTableOperation::AttachPartition {
+                            name: partition.clone(),
+                            bound: bound.clone(),
+                        },
+                    )
+                    .build()
+                    .with_context(|| {
+                        format!(
+                            "Could not build attach partition operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?,
+                );
+            }
+            Operation::DetachPartition { model, partition } => {
+                statements.push(
+                    alter_table(
+                        model.as_str(),
+                        SQLAlterTableOperation::DetachPartition {
+                            name: partition.clone(),
+                        },
+                    )
+                    .build()
+                    .with_context(|| {
+                        format!(
+                            "Could not build detach partition operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?,
+                );
+            }
+            Operation::SetInherit { model, parent } => {
+                statements.push(
+                    alter_table(
+                        model.as_str(),
+                        SQLAlterTableOperation::Inherit {
+                            parent: parent.clone(),
+                        },
+                    )
+                    .build()
+                    .with_context(|| {
+                        format!(
+                            "Could not build inherit operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?,
+                );
+            }
+            Operation::NoInherit { model, parent } => {
+                statements.push(
+                    alter_table(
+                        model.as_str(),
+                        SQLAlterTableOperation::NoInherit {
+                            parent: parent.clone(),
+                        },
+                    )
+                    .build()
+                    .with_context(|| {
+                        format!(
+                            "Could not build no inherit operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?,
+                );
+            }
+            Operation::SetCharset {
+                model,
+                charset,
+                collation,
+            } => match db_impl {
+                DBImpl::MySQL => {
+                    let (charset, collation) =
+                        resolve_charset(charset.as_ref(), collation.as_ref(), options);
+                    statements.push(
+                        alter_table(
+                            model.as_str(),
+                            SQLAlterTableOperation::ConvertCharset {
+                                charset: charset.cloned(),
+                                collation: collation.cloned(),
+                            },
+                        )
+                        .build()
+                        .with_context(|| {
+                            format!(
+                                "Could not build set charset operation for migration {}",
+                                migration.id.as_str()
+                            )
+                        })?,
+                    );
+                }
+                DBImpl::SQLite | DBImpl::Postgres => {
+                    info!(
+                        "Warning: Character set of table {} is ignored on {:?}",
+                        model, db_impl
+                    );
+                }
+            },
+            Operation::SetStorageParameters { model, set, reset } => match db_impl {
+                DBImpl::Postgres => {
+                    let mut operations = vec![];
+                    if !reset.is_empty() {
+                        operations.push(SQLAlterTableOperation::ResetStorageParameters {
+                            names: reset.clone(),
+                        });
+                    }
+                    if !set.is_empty() {
+                        operations.push(SQLAlterTableOperation::SetStorageParameters {
+                            parameters: set.clone(),
+                        });
+                    }
+
+                    for operation in operations {
+                        statements.push(
+                            alter_table(model.as_str(), operation)
+                                .build()
+                                .with_context(|| {
+                                    format!(
+                                        "Could not build storage parameter operation for migration {}",
+                                        migration.id.as_str()
+                                    )
+                                })?,
+                        );
+                    }
+                }
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    info!(
+                        "Warning: Storage parameters of table {} are ignored on {:?}",
+                        model, db_impl
+                    );
+                }
+            },
+            Operation::SetSequence {
+                model,
+                column,
+                value,
+            } => {
+                statements.push(
+                    db_impl
+                        .set_sequence(model.as_str(), column.as_str(), *value)
+                        .build()
+                        .with_context(|| {
+                            format!(
+                                "Could not build set sequence operation for migration {}",
+                                migration.id.as_str()
+                            )
+                        })?,
+                );
+            }
+            Operation::Truncate {
+                model,
+                cascade,
+                restart_identity,
+            } => {
+                let mut truncate = db_impl.truncate(model.as_str());
+                if *cascade {
+                    truncate = truncate.cascade();
+                }
+                if *restart_identity {
+                    truncate = truncate.restart_identity();
+                }
+                if let Some(warning) = truncate.ignored_options_warning() {
+                    info!("Warning: {}", warning);
+                }
+                statements.push(truncate.build());
+            }
+            Operation::CreateExtension {
+                name,
+                if_not_exists,
+            } => match db_impl {
+                DBImpl::Postgres => {
+                    let mut create_extension = db_impl.create_extension(name.as_str());
+                    if *if_not_exists || idempotent {
+                        create_extension = create_extension.if_not_exists();
+                    }
+                    statements.push(create_extension.build().with_context(|| {
+                        format!(
+                            "Could not build create extension operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?);
+                }
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    info!("Warning: Extension {} is ignored on {:?}", name, db_impl);
+                }
+            },
+            Operation::DropExtension { name, if_exists } => match db_impl {
+                DBImpl::Postgres => {
+                    let mut drop_extension = db_impl.drop_extension(name.as_str());
+                    if *if_exists || idempotent {
+                        drop_extension = drop_extension.if_exists();
+                    }
+                    statements.push(drop_extension.build().with_context(|| {
+                        format!(
+                            "Could not build drop extension operation for migration {}",
+                            migration.id.as_str()
+                        )
+                    })?);
+                }
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    info!("Warning: Extension {} is ignored on {:?}", name, db_impl);
+                }
+            },
+            Operation::RunSql {
+                sql,
+                timeout_seconds,
+            } => {
+                let sql = sql.trim();
+                let sql = if sql.ends_with(';') {
+                    sql.to_string()
+                } else {
+                    format!("{};", sql)
+                };
+
+                // Other dialects have no statement timeout for writes, the deadline is client-side only
+                match timeout_seconds {
+                    Some(seconds) if db_impl == DBImpl::Postgres => {
+                        let scope = if migration.atomic { "LOCAL " } else { "" };
+                        statements.push(format!(
+                            "SET {}statement_timeout = {};",
+                            scope,
+                            seconds * 1000
+                        ));
+                        statements.push(sql);
+                        statements.push(format!("SET {}statement_timeout = DEFAULT;", scope));
+                    }
+                    _ => statements.push(sql),
+                }
+            }
+            // Fields of other dialects don't exist
+            Operation::CreateField { .. }
+            | Operation::AlterField { .. }
+            | Operation::AlterEnum { .. } => {}
+            Operation::Only { .. } => unreachable!("dialect filters are removed by for_dialect"),
+        }
+
+        operations.push((operation, statements));
+    }
+
+    Ok(operations)
+}
+
+/**
+Checks, if a field exists on the dialect.
+
+`field`: [&Field]: The field to check.
+`db_impl`: [DBImpl]: The database implementation to use.
+*/
+pub(crate) fn on_dialect(field: &Field, db_impl: DBImpl) -> bool {
+    field.annotations.iter().all(|x| match x {
+        Annotation::Only(dialects) => dialects.contains(&db_impl.into()),
+        _ => true,
+    })
+}
+
+/// Name of an extension and the predicate of the columns requiring it
+type RequiredExtension = (&'static str, fn(&Field) -> bool);
+
+/**
+Helper function to check, if an operation creates or changes a column matching a predicate.
+
+`operation`: [&Operation]: The operation to check.
+`predicate`: [fn(&Field) -> bool]: The predicate the columns are checked against.
+*/
+fn uses_field(operation: &Operation, predicate: fn(&Field) -> bool) -> bool {
+    match operation {
+        Operation::CreateModel { fields, .. } => fields.iter().any(predicate),
+        Operation::CreateField { field, .. } | Operation::AlterField { field, .. } => {
+            predicate(field)
+        }
+        _ => false,
+    }
+}
+
+/**
+Helper function to refuse `NULLS NOT DISTINCT` on postgres servers older than 15.
+
+The clause is generated, if the version of the server is unknown, e.g. in a dry run.
+
+`operation`: [&Operation]: The operation on postgres.
+`server`: [Option<&ServerInfo>]: The server the migration is applied on.
+*/
+fn check_nulls_not_distinct(
+    operation: &Operation,
+    server: Option<&ServerInfo>,
+) -> anyhow::Result<()> {
+    let server = match server {
+        Some(server) if !server.supports_nulls_not_distinct() => server,
+        _ => return Ok(()),
+    };
+    let nulls_not_distinct = |field: &Field| {
+        field
+            .annotations
+            .iter()
+            .any(|x| matches!(x, Annotation::NullsNotDistinct))
+    };
+
+    let object = match operation {
+        Operation::CreateModel { name, fields, .. } => fields
+            .iter()
+            .find(|x| nulls_not_distinct(x))
+            .map(|x| format!("Field {}.{}", name, x.name)),
+        Operation::CreateField { model, field } | Operation::AlterField { model, field, .. } => {
+            nulls_not_distinct(field).then(|| format!("Field {}.{}", model, field.name))
+        }
+        Operation::CreateUnique {
+            name,
+            nulls_not_distinct: true,
+            ..
+        } => Some(format!("Unique constraint {}", name)),
+        _ => None,
+    };
+    match object {
+        None => Ok(()),
+        Some(object) => Err(anyhow!(
+            "{} is nulls_not_distinct, which requires Postgres 15, but the server runs {}",
+            object,
+            server.version
+        )),
+    }
+}
+
+/**
+Helper function to build the unique index of columns declared unique together.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`model`: [&str]: Name of the model.
+`name`: [&str]: Name of the index.
+`columns`: [&\[String\]]: The columns of the index.
+`idempotent`: [bool]: Whether an existing index is skipped.
+`nulls_not_distinct`: [bool]: Whether null values collide, only generated on postgres.
+*/
+fn unique_together_index(
+    db_impl: DBImpl,
+    model: &str,
+    name: &str,
+    columns: &[String],
+    idempotent: bool,
+    nulls_not_distinct: bool,
+) -> anyhow::Result<String> {
+    let mut create_index = db_impl.create_index(name, model).unique();
+    if idempotent {
+        create_index = create_index.if_not_exists();
+    }
+    if nulls_not_distinct {
+        create_index = create_index.nulls_not_distinct();
+    }
+    for column in columns {
+        create_index = create_index.add_column(column.as_str());
+    }
+    create_index.build()
+}
+
+/**
+Resolves the character set and collation of a table on mysql.
+
+The character set and collation of the model take precedence over the configured ones.
+The configured collation is only used, if the model doesn't set a character set.
+
+`charset`: [Option<&String>]: Character set of the model.
+`collation`: [Option<&String>]: Collation of the model.
+`options`: [&BuildOptions]: Options containing the configured character set and collation.
+*/
+fn resolve_charset<'a>(
+    charset: Option<&'a String>,
+    collation: Option<&'a String>,
+    options: &'a BuildOptions,
+) -> (Option<&'a String>, Option<&'a String>) {
+    match charset {
+        Some(charset) => (Some(charset), collation),
+        None => (
+            options.charset.as_ref(),
+            collation.or(options.collation.as_ref()),
+        ),
+    }
+}
+
+/**
+Renames the auto-named constraints and indexes of a renamed postgres table to its new name.
+
+Postgres names constraints and indexes after their table, e.g. `user_pkey` or `user_group_fkey`,
+and keeps the names when the table is renamed. The names starting with the old table name and
+ending with one of the suffixes postgres generates are renamed. Indexes of constraints are
+renamed with their constraint.
+
+`old`: [&str]: Old name of the table.
+`new`: [&str]: New name of the table.
+*/
+fn postgres_rename_constraints(old: &str, new: &str) -> String {
+    let prefix = format!("{}_", old).replace('\'', "''");
+    let new = new.replace('\'', "''");
+    format!(
+        "DO $$ DECLARE r record; BEGIN \
+FOR r IN SELECT conname AS name FROM pg_constraint WHERE conrelid = '{new}'::regclass AND left(conname, {len}) = '{prefix}' AND conname ~ '_(pkey|key|fkey|check|excl)$' LOOP \
+EXECUTE format('ALTER TABLE %I RENAME CONSTRAINT %I TO %I', '{new}', r.name, '{new}' || substr(r.name, {len})); END LOOP; \
+FOR r IN SELECT c.relname AS name FROM pg_index AS i JOIN pg_class AS c ON c.oid = i.indexrelid WHERE i.indrelid = '{new}'::regclass AND left(c.relname, {len}) = '{prefix}' AND c.relname ~ '_(key|idx)$' AND NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conindid = c.oid) LOOP \
+EXECUTE format('ALTER INDEX %I RENAME TO %I', r.name, '{new}' || substr(r.name, {len})); END LOOP; \
+END $$;",
+        new = new,
+        prefix = prefix,
+        len = old.chars().count() + 1,
+    )
+}
+
+/**
+Wraps a postgres statement in a block, which ignores the given error conditions.
+
+`statement`: [&str]: The statement to execute.
+`conditions`: [&[&str]]: Names of the error conditions to ignore, e.g. `duplicate_object`.
+*/
+fn postgres_ignore_errors(statement: &str, conditions: &[&str]) -> String {
+    format!(
+        "DO $$ BEGIN {} EXCEPTION WHEN {} THEN NULL; END $$;",
+        statement,
+        conditions.join(" OR ")
+    )
+}
+
+/**
+Helper method to convert a migration to a transaction string
+
+Migrations which are not atomic are not wrapped in a transaction.
+
+`db_impl`: [DBImpl]: The database implementation to use.
+`migration`: [&Migration]: Reference to the migration that should be converted.
+`options`: [&BuildOptions]: Options for generating the SQL.
+*/
+pub fn migration_to_sql(
+    db_impl: DBImpl,
+    migration: &Migration,
+    options: &BuildOptions,
+) -> Result<String, MigrationError> {
+    let statements = migration_to_statements(db_impl, migration, options)?;
+
+    if !migration.atomic {
+        return Ok(statements.join(" "));
+    }
+
+    let mut transaction = db_impl.start_transaction();
+    for statement in statements {
+        transaction = transaction.add_statement(statement);
+    }
+
+    transaction.finish().map_err(|err| MigrationError::Build {
+        migration: migration.id.clone(),
+        source: err.into(),
+    })
+}
+
+/**
+Helper method to hide the defaults of sensitive fields in SQL of a migration, so it can be printed.
+
+The defaults are replaced in the form of every dialect, so statements of any dialect can be passed.
+
+`migration`: [&Migration]: The migration the SQL belongs to.
+`sql`: [&str]: The SQL to redact.
+*/
+pub fn redact_sensitive_defaults(migration: &Migration, sql: &str) -> String {
+    let mut redacted = sql.to_string();
+    for operation in &migration.operations {
+        let fields = match operation.inner() {
+            Operation::CreateModel { fields, .. } => fields.iter().collect(),
+            Operation::CreateField { field, .. } | Operation::AlterField { field, .. } => {
+                vec![field]
+            }
+            _ => vec![],
+        };
+        for field in fields.into_iter().filter(|x| {
+            x.annotations
+                .iter()
+                .any(|y| matches!(y, Annotation::Sensitive))
+        }) {
+            for annotation in field.annotations.iter().filter(|x| {
+                matches!(
+                    x,
+                    Annotation::DefaultValue(_) | Annotation::ServerDefault(_)
+                )
+            }) {
+                for db_impl in [DBImpl::SQLite, DBImpl::Postgres, DBImpl::MySQL] {
+                    if let Ok(clause) = SQLAnnotation::from(annotation.clone()).build(db_impl) {
+                        redacted = redacted.replace(clause.as_str(), "DEFAULT <redacted>");
+                    }
+                }
+            }
+        }
+    }
+    redacted
+}
+
+/**
+Helper method to convert a migration to SQL for every dialect, e.g. to compare the SQL in tests.
+
+`migration`: [&Migration]: Reference to the migration that should be converted.
+`options`: [&BuildOptions]: Options for generating the SQL.
+*/
+pub fn migration_to_sql_per_dialect(
+    migration: &Migration,
+    options: &BuildOptions,
+) -> Vec<(DBImpl, Result<String, MigrationError>)> {
+    [DBImpl::SQLite, DBImpl::MySQL, DBImpl::Postgres]
+        .into_iter()
+        .map(|db_impl| (db_impl, migration_to_sql(db_impl, migration, options)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{
+        Annotation, DbType, DefaultValue, Dialect, Field, ForeignKey, Identity, ModelAnnotation,
+        PartitionBy, PartitionStrategy, Persistence, ReferentialAction, StorageParameter,
+    };
+    use rorm_sql::DBImpl;
+
+    use crate::declaration::{EnumAction, IndexKind, Migration, Operation, TypeChange};
+    use crate::migrate::error::MigrationError;
+    use crate::migrate::server::ServerInfo;
+    use crate::migrate::sql_builder::{
+        affected_tables, analyze_statements, migration_to_sql, operation_statements,
+        ordered_renames, redact_sensitive_defaults, sorted_operations, BuildOptions,
+    };
+    use crate::utils::test_helpers;
+
+    fn migration(operations: Vec<Operation>) -> Migration {
+        test_helpers::migration("0002_test", "0001_initial", operations)
+    }
+
+    #[test]
+    fn all_dialects() {
+        let m = migration(vec![Operation::CreateModel {
+            name: "user".to_string(),
+            fields: vec![
+                Field {
+                    name: "id".to_string(),
+                    db_type: DbType::UInt64,
+                    annotations: vec![Annotation::PrimaryKey, Annotation::AutoIncrement],
+                    source_defined_at: None,
+                },
+                Field {
+                    name: "username".to_string(),
+                    db_type: DbType::VarChar,
+                    annotations: vec![Annotation::MaxLength(255), Annotation::NotNull],
+                    source_defined_at: None,
+                },
+            ],
+            annotations: vec![],
+        }]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE TABLE user (id INTEGER PRIMARY KEY AUTOINCREMENT,username TEXT NOT NULL) STRICT; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE TABLE user (id BIGSERIAL PRIMARY KEY,username VARCHAR(255) NOT NULL); COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; CREATE TABLE user (id BIGINT UNSIGNED PRIMARY KEY AUTO_INCREMENT,username VARCHAR(255) NOT NULL) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn rename_index() {
+        let m = migration(vec![Operation::RenameIndex {
+            model: "user".to_string(),
+            old: "user_name_index".to_string(),
+            new: "user_username_index".to_string(),
+            columns: vec!["username".to_string()],
+            unique: true,
+        }]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; ALTER INDEX user_name_index RENAME TO user_username_index; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; DROP INDEX user_name_index; CREATE UNIQUE INDEX user_username_index ON user (username); COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; DROP INDEX user_name_index ON user; CREATE UNIQUE INDEX user_username_index ON user (username); COMMIT;"
+        );
+
+        // Servers with RENAME INDEX keep the index instead of rebuilding it
+        let server = |version: &str| BuildOptions {
+            server: Some(ServerInfo {
+                db_impl: DBImpl::MySQL,
+                version: version.to_string(),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &server("8.0.33")).unwrap(),
+            "START TRANSACTION; ALTER TABLE user RENAME INDEX user_name_index TO user_username_index; COMMIT;"
+        );
+        assert!(migration_to_sql(DBImpl::MySQL, &m, &server("5.6.51"))
+            .unwrap()
+            .contains("DROP INDEX"));
+    }
+
+    #[test]
+    fn rename_model_constraints() {
+        // Post has the foreign key post_author_fkey, which keeps its name when the table is renamed
+        let m = migration(vec![Operation::RenameModel {
+            old: "post".to_string(),
+            new: "article".to_string(),
+            rename_constraints: true,
+        }]);
+
+        let statements = operation_statements(DBImpl::Postgres, &m, &BuildOptions::default())
+            .unwrap()
+            .remove(0)
+            .1;
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0], "ALTER TABLE post RENAME TO article;");
+        for part in [
+            "conrelid = 'article'::regclass AND left(conname, 5) = 'post_'",
+            "conname ~ '_(pkey|key|fkey|check|excl)$'",
+            "EXECUTE format('ALTER TABLE %I RENAME CONSTRAINT %I TO %I', 'article', r.name, 'article' || substr(r.name, 5))",
+            "EXECUTE format('ALTER INDEX %I RENAME TO %I', r.name, 'article' || substr(r.name, 5))",
+        ] {
+            assert!(statements[1].contains(part), "{} is missing {}", statements[1], part);
+        }
+
+        // The auto-names of other dialects don't contain the table name
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; ALTER TABLE post RENAME TO article; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; ALTER TABLE post RENAME TO article; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn rename_constraint() {
+        let m = migration(vec![Operation::RenameConstraint {
+            model: "user".to_string(),
+            old: "user_name_key".to_string(),
+            new: "user_username_key".to_string(),
+            definition: Some("UNIQUE (username)".to_string()),
+        }]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; ALTER TABLE user RENAME CONSTRAINT user_name_key TO user_username_key; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; ALTER TABLE user DROP CONSTRAINT user_name_key; ALTER TABLE user ADD CONSTRAINT user_username_key UNIQUE (username); COMMIT;"
+        );
+        assert!(migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).is_err());
+    }
+
+    #[test]
+    fn set_sequence() {
+        let m = migration(vec![Operation::SetSequence {
+            model: "user".to_string(),
+            column: "id".to_string(),
+            value: 1000,
+        }]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; DELETE FROM sqlite_sequence WHERE name = 'user'; INSERT INTO sqlite_sequence (name, seq) VALUES ('user', 999); COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; SELECT setval(pg_get_serial_sequence('user', 'id'), 1000, false); COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; ALTER TABLE user AUTO_INCREMENT = 1000; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn storage_options() {
+        let m = migration(vec![
+            Operation::CreateModel {
+                name: "log".to_string(),
+                fields: vec![Field {
+                    name: "id".to_string(),
+                    db_type: DbType::Int64,
+                    annotations: vec![],
+                    source_defined_at: None,
+                }],
+                annotations: vec![
+                    ModelAnnotation::Tablespace("fast".to_string()),
+                    ModelAnnotation::StorageParameter(StorageParameter {
+                        name: "fillfactor".to_string(),
+                        value: "70".to_string(),
+                    }),
+                ],
+            },
+            Operation::SetTablespace {
+                model: "log".to_string(),
+                tablespace: None,
+            },
+            Operation::SetStorageParameters {
+                model: "log".to_string(),
+                set: vec![StorageParameter {
+                    name: "autovacuum_enabled".to_string(),
+                    value: "false".to_string(),
+                }],
+                reset: vec!["fillfactor".to_string()],
+            },
+        ]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE TABLE log (id BIGINT) WITH (fillfactor=70) TABLESPACE fast; ALTER TABLE log SET TABLESPACE pg_default; ALTER TABLE log RESET (fillfactor); ALTER TABLE log SET (autovacuum_enabled=false); COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE TABLE log (id INTEGER) STRICT; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn partitions() {
+        let m = migration(vec![
+            Operation::AttachPartition {
+                model: "event".to_string(),
+                partition: "event_archive".to_string(),
+                bound: "FOR VALUES FROM (MINVALUE) TO ('2024-01-01')".to_string(),
+            },
+            Operation::CreatePartition {
+                model: "event".to_string(),
+                name: "event_2024".to_string(),
+                bound: "FOR VALUES FROM ('2024-01-01') TO ('2025-01-01')".to_string(),
+            },
+            Operation::CreateModel {
+                name: "event".to_string(),
+                fields: vec![Field {
+                    name: "created_at".to_string(),
+                    db_type: DbType::Datetime,
+                    annotations: vec![Annotation::NotNull],
+                    source_defined_at: None,
+                }],
+                annotations: vec![ModelAnnotation::PartitionBy(PartitionBy {
+                    strategy: PartitionStrategy::Range,
+                    columns: vec!["created_at".to_string()],
+                })],
+            },
+            Operation::DetachPartition {
+                model: "event".to_string(),
+                partition: "event_2023".to_string(),
+            },
+        ]);
+
+        // Partitions are created after their tables and attached after all tables exist
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE TABLE event (created_at TIMESTAMP NOT NULL) PARTITION BY RANGE (created_at); \
+            CREATE TABLE event_2024 PARTITION OF event FOR VALUES FROM ('2024-01-01') TO ('2025-01-01'); \
+            ALTER TABLE event ATTACH PARTITION event_archive FOR VALUES FROM (MINVALUE) TO ('2024-01-01'); \
+            ALTER TABLE event DETACH PARTITION event_2023; COMMIT;"
+        );
+        for db_impl in [DBImpl::SQLite, DBImpl::MySQL] {
+            assert!(format!(
+                "{:?}",
+                migration_to_sql(db_impl, &m, &BuildOptions::default()).unwrap_err()
+            )
+            .contains("doesn't support partitioned tables"));
+        }
+    }
+
+    #[test]
+    fn extensions() {
+        let m = migration(vec![
+            Operation::CreateModel {
+                name: "session".to_string(),
+                fields: vec![Field {
+                    name: "id".to_string(),
+                    db_type: DbType::VarChar,
+                    annotations: vec![
+                        Annotation::PrimaryKey,
+                        Annotation::MaxLength(36),
+                        Annotation::ServerDefault("uuid_generate_v4()".to_string()),
+                    ],
+                    source_defined_at: None,
+                }],
+                annotations: vec![],
+            },
+            Operation::CreateExtension {
+                name: "uuid-ossp".to_string(),
+                if_not_exists: true,
+            },
+        ]);
+
+        // Extensions are created before the tables depending on them
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE EXTENSION IF NOT EXISTS \"uuid-ossp\"; \
+            CREATE TABLE session (id VARCHAR(36) PRIMARY KEY DEFAULT uuid_generate_v4()); COMMIT;"
+        );
+        assert!(
+            operation_statements(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap()[0]
+                .1
+                .is_empty()
+        );
+
+        // and dropped after them
+        let m = migration(vec![
+            Operation::DropExtension {
+                name: "uuid-ossp".to_string(),
+                if_exists: false,
+            },
+            Operation::DeleteModel {
+                name: "session".to_string(),
+                cascade: false,
+            },
+        ]);
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; DROP TABLE session; DROP EXTENSION \"uuid-ossp\"; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn uuid_default() {
+        let m = migration(vec![Operation::CreateModel {
+            name: "session".to_string(),
+            fields: vec![Field {
+                name: "id".to_string(),
+                db_type: DbType::Uuid,
+                annotations: vec![Annotation::PrimaryKey, Annotation::DefaultUuid],
+                source_defined_at: None,
+            }],
+            annotations: vec![],
+        }]);
+        let server = |version: &str| BuildOptions {
+            server: Some(ServerInfo {
+                db_impl: DBImpl::Postgres,
+                version: version.to_string(),
+            }),
+            ..Default::default()
+        };
+
+        // Postgres before 13 provides gen_random_uuid() with pgcrypto
+        let sql = "BEGIN; CREATE EXTENSION IF NOT EXISTS \"pgcrypto\"; \
+            CREATE TABLE session (id UUID PRIMARY KEY DEFAULT gen_random_uuid()); COMMIT;";
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &server("PostgreSQL 12.15")).unwrap(),
+            sql
+        );
+        // The extension is created if the version is unknown
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            sql
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &server("PostgreSQL 15.3")).unwrap(),
+            "BEGIN; CREATE TABLE session (id UUID PRIMARY KEY DEFAULT gen_random_uuid()); COMMIT;"
+        );
+
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; CREATE TABLE session (id CHAR(36) PRIMARY KEY DEFAULT (UUID())) \
+            DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci; COMMIT;"
+        );
+        assert!(
+            !migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default())
+                .unwrap()
+                .contains("EXTENSION")
+        );
+    }
+
+    #[test]
+    fn citext() {
+        let email = Field {
+            name: "email".to_string(),
+            db_type: DbType::Citext,
+            annotations: vec![Annotation::MaxLength(255), Annotation::Unique],
+            source_defined_at: None,
+        };
+        let m = migration(vec![
+            Operation::CreateModel {
+                name: "user".to_string(),
+                fields: vec![email.clone()],
+                annotations: vec![],
+            },
+            Operation::CreateField {
+                model: "newsletter".to_string(),
+                field: email.clone(),
+            },
+        ]);
+
+        // The extension is created once, before the first table using it
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE EXTENSION IF NOT EXISTS \"citext\"; \
+            CREATE TABLE user (email CITEXT UNIQUE); \
+            ALTER TABLE newsletter ADD COLUMN email CITEXT UNIQUE; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; CREATE TABLE user (email VARCHAR(255) COLLATE utf8mb4_general_ci UNIQUE) \
+            DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci; \
+            ALTER TABLE newsletter ADD COLUMN email VARCHAR(255) COLLATE utf8mb4_general_ci UNIQUE; COMMIT;"
+        );
+
+        // An extension created by the migration itself isn't created twice
+        let m = migration(vec![
+            Operation::CreateModel {
+                name: "user".to_string(),
+                fields: vec![email],
+                annotations: vec![],
+            },
+            Operation::CreateExtension {
+                name: "citext".to_string(),
+                if_not_exists: false,
+            },
+        ]);
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE EXTENSION \"citext\"; CREATE TABLE user (email CITEXT UNIQUE); COMMIT;"
+        );
+    }
+
+    #[test]
+    fn inheritance() {
+        let field = |name: &str| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+        let m = migration(vec![
+            Operation::NoInherit {
+                model: "employee".to_string(),
+                parent: "person".to_string(),
+            },
+            Operation::CreateModel {
+                name: "contractor".to_string(),
+                fields: vec![field("id"), field("rate")],
+                annotations: vec![ModelAnnotation::Inherits("person".to_string())],
+            },
+            Operation::SetInherit {
+                model: "employee".to_string(),
+                parent: "account".to_string(),
+            },
+        ]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; ALTER TABLE employee NO INHERIT person; \
+            CREATE TABLE contractor (id BIGINT,rate BIGINT) INHERITS (person); \
+            ALTER TABLE employee INHERIT account; COMMIT;"
+        );
+        for db_impl in [DBImpl::SQLite, DBImpl::MySQL] {
+            assert!(migration_to_sql(db_impl, &m, &BuildOptions::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn unique_together() {
+        let field = |name: &str| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+        let m = migration(vec![
+            Operation::CreateUnique {
+                model: "member".to_string(),
+                name: "member_group_user_key".to_string(),
+                columns: vec!["user".to_string(), "group".to_string()],
+                nulls_not_distinct: false,
+            },
+            Operation::DeleteField {
+                model: "member".to_string(),
+                name: "role".to_string(),
+                cascade: false,
+            },
+            Operation::DropUnique {
+                model: "member".to_string(),
+                name: "member_role_user_key".to_string(),
+                columns: vec!["user".to_string(), "role".to_string()],
+            },
+            Operation::CreateModel {
+                name: "team".to_string(),
+                fields: vec![field("owner"), field("name")],
+                annotations: vec![ModelAnnotation::UniqueTogether(vec![
+                    "owner".to_string(),
+                    "name".to_string(),
+                ])],
+            },
+        ]);
+
+        // Unique constraints are dropped before their columns, the tables get theirs after creation
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; DROP INDEX member_role_user_key; ALTER TABLE member DROP COLUMN role; \
+            CREATE TABLE team (owner BIGINT,name BIGINT); CREATE UNIQUE INDEX team_name_owner_key ON team (owner,name); \
+            CREATE UNIQUE INDEX member_group_user_key ON member (user,group); COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; DROP INDEX member_role_user_key ON member; ALTER TABLE member DROP COLUMN role; \
+            CREATE TABLE team (owner BIGINT,name BIGINT) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci; \
+            CREATE UNIQUE INDEX team_name_owner_key ON team (owner,name); \
+            CREATE UNIQUE INDEX member_group_user_key ON member (user,group); COMMIT;"
+        );
+    }
+
+    #[test]
+    fn model_indexes() {
+        let field = |name: &str| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+        let m = migration(vec![
+            Operation::DeleteField {
+                model: "event".to_string(),
+                name: "kind".to_string(),
+                cascade: false,
+            },
+            Operation::DropIndex {
+                model: "event".to_string(),
+                name: "event_kind_idx".to_string(),
+                columns: vec!["kind".to_string()],
+            },
+            Operation::CreateModel {
+                name: "visit".to_string(),
+                fields: vec![field("tenant_id"), field("created_at")],
+                annotations: vec![ModelAnnotation::Index(vec![
+                    "tenant_id".to_string(),
+                    "created_at".to_string(),
+                ])],
+            },
+        ]);
+
+        // Indexes are dropped before their columns, the tables get theirs after creation
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; DROP INDEX event_kind_idx; ALTER TABLE event DROP COLUMN kind; \
+            CREATE TABLE visit (tenant_id BIGINT,created_at BIGINT); \
+            CREATE INDEX visit_tenant_id_created_at_idx ON visit (tenant_id,created_at); COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(
+                DBImpl::SQLite,
+                &migration(vec![m.operations[1].clone()]),
+                &BuildOptions {
+                    idempotent: true,
+                    ..Default::default()
+                }
+            )
+            .unwrap(),
+            "BEGIN; DROP INDEX IF EXISTS event_kind_idx; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn nulls_not_distinct() {
+        let m = migration(vec![
+            Operation::CreateField {
+                model: "user".to_string(),
+                field: Field {
+                    name: "email".to_string(),
+                    db_type: DbType::VarChar,
+                    annotations: vec![
+                        Annotation::MaxLength(255),
+                        Annotation::Unique,
+                        Annotation::NullsNotDistinct,
+                    ],
+                    source_defined_at: None,
+                },
+            },
+            Operation::CreateUnique {
+                model: "member".to_string(),
+                name: "member_group_user_key".to_string(),
+                columns: vec!["user".to_string(), "group".to_string()],
+                nulls_not_distinct: true,
+            },
+        ]);
+        let server = |version: &str| BuildOptions {
+            server: Some(ServerInfo {
+                db_impl: DBImpl::Postgres,
+                version: version.to_string(),
+            }),
+            ..Default::default()
+        };
+
+        let sql = "BEGIN; ALTER TABLE user ADD COLUMN email VARCHAR(255) UNIQUE NULLS NOT DISTINCT; \
+            CREATE UNIQUE INDEX member_group_user_key ON member (user,group) NULLS NOT DISTINCT; COMMIT;";
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &server("PostgreSQL 15.3")).unwrap(),
+            sql
+        );
+        // The clause is generated if the version is unknown
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            sql
+        );
+        assert!(matches!(
+            migration_to_sql(DBImpl::Postgres, &m, &server("PostgreSQL 14.8")),
+            Err(MigrationError::Build { source, .. }) if source.to_string().contains("requires Postgres 15")
+        ));
+
+        // Other dialects treat the columns as unique
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; ALTER TABLE user ADD COLUMN email VARCHAR(255) UNIQUE; \
+            CREATE UNIQUE INDEX member_group_user_key ON member (user,group); COMMIT;"
+        );
+    }
+
+    #[test]
+    fn mysql_charset() {
+        let create_model = |name: &str, annotations| Operation::CreateModel {
+            name: name.to_string(),
+            fields: vec![Field {
+                name: "id".to_string(),
+                db_type: DbType::Int64,
+                annotations: vec![],
+                source_defined_at: None,
+            }],
+            annotations,
+        };
+        let m = migration(vec![
+            create_model("log", vec![]),
+            create_model(
+                "legacy",
+                vec![ModelAnnotation::Charset("latin1".to_string())],
+            ),
+            Operation::SetCharset {
+                model: "log".to_string(),
+                charset: None,
+                collation: Some("utf8mb4_bin".to_string()),
+            },
+        ]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; CREATE TABLE log (id BIGINT) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci; CREATE TABLE legacy (id BIGINT) DEFAULT CHARSET=latin1; ALTER TABLE log CONVERT TO CHARACTER SET utf8mb4 COLLATE utf8mb4_bin; COMMIT;"
+        );
+
+        // The configured charset is overridden by the model
+        let options = BuildOptions {
+            charset: Some("utf8mb3".to_string()),
+            collation: Some("utf8mb3_bin".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &options).unwrap(),
+            "START TRANSACTION; CREATE TABLE log (id BIGINT) DEFAULT CHARSET=utf8mb3 COLLATE=utf8mb3_bin; CREATE TABLE legacy (id BIGINT) DEFAULT CHARSET=latin1; ALTER TABLE log CONVERT TO CHARACTER SET utf8mb3 COLLATE utf8mb4_bin; COMMIT;"
+        );
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &options).unwrap(),
+            "BEGIN; CREATE TABLE log (id BIGINT); CREATE TABLE legacy (id BIGINT); COMMIT;"
+        );
+    }
+
+    #[test]
+    fn unlogged_table() {
+        let m = migration(vec![Operation::CreateModel {
+            name: "cache".to_string(),
+            fields: vec![Field {
+                name: "id".to_string(),
+                db_type: DbType::Int64,
+                annotations: vec![],
+                source_defined_at: None,
+            }],
+            annotations: vec![
+                ModelAnnotation::Persistence(Persistence::Unlogged),
+                ModelAnnotation::StorageParameter(StorageParameter {
+                    name: "fillfactor".to_string(),
+                    value: "70".to_string(),
+                }),
+            ],
+        }]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE UNLOGGED TABLE cache (id BIGINT) WITH (fillfactor=70); COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE TABLE cache (id INTEGER) STRICT; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn create_index_concurrently() {
+        let mut m = migration(vec![Operation::CreateIndex {
+            model: "user".to_string(),
+            name: "user_name_index".to_string(),
+            columns: vec!["name".to_string()],
+            unique: false,
+            concurrently: true,
+            kind: IndexKind::Index,
+        }]);
+
+        assert!(migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).is_err());
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE INDEX user_name_index ON user (name); COMMIT;"
+        );
+
+        m.atomic = false;
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "CREATE INDEX CONCURRENTLY user_name_index ON user (name);"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "CREATE INDEX user_name_index ON user (name);"
+        );
+    }
+
+    #[test]
+    fn phase_order() {
+        let field = |name: &str| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+
+        let m = migration(vec![
+            Operation::CreateIndex {
+                model: "user".to_string(),
+                name: "user_age_index".to_string(),
+                columns: vec!["age".to_string()],
+                unique: false,
+                concurrently: false,
+                kind: IndexKind::Index,
+            },
+            Operation::DeleteModel {
+                name: "group".to_string(),
+                cascade: false,
+            },
+            Operation::CreateField {
+                model: "user".to_string(),
+                field: field("age"),
+            },
+            Operation::SetSequence {
+                model: "user".to_string(),
+                column: "id".to_string(),
+                value: 10,
+            },
+            Operation::CreateModel {
+                name: "user".to_string(),
+                fields: vec![field("id")],
+                annotations: vec![],
+            },
+            Operation::DeleteField {
+                model: "member".to_string(),
+                name: "group".to_string(),
+                cascade: false,
+            },
+        ]);
+
+        let names: Vec<_> = sorted_operations(&m)
+            .into_iter()
+            .map(|operation| match operation {
+                Operation::CreateModel { .. } => "CreateModel",
+                Operation::DeleteModel { .. } => "DeleteModel",
+                Operation::CreateField { .. } => "CreateField",
+                Operation::DeleteField { .. } => "DeleteField",
+                Operation::CreateIndex { .. } => "CreateIndex",
+                Operation::SetSequence { .. } => "SetSequence",
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "DeleteField",
+                "DeleteModel",
+                "CreateModel",
+                "CreateField",
+                "CreateIndex",
+                "SetSequence"
+            ]
+        );
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; ALTER TABLE member DROP COLUMN group; DROP TABLE group; CREATE TABLE user (id BIGINT); ALTER TABLE user ADD COLUMN age BIGINT; CREATE INDEX user_age_index ON user (age); SELECT setval(pg_get_serial_sequence('user', 'id'), 10, false); COMMIT;"
+        );
+    }
+
+    #[test]
+    fn deferrable_foreign_keys() {
+        let field = |name: &str| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+        let foreign_key =
+            |model: &str, column: &str, table_name: &str| Operation::CreateForeignKey {
+                model: model.to_string(),
+                name: format!("{}_{}_fkey", model, column),
+                column: column.to_string(),
+                foreign_key: ForeignKey {
+                    table_name: table_name.to_string(),
+                    column_name: "id".to_string(),
+                    deferrable: true,
+                    initially_deferred: true,
+                    on_delete: ReferentialAction::NoAction,
+                    on_update: ReferentialAction::NoAction,
+                },
+                not_valid: false,
+            };
+
+        let m = migration(vec![
+            Operation::CreateModel {
+                name: "a".to_string(),
+                fields: vec![field("id"), field("b")],
+                annotations: vec![],
+            },
+            foreign_key("a", "b", "b"),
+            Operation::CreateModel {
+                name: "b".to_string(),
+                fields: vec![field("id"), field("a")],
+                annotations: vec![],
+            },
+            foreign_key("b", "a", "a"),
+        ]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE TABLE a (id BIGINT,b BIGINT); CREATE TABLE b (id BIGINT,a BIGINT); ALTER TABLE a ADD CONSTRAINT a_b_fkey FOREIGN KEY (b) REFERENCES b (id) DEFERRABLE INITIALLY DEFERRED; ALTER TABLE b ADD CONSTRAINT b_a_fkey FOREIGN KEY (a) REFERENCES a (id) DEFERRABLE INITIALLY DEFERRED; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; CREATE TABLE a (id BIGINT,b BIGINT) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci; CREATE TABLE b (id BIGINT,a BIGINT) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci; ALTER TABLE a ADD CONSTRAINT a_b_fkey FOREIGN KEY (b) REFERENCES b (id); ALTER TABLE b ADD CONSTRAINT b_a_fkey FOREIGN KEY (a) REFERENCES a (id); COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE TABLE a (id INTEGER,b INTEGER,CONSTRAINT a_b_fkey FOREIGN KEY (b) REFERENCES b (id) DEFERRABLE INITIALLY DEFERRED) STRICT; CREATE TABLE b (id INTEGER,a INTEGER,CONSTRAINT b_a_fkey FOREIGN KEY (a) REFERENCES a (id) DEFERRABLE INITIALLY DEFERRED) STRICT; COMMIT;"
+        );
+
+        // SQLite rebuilds existing tables to add foreign keys, which requires the previous state
+        assert!(matches!(
+            migration_to_sql(DBImpl::SQLite, &migration(vec![foreign_key("a", "b", "b")]), &BuildOptions::default()),
+            Err(MigrationError::Build { migration, .. }) if migration == "0002_test"
+        ));
+    }
+
+    #[test]
+    fn idempotent() {
+        let field = |name: &str| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+
+        let m = migration(vec![
+            Operation::CreateModel {
+                name: "user".to_string(),
+                fields: vec![field("id")],
+                annotations: vec![],
+            },
+            Operation::CreateField {
+                model: "user".to_string(),
+                field: field("age"),
+            },
+            Operation::RenameField {
+                table_name: "user".to_string(),
+                old: "age".to_string(),
+                new: "years".to_string(),
+            },
+            Operation::CreateIndex {
+                model: "user".to_string(),
+                name: "user_years_index".to_string(),
+                columns: vec!["years".to_string()],
+                unique: false,
+                concurrently: false,
+                kind: IndexKind::Index,
+            },
+            Operation::DeleteModel {
+                name: "group".to_string(),
+                cascade: false,
+            },
+        ]);
+        let idempotent = BuildOptions {
+            idempotent: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &idempotent).unwrap(),
+            "BEGIN; DROP TABLE IF EXISTS group; CREATE TABLE IF NOT EXISTS user (id BIGINT); ALTER TABLE user ADD COLUMN IF NOT EXISTS age BIGINT; DO $$ BEGIN ALTER TABLE user RENAME COLUMN age TO years; EXCEPTION WHEN undefined_column THEN NULL; END $$; CREATE INDEX IF NOT EXISTS user_years_index ON user (years); COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &idempotent).unwrap(),
+            "BEGIN; DROP TABLE IF EXISTS group; CREATE TABLE IF NOT EXISTS user (id INTEGER) STRICT; ALTER TABLE user ADD COLUMN age INTEGER; ALTER TABLE user RENAME COLUMN age TO years; CREATE INDEX IF NOT EXISTS user_years_index ON user (years); COMMIT;"
+        );
+        // MySQL has no IF NOT EXISTS for indexes, these are skipped when applying instead
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &idempotent).unwrap(),
+            "START TRANSACTION; DROP TABLE IF EXISTS group; CREATE TABLE IF NOT EXISTS user (id BIGINT) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci; ALTER TABLE user ADD COLUMN age BIGINT; ALTER TABLE user RENAME COLUMN age TO years; CREATE INDEX user_years_index ON user (years); COMMIT;"
+        );
+    }
+
+    #[test]
+    fn idempotent_delete_field() {
+        let m = migration(vec![Operation::DeleteField {
+            model: "user".to_string(),
+            name: "age".to_string(),
+            cascade: false,
+        }]);
+        let idempotent = BuildOptions {
+            idempotent: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &idempotent).unwrap(),
+            "BEGIN; ALTER TABLE user DROP COLUMN IF EXISTS age; COMMIT;"
+        );
+        // MySQL has no IF EXISTS, the column is looked up when applying instead
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &idempotent).unwrap(),
+            "START TRANSACTION; ALTER TABLE user DROP COLUMN age; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn truncate() {
+        let m = migration(vec![Operation::Truncate {
+            model: "user".to_string(),
+            cascade: true,
+            restart_identity: true,
+        }]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; TRUNCATE TABLE user RESTART IDENTITY CASCADE; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; TRUNCATE TABLE user; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; DELETE FROM user; DELETE FROM sqlite_sequence WHERE name = 'user'; COMMIT;"
+        );
+
+        let m = migration(vec![Operation::Truncate {
+            model: "user".to_string(),
+            cascade: false,
+            restart_identity: false,
+        }]);
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; TRUNCATE TABLE user; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; DELETE FROM user; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn not_valid_constraints() {
+        let m = migration(vec![
+            Operation::CreateCheck {
+                model: "user".to_string(),
+                name: "user_age_check".to_string(),
+                expression: "age >= 0".to_string(),
+                not_valid: true,
+            },
+            Operation::ValidateConstraint {
+                model: "user".to_string(),
+                name: "user_age_check".to_string(),
+            },
+        ]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; ALTER TABLE user ADD CONSTRAINT user_age_check CHECK (age >= 0) NOT VALID; ALTER TABLE user VALIDATE CONSTRAINT user_age_check; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; ALTER TABLE user ADD CONSTRAINT user_age_check CHECK (age >= 0); COMMIT;"
+        );
+        assert!(migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).is_err());
+    }
+
+    #[test]
+    fn identity_from_serial() {
+        let m = migration(vec![
+            Operation::AddIdentity {
+                model: "user".to_string(),
+                column: "id".to_string(),
+                identity: Identity::Always,
+                from_serial: true,
+            },
+            Operation::DropIdentity {
+                model: "post".to_string(),
+                column: "id".to_string(),
+            },
+        ]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; ALTER TABLE user ALTER COLUMN id DROP DEFAULT; DROP SEQUENCE IF EXISTS user_id_seq; ALTER TABLE user ALTER COLUMN id ADD GENERATED ALWAYS AS IDENTITY; SELECT setval(pg_get_serial_sequence('user', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM user; ALTER TABLE post ALTER COLUMN id DROP IDENTITY; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION;  COMMIT;"
+        );
+    }
+
+    #[test]
+    fn rename_fields() {
+        let renames = |x: &[(&str, &str)]| {
+            x.iter()
+                .map(|(old, new)| (old.to_string(), new.to_string()))
+                .collect::<Vec<(String, String)>>()
+        };
+
+        // Renames to a name which is renamed itself come last
+        assert_eq!(
+            ordered_renames(&renames(&[("a", "b"), ("b", "c")])),
+            renames(&[("b", "c"), ("a", "b")])
+        );
+        // Cycles use a temporary name
+        assert_eq!(
+            ordered_renames(&renames(&[("a", "b"), ("b", "a"), ("c", "c")])),
+            renames(&[("a", "a__drorm_tmp"), ("b", "a"), ("a__drorm_tmp", "b")])
+        );
+        assert_eq!(
+            ordered_renames(&renames(&[("a", "b"), ("b", "a"), ("a__drorm_tmp", "d")])),
+            renames(&[
+                ("a__drorm_tmp", "d"),
+                ("a", "a__drorm_tmp_"),
+                ("b", "a"),
+                ("a__drorm_tmp_", "b")
+            ])
+        );
+
+        let m = migration(vec![Operation::RenameFields {
+            table_name: "user".to_string(),
+            renames: renames(&[("first", "last"), ("last", "first")]),
+        }]);
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; ALTER TABLE user RENAME COLUMN first TO first__drorm_tmp; ALTER TABLE user RENAME COLUMN last TO first; ALTER TABLE user RENAME COLUMN first__drorm_tmp TO last; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn run_sql() {
+        let mut m = migration(vec![Operation::RunSql {
+            sql: "UPDATE user SET age = 0 WHERE age IS NULL".to_string(),
+            timeout_seconds: Some(30),
+        }]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; SET LOCAL statement_timeout = 30000; UPDATE user SET age = 0 WHERE age IS NULL; SET LOCAL statement_timeout = DEFAULT; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; UPDATE user SET age = 0 WHERE age IS NULL; COMMIT;"
+        );
+
+        m.atomic = false;
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "SET statement_timeout = 30000; UPDATE user SET age = 0 WHERE age IS NULL; SET statement_timeout = DEFAULT;"
+        );
+    }
+
+    #[test]
+    fn lock_timeout() {
+        let mut m = migration(vec![Operation::RunSql {
+            sql: "ALTER TABLE user ADD CONSTRAINT age CHECK (age >= 0)".to_string(),
+            timeout_seconds: None,
+        }]);
+        m.lock_timeout_ms = Some(1500);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; SET LOCAL lock_timeout = '1500ms'; ALTER TABLE user ADD CONSTRAINT age CHECK (age >= 0); SET LOCAL lock_timeout = DEFAULT; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; SET SESSION lock_wait_timeout = 2; ALTER TABLE user ADD CONSTRAINT age CHECK (age >= 0); SET SESSION lock_wait_timeout = DEFAULT; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; PRAGMA busy_timeout = 1500; ALTER TABLE user ADD CONSTRAINT age CHECK (age >= 0); PRAGMA busy_timeout = 5000; COMMIT;"
+        );
+
+        m.atomic = false;
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "SET lock_timeout = '1500ms'; ALTER TABLE user ADD CONSTRAINT age CHECK (age >= 0); SET lock_timeout = DEFAULT;"
+        );
+    }
+
+    #[test]
+    fn analyze() {
+        let m = migration(vec![
+            Operation::DropIndex {
+                model: "post".to_string(),
+                name: "post_title_index".to_string(),
+                columns: vec!["title".to_string()],
+            },
+            Operation::RenameModel {
+                old: "post".to_string(),
+                new: "article".to_string(),
+                rename_constraints: false,
+            },
+            Operation::CreateIndex {
+                model: "article".to_string(),
+                name: "article_title_index".to_string(),
+                columns: vec!["title".to_string()],
+                unique: false,
+                concurrently: false,
+                kind: IndexKind::Index,
+            },
+            Operation::DeleteField {
+                model: "user".to_string(),
+                name: "age".to_string(),
+                cascade: false,
+            },
+            Operation::DeleteModel {
+                name: "tag".to_string(),
+                cascade: false,
+            },
+            Operation::Only {
+                dialects: vec![Dialect::Postgres],
+                operation: Box::new(Operation::Truncate {
+                    model: "session".to_string(),
+                    cascade: false,
+                    restart_identity: false,
+                }),
+            },
+            Operation::RunSql {
+                sql: "UPDATE comment SET flagged = false".to_string(),
+                timeout_seconds: None,
+            },
+        ]);
+
+        // Renamed tables by their new name, dropped tables and raw SQL are left out
+        assert_eq!(
+            affected_tables(DBImpl::SQLite, &m),
+            vec!["user".to_string(), "article".to_string()]
+        );
+        let tables = affected_tables(DBImpl::Postgres, &m);
+        assert_eq!(tables, vec!["user", "article", "session"]);
+
+        assert_eq!(
+            analyze_statements(DBImpl::Postgres, &tables),
+            vec!["ANALYZE user;", "ANALYZE article;", "ANALYZE session;"]
+        );
+        assert_eq!(
+            analyze_statements(DBImpl::MySQL, &affected_tables(DBImpl::MySQL, &m)),
+            vec!["ANALYZE TABLE user;", "ANALYZE TABLE article;"]
+        );
+        assert_eq!(
+            analyze_statements(DBImpl::SQLite, &affected_tables(DBImpl::SQLite, &m)),
+            vec!["ANALYZE user;", "ANALYZE article;"]
+        );
+    }
+
+    #[test]
+    fn dialect_filter() {
+        // Stands in for a PostGIS geometry column
+        let location = Field {
+            name: "location".to_string(),
+            db_type: DbType::VarBinary,
+            annotations: vec![Annotation::Only(vec![Dialect::Postgres])],
+            source_defined_at: None,
+        };
+        let id = Field {
+            name: "id".to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+
+        let m = migration(vec![
+            Operation::CreateModel {
+                name: "place".to_string(),
+                fields: vec![id, location.clone()],
+                annotations: vec![],
+            },
+            Operation::Only {
+                dialects: vec![Dialect::Postgres],
+                operation: Box::new(Operation::CreateField {
+                    model: "user".to_string(),
+                    field: Field {
+                        name: "home".to_string(),
+                        ..location
+                    },
+                }),
+            },
+            Operation::Only {
+                dialects: vec![Dialect::Postgres],
+                operation: Box::new(Operation::CreateIndex {
+                    model: "user".to_string(),
+                    name: "user_home_index".to_string(),
+                    columns: vec!["home".to_string()],
+                    unique: false,
+                    concurrently: false,
+                    kind: IndexKind::Index,
+                }),
+            },
+        ]);
+
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE TABLE place (id BIGINT,location BYTEA); ALTER TABLE user ADD COLUMN home BYTEA; CREATE INDEX user_home_index ON user (home); COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; CREATE TABLE place (id INTEGER) STRICT; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn redact_sensitive() {
+        let field = |name: &str, annotations| Field {
+            name: name.to_string(),
+            db_type: DbType::VarBinary,
+            annotations,
+            source_defined_at: None,
+        };
+        let m = migration(vec![
+            Operation::CreateModel {
+                name: "account".to_string(),
+                fields: vec![field(
+                    "token",
+                    vec![
+                        Annotation::Sensitive,
+                        Annotation::DefaultValue(DefaultValue::String("cafe".to_string())),
+                    ],
+                )],
+                annotations: vec![],
+            },
+            Operation::CreateField {
+                model: "account".to_string(),
+                field: field(
+                    "secret",
+                    vec![
+                        Annotation::Sensitive,
+                        Annotation::ServerDefault("gen_random_bytes(16)".to_string()),
+                    ],
+                ),
+            },
+        ]);
+
+        for db_impl in [DBImpl::SQLite, DBImpl::Postgres, DBImpl::MySQL] {
+            let sql = migration_to_sql(db_impl, &m, &BuildOptions::default()).unwrap();
+            assert!(sql.contains("'cafe'") && sql.contains("gen_random_bytes"));
+            if db_impl == DBImpl::Postgres {
+                assert!(sql.contains("token BYTEA") && sql.contains("secret BYTEA"));
+            }
+            let redacted = redact_sensitive_defaults(&m, sql.as_str());
+            assert!(!redacted.contains("cafe") && !redacted.contains("gen_random_bytes"));
+            assert_eq!(redacted.matches("DEFAULT <redacted>").count(), 2);
+        }
+    }
+
+    #[test]
+    fn alter_field() {
+        let alter_field = |db_type, change| {
+            migration(vec![Operation::AlterField {
+                model: "user".to_string(),
+                field: Field {
+                    name: "age".to_string(),
+                    db_type,
+                    annotations: vec![Annotation::NotNull],
+                    source_defined_at: None,
+                },
+                change,
+            }])
+        };
+
+        let m = alter_field(DbType::Int64, TypeChange::Widening);
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+            "BEGIN; ALTER TABLE user ALTER COLUMN age TYPE BIGINT; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; ALTER TABLE user MODIFY COLUMN age BIGINT NOT NULL; COMMIT;"
+        );
+        // The storage class of the column doesn't change on SQLite
+        assert!(
+            operation_statements(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap()[0]
+                .1
+                .is_empty()
+        );
+
+        let m = alter_field(DbType::VarChar, TypeChange::Incompatible);
+        assert!(migration_to_sql(DBImpl::SQLite, &m, &BuildOptions::default()).is_err());
+    }
+
+    #[test]
+    fn alter_max_length() {
+        let alter_max_length = |max_length, change| {
+            migration(vec![Operation::AlterField {
+                model: "user".to_string(),
+                field: Field {
+                    name: "name".to_string(),
+                    db_type: DbType::VarChar,
+                    annotations: vec![Annotation::NotNull, Annotation::MaxLength(max_length)],
+                    source_defined_at: None,
+                },
+                change,
+            }])
+        };
+
+        for (max_length, change) in [(255, TypeChange::Widening), (100, TypeChange::Narrowing)] {
+            let m = alter_max_length(max_length, change);
+            assert_eq!(
+                migration_to_sql(DBImpl::Postgres, &m, &BuildOptions::default()).unwrap(),
+                format!(
+                    "BEGIN; ALTER TABLE user ALTER COLUMN name TYPE VARCHAR({}); COMMIT;",
+                    max_length
+                )
+            );
+            assert_eq!(
+                migration_to_sql(DBImpl::MySQL, &m, &BuildOptions::default()).unwrap(),
+                format!(
+                    "START TRANSACTION; ALTER TABLE user MODIFY COLUMN name VARCHAR({}) NOT NULL; COMMIT;",
+                    max_length
+                )
+            );
+            // SQLite doesn't enforce the length of text columns
+            assert!(
+                operation_statements(DBImpl::SQLite, &m, &BuildOptions::default()).unwrap()[0]
+                    .1
+                    .is_empty()
+            );
+        }
+    }
+
+    #[test]
+    fn alter_enum() {
+        let alter_enum = |choices: &[&str], action| {
+            migration(vec![Operation::AlterEnum {
+                model: "user".to_string(),
+                field: Field {
+                    name: "color".to_string(),
+                    db_type: DbType::Choices,
+                    annotations: vec![
+                        Annotation::NotNull,
+                        Annotation::Choices(choices.iter().map(|x| x.to_string()).collect()),
+                    ],
+                    source_defined_at: None,
+                },
+                action,
+            }])
+        };
+
+        let add = alter_enum(
+            &["red", "teal"],
+            EnumAction::Add {
+                value: "teal".to_string(),
+            },
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &add, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; ALTER TABLE user MODIFY COLUMN color ENUM('red','teal') NOT NULL; COMMIT;"
+        );
+        // The values are stored as strings, so nothing has to be changed
+        for db_impl in [DBImpl::Postgres, DBImpl::SQLite] {
+            assert!(
+                operation_statements(db_impl, &add, &BuildOptions::default()).unwrap()[0]
+                    .1
+                    .is_empty()
+            );
+        }
+
+        let rename = alter_enum(
+            &["red", "gray"],
+            EnumAction::Rename {
+                old: "grey".to_string(),
+                new: "gray".to_string(),
+            },
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::MySQL, &rename, &BuildOptions::default()).unwrap(),
+            "START TRANSACTION; ALTER TABLE user MODIFY COLUMN color ENUM('red','gray','grey') NOT NULL; UPDATE user SET color = 'gray' WHERE color = 'grey'; ALTER TABLE user MODIFY COLUMN color ENUM('red','gray') NOT NULL; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::Postgres, &rename, &BuildOptions::default()).unwrap(),
+            "BEGIN; UPDATE user SET color = 'gray' WHERE color = 'grey'; COMMIT;"
+        );
+        assert_eq!(
+            migration_to_sql(DBImpl::SQLite, &rename, &BuildOptions::default()).unwrap(),
+            "BEGIN; UPDATE user SET color = 'gray' WHERE color = 'grey'; COMMIT;"
+        );
+    }
 }
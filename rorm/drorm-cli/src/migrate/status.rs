@@ -0,0 +1,162 @@
+use std::fmt::{Display, Formatter};
+
+use rorm_sql::imr::Dialect;
+use serde::Serialize;
+
+use crate::utils::output::{paint, Style};
+
+/// Version of the JSON representation of [Status], increased on breaking changes
+pub const STATUS_VERSION: u32 = 1;
+
+/**
+The migrations and whether they have been applied, printed by `migrate --status`.
+*/
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct Status {
+    /// Version of the representation, see [STATUS_VERSION]
+    pub version: u32,
+    /// The dialect of the database
+    pub dialect: Dialect,
+    /// The last migration recorded as applied
+    pub last_applied: Option<String>,
+    /// The migrations in the order they are applied in
+    pub migrations: Vec<MigrationStatus>,
+    /// Number of migrations left out by [Status::since_applied] or [Status::last]
+    pub hidden: usize,
+    /// The recorded migrations, which don't exist anymore.
+    /// They are never left out, see `drorm repair`.
+    pub unknown: Vec<String>,
+}
+
+/**
+A migration of a [Status].
+*/
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct MigrationStatus {
+    /// ID of the migration including its namespace, e.g. `users/0002_add_email`
+    pub id: String,
+    /// Whether the migration has been applied, a squash is applied
+    /// if all of the migrations it replaces are
+    pub applied: bool,
+}
+
+impl Status {
+    /**
+    Leaves out the applied migrations before the last applied one.
+
+    Only the last applied and the pending migrations are kept.
+    If no migration has been applied, all of them are kept.
+    */
+    pub fn since_applied(self) -> Self {
+        let tip = self.migrations.iter().rposition(|x| x.applied);
+        self.skip(tip.unwrap_or_default())
+    }
+
+    /**
+    Leaves out all but the most recent migrations.
+
+    `count`: [usize]: Number of migrations to keep.
+    */
+    pub fn last(self, count: usize) -> Self {
+        let skipped = self.migrations.len().saturating_sub(count);
+        self.skip(skipped)
+    }
+
+    /**
+    Helper method to leave out the first migrations.
+
+    `count`: [usize]: Number of migrations to leave out.
+    */
+    fn skip(mut self, count: usize) -> Self {
+        let count = count.min(self.migrations.len());
+        self.migrations.drain(..count);
+        self.hidden += count;
+        self
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.last_applied {
+            None => writeln!(
+                f,
+                "No migration has been applied to the {} database",
+                self.dialect
+            )?,
+            Some(id) => writeln!(
+                f,
+                "Last applied migration on the {} database: {}",
+                self.dialect, id
+            )?,
+        }
+
+        write!(f, "Migrations:")?;
+        if self.hidden > 0 {
+            write!(f, "\n    ... {} earlier migrations", self.hidden)?;
+        }
+        for migration in &self.migrations {
+            let state = if migration.applied {
+                paint(Style::Success, "applied")
+            } else {
+                paint(Style::Warning, "pending")
+            };
+            write!(f, "\n    {} {}", state, migration.id)?;
+        }
+        for id in &self.unknown {
+            write!(f, "\n    {} {}", paint(Style::Error, "unknown"), id)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::Dialect;
+
+    use crate::migrate::status::{MigrationStatus, Status, STATUS_VERSION};
+
+    fn status(applied: usize, pending: usize) -> Status {
+        Status {
+            version: STATUS_VERSION,
+            dialect: Dialect::SQLite,
+            last_applied: None,
+            migrations: (0..applied + pending)
+                .map(|x| MigrationStatus {
+                    id: format!("{:04}_migration", x + 1),
+                    applied: x < applied,
+                })
+                .collect(),
+            hidden: 0,
+            unknown: vec!["0000_removed".to_string()],
+        }
+    }
+
+    #[test]
+    fn filters() {
+        let ids = |status: &Status| {
+            status
+                .migrations
+                .iter()
+                .map(|x| x.id.clone())
+                .collect::<Vec<String>>()
+        };
+
+        let since = status(3, 2).since_applied();
+        assert_eq!(
+            ids(&since),
+            vec!["0003_migration", "0004_migration", "0005_migration"]
+        );
+        assert_eq!(since.hidden, 2);
+        assert_eq!(status(0, 2).since_applied().migrations.len(), 2);
+
+        let last = status(3, 2).since_applied().last(1);
+        assert_eq!(ids(&last), vec!["0005_migration"]);
+        assert_eq!(last.hidden, 4);
+        assert_eq!(status(1, 0).last(5).hidden, 0);
+
+        // Unknown recorded migrations are kept
+        assert_eq!(last.unknown, vec!["0000_removed".to_string()]);
+    }
+}
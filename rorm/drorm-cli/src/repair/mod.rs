@@ -0,0 +1,517 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use rorm_sql::imr::{Annotation, InternalModelFormat, ModelAnnotation, Persistence};
+use sqlx::any::AnyRow;
+use sqlx::{query, Row};
+
+use crate::declaration::Migration;
+use crate::make_migrations::introspect::{columns, foreign_keys, tables, IntrospectedForeignKey};
+use crate::migrate::config::load_db_conf;
+use crate::migrate::{
+    bookkeeping_delete, bookkeeping_insert, bookkeeping_table_exists, connect,
+    create_bookkeeping_table,
+};
+use crate::utils::migrations::{convert_migrations_to_internal_models, get_existing_migrations};
+use crate::utils::output::{info, paint_diff_line};
+use crate::utils::question::question;
+
+/**
+Options for repairing the last migration table
+*/
+pub struct RepairOptions {
+    /// Directory, migrations exist in
+    pub migration_dir: String,
+
+    /// If set, the subdirectories of the migration directory are searched as well
+    pub recursive: bool,
+
+    /// Path to the database configuration file
+    pub database_config: String,
+
+    /// Url of the database, used instead of the configuration file
+    pub database_url: Option<String>,
+
+    /// Update the last migration table without asking
+    pub non_interactive: bool,
+}
+
+/**
+Tables of a database with the names of their columns
+*/
+pub type Schema = BTreeMap<String, BTreeSet<String>>;
+
+/**
+Result of comparing the schema of a database to the state of its migrations.
+
+The numbers are the number of migrations of the chain, which are applied.
+*/
+#[derive(Debug, PartialEq, Eq)]
+pub enum Diagnosis {
+    /// The recorded migrations match the schema
+    Consistent,
+    /// The schema matches the state after the migrations
+    Matches(usize),
+    /// The tables created by the last of the migrations exist, but the schema doesn't match exactly
+    TablesExist(usize),
+    /// The schema doesn't match any state
+    Unknown,
+}
+
+/**
+Returns the tables and columns the models result in.
+
+Temporary tables don't outlive their connection, so they are left out.
+
+`models`: [&InternalModelFormat]: The models to convert.
+*/
+pub fn schema_of(models: &InternalModelFormat) -> Schema {
+    models
+        .models
+        .iter()
+        .filter(|x| {
+            !x.annotations
+                .contains(&ModelAnnotation::Persistence(Persistence::Temporary))
+        })
+        .map(|x| {
+            (
+                x.name.clone(),
+                x.fields.iter().map(|y| y.name.clone()).collect(),
+            )
+        })
+        .collect()
+}
+
+/**
+Returns the number of migrations the recorded migrations correspond to.
+
+`None` is returned if the recorded migrations are not the beginning of the chain.
+
+`migrations`: [&\[Migration\]]: The migrations ordered by their dependency chain.
+`recorded`: [&\[String\]]: The migrations recorded in the last migration table.
+*/
+pub fn recorded_prefix(migrations: &[Migration], recorded: &[String]) -> Option<usize> {
+    let recorded: HashSet<&str> = recorded.iter().map(String::as_str).collect();
+    let prefix: HashSet<&str> = migrations
+        .iter()
+        .take(recorded.len())
+        .map(|x| x.id.as_str())
+        .collect();
+    (prefix.len() == recorded.len() && prefix == recorded).then_some(recorded.len())
+}
+
+/**
+Compares the schema of a database to the states of the migration chain.
+
+If several states match, e.g. because a migration only changes data,
+the recorded state is preferred and the latest state otherwise.
+
+`states`: [&\[Schema\]]: The schema after each number of applied migrations, starting with none.
+`actual`: [&Schema]: The schema of the database.
+`recorded`: [Option<usize>]: The number of migrations recorded as applied.
+*/
+pub fn diagnose(states: &[Schema], actual: &Schema, recorded: Option<usize>) -> Diagnosis {
+    let matching: Vec<usize> = (0..states.len())
+        .filter(|x| states[*x] == *actual)
+        .collect();
+    if recorded.is_some_and(|x| matching.contains(&x)) {
+        return Diagnosis::Consistent;
+    }
+    if let Some(latest) = matching.last() {
+        return Diagnosis::Matches(*latest);
+    }
+
+    // A migration may have been applied partially, e.g. on MySQL which commits DDL implicitly
+    let start = recorded.map_or(1, |x| x + 1);
+    (start..states.len())
+        .rev()
+        .find(|x| {
+            states[*x].keys().all(|table| actual.contains_key(table))
+                && states[*x]
+                    .keys()
+                    .any(|table| !states[x - 1].contains_key(table))
+        })
+        .map_or(Diagnosis::Unknown, Diagnosis::TablesExist)
+}
+
+/**
+Compares the actions of the foreign keys of the models to the foreign keys of a database.
+
+Returns a description of each foreign key whose actions differ.
+Foreign keys which only exist on one side are not reported.
+
+`models`: [&InternalModelFormat]: The models resulting from the migrations.
+`actual`: [&BTreeMap<String, Vec<IntrospectedForeignKey>>]: The foreign keys of each table of the database.
+*/
+pub fn foreign_key_mismatches(
+    models: &InternalModelFormat,
+    actual: &BTreeMap<String, Vec<IntrospectedForeignKey>>,
+) -> Vec<String> {
+    let mut mismatches = vec![];
+    for model in &models.models {
+        for field in &model.fields {
+            let expected_keys = field.annotations.iter().filter_map(|x| match x {
+                Annotation::ForeignKey(foreign_key) => Some(foreign_key),
+                _ => None,
+            });
+            for expected in expected_keys {
+                let found = match actual.get(&model.name).and_then(|x| {
+                    x.iter().find(|y| {
+                        y.column == field.name
+                            && y.foreign_key.table_name == expected.table_name
+                            && y.foreign_key.column_name == expected.column_name
+                    })
+                }) {
+                    Some(found) => found,
+                    None => continue,
+                };
+
+                for (event, expected, found) in [
+                    ("DELETE", expected.on_delete, found.foreign_key.on_delete),
+                    ("UPDATE", expected.on_update, found.foreign_key.on_update),
+                ] {
+                    if expected != found {
+                        mismatches.push(format!(
+                            "Foreign key {}.{} is ON {} {} in the database, but ON {} {} in the migrations",
+                            model.name,
+                            field.name,
+                            event,
+                            found.sql(),
+                            event,
+                            expected.sql()
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    mismatches
+}
+
+/**
+Compares the schema of the database to the migrations and corrects the last migration table.
+*/
+pub async fn run_repair(options: RepairOptions) -> anyhow::Result<()> {
+    let db_conf = match load_db_conf(
+        Path::new(options.database_config.as_str()),
+        options.database_url.as_deref(),
+    )? {
+        Some(db_conf) => db_conf,
+        None => return Ok(()),
+    };
+    let migrations = get_existing_migrations(options.migration_dir.as_str(), options.recursive)?;
+    let states = (0..=migrations.len())
+        .map(|x| convert_migrations_to_internal_models(&migrations[..x]).map(|y| schema_of(&y)))
+        .collect::<anyhow::Result<Vec<Schema>>>()?;
+
+    let db_impl = db_conf.driver.db_impl();
+    let table = db_conf.last_migration_table_name.as_str();
+    let pool = connect(&db_conf).await?;
+
+    let mut actual = Schema::new();
+    let mut actual_foreign_keys = BTreeMap::new();
+    for name in tables(db_impl, &pool).await? {
+        if name == table {
+            continue;
+        }
+        let columns = columns(db_impl, &pool, name.as_str())
+            .await
+            .with_context(|| format!("Couldn't retrieve the columns of table {}", name))?;
+        let foreign_keys = foreign_keys(db_impl, &pool, name.as_str())
+            .await
+            .with_context(|| format!("Couldn't retrieve the foreign keys of table {}", name))?;
+        actual.insert(name.clone(), columns.into_iter().map(|x| x.name).collect());
+        actual_foreign_keys.insert(name, foreign_keys);
+    }
+
+    let recorded: Vec<String> = if bookkeeping_table_exists(db_impl, &pool, table).await? {
+        query(format!("SELECT migration_name FROM {} ORDER BY id ASC;", table).as_str())
+            .map(|x: AnyRow| x.get(0))
+            .fetch_all(&pool)
+            .await
+            .with_context(|| {
+                "Couldn't fetch information about successful migrations from migration table"
+            })?
+    } else {
+        vec![]
+    };
+
+    let recorded_count = recorded_prefix(&migrations, &recorded);
+    let diagnosis = diagnose(&states, &actual, recorded_count);
+
+    // The columns only tell which migrations have been applied, not whether they have been changed
+    if let Some(applied) = match diagnosis {
+        Diagnosis::Consistent => recorded_count,
+        Diagnosis::Matches(applied) | Diagnosis::TablesExist(applied) => Some(applied),
+        Diagnosis::Unknown => None,
+    } {
+        let models = convert_migrations_to_internal_models(&migrations[..applied])?;
+        for mismatch in foreign_key_mismatches(&models, &actual_foreign_keys) {
+            info!("Warning: {}", mismatch);
+        }
+    }
+
+    let applied = match diagnosis {
+        Diagnosis::Consistent => {
+            info!("The last migration table matches the schema of the database");
+            pool.close().await;
+            return Ok(());
+        }
+        Diagnosis::Matches(applied) => applied,
+        Diagnosis::TablesExist(applied) => {
+            info!(
+                "Warning: The tables of migration {} exist, but the schema doesn't match it exactly. Check the schema before marking it as applied.",
+                migrations[applied - 1].id
+            );
+            applied
+        }
+        Diagnosis::Unknown => {
+            pool.close().await;
+            return Err(anyhow!(
+                "The schema of the database doesn't match any migration in {}, the last migration table can't be repaired",
+                options.migration_dir
+            ));
+        }
+    };
+
+    match applied {
+        0 => info!("Proposed last applied migration: none"),
+        _ => info!(
+            "Proposed last applied migration: {}",
+            migrations[applied - 1].id
+        ),
+    }
+
+    let ids: Vec<&str> = migrations[..applied]
+        .iter()
+        .map(|x| x.id.as_str())
+        .collect();
+    let to_delete: Vec<&str> = recorded
+        .iter()
+        .map(String::as_str)
+        .filter(|x| !ids.contains(x))
+        .collect();
+    let to_insert: Vec<&str> = ids
+        .iter()
+        .filter(|x| !recorded.iter().any(|y| y == *x))
+        .copied()
+        .collect();
+    for id in &to_insert {
+        info!("{}", paint_diff_line(&format!("+ record {}", id)));
+    }
+    for id in &to_delete {
+        info!("{}", paint_diff_line(&format!("- forget {}", id)));
+    }
+    if to_insert.is_empty() && to_delete.is_empty() {
+        info!("The last migration table matches the schema of the database");
+        pool.close().await;
+        return Ok(());
+    }
+
+    if !options.non_interactive
+        && !question(format!("Do you want to update the last migration table {}?", table).as_str())?
+    {
+        info!("Aborted, the last migration table has not been changed.");
+        pool.close().await;
+        return Ok(());
+    }
+
+    create_bookkeeping_table(db_impl, &pool, table).await?;
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Couldn't start a transaction")?;
+    for id in &to_delete {
+        query(bookkeeping_delete(db_impl, table).as_str())
+            .bind(*id)
+            .execute(&mut tx)
+            .await
+            .with_context(|| format!("Couldn't remove migration {} from migration table", id))?;
+    }
+    for id in &to_insert {
+        query(bookkeeping_insert(db_impl, table).as_str())
+            .bind(*id)
+            .bind(
+                migrations
+                    .iter()
+                    .find(|x| x.id == **id)
+                    .map(|x| x.hash.as_str()),
+            )
+            .execute(&mut tx)
+            .await
+            .with_context(|| format!("Couldn't record migration {} in migration table", id))?;
+    }
+    tx.commit()
+        .await
+        .with_context(|| "Couldn't commit the changes of the migration table")?;
+    pool.close().await;
+
+    info!("Updated the last migration table {}", table);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rorm_sql::imr::{
+        Annotation, DbType, Field, ForeignKey, InternalModelFormat, Model, ReferentialAction,
+    };
+    use rorm_sql::DBImpl;
+    use sqlx::Executor;
+
+    use crate::declaration::Migration;
+    use crate::make_migrations::introspect::foreign_keys;
+    use crate::migrate::config::{DatabaseConfig, DatabaseDriver};
+    use crate::migrate::connect;
+    use crate::repair::{diagnose, foreign_key_mismatches, recorded_prefix, Diagnosis, Schema};
+
+    fn schema(tables: &[(&str, &[&str])]) -> Schema {
+        tables
+            .iter()
+            .map(|(name, columns)| {
+                (
+                    name.to_string(),
+                    columns.iter().map(|x| x.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diagnosis() {
+        let states = vec![
+            schema(&[]),
+            schema(&[("user", &["id"])]),
+            schema(&[("user", &["id", "age"])]),
+            schema(&[("user", &["id", "age"]), ("post", &["id", "title"])]),
+        ];
+
+        assert_eq!(
+            diagnose(&states, &states[2], Some(2)),
+            Diagnosis::Consistent
+        );
+        // Applied, but not recorded
+        assert_eq!(
+            diagnose(&states, &states[3], Some(2)),
+            Diagnosis::Matches(3)
+        );
+        // Recorded, but not applied
+        assert_eq!(
+            diagnose(&states, &states[1], Some(2)),
+            Diagnosis::Matches(1)
+        );
+        assert_eq!(diagnose(&states, &states[2], None), Diagnosis::Matches(2));
+
+        // Table exists, but the migration is not recorded
+        let partial = schema(&[("user", &["id", "age"]), ("post", &["id"])]);
+        assert_eq!(
+            diagnose(&states, &partial, Some(2)),
+            Diagnosis::TablesExist(3)
+        );
+        assert_eq!(
+            diagnose(&states, &schema(&[("comment", &["id"])]), Some(2)),
+            Diagnosis::Unknown
+        );
+    }
+
+    #[test]
+    fn recorded() {
+        let migration = |id: &str| Migration {
+            hash: "".to_string(),
+            initial: false,
+            id: id.to_string(),
+            dependency: "".to_string(),
+            external_dependencies: vec![],
+            merges: vec![],
+            replaces: vec![],
+            atomic: true,
+            operations: vec![],
+            precondition: None,
+            lock_timeout_ms: None,
+            naming_policy: None,
+            meta: None,
+        };
+        let migrations = vec![migration("0001_a"), migration("0002_b")];
+        let ids = |x: &[&str]| x.iter().map(|y| y.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(recorded_prefix(&migrations, &[]), Some(0));
+        assert_eq!(
+            recorded_prefix(&migrations, &ids(&["0002_b", "0001_a"])),
+            Some(2)
+        );
+        assert_eq!(recorded_prefix(&migrations, &ids(&["0002_b"])), None);
+        assert_eq!(
+            recorded_prefix(&migrations, &ids(&["0001_a", "0002_b", "0003_c"])),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn foreign_key_actions() {
+        let path =
+            std::env::temp_dir().join(format!("drorm_fk_actions_{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_conf = DatabaseConfig {
+            driver: DatabaseDriver::SQLite,
+            name: path.to_str().unwrap().to_string(),
+            host: "".to_string(),
+            port: 0,
+            user: "".to_string(),
+            password: "".to_string(),
+            last_migration_table_name: "_drorm__last_migration".to_string(),
+            charset: None,
+            collation: None,
+            pre_migrate_command: None,
+            post_migrate_command: None,
+            analyze: false,
+        };
+
+        let pool = connect(&db_conf).await.unwrap();
+        pool.execute("CREATE TABLE user (id INTEGER PRIMARY KEY);")
+            .await
+            .unwrap();
+        pool.execute(
+            "CREATE TABLE post (id INTEGER PRIMARY KEY, author INTEGER REFERENCES user ON DELETE CASCADE);",
+        )
+        .await
+        .unwrap();
+        let mut actual = BTreeMap::new();
+        for table in ["user", "post"] {
+            actual.insert(
+                table.to_string(),
+                foreign_keys(DBImpl::SQLite, &pool, table).await.unwrap(),
+            );
+        }
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+
+        let models = |on_delete: ReferentialAction| InternalModelFormat {
+            models: vec![Model {
+                name: "post".to_string(),
+                fields: vec![Field {
+                    name: "author".to_string(),
+                    db_type: DbType::Int64,
+                    annotations: vec![Annotation::ForeignKey(ForeignKey {
+                        table_name: "user".to_string(),
+                        column_name: "id".to_string(),
+                        deferrable: false,
+                        initially_deferred: false,
+                        on_delete,
+                        on_update: ReferentialAction::NoAction,
+                    })],
+                    source_defined_at: None,
+                }],
+                annotations: vec![],
+                source_defined_at: None,
+            }],
+        };
+
+        assert!(foreign_key_mismatches(&models(ReferentialAction::Cascade), &actual).is_empty());
+        assert_eq!(
+            foreign_key_mismatches(&models(ReferentialAction::Restrict), &actual),
+            vec!["Foreign key post.author is ON DELETE CASCADE in the database, but ON DELETE RESTRICT in the migrations"]
+        );
+    }
+}
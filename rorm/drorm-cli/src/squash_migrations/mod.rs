@@ -0,0 +1,583 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::remove_file;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context};
+
+use crate::declaration::{Migration, Operation};
+use crate::make_migrations::naming::recorded_naming_policy;
+use crate::make_migrations::{
+    audit_info, create_models_operations, following_number, index_name, models_hash,
+    unique_together_name,
+};
+use crate::migrate::sql_builder::sorted_operations;
+use crate::utils::fold::{fold_operations, model_of};
+use crate::utils::migrations::{
+    convert_file_to_migration, convert_migration_to_file, convert_migrations_to_internal_models,
+    leaf_migrations, migration_files, order_migrations, replaced_migrations,
+};
+use crate::utils::output::info;
+
+/**
+Options for squashing migrations
+*/
+pub struct SquashOptions {
+    /// Directory, migrations exist in
+    pub migration_dir: String,
+
+    /// If set, the subdirectories of the migration directory are searched as well
+    pub recursive: bool,
+
+    /// If set, branches of the dependency chain are squashed as well
+    pub force: bool,
+
+    /// If set, the files of the squashed migrations are deleted
+    pub delete: bool,
+}
+
+/**
+Helper function to find the branch points of the dependency chain.
+
+Returns a description of every migration, which more than one migration depends on.
+
+`migrations`: [&\[Migration\]]: The migrations to check.
+*/
+fn branch_points(migrations: &[Migration]) -> Vec<String> {
+    let mut children: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for migration in migrations.iter().filter(|x| !x.dependency.is_empty()) {
+        children
+            .entry(migration.dependency.as_str())
+            .or_default()
+            .push(migration.id.as_str());
+    }
+    children
+        .iter()
+        .filter(|(_, x)| x.len() > 1)
+        .map(|(dependency, children)| {
+            format!("{} all depend on {}", children.join(", "), dependency)
+        })
+        .collect()
+}
+
+/**
+Helper function to order migrations, whose dependency chain has branches.
+
+A migration is ordered after its dependency, migrations of different branches
+are ordered by their position in the list, i.e. by their number.
+
+`migrations`: [Vec<Migration>]: The migrations ordered by their number.
+*/
+fn order_branches(migrations: Vec<Migration>) -> anyhow::Result<Vec<Migration>> {
+    let mut placed: HashSet<String> = HashSet::new();
+    let mut remaining = migrations;
+    let mut ordered = vec![];
+    while !remaining.is_empty() {
+        let next = remaining
+            .iter()
+            .position(|x| {
+                (x.dependency.is_empty() || placed.contains(&x.dependency))
+                    && x.merges.iter().all(|x| placed.contains(x))
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "Migrations {} are not reachable from an initial migration",
+                    remaining
+                        .iter()
+                        .map(|x| x.id.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?;
+        let migration = remaining.remove(next);
+        placed.insert(migration.id.clone());
+        ordered.push(migration);
+    }
+    Ok(ordered)
+}
+
+/**
+Helper function to check whether an index is named like the indexes make-migrations creates.
+
+`model`: [&str]: Name of the model of the index.
+`name`: [&str]: Name of the index.
+`columns`: [&\[String\]]: Columns of the index.
+*/
+fn is_managed_index(model: &str, name: &str, columns: &[String]) -> bool {
+    name == index_name(model, columns)
+        || name == unique_together_name(model, columns)
+        || matches!(columns, [column] if name == format!("{}_{}_fulltext_idx", model, column))
+}
+
+/**
+Helper function to check whether an operation is kept in a squash.
+
+Raw SQL, extensions, checks, partitions, sequences and indexes not created by make-migrations
+are not part of the models, so these operations can't be replayed into the models
+and are kept instead.
+
+`operation`: [&Operation]: The operation to check.
+*/
+fn is_kept(operation: &Operation) -> bool {
+    match operation.inner() {
+        Operation::RunSql { .. }
+        | Operation::CreateExtension { .. }
+        | Operation::DropExtension { .. }
+        | Operation::CreateCheck { .. }
+        | Operation::ValidateConstraint { .. }
+        | Operation::DropConstraint { .. }
+        | Operation::RenameConstraint { .. }
+        | Operation::CreatePartition { .. }
+        | Operation::AttachPartition { .. }
+        | Operation::DetachPartition { .. }
+        | Operation::SetSequence { .. } => true,
+        Operation::CreateIndex {
+            model,
+            name,
+            columns,
+            ..
+        }
+        | Operation::DropIndex {
+            model,
+            name,
+            columns,
+        }
+        | Operation::RenameIndex {
+            model,
+            new: name,
+            columns,
+            ..
+        } => !is_managed_index(model, name, columns),
+        _ => false,
+    }
+}
+
+/**
+Squashes migrations into a single initial migration.
+
+The operations of the migrations are replayed in the order of their dependency chain
+into the resulting models, which the squash creates like an initial migration,
+e.g. a field created and deleted later on is left out completely.
+The operations which are not part of the models, e.g. raw SQL, are kept after them,
+see [is_kept]. A squash whose kept operations refer to renamed or deleted models is refused.
+The squash replaces all of the migrations, including the ones replaced by
+the migrations, e.g. by a former squash, so databases on which only
+the original migrations have been applied don't apply the squash again.
+
+Migrations replaced by another migration of the list are not squashed again.
+The squash has to result in the same models as the migrations, otherwise it is refused.
+
+`migrations`: [Vec<Migration>]: The migrations to squash.
+`force`: [bool]: If set, branches of the dependency chain are squashed as well,
+instead of being refused.
+`now`: [SystemTime]: The current time, used for the id and the audit trail.
+*/
+pub fn squash_migrations(
+    migrations: Vec<Migration>,
+    force: bool,
+    now: SystemTime,
+) -> anyhow::Result<Migration> {
+    let replaced = replaced_migrations(&migrations);
+    let migrations: Vec<Migration> = migrations
+        .into_iter()
+        .filter(|x| !replaced.contains(&x.id))
+        .collect();
+    if migrations.len() < 2 {
+        return Err(anyhow!(
+            "Found {} migrations, at least two are needed for a squash",
+            migrations.len()
+        ));
+    }
+
+    // Merged branches are ordered like they are applied
+    let branches = match leaf_migrations(&migrations).len() {
+        1 => vec![],
+        _ => branch_points(&migrations),
+    };
+    let ordered = if branches.is_empty() {
+        order_migrations(migrations)?
+    } else if force {
+        order_branches(migrations)?
+    } else {
+        return Err(anyhow!(
+            "The migrations have branches, migrations {}. Merge them into a single chain or use --force to squash them in the order of their numbers",
+            branches.join("; migrations ")
+        ));
+    };
+
+    if let Some(migration) = ordered.iter().find(|x| x.precondition.is_some()) {
+        return Err(anyhow!(
+            "Migration {} has a precondition, which can't be kept in a squash",
+            migration.id
+        ));
+    }
+
+    let mut replaces: Vec<String> = vec![];
+    let mut external_dependencies: Vec<String> = vec![];
+    for migration in &ordered {
+        for id in migration.replaces.iter().chain([&migration.id]) {
+            if !replaces.contains(id) {
+                replaces.push(id.clone());
+            }
+        }
+        for dependency in &migration.external_dependencies {
+            if !external_dependencies.contains(dependency) {
+                external_dependencies.push(dependency.clone());
+            }
+        }
+    }
+
+    let models = convert_migrations_to_internal_models(&ordered)?;
+    let mut operations = create_models_operations(&models);
+    let kept = fold_operations(
+        ordered
+            .iter()
+            .flat_map(sorted_operations)
+            .filter(|x| is_kept(x))
+            .cloned()
+            .collect(),
+    );
+    for operation in kept {
+        if let Some(model) =
+            model_of(&operation).filter(|x| !models.models.iter().any(|y| y.name == *x))
+        {
+            return Err(anyhow!(
+                "The squashed migrations keep an operation on model {}, which is renamed or deleted later, squash them manually",
+                model
+            ));
+        }
+        // The extensions required by the models are already created
+        let created = |name: &String| {
+            operations.iter().any(|x| {
+                matches!(x.inner(), Operation::CreateExtension { name: created, .. } if created == name)
+            })
+        };
+        match operation.inner() {
+            Operation::CreateExtension { name, .. } if created(name) => {}
+            _ => operations.push(operation),
+        }
+    }
+
+    let mut squash = Migration {
+        hash: "".to_string(),
+        initial: true,
+        id: format!("{}_squashed", following_number(&ordered, now)?),
+        dependency: "".to_string(),
+        external_dependencies,
+        merges: vec![],
+        replaces,
+        atomic: ordered.iter().all(|x| x.atomic),
+        operations,
+        precondition: None,
+        lock_timeout_ms: None,
+        naming_policy: recorded_naming_policy(&ordered),
+        meta: Some(audit_info(
+            Some(
+                format!(
+                    "Squash of the migrations {} to {}",
+                    ordered[0].id,
+                    ordered[ordered.len() - 1].id
+                )
+                .as_str(),
+            ),
+            now,
+        )),
+    };
+
+    let expected = models_hash(&models);
+    squash.hash = models_hash(&convert_migrations_to_internal_models(&[squash.clone()])?);
+    if squash.hash != expected {
+        return Err(anyhow!(
+            "The squashed operations don't result in the models of the migrations, squash them manually"
+        ));
+    }
+
+    Ok(squash)
+}
+
+/**
+Squashes the migrations of a migration directory into a single new migration file.
+
+The files of the squashed migrations are only deleted with `delete`.
+Kept files are ignored as long as the squash exists, so databases
+which haven't applied all of them yet can still be migrated.
+
+`options`: [SquashOptions]: The options of the run.
+*/
+pub fn run_squash_migrations(options: SquashOptions) -> anyhow::Result<()> {
+    let mut migrations = vec![];
+    let mut paths: HashMap<String, PathBuf> = HashMap::new();
+    for file in &migration_files(options.migration_dir.as_str(), options.recursive)? {
+        let migration = convert_file_to_migration(file)?.migration;
+        paths.insert(migration.id.clone(), file.path());
+        migrations.push(migration);
+    }
+
+    let squash =
+        squash_migrations(migrations, options.force, SystemTime::now()).with_context(|| {
+            format!(
+                "Couldn't squash the migrations in {}",
+                options.migration_dir
+            )
+        })?;
+
+    let path = Path::new(options.migration_dir.as_str()).join(format!("{}.toml", squash.id));
+    if path.exists() {
+        return Err(anyhow!("Migration file {} exists already", path.display()));
+    }
+    let replaces = squash.replaces.clone();
+    convert_migration_to_file(squash, &path)?;
+    info!(
+        "Squashed {} migrations into {}",
+        replaces.len(),
+        path.display()
+    );
+
+    let squashed: Vec<&PathBuf> = replaces.iter().filter_map(|x| paths.get(x)).collect();
+    if options.delete {
+        for file in &squashed {
+            remove_file(file)
+                .with_context(|| format!("Couldn't delete migration file {}", file.display()))?;
+        }
+        info!("Deleted {} squashed migration files", squashed.len());
+    } else if !squashed.is_empty() {
+        info!(
+            "Kept {} squashed migration files, they are ignored as long as the squash exists. Delete them once every database has applied them.",
+            squashed.len()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use rorm_sql::imr::{Annotation, DbType, Field};
+
+    use crate::declaration::{Migration, Operation, TypeChange};
+    use crate::squash_migrations::{run_squash_migrations, squash_migrations, SquashOptions};
+    use crate::utils::migrations::{convert_migration_to_file, get_existing_migrations};
+    use crate::utils::test_helpers::migration;
+
+    fn create_field(model: &str, name: &str) -> Operation {
+        Operation::CreateField {
+            model: model.to_string(),
+            field: Field {
+                name: name.to_string(),
+                db_type: DbType::Int32,
+                annotations: vec![],
+                source_defined_at: None,
+            },
+        }
+    }
+
+    fn history() -> Vec<Migration> {
+        vec![
+            migration(
+                "0001_initial",
+                "",
+                vec![Operation::CreateModel {
+                    name: "user".to_string(),
+                    fields: vec![],
+                    annotations: vec![],
+                }],
+            ),
+            migration(
+                "0002_age",
+                "0001_initial",
+                vec![create_field("user", "age"), create_field("user", "name")],
+            ),
+            migration(
+                "0003_drop_age",
+                "0002_age",
+                vec![Operation::DeleteField {
+                    model: "user".to_string(),
+                    name: "age".to_string(),
+                    cascade: false,
+                }],
+            ),
+        ]
+    }
+
+    #[test]
+    fn squash() {
+        let squash = squash_migrations(history(), false, SystemTime::now()).unwrap();
+        assert_eq!(squash.id, "0004_squashed");
+        assert!(squash.initial);
+        assert!(squash.dependency.is_empty());
+        assert_eq!(
+            squash.replaces,
+            vec!["0001_initial", "0002_age", "0003_drop_age"]
+        );
+        assert!(!squash.hash.is_empty());
+        match squash.operations.as_slice() {
+            [Operation::CreateModel { name, fields, .. }] => {
+                assert_eq!(name, "user");
+                let names: Vec<&str> = fields.iter().map(|x| x.name.as_str()).collect();
+                assert_eq!(names, vec!["name"]);
+            }
+            operations => panic!("Unexpected operations {:?}", operations),
+        }
+
+        // A former squash is squashed again with the migrations depending on it
+        let mut migrations = history();
+        migrations.push(squash.clone());
+        migrations.push(migration(
+            "0005_email",
+            "0004_squashed",
+            vec![create_field("user", "email")],
+        ));
+        let again = squash_migrations(migrations, false, SystemTime::now()).unwrap();
+        assert_eq!(again.id, "0006_squashed");
+        assert_eq!(
+            again.replaces,
+            vec![
+                "0001_initial",
+                "0002_age",
+                "0003_drop_age",
+                "0004_squashed",
+                "0005_email"
+            ]
+        );
+
+        assert!(squash_migrations(vec![squash], false, SystemTime::now()).is_err());
+    }
+
+    #[test]
+    fn replayed_models() {
+        let title = |max_length| Field {
+            name: "title".to_string(),
+            db_type: DbType::VarChar,
+            annotations: vec![Annotation::MaxLength(max_length)],
+            source_defined_at: None,
+        };
+        let migrations = vec![
+            migration(
+                "0001_initial",
+                "",
+                vec![Operation::CreateModel {
+                    name: "post".to_string(),
+                    fields: vec![title(100)],
+                    annotations: vec![],
+                }],
+            ),
+            migration(
+                "0002_title",
+                "0001_initial",
+                vec![
+                    Operation::AlterField {
+                        model: "post".to_string(),
+                        change: TypeChange::Widening,
+                        field: title(255),
+                    },
+                    Operation::RunSql {
+                        sql: "UPDATE post SET title = '';".to_string(),
+                        timeout_seconds: None,
+                    },
+                ],
+            ),
+        ];
+
+        // The model is created with the altered field, the raw SQL is kept after it
+        let squash = squash_migrations(migrations.clone(), false, SystemTime::now()).unwrap();
+        match squash.operations.as_slice() {
+            [Operation::CreateModel { fields, .. }, Operation::RunSql { .. }] => {
+                assert!(matches!(
+                    fields[0].annotations.as_slice(),
+                    [Annotation::MaxLength(255)]
+                ));
+            }
+            operations => panic!("Unexpected operations {:?}", operations),
+        }
+
+        // Kept operations have to refer to the resulting models
+        let mut renamed = migrations;
+        renamed[1].operations.insert(
+            0,
+            Operation::CreateCheck {
+                model: "post".to_string(),
+                name: "post_title_check".to_string(),
+                expression: "title <> ''".to_string(),
+                not_valid: false,
+            },
+        );
+        renamed[1].operations.push(Operation::RenameModel {
+            old: "post".to_string(),
+            new: "article".to_string(),
+            rename_constraints: false,
+        });
+        assert!(squash_migrations(renamed, false, SystemTime::now()).is_err());
+    }
+
+    #[test]
+    fn branches() {
+        let mut migrations = history();
+        migrations.insert(
+            2,
+            migration(
+                "0003_email",
+                "0002_age",
+                vec![create_field("user", "email")],
+            ),
+        );
+
+        let err = squash_migrations(migrations.clone(), false, SystemTime::now()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("0003_email, 0003_drop_age all depend on 0002_age"));
+
+        let squash = squash_migrations(migrations, true, SystemTime::now()).unwrap();
+        assert_eq!(
+            squash.replaces,
+            vec!["0001_initial", "0002_age", "0003_email", "0003_drop_age"]
+        );
+        match squash.operations.as_slice() {
+            [Operation::CreateModel { fields, .. }] => {
+                let names: Vec<&str> = fields.iter().map(|x| x.name.as_str()).collect();
+                assert_eq!(names, vec!["name", "email"]);
+            }
+            operations => panic!("Unexpected operations {:?}", operations),
+        }
+    }
+
+    #[test]
+    fn squash_directory() {
+        let dir = std::env::temp_dir().join(format!("drorm_squash_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for migration in history() {
+            let path = dir.join(format!("{}.toml", migration.id));
+            convert_migration_to_file(migration, &path).unwrap();
+        }
+        let options = |delete| SquashOptions {
+            migration_dir: dir.to_str().unwrap().to_string(),
+            recursive: false,
+            force: false,
+            delete,
+        };
+        let ids = || {
+            get_existing_migrations(dir.to_str().unwrap(), false)
+                .unwrap()
+                .into_iter()
+                .map(|x| x.id)
+                .collect::<Vec<_>>()
+        };
+
+        // The kept originals are replaced by the squash
+        run_squash_migrations(options(false)).unwrap();
+        assert_eq!(ids(), vec!["0004_squashed"]);
+        assert!(dir.join("0001_initial.toml").exists());
+
+        // Nothing is left to squash, the squash is the only migration in effect
+        assert!(run_squash_migrations(options(true)).is_err());
+        std::fs::remove_file(dir.join("0004_squashed.toml")).unwrap();
+
+        run_squash_migrations(options(true)).unwrap();
+        assert_eq!(ids(), vec!["0004_squashed"]);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
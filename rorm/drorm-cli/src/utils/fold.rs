@@ -0,0 +1,534 @@
+use rorm_sql::create_index::is_column_name;
+
+use crate::declaration::Operation;
+
+/**
+Returns the model an operation changes, if it changes an existing model.
+
+Operations creating, renaming or deleting a model and raw SQL return `None`.
+*/
+pub(crate) fn model_of(operation: &Operation) -> Option<&str> {
+    match operation.inner() {
+        Operation::CreateModel { .. }
+        | Operation::RenameModel { .. }
+        | Operation::DeleteModel { .. }
+        | Operation::RunSql { .. }
+        | Operation::CreateExtension { .. }
+        | Operation::DropExtension { .. }
+        | Operation::Only { .. } => None,
+        Operation::RenameField { table_name, .. } | Operation::RenameFields { table_name, .. } => {
+            Some(table_name)
+        }
+        Operation::CreateField { model, .. }
+        | Operation::AlterField { model, .. }
+        | Operation::AlterEnum { model, .. }
+        | Operation::DeleteField { model, .. }
+        | Operation::CreateIndex { model, .. }
+        | Operation::DropIndex { model, .. }
+        | Operation::RenameIndex { model, .. }
+        | Operation::RenameConstraint { model, .. }
+        | Operation::CreateForeignKey { model, .. }
+        | Operation::CreateCheck { model, .. }
+        | Operation::ValidateConstraint { model, .. }
+        | Operation::DropConstraint { model, .. }
+        | Operation::SetSequence { model, .. }
+        | Operation::AddIdentity { model, .. }
+        | Operation::SetIdentity { model, .. }
+        | Operation::DropIdentity { model, .. }
+        | Operation::Truncate { model, .. }
+        | Operation::SetTablespace { model, .. }
+        | Operation::SetStorageParameters { model, .. }
+        | Operation::SetCharset { model, .. }
+        | Operation::CreatePartition { model, .. }
+        | Operation::AttachPartition { model, .. }
+        | Operation::DetachPartition { model, .. }
+        | Operation::SetInherit { model, .. }
+        | Operation::NoInherit { model, .. }
+        | Operation::CreateUnique { model, .. }
+        | Operation::DropUnique { model, .. } => Some(model),
+    }
+}
+
+/**
+Checks whether an operation refers to a model, either by changing it or by referencing it.
+*/
+fn refers_to_model(operation: &Operation, model: &str) -> bool {
+    model_of(operation) == Some(model)
+        || match operation.inner() {
+            Operation::RenameModel { old, new, .. } => old == model || new == model,
+            Operation::CreateForeignKey { foreign_key, .. } => foreign_key.table_name == model,
+            Operation::SetInherit { parent, .. } | Operation::NoInherit { parent, .. } => {
+                parent == model
+            }
+            _ => false,
+        }
+}
+
+/**
+Checks whether an operation refers to a field of a model, other than by creating it.
+*/
+fn refers_to_field(operation: &Operation, model: &str, field: &str) -> bool {
+    match operation.inner() {
+        Operation::CreateForeignKey { foreign_key, .. }
+            if foreign_key.table_name == model && foreign_key.column_name == field =>
+        {
+            return true
+        }
+        _ => {}
+    }
+    if model_of(operation) != Some(model) {
+        return false;
+    }
+    match operation.inner() {
+        Operation::RenameField { old, new, .. } => old == field || new == field,
+        Operation::RenameFields { renames, .. } => renames
+            .iter()
+            .any(|(old, new)| old == field || new == field),
+        Operation::DeleteField { name, .. } => name == field,
+        Operation::AlterField { field: altered, .. }
+        | Operation::AlterEnum { field: altered, .. } => altered.name == field,
+        // Expressions of indexes may use the field
+        Operation::CreateIndex { columns, .. }
+        | Operation::DropIndex { columns, .. }
+        | Operation::RenameIndex { columns, .. } => {
+            columns.iter().any(|x| x == field || !is_column_name(x))
+        }
+        Operation::DropConstraint {
+            column: Some(column),
+            ..
+        } => column == field,
+        Operation::CreateForeignKey { column, .. }
+        | Operation::SetSequence { column, .. }
+        | Operation::AddIdentity { column, .. }
+        | Operation::SetIdentity { column, .. }
+        | Operation::DropIdentity { column, .. } => column == field,
+        // The expression may use the field
+        Operation::CreateCheck { .. } => true,
+        _ => false,
+    }
+}
+
+/**
+Returns the index of the operation creating a model, if it still exists at the end of the list.
+*/
+fn created_model(operations: &[Operation], model: &str) -> Option<usize> {
+    let index = operations
+        .iter()
+        .rposition(|x| matches!(x, Operation::CreateModel { name, .. } if name == model))?;
+    let renamed = operations[index + 1..]
+        .iter()
+        .any(|x| matches!(x.inner(), Operation::RenameModel { old, .. } if old == model));
+    (!renamed).then_some(index)
+}
+
+/**
+Folds a list of operations into an equivalent, shorter list.
+
+Operations which cancel each other out are removed, e.g. creating and deleting
+the same field, and consecutive changes are combined, e.g. a field created
+after its model is added to the create model operation.
+Operations restricted to some dialects are never combined with others.
+Raw SQL may use any model or field, so operations before and after it are never combined.
+
+`operations`: [Vec<Operation>]: The operations to fold, in the order they are applied.
+*/
+pub fn fold_operations(operations: Vec<Operation>) -> Vec<Operation> {
+    let mut folded = vec![];
+    let mut segment = vec![];
+    for operation in operations {
+        if matches!(operation.inner(), Operation::RunSql { .. }) {
+            folded.append(&mut fold_segment(std::mem::take(&mut segment)));
+            folded.push(operation);
+        } else {
+            segment.push(operation);
+        }
+    }
+    folded.append(&mut fold_segment(segment));
+    folded
+}
+
+/**
+Folds operations, which are not separated by raw SQL.
+*/
+fn fold_segment(operations: Vec<Operation>) -> Vec<Operation> {
+    let mut folded: Vec<Operation> = vec![];
+
+    for operation in operations {
+        match operation {
+            Operation::DeleteModel { name, cascade } => {
+                if let Some(index) = created_model(&folded, name.as_str()) {
+                    let mut position = 0;
+                    folded.retain(|x| {
+                        position += 1;
+                        position <= index || !refers_to_model(x, name.as_str())
+                    });
+                    folded.remove(index);
+                    continue;
+                }
+
+                // Changes of the model are lost anyway
+                folded.retain(|x| model_of(x) != Some(name.as_str()));
+                let renamed = folded
+                    .iter()
+                    .rposition(|x| matches!(x, Operation::RenameModel { new, .. } if *new == name));
+                match renamed {
+                    Some(index) => {
+                        if let Operation::RenameModel { old, .. } = folded.remove(index) {
+                            folded.retain(|x| model_of(x) != Some(old.as_str()));
+                            folded.push(Operation::DeleteModel { name: old, cascade });
+                        }
+                    }
+                    None => folded.push(Operation::DeleteModel { name, cascade }),
+                }
+            }
+            Operation::RenameModel {
+                old,
+                new,
+                rename_constraints,
+            } => {
+                if let Some(index) = created_model(&folded, old.as_str()) {
+                    if !folded[index + 1..]
+                        .iter()
+                        .any(|x| refers_to_model(x, old.as_str()))
+                    {
+                        if let Operation::CreateModel { name, .. } = &mut folded[index] {
+                            *name = new;
+                        }
+                        continue;
+                    }
+                }
+
+                let renamed = folded
+                    .iter()
+                    .rposition(|x| matches!(x, Operation::RenameModel { new, .. } if *new == old));
+                match renamed {
+                    Some(index)
+                        if !folded[index + 1..]
+                            .iter()
+                            .any(|x| refers_to_model(x, old.as_str())) =>
+                    {
+                        if let Operation::RenameModel {
+                            old: first,
+                            rename_constraints: first_constraints,
+                            ..
+                        } = folded.remove(index)
+                        {
+                            if first != new {
+                                folded.insert(
+                                    index,
+                                    Operation::RenameModel {
+                                        old: first,
+                                        new,
+                                        rename_constraints: first_constraints || rename_constraints,
+                                    },
+                                );
+                            }
+                        }
+                    }
+                    _ => folded.push(Operation::RenameModel {
+                        old,
+                        new,
+                        rename_constraints,
+                    }),
+                }
+            }
+            Operation::CreateField { model, field } => {
+                match created_model(&folded, model.as_str()) {
+                    Some(index) => {
+                        if let Operation::CreateModel { fields, .. } = &mut folded[index] {
+                            fields.push(field);
+                        }
+                    }
+                    None => folded.push(Operation::CreateField { model, field }),
+                }
+            }
+            Operation::DeleteField {
+                model,
+                name,
+                cascade,
+            } => {
+                let created = created_model(&folded, model.as_str())
+                    .filter(|index| match &folded[*index] {
+                        Operation::CreateModel { fields, .. } => {
+                            fields.iter().any(|x| x.name == name)
+                        }
+                        _ => false,
+                    })
+                    .or_else(|| {
+                        folded.iter().rposition(|x| {
+                            matches!(
+                                x,
+                                Operation::CreateField { model: m, field } if *m == model && field.name == name
+                            )
+                        })
+                    });
+
+                // Check constraints may use the field and batch renames may rename other fields
+                // as well, so they are kept together with the deletion
+                match created.filter(|index| {
+                    !folded[index + 1..].iter().any(|x| {
+                        model_of(x) == Some(model.as_str())
+                            && matches!(
+                                x.inner(),
+                                Operation::CreateCheck { .. } | Operation::RenameFields { .. }
+                            )
+                    })
+                }) {
+                    Some(index) => {
+                        let mut position = 0;
+                        folded.retain(|x| {
+                            position += 1;
+                            position <= index || !refers_to_field(x, model.as_str(), name.as_str())
+                        });
+                        if let Operation::CreateModel { fields, .. } = &mut folded[index] {
+                            fields.retain(|x| x.name != name);
+                        } else {
+                            folded.remove(index);
+                        }
+                    }
+                    None => folded.push(Operation::DeleteField {
+                        model,
+                        name,
+                        cascade,
+                    }),
+                }
+            }
+            Operation::RenameField {
+                table_name,
+                old,
+                new,
+            } => {
+                // Index of an operation which created the field or renamed it to its old name
+                let index = folded.iter().rposition(|x| match x {
+                    Operation::CreateModel { name, fields, .. } => {
+                        *name == table_name && fields.iter().any(|y| y.name == old)
+                    }
+                    Operation::CreateField { model, field } => {
+                        *model == table_name && field.name == old
+                    }
+                    Operation::RenameField {
+                        table_name: t,
+                        new: n,
+                        ..
+                    } => *t == table_name && *n == old,
+                    _ => false,
+                });
+
+                match index {
+                    Some(index)
+                        if !folded[index + 1..].iter().any(|x| {
+                            refers_to_model(x, table_name.as_str())
+                                && (refers_to_field(x, table_name.as_str(), old.as_str())
+                                    || model_of(x).is_none())
+                        }) =>
+                    {
+                        if let Operation::RenameField { old: first, .. } = &folded[index] {
+                            if *first == new {
+                                folded.remove(index);
+                            } else {
+                                folded[index] = Operation::RenameField {
+                                    table_name,
+                                    old: first.clone(),
+                                    new,
+                                };
+                            }
+                            continue;
+                        }
+                        match &mut folded[index] {
+                            Operation::CreateModel { fields, .. } => {
+                                for field in fields.iter_mut().filter(|x| x.name == old) {
+                                    field.name = new.clone();
+                                }
+                            }
+                            Operation::CreateField { field, .. } => field.name = new,
+                            _ => {}
+                        }
+                    }
+                    _ => folded.push(Operation::RenameField {
+                        table_name,
+                        old,
+                        new,
+                    }),
+                }
+            }
+            operation => folded.push(operation),
+        }
+    }
+
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{DbType, Field};
+
+    use crate::declaration::{IndexKind, Operation};
+    use crate::make_migrations::operation_to_diff_line;
+    use crate::utils::fold::fold_operations;
+
+    fn field(name: &str) -> Field {
+        Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        }
+    }
+
+    fn create_field(model: &str, name: &str) -> Operation {
+        Operation::CreateField {
+            model: model.to_string(),
+            field: field(name),
+        }
+    }
+
+    fn delete_field(model: &str, name: &str) -> Operation {
+        Operation::DeleteField {
+            model: model.to_string(),
+            name: name.to_string(),
+            cascade: false,
+        }
+    }
+
+    fn rename_field(model: &str, old: &str, new: &str) -> Operation {
+        Operation::RenameField {
+            table_name: model.to_string(),
+            old: old.to_string(),
+            new: new.to_string(),
+        }
+    }
+
+    fn lines(operations: Vec<Operation>) -> Vec<String> {
+        fold_operations(operations)
+            .iter()
+            .map(operation_to_diff_line)
+            .collect()
+    }
+
+    #[test]
+    fn cancel_out() {
+        assert!(lines(vec![
+            create_field("user", "age"),
+            delete_field("user", "age")
+        ])
+        .is_empty());
+        assert!(lines(vec![
+            rename_field("user", "name", "title"),
+            rename_field("user", "title", "name"),
+        ])
+        .is_empty());
+        assert!(lines(vec![
+            Operation::CreateModel {
+                name: "log".to_string(),
+                fields: vec![field("id")],
+                annotations: vec![],
+            },
+            create_field("log", "level"),
+            Operation::CreateIndex {
+                model: "log".to_string(),
+                name: "log_level_index".to_string(),
+                columns: vec!["level".to_string()],
+                unique: false,
+                concurrently: false,
+                kind: IndexKind::Index,
+            },
+            Operation::DeleteModel {
+                name: "log".to_string(),
+                cascade: false,
+            },
+        ])
+        .is_empty());
+    }
+
+    #[test]
+    fn combine() {
+        let folded = fold_operations(vec![
+            Operation::CreateModel {
+                name: "log".to_string(),
+                fields: vec![field("id")],
+                annotations: vec![],
+            },
+            create_field("log", "level"),
+            rename_field("log", "level", "severity"),
+            Operation::RenameModel {
+                old: "log".to_string(),
+                new: "event".to_string(),
+                rename_constraints: false,
+            },
+        ]);
+        match &folded[..] {
+            [Operation::CreateModel { name, fields, .. }] => {
+                assert_eq!(name, "event");
+                assert_eq!(
+                    fields.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+                    vec!["id", "severity"]
+                );
+            }
+            _ => panic!("Unexpected operations {:?}", folded),
+        }
+
+        assert_eq!(
+            lines(vec![
+                rename_field("user", "a", "b"),
+                rename_field("user", "b", "c"),
+                Operation::RenameModel {
+                    old: "user".to_string(),
+                    new: "member".to_string(),
+                    rename_constraints: false,
+                },
+                Operation::RenameModel {
+                    old: "member".to_string(),
+                    new: "account".to_string(),
+                    rename_constraints: false,
+                },
+            ]),
+            vec!["~ user.a -> user.c", "~ model user -> account"]
+        );
+    }
+
+    #[test]
+    fn keep_unrelated() {
+        assert_eq!(
+            lines(vec![
+                delete_field("user", "age"),
+                create_field("user", "age"),
+                create_field("user", "name"),
+            ]),
+            vec!["- user.age", "+ user.age", "+ user.name"]
+        );
+
+        // A renamed model is deleted by its old name
+        assert_eq!(
+            lines(vec![
+                Operation::RenameModel {
+                    old: "user".to_string(),
+                    new: "member".to_string(),
+                    rename_constraints: false,
+                },
+                create_field("member", "age"),
+                Operation::DeleteModel {
+                    name: "member".to_string(),
+                    cascade: false,
+                },
+            ]),
+            vec!["- model user"]
+        );
+
+        // Raw SQL may use the field
+        assert_eq!(
+            lines(vec![
+                create_field("user", "age"),
+                Operation::RunSql {
+                    sql: "UPDATE user SET age = 0;".to_string(),
+                    timeout_seconds: None,
+                },
+                delete_field("user", "age"),
+            ]),
+            vec![
+                "+ user.age",
+                "~ run sql UPDATE user SET age = 0;",
+                "- user.age"
+            ]
+        );
+    }
+}
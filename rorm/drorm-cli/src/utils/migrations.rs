@@ -1,16 +1,35 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::{read_dir, read_to_string, DirEntry, File};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use once_cell::sync::Lazy;
 use regex::Regex;
-use rorm_sql::imr::{InternalModelFormat, Model};
+use rorm_sql::imr::{Annotation, Field, Identity, InternalModelFormat, Model, ModelAnnotation};
 
-use crate::declaration::{Migration, MigrationFile, Operation};
+use crate::declaration::{
+    IndexKind, Migration, MigrationFile, MigrationHeader, MigrationHeaderFile, MigrationMeta,
+    Operation,
+};
+use crate::make_migrations::index_name;
+use crate::migrate::error::MigrationError;
+use crate::migrate::sql_builder::sorted_operations;
 
+/// Migrations are numbered sequentially, by default with four digits, or by a timestamp with fourteen digits
 pub static RE_ALLOWED_NAME: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r#"^[0-9]{4}_\w+\.toml$"#).unwrap());
+    Lazy::new(|| Regex::new(r#"^[0-9]+_\w+\.toml$"#).unwrap());
+
+/**
+Serializes a migration file into its canonical TOML representation.
+
+This is the form make-migrations writes migrations in, see [convert_migration_to_file].
+
+`file` [&MigrationFile]: Migration file to be converted into TOML
+ */
+pub fn migration_file_to_toml(file: &MigrationFile) -> anyhow::Result<String> {
+    toml::to_string_pretty(file).with_context(|| "Error while serializing migration")
+}
 
 /**
 This function is used to convert the [InternalModelFormat] into its TOML representation.
@@ -19,8 +38,7 @@ This function is used to convert the [InternalModelFormat] into its TOML represe
 `path` [&str]: The path to write the resulting TOML to
  */
 pub fn convert_migration_to_file(migration: Migration, path: &Path) -> anyhow::Result<()> {
-    let toml_str = toml::to_string_pretty(&MigrationFile { migration })
-        .with_context(|| "Error while serializing migration")?;
+    let toml_str = migration_file_to_toml(&MigrationFile { migration })?;
 
     let mut output = File::create(path).with_context(|| {
         format!(
@@ -34,160 +52,1761 @@ pub fn convert_migration_to_file(migration: Migration, path: &Path) -> anyhow::R
 }
 
 /**
-This function tries to convert a file to a [Migration].
+Derives the id of a migration from the name of its file.
 
-`path` [&DirEntry]: Path to the file that should be parsed.
+`file_name` [&str]: Name of the migration file.
 */
-pub fn convert_file_to_migration(path: &DirEntry) -> anyhow::Result<MigrationFile> {
-    let toml_str = read_to_string(path.path()).with_context(|| {
-        format!(
-            "Error occurred while reading {}",
-            path.path().to_str().unwrap()
-        )
-    })?;
+fn migration_id(file_name: &str) -> String {
+    Path::new(file_name)
+        .file_stem()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+/**
+This function tries to convert the content of a migration file to a [Migration].
+
+`file_name` [&str]: Name of the migration file, the id of the migration is derived from it.
+`toml_str` [&str]: Content of the migration file.
+*/
+pub fn convert_str_to_migration(file_name: &str, toml_str: &str) -> anyhow::Result<MigrationFile> {
+    let mut migration: MigrationFile = match toml::from_str(toml_str) {
+        Ok(migration) => migration,
+        Err(err) => {
+            // Migrations written by a newer version may use operations this version doesn't know
+            if let Some(operation) = unsupported_operation(toml_str) {
+                return Err(MigrationError::UnsupportedOperation {
+                    migration: migration_id(file_name),
+                    operation,
+                }
+                .into());
+            }
+            return Err(err).with_context(|| {
+                format!(
+                    "Error while deserializing migration {:?} from TOML",
+                    file_name
+                )
+            });
+        }
+    };
+
+    migration.migration.id = migration_id(file_name);
+
+    Ok(migration)
+}
+
+/**
+Helper function to find the type of the first operation of a migration file,
+which is not known to this version.
 
-    let mut migration: MigrationFile = toml::from_str(toml_str.as_str()).with_context(|| {
+Operations wrapped by an `Only` operation are checked as well.
+
+`toml_str` [&str]: Content of the migration file.
+*/
+fn unsupported_operation(toml_str: &str) -> Option<String> {
+    fn check(operation: &toml::Value) -> Option<String> {
+        let type_name = operation.get("Type")?.as_str()?;
+        if type_name == "Only" {
+            return check(operation.get("Operation")?);
+        }
+        match operation.clone().try_into::<Operation>() {
+            Err(err)
+                if err
+                    .to_string()
+                    .contains(&format!("unknown variant `{}`", type_name)) =>
+            {
+                Some(type_name.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    let document: toml::Value = toml::from_str(toml_str).ok()?;
+    document
+        .get("Migration")?
+        .get("Operations")?
+        .as_array()?
+        .iter()
+        .find_map(check)
+}
+
+/**
+This function tries to convert the content of a migration file to a [MigrationHeader],
+without deserializing its operations.
+
+`file_name` [&str]: Name of the migration file, the id of the migration is derived from it.
+`toml_str` [&str]: Content of the migration file.
+*/
+pub fn convert_str_to_migration_header(
+    file_name: &str,
+    toml_str: &str,
+) -> anyhow::Result<MigrationHeaderFile> {
+    // The operations are only counted by the header, their fields are ignored
+    let mut migration = toml::from_str::<MigrationHeaderFile>(toml_str).with_context(|| {
         format!(
             "Error while deserializing migration {:?} from TOML",
-            path.file_name()
+            file_name
         )
     })?;
 
-    migration.migration.id = path
-        .path()
-        .file_stem()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string();
+    migration.migration.id = migration_id(file_name);
 
     Ok(migration)
 }
 
 /**
-Helper function to retrieve a sorted list of migrations in a given directory.
+Helper function to read the content of a migration file.
 
-`migration_dir`: [&str] The directory to search for files.
+`path` [&DirEntry]: Path to the file that should be read.
 */
-pub fn get_existing_migrations(migration_dir: &str) -> anyhow::Result<Vec<Migration>> {
-    let dir_entries =
-        read_dir(migration_dir).with_context(|| "Error while searching the migration directory")?;
-
-    let mut file_list: Vec<DirEntry> = dir_entries
-        .filter(|x| {
-            x.as_ref().unwrap().file_type().unwrap().is_file()
-                && RE_ALLOWED_NAME.is_match(
-                    x.as_ref()
-                        .unwrap()
-                        .file_name()
-                        .into_string()
-                        .unwrap()
-                        .as_str(),
-                )
-        })
-        .map(|x| x.unwrap())
+fn read_migration_file(path: &DirEntry) -> anyhow::Result<String> {
+    read_to_string(path.path()).with_context(|| {
+        format!(
+            "Error occurred while reading {}",
+            path.path().to_str().unwrap()
+        )
+    })
+}
+
+/**
+This function tries to convert a file to a [Migration].
+
+`path` [&DirEntry]: Path to the file that should be parsed.
+*/
+pub fn convert_file_to_migration(path: &DirEntry) -> anyhow::Result<MigrationFile> {
+    convert_str_to_migration(
+        path.file_name().to_str().unwrap(),
+        read_migration_file(path)?.as_str(),
+    )
+}
+
+/**
+This function tries to convert a file to a [MigrationHeader], without deserializing its operations.
+
+`path` [&DirEntry]: Path to the file that should be parsed.
+*/
+pub fn convert_file_to_migration_header(path: &DirEntry) -> anyhow::Result<MigrationHeaderFile> {
+    convert_str_to_migration_header(
+        path.file_name().to_str().unwrap(),
+        read_migration_file(path)?.as_str(),
+    )
+}
+
+/**
+Returns the ids of the migrations, which are replaced by another migration of the list.
+
+`migrations`: [&\[T\]]: List of migrations or their headers.
+*/
+pub fn replaced_migrations<T: MigrationMeta>(migrations: &[T]) -> HashSet<String> {
+    let ids: HashSet<&str> = migrations.iter().map(|x| x.id()).collect();
+    migrations
+        .iter()
+        .flat_map(|x| x.replaces())
+        .filter(|x| ids.contains(x.as_str()))
+        .cloned()
+        .collect()
+}
+
+/**
+Returns the migrations, which no other migration of the list depends on or merges.
+
+The leaves are returned in the order of the list.
+A chain without branches, or whose branches have been merged, has a single leaf.
+
+`migrations`: [&\[T\]]: List of migrations or their headers.
+*/
+pub fn leaf_migrations<T: MigrationMeta>(migrations: &[T]) -> Vec<&T> {
+    let depended: HashSet<&str> = migrations
+        .iter()
+        .flat_map(|x| std::iter::once(x.dependency()).chain(x.merges().iter().map(String::as_str)))
         .collect();
+    migrations
+        .iter()
+        .filter(|x| !depended.contains(x.id()))
+        .collect()
+}
 
-    file_list.sort_by(|x, y| {
-        x.file_name().into_string().unwrap()[0..4]
-            .parse::<u16>()
-            .unwrap()
-            .cmp(
-                &y.file_name().into_string().unwrap()[0..4]
-                    .parse::<u16>()
-                    .unwrap(),
-            )
+/**
+Helper function to find where the branches of two leaves diverge.
+
+Returns the migrations on the way to the first and the second leaf
+and the migration both of them depend on, if the leaves share an ancestor.
+
+`lookup`: [&HashMap<&str, &T>]: The migrations by their id.
+`first`: [&T]: The first leaf.
+`second`: [&T]: The second leaf.
+*/
+pub(crate) fn divergence<'a, T: MigrationMeta>(
+    lookup: &HashMap<&'a str, &'a T>,
+    first: &'a T,
+    second: &'a T,
+) -> Option<(&'a str, &'a str, &'a str)> {
+    // The ancestors of the first leaf and the migration on the way to them
+    let mut ancestors: HashMap<&str, &str> = HashMap::new();
+    let mut current = first;
+    while let Some(&next) = lookup.get(current.dependency()) {
+        if ancestors.insert(next.id(), current.id()).is_some() {
+            break;
+        }
+        current = next;
+    }
+
+    let mut seen = HashSet::new();
+    let mut current = second;
+    while let Some(&next) = lookup.get(current.dependency()) {
+        if let Some(child) = ancestors.get(next.id()) {
+            return Some((child, current.id(), next.id()));
+        }
+        if !seen.insert(next.id()) {
+            break;
+        }
+        current = next;
+    }
+    None
+}
+
+/**
+Helper function to order migrations by their dependency chain.
+
+The chain starts at the only migration without a dependency.
+The branches joined by a merge migration are applied one after another,
+first the branches of its merged leaves in their order, then the branch of its dependency.
+Missing dependencies, branches which haven't been merged, cycles and migrations
+which are not reachable from the end of the chain are rejected.
+Migrations replaced by another migration of the list, e.g. by a squash,
+are left out, the replacing migration takes their place.
+
+`migrations`: [Vec<T>]: List of migrations or their headers to order
+*/
+pub fn order_migrations<T: MigrationMeta>(migrations: Vec<T>) -> anyhow::Result<Vec<T>> {
+    let replaced = replaced_migrations(&migrations);
+    let migrations: Vec<T> = migrations
+        .into_iter()
+        .filter(|x| !replaced.contains(x.id()))
+        .collect();
+    if migrations.is_empty() {
+        return Ok(migrations);
+    }
+
+    let indices: HashMap<&str, usize> = migrations
+        .iter()
+        .enumerate()
+        .map(|(index, x)| (x.id(), index))
+        .collect();
+    let mut initial: Option<usize> = None;
+    for (index, migration) in migrations.iter().enumerate() {
+        if migration.dependency().is_empty() {
+            if let Some(other) = initial {
+                return Err(anyhow!(
+                    "Migrations {} and {} both have no dependency, only the initial migration may have none",
+                    migrations[other].id(),
+                    migration.id()
+                ));
+            }
+            initial = Some(index);
+        }
+
+        for dependency in std::iter::once(migration.dependency())
+            .filter(|x| !x.is_empty())
+            .chain(migration.merges().iter().map(String::as_str))
+        {
+            if !indices.contains_key(dependency) {
+                return Err(anyhow!(
+                    "Migration {} depends on {}, which does not exist",
+                    migration.id(),
+                    dependency
+                ));
+            }
+        }
+    }
+    if initial.is_none() {
+        return Err(anyhow!("No initial migration was found"));
+    }
+
+    let leaves = leaf_migrations(&migrations);
+    if let [first, second, ..] = leaves[..] {
+        let lookup: HashMap<&str, &T> = migrations.iter().map(|x| (x.id(), x)).collect();
+        if let Some((a, b, dependency)) = divergence(&lookup, first, second) {
+            return Err(anyhow!(
+                "Migrations {} and {} both depend on {}, merge them into a single chain with drorm merge-migrations",
+                a,
+                b,
+                dependency
+            ));
+        }
+    }
+    let leaf = match leaves.first() {
+        None => {
+            return Err(anyhow!(
+                "The migrations have a dependency cycle, none of them is the last one"
+            ))
+        }
+        Some(leaf) => indices[leaf.id()],
+    };
+
+    // Depth first from the end of the chain, a migration follows everything it depends on
+    let mut done = vec![false; migrations.len()];
+    let mut visiting = vec![false; migrations.len()];
+    let mut order = vec![];
+    let mut stack = vec![(leaf, false)];
+    while let Some((index, expanded)) = stack.pop() {
+        if expanded {
+            visiting[index] = false;
+            done[index] = true;
+            order.push(index);
+            continue;
+        }
+        if done[index] {
+            continue;
+        }
+        if visiting[index] {
+            return Err(anyhow!(
+                "Migration {} is part of a dependency cycle",
+                migrations[index].id()
+            ));
+        }
+        visiting[index] = true;
+        stack.push((index, true));
+
+        let migration = &migrations[index];
+        let dependencies: Vec<usize> = migration
+            .merges()
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(migration.dependency()).filter(|x| !x.is_empty()))
+            .map(|x| indices[x])
+            .collect();
+        stack.extend(dependencies.into_iter().rev().map(|x| (x, false)));
+    }
+
+    if order.len() != migrations.len() {
+        let mut unreachable: Vec<&str> = migrations
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !done[*index])
+            .map(|(_, x)| x.id())
+            .collect();
+        unreachable.sort_unstable();
+        return Err(anyhow!(
+            "Migrations {} are not reachable from the initial migration",
+            unreachable.join(", ")
+        ));
+    }
+
+    // Move the migrations into their order without cloning them
+    let mut slots: Vec<Option<T>> = migrations.into_iter().map(Some).collect();
+    Ok(order
+        .into_iter()
+        .map(|index| slots[index].take().unwrap())
+        .collect())
+}
+
+/**
+Helper function to list the migration files in a given directory, ordered by their number.
+
+Sequential numbers are ordered before timestamps.
+With `recursive`, the subdirectories are searched as well and their files are flattened
+into one list. The ids are derived from the file names, so two files with the same name
+in different subdirectories are rejected.
+
+`migration_dir`: [&str] The directory to search for files.
+`recursive`: [bool]: Whether the subdirectories are searched as well.
+*/
+pub fn migration_files(migration_dir: &str, recursive: bool) -> anyhow::Result<Vec<DirEntry>> {
+    let mut file_list: Vec<DirEntry> = vec![];
+    let mut dirs = vec![PathBuf::from(migration_dir)];
+    while let Some(dir) = dirs.pop() {
+        let dir_entries = read_dir(&dir)
+            .with_context(|| format!("Error while searching the migration directory {:?}", dir))?;
+        for entry in dir_entries {
+            let entry = entry.with_context(|| "Error while searching the migration directory")?;
+            let file_type = entry
+                .file_type()
+                .with_context(|| format!("Couldn't read the type of {:?}", entry.path()))?;
+            if file_type.is_dir() && recursive {
+                dirs.push(entry.path());
+            } else if file_type.is_file()
+                && RE_ALLOWED_NAME.is_match(entry.file_name().to_string_lossy().as_ref())
+            {
+                file_list.push(entry);
+            }
+        }
+    }
+
+    let mut ids: HashMap<String, PathBuf> = HashMap::new();
+    for file in &file_list {
+        let id = migration_id(file.file_name().to_str().unwrap());
+        if let Some(other) = ids.insert(id.clone(), file.path()) {
+            return Err(anyhow!(
+                "Migration files {:?} and {:?} both have the id {}",
+                other,
+                file.path(),
+                id
+            ));
+        }
+    }
+
+    file_list.sort_by_key(|x| {
+        let file_name = x.file_name().into_string().unwrap();
+        (
+            file_name.split('_').next().unwrap().parse::<u64>().unwrap(),
+            file_name,
+        )
     });
 
+    Ok(file_list)
+}
+
+/**
+Helper function to read the migrations in a given directory, ordered by their number.
+
+Sequential numbers are ordered before timestamps.
+
+`migration_dir`: [&str] The directory to search for files.
+`recursive`: [bool]: Whether the subdirectories are searched as well, see [migration_files].
+*/
+pub fn read_migrations(migration_dir: &str, recursive: bool) -> anyhow::Result<Vec<Migration>> {
     let mut migration: Vec<Migration> = vec![];
-    for file in &file_list {
+    for file in &migration_files(migration_dir, recursive)? {
         migration.push(convert_file_to_migration(file)?.migration);
     }
 
     Ok(migration)
 }
 
+/**
+Helper function to read the headers of the migrations in a given directory, ordered by their number.
+
+The operations are not deserialized, use this if only the metadata of the migrations is needed.
+
+`migration_dir`: [&str] The directory to search for files.
+`recursive`: [bool]: Whether the subdirectories are searched as well, see [migration_files].
+*/
+pub fn read_migration_headers(
+    migration_dir: &str,
+    recursive: bool,
+) -> anyhow::Result<Vec<MigrationHeader>> {
+    let mut migration: Vec<MigrationHeader> = vec![];
+    for file in &migration_files(migration_dir, recursive)? {
+        migration.push(convert_file_to_migration_header(file)?.migration);
+    }
+
+    Ok(migration)
+}
+
+/**
+Helper function to retrieve a sorted list of migrations in a given directory.
+
+The migrations are ordered by their dependency chain, see [order_migrations].
+
+`migration_dir`: [&str] The directory to search for files.
+`recursive`: [bool]: Whether the subdirectories are searched as well, see [migration_files].
+*/
+pub fn get_existing_migrations(
+    migration_dir: &str,
+    recursive: bool,
+) -> anyhow::Result<Vec<Migration>> {
+    order_migrations(read_migrations(migration_dir, recursive)?)
+        .with_context(|| format!("Invalid migrations in {}", migration_dir))
+}
+
+/**
+Helper function to retrieve a sorted list of the headers of the migrations in a given directory.
+
+The migrations are ordered by their dependency chain, see [order_migrations].
+
+`migration_dir`: [&str] The directory to search for files.
+`recursive`: [bool]: Whether the subdirectories are searched as well, see [migration_files].
+*/
+pub fn get_existing_migration_headers(
+    migration_dir: &str,
+    recursive: bool,
+) -> anyhow::Result<Vec<MigrationHeader>> {
+    order_migrations(read_migration_headers(migration_dir, recursive)?)
+        .with_context(|| format!("Invalid migrations in {}", migration_dir))
+}
+
+/**
+Helper function to read migrations from embedded files.
+
+`files`: [&\[(&str, &str)\]]: Pairs of file name and content of the migrations.
+*/
+pub fn read_embedded_migrations(files: &[(&str, &str)]) -> anyhow::Result<Vec<Migration>> {
+    let mut migration: Vec<Migration> = vec![];
+    for (file_name, toml_str) in files {
+        if !RE_ALLOWED_NAME.is_match(file_name) {
+            return Err(anyhow!(
+                "Embedded migration {:?} must be named like 0001_name.toml",
+                file_name
+            ));
+        }
+        migration.push(convert_str_to_migration(file_name, toml_str)?.migration);
+    }
+
+    Ok(migration)
+}
+
+/**
+Helper function to retrieve a sorted list of migrations from embedded files.
+
+The migrations are ordered by their dependency chain, see [order_migrations].
+
+`files`: [&\[(&str, &str)\]]: Pairs of file name and content of the migrations.
+*/
+pub fn get_embedded_migrations(files: &[(&str, &str)]) -> anyhow::Result<Vec<Migration>> {
+    order_migrations(read_embedded_migrations(files)?)
+        .with_context(|| "Invalid embedded migrations")
+}
+
+/**
+A migration directory and the namespace the ids of its migrations are prefixed with.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationDir {
+    /// Namespace of the migrations
+    pub namespace: String,
+    /// Path to the directory
+    pub path: String,
+}
+
+impl MigrationDir {
+    /**
+    Parses a migration directory given as `namespace=path` or `path`.
+
+    Without an explicit namespace, the name of the directory is used.
+    Directories named `migrations` use the name of their parent directory instead.
+
+    `dir`: [&str]: The directory to parse.
+    */
+    pub fn parse(dir: &str) -> Self {
+        if let Some((namespace, path)) = dir.split_once('=') {
+            return MigrationDir {
+                namespace: namespace.to_string(),
+                path: path.to_string(),
+            };
+        }
+
+        let path = Path::new(dir);
+        let name = |p: &Path| {
+            p.file_name()
+                .map(|x| x.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+        let mut namespace = name(path);
+        if namespace == "migrations" {
+            if let Some(parent) = path.parent() {
+                namespace = name(parent);
+            }
+        }
+
+        MigrationDir {
+            namespace,
+            path: dir.to_string(),
+        }
+    }
+}
+
+/**
+Helper function to merge the migrations of multiple directories into one apply order.
+
+The ids, dependencies and replaced migrations are prefixed with the namespace of their
+directory, e.g. `users/0001_initial`. If only one directory is given, the ids are kept as
+they are, so existing bookkeeping stays valid.
+
+Migrations of each directory keep their order. Across directories, a migration is applied
+as soon as all of its external dependencies are applied, preferring earlier directories.
+
+`dirs`: [Vec<(MigrationDir, Vec<Migration>)>]: Directories and their ordered migrations.
+*/
+pub fn merge_migration_dirs(
+    dirs: Vec<(MigrationDir, Vec<Migration>)>,
+) -> anyhow::Result<Vec<Migration>> {
+    let namespaced = dirs.len() > 1;
+
+    let mut namespaces: HashMap<&str, &str> = HashMap::new();
+    for (dir, _) in &dirs {
+        if let Some(other) = namespaces.insert(dir.namespace.as_str(), dir.path.as_str()) {
+            return Err(anyhow!(
+                "Migration directories {} and {} both use the namespace {:?}, set one explicitly with --migration-dir <namespace>=<path>",
+                other,
+                dir.path,
+                dir.namespace
+            ));
+        }
+        if namespaced && (dir.namespace.is_empty() || dir.namespace.contains('/')) {
+            return Err(anyhow!(
+                "Invalid namespace {:?} of migration directory {}",
+                dir.namespace,
+                dir.path
+            ));
+        }
+    }
+
+    let prefix = |namespace: &str, id: &str| {
+        if namespaced && !id.is_empty() {
+            format!("{}/{}", namespace, id)
+        } else {
+            id.to_string()
+        }
+    };
+
+    let mut queues: Vec<VecDeque<Migration>> = vec![];
+    for (dir, migrations) in dirs {
+        queues.push(
+            migrations
+                .into_iter()
+                .map(|mut migration| {
+                    migration.id = prefix(dir.namespace.as_str(), migration.id.as_str());
+                    migration.dependency =
+                        prefix(dir.namespace.as_str(), migration.dependency.as_str());
+                    migration.merges = migration
+                        .merges
+                        .iter()
+                        .map(|x| prefix(dir.namespace.as_str(), x.as_str()))
+                        .collect();
+                    migration.replaces = migration
+                        .replaces
+                        .iter()
+                        .map(|x| prefix(dir.namespace.as_str(), x.as_str()))
+                        .collect();
+                    migration
+                })
+                .collect(),
+        );
+    }
+
+    let ids: HashSet<String> = queues.iter().flatten().map(|x| x.id.clone()).collect();
+    for migration in queues.iter().flatten() {
+        for dependency in &migration.external_dependencies {
+            if !ids.contains(dependency) {
+                return Err(anyhow!(
+                    "Migration {} depends on {}, which does not exist in the given migration directories",
+                    migration.id,
+                    dependency
+                ));
+            }
+        }
+    }
+
+    let mut merged: Vec<Migration> = vec![];
+    let mut applied: HashSet<String> = HashSet::new();
+    while queues.iter().any(|x| !x.is_empty()) {
+        let next = queues.iter().position(|x| {
+            x.front()
+                .is_some_and(|m| m.external_dependencies.iter().all(|d| applied.contains(d)))
+        });
+
+        match next {
+            Some(idx) => {
+                let migration = queues[idx].pop_front().unwrap();
+                applied.insert(migration.id.clone());
+                merged.push(migration);
+            }
+            None => {
+                return Err(anyhow!(
+                    "Migrations {} wait for each other's external dependencies",
+                    queues
+                        .iter()
+                        .filter_map(|x| x.front())
+                        .map(|x| x.id.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+    }
+
+    Ok(merged)
+}
+
+/**
+Helper function to apply a batch of renames to the fields of a model.
+
+All old names refer to the fields before the batch, so fields can swap their names.
+
+`fields`: [&mut \[Field\]]: The fields of the model.
+`renames`: [&\[(String, String)\]]: Pairs of old and new names.
+*/
+pub fn rename_fields(fields: &mut [Field], renames: &[(String, String)]) {
+    for field in fields.iter_mut() {
+        if let Some((_, new)) = renames.iter().find(|(old, _)| *old == field.name) {
+            field.name = new.clone();
+        }
+    }
+}
+
+/**
+Helper function to update the foreign keys referencing renamed models or fields.
+
+The columns declared unique together of the renamed model are updated as well.
+
+`models`: [&mut \[Model\]]: The models whose foreign keys are updated.
+`table`: [&str]: Name of the referenced model before the rename.
+`new_table`: [&str]: Name of the referenced model after the rename.
+`renames`: [&\[(String, String)\]]: Pairs of old and new names of fields of the referenced model.
+*/
+pub fn rename_references(
+    models: &mut [Model],
+    table: &str,
+    new_table: &str,
+    renames: &[(String, String)],
+) {
+    for model in models.iter_mut().filter(|x| x.name == new_table) {
+        for annotation in model.annotations.iter_mut() {
+            if let ModelAnnotation::UniqueTogether(columns) | ModelAnnotation::Index(columns) =
+                annotation
+            {
+                for column in columns.iter_mut() {
+                    if let Some((_, new)) = renames.iter().find(|(old, _)| old == column) {
+                        *column = new.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    for annotation in models.iter_mut().flat_map(|x| x.annotations.iter_mut()) {
+        if let ModelAnnotation::Inherits(parent) = annotation {
+            if parent == table {
+                *parent = new_table.to_string();
+            }
+        }
+    }
+
+    for field in models.iter_mut().flat_map(|x| x.fields.iter_mut()) {
+        for annotation in field.annotations.iter_mut() {
+            if let Annotation::ForeignKey(foreign_key) = annotation {
+                if foreign_key.table_name != table {
+                    continue;
+                }
+                foreign_key.table_name = new_table.to_string();
+                if let Some((_, new)) = renames
+                    .iter()
+                    .find(|(old, _)| *old == foreign_key.column_name)
+                {
+                    foreign_key.column_name = new.clone();
+                }
+            }
+        }
+    }
+}
+
+/**
+Helper function to replace the identity of a field.
+
+Identity columns replace auto increment, so both annotations are removed
+before the new identity is added.
+
+`field`: [&mut Field]: The field to change.
+`identity`: [Option<Identity>]: The new identity, removes the identity if not set.
+*/
+pub fn set_identity(field: &mut Field, identity: Option<Identity>) {
+    field
+        .annotations
+        .retain(|x| !matches!(x, Annotation::AutoIncrement | Annotation::Identity(_)));
+    if let Some(identity) = identity {
+        field.annotations.push(Annotation::Identity(identity));
+    }
+}
+
 /**
 Helper function to converts a list of migrations to an internal model.
 
 `migrations`: [Vec<Migration>]: List of migrations
  */
 pub fn convert_migrations_to_internal_models(
-    migrations: &Vec<Migration>,
+    migrations: &[Migration],
 ) -> anyhow::Result<InternalModelFormat> {
     let mut m = vec![];
 
-    migrations.iter().for_each(|x| {
-        x.operations.iter().for_each(|y| match y {
-            Operation::CreateModel { name, fields } => {
-                m.push(Model {
-                    name: name.clone(),
-                    fields: fields.clone(),
-                    source_defined_at: None,
-                });
-            }
-            Operation::RenameModel { old, new } => {
-                m = m
-                    .iter()
-                    .map(|z| {
-                        let mut a = z.clone();
-                        if &a.name == old {
-                            a.name = new.to_string();
-                        }
-                        return a;
-                    })
-                    .collect();
-            }
-            Operation::DeleteModel { name } => {
-                m = m.iter().filter(|z| z.name != *name).cloned().collect();
-            }
-            Operation::CreateField { model, field } => {
-                for i in 0..m.len() {
-                    if m[i].name == *model {
-                        m[i].fields.push(field.clone());
+    for x in migrations {
+        for y in sorted_operations(x) {
+            // The models are the same on all dialects, only their DDL differs
+            match y.inner() {
+                Operation::CreateModel {
+                    name,
+                    fields,
+                    annotations,
+                } => {
+                    m.push(Model {
+                        name: name.clone(),
+                        fields: fields.clone(),
+                        annotations: annotations.clone(),
+                        source_defined_at: None,
+                    });
+                }
+                Operation::RenameModel { old, new, .. } => {
+                    m = m
+                        .iter()
+                        .map(|z| {
+                            let mut a = z.clone();
+                            if &a.name == old {
+                                a.name = new.to_string();
+                            }
+                            a
+                        })
+                        .collect();
+                    rename_references(&mut m, old, new, &[]);
+                }
+                Operation::DeleteModel { name, .. } => {
+                    m = m.iter().filter(|z| z.name != *name).cloned().collect();
+                }
+                Operation::CreateField { model, field } => {
+                    for z in m.iter_mut() {
+                        if z.name == *model {
+                            z.fields.push(field.clone());
+                        }
                     }
                 }
-            }
-            Operation::RenameField {
-                table_name,
-                old,
-                new,
-            } => {
-                m = m
-                    .iter()
-                    .map(|z| {
-                        let mut a = z.clone();
-                        if &a.name == table_name {
-                            a.fields = a
+                Operation::RenameField {
+                    table_name,
+                    old,
+                    new,
+                } => {
+                    m = m
+                        .iter()
+                        .map(|z| {
+                            let mut a = z.clone();
+                            if &a.name == table_name {
+                                a.fields = a
+                                    .fields
+                                    .iter()
+                                    .map(|b| {
+                                        let mut c = b.clone();
+                                        if &c.name == old {
+                                            c.name = new.to_string();
+                                        }
+                                        c
+                                    })
+                                    .collect();
+                            }
+                            a
+                        })
+                        .collect();
+                    rename_references(
+                        &mut m,
+                        table_name,
+                        table_name,
+                        &[(old.clone(), new.clone())],
+                    );
+                }
+                Operation::RenameFields {
+                    table_name,
+                    renames,
+                } => {
+                    for z in m.iter_mut() {
+                        if z.name == *table_name {
+                            rename_fields(&mut z.fields, renames);
+                        }
+                    }
+                    rename_references(&mut m, table_name, table_name, renames);
+                }
+                Operation::AlterField { model, field, .. }
+                | Operation::AlterEnum { model, field, .. } => {
+                    match m
+                        .iter_mut()
+                        .find(|z| z.name == *model)
+                        .and_then(|z| z.fields.iter_mut().find(|a| a.name == field.name))
+                    {
+                        None => {
+                            return Err(anyhow!(
+                                "Migration {} alters the field {}.{}, which does not exist",
+                                x.id,
+                                model,
+                                field.name
+                            ))
+                        }
+                        Some(existing) => *existing = field.clone(),
+                    }
+                }
+                Operation::DeleteField { model, name, .. } => {
+                    for z in m.iter_mut() {
+                        if z.name == *model {
+                            z.fields = z
                                 .fields
                                 .iter()
-                                .map(|b| {
-                                    let mut c = b.clone();
-                                    if &c.name == old {
-                                        c.name = new.to_string();
-                                    }
-                                    return c;
-                                })
+                                .filter(|a| a.name != *name)
+                                .cloned()
                                 .collect();
+                            // Unique indexes and indexes are dropped with their columns
+                            z.annotations.retain(|a| {
+                                !matches!(a, ModelAnnotation::UniqueTogether(columns) | ModelAnnotation::Index(columns) if columns.contains(name))
+                            });
                         }
-                        return a;
-                    })
-                    .collect();
-            }
-            Operation::DeleteField { model, name } => {
-                for i in 0..m.len() {
-                    if m[i].name == *model {
-                        m[i].fields = m[i]
-                            .fields
-                            .iter()
-                            .filter(|z| z.name != *name)
-                            .cloned()
-                            .collect();
                     }
                 }
+                // Indexes managed by make-migrations are recognized by their name,
+                // other indexes and constraints are not part of the internal model format
+                Operation::CreateIndex {
+                    model,
+                    name,
+                    columns,
+                    unique: false,
+                    kind: IndexKind::Index,
+                    ..
+                } if *name == index_name(model, columns) => {
+                    for z in m.iter_mut() {
+                        if z.name == *model {
+                            z.annotations.push(ModelAnnotation::Index(columns.clone()));
+                        }
+                    }
+                }
+                Operation::DropIndex { model, columns, .. } => {
+                    for z in m.iter_mut() {
+                        if z.name == *model {
+                            z.annotations.retain(
+                                |a| !matches!(a, ModelAnnotation::Index(existing) if existing == columns),
+                            );
+                        }
+                    }
+                }
+                Operation::CreateIndex { .. }
+                | Operation::RenameIndex { .. }
+                | Operation::RenameConstraint { .. }
+                | Operation::CreateCheck { .. }
+                | Operation::ValidateConstraint { .. }
+                | Operation::DropConstraint { column: None, .. } => {}
+                Operation::DropConstraint {
+                    model,
+                    column: Some(column),
+                    ..
+                } => {
+                    match m
+                        .iter_mut()
+                        .find(|z| z.name == *model)
+                        .and_then(|z| z.fields.iter_mut().find(|a| a.name == *column))
+                    {
+                        None => {
+                            return Err(anyhow!(
+                                "Migration {} drops a foreign key of {}.{}, which does not exist",
+                                x.id,
+                                model,
+                                column
+                            ))
+                        }
+                        Some(field) => field
+                            .annotations
+                            .retain(|a| !matches!(a, Annotation::ForeignKey(_))),
+                    }
+                }
+                Operation::CreateForeignKey {
+                    model,
+                    column,
+                    foreign_key,
+                    ..
+                } => {
+                    match m
+                        .iter_mut()
+                        .find(|z| z.name == *model)
+                        .and_then(|z| z.fields.iter_mut().find(|a| a.name == *column))
+                    {
+                        None => {
+                            return Err(anyhow!(
+                                "Migration {} creates a foreign key on {}.{}, which does not exist",
+                                x.id,
+                                model,
+                                column
+                            ))
+                        }
+                        Some(field) => field
+                            .annotations
+                            .push(Annotation::ForeignKey(foreign_key.clone())),
+                    }
+                }
+                // Partitions are tables of their own, but not models
+                Operation::CreatePartition { model, .. }
+                | Operation::AttachPartition { model, .. } => {
+                    match m.iter().find(|z| z.name == *model) {
+                        None => {
+                            return Err(anyhow!(
+                                "Migration {} creates a partition of {}, which does not exist",
+                                x.id,
+                                model
+                            ))
+                        }
+                        Some(z)
+                            if !z
+                                .annotations
+                                .iter()
+                                .any(|a| matches!(a, ModelAnnotation::PartitionBy(_))) =>
+                        {
+                            return Err(anyhow!(
+                                "Migration {} creates a partition of {}, which is not partitioned",
+                                x.id,
+                                model
+                            ))
+                        }
+                        Some(_) => {}
+                    }
+                }
+                Operation::DetachPartition { .. } => {}
+                Operation::SetInherit { model, parent } => {
+                    if !m.iter().any(|z| z.name == *parent) {
+                        return Err(anyhow!(
+                            "Migration {} inherits {} from {}, which does not exist",
+                            x.id,
+                            model,
+                            parent
+                        ));
+                    }
+                    let z = m.iter_mut().find(|z| z.name == *model).ok_or_else(|| {
+                        anyhow!(
+                            "Migration {} inherits {} from {}, but {} does not exist",
+                            x.id,
+                            model,
+                            parent,
+                            model
+                        )
+                    })?;
+                    z.annotations
+                        .push(ModelAnnotation::Inherits(parent.clone()));
+                }
+                Operation::NoInherit { model, parent } => {
+                    for z in m.iter_mut() {
+                        if z.name == *model {
+                            z.annotations
+                                .retain(|a| *a != ModelAnnotation::Inherits(parent.clone()));
+                        }
+                    }
+                }
+                Operation::CreateUnique { model, columns, .. } => {
+                    let z = m.iter_mut().find(|z| z.name == *model).ok_or_else(|| {
+                        anyhow!(
+                            "Migration {} creates a unique constraint on {}, which does not exist",
+                            x.id,
+                            model
+                        )
+                    })?;
+                    if let Some(column) = columns
+                        .iter()
+                        .find(|a| !z.fields.iter().any(|b| b.name == **a))
+                    {
+                        return Err(anyhow!(
+                            "Migration {} creates a unique constraint on {}.{}, which does not exist",
+                            x.id,
+                            model,
+                            column
+                        ));
+                    }
+                    z.annotations
+                        .push(ModelAnnotation::UniqueTogether(columns.clone()));
+                }
+                Operation::DropUnique { model, columns, .. } => {
+                    let mut key: Vec<&String> = columns.iter().collect();
+                    key.sort();
+                    for z in m.iter_mut() {
+                        if z.name == *model {
+                            z.annotations.retain(|a| match a {
+                                ModelAnnotation::UniqueTogether(existing) => {
+                                    let mut existing: Vec<&String> = existing.iter().collect();
+                                    existing.sort();
+                                    existing != key
+                                }
+                                _ => true,
+                            });
+                        }
+                    }
+                }
+                Operation::SetTablespace { model, tablespace } => {
+                    for z in m.iter_mut() {
+                        if z.name == *model {
+                            z.annotations
+                                .retain(|a| !matches!(a, ModelAnnotation::Tablespace(_)));
+                            if let Some(t) = tablespace {
+                                z.annotations.push(ModelAnnotation::Tablespace(t.clone()));
+                            }
+                        }
+                    }
+                }
+                Operation::SetCharset {
+                    model,
+                    charset,
+                    collation,
+                } => {
+                    for z in m.iter_mut() {
+                        if z.name == *model {
+                            z.annotations.retain(|a| {
+                                !matches!(
+                                    a,
+                                    ModelAnnotation::Charset(_) | ModelAnnotation::Collation(_)
+                                )
+                            });
+                            if let Some(c) = charset {
+                                z.annotations.push(ModelAnnotation::Charset(c.clone()));
+                            }
+                            if let Some(c) = collation {
+                                z.annotations.push(ModelAnnotation::Collation(c.clone()));
+                            }
+                        }
+                    }
+                }
+                Operation::SetStorageParameters { model, set, reset } => {
+                    for z in m.iter_mut() {
+                        if z.name == *model {
+                            z.annotations.retain(|a| match a {
+                                ModelAnnotation::StorageParameter(p) => {
+                                    !reset.contains(&p.name)
+                                        && !set.iter().any(|b| b.name == p.name)
+                                }
+                                _ => true,
+                            });
+                            z.annotations
+                                .extend(set.iter().cloned().map(ModelAnnotation::StorageParameter));
+                        }
+                    }
+                }
+                Operation::SetSequence { model, column, .. } => {
+                    let auto_increment = m
+                        .iter()
+                        .find(|z| z.name == *model)
+                        .and_then(|z| z.fields.iter().find(|a| a.name == *column))
+                        .map(|a| {
+                            a.annotations.iter().any(|b| {
+                                matches!(b, Annotation::AutoIncrement | Annotation::Identity(_))
+                            })
+                        });
+
+                    match auto_increment {
+                    None => {
+                        return Err(anyhow!(
+                            "Migration {} sets the sequence of {}.{}, which does not exist",
+                            x.id,
+                            model,
+                            column
+                        ))
+                    }
+                    Some(false) => {
+                        return Err(anyhow!(
+                            "Migration {} sets the sequence of {}.{}, which is not an auto_increment field",
+                            x.id,
+                            model,
+                            column
+                        ))
+                    }
+                    Some(true) => {}
+                }
+                }
+                Operation::AddIdentity {
+                    model,
+                    column,
+                    identity,
+                    ..
+                }
+                | Operation::SetIdentity {
+                    model,
+                    column,
+                    identity,
+                } => {
+                    match m
+                        .iter_mut()
+                        .find(|z| z.name == *model)
+                        .and_then(|z| z.fields.iter_mut().find(|a| a.name == *column))
+                    {
+                        None => {
+                            return Err(anyhow!(
+                                "Migration {} sets the identity of {}.{}, which does not exist",
+                                x.id,
+                                model,
+                                column
+                            ))
+                        }
+                        Some(field) => set_identity(field, Some(*identity)),
+                    }
+                }
+                Operation::DropIdentity { model, column } => {
+                    match m
+                        .iter_mut()
+                        .find(|z| z.name == *model)
+                        .and_then(|z| z.fields.iter_mut().find(|a| a.name == *column))
+                    {
+                        None => {
+                            return Err(anyhow!(
+                                "Migration {} drops the identity of {}.{}, which does not exist",
+                                x.id,
+                                model,
+                                column
+                            ))
+                        }
+                        Some(field) => set_identity(field, None),
+                    }
+                }
+                Operation::Truncate { model, .. } => {
+                    if !m.iter().any(|z| z.name == *model) {
+                        return Err(anyhow!(
+                            "Migration {} truncates model {}, which does not exist",
+                            x.id,
+                            model
+                        ));
+                    }
+                }
+                // Raw SQL and extensions are not reflected in the models
+                Operation::RunSql { .. }
+                | Operation::CreateExtension { .. }
+                | Operation::DropExtension { .. } => {}
+                Operation::Only { .. } => unreachable!("dialect filters are removed by inner"),
             }
-        })
-    });
+        }
+    }
 
     Ok(InternalModelFormat { models: m })
 }
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{Annotation, DbType, Field, ModelAnnotation};
+
+    use crate::declaration::{IndexKind, Migration, Operation};
+    use crate::migrate::error::MigrationError;
+    use crate::utils::migrations::{
+        convert_migrations_to_internal_models, convert_str_to_migration,
+        convert_str_to_migration_header, get_existing_migrations, merge_migration_dirs,
+        order_migrations, read_migrations, MigrationDir,
+    };
+    use crate::utils::test_helpers;
+
+    fn migration(id: &str, dependency: &str) -> Migration {
+        test_helpers::migration(id, dependency, vec![])
+    }
+
+    fn ids(migrations: Vec<Migration>) -> Vec<String> {
+        migrations.into_iter().map(|x| x.id).collect()
+    }
+
+    #[test]
+    fn order_by_dependency() {
+        let ordered = order_migrations(vec![
+            migration("0010_c", "0009_b"),
+            migration("0009_b", "0001_initial"),
+            migration("0001_initial", ""),
+        ])
+        .unwrap();
+        assert_eq!(ids(ordered), vec!["0001_initial", "0009_b", "0010_c"]);
+
+        // A squash takes the place of the migrations it replaces, while they still exist
+        let mut squashed = migration("0011_squashed", "");
+        squashed.replaces = vec!["0001_initial".to_string(), "0009_b".to_string()];
+        let ordered = order_migrations(vec![
+            migration("0001_initial", ""),
+            migration("0009_b", "0001_initial"),
+            squashed,
+            migration("0012_d", "0011_squashed"),
+        ])
+        .unwrap();
+        assert_eq!(ids(ordered), vec!["0011_squashed", "0012_d"]);
+    }
+
+    #[test]
+    fn order_merged_branches() {
+        let branches = || {
+            vec![
+                migration("0001_initial", ""),
+                migration("0002_a", "0001_initial"),
+                migration("0002_b", "0001_initial"),
+                migration("0003_b", "0002_b"),
+            ]
+        };
+        let err = order_migrations(branches()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Migrations 0002_a and 0002_b both depend on 0001_initial"));
+
+        // The merged branches are applied one after another, before the merge migration
+        let mut merge = migration("0004_merge", "0002_a");
+        merge.merges = vec!["0003_b".to_string()];
+        let mut migrations = branches();
+        migrations.insert(0, merge.clone());
+        migrations.push(migration("0005_c", "0004_merge"));
+        assert_eq!(
+            ids(order_migrations(migrations).unwrap()),
+            vec![
+                "0001_initial",
+                "0002_b",
+                "0003_b",
+                "0002_a",
+                "0004_merge",
+                "0005_c"
+            ]
+        );
+
+        // Merging a leaf which doesn't exist or a cycle is refused
+        merge.merges = vec!["0003_c".to_string()];
+        let mut migrations = branches();
+        migrations.push(merge.clone());
+        assert!(order_migrations(migrations).is_err());
+
+        let mut cycle = migration("0002_a", "0001_initial");
+        cycle.merges = vec!["0004_merge".to_string()];
+        merge.merges = vec!["0003_b".to_string()];
+        let mut migrations = branches();
+        migrations[1] = cycle;
+        migrations.push(merge);
+        let err = order_migrations(migrations).unwrap_err();
+        assert!(err.to_string().contains("dependency cycle"));
+    }
+
+    #[test]
+    fn unsupported_operation() {
+        let toml_str = |operation: &str| {
+            format!(
+                r#"[Migration]
+Hash = ""
+Initial = true
+Dependency = ""
+Replaces = []
+
+[[Migration.Operations]]
+Type = "DeleteModel"
+Name = "post"
+
+[[Migration.Operations]]
+{}
+"#,
+                operation
+            )
+        };
+        let unsupported = |operation: &str| match convert_str_to_migration(
+            "0002_view.toml",
+            toml_str(operation).as_str(),
+        )
+        .unwrap_err()
+        .downcast::<MigrationError>()
+        {
+            Ok(MigrationError::UnsupportedOperation {
+                migration,
+                operation,
+            }) => Some((migration, operation)),
+            _ => None,
+        };
+
+        assert_eq!(
+            unsupported("Type = \"CreateMaterializedView\"\nModel = \"user\""),
+            Some((
+                "0002_view".to_string(),
+                "CreateMaterializedView".to_string()
+            ))
+        );
+        assert_eq!(
+            unsupported(
+                "Type = \"Only\"\nDialects = [\"postgres\"]\nOperation = { Type = \"CreateMaterializedView\" }"
+            ),
+            Some(("0002_view".to_string(), "CreateMaterializedView".to_string()))
+        );
+        // Malformed known operations and unknown values of fields are reported as usual
+        assert_eq!(unsupported("Type = \"CreateModel\"\nName = \"user\""), None);
+        assert_eq!(
+            unsupported("Type = \"Only\"\nDialects = [\"oracle\"]\nOperation = { Type = \"DeleteModel\", Name = \"user\" }"),
+            None
+        );
+    }
+
+    #[test]
+    fn headers() {
+        let toml_str = r#"[Migration]
+Hash = "123"
+Initial = false
+Dependency = "0001_initial"
+Replaces = []
+
+[[Migration.Operations]]
+Type = "CreateModel"
+Name = "user"
+
+[[Migration.Operations.Fields]]
+Name = "id"
+Type = "int64"
+Annotations = [{ Type = "primary_key" }]
+
+[[Migration.Operations]]
+Type = "SomethingNew"
+"#;
+
+        // The operations are only counted, so unknown ones don't matter
+        let header = convert_str_to_migration_header("0002_user.toml", toml_str)
+            .unwrap()
+            .migration;
+        assert_eq!(header.id, "0002_user");
+        assert_eq!(header.dependency, "0001_initial");
+        assert_eq!(header.hash, "123");
+        assert!(header.atomic);
+        assert_eq!(header.operation_count, 2);
+        assert!(convert_str_to_migration("0002_user.toml", toml_str).is_err());
+
+        let empty = convert_str_to_migration_header(
+            "0001_initial.toml",
+            "[Migration]\nHash = \"\"\nInitial = true\nDependency = \"\"\nReplaces = []\nOperations = []\n",
+        )
+        .unwrap()
+        .migration;
+        assert_eq!(empty.operation_count, 0);
+
+        let inline = convert_str_to_migration_header(
+            "0003_inline.toml",
+            "[Migration]\nHash = \"\"\nInitial = false\nDependency = \"0002_user\"\nReplaces = []\nOperations = [\n    { Type = \"RenameFields\", TableName = \"user\", Renames = [\n        [\"a\", \"b\"],\n    ] },\n    { Type = \"RunSql\", Sql = \"SELECT 1;\" },\n]\n",
+        )
+        .unwrap()
+        .migration;
+        assert_eq!(inline.operation_count, 2);
+
+        // Multi-line strings may contain lines looking like table headers
+        let multi_line = convert_str_to_migration_header(
+            "0004_sql.toml",
+            "[Migration]\nHash = \"\"\nInitial = false\nDependency = \"0003_inline\"\nReplaces = []\n\n[[Migration.Operations]]\nType = \"RunSql\"\nSql = \"\"\"\n[[Migration.Operations]]\nSELECT 1;\n\"\"\"\n\n[[Migration.Operations]]\nType = \"RunSql\"\nSql = \"SELECT 2;\"\n",
+        )
+        .unwrap()
+        .migration;
+        assert_eq!(multi_line.operation_count, 2);
+
+        let ordered = order_migrations(vec![header, empty]).unwrap();
+        assert_eq!(ordered[0].id, "0001_initial");
+        assert_eq!(ordered[1].id, "0002_user");
+    }
+
+    #[test]
+    fn missing_dependency() {
+        assert!(order_migrations(vec![
+            migration("0001_initial", ""),
+            migration("0006_a", "0005_missing"),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn branch() {
+        assert!(order_migrations(vec![
+            migration("0001_initial", ""),
+            migration("0002_a", "0001_initial"),
+            migration("0002_b", "0001_initial"),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn unreachable() {
+        assert!(order_migrations(vec![
+            migration("0001_initial", ""),
+            migration("0002_a", "0003_b"),
+            migration("0003_b", "0002_a"),
+        ])
+        .is_err());
+        assert!(order_migrations(vec![
+            migration("0002_a", "0003_b"),
+            migration("0003_b", "0002_a")
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn set_sequence_requires_auto_increment() {
+        let state = |annotations| {
+            let mut m = migration("0001_initial", "");
+            m.operations = vec![
+                Operation::CreateModel {
+                    name: "user".to_string(),
+                    fields: vec![Field {
+                        name: "id".to_string(),
+                        db_type: DbType::UInt64,
+                        annotations,
+                        source_defined_at: None,
+                    }],
+                    annotations: vec![],
+                },
+                Operation::SetSequence {
+                    model: "user".to_string(),
+                    column: "id".to_string(),
+                    value: 100,
+                },
+            ];
+            convert_migrations_to_internal_models(&[m])
+        };
+
+        assert!(state(vec![Annotation::PrimaryKey, Annotation::AutoIncrement]).is_ok());
+        assert!(state(vec![Annotation::PrimaryKey]).is_err());
+    }
+
+    #[test]
+    fn migration_dir_namespace() {
+        assert_eq!(
+            MigrationDir::parse("crates/users/migrations/").namespace,
+            "users"
+        );
+        assert_eq!(MigrationDir::parse("./billing").namespace, "billing");
+        assert_eq!(
+            MigrationDir::parse("auth=crates/users/migrations"),
+            MigrationDir {
+                namespace: "auth".to_string(),
+                path: "crates/users/migrations".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn recursive_discovery() {
+        let dir = std::env::temp_dir().join(format!("drorm_recursive_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let write = |path: &str, dependency: &str| {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(
+                path,
+                format!(
+                    "[Migration]\nHash = \"\"\nInitial = {}\nDependency = \"{}\"\nReplaces = []\nOperations = []\n",
+                    dependency.is_empty(),
+                    dependency
+                ),
+            )
+            .unwrap();
+        };
+        write("0001_initial.toml", "");
+        write("2024q1/0002_users.toml", "0001_initial");
+        write("2024q1/billing/0003_invoices.toml", "0002_users");
+        write("2024q2/0004_payments.toml", "0003_invoices");
+        write("2024q2/README.md", "");
+        let path = dir.to_str().unwrap();
+
+        // Without recursion, only the top level is searched
+        assert_eq!(
+            ids(read_migrations(path, false).unwrap()),
+            vec!["0001_initial"]
+        );
+        assert_eq!(
+            ids(get_existing_migrations(path, true).unwrap()),
+            vec![
+                "0001_initial",
+                "0002_users",
+                "0003_invoices",
+                "0004_payments"
+            ]
+        );
+
+        write("2024q2/0002_users.toml", "0001_initial");
+        let err = read_migrations(path, true).unwrap_err().to_string();
+        assert!(err.ends_with("both have the id 0002_users"), "{}", err);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merge_dirs() {
+        let mut orders = migration("0001_initial", "");
+        orders.external_dependencies = vec!["users/0002_b".to_string()];
+
+        let merged = merge_migration_dirs(vec![
+            (
+                MigrationDir::parse("orders=o"),
+                vec![orders, migration("0002_b", "0001_initial")],
+            ),
+            (
+                MigrationDir::parse("users=u"),
+                vec![
+                    migration("0001_initial", ""),
+                    migration("0002_b", "0001_initial"),
+                ],
+            ),
+        ])
+        .unwrap();
+        assert_eq!(merged[3].dependency, "orders/0001_initial");
+        assert_eq!(
+            ids(merged),
+            vec![
+                "users/0001_initial",
+                "users/0002_b",
+                "orders/0001_initial",
+                "orders/0002_b"
+            ]
+        );
+
+        // A single directory keeps its ids
+        let merged = merge_migration_dirs(vec![(
+            MigrationDir::parse("m"),
+            vec![migration("0001_initial", "")],
+        )])
+        .unwrap();
+        assert_eq!(ids(merged), vec!["0001_initial"]);
+    }
+
+    #[test]
+    fn merge_dirs_errors() {
+        let dir = |path: &str| {
+            (
+                MigrationDir::parse(path),
+                vec![migration("0001_initial", "")],
+            )
+        };
+        assert!(merge_migration_dirs(vec![dir("a/migrations"), dir("b/a")]).is_err());
+
+        let mut waiting = migration("0001_initial", "");
+        waiting.external_dependencies = vec!["b/0001_initial".to_string()];
+        let mut other = migration("0001_initial", "");
+        other.external_dependencies = vec!["a/0001_initial".to_string()];
+        assert!(merge_migration_dirs(vec![
+            (MigrationDir::parse("a"), vec![waiting.clone()]),
+            (MigrationDir::parse("b"), vec![other])
+        ])
+        .is_err());
+
+        assert!(
+            merge_migration_dirs(vec![(MigrationDir::parse("a"), vec![waiting]), dir("c")])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn truncate_requires_model() {
+        let mut m = migration("0001_initial", "");
+        m.operations = vec![Operation::Truncate {
+            model: "user".to_string(),
+            cascade: false,
+            restart_identity: false,
+        }];
+
+        assert!(convert_migrations_to_internal_models(&[m]).is_err());
+    }
+
+    #[test]
+    fn unique_together_state() {
+        let field = |name: &str| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+        let columns = |x: &[&str]| x.iter().map(|y| y.to_string()).collect::<Vec<String>>();
+        let unique = |unique: &[&str], operations: Vec<Operation>| {
+            let mut initial = migration("0001_initial", "");
+            initial.operations = vec![
+                Operation::CreateModel {
+                    name: "member".to_string(),
+                    fields: vec![field("user"), field("group")],
+                    annotations: vec![],
+                },
+                Operation::CreateUnique {
+                    model: "member".to_string(),
+                    name: "member_unique".to_string(),
+                    columns: columns(unique),
+                    nulls_not_distinct: false,
+                },
+            ];
+            let mut change = migration("0002_change", "0001_initial");
+            change.operations = operations;
+            convert_migrations_to_internal_models(&[initial, change])
+                .map(|x| x.models[0].annotations.clone())
+        };
+
+        // Renamed columns are renamed in the declaration
+        let rename = Operation::RenameField {
+            table_name: "member".to_string(),
+            old: "user".to_string(),
+            new: "account".to_string(),
+        };
+        assert_eq!(
+            unique(&["user", "group"], vec![rename.clone()]).unwrap(),
+            vec![ModelAnnotation::UniqueTogether(columns(&[
+                "account", "group"
+            ]))]
+        );
+        // The declaration is dropped by its set of columns before the renames
+        assert!(unique(
+            &["user", "group"],
+            vec![
+                rename,
+                Operation::DropUnique {
+                    model: "member".to_string(),
+                    name: "member_unique".to_string(),
+                    columns: columns(&["group", "user"]),
+                },
+            ]
+        )
+        .unwrap()
+        .is_empty());
+        assert!(unique(
+            &["user", "group"],
+            vec![Operation::DeleteField {
+                model: "member".to_string(),
+                name: "group".to_string(),
+                cascade: false,
+            }]
+        )
+        .unwrap()
+        .is_empty());
+
+        assert!(unique(&["user", "role"], vec![]).is_err());
+    }
+
+    #[test]
+    fn index_state() {
+        let field = |name: &str| Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        };
+        let columns = |x: &[&str]| x.iter().map(|y| y.to_string()).collect::<Vec<String>>();
+        let index = |name: &str, operations: Vec<Operation>| {
+            let mut initial = migration("0001_initial", "");
+            initial.operations = vec![
+                Operation::CreateModel {
+                    name: "event".to_string(),
+                    fields: vec![field("tenant_id"), field("created_at")],
+                    annotations: vec![],
+                },
+                Operation::CreateIndex {
+                    model: "event".to_string(),
+                    name: name.to_string(),
+                    columns: columns(&["tenant_id", "created_at"]),
+                    unique: false,
+                    concurrently: false,
+                    kind: IndexKind::Index,
+                },
+            ];
+            let mut change = migration("0002_change", "0001_initial");
+            change.operations = operations;
+            convert_migrations_to_internal_models(&[initial, change])
+                .unwrap()
+                .models[0]
+                .annotations
+                .clone()
+        };
+
+        // Only indexes named by make-migrations are managed by it
+        assert_eq!(
+            index("event_tenant_id_created_at_idx", vec![]),
+            vec![ModelAnnotation::Index(columns(&[
+                "tenant_id",
+                "created_at"
+            ]))]
+        );
+        assert!(index("event_custom", vec![]).is_empty());
+
+        assert_eq!(
+            index(
+                "event_tenant_id_created_at_idx",
+                vec![Operation::RenameField {
+                    table_name: "event".to_string(),
+                    old: "tenant_id".to_string(),
+                    new: "team_id".to_string(),
+                }]
+            ),
+            vec![ModelAnnotation::Index(columns(&["team_id", "created_at"]))]
+        );
+        assert!(index(
+            "event_tenant_id_created_at_idx",
+            vec![Operation::DropIndex {
+                model: "event".to_string(),
+                name: "event_tenant_id_created_at_idx".to_string(),
+                columns: columns(&["tenant_id", "created_at"]),
+            }]
+        )
+        .is_empty());
+        assert!(index(
+            "event_tenant_id_created_at_idx",
+            vec![Operation::DeleteField {
+                model: "event".to_string(),
+                name: "created_at".to_string(),
+                cascade: false,
+            }]
+        )
+        .is_empty());
+    }
+}
@@ -1 +1,6 @@
+pub mod fold;
 pub mod migrations;
+pub mod output;
+pub mod question;
+#[cfg(test)]
+pub(crate) mod test_helpers;
@@ -0,0 +1,197 @@
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use clap::ValueEnum;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/**
+Suppresses all informational output and warnings of the process, e.g. for `--quiet`.
+
+Errors are still returned, and questions fail instead of being asked.
+
+`quiet`: [bool]: Whether the output should be suppressed.
+*/
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/**
+Returns whether informational output is suppressed.
+*/
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/**
+Sets how much diagnostic output is printed, e.g. the number of `-v` flags.
+
+`1` prints the database connected to, `2` additionally prints the detected server version.
+Diagnostic output is suppressed by [set_quiet] as well.
+
+`verbosity`: [u8]: The level of diagnostic output.
+*/
+pub fn set_verbosity(verbosity: u8) {
+    VERBOSITY.store(verbosity, Ordering::Relaxed);
+}
+
+/**
+Returns the level of diagnostic output, see [set_verbosity].
+*/
+pub fn verbosity() -> u8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+/**
+When the output is colored, e.g. for `--color`.
+*/
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, ValueEnum)]
+pub enum ColorChoice {
+    /// Color the output, if stdout is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    /// Always color the output
+    Always,
+    /// Never color the output
+    Never,
+}
+
+impl ColorChoice {
+    /**
+    Whether the output is colored with this choice.
+
+    `terminal`: [bool]: Whether stdout is a terminal, which supports colors.
+    */
+    pub fn colors(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => terminal,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/**
+Sets whether the output of the process is colored, see [paint].
+
+Output which is machine readable, e.g. JSON, must not be colored.
+The output of the library interface isn't colored, unless this is set.
+
+`choice`: [ColorChoice]: When the output is colored.
+*/
+pub fn set_color(choice: ColorChoice) {
+    let terminal = std::io::stdout().is_terminal()
+        && std::env::var_os("NO_COLOR").is_none()
+        && std::env::var("TERM").map_or(true, |x| x != "dumb");
+    COLOR.store(choice.colors(terminal), Ordering::Relaxed);
+}
+
+/**
+Returns whether the output is colored, see [set_color].
+*/
+pub fn is_colored() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/**
+The meaning of highlighted output, which determines its color.
+*/
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// Something has been done successfully, e.g. an applied migration
+    Success,
+    /// Something requires attention, e.g. a warning or a pending migration
+    Warning,
+    /// Something failed
+    Error,
+}
+
+impl Style {
+    /**
+    Wraps the text in the escape codes of the style.
+
+    `text`: [impl Display]: The text to color.
+    */
+    pub fn apply(self, text: impl Display) -> String {
+        let code = match self {
+            Style::Success => "32",
+            Style::Warning => "33",
+            Style::Error => "31",
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    }
+}
+
+/**
+Colors the text in the given style, if the output is colored.
+
+`style`: [Style]: The meaning of the text.
+`text`: [impl Display]: The text to color.
+*/
+pub fn paint(style: Style, text: impl Display) -> String {
+    match is_colored() {
+        true => style.apply(text),
+        false => text.to_string(),
+    }
+}
+
+/**
+Colors a line of a diff by its prefix, `+` as success, `-` as error and `~` as warning.
+
+`line`: [&str]: The diff line, e.g. `+ user.age`.
+*/
+pub fn paint_diff_line(line: &str) -> String {
+    match line.trim_start().chars().next() {
+        Some('+') => paint(Style::Success, line),
+        Some('-') => paint(Style::Error, line),
+        Some('~') => paint(Style::Warning, line),
+        _ => line.to_string(),
+    }
+}
+
+/**
+Helper method to color the warnings printed by [info].
+
+`line`: [String]: The printed line.
+*/
+pub fn highlight(line: String) -> String {
+    match line.starts_with("Warning:") || line.starts_with("WARNING:") {
+        true => paint(Style::Warning, line),
+        false => line,
+    }
+}
+
+/**
+Prints a progress message, an information or a warning, unless the output is suppressed.
+
+Lines starting with `Warning:` are colored as warning, see [set_color].
+Output which was explicitly requested, e.g. the SQL of `--dry-run`, is printed with `println!`.
+*/
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::utils::output::is_quiet() {
+            println!("{}", $crate::utils::output::highlight(format!($($arg)*)));
+        }
+    };
+}
+
+pub(crate) use info;
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::output::{ColorChoice, Style};
+
+    #[test]
+    fn colors() {
+        assert!(ColorChoice::Auto.colors(true));
+        assert!(!ColorChoice::Auto.colors(false));
+        assert!(ColorChoice::Always.colors(false));
+        assert!(!ColorChoice::Never.colors(true));
+
+        assert_eq!(Style::Success.apply("Applied"), "\x1b[32mApplied\x1b[0m");
+        assert_eq!(Style::Error.apply(1), "\x1b[31m1\x1b[0m");
+    }
+}
@@ -0,0 +1,63 @@
+use std::io::{stdin, stdout, Write};
+
+use anyhow::{anyhow, Context};
+
+use crate::utils::output::is_quiet;
+
+/**
+Helper function to ask the user a yes / no question on the terminal.
+
+Anything other than `y` or `yes` is treated as no.
+If the output is suppressed, e.g. by `--quiet`, no question is asked and an error is returned.
+
+`question`: [&str]: The question to ask.
+*/
+pub fn question(question: &str) -> anyhow::Result<bool> {
+    if is_quiet() {
+        return Err(anyhow!(
+            "{} Can't ask while --quiet is set, pass --non-interactive to decide without asking",
+            question
+        ));
+    }
+
+    print!("{} [y/N] ", question);
+    stdout()
+        .flush()
+        .with_context(|| "Couldn't flush question to stdout")?;
+
+    let mut answer = String::new();
+    stdin()
+        .read_line(&mut answer)
+        .with_context(|| "Couldn't read answer from stdin")?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/**
+Helper function to ask the user for an answer on the terminal.
+
+Returns the answer without surrounding whitespace.
+If the output is suppressed, e.g. by `--quiet`, no question is asked and an error is returned.
+
+`question`: [&str]: The question to ask, including the expected format of the answer.
+*/
+pub fn ask(question: &str) -> anyhow::Result<String> {
+    if is_quiet() {
+        return Err(anyhow!(
+            "{} Can't ask while --quiet is set, pass --non-interactive to decide without asking",
+            question
+        ));
+    }
+
+    print!("{} ", question);
+    stdout()
+        .flush()
+        .with_context(|| "Couldn't flush question to stdout")?;
+
+    let mut answer = String::new();
+    stdin()
+        .read_line(&mut answer)
+        .with_context(|| "Couldn't read answer from stdin")?;
+
+    Ok(answer.trim().to_string())
+}
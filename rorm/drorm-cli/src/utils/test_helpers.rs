@@ -0,0 +1,28 @@
+use crate::declaration::{Migration, Operation};
+
+/**
+Builds a migration of the tests, applied in a single transaction.
+
+The migration is the initial one, if it has no dependency.
+
+`id`: [&str]: Id of the migration.
+`dependency`: [&str]: Id of the migration it depends on, or an empty string.
+`operations`: [Vec<Operation>]: Operations of the migration.
+*/
+pub(crate) fn migration(id: &str, dependency: &str, operations: Vec<Operation>) -> Migration {
+    Migration {
+        hash: "".to_string(),
+        initial: dependency.is_empty(),
+        id: id.to_string(),
+        dependency: dependency.to_string(),
+        external_dependencies: vec![],
+        merges: vec![],
+        replaces: vec![],
+        atomic: true,
+        operations,
+        precondition: None,
+        lock_timeout_ms: None,
+        naming_policy: None,
+        meta: None,
+    }
+}
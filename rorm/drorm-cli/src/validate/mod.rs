@@ -0,0 +1,1199 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use rorm_sql::create_index::is_column_name;
+use rorm_sql::imr::{Annotation, DbType, Field, ForeignKey, ReferentialAction};
+
+use crate::declaration::{EnumAction, Migration, MigrationMeta, Operation, TypeChange};
+use crate::make_migrations::naming::{apply_naming_policy, recorded_naming_policy};
+use crate::make_migrations::{
+    get_internal_models, is_legacy_hash, is_valid_hash, models_hash, operation_to_diff_line,
+};
+use crate::migrate::hashes::{hash_mismatches, recompute_hash};
+use crate::migrate::sql_builder::sorted_operations;
+use crate::utils::migrations::{
+    convert_file_to_migration, convert_file_to_migration_header, divergence, leaf_migrations,
+    order_migrations, rename_fields, replaced_migrations, set_identity, RE_ALLOWED_NAME,
+};
+use crate::utils::output::{info, paint, Style};
+
+/**
+Options for validating migrations
+*/
+pub struct ValidateOptions {
+    /// Directory, migrations exist in
+    pub migration_dir: String,
+
+    /// If set, the subdirectories of the migration directory are searched as well
+    pub recursive: bool,
+
+    /// If set, the hash of the last migration is compared with the models in this file
+    pub models_file: Option<String>,
+
+    /// If set, only the file names and the dependency chain are checked,
+    /// the operations are not read
+    pub metadata_only: bool,
+}
+
+/**
+Checks that a sensitive field is stored as varbinary.
+
+`model`: [&str]: Name of the model of the field.
+`field`: [&Field]: The field to check.
+*/
+fn sensitive_problem(model: &str, field: &Field) -> Option<String> {
+    (field
+        .annotations
+        .iter()
+        .any(|x| matches!(x, Annotation::Sensitive))
+        && !matches!(field.db_type, DbType::VarBinary))
+    .then(|| {
+        format!(
+            "Field {}.{} is sensitive, but not of type varbinary",
+            model, field.name
+        )
+    })
+}
+
+/**
+Checks that a fixed length field, i.e. of type char or binary_fixed, has a positive max_length.
+
+`model`: [&str]: Name of the model of the field.
+`field`: [&Field]: The field to check.
+*/
+fn fixed_length_problem(model: &str, field: &Field) -> Option<String> {
+    let type_name = match field.db_type {
+        DbType::Char => "char",
+        DbType::Binary => "binary_fixed",
+        _ => return None,
+    };
+    let positive = field
+        .annotations
+        .iter()
+        .any(|x| matches!(x, Annotation::MaxLength(l) if *l > 0));
+    (!positive).then(|| {
+        format!(
+            "Field {}.{} is of type {}, but has no positive max_length",
+            model, field.name, type_name
+        )
+    })
+}
+
+/**
+Checks that the referencing field of a foreign key can take the value its actions set it to.
+
+`SET NULL` requires a nullable field and `SET DEFAULT` a default of the database,
+otherwise the action fails when a referenced row is deleted or updated.
+
+`model`: [&str]: Name of the model of the field.
+`field`: [&Field]: The referencing field.
+`foreign_key`: [&ForeignKey]: The foreign key of the field.
+*/
+pub fn referential_action_problems(
+    model: &str,
+    field: &Field,
+    foreign_key: &ForeignKey,
+) -> Vec<String> {
+    let not_null = field
+        .annotations
+        .iter()
+        .any(|x| matches!(x, Annotation::NotNull | Annotation::PrimaryKey));
+    let default = field.annotations.iter().any(|x| {
+        matches!(
+            x,
+            Annotation::DefaultValue(_) | Annotation::ServerDefault(_) | Annotation::DefaultUuid
+        )
+    });
+
+    let mut problems = vec![];
+    for (action, event) in [
+        (&foreign_key.on_delete, "delete"),
+        (&foreign_key.on_update, "update"),
+    ] {
+        match action {
+            ReferentialAction::SetNull if not_null => problems.push(format!(
+                "Field {}.{} is not nullable, but its foreign key sets it to NULL on {}",
+                model, field.name, event
+            )),
+            ReferentialAction::SetDefault if !default => problems.push(format!(
+                "Field {}.{} has no default, but its foreign key sets it to its default on {}",
+                model, field.name, event
+            )),
+            _ => {}
+        }
+    }
+    problems
+}
+
+/**
+Checks the foreign keys declared as annotations of fields, see [referential_action_problems].
+
+`model`: [&str]: Name of the model of the field.
+`field`: [&Field]: The field to check.
+*/
+fn foreign_key_annotation_problems(model: &str, field: &Field) -> Vec<String> {
+    field
+        .annotations
+        .iter()
+        .filter_map(|x| match x {
+            Annotation::ForeignKey(foreign_key) => Some(foreign_key),
+            _ => None,
+        })
+        .flat_map(|x| referential_action_problems(model, field, x))
+        .collect()
+}
+
+/**
+Helper function to check the dependency chain of migrations.
+
+Returns the migrations along the chain in the order they are applied, see [order_migrations],
+and all problems found. If the chain can't be ordered, it ends at the first branch.
+Migrations replaced by another migration, e.g. by a squash, are not part of the chain.
+
+`migrations`: [&\[T\]]: The migrations or their headers to check.
+*/
+pub fn validate_chain<T: MigrationMeta>(migrations: &[T]) -> (Vec<&T>, Vec<String>) {
+    let mut problems = vec![];
+
+    let ids: HashSet<&str> = migrations.iter().map(|x| x.id()).collect();
+    let replaced = replaced_migrations(migrations);
+
+    // Branches created independently often share numbers, which is fine once they are merged
+    let lookup: HashMap<&str, &T> = migrations.iter().map(|x| (x.id(), x)).collect();
+    let mut merged: HashSet<&str> = HashSet::new();
+    let mut pending: Vec<&str> = migrations
+        .iter()
+        .filter(|x| !x.merges().is_empty())
+        .flat_map(|x| std::iter::once(x.dependency()).chain(x.merges().iter().map(String::as_str)))
+        .collect();
+    while let Some(id) = pending.pop() {
+        if let Some(migration) = lookup.get(id) {
+            if merged.insert(id) {
+                pending.push(migration.dependency());
+                pending.extend(migration.merges().iter().map(String::as_str));
+            }
+        }
+    }
+
+    let mut numbers: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for migration in migrations {
+        numbers
+            .entry(migration.id().split('_').next().unwrap_or_default())
+            .or_default()
+            .push(migration.id());
+    }
+    for (number, ids) in numbers
+        .iter()
+        .filter(|(_, ids)| ids.len() > 1 && !ids.iter().all(|x| merged.contains(x)))
+    {
+        problems.push(format!(
+            "Migrations {} share the number {}",
+            ids.join(", "),
+            number
+        ));
+    }
+
+    let initials: Vec<&T> = migrations
+        .iter()
+        .filter(|x| x.dependency().is_empty() && !replaced.contains(x.id()))
+        .collect();
+    match initials.len() {
+        0 => problems.push("No initial migration without a dependency was found".to_string()),
+        1 => {}
+        _ => problems.push(format!(
+            "Migrations {} have no dependency, only the initial migration may have none",
+            initials
+                .iter()
+                .map(|x| x.id())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+
+    let mut children: BTreeMap<&str, Vec<&T>> = BTreeMap::new();
+    for migration in migrations {
+        if migration.initial() && !migration.dependency().is_empty() {
+            problems.push(format!(
+                "Migration {} is marked as initial, but depends on {}",
+                migration.id(),
+                migration.dependency()
+            ));
+        }
+        if !migration.initial() && migration.dependency().is_empty() {
+            problems.push(format!(
+                "Migration {} has no dependency, but is not marked as initial",
+                migration.id()
+            ));
+        }
+
+        for dependency in std::iter::once(migration.dependency())
+            .filter(|x| !x.is_empty())
+            .chain(migration.merges().iter().map(String::as_str))
+        {
+            if !ids.contains(dependency) {
+                problems.push(format!(
+                    "Migration {} depends on {}, which does not exist",
+                    migration.id(),
+                    dependency
+                ));
+            }
+            if !replaced.contains(migration.id()) {
+                children.entry(dependency).or_default().push(migration);
+            }
+        }
+
+        // The migrations replaced by an initial squash may have been deleted
+        for replaced in migration.replaces() {
+            if !ids.contains(replaced.as_str()) && !migration.initial() {
+                problems.push(format!(
+                    "Migration {} replaces {}, which does not exist",
+                    migration.id(),
+                    replaced
+                ));
+            }
+        }
+
+        if !is_valid_hash(migration.hash()) && !migration.hash().is_empty() {
+            problems.push(format!(
+                "Migration {} has the invalid hash {:?}",
+                migration.id(),
+                migration.hash()
+            ));
+        }
+    }
+
+    // Branches are fine once they are merged, i.e. if a single leaf is left
+    let active: Vec<&T> = migrations
+        .iter()
+        .filter(|x| !replaced.contains(x.id()))
+        .collect();
+    let leaves = leaf_migrations(&active);
+    let mut branches: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for leaf in leaves.iter().skip(1) {
+        if let Some((first, second, dependency)) = divergence(&lookup, *leaves[0], **leaf) {
+            branches
+                .entry(dependency)
+                .or_default()
+                .extend([first, second]);
+        }
+    }
+    for (dependency, children) in &branches {
+        problems.push(format!(
+            "Migrations {} all depend on {}, merge them into a single chain with drorm merge-migrations",
+            children.iter().copied().collect::<Vec<_>>().join(", "),
+            dependency
+        ));
+    }
+
+    // Mark everything reachable from the initial migrations
+    let mut reachable: HashSet<&str> = HashSet::new();
+    let mut pending: Vec<&str> = initials.iter().map(|x| x.id()).collect();
+    while let Some(id) = pending.pop() {
+        if reachable.insert(id) {
+            if let Some(children) = children.get(id) {
+                pending.extend(children.iter().map(|x| x.id()));
+            }
+        }
+    }
+
+    for migration in migrations
+        .iter()
+        .filter(|x| !reachable.contains(x.id()) && !replaced.contains(x.id()))
+    {
+        // Following the dependencies of an unreachable migration either ends
+        // at a missing dependency or runs in a cycle
+        let mut seen = HashSet::new();
+        let mut current = migration;
+        let cycle = loop {
+            if !seen.insert(current.id()) {
+                break Some(current.id() == migration.id());
+            }
+            match lookup.get(current.dependency()) {
+                Some(next) => current = next,
+                None => break None,
+            }
+        };
+
+        problems.push(if cycle == Some(true) {
+            format!("Migration {} is part of a dependency cycle", migration.id())
+        } else if cycle == Some(false) {
+            format!("Migration {} depends on a dependency cycle", migration.id())
+        } else {
+            format!(
+                "Migration {} is not reachable from the initial migration",
+                migration.id()
+            )
+        });
+    }
+
+    if let Ok(chain) = order_migrations(active) {
+        return (chain, problems);
+    }
+    let mut chain = vec![];
+    if let [initial] = initials.as_slice() {
+        let mut current = *initial;
+        loop {
+            chain.push(current);
+            match children.get(current.id()).map(Vec::as_slice) {
+                Some([next]) => current = next,
+                _ => break,
+            }
+        }
+    }
+
+    (chain, problems)
+}
+
+/**
+Helper function to check the operations of a chain of migrations
+against the state resulting from the previous operations.
+
+`chain`: [&\[&Migration\]]: The migrations in the order they are applied.
+*/
+pub fn validate_operations(chain: &[&Migration]) -> Vec<String> {
+    let mut problems = vec![];
+    let mut models: HashMap<String, Vec<Field>> = HashMap::new();
+
+    for migration in chain {
+        for operation in sorted_operations(migration) {
+            let mut problem = |issue: String| {
+                problems.push(format!(
+                    "{}: {}: {}",
+                    migration.id,
+                    operation_to_diff_line(operation),
+                    issue
+                ))
+            };
+
+            // Checks that a model exists and returns its fields
+            macro_rules! model {
+                ($name:expr) => {
+                    match models.get_mut($name.as_str()) {
+                        Some(fields) => fields,
+                        None => {
+                            problem(format!("Model {} does not exist", $name));
+                            continue;
+                        }
+                    }
+                };
+            }
+
+            if let Operation::Only { dialects, .. } = operation {
+                if dialects.is_empty() {
+                    problem("Only requires at least one dialect".to_string());
+                }
+            }
+
+            match operation.inner() {
+                Operation::CreateModel { name, fields, .. } => {
+                    if models.contains_key(name.as_str()) {
+                        problem(format!("Model {} already exists", name));
+                        continue;
+                    }
+                    let mut names = HashSet::new();
+                    for field in fields {
+                        if !names.insert(field.name.as_str()) {
+                            problem(format!("Field {} is defined more than once", field.name));
+                        }
+                        if let Some(issue) = sensitive_problem(name, field) {
+                            problem(issue);
+                        }
+                        if let Some(issue) = fixed_length_problem(name, field) {
+                            problem(issue);
+                        }
+                        foreign_key_annotation_problems(name, field)
+                            .into_iter()
+                            .for_each(&mut problem);
+                    }
+                    models.insert(name.clone(), fields.clone());
+                }
+                Operation::RenameModel { old, new, .. } => {
+                    if models.contains_key(new.as_str()) {
+                        problem(format!("Model {} already exists", new));
+                        continue;
+                    }
+                    let fields = model!(old).clone();
+                    models.remove(old.as_str());
+                    models.insert(new.clone(), fields);
+                }
+                Operation::DeleteModel { name, .. } => {
+                    model!(name);
+                    models.remove(name.as_str());
+                }
+                Operation::CreateField { model, field } => {
+                    if let Some(issue) = sensitive_problem(model, field) {
+                        problem(issue);
+                    }
+                    if let Some(issue) = fixed_length_problem(model, field) {
+                        problem(issue);
+                    }
+                    foreign_key_annotation_problems(model, field)
+                        .into_iter()
+                        .for_each(&mut problem);
+                    let fields = model!(model);
+                    if fields.iter().any(|x| x.name == field.name) {
+                        problem(format!("Field {}.{} already exists", model, field.name));
+                    } else {
+                        fields.push(field.clone());
+                    }
+                }
+                Operation::RenameField {
+                    table_name,
+                    old,
+                    new,
+                } => {
+                    let fields = model!(table_name);
+                    if fields.iter().any(|x| x.name == *new) {
+                        problem(format!("Field {}.{} already exists", table_name, new));
+                    } else if let Some(field) = fields.iter_mut().find(|x| x.name == *old) {
+                        field.name = new.clone();
+                    } else {
+                        problem(format!("Field {}.{} does not exist", table_name, old));
+                    }
+                }
+                Operation::RenameFields {
+                    table_name,
+                    renames,
+                } => {
+                    let fields = model!(table_name);
+                    let mut olds = HashSet::new();
+                    let mut news = HashSet::new();
+                    for (old, new) in renames {
+                        if !olds.insert(old.as_str()) {
+                            problem(format!(
+                                "Field {}.{} is renamed more than once",
+                                table_name, old
+                            ));
+                        }
+                        if !news.insert(new.as_str()) {
+                            problem(format!(
+                                "Field {}.{} is the new name of more than one field",
+                                table_name, new
+                            ));
+                        }
+                        if !fields.iter().any(|x| x.name == *old) {
+                            problem(format!("Field {}.{} does not exist", table_name, old));
+                        }
+                    }
+                    for new in news {
+                        if !olds.contains(new) && fields.iter().any(|x| x.name == new) {
+                            problem(format!("Field {}.{} already exists", table_name, new));
+                        }
+                    }
+                    rename_fields(fields, renames);
+                }
+                Operation::AlterField {
+                    model,
+                    field,
+                    change,
+                } => {
+                    if let Some(issue) = sensitive_problem(model, field) {
+                        problem(issue);
+                    }
+                    if let Some(issue) = fixed_length_problem(model, field) {
+                        problem(issue);
+                    }
+                    let fields = model!(model);
+                    match fields.iter_mut().find(|x| x.name == field.name) {
+                        None => problem(format!("Field {}.{} does not exist", model, field.name)),
+                        Some(existing) => {
+                            match TypeChange::classify(existing, field) {
+                                None => problem(format!(
+                                    "AlterField of {}.{} doesn't change its type",
+                                    model, field.name
+                                )),
+                                Some(actual) if actual != *change => problem(format!(
+                                    "AlterField of {}.{} is marked as {}, but the change is {}",
+                                    model, field.name, change, actual
+                                )),
+                                Some(_) => {}
+                            }
+                            *existing = field.clone();
+                        }
+                    }
+                }
+                Operation::AlterEnum {
+                    model,
+                    field,
+                    action,
+                } => {
+                    let choices = |field: &Field| {
+                        field
+                            .annotations
+                            .iter()
+                            .find_map(|x| match x {
+                                Annotation::Choices(c) => Some(c.clone()),
+                                _ => None,
+                            })
+                            .unwrap_or_default()
+                    };
+                    let fields = model!(model);
+                    match fields.iter_mut().find(|x| x.name == field.name) {
+                        None => problem(format!("Field {}.{} does not exist", model, field.name)),
+                        Some(existing)
+                            if !matches!(existing.db_type, DbType::Choices)
+                                || !matches!(field.db_type, DbType::Choices) =>
+                        {
+                            problem(format!(
+                                "AlterEnum of {}.{} requires a field of type choices",
+                                model, field.name
+                            ))
+                        }
+                        Some(existing) => {
+                            let (old, new) = (choices(existing), choices(field));
+                            let (removed, added) = match action {
+                                EnumAction::Add { value } => (None, value),
+                                EnumAction::Rename { old, new } => (Some(old), new),
+                            };
+                            if let Some(removed) = removed {
+                                if !old.contains(removed) || new.contains(removed) {
+                                    problem(format!(
+                                        "AlterEnum of {}.{} renames the value {}, which is not a choice",
+                                        model, field.name, removed
+                                    ));
+                                }
+                            }
+                            if old.contains(added) || !new.contains(added) {
+                                problem(format!(
+                                    "AlterEnum of {}.{} adds the value {}, which is already a choice or missing from the field",
+                                    model, field.name, added
+                                ));
+                            }
+                            *existing = field.clone();
+                        }
+                    }
+                }
+                Operation::DeleteField { model, name, .. } => {
+                    let fields = model!(model);
+                    if !fields.iter().any(|x| x.name == *name) {
+                        problem(format!("Field {}.{} does not exist", model, name));
+                    }
+                    fields.retain(|x| x.name != *name);
+                }
+                Operation::CreateIndex { model, columns, .. }
+                | Operation::RenameIndex { model, columns, .. }
+                | Operation::CreateUnique { model, columns, .. } => {
+                    let fields = model!(model);
+                    for column in columns.iter().filter(|x| is_column_name(x)) {
+                        if !fields.iter().any(|x| x.name == *column) {
+                            problem(format!("Field {}.{} does not exist", model, column));
+                        }
+                    }
+                }
+                Operation::CreateForeignKey {
+                    model,
+                    column,
+                    foreign_key,
+                    ..
+                } => {
+                    match models.get(foreign_key.table_name.as_str()) {
+                        None => problem(format!(
+                            "Referenced model {} does not exist",
+                            foreign_key.table_name
+                        )),
+                        Some(fields) => {
+                            if !fields.iter().any(|x| x.name == foreign_key.column_name) {
+                                problem(format!(
+                                    "Referenced field {}.{} does not exist",
+                                    foreign_key.table_name, foreign_key.column_name
+                                ))
+                            }
+                        }
+                    }
+                    if foreign_key.initially_deferred && !foreign_key.deferrable {
+                        problem("InitiallyDeferred requires Deferrable".to_string());
+                    }
+                    let fields = model!(model);
+                    match fields.iter().find(|x| x.name == *column) {
+                        None => problem(format!("Field {}.{} does not exist", model, column)),
+                        Some(field) => referential_action_problems(model, field, foreign_key)
+                            .into_iter()
+                            .for_each(&mut problem),
+                    }
+                }
+                Operation::DropConstraint {
+                    model,
+                    column: Some(column),
+                    ..
+                } => {
+                    let fields = model!(model);
+                    match fields.iter_mut().find(|x| x.name == *column) {
+                        None => problem(format!("Field {}.{} does not exist", model, column)),
+                        Some(field) => field
+                            .annotations
+                            .retain(|x| !matches!(x, Annotation::ForeignKey(_))),
+                    }
+                }
+                Operation::SetSequence { model, column, .. } => {
+                    let fields = model!(model);
+                    match fields.iter().find(|x| x.name == *column) {
+                        None => problem(format!("Field {}.{} does not exist", model, column)),
+                        Some(field) => {
+                            if !field.annotations.iter().any(|x| {
+                                matches!(x, Annotation::AutoIncrement | Annotation::Identity(_))
+                            }) {
+                                problem(format!("Field {}.{} is not auto_increment", model, column))
+                            }
+                        }
+                    }
+                }
+                Operation::AddIdentity {
+                    model,
+                    column,
+                    identity,
+                    ..
+                } => {
+                    let fields = model!(model);
+                    match fields.iter_mut().find(|x| x.name == *column) {
+                        None => problem(format!("Field {}.{} does not exist", model, column)),
+                        Some(field) => {
+                            if field
+                                .annotations
+                                .iter()
+                                .any(|x| matches!(x, Annotation::Identity(_)))
+                            {
+                                problem(format!(
+                                    "Field {}.{} is already an identity",
+                                    model, column
+                                ))
+                            }
+                            set_identity(field, Some(*identity));
+                        }
+                    }
+                }
+                Operation::SetIdentity {
+                    model,
+                    column,
+                    identity,
+                } => {
+                    let fields = model!(model);
+                    match fields.iter_mut().find(|x| x.name == *column) {
+                        None => problem(format!("Field {}.{} does not exist", model, column)),
+                        Some(field) => {
+                            if !field
+                                .annotations
+                                .iter()
+                                .any(|x| matches!(x, Annotation::Identity(_)))
+                            {
+                                problem(format!("Field {}.{} is not an identity", model, column))
+                            }
+                            set_identity(field, Some(*identity));
+                        }
+                    }
+                }
+                Operation::DropIdentity { model, column } => {
+                    let fields = model!(model);
+                    match fields.iter_mut().find(|x| x.name == *column) {
+                        None => problem(format!("Field {}.{} does not exist", model, column)),
+                        Some(field) => {
+                            if !field
+                                .annotations
+                                .iter()
+                                .any(|x| matches!(x, Annotation::Identity(_)))
+                            {
+                                problem(format!("Field {}.{} is not an identity", model, column))
+                            }
+                            set_identity(field, None);
+                        }
+                    }
+                }
+                Operation::RenameConstraint { model, .. }
+                | Operation::CreateCheck { model, .. }
+                | Operation::ValidateConstraint { model, .. }
+                | Operation::DropConstraint { model, .. }
+                | Operation::Truncate { model, .. }
+                | Operation::SetTablespace { model, .. }
+                | Operation::SetCharset { model, .. }
+                | Operation::SetStorageParameters { model, .. }
+                | Operation::CreatePartition { model, .. }
+                | Operation::AttachPartition { model, .. }
+                | Operation::DetachPartition { model, .. }
+                | Operation::DropUnique { model, .. }
+                | Operation::DropIndex { model, .. } => {
+                    model!(model);
+                }
+                Operation::SetInherit { model, parent }
+                | Operation::NoInherit { model, parent } => {
+                    model!(model);
+                    model!(parent);
+                }
+                Operation::RunSql {
+                    sql,
+                    timeout_seconds,
+                } => {
+                    if sql.trim().is_empty() {
+                        problem("RunSql requires SQL to execute".to_string());
+                    }
+                    if *timeout_seconds == Some(0) {
+                        problem("TimeoutSeconds must be greater than 0".to_string());
+                    }
+                }
+                Operation::CreateExtension { name, .. } | Operation::DropExtension { name, .. } => {
+                    if name.trim().is_empty() {
+                        problem("The extension requires a name".to_string());
+                    }
+                }
+                Operation::Only { .. } => unreachable!("dialect filters are removed by inner"),
+            }
+        }
+    }
+
+    problems
+}
+
+/**
+Validates the migrations in a directory without connecting to a database.
+
+All problems are printed, an error is returned if any problem was found.
+*/
+pub fn run_validate(options: ValidateOptions) -> anyhow::Result<()> {
+    let mut problems = vec![];
+    let mut migrations = vec![];
+    let mut headers = vec![];
+
+    let mut entries = vec![];
+    let mut dirs = vec![PathBuf::from(options.migration_dir.as_str())];
+    while let Some(dir) = dirs.pop() {
+        for entry in
+            read_dir(&dir).with_context(|| "Error while searching the migration directory")?
+        {
+            let entry = entry.with_context(|| "Error while searching the migration directory")?;
+            if options.recursive && entry.path().is_dir() {
+                dirs.push(entry.path());
+            } else {
+                entries.push(entry);
+            }
+        }
+    }
+    entries.sort_by_key(|x| (x.file_name(), x.path()));
+
+    let mut files: HashMap<String, PathBuf> = HashMap::new();
+    for entry in entries {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if !entry.path().is_file() || !file_name.ends_with(".toml") {
+            continue;
+        }
+        if !RE_ALLOWED_NAME.is_match(file_name.as_str()) {
+            problems.push(format!(
+                "File {} is not named like a migration, e.g. 0001_initial.toml",
+                file_name
+            ));
+            continue;
+        }
+        if let Some(other) = files.insert(file_name.clone(), entry.path()) {
+            problems.push(format!(
+                "Migration files {:?} and {:?} both have the id {}",
+                other,
+                entry.path(),
+                file_name.trim_end_matches(".toml")
+            ));
+            continue;
+        }
+
+        if options.metadata_only {
+            match convert_file_to_migration_header(&entry) {
+                Ok(file) => headers.push(file.migration),
+                Err(err) => problems.push(format!("{:#}", err)),
+            }
+        } else {
+            match convert_file_to_migration(&entry) {
+                Ok(file) => migrations.push(file.migration),
+                Err(err) => problems.push(format!("{:#}", err)),
+            }
+        }
+    }
+
+    let (count, last_migration, naming_policy) = if options.metadata_only {
+        let (chain, chain_problems) = validate_chain(&headers);
+        problems.extend(chain_problems);
+        (
+            headers.len(),
+            chain
+                .last()
+                .copied()
+                .map(|x| (x.id().to_string(), x.hash().to_string())),
+            recorded_naming_policy(chain.iter().copied()),
+        )
+    } else {
+        let (chain, chain_problems) = validate_chain(&migrations);
+        problems.extend(chain_problems);
+        problems.extend(validate_operations(&chain));
+        // The same comparison of the hashes as migrate does, without a database to compare with
+        let ordered: Vec<Migration> = chain.iter().map(|x| (*x).clone()).collect();
+        let pending: Vec<&Migration> = ordered.iter().collect();
+        problems.extend(hash_mismatches(&ordered, &pending, &[]));
+        // A legacy hash of the last migration is replaced by the hash of its operations
+        let last_migration = ordered.last().map(|x| {
+            let hash = if is_legacy_hash(&x.hash) {
+                recompute_hash(&ordered, ordered.len() - 1).unwrap_or_default()
+            } else {
+                x.hash.clone()
+            };
+            (x.id.clone(), hash)
+        });
+        (
+            migrations.len(),
+            last_migration,
+            recorded_naming_policy(chain.iter().copied()),
+        )
+    };
+
+    if let Some(models_file) = &options.models_file {
+        if !Path::new(models_file.as_str()).exists() {
+            problems.push(format!("Models file {} does not exist", models_file));
+        // Legacy hashes are only left with --metadata-only, which doesn't replay the operations
+        } else if let Some((id, hash)) = last_migration.filter(|(_, x)| !is_legacy_hash(x)) {
+            let mut internal_models = get_internal_models(models_file.as_str())
+                .with_context(|| "Couldn't retrieve internal model files.")?;
+            apply_naming_policy(naming_policy.unwrap_or_default(), &mut internal_models);
+            if hash != models_hash(&internal_models) {
+                problems.push(format!(
+                    "The models in {} changed since migration {}, run make-migrations",
+                    models_file, id
+                ));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        info!(
+            "No problems found in {} migrations in {}",
+            count, options.migration_dir
+        );
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{}", paint(Style::Error, problem));
+    }
+
+    Err(anyhow!(
+        "Found {} problems in {}",
+        problems.len(),
+        options.migration_dir
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use rorm_sql::imr::{Annotation, DbType, DefaultValue, Field, ForeignKey, ReferentialAction};
+
+    use crate::declaration::Operation;
+    use crate::make_migrations::MODELS_HASH_VERSION;
+    use crate::migrate::hashes::recompute_hash;
+    use crate::utils::migrations::convert_migration_to_file;
+    use crate::utils::test_helpers::migration;
+    use crate::validate::{run_validate, validate_chain, validate_operations, ValidateOptions};
+
+    fn field(name: &str) -> Field {
+        Field {
+            name: name.to_string(),
+            db_type: DbType::Int64,
+            annotations: vec![],
+            source_defined_at: None,
+        }
+    }
+
+    #[test]
+    fn chain_reports_all_problems() {
+        let mut replacing = migration("0002_b", "0001_initial", vec![]);
+        replacing.replaces.push("0009_gone".to_string());
+
+        let migrations = vec![
+            migration("0001_initial", "", vec![]),
+            migration("0002_a", "0001_initial", vec![]),
+            replacing,
+            migration("0003_missing", "0000_none", vec![]),
+            migration("0004_x", "0005_y", vec![]),
+            migration("0005_y", "0004_x", vec![]),
+        ];
+
+        let (chain, problems) = validate_chain(&migrations);
+        assert_eq!(chain.len(), 1);
+        for expected in [
+            "share the number 0002",
+            "replaces 0009_gone",
+            "depends on 0000_none, which does not exist",
+            "all depend on 0001_initial",
+            "Migration 0003_missing is not reachable",
+            "Migration 0004_x is part of a dependency cycle",
+            "Migration 0005_y is part of a dependency cycle",
+        ] {
+            assert!(
+                problems.iter().any(|x| x.contains(expected)),
+                "{} not in {:?}",
+                expected,
+                problems
+            );
+        }
+        assert_eq!(problems.len(), 7);
+    }
+
+    #[test]
+    fn chain_with_squash() {
+        let mut squashed = migration("0003_squashed", "", vec![]);
+        squashed.replaces = vec!["0001_initial".to_string(), "0002_b".to_string()];
+        let migrations = vec![
+            migration("0001_initial", "", vec![]),
+            migration("0002_b", "0001_initial", vec![]),
+            squashed.clone(),
+            migration("0004_c", "0003_squashed", vec![]),
+        ];
+
+        // The squash takes the place of the kept originals
+        let (chain, problems) = validate_chain(&migrations);
+        assert!(problems.is_empty(), "{:?}", problems);
+        let ids: Vec<&str> = chain.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, vec!["0003_squashed", "0004_c"]);
+
+        // The originals may have been deleted
+        let migrations = vec![squashed, migration("0004_c", "0003_squashed", vec![])];
+        let (chain, problems) = validate_chain(&migrations);
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(chain.len(), 2);
+    }
+
+    #[test]
+    fn chain_with_merge() {
+        let mut migrations = vec![
+            migration("0001_initial", "", vec![]),
+            migration("0002_a", "0001_initial", vec![]),
+            migration("0002_b", "0001_initial", vec![]),
+        ];
+        let (_, problems) = validate_chain(&migrations);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems
+            .iter()
+            .any(|x| x.contains("Migrations 0002_a, 0002_b all depend on 0001_initial")));
+
+        // The merged branches are part of the chain in the order they are applied,
+        // they may share numbers
+        let mut merge = migration("0003_merge", "0002_b", vec![]);
+        merge.merges = vec!["0002_a".to_string()];
+        migrations.push(merge);
+        let (chain, problems) = validate_chain(&migrations);
+        assert!(problems.is_empty(), "{:?}", problems);
+        let ids: Vec<&str> = chain.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, vec!["0001_initial", "0002_a", "0002_b", "0003_merge"]);
+
+        // A new migration reusing a number of the merged branches is still reported
+        migrations.push(migration("0002_c", "0003_merge", vec![]));
+        let (_, problems) = validate_chain(&migrations);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("share the number 0002"));
+    }
+
+    #[test]
+    fn operations() {
+        let initial = migration(
+            "0001_initial",
+            "",
+            vec![Operation::CreateModel {
+                name: "user".to_string(),
+                fields: vec![field("id")],
+                annotations: vec![],
+            }],
+        );
+        let second = migration(
+            "0002_b",
+            "0001_initial",
+            vec![
+                Operation::CreateField {
+                    model: "group".to_string(),
+                    field: field("id"),
+                },
+                Operation::CreateField {
+                    model: "user".to_string(),
+                    field: field("id"),
+                },
+                Operation::DeleteField {
+                    model: "user".to_string(),
+                    name: "age".to_string(),
+                    cascade: false,
+                },
+                Operation::SetSequence {
+                    model: "user".to_string(),
+                    column: "id".to_string(),
+                    value: 1,
+                },
+                Operation::CreateField {
+                    model: "user".to_string(),
+                    field: Field {
+                        annotations: vec![Annotation::Sensitive],
+                        ..field("token")
+                    },
+                },
+                Operation::CreateField {
+                    model: "user".to_string(),
+                    field: Field {
+                        db_type: DbType::Char,
+                        ..field("country")
+                    },
+                },
+            ],
+        );
+
+        let problems = validate_operations(&[&initial, &second]);
+        assert_eq!(
+            problems,
+            vec![
+                "0002_b: - user.age: Field user.age does not exist",
+                "0002_b: + group.id: Model group does not exist",
+                "0002_b: + user.id: Field user.id already exists",
+                "0002_b: + user.token: Field user.token is sensitive, but not of type varbinary",
+                "0002_b: + user.country: Field user.country is of type char, but has no positive max_length",
+                "0002_b: ~ sequence user.id = 1: Field user.id is not auto_increment",
+            ]
+        );
+    }
+
+    #[test]
+    fn referential_actions() {
+        let foreign_key = |on_delete, on_update| ForeignKey {
+            table_name: "user".to_string(),
+            column_name: "id".to_string(),
+            deferrable: false,
+            initially_deferred: false,
+            on_delete,
+            on_update,
+        };
+        let initial = migration(
+            "0001_initial",
+            "",
+            vec![
+                Operation::CreateModel {
+                    name: "user".to_string(),
+                    fields: vec![field("id")],
+                    annotations: vec![],
+                },
+                Operation::CreateModel {
+                    name: "post".to_string(),
+                    fields: vec![
+                        field("id"),
+                        Field {
+                            annotations: vec![
+                                Annotation::NotNull,
+                                Annotation::ForeignKey(foreign_key(
+                                    ReferentialAction::SetNull,
+                                    ReferentialAction::NoAction,
+                                )),
+                            ],
+                            ..field("author")
+                        },
+                        // Nullable fields can be set to NULL
+                        Field {
+                            annotations: vec![Annotation::ForeignKey(foreign_key(
+                                ReferentialAction::SetNull,
+                                ReferentialAction::SetNull,
+                            ))],
+                            ..field("reviewer")
+                        },
+                    ],
+                    annotations: vec![],
+                },
+            ],
+        );
+        let second = migration(
+            "0002_b",
+            "0001_initial",
+            vec![
+                Operation::CreateField {
+                    model: "post".to_string(),
+                    field: field("editor"),
+                },
+                Operation::CreateField {
+                    model: "post".to_string(),
+                    field: Field {
+                        annotations: vec![Annotation::DefaultValue(DefaultValue::Integer(1))],
+                        ..field("owner")
+                    },
+                },
+                Operation::CreateForeignKey {
+                    model: "post".to_string(),
+                    name: "post_editor_fkey".to_string(),
+                    column: "editor".to_string(),
+                    foreign_key: foreign_key(
+                        ReferentialAction::Cascade,
+                        ReferentialAction::SetDefault,
+                    ),
+                    not_valid: false,
+                },
+                Operation::CreateForeignKey {
+                    model: "post".to_string(),
+                    name: "post_owner_fkey".to_string(),
+                    column: "owner".to_string(),
+                    foreign_key: foreign_key(
+                        ReferentialAction::SetDefault,
+                        ReferentialAction::SetDefault,
+                    ),
+                    not_valid: false,
+                },
+            ],
+        );
+
+        let problems = validate_operations(&[&initial, &second]);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].ends_with(
+            "Field post.author is not nullable, but its foreign key sets it to NULL on delete"
+        ));
+        assert!(problems[1].ends_with(
+            "Field post.editor has no default, but its foreign key sets it to its default on update"
+        ));
+    }
+
+    #[test]
+    fn hashes() {
+        let dir = std::env::temp_dir().join(format!("drorm_validate_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let initial = migration(
+            "0001_initial",
+            "",
+            vec![Operation::CreateModel {
+                name: "user".to_string(),
+                fields: vec![field("id")],
+                annotations: vec![],
+            }],
+        );
+        let validate = |hash: &str| {
+            let mut file = initial.clone();
+            file.hash = hash.to_string();
+            convert_migration_to_file(file, &dir.join("0001_initial.toml")).unwrap();
+            run_validate(ValidateOptions {
+                migration_dir: dir.to_str().unwrap().to_string(),
+                recursive: false,
+                models_file: None,
+                metadata_only: false,
+            })
+        };
+
+        // The hash has to match the models resulting from the operations, as in migrate
+        let hash = recompute_hash(std::slice::from_ref(&initial), 0).unwrap();
+        assert!(validate(hash.as_str()).is_ok());
+        assert!(validate(format!("{}:{}", MODELS_HASH_VERSION, "0".repeat(64)).as_str()).is_err());
+        assert!(validate("").is_ok());
+        assert!(validate("v2:123").is_err());
+
+        // Legacy hashes can't be recomputed and are accepted
+        assert!(validate("123").is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn legacy_hashes() {
+        // Written by make-migrations of the version before the hashes were versioned
+        let dir = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/legacy");
+        for metadata_only in [false, true] {
+            run_validate(ValidateOptions {
+                migration_dir: dir.join("migrations").to_str().unwrap().to_string(),
+                recursive: false,
+                models_file: Some(dir.join("models.json").to_str().unwrap().to_string()),
+                metadata_only,
+            })
+            .unwrap();
+        }
+    }
+}
@@ -0,0 +1,228 @@
+/*!
+Golden tests of the SQL generated for migrations.
+
+The SQL of every dialect is compared with the file of the fixture in `tests/golden`.
+Run the tests with `DRORM_UPDATE_GOLDEN=1` to write the files after an intended change
+and review their diff before committing them.
+*/
+
+use std::fs::{read_to_string, write};
+use std::path::PathBuf;
+
+use drorm_cli::declaration::{IndexKind, Migration, Operation};
+use drorm_cli::migrate::sql_builder::{migration_to_sql_per_dialect, BuildOptions};
+use rorm_sql::imr::{Annotation, DbType, DefaultValue, Dialect, Field};
+
+fn migration(operations: Vec<Operation>) -> Migration {
+    Migration {
+        hash: "".to_string(),
+        initial: false,
+        id: "0002_golden".to_string(),
+        dependency: "0001_initial".to_string(),
+        external_dependencies: vec![],
+        merges: vec![],
+        replaces: vec![],
+        atomic: true,
+        operations,
+        precondition: None,
+        lock_timeout_ms: None,
+        naming_policy: None,
+        meta: None,
+    }
+}
+
+fn field(name: &str, db_type: DbType, annotations: Vec<Annotation>) -> Field {
+    Field {
+        name: name.to_string(),
+        db_type,
+        annotations,
+        source_defined_at: None,
+    }
+}
+
+fn id() -> Field {
+    field(
+        "id",
+        DbType::Int64,
+        vec![Annotation::PrimaryKey, Annotation::AutoIncrement],
+    )
+}
+
+/**
+Compares the SQL of a migration for every dialect with the golden file `tests/golden/{name}.sql`.
+*/
+fn assert_golden(name: &str, migration: &Migration) {
+    let mut actual = String::new();
+    for (db_impl, sql) in migration_to_sql_per_dialect(migration, &BuildOptions::default()) {
+        let sql = sql.unwrap_or_else(|err| format!("Error: {:#}", anyhow::Error::from(err)));
+        actual.push_str(format!("-- {}\n{}\n\n", Dialect::from(db_impl), sql).as_str());
+    }
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.sql", name));
+    if std::env::var_os("DRORM_UPDATE_GOLDEN").is_some() {
+        write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "Golden file {:?} is missing, run with DRORM_UPDATE_GOLDEN=1 to create it",
+            path
+        )
+    });
+    assert_eq!(
+        actual, expected,
+        "SQL of {} differs from {:?}, run with DRORM_UPDATE_GOLDEN=1 to update it",
+        name, path
+    );
+}
+
+#[test]
+fn create_model() {
+    assert_golden(
+        "create_model",
+        &migration(vec![Operation::CreateModel {
+            name: "user".to_string(),
+            fields: vec![
+                id(),
+                field(
+                    "username",
+                    DbType::VarChar,
+                    vec![
+                        Annotation::MaxLength(255),
+                        Annotation::NotNull,
+                        Annotation::Unique,
+                    ],
+                ),
+                field("age", DbType::Int16, vec![]),
+                field("score", DbType::Double, vec![]),
+                field(
+                    "active",
+                    DbType::Boolean,
+                    vec![
+                        Annotation::NotNull,
+                        Annotation::DefaultValue(DefaultValue::Boolean(true)),
+                    ],
+                ),
+                field(
+                    "created_at",
+                    DbType::Datetime,
+                    vec![Annotation::AutoCreateTime],
+                ),
+            ],
+            annotations: vec![],
+        }]),
+    );
+}
+
+/// String defaults were emitted unquoted on SQLite, so an empty default resulted in `DEFAULT `
+#[test]
+fn empty_string_default() {
+    assert_golden(
+        "empty_string_default",
+        &migration(vec![Operation::CreateModel {
+            name: "user".to_string(),
+            fields: vec![
+                id(),
+                field(
+                    "nickname",
+                    DbType::VarChar,
+                    vec![
+                        Annotation::MaxLength(255),
+                        Annotation::DefaultValue(DefaultValue::String("".to_string())),
+                    ],
+                ),
+                field(
+                    "title",
+                    DbType::VarChar,
+                    vec![
+                        Annotation::MaxLength(255),
+                        Annotation::DefaultValue(DefaultValue::String("it's".to_string())),
+                    ],
+                ),
+            ],
+            annotations: vec![],
+        }]),
+    );
+}
+
+/// The max length of integer fields must not result in a display width like `BIGINT(255)`
+#[test]
+fn integer_max_length() {
+    assert_golden(
+        "integer_max_length",
+        &migration(vec![Operation::CreateField {
+            model: "user".to_string(),
+            field: field(
+                "external_id",
+                DbType::Int64,
+                vec![Annotation::MaxLength(255)],
+            ),
+        }]),
+    );
+}
+
+/// SQLite can't add not null columns without a default to existing tables
+#[test]
+fn add_not_null_field() {
+    assert_golden(
+        "add_not_null_field",
+        &migration(vec![Operation::CreateField {
+            model: "user".to_string(),
+            field: field("age", DbType::Int32, vec![Annotation::NotNull]),
+        }]),
+    );
+    assert_golden(
+        "add_not_null_field_with_default",
+        &migration(vec![Operation::CreateField {
+            model: "user".to_string(),
+            field: field(
+                "age",
+                DbType::Int32,
+                vec![
+                    Annotation::NotNull,
+                    Annotation::DefaultValue(DefaultValue::Integer(0)),
+                ],
+            ),
+        }]),
+    );
+}
+
+/// Dropping a column referenced by a foreign key fails unless the foreign key is dropped as well
+#[test]
+fn delete_referenced_field() {
+    let delete = |cascade| {
+        migration(vec![Operation::DeleteField {
+            model: "user".to_string(),
+            name: "id".to_string(),
+            cascade,
+        }])
+    };
+    assert_golden("delete_referenced_field", &delete(false));
+    assert_golden("delete_referenced_field_cascade", &delete(true));
+    assert_golden(
+        "delete_referenced_model_cascade",
+        &migration(vec![Operation::DeleteModel {
+            name: "user".to_string(),
+            cascade: true,
+        }]),
+    );
+}
+
+/// Full-text indexes are not supported by sqlite and left out there
+#[test]
+fn full_text_index() {
+    assert_golden(
+        "full_text_index",
+        &migration(vec![Operation::CreateIndex {
+            model: "post".to_string(),
+            name: "post_search_idx".to_string(),
+            columns: vec!["title".to_string(), "body".to_string()],
+            unique: false,
+            concurrently: false,
+            kind: IndexKind::FullText,
+        }]),
+    );
+}
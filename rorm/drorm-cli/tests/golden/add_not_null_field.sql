@@ -0,0 +1,9 @@
+-- sqlite
+Error: Couldn't generate the SQL of migration 0002_golden: Could not build add column operation for migration 0002_golden: Column age is not null, but has no default value. SQLite can only add not null columns to existing tables with a default value
+
+-- mysql
+START TRANSACTION; ALTER TABLE user ADD COLUMN age INT NOT NULL; COMMIT;
+
+-- postgres
+BEGIN; ALTER TABLE user ADD COLUMN age INTEGER NOT NULL; COMMIT;
+
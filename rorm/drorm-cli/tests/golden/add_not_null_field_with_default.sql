@@ -0,0 +1,9 @@
+-- sqlite
+BEGIN; ALTER TABLE user ADD COLUMN age INTEGER NOT NULL DEFAULT 0; COMMIT;
+
+-- mysql
+START TRANSACTION; ALTER TABLE user ADD COLUMN age INT NOT NULL DEFAULT 0; COMMIT;
+
+-- postgres
+BEGIN; ALTER TABLE user ADD COLUMN age INTEGER NOT NULL DEFAULT 0; COMMIT;
+
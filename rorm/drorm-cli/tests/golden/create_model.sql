@@ -0,0 +1,9 @@
+-- sqlite
+BEGIN; CREATE TABLE user (id INTEGER PRIMARY KEY AUTOINCREMENT,username TEXT NOT NULL UNIQUE,age INTEGER,score REAL,active INTEGER NOT NULL DEFAULT 1,created_at TEXT DEFAULT CURRENT_TIMESTAMP) STRICT; COMMIT;
+
+-- mysql
+START TRANSACTION; CREATE TABLE user (id BIGINT PRIMARY KEY AUTO_INCREMENT,username VARCHAR(255) NOT NULL UNIQUE,age SMALLINT,score DOUBLE,active BOOL NOT NULL DEFAULT 1,created_at DATETIME DEFAULT CURRENT_TIMESTAMP) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci; COMMIT;
+
+-- postgres
+BEGIN; CREATE TABLE user (id BIGSERIAL PRIMARY KEY,username VARCHAR(255) NOT NULL UNIQUE,age SMALLINT,score DOUBLE PRECISION,active BOOLEAN NOT NULL DEFAULT true,created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP); COMMIT;
+
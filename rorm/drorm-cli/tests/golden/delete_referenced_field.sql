@@ -0,0 +1,9 @@
+-- sqlite
+BEGIN; ALTER TABLE user DROP COLUMN id; COMMIT;
+
+-- mysql
+START TRANSACTION; ALTER TABLE user DROP COLUMN id; COMMIT;
+
+-- postgres
+BEGIN; ALTER TABLE user DROP COLUMN id; COMMIT;
+
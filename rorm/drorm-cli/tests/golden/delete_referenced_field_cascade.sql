@@ -0,0 +1,9 @@
+-- sqlite
+Error: Couldn't generate the SQL of migration 0002_golden: Could not build drop column operation for migration 0002_golden: SQLite can't drop the objects depending on column id, it doesn't support CASCADE
+
+-- mysql
+START TRANSACTION; ALTER TABLE user DROP COLUMN id; COMMIT;
+
+-- postgres
+BEGIN; ALTER TABLE user DROP COLUMN id CASCADE; COMMIT;
+
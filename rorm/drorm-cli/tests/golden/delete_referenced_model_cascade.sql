@@ -0,0 +1,9 @@
+-- sqlite
+Error: Couldn't generate the SQL of migration 0002_golden: Could not build drop table operation for migration 0002_golden: SQLite can't drop the objects depending on table user, it doesn't support CASCADE
+
+-- mysql
+START TRANSACTION; DROP TABLE user; COMMIT;
+
+-- postgres
+BEGIN; DROP TABLE user CASCADE; COMMIT;
+
@@ -0,0 +1,9 @@
+-- sqlite
+BEGIN; CREATE TABLE user (id INTEGER PRIMARY KEY AUTOINCREMENT,nickname TEXT DEFAULT '',title TEXT DEFAULT 'it''s') STRICT; COMMIT;
+
+-- mysql
+START TRANSACTION; CREATE TABLE user (id BIGINT PRIMARY KEY AUTO_INCREMENT,nickname VARCHAR(255) DEFAULT '',title VARCHAR(255) DEFAULT 'it''s') DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci; COMMIT;
+
+-- postgres
+BEGIN; CREATE TABLE user (id BIGSERIAL PRIMARY KEY,nickname VARCHAR(255) DEFAULT '',title VARCHAR(255) DEFAULT 'it''s'); COMMIT;
+
@@ -0,0 +1,9 @@
+-- sqlite
+BEGIN;  COMMIT;
+
+-- mysql
+START TRANSACTION; CREATE FULLTEXT INDEX post_search_idx ON post (title,body); COMMIT;
+
+-- postgres
+BEGIN; CREATE INDEX post_search_idx ON post USING GIN (to_tsvector('simple', coalesce(title, '') || ' ' || coalesce(body, ''))); COMMIT;
+
@@ -0,0 +1,9 @@
+-- sqlite
+BEGIN; ALTER TABLE user ADD COLUMN external_id INTEGER; COMMIT;
+
+-- mysql
+START TRANSACTION; ALTER TABLE user ADD COLUMN external_id BIGINT; COMMIT;
+
+-- postgres
+BEGIN; ALTER TABLE user ADD COLUMN external_id BIGINT; COMMIT;
+
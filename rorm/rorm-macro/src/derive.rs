@@ -97,10 +97,13 @@ pub fn model(strct: TokenStream) -> TokenStream {
 
     let model_name = syn::LitStr::new(&strct.ident.to_string(), strct.ident.span());
     let model_source = get_source(&strct);
+    let model_annotations = parse_model_annotations(&strct.attrs, &errors);
     let mut model_fields = Vec::new();
     let mut field_idents = Vec::new();
     for field in strct.fields.iter() {
         let mut annotations = Vec::new();
+        let mut citext = false;
+        let mut fixed_length = false;
         for meta in iter_rorm_attributes(&field.attrs, &errors) {
             // Get the annotation's identifier.
             // Since one is required for every annotation, error if it is missing.
@@ -133,11 +136,41 @@ pub fn model(strct: TokenStream) -> TokenStream {
                 "auto_update_time" => parse_anno!("auto_update_time", "AutoUpdateTime"),
                 "primary_key" => parse_anno!("primary_key", "PrimaryKey"),
                 "unique" => parse_anno!("unique", "Unique"),
+                "nulls_not_distinct" => parse_anno!("nulls_not_distinct", "NullsNotDistinct"),
                 "autoincrement" => parse_anno!("autoincrement", "AutoIncrement"),
+                "client_default" => parse_anno!("client_default", "ClientDefault"),
+                "fulltext" => parse_anno!("fulltext", "FullText"),
+                "sensitive" => parse_anno!("sensitive", "Sensitive"),
+                "default_uuid" => parse_anno!("default_uuid", "DefaultUuid"),
+                "citext" => {
+                    if let syn::Meta::Path(_) = meta {
+                        citext = true;
+                    } else {
+                        errors.push_new(meta.span(), "citext doesn't take any values: #[rorm(citext)]");
+                    }
+                },
+                "fixed_length" => {
+                    if let syn::Meta::Path(_) = meta {
+                        fixed_length = true;
+                    } else {
+                        errors.push_new(meta.span(), "fixed_length doesn't take any values: #[rorm(fixed_length)]");
+                    }
+                },
                 "default" => parse_default(&mut annotations, &errors, &meta),
+                "server_default" => parse_server_default(&mut annotations, &errors, &meta),
+                "foreign_key" => parse_foreign_key(&mut annotations, &errors, &meta),
                 "max_length" => parse_max_length(&mut annotations, &errors, &meta),
                 "choices" => parse_choices(&mut annotations, &errors, &meta),
                 "index" => parse_index(&mut annotations, &errors, &meta),
+                "only" => parse_only(&mut annotations, &errors, &meta),
+                "identity" => parse_identity(&mut annotations, &errors, &meta),
+                "rename_from" => {
+                    if let Some(name) = parse_string(&errors, &meta, "rename_from expects the previous name: #[rorm(rename_from = \"..\")]") {
+                        annotations.push(quote! {
+                            ::rorm::imr::Annotation::RenameFrom(#name.to_string())
+                        });
+                    }
+                },
                 _ => errors.push_new(ident.span(), "Unknown annotation")
             );
         }
@@ -150,7 +183,21 @@ pub fn model(strct: TokenStream) -> TokenStream {
                 let mut annotations = vec![
                     #(#annotations),*
                 ];
-                let db_type = #field_type::as_db_type(&annotations);
+                // Sensitive data is stored as binary, e.g. after encrypting it
+                let db_type = if annotations.iter().any(|x| matches!(x, ::rorm::imr::Annotation::Sensitive)) {
+                    ::rorm::imr::DbType::VarBinary
+                } else if #citext {
+                    // Case insensitive text replaces the type of the field
+                    ::rorm::imr::DbType::Citext
+                } else if #fixed_length {
+                    // Binaries are stored as binary_fixed, anything else as char
+                    match #field_type::as_db_type(&annotations) {
+                        ::rorm::imr::DbType::VarBinary => ::rorm::imr::DbType::Binary,
+                        _ => ::rorm::imr::DbType::Char,
+                    }
+                } else {
+                    #field_type::as_db_type(&annotations)
+                };
                 annotations.append(&mut #field_type::implicit_annotations());
                 ::rorm::model_def::Field {
                     name: #field_name,
@@ -177,7 +224,7 @@ pub fn model(strct: TokenStream) -> TokenStream {
     let fields_enum = Ident::new(&format!("__{}_Fields", strct.ident), span);
 
     let strct_ident = strct.ident;
-    TokenStream::from({
+    {
         quote! {
             #[allow(non_camel_case_types)]
             #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -196,6 +243,7 @@ pub fn model(strct: TokenStream) -> TokenStream {
                         name: #model_name,
                         source: #model_source,
                         fields: vec![ #(#model_fields),* ],
+                        annotations: vec![ #(#model_annotations),* ],
                     }
                 }
             }
@@ -205,12 +253,314 @@ pub fn model(strct: TokenStream) -> TokenStream {
 
             #[allow(non_snake_case)]
             #[::rorm::linkme::distributed_slice(::rorm::model_def::MODELS)]
-            #[::rorm::rename_linkme]
+            #[linkme(crate = ::rorm::linkme)]
             static #definition_getter_dyn_obj: &'static dyn ::rorm::model_def::GetModelDefinition = &#definition_getter_instance;
 
             #errors
         }
-    })
+    }
+}
+
+/// Parse the annotations of a model.
+///
+/// Supported annotations are:
+/// - `#[rorm(tablespace = <string literal>)]`
+/// - `#[rorm(charset = <string literal>)]`
+/// - `#[rorm(collation = <string literal>)]`
+/// - `#[rorm(rename_from = <string literal>)]`
+/// - `#[rorm(unlogged)]` and `#[rorm(temporary)]`
+/// - `#[rorm(storage(<name> = <literal>, ..))]`
+///   e.g. `#[rorm(storage(fillfactor = 70, autovacuum_enabled = false))]`
+/// - `#[rorm(partition_by(<range|list|hash>(<column>, ..)))]`
+///   e.g. `#[rorm(partition_by(range(created_at)))]`
+/// - `#[rorm(unique_together(<column>, ..))]`, which may be repeated
+///   e.g. `#[rorm(unique_together(owner, name))]`
+/// - `#[rorm(indexes(index(<column>, ..), ..))]`
+///   e.g. `#[rorm(indexes(index(email), index(tenant_id, created_at)))]`
+fn parse_model_annotations(attrs: &[syn::Attribute], errors: &Errors) -> Vec<TokenStream> {
+    let mut annotations = Vec::new();
+    for meta in iter_rorm_attributes(attrs, errors) {
+        let ident = if let Some(ident) = meta.path().get_ident() {
+            ident
+        } else {
+            errors.push_new(meta.path().span(), "expected identifier");
+            continue;
+        };
+
+        match_ident!(ident,
+            "tablespace" => {
+                if let Some(name) = parse_string(errors, &meta, "tablespace expects a single string literal: #[rorm(tablespace = \"..\")]") {
+                    annotations.push(quote! {
+                        ::rorm::imr::ModelAnnotation::Tablespace(#name.to_string())
+                    });
+                }
+            },
+            "charset" => {
+                if let Some(name) = parse_string(errors, &meta, "charset expects a single string literal: #[rorm(charset = \"..\")]") {
+                    annotations.push(quote! {
+                        ::rorm::imr::ModelAnnotation::Charset(#name.to_string())
+                    });
+                }
+            },
+            "rename_from" => {
+                if let Some(name) = parse_string(errors, &meta, "rename_from expects the previous name: #[rorm(rename_from = \"..\")]") {
+                    annotations.push(quote! {
+                        ::rorm::imr::ModelAnnotation::RenameFrom(#name.to_string())
+                    });
+                }
+            },
+            "collation" => {
+                if let Some(name) = parse_string(errors, &meta, "collation expects a single string literal: #[rorm(collation = \"..\")]") {
+                    annotations.push(quote! {
+                        ::rorm::imr::ModelAnnotation::Collation(#name.to_string())
+                    });
+                }
+            },
+            "inherits" => {
+                if let Some(name) = parse_string(errors, &meta, "inherits expects the name of the parent model: #[rorm(inherits = \"..\")]") {
+                    annotations.push(quote! {
+                        ::rorm::imr::ModelAnnotation::Inherits(#name.to_string())
+                    });
+                }
+            },
+            "storage" => parse_storage(&mut annotations, errors, &meta),
+            "partition_by" => parse_partition_by(&mut annotations, errors, &meta),
+            "unique_together" => parse_unique_together(&mut annotations, errors, &meta),
+            "indexes" => parse_indexes(&mut annotations, errors, &meta),
+            "unlogged" => parse_persistence(&mut annotations, errors, &meta, "unlogged", "Unlogged"),
+            "temporary" => parse_persistence(&mut annotations, errors, &meta, "temporary", "Temporary"),
+            _ => errors.push_new(ident.span(), "Unknown model annotation")
+        );
+    }
+    annotations
+}
+
+/// Parse the `#[rorm(unlogged)]` and `#[rorm(temporary)]` annotations.
+///
+/// They don't take any values.
+fn parse_persistence(
+    annotations: &mut Vec<TokenStream>,
+    errors: &Errors,
+    meta: &syn::Meta,
+    name: &str,
+    variant: &str,
+) {
+    if let syn::Meta::Path(_) = meta {
+        let variant = Ident::new(variant, meta.span());
+        annotations.push(quote! {
+            ::rorm::imr::ModelAnnotation::Persistence(::rorm::imr::Persistence::#variant)
+        });
+    } else {
+        errors.push_new(
+            meta.span(),
+            format!("{} doesn't take any values: #[rorm({})]", name, name),
+        );
+    }
+}
+
+/// Parse an annotation of the form `#[rorm(<name> = <string literal>)]`.
+///
+/// Reports the usage string as error, if the annotation has another form.
+fn parse_string<'a>(
+    errors: &Errors,
+    meta: &'a syn::Meta,
+    usage_string: &str,
+) -> Option<&'a syn::LitStr> {
+    match meta {
+        syn::Meta::NameValue(syn::MetaNameValue {
+            lit: syn::Lit::Str(value),
+            ..
+        }) => Some(value),
+        _ => {
+            errors.push_new(meta.span(), usage_string);
+            None
+        }
+    }
+}
+
+/// Parse the `#[rorm(storage(..))]` annotation.
+///
+/// It accepts any number of keyword arguments with a literal as value.
+fn parse_storage(annotations: &mut Vec<TokenStream>, errors: &Errors, meta: &syn::Meta) {
+    let usage_string = "storage expects keyword arguments: #[rorm(storage(fillfactor = 70))]";
+
+    if let syn::Meta::List(syn::MetaList { nested, .. }) = meta {
+        for nested_meta in nested.iter() {
+            let (name, value) =
+                if let syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit,
+                    ..
+                })) = nested_meta
+                {
+                    match path.get_ident() {
+                        Some(ident) => (ident.to_string(), lit),
+                        None => {
+                            errors.push_new(nested_meta.span(), usage_string);
+                            continue;
+                        }
+                    }
+                } else {
+                    errors.push_new(nested_meta.span(), usage_string);
+                    continue;
+                };
+
+            let value = match value {
+                syn::Lit::Str(value) => value.value(),
+                syn::Lit::Int(value) => value.to_string(),
+                syn::Lit::Float(value) => value.to_string(),
+                syn::Lit::Bool(value) => value.value.to_string(),
+                _ => {
+                    errors.push_new(value.span(), "unsupported literal");
+                    continue;
+                }
+            };
+
+            annotations.push(quote! {
+                ::rorm::imr::ModelAnnotation::StorageParameter(::rorm::imr::StorageParameter {
+                    name: #name.to_string(),
+                    value: #value.to_string(),
+                })
+            });
+        }
+    } else {
+        errors.push_new(meta.span(), usage_string);
+    }
+}
+
+/// Parse the `#[rorm(partition_by(..))]` annotation.
+///
+/// It accepts a single strategy with the column names as arguments.
+fn parse_partition_by(annotations: &mut Vec<TokenStream>, errors: &Errors, meta: &syn::Meta) {
+    let usage_string =
+        "partition_by expects a strategy and columns: #[rorm(partition_by(range(created_at)))]";
+
+    let strategy = match meta {
+        syn::Meta::List(syn::MetaList { nested, .. }) if nested.len() == 1 => match &nested[0] {
+            syn::NestedMeta::Meta(syn::Meta::List(strategy)) => strategy,
+            _ => {
+                errors.push_new(meta.span(), usage_string);
+                return;
+            }
+        },
+        _ => {
+            errors.push_new(meta.span(), usage_string);
+            return;
+        }
+    };
+
+    let variant = match strategy.path.get_ident().map(|x| x.to_string()).as_deref() {
+        Some("range") => "Range",
+        Some("list") => "List",
+        Some("hash") => "Hash",
+        _ => {
+            errors.push_new(
+                strategy.path.span(),
+                "Unknown partition strategy, expected range, list or hash",
+            );
+            return;
+        }
+    };
+    let variant = Ident::new(variant, strategy.path.span());
+
+    let mut columns = vec![];
+    for nested_meta in strategy.nested.iter() {
+        match nested_meta {
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.get_ident().is_some() => {
+                columns.push(path.get_ident().unwrap().to_string());
+            }
+            _ => errors.push_new(nested_meta.span(), usage_string),
+        }
+    }
+    if columns.is_empty() {
+        errors.push_new(strategy.span(), usage_string);
+        return;
+    }
+
+    annotations.push(quote! {
+        ::rorm::imr::ModelAnnotation::PartitionBy(::rorm::imr::PartitionBy {
+            strategy: ::rorm::imr::PartitionStrategy::#variant,
+            columns: vec![ #(#columns.to_string()),* ],
+        })
+    });
+}
+
+/// Parse the `#[rorm(unique_together(..))]` annotation.
+///
+/// It accepts the names of at least two columns.
+fn parse_unique_together(annotations: &mut Vec<TokenStream>, errors: &Errors, meta: &syn::Meta) {
+    let usage_string =
+        "unique_together expects at least two columns: #[rorm(unique_together(owner, name))]";
+
+    let nested = match meta {
+        syn::Meta::List(syn::MetaList { nested, .. }) => nested,
+        _ => {
+            errors.push_new(meta.span(), usage_string);
+            return;
+        }
+    };
+
+    let mut columns = vec![];
+    for nested_meta in nested.iter() {
+        match nested_meta {
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.get_ident().is_some() => {
+                columns.push(path.get_ident().unwrap().to_string());
+            }
+            _ => errors.push_new(nested_meta.span(), usage_string),
+        }
+    }
+    if columns.len() < 2 {
+        errors.push_new(meta.span(), usage_string);
+        return;
+    }
+
+    annotations.push(quote! {
+        ::rorm::imr::ModelAnnotation::UniqueTogether(vec![ #(#columns.to_string()),* ])
+    });
+}
+
+/// Parse the `#[rorm(indexes(..))]` annotation.
+///
+/// It accepts a list of indexes, each of them listing its columns in index order.
+fn parse_indexes(annotations: &mut Vec<TokenStream>, errors: &Errors, meta: &syn::Meta) {
+    let usage_string =
+        "indexes expects a list of indexes: #[rorm(indexes(index(email), index(tenant_id, created_at)))]";
+
+    let nested = match meta {
+        syn::Meta::List(syn::MetaList { nested, .. }) => nested,
+        _ => {
+            errors.push_new(meta.span(), usage_string);
+            return;
+        }
+    };
+
+    for nested_meta in nested.iter() {
+        let index = match nested_meta {
+            syn::NestedMeta::Meta(syn::Meta::List(index)) if index.path.is_ident("index") => index,
+            _ => {
+                errors.push_new(nested_meta.span(), usage_string);
+                continue;
+            }
+        };
+
+        let mut columns = vec![];
+        for column in index.nested.iter() {
+            match column {
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.get_ident().is_some() => {
+                    columns.push(path.get_ident().unwrap().to_string());
+                }
+                _ => errors.push_new(column.span(), usage_string),
+            }
+        }
+        if columns.is_empty() {
+            errors.push_new(index.span(), usage_string);
+            continue;
+        }
+
+        annotations.push(quote! {
+            ::rorm::imr::ModelAnnotation::Index(vec![ #(#columns.to_string()),* ])
+        });
+    }
 }
 
 /// Parse the `#[rorm(default = ..)]` annotation.
@@ -252,6 +602,174 @@ fn parse_default(annotations: &mut Vec<TokenStream>, errors: &Errors, meta: &syn
     });
 }
 
+/// Parse the `#[rorm(server_default = ..)]` annotation.
+///
+/// It accepts a single string literal containing the SQL expression as argument.
+fn parse_server_default(annotations: &mut Vec<TokenStream>, errors: &Errors, meta: &syn::Meta) {
+    match meta {
+        syn::Meta::NameValue(syn::MetaNameValue {
+            lit: syn::Lit::Str(expression),
+            ..
+        }) => annotations.push(quote! {
+            ::rorm::imr::Annotation::ServerDefault(#expression.to_string())
+        }),
+        _ => errors.push_new(
+            meta.span(),
+            "server_default expects a single string literal: #[rorm(server_default = \"..\")]",
+        ),
+    }
+}
+
+/// Parse the `#[rorm(foreign_key(..))]` annotation.
+///
+/// It requires the keyword arguments `table` and `column` with string literals
+/// and accepts the flags `deferrable` and `initially_deferred`
+/// as well as the actions `on_delete` and `on_update`, e.g. `on_delete = "cascade"`.
+fn parse_foreign_key(annotations: &mut Vec<TokenStream>, errors: &Errors, meta: &syn::Meta) {
+    let usage_string =
+        "foreign_key expects the referenced table and column: #[rorm(foreign_key(table = \"..\", column = \"..\"))]";
+
+    let nested = if let syn::Meta::List(syn::MetaList { nested, .. }) = meta {
+        nested
+    } else {
+        errors.push_new(meta.span(), usage_string);
+        return;
+    };
+
+    let mut table = None;
+    let mut column = None;
+    let mut deferrable = false;
+    let mut initially_deferred = false;
+    let mut on_delete = quote! { NoAction };
+    let mut on_update = quote! { NoAction };
+    for nested_meta in nested.iter() {
+        match nested_meta {
+            syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                lit: syn::Lit::Str(literal),
+                ..
+            })) if path.is_ident("table") => table = Some(literal.clone()),
+            syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                lit: syn::Lit::Str(literal),
+                ..
+            })) if path.is_ident("column") => column = Some(literal.clone()),
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("deferrable") => {
+                deferrable = true
+            }
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("initially_deferred") => {
+                initially_deferred = true
+            }
+            syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                lit: syn::Lit::Str(literal),
+                ..
+            })) if path.is_ident("on_delete") || path.is_ident("on_update") => {
+                let action = match literal.value().as_str() {
+                    "no_action" => quote! { NoAction },
+                    "restrict" => quote! { Restrict },
+                    "cascade" => quote! { Cascade },
+                    "set_null" => quote! { SetNull },
+                    "set_default" => quote! { SetDefault },
+                    _ => {
+                        errors.push_new(
+                            literal.span(),
+                            "Unknown action, expected one of no_action, restrict, cascade, set_null and set_default",
+                        );
+                        continue;
+                    }
+                };
+                if path.is_ident("on_delete") {
+                    on_delete = action;
+                } else {
+                    on_update = action;
+                }
+            }
+            _ => errors.push_new(nested_meta.span(), usage_string),
+        }
+    }
+
+    if initially_deferred && !deferrable {
+        errors.push_new(meta.span(), "initially_deferred requires deferrable");
+    }
+
+    match (table, column) {
+        (Some(table), Some(column)) => annotations.push(quote! {
+            ::rorm::imr::Annotation::ForeignKey(::rorm::imr::ForeignKey {
+                table_name: #table.to_string(),
+                column_name: #column.to_string(),
+                deferrable: #deferrable,
+                initially_deferred: #initially_deferred,
+                on_delete: ::rorm::imr::ReferentialAction::#on_delete,
+                on_update: ::rorm::imr::ReferentialAction::#on_update,
+            })
+        }),
+        _ => errors.push_new(meta.span(), usage_string),
+    }
+}
+
+/// Parse the `#[rorm(only(..))]` annotation.
+///
+/// It accepts a non-empty list of the dialects `sqlite`, `postgres` and `mysql`.
+fn parse_only(annotations: &mut Vec<TokenStream>, errors: &Errors, meta: &syn::Meta) {
+    let usage_string = "only expects a list of dialects: #[rorm(only(sqlite, postgres, mysql))]";
+
+    let nested = match meta {
+        syn::Meta::List(syn::MetaList { nested, .. }) if !nested.is_empty() => nested,
+        _ => {
+            errors.push_new(meta.span(), usage_string);
+            return;
+        }
+    };
+
+    let mut dialects = Vec::new();
+    for nested_meta in nested.iter() {
+        let dialect = match nested_meta {
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("sqlite") => "SQLite",
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("postgres") => "Postgres",
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("mysql") => "MySQL",
+            _ => {
+                errors.push_new(nested_meta.span(), usage_string);
+                continue;
+            }
+        };
+        let dialect = Ident::new(dialect, nested_meta.span());
+        dialects.push(quote! { ::rorm::imr::Dialect::#dialect });
+    }
+
+    annotations.push(quote! {
+        ::rorm::imr::Annotation::Only(vec![#(#dialects),*])
+    });
+}
+
+/// Parse the `#[rorm(identity)]` annotation.
+///
+/// It optionally accepts `"always"` or `"by_default"` as value, `"always"` if omitted.
+fn parse_identity(annotations: &mut Vec<TokenStream>, errors: &Errors, meta: &syn::Meta) {
+    let variant = match meta {
+        syn::Meta::Path(_) => "Always",
+        syn::Meta::NameValue(syn::MetaNameValue {
+            lit: syn::Lit::Str(value),
+            ..
+        }) if value.value() == "always" => "Always",
+        syn::Meta::NameValue(syn::MetaNameValue {
+            lit: syn::Lit::Str(value),
+            ..
+        }) if value.value() == "by_default" => "ByDefault",
+        _ => {
+            errors.push_new(
+                meta.span(),
+                "identity expects always or by_default: #[rorm(identity = \"by_default\")]",
+            );
+            return;
+        }
+    };
+    let variant = Ident::new(variant, meta.span());
+    annotations.push(quote! {
+        ::rorm::imr::Annotation::Identity(::rorm::imr::Identity::#variant)
+    });
+}
+
 /// Parse the `#[rorm(max_length = ..)]` annotation.
 ///
 /// It accepts a single integer literal as argument.
@@ -313,10 +831,10 @@ fn parse_choices(annotations: &mut Vec<TokenStream>, errors: &Errors, meta: &syn
 /// It accepts four different syntax's:
 /// - `#[rorm(index)]`
 /// - `#[rorm(index())]`
-///    *(semantically identical to first one)*
+///   *(semantically identical to first one)*
 /// - `#[rorm(name = <string literal>)]`
 /// - `#[rorm(name = <string literal>, priority = <integer literal>)]`
-///    *(insensitive to argument order)*
+///   *(insensitive to argument order)*
 fn parse_index(annotations: &mut Vec<TokenStream>, errors: &Errors, meta: &syn::Meta) {
     match &meta {
         // index was used on its own without arguments
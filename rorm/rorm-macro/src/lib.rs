@@ -6,6 +6,7 @@ use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::{quote, ToTokens};
 
+#[allow(dead_code)]
 mod args;
 mod derive;
 mod errors;
@@ -37,6 +38,33 @@ pub fn derive_db_enum(input: TokenStream) -> TokenStream {
 ///     gender: String,
 /// }
 /// ```
+///
+/// Options of the table can be set on the struct itself.
+/// Tablespaces and storage parameters are only supported by postgres,
+/// character sets and collations only by mysql.
+///
+/// ```
+/// use rorm::Model;
+///
+/// #[derive(Model)]
+/// #[rorm(tablespace = "fast", storage(fillfactor = 70), charset = "utf8mb4")]
+/// struct Log {
+///     #[rorm(primary_key)]
+///     id: i64,
+/// }
+/// ```
+///
+/// Postgres tables can be partitioned with `#[rorm(partition_by(range(created_at)))]`,
+/// its partitions are created by migrations.
+///
+/// A combination of columns is made unique with `#[rorm(unique_together(owner, name))]`,
+/// which may be repeated for several combinations.
+///
+/// Indexes are listed with `#[rorm(indexes(index(email), index(tenant_id, created_at)))]`,
+/// each of them with its columns in index order.
+///
+/// Unique columns treat NULL values as distinct, so several rows may be NULL.
+/// `#[rorm(unique, nulls_not_distinct)]` allows a single NULL on postgres 15 and newer.
 #[proc_macro_derive(Model, attributes(rorm))]
 pub fn derive_model(input: TokenStream) -> TokenStream {
     derive::model(input.into()).into()
@@ -79,7 +79,7 @@ pub fn rename_path(path: &mut syn::Path) {
     if path
         .segments
         .first()
-        .map_or(true, |segment| segment.ident != "linkme")
+        .is_none_or(|segment| segment.ident != "linkme")
     {
         return;
     }
@@ -90,6 +90,6 @@ pub fn rename_path(path: &mut syn::Path) {
         arguments: syn::PathArguments::None,
     }]
     .into_iter()
-    .chain(segments.into_iter())
+    .chain(segments)
     .collect();
 }
@@ -0,0 +1,60 @@
+use anyhow::anyhow;
+
+use crate::DBImpl;
+
+/**
+Representation of operations to execute in the context of an ALTER INDEX statement.
+*/
+pub enum SQLAlterIndexOperation {
+    /// Use this operation to rename an index
+    RenameTo { name: String },
+}
+
+/**
+Representation of an ALTER INDEX statement.
+*/
+pub struct SQLAlterIndex {
+    pub(crate) dialect: DBImpl,
+    /// Name of the index to operate on
+    pub(crate) name: String,
+    /// Operation to execute
+    pub(crate) operation: SQLAlterIndexOperation,
+    /// Skip the operation if the index doesn't exist
+    pub(crate) if_exists: bool,
+}
+
+impl SQLAlterIndex {
+    /**
+    Alters the index only, if it exists.
+    */
+    pub fn if_exists(mut self) -> Self {
+        self.if_exists = true;
+        self
+    }
+
+    /**
+    This method is used to build the alter index statement.
+
+    Only postgres supports altering an index directly, on all other dialects
+    the index has to be dropped and created again.
+    */
+    pub fn build(self) -> anyhow::Result<String> {
+        match self.dialect {
+            DBImpl::Postgres => Ok(match self.operation {
+                SQLAlterIndexOperation::RenameTo { name } => {
+                    format!(
+                        "ALTER INDEX {}{} RENAME TO {};",
+                        if self.if_exists { "IF EXISTS " } else { "" },
+                        self.name,
+                        name
+                    )
+                }
+            }),
+            DBImpl::SQLite | DBImpl::MySQL => Err(anyhow!(
+                "Couldn't alter index {}: {:?} doesn't support ALTER INDEX, recreate the index instead",
+                self.name,
+                self.dialect
+            )),
+        }
+    }
+}
@@ -1,3 +1,8 @@
+use anyhow::anyhow;
+
+use crate::create_column::identity_keyword;
+use crate::create_table::mysql_charset;
+use crate::imr::{Annotation, DefaultValue, Identity, StorageParameter};
 use crate::{DBImpl, SQLCreateColumn};
 
 /**
@@ -14,12 +19,73 @@ pub enum SQLAlterTableOperation {
     /// Use this operation to add a column to an existing table.
     /// Can be generated by using [crate::create_table::SQLCreateColumn]
     AddColumn { operation: SQLCreateColumn },
+    /// Use this operation to change the type of an existing column.
+    /// Postgres only changes the type, MySQL redefines the column including its annotations.
+    /// SQLite can't change column types.
+    AlterColumnType { operation: SQLCreateColumn },
     /// Use this operation to drop an existing column.
-    DropColumn { name: String },
+    /// If cascade is set, objects depending on the column are dropped as well, only supported by postgres.
+    DropColumn { name: String, cascade: bool },
+    /// Use this operation to add a named constraint to an existing table.
+    /// The definition is the constraint without its name, e.g. `UNIQUE (a, b)`.
+    /// If not_valid is set, existing rows are not checked, only supported by postgres.
+    AddConstraint {
+        name: String,
+        definition: String,
+        not_valid: bool,
+    },
+    /// Use this operation to check the existing rows against a constraint added with not_valid.
+    ValidateConstraint { name: String },
+    /// Use this operation to rename a constraint within a table
+    RenameConstraintTo {
+        constraint_name: String,
+        new_constraint_name: String,
+    },
+    /// Use this operation to drop an existing constraint.
+    DropConstraint { name: String },
+    /// Use this operation to drop the default value of a column.
+    DropDefault { column: String },
+    /// Use this operation to turn an existing column into an identity column.
+    AddIdentity { column: String, identity: Identity },
+    /// Use this operation to change how the values of an identity column are generated.
+    SetIdentity { column: String, identity: Identity },
+    /// Use this operation to turn an identity column into a plain column.
+    DropIdentity { column: String },
+    /// Use this operation to move a table to another tablespace.
+    SetTablespace { name: String },
+    /// Use this operation to set storage parameters of a table.
+    SetStorageParameters { parameters: Vec<StorageParameter> },
+    /// Use this operation to reset storage parameters of a table to their defaults.
+    ResetStorageParameters { names: Vec<String> },
+    /// Use this operation to convert a table and its columns to another character set.
+    /// The defaults of [crate::create_table::SQLCreateTable::charset] apply.
+    ConvertCharset {
+        charset: Option<String>,
+        collation: Option<String>,
+    },
+    /// Use this operation to attach an existing table as partition of a partitioned table.
+    /// The bound describes the rows of the partition, e.g. `FOR VALUES IN ('eu')` or `DEFAULT`.
+    AttachPartition { name: String, bound: String },
+    /// Use this operation to detach a partition, it is kept as standalone table.
+    DetachPartition { name: String },
+    /// Use this operation to rename an index of the table, only supported by mysql.
+    /// Other dialects rename indexes with ALTER INDEX.
+    RenameIndex { name: String, new_name: String },
+    /// Use this operation to add a parent table, only supported by postgres.
+    /// The table must already have all columns of the parent.
+    Inherit { parent: String },
+    /// Use this operation to remove a parent table, the inherited columns are kept.
+    NoInherit { parent: String },
 }
 
 impl SQLAlterTableOperation {
-    fn build(self) -> anyhow::Result<(String, Option<Vec<String>>)> {
+    fn build(
+        self,
+        dialect: DBImpl,
+        idempotent: bool,
+    ) -> anyhow::Result<(String, Option<Vec<String>>)> {
+        // Only postgres can skip operations which have already been applied
+        let idempotent = idempotent && dialect == DBImpl::Postgres;
         Ok(match self {
             SQLAlterTableOperation::RenameTo { name } => (format!("RENAME TO {}", name), None),
             SQLAlterTableOperation::RenameColumnTo {
@@ -30,10 +96,271 @@ impl SQLAlterTableOperation {
                 None,
             ),
             SQLAlterTableOperation::AddColumn { operation } => {
+                // SQLite fills the new column of existing rows with its default, even if the table is empty
+                if dialect == DBImpl::SQLite
+                    && operation.has_annotation(&Annotation::NotNull)
+                    && !operation.has_annotation(&Annotation::DefaultValue(DefaultValue::Integer(0)))
+                    && !operation.has_annotation(&Annotation::ServerDefault(String::new()))
+                    && !operation.has_annotation(&Annotation::DefaultUuid)
+                {
+                    return Err(anyhow!(
+                        "Column {} is not null, but has no default value. SQLite can only add not null columns to existing tables with a default value",
+                        operation.name
+                    ));
+                }
+
                 let (sql, annotation) = operation.build()?;
-                (format!("ADD COLUMN {}", sql), Some(annotation))
+                (
+                    format!(
+                        "ADD COLUMN {}{}",
+                        if idempotent { "IF NOT EXISTS " } else { "" },
+                        sql
+                    ),
+                    Some(annotation),
+                )
             }
-            SQLAlterTableOperation::DropColumn { name } => (format!("DROP COLUMN {}", name), None),
+            SQLAlterTableOperation::AlterColumnType { operation } => match dialect {
+                DBImpl::Postgres => (
+                    format!(
+                        "ALTER COLUMN {} TYPE {}",
+                        operation.name,
+                        // Serial types are only allowed when creating a column
+                        operation.type_name(false)?
+                    ),
+                    None,
+                ),
+                DBImpl::MySQL => {
+                    // The existing keys of the column are kept by MODIFY COLUMN
+                    let mut operation = operation;
+                    operation.annotations.retain(|x| {
+                        !matches!(x.annotation, Annotation::PrimaryKey | Annotation::Unique)
+                    });
+                    (format!("MODIFY COLUMN {}", operation.build()?.0), None)
+                }
+                DBImpl::SQLite => {
+                    return Err(anyhow!(
+                        "SQLite can't change the type of column {}, the table has to be rebuilt",
+                        operation.name
+                    ))
+                }
+            },
+            SQLAlterTableOperation::DropColumn { name, cascade } => match dialect {
+                DBImpl::SQLite if cascade => {
+                    return Err(anyhow!(
+                        "SQLite can't drop the objects depending on column {}, it doesn't support CASCADE",
+                        name
+                    ))
+                }
+                // MySQL drops the indexes of the column and fails on foreign keys,
+                // which have to be dropped before
+                DBImpl::SQLite | DBImpl::MySQL => (format!("DROP COLUMN {}", name), None),
+                DBImpl::Postgres => (
+                    format!(
+                        "DROP COLUMN {}{}{}",
+                        if idempotent { "IF EXISTS " } else { "" },
+                        name,
+                        if cascade { " CASCADE" } else { "" }
+                    ),
+                    None,
+                ),
+            },
+            SQLAlterTableOperation::AddConstraint {
+                name,
+                definition,
+                not_valid,
+            } => match dialect {
+                DBImpl::Postgres => (
+                    format!(
+                        "ADD CONSTRAINT {} {}{}",
+                        name,
+                        definition,
+                        if not_valid { " NOT VALID" } else { "" }
+                    ),
+                    None,
+                ),
+                DBImpl::MySQL => (format!("ADD CONSTRAINT {} {}", name, definition), None),
+                DBImpl::SQLite => {
+                    return Err(anyhow!(
+                        "SQLite doesn't support adding constraint {} to an existing table",
+                        name
+                    ))
+                }
+            },
+            SQLAlterTableOperation::RenameConstraintTo {
+                constraint_name,
+                new_constraint_name,
+            } => match dialect {
+                DBImpl::Postgres => (
+                    format!(
+                        "RENAME CONSTRAINT {} TO {}",
+                        constraint_name, new_constraint_name
+                    ),
+                    None,
+                ),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!(
+                    "{:?} doesn't support renaming constraint {}, recreate the constraint instead",
+                    dialect,
+                    constraint_name
+                ))
+                }
+            },
+            SQLAlterTableOperation::ValidateConstraint { name } => match dialect {
+                DBImpl::Postgres => (format!("VALIDATE CONSTRAINT {}", name), None),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!(
+                        "{:?} validates constraints when adding them, so constraint {} can't be validated",
+                        dialect,
+                        name
+                    ))
+                }
+            },
+            SQLAlterTableOperation::DropConstraint { name } => match dialect {
+                DBImpl::Postgres | DBImpl::MySQL => (
+                    format!(
+                        "DROP CONSTRAINT {}{}",
+                        if idempotent { "IF EXISTS " } else { "" },
+                        name
+                    ),
+                    None,
+                ),
+                DBImpl::SQLite => {
+                    return Err(anyhow!(
+                        "SQLite doesn't support dropping constraint {} from an existing table",
+                        name
+                    ))
+                }
+            },
+            SQLAlterTableOperation::DropDefault { column } => match dialect {
+                DBImpl::Postgres | DBImpl::MySQL => {
+                    (format!("ALTER COLUMN {} DROP DEFAULT", column), None)
+                }
+                DBImpl::SQLite => {
+                    return Err(anyhow!(
+                        "SQLite doesn't support dropping the default of column {}",
+                        column
+                    ))
+                }
+            },
+            SQLAlterTableOperation::AddIdentity { column, identity } => match dialect {
+                DBImpl::Postgres => (
+                    format!(
+                        "ALTER COLUMN {} ADD GENERATED {} AS IDENTITY",
+                        column,
+                        identity_keyword(identity)
+                    ),
+                    None,
+                ),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!("{:?} doesn't support identity columns", dialect))
+                }
+            },
+            SQLAlterTableOperation::SetIdentity { column, identity } => match dialect {
+                DBImpl::Postgres => (
+                    format!(
+                        "ALTER COLUMN {} SET GENERATED {}",
+                        column,
+                        identity_keyword(identity)
+                    ),
+                    None,
+                ),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!("{:?} doesn't support identity columns", dialect))
+                }
+            },
+            SQLAlterTableOperation::DropIdentity { column } => match dialect {
+                DBImpl::Postgres => (
+                    format!(
+                        "ALTER COLUMN {} DROP IDENTITY{}",
+                        column,
+                        if idempotent { " IF EXISTS" } else { "" }
+                    ),
+                    None,
+                ),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!("{:?} doesn't support identity columns", dialect))
+                }
+            },
+            SQLAlterTableOperation::SetTablespace { name } => match dialect {
+                DBImpl::Postgres => (format!("SET TABLESPACE {}", name), None),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!("{:?} doesn't support tablespaces", dialect))
+                }
+            },
+            SQLAlterTableOperation::SetStorageParameters { parameters } => match dialect {
+                DBImpl::Postgres => (
+                    format!(
+                        "SET ({})",
+                        parameters
+                            .iter()
+                            .map(|x| format!("{}={}", x.name, x.value))
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
+                    None,
+                ),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!("{:?} doesn't support storage parameters", dialect))
+                }
+            },
+            SQLAlterTableOperation::ResetStorageParameters { names } => match dialect {
+                DBImpl::Postgres => (format!("RESET ({})", names.join(", ")), None),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!("{:?} doesn't support storage parameters", dialect))
+                }
+            },
+            SQLAlterTableOperation::ConvertCharset { charset, collation } => match dialect {
+                DBImpl::MySQL => {
+                    let (charset, collation) =
+                        mysql_charset(charset.as_deref(), collation.as_deref());
+                    (
+                        format!(
+                            "CONVERT TO CHARACTER SET {}{}",
+                            charset,
+                            collation
+                                .map(|x| format!(" COLLATE {}", x))
+                                .unwrap_or_default()
+                        ),
+                        None,
+                    )
+                }
+                DBImpl::SQLite | DBImpl::Postgres => {
+                    return Err(anyhow!("{:?} doesn't support character sets", dialect))
+                }
+            },
+            SQLAlterTableOperation::AttachPartition { name, bound } => match dialect {
+                DBImpl::Postgres => (format!("ATTACH PARTITION {} {}", name, bound), None),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!("{:?} doesn't support partitioned tables", dialect))
+                }
+            },
+            SQLAlterTableOperation::DetachPartition { name } => match dialect {
+                DBImpl::Postgres => (format!("DETACH PARTITION {}", name), None),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!("{:?} doesn't support partitioned tables", dialect))
+                }
+            },
+            SQLAlterTableOperation::Inherit { parent } => match dialect {
+                DBImpl::Postgres => (format!("INHERIT {}", parent), None),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!("{:?} doesn't support table inheritance", dialect))
+                }
+            },
+            SQLAlterTableOperation::NoInherit { parent } => match dialect {
+                DBImpl::Postgres => (format!("NO INHERIT {}", parent), None),
+                DBImpl::SQLite | DBImpl::MySQL => {
+                    return Err(anyhow!("{:?} doesn't support table inheritance", dialect))
+                }
+            },
+            SQLAlterTableOperation::RenameIndex { name, new_name } => match dialect {
+                DBImpl::MySQL => (format!("RENAME INDEX {} TO {}", name, new_name), None),
+                DBImpl::SQLite | DBImpl::Postgres => {
+                    return Err(anyhow!(
+                        "{:?} doesn't support renaming indexes with ALTER TABLE",
+                        dialect
+                    ))
+                }
+            },
         })
     }
 }
@@ -47,30 +374,43 @@ pub struct SQLAlterTable {
     pub(crate) name: String,
     /// Operation to execute
     pub(crate) operation: SQLAlterTableOperation,
+    /// Skip the operation if it has already been applied
+    pub(crate) idempotent: bool,
 }
 
 impl SQLAlterTable {
+    /**
+    Skips the operation if it has already been applied.
+
+    Only supported by postgres for renaming a table, adding or dropping a column
+    and dropping a constraint or identity, ignored otherwise.
+    */
+    pub fn idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+
     /**
     This method is used to build the alter table statement.
     */
     pub fn build(self) -> anyhow::Result<String> {
-        Ok(match self.dialect {
-            DBImpl::SQLite => {
-                let (sql, trigger) = self.operation.build()?;
-                format!(
-                    "ALTER TABLE {} {};{}",
-                    self.name.as_str(),
-                    sql,
-                    match trigger {
-                        None => {
-                            "".to_string()
-                        }
-                        Some(t) => {
-                            t.join(" ")
-                        }
-                    }
-                )
+        let if_exists = self.idempotent
+            && self.dialect == DBImpl::Postgres
+            && matches!(self.operation, SQLAlterTableOperation::RenameTo { .. });
+        let (sql, trigger) = self.operation.build(self.dialect, self.idempotent)?;
+        Ok(format!(
+            "ALTER TABLE {}{} {};{}",
+            if if_exists { "IF EXISTS " } else { "" },
+            self.name.as_str(),
+            sql,
+            match trigger {
+                None => {
+                    "".to_string()
+                }
+                Some(t) => {
+                    t.join(" ")
+                }
             }
-        })
+        ))
     }
 }
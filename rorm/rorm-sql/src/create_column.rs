@@ -1,10 +1,39 @@
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 
 use crate::create_trigger::trigger_annotation_to_trigger;
 
-use crate::imr::DefaultValue;
+use crate::imr::{DefaultValue, Identity};
 use crate::{Annotation, DBImpl, DbType};
 
+/// Maximum length of a VARCHAR column on MySQL, assuming 4 byte utf8mb4 characters
+pub(crate) const MYSQL_MAX_VARCHAR_LENGTH: i32 = 16383;
+/// Type names allowed in the STRICT tables of SQLite
+pub const SQLITE_STRICT_TYPES: [&str; 6] = ["INT", "INTEGER", "REAL", "TEXT", "BLOB", "ANY"];
+/// Maximum length of a TEXT column on MySQL
+pub(crate) const MYSQL_MAX_TEXT_LENGTH: i32 = 65535;
+/// Maximum length of a MEDIUMTEXT column on MySQL
+pub(crate) const MYSQL_MAX_MEDIUMTEXT_LENGTH: i32 = 16777215;
+/// Maximum length of a CHAR or BINARY column on MySQL
+pub(crate) const MYSQL_MAX_CHAR_LENGTH: i32 = 255;
+/// Maximum length of a VARBINARY column on MySQL in bytes
+pub(crate) const MYSQL_MAX_VARBINARY_LENGTH: i32 = 65535;
+/// Maximum size of a row on MySQL in bytes
+pub(crate) const MYSQL_MAX_ROW_SIZE: usize = 65535;
+/// Case insensitive collation of citext columns on MySQL
+pub(crate) const MYSQL_CITEXT_COLLATION: &str = "utf8mb4_general_ci";
+/// Expression generating a random version 4 uuid on SQLite, which has no function for it
+pub const SQLITE_RANDOM_UUID: &str = "(lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4' || substr(lower(hex(randomblob(2))), 2) || '-' || substr('89ab', abs(random()) % 4 + 1, 1) || substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6))))";
+
+/**
+Returns the keyword of the identity kind used by postgres
+*/
+pub(crate) fn identity_keyword(identity: Identity) -> &'static str {
+    match identity {
+        Identity::Always => "ALWAYS",
+        Identity::ByDefault => "BY DEFAULT",
+    }
+}
+
 /**
 Representation of an annotation
  */
@@ -12,6 +41,12 @@ pub struct SQLAnnotation {
     pub(crate) annotation: Annotation,
 }
 
+impl From<Annotation> for SQLAnnotation {
+    fn from(annotation: Annotation) -> Self {
+        SQLAnnotation { annotation }
+    }
+}
+
 impl SQLAnnotation {
     /**
     Converts the struct into the String for the given dialect.
@@ -20,28 +55,81 @@ impl SQLAnnotation {
      */
     pub fn build(&self, dialect: DBImpl) -> anyhow::Result<String> {
         match dialect {
-            DBImpl::SQLite => {
-                return Ok(match &self.annotation {
-                    Annotation::AutoIncrement => "AUTOINCREMENT".to_string(),
-                    Annotation::AutoCreateTime => "DEFAULT CURRENT_TIMESTAMP".to_string(),
-                    Annotation::DefaultValue(d) => match d {
-                        DefaultValue::String(s) => format!("DEFAULT {}", s),
-                        DefaultValue::Integer(i) => format!("DEFAULT {}", i),
-                        DefaultValue::Float(f) => format!("DEFAULT {}", f),
-                        DefaultValue::Boolean(b) => {
-                            if *b {
-                                "DEFAULT 1".to_string()
-                            } else {
-                                "DEFAULT 0".to_string()
-                            }
+            DBImpl::SQLite => Ok(match &self.annotation {
+                // Explicit values can't be rejected by SQLite
+                Annotation::AutoIncrement | Annotation::Identity(_) => "AUTOINCREMENT".to_string(),
+                Annotation::AutoCreateTime => "DEFAULT CURRENT_TIMESTAMP".to_string(),
+                Annotation::DefaultValue(d) => match d {
+                    DefaultValue::String(s) => format!("DEFAULT '{}'", s.replace('\'', "''")),
+                    DefaultValue::Integer(i) => format!("DEFAULT {}", i),
+                    DefaultValue::Float(f) => format!("DEFAULT {}", f),
+                    DefaultValue::Boolean(b) => {
+                        if *b {
+                            "DEFAULT 1".to_string()
+                        } else {
+                            "DEFAULT 0".to_string()
                         }
-                    },
-                    Annotation::NotNull => "NOT NULL".to_string(),
-                    Annotation::PrimaryKey => "PRIMARY KEY".to_string(),
-                    Annotation::Unique => "UNIQUE".to_string(),
-                    _ => "".to_string(),
-                });
-            }
+                    }
+                },
+                Annotation::ServerDefault(e) => format!("DEFAULT {}", e),
+                Annotation::DefaultUuid => format!("DEFAULT {}", SQLITE_RANDOM_UUID),
+                // Client defaults are applied by the orm only
+                Annotation::ClientDefault => "".to_string(),
+                Annotation::NotNull => "NOT NULL".to_string(),
+                Annotation::PrimaryKey => "PRIMARY KEY".to_string(),
+                Annotation::Unique => "UNIQUE".to_string(),
+                _ => "".to_string(),
+            }),
+            DBImpl::Postgres => Ok(match &self.annotation {
+                // Auto increment is expressed by the SERIAL types on postgres
+                Annotation::AutoIncrement => "".to_string(),
+                Annotation::Identity(i) => {
+                    format!("GENERATED {} AS IDENTITY", identity_keyword(*i))
+                }
+                Annotation::AutoCreateTime => "DEFAULT CURRENT_TIMESTAMP".to_string(),
+                Annotation::DefaultValue(d) => match d {
+                    DefaultValue::String(s) => format!("DEFAULT '{}'", s.replace('\'', "''")),
+                    DefaultValue::Integer(i) => format!("DEFAULT {}", i),
+                    DefaultValue::Float(f) => format!("DEFAULT {}", f),
+                    DefaultValue::Boolean(b) => format!("DEFAULT {}", b),
+                },
+                Annotation::ServerDefault(e) => format!("DEFAULT {}", e),
+                // Built in since postgres 13, provided by pgcrypto before
+                Annotation::DefaultUuid => "DEFAULT gen_random_uuid()".to_string(),
+                // Client defaults are applied by the orm only
+                Annotation::ClientDefault => "".to_string(),
+                Annotation::NotNull => "NOT NULL".to_string(),
+                Annotation::PrimaryKey => "PRIMARY KEY".to_string(),
+                Annotation::Unique => "UNIQUE".to_string(),
+                _ => "".to_string(),
+            }),
+            DBImpl::MySQL => Ok(match &self.annotation {
+                // Explicit values can't be rejected by MySQL
+                Annotation::AutoIncrement | Annotation::Identity(_) => "AUTO_INCREMENT".to_string(),
+                Annotation::AutoCreateTime => "DEFAULT CURRENT_TIMESTAMP".to_string(),
+                Annotation::AutoUpdateTime => "ON UPDATE CURRENT_TIMESTAMP".to_string(),
+                // Expressions as default require mysql 8.0.13
+                Annotation::DefaultUuid => "DEFAULT (UUID())".to_string(),
+                Annotation::DefaultValue(d) => match d {
+                    DefaultValue::String(s) => format!("DEFAULT '{}'", s.replace('\'', "''")),
+                    DefaultValue::Integer(i) => format!("DEFAULT {}", i),
+                    DefaultValue::Float(f) => format!("DEFAULT {}", f),
+                    DefaultValue::Boolean(b) => {
+                        if *b {
+                            "DEFAULT 1".to_string()
+                        } else {
+                            "DEFAULT 0".to_string()
+                        }
+                    }
+                },
+                Annotation::ServerDefault(e) => format!("DEFAULT {}", e),
+                // Client defaults are applied by the orm only
+                Annotation::ClientDefault => "".to_string(),
+                Annotation::NotNull => "NOT NULL".to_string(),
+                Annotation::PrimaryKey => "PRIMARY KEY".to_string(),
+                Annotation::Unique => "UNIQUE".to_string(),
+                _ => "".to_string(),
+            }),
         }
     }
 }
@@ -58,19 +146,349 @@ pub struct SQLCreateColumn {
 }
 
 impl SQLCreateColumn {
+    /**
+    Returns the value of the max_length annotation, if set.
+    */
+    fn max_length(&self) -> Option<i32> {
+        self.annotations.iter().find_map(|x| match x.annotation {
+            Annotation::MaxLength(l) => Some(l),
+            _ => None,
+        })
+    }
+
+    /**
+    Returns the values of the choices annotation formatted as SQL string literals.
+    */
+    fn choices(&self) -> Option<String> {
+        self.annotations.iter().find_map(|x| match &x.annotation {
+            Annotation::Choices(c) => Some(
+                c.iter()
+                    .map(|y| format!("'{}'", y.replace('\'', "''")))
+                    .collect::<Vec<String>>()
+                    .join(","),
+            ),
+            _ => None,
+        })
+    }
+
+    /**
+    Checks whether the column carries the given annotation.
+    */
+    pub(crate) fn has_annotation(&self, annotation: &Annotation) -> bool {
+        self.annotations
+            .iter()
+            .any(|x| std::mem::discriminant(&x.annotation) == std::mem::discriminant(annotation))
+    }
+
+    /**
+    Converts the data type of the column to its representation in the given dialect.
+    */
+    fn build_type(&self) -> anyhow::Result<String> {
+        self.type_name(self.has_annotation(&Annotation::AutoIncrement))
+    }
+
+    /**
+    Converts the data type of the column to its representation in the given dialect.
+
+    `serial`: [bool]: Whether the serial types of postgres are used for integers.
+    */
+    pub(crate) fn type_name(&self, serial: bool) -> anyhow::Result<String> {
+        Ok(match self.dialect {
+            DBImpl::SQLite => match self.data_type {
+                DbType::VarChar
+                | DbType::Date
+                | DbType::Datetime
+                | DbType::Timestamp
+                | DbType::Time
+                | DbType::Choices
+                | DbType::Set
+                | DbType::Uuid
+                | DbType::Char
+                // The collation is added to the constraints of the column
+                | DbType::Citext => "TEXT",
+                DbType::Int8
+                | DbType::Int16
+                | DbType::Int32
+                | DbType::Int64
+                | DbType::UInt8
+                | DbType::UInt16
+                | DbType::UInt32
+                | DbType::UInt64
+                | DbType::Boolean
+                | DbType::Interval => "INTEGER",
+                DbType::Float | DbType::Double => "REAL",
+                DbType::VarBinary | DbType::Binary => "BLOB",
+            }
+            .to_string(),
+            DBImpl::Postgres => match self.data_type {
+                DbType::VarChar | DbType::Choices | DbType::Set => match self.max_length() {
+                    None => "VARCHAR".to_string(),
+                    Some(l) => format!("VARCHAR({})", l),
+                },
+                DbType::Char => format!("CHAR({})", self.fixed_length()?),
+                // Postgres has no fixed length binary type
+                DbType::VarBinary | DbType::Binary => "BYTEA".to_string(),
+                DbType::Int8 | DbType::Int16 | DbType::UInt8 => {
+                    if serial { "SMALLSERIAL" } else { "SMALLINT" }.to_string()
+                }
+                DbType::Int32 | DbType::UInt16 => {
+                    if serial { "SERIAL" } else { "INTEGER" }.to_string()
+                }
+                DbType::Int64 | DbType::UInt32 | DbType::UInt64 => {
+                    if serial { "BIGSERIAL" } else { "BIGINT" }.to_string()
+                }
+                DbType::Float => "REAL".to_string(),
+                DbType::Double => "DOUBLE PRECISION".to_string(),
+                DbType::Boolean => "BOOLEAN".to_string(),
+                DbType::Date => "DATE".to_string(),
+                DbType::Datetime | DbType::Timestamp => "TIMESTAMP".to_string(),
+                DbType::Time => "TIME".to_string(),
+                DbType::Uuid => "UUID".to_string(),
+                // Provided by the citext extension
+                DbType::Citext => "CITEXT".to_string(),
+                DbType::Interval => "INTERVAL".to_string(),
+            },
+            DBImpl::MySQL => match self.data_type {
+                DbType::VarChar | DbType::Citext => {
+                    let text = match self.max_length() {
+                        None => {
+                            return Err(anyhow!(
+                                "Column {} of type {} requires a max_length annotation on MySQL",
+                                self.name,
+                                match self.data_type {
+                                    DbType::Citext => "citext",
+                                    _ => "varchar",
+                                }
+                            ))
+                        }
+                        // Lengths exceeding the VARCHAR limit use the smallest fitting TEXT type
+                        Some(l) if l <= MYSQL_MAX_VARCHAR_LENGTH => format!("VARCHAR({})", l),
+                        Some(l) if l <= MYSQL_MAX_TEXT_LENGTH => "TEXT".to_string(),
+                        Some(l) if l <= MYSQL_MAX_MEDIUMTEXT_LENGTH => "MEDIUMTEXT".to_string(),
+                        Some(_) => "LONGTEXT".to_string(),
+                    };
+                    match self.data_type {
+                        DbType::Citext => format!("{} COLLATE {}", text, MYSQL_CITEXT_COLLATION),
+                        _ => text,
+                    }
+                }
+                DbType::VarBinary => match self.max_length() {
+                    Some(l) if l <= MYSQL_MAX_VARBINARY_LENGTH => format!("VARBINARY({})", l),
+                    _ => "LONGBLOB".to_string(),
+                },
+                DbType::Char | DbType::Binary => {
+                    let length = self.fixed_length()?;
+                    if length > MYSQL_MAX_CHAR_LENGTH {
+                        return Err(anyhow!(
+                            "Column {} of type {} exceeds the maximum length of {} on MySQL",
+                            self.name,
+                            self.type_label(),
+                            MYSQL_MAX_CHAR_LENGTH
+                        ));
+                    }
+                    match self.data_type {
+                        DbType::Char => format!("CHAR({})", length),
+                        _ => format!("BINARY({})", length),
+                    }
+                }
+                DbType::Int8 => "TINYINT".to_string(),
+                DbType::Int16 => "SMALLINT".to_string(),
+                DbType::Int32 => "INT".to_string(),
+                DbType::Int64 => "BIGINT".to_string(),
+                DbType::UInt8 => "TINYINT UNSIGNED".to_string(),
+                DbType::UInt16 => "SMALLINT UNSIGNED".to_string(),
+                DbType::UInt32 => "INT UNSIGNED".to_string(),
+                DbType::UInt64 => "BIGINT UNSIGNED".to_string(),
+                DbType::Float => "FLOAT".to_string(),
+                DbType::Double => "DOUBLE".to_string(),
+                DbType::Boolean => "BOOL".to_string(),
+                DbType::Date => "DATE".to_string(),
+                DbType::Datetime => "DATETIME".to_string(),
+                DbType::Timestamp => "TIMESTAMP".to_string(),
+                DbType::Time => "TIME".to_string(),
+                DbType::Uuid => "CHAR(36)".to_string(),
+                // MySQL has no type for durations, TIME is limited to 838 hours
+                DbType::Interval => "BIGINT".to_string(),
+                DbType::Choices | DbType::Set => match self.choices() {
+                    None => {
+                        return Err(anyhow!(
+                            "Column {} requires a choices annotation on MySQL",
+                            self.name
+                        ))
+                    }
+                    Some(c) => match self.data_type {
+                        DbType::Set => format!("SET({})", c),
+                        _ => format!("ENUM({})", c),
+                    },
+                },
+            },
+        })
+    }
+
+    /**
+    Returns the length of a fixed length column, which is set by its max_length annotation.
+    */
+    fn fixed_length(&self) -> anyhow::Result<i32> {
+        match self.max_length() {
+            Some(l) if l > 0 => Ok(l),
+            _ => Err(anyhow!(
+                "Column {} of type {} requires a positive max_length annotation",
+                self.name,
+                self.type_label()
+            )),
+        }
+    }
+
+    /**
+    Returns the name of the fixed length types in the models, used for error messages.
+    */
+    fn type_label(&self) -> &'static str {
+        match self.data_type {
+            DbType::Binary => "binary_fixed",
+            _ => "char",
+        }
+    }
+
+    /**
+    Returns the maximum number of bytes the column occupies in a MySQL row.
+
+    Only VARCHAR, VARBINARY, CHAR and BINARY columns are taken into account,
+    as TEXT and BLOB columns are stored off-page.
+    */
+    pub(crate) fn mysql_row_size(&self) -> usize {
+        match (&self.data_type, self.max_length()) {
+            (DbType::VarChar | DbType::Citext, Some(l))
+                if (0..=MYSQL_MAX_VARCHAR_LENGTH).contains(&l) =>
+            {
+                let bytes = l as usize * 4;
+                // Length prefix of the value
+                bytes + if bytes > 255 { 2 } else { 1 }
+            }
+            (DbType::VarBinary, Some(l)) if (0..=MYSQL_MAX_VARBINARY_LENGTH).contains(&l) => {
+                l as usize + if l > 255 { 2 } else { 1 }
+            }
+            (DbType::Char, Some(l)) if (0..=MYSQL_MAX_CHAR_LENGTH).contains(&l) => l as usize * 4,
+            (DbType::Binary, Some(l)) if (0..=MYSQL_MAX_CHAR_LENGTH).contains(&l) => l as usize,
+            (DbType::Uuid, _) => 36 * 4,
+            _ => 0,
+        }
+    }
+
+    /**
+    Returns a warning, if the type of the column has no native representation in the dialect.
+    */
+    pub fn degraded_type_warning(&self) -> Option<String> {
+        match (&self.data_type, self.dialect) {
+            (DbType::Interval, DBImpl::MySQL | DBImpl::SQLite) => Some(format!(
+                "Column {}.{} of type interval is stored as integer number of microseconds on {:?}",
+                self.table_name, self.name, self.dialect
+            )),
+            (DbType::Binary, DBImpl::Postgres) => Some(format!(
+                "Column {}.{} of type binary_fixed is stored as BYTEA without a fixed length on Postgres",
+                self.table_name, self.name
+            )),
+            _ => None,
+        }
+    }
+
+    /**
+    Converts the default of an interval column.
+
+    Postgres takes interval literals, e.g. `1 day`, and integers as number of microseconds.
+    The other dialects store the number of microseconds, so they only take integers.
+
+    `default`: [&DefaultValue]: The default of the column.
+    */
+    fn interval_default(&self, default: &DefaultValue) -> anyhow::Result<String> {
+        match (default, self.dialect) {
+            (DefaultValue::String(s), DBImpl::Postgres) => {
+                Ok(format!("DEFAULT INTERVAL '{}'", s.replace('\'', "''")))
+            }
+            (DefaultValue::Integer(i), DBImpl::Postgres) => {
+                Ok(format!("DEFAULT INTERVAL '{} microseconds'", i))
+            }
+            (DefaultValue::Integer(i), DBImpl::MySQL | DBImpl::SQLite) => {
+                Ok(format!("DEFAULT {}", i))
+            }
+            (DefaultValue::String(s), DBImpl::MySQL | DBImpl::SQLite) => Err(anyhow!(
+                "Column {} has the default {:?}, but interval literals are only supported by Postgres. Use the number of microseconds instead",
+                self.name,
+                s
+            )),
+            _ => Err(anyhow!(
+                "Column {} of type interval takes an interval literal or a number of microseconds as default",
+                self.name
+            )),
+        }
+    }
+
+    /**
+    Converts the default of a boolean column.
+
+    Postgres has a native boolean type and takes `true` and `false`.
+    MySQL and SQLite store booleans as integers, so they take `1` and `0`.
+    Defaults written as integer or string, e.g. by hand or read from a database,
+    are normalized first, so they are rendered the same way.
+
+    `default`: [&DefaultValue]: The default of the column.
+    */
+    fn boolean_default(&self, default: &DefaultValue) -> anyhow::Result<String> {
+        let value = match default {
+            DefaultValue::Boolean(b) => *b,
+            DefaultValue::Integer(0) => false,
+            DefaultValue::Integer(1) => true,
+            DefaultValue::String(s) => match s.to_lowercase().as_str() {
+                "true" | "t" | "1" => true,
+                "false" | "f" | "0" => false,
+                _ => {
+                    return Err(anyhow!(
+                        "Column {} of type boolean has the default {:?}, which is not a boolean",
+                        self.name,
+                        s
+                    ))
+                }
+            },
+            _ => {
+                return Err(anyhow!(
+                    "Column {} of type boolean has the default {:?}, which is not a boolean",
+                    self.name,
+                    default
+                ))
+            }
+        };
+
+        Ok(match (self.dialect, value) {
+            (DBImpl::Postgres, value) => format!("DEFAULT {}", value),
+            (DBImpl::MySQL | DBImpl::SQLite, true) => "DEFAULT 1".to_string(),
+            (DBImpl::MySQL | DBImpl::SQLite, false) => "DEFAULT 0".to_string(),
+        })
+    }
+
     pub fn build(self) -> anyhow::Result<(String, Vec<String>)> {
-        match self.dialect {
-            DBImpl::SQLite => {
-                let db_type = match self.data_type {
-                    DbType::VarChar
-                    | DbType::VarBinary
-                    | DbType::Date
-                    | DbType::Datetime
-                    | DbType::Timestamp
-                    | DbType::Time
-                    | DbType::Choices
-                    | DbType::Set => "TEXT",
-                    DbType::Int8
+        let db_type = self
+            .build_type()
+            .with_context(|| format!("Error while building column {}", self.name))?;
+
+        // Tables are created as STRICT on SQLite, which rejects other type names
+        if self.dialect == DBImpl::SQLite && !SQLITE_STRICT_TYPES.contains(&db_type.as_str()) {
+            return Err(anyhow!(
+                "Column {} has the type {:?}, which can't be represented in STRICT tables of SQLite. Allowed types are {}",
+                self.name,
+                self.data_type,
+                SQLITE_STRICT_TYPES.join(", ")
+            ));
+        }
+
+        if self.has_annotation(&Annotation::Identity(Identity::Always)) {
+            if self.has_annotation(&Annotation::AutoIncrement) {
+                return Err(anyhow!(
+                    "Column {} can't be auto_increment and identity at the same time",
+                    self.name
+                ));
+            }
+            if !matches!(
+                self.data_type,
+                DbType::Int8
                     | DbType::Int16
                     | DbType::Int32
                     | DbType::Int64
@@ -78,42 +496,105 @@ impl SQLCreateColumn {
                     | DbType::UInt16
                     | DbType::UInt32
                     | DbType::UInt64
-                    | DbType::Boolean => "INTEGER",
-                    DbType::Float | DbType::Double => "REAL",
-                };
-
-                let mut annotations = vec![];
-                let mut trigger = vec![];
-                for annotation in &self.annotations {
-                    annotations.push(
-                        annotation.build(DBImpl::SQLite).with_context(|| {
-                            format!("Error while building column {}", self.name)
-                        })?,
-                    );
-
-                    // If annotation requires a trigger, create those
-                    trigger.extend(trigger_annotation_to_trigger(
-                        DBImpl::SQLite,
-                        &annotation.annotation,
-                        self.table_name.as_str(),
-                        self.name.as_str(),
-                    )?)
-                }
-
-                return Ok((
-                    format!(
-                        "{} {}{}",
-                        self.name,
-                        db_type,
-                        if annotations.len() > 0 {
-                            format!(" {}", annotations.join(" "))
-                        } else {
-                            annotations.join(" ")
-                        }
-                    ),
-                    trigger,
+            ) {
+                return Err(anyhow!(
+                    "Column {} has the type {:?}, but identity columns require an integer type",
+                    self.name,
+                    self.data_type
                 ));
             }
         }
+
+        // All of them are emitted as DEFAULT clause
+        let server_defaults = self
+            .annotations
+            .iter()
+            .filter(|x| {
+                matches!(
+                    x.annotation,
+                    Annotation::DefaultValue(_)
+                        | Annotation::ServerDefault(_)
+                        | Annotation::DefaultUuid
+                        | Annotation::AutoCreateTime
+                )
+            })
+            .count();
+        if server_defaults > 1 {
+            return Err(anyhow!(
+                "Column {} has more than one server default",
+                self.name
+            ));
+        }
+
+        if self.has_annotation(&Annotation::DefaultUuid) && !matches!(self.data_type, DbType::Uuid)
+        {
+            return Err(anyhow!(
+                "Column {} has a uuid default, which requires the type uuid",
+                self.name
+            ));
+        }
+
+        if self.has_annotation(&Annotation::NullsNotDistinct)
+            && !self.has_annotation(&Annotation::Unique)
+        {
+            return Err(anyhow!(
+                "Column {} is nulls_not_distinct, which requires it to be unique",
+                self.name
+            ));
+        }
+        let nulls_not_distinct =
+            self.dialect == DBImpl::Postgres && self.has_annotation(&Annotation::NullsNotDistinct);
+
+        let mut annotations = vec![];
+        if self.dialect == DBImpl::SQLite && matches!(self.data_type, DbType::Citext) {
+            annotations.push("COLLATE NOCASE".to_string());
+        }
+        let mut trigger = vec![];
+        for annotation in &self.annotations {
+            let a = match &annotation.annotation {
+                Annotation::Unique if nulls_not_distinct => "UNIQUE NULLS NOT DISTINCT".to_string(),
+                Annotation::DefaultValue(default) if matches!(self.data_type, DbType::Interval) => {
+                    self.interval_default(default)?
+                }
+                Annotation::DefaultValue(default) if matches!(self.data_type, DbType::Boolean) => {
+                    self.boolean_default(default)?
+                }
+                Annotation::DefaultValue(DefaultValue::Boolean(b)) => {
+                    return Err(anyhow!(
+                    "Column {} has the default {}, but boolean defaults require the type boolean",
+                    self.name,
+                    b
+                ))
+                }
+                _ => annotation
+                    .build(self.dialect)
+                    .with_context(|| format!("Error while building column {}", self.name))?,
+            };
+            if !a.is_empty() {
+                annotations.push(a);
+            }
+
+            // If annotation requires a trigger, create those
+            trigger.extend(trigger_annotation_to_trigger(
+                self.dialect,
+                &annotation.annotation,
+                self.table_name.as_str(),
+                self.name.as_str(),
+            )?)
+        }
+
+        Ok((
+            format!(
+                "{} {}{}",
+                self.name,
+                db_type,
+                if !annotations.is_empty() {
+                    format!(" {}", annotations.join(" "))
+                } else {
+                    annotations.join(" ")
+                }
+            ),
+            trigger,
+        ))
     }
 }
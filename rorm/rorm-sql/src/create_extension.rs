@@ -0,0 +1,45 @@
+use anyhow::anyhow;
+
+use crate::DBImpl;
+
+/**
+Representation of a create extension operation, only supported by postgres
+*/
+pub struct SQLCreateExtension {
+    pub(crate) dialect: DBImpl,
+    pub(crate) name: String,
+    pub(crate) if_not_exists: bool,
+}
+
+impl SQLCreateExtension {
+    /**
+    Creates the extension only, if it doesn't exist yet.
+    */
+    pub fn if_not_exists(mut self) -> Self {
+        self.if_not_exists = true;
+        self
+    }
+
+    /**
+    This method is used to build the create extension operation
+    */
+    pub fn build(self) -> anyhow::Result<String> {
+        match self.dialect {
+            // The name is quoted, as names like uuid-ossp are no valid identifiers
+            DBImpl::Postgres => Ok(format!(
+                "CREATE EXTENSION {}\"{}\";",
+                if self.if_not_exists {
+                    "IF NOT EXISTS "
+                } else {
+                    ""
+                },
+                self.name
+            )),
+            DBImpl::SQLite | DBImpl::MySQL => Err(anyhow!(
+                "Extension {} can't be created, {:?} doesn't support extensions",
+                self.name,
+                self.dialect
+            )),
+        }
+    }
+}
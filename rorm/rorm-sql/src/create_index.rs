@@ -1,13 +1,19 @@
 use anyhow::anyhow;
 
+use crate::DBImpl;
+
 /**
 Representation of a create index operation
 */
 pub struct SQLCreateIndex {
+    pub(crate) dialect: DBImpl,
     pub(crate) name: String,
     pub(crate) table_name: String,
     pub(crate) unique: bool,
     pub(crate) if_not_exists: bool,
+    pub(crate) concurrently: bool,
+    pub(crate) full_text: bool,
+    pub(crate) nulls_not_distinct: bool,
     pub(crate) columns: Vec<String>,
     pub(crate) condition: Option<String>,
 }
@@ -19,7 +25,17 @@ impl SQLCreateIndex {
     */
     pub fn unique(mut self) -> Self {
         self.unique = true;
-        return self;
+        self
+    }
+
+    /**
+    Null values of a unique index collide with each other.
+
+    Only supported by postgres 15 and newer, ignored by other dialects.
+    */
+    pub fn nulls_not_distinct(mut self) -> Self {
+        self.nulls_not_distinct = true;
+        self
     }
 
     /**
@@ -27,15 +43,39 @@ impl SQLCreateIndex {
     */
     pub fn if_not_exists(mut self) -> Self {
         self.if_not_exists = true;
-        return self;
+        self
+    }
+
+    /**
+    Builds the index without locking out writes to the table.
+
+    Only supported by postgres, ignored by other dialects.
+    On postgres the statement can't be executed inside a transaction.
+    */
+    pub fn concurrently(mut self) -> Self {
+        self.concurrently = true;
+        self
+    }
+
+    /**
+    Creates a full-text search index.
+
+    MySQL creates a `FULLTEXT` index, postgres a `GIN` index on the `to_tsvector` of the columns.
+    SQLite only supports full-text search with virtual tables, so the index can't be built.
+    */
+    pub fn full_text(mut self) -> Self {
+        self.full_text = true;
+        self
     }
 
     /**
     Adds a column to the index.
+
+    Anything but a plain column name is treated as an expression, e.g. `lower(name)`.
     */
     pub fn add_column(mut self, column: &str) -> Self {
         self.columns.push(column.to_string());
-        return self;
+        self
     }
 
     /**
@@ -43,35 +83,145 @@ impl SQLCreateIndex {
     */
     pub fn set_condition(mut self, condition: &str) -> Self {
         self.condition = Some(condition.to_string());
-        return self;
+        self
+    }
+
+    /**
+    Returns the key parts of the index, expressions are enclosed in parentheses.
+    */
+    fn key_parts(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|x| {
+                if is_column_name(x) {
+                    x.clone()
+                } else {
+                    format!("({})", x)
+                }
+            })
+            .collect()
+    }
+
+    /**
+    Returns the key parts of a full-text index on postgres.
+
+    The plain columns are combined into a single `tsvector`, so a search matches any of them.
+    Expressions are used as they are, e.g. `to_tsvector('english', title)` to use a language.
+    */
+    fn postgres_full_text_parts(&self) -> Vec<String> {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .filter(|x| is_column_name(x))
+            .map(|x| format!("coalesce({}, '')", x))
+            .collect();
+        let mut parts = vec![];
+        if !columns.is_empty() {
+            parts.push(format!(
+                "to_tsvector('simple', {})",
+                columns.join(" || ' ' || ")
+            ));
+        }
+        parts.extend(
+            self.columns
+                .iter()
+                .filter(|x| !is_column_name(x))
+                .map(|x| format!("({})", x)),
+        );
+        parts
     }
 
     /**
     This method is used to build the create index operation
     */
     pub fn build(self) -> anyhow::Result<String> {
-        if self.columns.len() == 0 {
+        if self.columns.is_empty() {
             return Err(anyhow!(
                 "Couldn't create index on {}: Missing column(s) to create the index on",
                 self.table_name
             ));
         }
+        if self.full_text && self.unique {
+            return Err(anyhow!(
+                "Couldn't create index on {}: Full-text indexes can't be unique",
+                self.table_name
+            ));
+        }
 
-        Ok(format!(
-            "CREATE {} INDEX {} {} ON {} ({}) {};",
-            if self.unique { "UNIQUE" } else { "" },
-            if self.if_not_exists {
-                "IF NOT EXISTS"
-            } else {
-                ""
-            },
-            self.name,
-            self.table_name,
-            self.columns.join(","),
-            match self.condition {
-                None => "".to_string(),
-                Some(s) => s,
+        match self.dialect {
+            DBImpl::SQLite if self.full_text => Err(anyhow!(
+                "Couldn't create index on {}: SQLite doesn't support full-text indexes",
+                self.table_name
+            )),
+            DBImpl::SQLite | DBImpl::Postgres => Ok(format!(
+                "CREATE {}INDEX {}{}{} ON {}{} ({}){}{};",
+                if self.unique { "UNIQUE " } else { "" },
+                if self.concurrently && self.dialect == DBImpl::Postgres {
+                    "CONCURRENTLY "
+                } else {
+                    ""
+                },
+                if self.if_not_exists {
+                    "IF NOT EXISTS "
+                } else {
+                    ""
+                },
+                self.name,
+                self.table_name,
+                if self.full_text { " USING GIN" } else { "" },
+                if self.full_text {
+                    self.postgres_full_text_parts()
+                } else {
+                    self.key_parts()
+                }
+                .join(","),
+                if self.unique && self.nulls_not_distinct && self.dialect == DBImpl::Postgres {
+                    " NULLS NOT DISTINCT"
+                } else {
+                    ""
+                },
+                match self.condition {
+                    None => "".to_string(),
+                    Some(s) => format!(" WHERE {}", s),
+                }
+            )),
+            DBImpl::MySQL => {
+                if self.condition.is_some() {
+                    return Err(anyhow!(
+                        "Couldn't create index on {}: MySQL doesn't support partial indexes",
+                        self.table_name
+                    ));
+                }
+                if self.full_text && self.columns.iter().any(|x| !is_column_name(x)) {
+                    return Err(anyhow!(
+                        "Couldn't create index on {}: MySQL doesn't support expressions in full-text indexes",
+                        self.table_name
+                    ));
+                }
+
+                Ok(format!(
+                    "CREATE {}INDEX {} ON {} ({});",
+                    if self.unique {
+                        "UNIQUE "
+                    } else if self.full_text {
+                        "FULLTEXT "
+                    } else {
+                        ""
+                    },
+                    self.name,
+                    self.table_name,
+                    self.key_parts().join(","),
+                ))
             }
-        ))
+        }
     }
 }
+
+/**
+Checks whether an entry of the columns of an index is a plain column name.
+
+`column`: [&str]: The entry to check.
+*/
+pub fn is_column_name(column: &str) -> bool {
+    !column.is_empty() && column.chars().all(|x| x.is_alphanumeric() || x == '_')
+}
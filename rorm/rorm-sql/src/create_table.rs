@@ -1,12 +1,50 @@
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 
+use crate::create_column::MYSQL_MAX_ROW_SIZE;
+use crate::foreign_key::SQLForeignKey;
+use crate::imr::{PartitionBy, PartitionStrategy, Persistence, StorageParameter};
 use crate::{DBImpl, SQLCreateColumn};
 
+/// Character set of tables created on MySQL, if none is given
+pub const MYSQL_DEFAULT_CHARSET: &str = "utf8mb4";
+
+/// Collation of tables created on MySQL, if neither a character set nor a collation is given
+pub const MYSQL_DEFAULT_COLLATION: &str = "utf8mb4_unicode_ci";
+
+/**
+Applies the defaults to the character set and collation of a mysql table.
+
+The server default may be the 3-byte `utf8`, which can't store e.g. emojis,
+so [MYSQL_DEFAULT_CHARSET] is used instead.
+
+`charset`: [Option<&str>]: The character set of the table.
+`collation`: [Option<&str>]: The collation of the table.
+*/
+pub(crate) fn mysql_charset<'a>(
+    charset: Option<&'a str>,
+    collation: Option<&'a str>,
+) -> (&'a str, Option<&'a str>) {
+    match (charset, collation) {
+        (None, None) => (MYSQL_DEFAULT_CHARSET, Some(MYSQL_DEFAULT_COLLATION)),
+        (charset, collation) => (charset.unwrap_or(MYSQL_DEFAULT_CHARSET), collation),
+    }
+}
+
 pub struct SQLCreateTable {
     pub(crate) dialect: DBImpl,
     pub(crate) name: String,
     pub(crate) columns: Vec<SQLCreateColumn>,
+    pub(crate) foreign_keys: Vec<SQLForeignKey>,
+    pub(crate) checks: Vec<(String, String)>,
     pub(crate) if_not_exists: bool,
+    pub(crate) tablespace: Option<String>,
+    pub(crate) storage_parameters: Vec<StorageParameter>,
+    pub(crate) charset: Option<String>,
+    pub(crate) collation: Option<String>,
+    pub(crate) persistence: Option<Persistence>,
+    pub(crate) partition_by: Option<PartitionBy>,
+    pub(crate) partition_of: Option<(String, String)>,
+    pub(crate) inherits: Vec<String>,
 }
 
 impl SQLCreateTable {
@@ -15,7 +53,28 @@ impl SQLCreateTable {
     */
     pub fn add_column(mut self, column: SQLCreateColumn) -> Self {
         self.columns.push(column);
-        return self;
+        self
+    }
+
+    /**
+    Add a foreign key constraint to the table.
+
+    The referenced table doesn't need to exist yet on sqlite.
+    */
+    pub fn add_foreign_key(mut self, foreign_key: SQLForeignKey) -> Self {
+        self.foreign_keys.push(foreign_key);
+        self
+    }
+
+    /**
+    Add a check constraint to the table.
+
+    `name`: [&str]: Name of the constraint.
+    `expression`: [&str]: The condition rows of the table have to fulfill.
+    */
+    pub fn add_check(mut self, name: &str, expression: &str) -> Self {
+        self.checks.push((name.to_string(), expression.to_string()));
+        self
     }
 
     /**
@@ -23,38 +82,299 @@ impl SQLCreateTable {
     */
     pub fn if_not_exists(mut self) -> Self {
         self.if_not_exists = true;
-        return self;
+        self
+    }
+
+    /**
+    Sets the tablespace to create the table in.
+
+    Only supported by postgres, ignored by other dialects.
+    */
+    pub fn tablespace(mut self, name: &str) -> Self {
+        self.tablespace = Some(name.to_string());
+        self
+    }
+
+    /**
+    Add a storage parameter to the table.
+
+    Only supported by postgres, ignored by other dialects.
+    */
+    pub fn add_storage_parameter(mut self, parameter: StorageParameter) -> Self {
+        self.storage_parameters.push(parameter);
+        self
+    }
+
+    /**
+    Sets the character set of the table, [MYSQL_DEFAULT_CHARSET] is used if not set.
+
+    Only supported by mysql, ignored by other dialects.
+    */
+    pub fn charset(mut self, name: &str) -> Self {
+        self.charset = Some(name.to_string());
+        self
+    }
+
+    /**
+    Sets the collation of the table.
+
+    If not set, the default collation of the character set is used,
+    or [MYSQL_DEFAULT_COLLATION] if the character set isn't set either.
+    Only supported by mysql, ignored by other dialects.
+    */
+    pub fn collation(mut self, name: &str) -> Self {
+        self.collation = Some(name.to_string());
+        self
+    }
+
+    /**
+    Sets how the table is persisted.
+
+    Unlogged tables are only supported by postgres, other dialects create a logged table.
+    */
+    pub fn persistence(mut self, persistence: Persistence) -> Self {
+        self.persistence = Some(persistence);
+        self
+    }
+
+    /**
+    Splits the rows of the table into partitions.
+
+    Only supported by postgres, building the table fails on other dialects.
+    */
+    pub fn partition_by(mut self, partition_by: PartitionBy) -> Self {
+        self.partition_by = Some(partition_by);
+        self
+    }
+
+    /**
+    Creates the table as partition of a partitioned table.
+
+    The columns are inherited from the partitioned table, added columns are ignored.
+    Only supported by postgres, building the table fails on other dialects.
+
+    `parent`: [&str]: Name of the partitioned table.
+    `bound`: [&str]: The rows of the partition, e.g. `FOR VALUES FROM (1) TO (10)` or `DEFAULT`.
+    */
+    pub fn partition_of(mut self, parent: &str, bound: &str) -> Self {
+        self.partition_of = Some((parent.to_string(), bound.to_string()));
+        self
+    }
+
+    /**
+    Adds a parent table, whose columns and check constraints the table inherits.
+
+    Only supported by postgres, building the table fails on other dialects
+    and for partitioned tables and partitions.
+
+    `parent`: [&str]: Name of the parent table.
+    */
+    pub fn inherit(mut self, parent: &str) -> Self {
+        self.inherits.push(parent.to_string());
+        self
+    }
+
+    /**
+    Returns a warning, if options of the table are not supported and ignored by the dialect.
+    */
+    pub fn ignored_options_warning(&self) -> Option<String> {
+        match self.dialect {
+            DBImpl::Postgres => None,
+            DBImpl::SQLite | DBImpl::MySQL => {
+                if self.persistence == Some(Persistence::Unlogged) {
+                    Some(format!(
+                        "Table {} is created as logged table, as {:?} doesn't support unlogged tables",
+                        self.name, self.dialect
+                    ))
+                } else if self.tablespace.is_none() && self.storage_parameters.is_empty() {
+                    None
+                } else {
+                    Some(format!(
+                        "Tablespace and storage parameters of table {} are ignored on {:?}",
+                        self.name, self.dialect
+                    ))
+                }
+            }
+        }
+    }
+
+    /**
+    Returns a warning, if the columns of the table can exceed the maximum row size of the dialect.
+    */
+    pub fn row_size_warning(&self) -> Option<String> {
+        match self.dialect {
+            DBImpl::MySQL => {
+                let size: usize = self.columns.iter().map(|x| x.mysql_row_size()).sum();
+                if size > MYSQL_MAX_ROW_SIZE {
+                    Some(format!(
+                        "The VARCHAR columns of table {} can occupy up to {} bytes, exceeding the MySQL row size limit of {} bytes",
+                        self.name, size, MYSQL_MAX_ROW_SIZE
+                    ))
+                } else {
+                    None
+                }
+            }
+            DBImpl::SQLite | DBImpl::Postgres => None,
+        }
+    }
+
+    /**
+    Returns a warning, if columns of the table have no native representation in the dialect.
+    */
+    pub fn degraded_types_warning(&self) -> Option<String> {
+        let warnings: Vec<String> = self
+            .columns
+            .iter()
+            .filter_map(|x| x.degraded_type_warning())
+            .collect();
+        match warnings.is_empty() {
+            true => None,
+            false => Some(warnings.join(", ")),
+        }
     }
 
     /**
     This method is used to convert the current state for the given dialect in a [String].
     */
     pub fn build(self) -> anyhow::Result<String> {
-        return match self.dialect {
-            DBImpl::SQLite => {
-                let mut columns = vec![];
-                let mut trigger = vec![];
-                for column in self.columns {
-                    let (s, c_trigger) = column.build().with_context(|| {
-                        format!("Error while building CREATE TABLE {}", self.name)
-                    })?;
-                    columns.push(s);
-
-                    trigger.extend(c_trigger);
-                }
+        let (table, trigger) = self.build_with_triggers()?;
+        Ok(format!("{}{}", table, trigger.join(" ")))
+    }
 
-                Ok(format!(
-                    r#"CREATE TABLE{} {} ({}) STRICT;{}"#,
-                    if self.if_not_exists {
-                        " IF NOT EXISTS"
-                    } else {
-                        ""
-                    },
+    /**
+    Converts the current state for the given dialect into the CREATE TABLE statement
+    and the statements creating the triggers of its columns.
+
+    The triggers have to be created after the table, e.g. after it has been filled.
+    */
+    pub fn build_with_triggers(self) -> anyhow::Result<(String, Vec<String>)> {
+        if self.dialect != DBImpl::Postgres
+            && (self.partition_by.is_some() || self.partition_of.is_some())
+        {
+            return Err(anyhow!(
+                "Could not create table {}: {:?} doesn't support partitioned tables",
+                self.name,
+                self.dialect
+            ));
+        }
+
+        if !self.inherits.is_empty() {
+            if self.dialect != DBImpl::Postgres {
+                return Err(anyhow!(
+                    "Could not create table {}: {:?} doesn't support table inheritance",
                     self.name,
-                    columns.join(","),
-                    trigger.join(" "),
-                ))
+                    self.dialect
+                ));
+            }
+            if self.partition_by.is_some() || self.partition_of.is_some() {
+                return Err(anyhow!(
+                    "Could not create table {}: Partitioned tables and partitions can't inherit from other tables",
+                    self.name
+                ));
             }
+        }
+
+        let mut columns = vec![];
+        let mut trigger = vec![];
+        for column in self.columns {
+            let (s, c_trigger) = column
+                .build()
+                .with_context(|| format!("Error while building CREATE TABLE {}", self.name))?;
+            columns.push(s);
+
+            trigger.extend(c_trigger);
+        }
+        for foreign_key in &self.foreign_keys {
+            columns.push(format!(
+                "CONSTRAINT {} {}",
+                foreign_key.name(),
+                foreign_key.build()
+            ));
+        }
+        for (name, expression) in &self.checks {
+            columns.push(format!("CONSTRAINT {} CHECK ({})", name, expression));
+        }
+
+        let mut options = String::new();
+        if !self.inherits.is_empty() {
+            options.push_str(format!(" INHERITS ({})", self.inherits.join(", ")).as_str());
+        }
+        if let Some(partition_by) = &self.partition_by {
+            options.push_str(
+                format!(
+                    " PARTITION BY {} ({})",
+                    match partition_by.strategy {
+                        PartitionStrategy::Range => "RANGE",
+                        PartitionStrategy::List => "LIST",
+                        PartitionStrategy::Hash => "HASH",
+                    },
+                    partition_by.columns.join(", ")
+                )
+                .as_str(),
+            );
+        }
+        if let DBImpl::MySQL = self.dialect {
+            let (charset, collation) =
+                mysql_charset(self.charset.as_deref(), self.collation.as_deref());
+            options.push_str(format!(" DEFAULT CHARSET={}", charset).as_str());
+            if let Some(collation) = collation {
+                options.push_str(format!(" COLLATE={}", collation).as_str());
+            }
+        }
+        if let DBImpl::Postgres = self.dialect {
+            if !self.storage_parameters.is_empty() {
+                options.push_str(
+                    format!(
+                        " WITH ({})",
+                        self.storage_parameters
+                            .iter()
+                            .map(|x| format!("{}={}", x.name, x.value))
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    )
+                    .as_str(),
+                );
+            }
+            if let Some(tablespace) = &self.tablespace {
+                options.push_str(format!(" TABLESPACE {}", tablespace).as_str());
+            }
+        }
+
+        let persistence = match (self.dialect, self.persistence) {
+            (DBImpl::Postgres, Some(Persistence::Unlogged)) => " UNLOGGED",
+            (_, Some(Persistence::Temporary)) => " TEMPORARY",
+            _ => "",
         };
+
+        let if_not_exists = if self.if_not_exists {
+            " IF NOT EXISTS"
+        } else {
+            ""
+        };
+        if let Some((parent, bound)) = &self.partition_of {
+            return Ok((
+                format!(
+                    "CREATE{} TABLE{} {} PARTITION OF {} {}{};",
+                    persistence, if_not_exists, self.name, parent, bound, options
+                ),
+                trigger,
+            ));
+        }
+
+        Ok((
+            format!(
+                r#"CREATE{} TABLE{} {} ({}){}{};"#,
+                persistence,
+                if_not_exists,
+                self.name,
+                columns.join(","),
+                options,
+                match self.dialect {
+                    DBImpl::SQLite => " STRICT",
+                    DBImpl::Postgres | DBImpl::MySQL => "",
+                },
+            ),
+            trigger,
+        ))
     }
 }
@@ -53,63 +53,86 @@ pub(crate) fn trigger_annotation_to_trigger(
 ) -> anyhow::Result<Vec<String>> {
     let mut trigger: Vec<String> = vec![];
     match dialect {
-        DBImpl::SQLite => match annotation {
-            Annotation::AutoUpdateTime => {
+        DBImpl::SQLite => {
+            if let Annotation::AutoUpdateTime = annotation {
                 let update_statement = format!(
                     "UPDATE {} SET {} = CURRENT_TIMESTAMP WHERE id = NEW.id;",
                     table_name, column_name
                 );
 
                 trigger.push(DBImpl::SQLite
-                    .create_trigger(
-                        format!(
-                            "{}_{}_auto_update_time_insert",
-                            table_name, column_name
-                        ).as_str(),
+                .create_trigger(
+                    format!(
+                        "{}_{}_auto_update_time_insert",
+                        table_name, column_name
+                    ).as_str(),
+                    table_name,
+                    Some(SQLCreateTriggerPointInTime::After),
+                    SQLCreateTriggerOperation::Insert,
+                ).if_not_exists()
+                .add_statement(
+                    update_statement.clone(),
+                )
+                .build()
+                .with_context(
+                    || format!(
+                        "Couldn't create insert trigger for auto_update_time annotation on field {} in table {}",
+                        column_name,
+                        table_name,
+                    )
+                )?);
+                trigger.push(
+                DBImpl::SQLite.create_trigger(
+                    format!(
+                        "{}_{}_auto_update_time_update",
                         table_name,
-                        Some(SQLCreateTriggerPointInTime::After),
-                        SQLCreateTriggerOperation::Insert,
-                    ).if_not_exists()
-                    .add_statement(
+                        column_name
+                    ).as_str(),
+                    table_name,
+                    Some(SQLCreateTriggerPointInTime::After),
+                    SQLCreateTriggerOperation::Update { columns: None },
+                )
+                    .if_not_exists().
+                    add_statement(
                         update_statement.clone(),
                     )
                     .build()
                     .with_context(
                         || format!(
-                            "Couldn't create insert trigger for auto_update_time annotation on field {} in table {}",
+                            "Couldn't create update trigger for auto_update_time annotation on field {} in table {}",
                             column_name,
-                            table_name,
+                            table_name
                         )
-                    )?);
+                    )?
+            )
+            }
+        }
+        DBImpl::Postgres => {
+            if let Annotation::AutoUpdateTime = annotation {
                 trigger.push(
-                    DBImpl::SQLite.create_trigger(
-                        format!(
-                            "{}_{}_auto_update_time_update",
+                    DBImpl::Postgres
+                        .create_trigger(
+                            format!("{}_{}_auto_update_time_update", table_name, column_name)
+                                .as_str(),
                             table_name,
-                            column_name
-                        ).as_str(),
-                        table_name,
-                        Some(SQLCreateTriggerPointInTime::After),
-                        SQLCreateTriggerOperation::Update { columns: None },
-                    )
-                        .if_not_exists().
-                        add_statement(
-                            update_statement.clone(),
+                            Some(SQLCreateTriggerPointInTime::Before),
+                            SQLCreateTriggerOperation::Update { columns: None },
                         )
+                        .add_statement(format!("NEW.{} = now();", column_name))
                         .build()
-                        .with_context(
-                            || format!(
+                        .with_context(|| {
+                            format!(
                                 "Couldn't create update trigger for auto_update_time annotation on field {} in table {}",
-                                column_name,
-                                table_name
+                                column_name, table_name
                             )
-                        )?
+                        })?,
                 )
             }
-            _ => {}
-        },
+        }
+        // MySQL handles auto_update_time with an ON UPDATE clause on the column
+        DBImpl::MySQL => {}
     };
-    return Ok(trigger);
+    Ok(trigger)
 }
 
 /**
@@ -131,7 +154,7 @@ impl SQLCreateTrigger {
     */
     pub fn if_not_exists(mut self) -> Self {
         self.if_not_exists = true;
-        return self;
+        self
     }
 
     /**
@@ -139,20 +162,20 @@ impl SQLCreateTrigger {
     */
     pub fn add_statement(mut self, statement: String) -> Self {
         self.statements.push(statement);
-        return self;
+        self
     }
 
     /**
     Generate the resulting SQL string
     */
     pub fn build(self) -> anyhow::Result<String> {
-        return match self.dialect {
+        match self.dialect {
             DBImpl::SQLite => {
-                if self.name == "" {
+                if self.name.is_empty() {
                     return Err(anyhow!("Name of the trigger must not empty"));
                 }
 
-                if self.table_name == "" {
+                if self.table_name.is_empty() {
                     return Err(anyhow!("Name of the table must not be empty"));
                 }
 
@@ -173,6 +196,70 @@ impl SQLCreateTrigger {
                     self.statements.join(" "),
                 ))
             }
-        };
+            DBImpl::Postgres => {
+                if self.name.is_empty() {
+                    return Err(anyhow!("Name of the trigger must not empty"));
+                }
+
+                if self.table_name.is_empty() {
+                    return Err(anyhow!("Name of the table must not be empty"));
+                }
+
+                // Postgres triggers execute a function, so the statements are
+                // wrapped in a dedicated plpgsql function
+                Ok(format!(
+                    "CREATE OR REPLACE FUNCTION {}_procedure() RETURNS TRIGGER AS $$ BEGIN {} RETURN NEW; END; $$ LANGUAGE plpgsql; CREATE {}TRIGGER {} {} {} ON {} FOR EACH ROW EXECUTE PROCEDURE {}_procedure();",
+                    self.name,
+                    self.statements.join(" "),
+                    if self.if_not_exists { "OR REPLACE " } else { "" },
+                    self.name,
+                    match self.point_in_time {
+                        None => "".to_string(),
+                        Some(s) => s.to_string(),
+                    },
+                    self.operation,
+                    self.table_name,
+                    self.name,
+                ))
+            }
+            DBImpl::MySQL => {
+                if self.name.is_empty() {
+                    return Err(anyhow!("Name of the trigger must not empty"));
+                }
+
+                if self.table_name.is_empty() {
+                    return Err(anyhow!("Name of the table must not be empty"));
+                }
+
+                if let SQLCreateTriggerOperation::Update { columns: Some(_) } = self.operation {
+                    return Err(anyhow!(
+                        "MySQL doesn't support column specific update triggers"
+                    ));
+                }
+
+                let point_in_time = match self.point_in_time {
+                    None | Some(SQLCreateTriggerPointInTime::InsteadOf) => {
+                        return Err(anyhow!(
+                            "MySQL triggers must be executed either BEFORE or AFTER"
+                        ))
+                    }
+                    Some(s) => s.to_string(),
+                };
+
+                Ok(format!(
+                    "CREATE TRIGGER {}{} {} {} ON {} FOR EACH ROW BEGIN {} END;",
+                    if self.if_not_exists {
+                        "IF NOT EXISTS "
+                    } else {
+                        ""
+                    },
+                    self.name,
+                    point_in_time,
+                    self.operation,
+                    self.table_name,
+                    self.statements.join(" "),
+                ))
+            }
+        }
     }
 }
@@ -0,0 +1,40 @@
+use anyhow::anyhow;
+
+use crate::DBImpl;
+
+/**
+Representation of a drop extension operation, only supported by postgres
+*/
+pub struct SQLDropExtension {
+    pub(crate) dialect: DBImpl,
+    pub(crate) name: String,
+    pub(crate) if_exists: bool,
+}
+
+impl SQLDropExtension {
+    /**
+    Drops the extension only, if it exists.
+    */
+    pub fn if_exists(mut self) -> Self {
+        self.if_exists = true;
+        self
+    }
+
+    /**
+    This method is used to build the drop extension operation
+    */
+    pub fn build(self) -> anyhow::Result<String> {
+        match self.dialect {
+            DBImpl::Postgres => Ok(format!(
+                "DROP EXTENSION {}\"{}\";",
+                if self.if_exists { "IF EXISTS " } else { "" },
+                self.name
+            )),
+            DBImpl::SQLite | DBImpl::MySQL => Err(anyhow!(
+                "Extension {} can't be dropped, {:?} doesn't support extensions",
+                self.name,
+                self.dialect
+            )),
+        }
+    }
+}
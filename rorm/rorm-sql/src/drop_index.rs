@@ -0,0 +1,36 @@
+use crate::DBImpl;
+
+/**
+Representation of a drop index operation
+*/
+pub struct SQLDropIndex {
+    pub(crate) dialect: DBImpl,
+    pub(crate) name: String,
+    pub(crate) table_name: String,
+    pub(crate) if_exists: bool,
+}
+
+impl SQLDropIndex {
+    /**
+    Drops the index only, if it exists.
+    */
+    pub fn if_exists(mut self) -> Self {
+        self.if_exists = true;
+        self
+    }
+
+    /**
+    This method is used to build the drop index operation
+    */
+    pub fn build(self) -> anyhow::Result<String> {
+        Ok(match self.dialect {
+            DBImpl::SQLite | DBImpl::Postgres => format!(
+                "DROP INDEX {}{};",
+                if self.if_exists { "IF EXISTS " } else { "" },
+                self.name
+            ),
+            // MySQL indexes are scoped to a table and can't be dropped conditionally
+            DBImpl::MySQL => format!("DROP INDEX {} ON {};", self.name, self.table_name),
+        })
+    }
+}
@@ -1,3 +1,5 @@
+use anyhow::anyhow;
+
 use crate::DBImpl;
 
 /**
@@ -7,6 +9,7 @@ pub struct SQLDropTable {
     pub(crate) dialect: DBImpl,
     pub(crate) name: String,
     pub(crate) if_exists: bool,
+    pub(crate) cascade: bool,
 }
 
 impl SQLDropTable {
@@ -15,21 +18,41 @@ impl SQLDropTable {
      */
     pub fn if_exists(mut self) -> Self {
         self.if_exists = true;
-        return self;
+        self
+    }
+
+    /**
+    Drops the objects depending on the table, e.g. foreign keys referencing it.
+
+    Only supported by postgres, mysql accepts the keyword but ignores it.
+    */
+    pub fn cascade(mut self) -> Self {
+        self.cascade = true;
+        self
     }
 
     /**
     This method is used to build the drop table statement.
     */
     pub fn build(self) -> anyhow::Result<String> {
-        return Ok(match self.dialect {
-            DBImpl::SQLite => {
-                format!(
-                    "DROP TABLE {} {};",
-                    self.name.as_str(),
-                    if self.if_exists { "IF EXISTS" } else { "" }
-                )
+        Ok(match self.dialect {
+            DBImpl::SQLite if self.cascade => {
+                return Err(anyhow!(
+                "SQLite can't drop the objects depending on table {}, it doesn't support CASCADE",
+                self.name
+            ))
             }
-        });
+            DBImpl::SQLite | DBImpl::MySQL => format!(
+                "DROP TABLE {}{};",
+                if self.if_exists { "IF EXISTS " } else { "" },
+                self.name.as_str(),
+            ),
+            DBImpl::Postgres => format!(
+                "DROP TABLE {}{}{};",
+                if self.if_exists { "IF EXISTS " } else { "" },
+                self.name.as_str(),
+                if self.cascade { " CASCADE" } else { "" },
+            ),
+        })
     }
 }
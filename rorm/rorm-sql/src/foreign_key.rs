@@ -0,0 +1,81 @@
+use crate::imr::{ForeignKey, ReferentialAction};
+use crate::DBImpl;
+
+/**
+Representation of a foreign key constraint
+*/
+pub struct SQLForeignKey {
+    pub(crate) dialect: DBImpl,
+    pub(crate) name: String,
+    pub(crate) column_name: String,
+    pub(crate) foreign_key: ForeignKey,
+}
+
+impl SQLForeignKey {
+    /**
+    Returns a warning, if options of the constraint are not supported and ignored by the dialect.
+    */
+    pub fn ignored_options_warning(&self) -> Option<String> {
+        match self.dialect {
+            DBImpl::SQLite | DBImpl::Postgres => None,
+            DBImpl::MySQL => {
+                if self.foreign_key.deferrable {
+                    Some(format!(
+                        "MySQL doesn't support deferrable foreign keys, {} is checked immediately",
+                        self.name
+                    ))
+                } else if self.foreign_key.on_delete == ReferentialAction::SetDefault
+                    || self.foreign_key.on_update == ReferentialAction::SetDefault
+                {
+                    Some(format!(
+                        "MySQL rejects SET DEFAULT on InnoDB tables, {} can't be created",
+                        self.name
+                    ))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /**
+    Name of the constraint.
+    */
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /**
+    This method is used to build the definition of the constraint without its name,
+    e.g. `FOREIGN KEY (user) REFERENCES user (id) ON DELETE CASCADE`.
+    */
+    pub fn build(&self) -> String {
+        let deferrable = match self.dialect {
+            DBImpl::SQLite | DBImpl::Postgres if self.foreign_key.deferrable => {
+                if self.foreign_key.initially_deferred {
+                    " DEFERRABLE INITIALLY DEFERRED"
+                } else {
+                    " DEFERRABLE"
+                }
+            }
+            _ => "",
+        };
+
+        let mut actions = String::new();
+        if !self.foreign_key.on_delete.is_default() {
+            actions.push_str(format!(" ON DELETE {}", self.foreign_key.on_delete.sql()).as_str());
+        }
+        if !self.foreign_key.on_update.is_default() {
+            actions.push_str(format!(" ON UPDATE {}", self.foreign_key.on_update.sql()).as_str());
+        }
+
+        format!(
+            "FOREIGN KEY ({}) REFERENCES {} ({}){}{}",
+            self.column_name,
+            self.foreign_key.table_name,
+            self.foreign_key.column_name,
+            actions,
+            deferrable
+        )
+    }
+}
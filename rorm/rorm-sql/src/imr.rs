@@ -2,6 +2,8 @@
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 
+use crate::DBImpl;
+
 /// A collection of all models used in the resulting application
 #[derive(Serialize, Deserialize, Debug, Clone, Hash)]
 #[serde(rename_all = "PascalCase")]
@@ -17,11 +19,83 @@ pub struct Model {
 
     pub fields: Vec<Field>,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<ModelAnnotation>,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_defined_at: Option<Source>,
 }
 
+/// Annotations of a model i.e. options of the database table
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(tag = "Type", content = "Value")]
+#[serde(rename_all = "snake_case")]
+pub enum ModelAnnotation {
+    /// Tablespace to store the table in, only supported by postgres
+    Tablespace(String),
+    /// Storage parameter of the table, only supported by postgres
+    StorageParameter(StorageParameter),
+    /// Character set of the table, only supported by mysql
+    Charset(String),
+    /// Collation of the table, only supported by mysql
+    Collation(String),
+    /// Previous name of the model, only used by make-migrations to detect a rename
+    RenameFrom(String),
+    /// How the table is persisted, logged tables are created if not set
+    Persistence(Persistence),
+    /// How the rows are split into partitions, only supported by postgres
+    PartitionBy(PartitionBy),
+    /// Columns whose combination of values is unique, the order of the columns is
+    /// the order of the unique index
+    UniqueTogether(Vec<String>),
+    /// Columns of an index managed by make-migrations, in index order
+    Index(Vec<String>),
+    /// Parent table the table inherits the columns of, only supported by postgres
+    Inherits(String),
+}
+
+/// How the rows of a partitioned table are split into its partitions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "PascalCase")]
+pub struct PartitionBy {
+    pub strategy: PartitionStrategy,
+    /// The columns the partition of a row is determined by
+    pub columns: Vec<String>,
+}
+
+/// The partitioning strategy of a table
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionStrategy {
+    /// Each partition holds a range of values, e.g. a month
+    Range,
+    /// Each partition holds a list of values
+    List,
+    /// The rows are distributed by the hash of their values
+    Hash,
+}
+
+/// How the rows of a table are persisted
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Persistence {
+    /// Changes are not written to the write-ahead log, only supported by postgres.
+    /// The table is faster, but truncated after a crash and not replicated.
+    Unlogged,
+    /// The table only exists for the current session
+    Temporary,
+}
+
+/// A storage parameter of a table, e.g. `fillfactor = 70`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "PascalCase")]
+pub struct StorageParameter {
+    pub name: String,
+    pub value: String,
+}
+
 /// Model's fields i.e. the table's columns
 #[derive(Serialize, Deserialize, Debug, Clone, Hash)]
 #[serde(rename_all = "PascalCase")]
@@ -74,6 +148,21 @@ pub enum DbType {
     Time,
     Choices,
     Set,
+    /// Stored as UUID on postgres, CHAR(36) on mysql and TEXT on sqlite
+    Uuid,
+    /// Case insensitive text, stored as CITEXT on postgres, VARCHAR with a case insensitive
+    /// collation on mysql and TEXT COLLATE NOCASE on sqlite
+    Citext,
+    /// Duration, stored as INTERVAL on postgres and as BIGINT (mysql) or INTEGER (sqlite)
+    /// holding the number of microseconds on the other dialects
+    Interval,
+    /// Fixed length text of max_length characters, stored as CHAR(n) on postgres and mysql
+    /// and TEXT on sqlite, which neither pads nor limits the values
+    Char,
+    /// Fixed length binary of max_length bytes, stored as BINARY(n) on mysql, BYTEA on postgres
+    /// and BLOB on sqlite, which neither pad nor limit the values
+    #[serde(rename = "binary_fixed")]
+    Binary,
 }
 
 /// The subset of annotations which need to be communicated with the migration tool
@@ -92,6 +181,145 @@ pub enum Annotation {
     NotNull,
     PrimaryKey,
     Unique,
+    /// SQL expression used as default by the database, e.g. `CURRENT_TIMESTAMP`
+    ServerDefault(String),
+    /// The default is only applied by the orm when inserting and never emitted in the DDL
+    ClientDefault,
+    /// The field references a field of another model
+    ForeignKey(ForeignKey),
+    /// The field only exists on the given dialects
+    Only(Vec<Dialect>),
+    /// Previous name of the field, only used by make-migrations to detect a rename
+    RenameFrom(String),
+    /// The column is an identity column on postgres, auto increment on other dialects
+    Identity(Identity),
+    /// A full-text search index is created for the field, ignored on sqlite
+    #[serde(rename = "fulltext")]
+    FullText,
+    /// The field holds sensitive data, e.g. encrypted by the application.
+    /// It's stored as varbinary, which is BYTEA on postgres, and its default is never
+    /// printed by the migration tool.
+    Sensitive,
+    /// Null values collide in the unique constraint of the field, only supported by postgres 15+.
+    /// Requires the unique annotation, null values are distinct by default.
+    NullsNotDistinct,
+    /// A random uuid is generated by the database as default, see [DbType::Uuid]
+    DefaultUuid,
+}
+
+/// How the values of an identity column are generated
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Identity {
+    /// Explicit values are rejected
+    Always,
+    /// Explicit values are allowed, like serial columns
+    ByDefault,
+}
+
+/// A database dialect, used to restrict fields and operations to some dialects
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    SQLite,
+    Postgres,
+    MySQL,
+}
+
+impl std::fmt::Display for Dialect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Dialect::SQLite => "sqlite",
+            Dialect::Postgres => "postgres",
+            Dialect::MySQL => "mysql",
+        })
+    }
+}
+
+impl From<DBImpl> for Dialect {
+    fn from(db_impl: DBImpl) -> Self {
+        match db_impl {
+            DBImpl::SQLite => Dialect::SQLite,
+            DBImpl::Postgres => Dialect::Postgres,
+            DBImpl::MySQL => Dialect::MySQL,
+        }
+    }
+}
+
+/// The field of another model referenced by a foreign key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "PascalCase")]
+pub struct ForeignKey {
+    pub table_name: String,
+
+    pub column_name: String,
+
+    /// The constraint may be checked at the end of the transaction, only supported by postgres and sqlite
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub deferrable: bool,
+
+    /// The constraint is checked at the end of the transaction by default, requires deferrable
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub initially_deferred: bool,
+
+    /// Action executed when the referenced row is deleted
+    #[serde(default)]
+    #[serde(skip_serializing_if = "ReferentialAction::is_default")]
+    pub on_delete: ReferentialAction,
+
+    /// Action executed when the referenced field is updated
+    #[serde(default)]
+    #[serde(skip_serializing_if = "ReferentialAction::is_default")]
+    pub on_update: ReferentialAction,
+}
+
+/// What happens to the referencing rows, if the referenced row is deleted or updated
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferentialAction {
+    /// The change is rejected, checked at the end of the statement or transaction
+    #[default]
+    NoAction,
+    /// The change is rejected immediately
+    Restrict,
+    /// The referencing rows are deleted or updated as well
+    Cascade,
+    /// The referencing field is set to null
+    SetNull,
+    /// The referencing field is set to its default
+    SetDefault,
+}
+
+impl ReferentialAction {
+    /// Whether the action is the default of all dialects
+    pub fn is_default(&self) -> bool {
+        *self == ReferentialAction::NoAction
+    }
+
+    /// The action in its SQL form, e.g. `SET NULL`
+    pub fn sql(&self) -> &'static str {
+        match self {
+            ReferentialAction::NoAction => "NO ACTION",
+            ReferentialAction::Restrict => "RESTRICT",
+            ReferentialAction::Cascade => "CASCADE",
+            ReferentialAction::SetNull => "SET NULL",
+            ReferentialAction::SetDefault => "SET DEFAULT",
+        }
+    }
+
+    /// Parses the SQL form of an action, as reported by the database
+    pub fn from_sql(action: &str) -> Option<Self> {
+        Some(match action.trim().to_uppercase().as_str() {
+            "NO ACTION" => ReferentialAction::NoAction,
+            "RESTRICT" => ReferentialAction::Restrict,
+            "CASCADE" => ReferentialAction::Cascade,
+            "SET NULL" => ReferentialAction::SetNull,
+            "SET DEFAULT" => ReferentialAction::SetDefault,
+            _ => return None,
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Hash)]
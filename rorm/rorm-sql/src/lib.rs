@@ -1,28 +1,45 @@
+use crate::alter_index::{SQLAlterIndex, SQLAlterIndexOperation};
 use crate::alter_table::{SQLAlterTable, SQLAlterTableOperation};
 use crate::create_column::{SQLAnnotation, SQLCreateColumn};
+use crate::create_extension::SQLCreateExtension;
 use crate::create_index::SQLCreateIndex;
 use crate::create_table::SQLCreateTable;
 use crate::create_trigger::{
     SQLCreateTrigger, SQLCreateTriggerOperation, SQLCreateTriggerPointInTime,
 };
+use crate::drop_extension::SQLDropExtension;
+use crate::drop_index::SQLDropIndex;
 use crate::drop_table::SQLDropTable;
-use crate::imr::{Annotation, DbType};
+use crate::foreign_key::SQLForeignKey;
+use crate::imr::{Annotation, DbType, ForeignKey};
+use crate::set_sequence::SQLSetSequence;
 use crate::transaction::SQLTransaction;
+use crate::truncate::SQLTruncate;
 
+pub mod alter_index;
 pub mod alter_table;
 pub mod create_column;
+pub mod create_extension;
 pub mod create_index;
 pub mod create_table;
 pub mod create_trigger;
+pub mod drop_extension;
+pub mod drop_index;
 pub mod drop_table;
+pub mod foreign_key;
 pub mod imr;
+pub mod set_sequence;
 pub mod transaction;
+pub mod truncate;
 
 /**
 The main interface for creating sql strings
 */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum DBImpl {
     SQLite,
+    Postgres,
+    MySQL,
 }
 
 impl DBImpl {
@@ -32,13 +49,21 @@ impl DBImpl {
     `name`: [&str]: Name of the table
     */
     pub fn create_table(&self, name: &str) -> SQLCreateTable {
-        match self {
-            DBImpl::SQLite { .. } => SQLCreateTable {
-                dialect: DBImpl::SQLite,
-                name: name.to_string(),
-                columns: vec![],
-                if_not_exists: false,
-            },
+        SQLCreateTable {
+            dialect: *self,
+            name: name.to_string(),
+            columns: vec![],
+            foreign_keys: vec![],
+            checks: vec![],
+            if_not_exists: false,
+            tablespace: None,
+            storage_parameters: vec![],
+            charset: None,
+            collation: None,
+            persistence: None,
+            partition_by: None,
+            partition_of: None,
+            inherits: vec![],
         }
     }
 
@@ -57,16 +82,14 @@ impl DBImpl {
         point_in_time: Option<SQLCreateTriggerPointInTime>,
         operation: SQLCreateTriggerOperation,
     ) -> SQLCreateTrigger {
-        match self {
-            DBImpl::SQLite => SQLCreateTrigger {
-                dialect: DBImpl::SQLite,
-                name: name.to_string(),
-                table_name: table_name.to_string(),
-                if_not_exists: false,
-                point_in_time,
-                operation,
-                statements: vec![],
-            },
+        SQLCreateTrigger {
+            dialect: *self,
+            name: name.to_string(),
+            table_name: table_name.to_string(),
+            if_not_exists: false,
+            point_in_time,
+            operation,
+            statements: vec![],
         }
     }
 
@@ -75,18 +98,96 @@ impl DBImpl {
 
     `name`: [&str]: Name of the index.
     `table_name`: [&str]: Table to create the index on.
-    ``
     */
     pub fn create_index(&self, name: &str, table_name: &str) -> SQLCreateIndex {
-        match self {
-            DBImpl::SQLite => SQLCreateIndex {
-                name: name.to_string(),
-                table_name: table_name.to_string(),
-                unique: false,
-                if_not_exists: false,
-                columns: vec![],
-                condition: None,
-            },
+        SQLCreateIndex {
+            dialect: *self,
+            name: name.to_string(),
+            table_name: table_name.to_string(),
+            unique: false,
+            if_not_exists: false,
+            concurrently: false,
+            full_text: false,
+            nulls_not_distinct: false,
+            columns: vec![],
+            condition: None,
+        }
+    }
+
+    /**
+    The entry point to alter an index.
+
+    `name`: [&str]: Name of the index.
+    `operation`: [SQLAlterIndexOperation]: The operation to execute.
+    */
+    pub fn alter_index(&self, name: &str, operation: SQLAlterIndexOperation) -> SQLAlterIndex {
+        SQLAlterIndex {
+            dialect: *self,
+            name: name.to_string(),
+            operation,
+            if_exists: false,
+        }
+    }
+
+    /**
+    The entry point to create an extension, only supported by postgres.
+
+    `name`: [&str]: Name of the extension, e.g. `uuid-ossp`.
+    */
+    pub fn create_extension(&self, name: &str) -> SQLCreateExtension {
+        SQLCreateExtension {
+            dialect: *self,
+            name: name.to_string(),
+            if_not_exists: false,
+        }
+    }
+
+    /**
+    The entry point to drop an extension, only supported by postgres.
+
+    `name`: [&str]: Name of the extension.
+    */
+    pub fn drop_extension(&self, name: &str) -> SQLDropExtension {
+        SQLDropExtension {
+            dialect: *self,
+            name: name.to_string(),
+            if_exists: false,
+        }
+    }
+
+    /**
+    The entry point to drop an index.
+
+    `name`: [&str]: Name of the index.
+    `table_name`: [&str]: Table the index was created on.
+    */
+    pub fn drop_index(&self, name: &str, table_name: &str) -> SQLDropIndex {
+        SQLDropIndex {
+            dialect: *self,
+            name: name.to_string(),
+            table_name: table_name.to_string(),
+            if_exists: false,
+        }
+    }
+
+    /**
+    The entry point to define a foreign key constraint.
+
+    `name`: [&str]: Name of the constraint.
+    `column_name`: [&str]: Name of the referencing column.
+    `foreign_key`: [ForeignKey]: The referenced column.
+    */
+    pub fn foreign_key(
+        &self,
+        name: &str,
+        column_name: &str,
+        foreign_key: ForeignKey,
+    ) -> SQLForeignKey {
+        SQLForeignKey {
+            dialect: *self,
+            name: name.to_string(),
+            column_name: column_name.to_string(),
+            foreign_key,
         }
     }
 
@@ -94,11 +195,9 @@ impl DBImpl {
     The entry point to start a transaction
     */
     pub fn start_transaction(&self) -> SQLTransaction {
-        match self {
-            DBImpl::SQLite => SQLTransaction {
-                dialect: DBImpl::SQLite,
-                statements: vec![],
-            },
+        SQLTransaction {
+            dialect: *self,
+            statements: vec![],
         }
     }
 
@@ -108,12 +207,11 @@ impl DBImpl {
     `name`: [&str]: Name of the table to drop.
     */
     pub fn drop_table(&self, name: &str) -> SQLDropTable {
-        match self {
-            DBImpl::SQLite => SQLDropTable {
-                dialect: DBImpl::SQLite,
-                name: name.to_string(),
-                if_exists: false,
-            },
+        SQLDropTable {
+            dialect: *self,
+            name: name.to_string(),
+            if_exists: false,
+            cascade: false,
         }
     }
 
@@ -124,12 +222,41 @@ impl DBImpl {
     `operation`: [crate::alter_table::SQLAlterTableOperation]: The operation to execute.
     */
     pub fn alter_table(&self, name: &str, operation: SQLAlterTableOperation) -> SQLAlterTable {
-        match self {
-            DBImpl::SQLite => SQLAlterTable {
-                dialect: DBImpl::SQLite,
-                name: name.to_string(),
-                operation,
-            },
+        SQLAlterTable {
+            dialect: *self,
+            name: name.to_string(),
+            operation,
+            idempotent: false,
+        }
+    }
+
+    /**
+    The entry point to set the next value of an auto increment column.
+
+    - `table_name`: [&str]: Name of the table.
+    - `column_name`: [&str]: Name of the auto increment column.
+    - `value`: [i64]: Value the next inserted row receives.
+    */
+    pub fn set_sequence(&self, table_name: &str, column_name: &str, value: i64) -> SQLSetSequence {
+        SQLSetSequence {
+            dialect: *self,
+            table_name: table_name.to_string(),
+            column_name: column_name.to_string(),
+            value,
+        }
+    }
+
+    /**
+    The entry point to remove all rows of a table.
+
+    - `table_name`: [&str]: Name of the table.
+    */
+    pub fn truncate(&self, table_name: &str) -> SQLTruncate {
+        SQLTruncate {
+            dialect: *self,
+            table_name: table_name.to_string(),
+            cascade: false,
+            restart_identity: false,
         }
     }
 
@@ -148,25 +275,29 @@ impl DBImpl {
         data_type: DbType,
         annotations: Vec<Annotation>,
     ) -> SQLCreateColumn {
-        match self {
-            DBImpl::SQLite => SQLCreateColumn {
-                dialect: DBImpl::SQLite,
-                name: name.to_string(),
-                table_name: table_name.to_string(),
-                data_type,
-                annotations: annotations
-                    .into_iter()
-                    .map(|x| SQLAnnotation { annotation: x })
-                    .collect(),
-            },
+        SQLCreateColumn {
+            dialect: *self,
+            name: name.to_string(),
+            table_name: table_name.to_string(),
+            data_type,
+            annotations: annotations
+                .into_iter()
+                .map(|x| SQLAnnotation { annotation: x })
+                .collect(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::alter_index::SQLAlterIndexOperation;
+    use crate::alter_table::SQLAlterTableOperation;
+    use crate::create_column::SQLITE_STRICT_TYPES;
+    use crate::imr::{
+        Annotation, DbType, DefaultValue, ForeignKey, Identity, PartitionBy, PartitionStrategy,
+        Persistence, ReferentialAction, StorageParameter,
+    };
     use crate::DBImpl;
-    use rorm_common::imr::{Annotation, DbType};
 
     #[test]
     fn sqlite_01() {
@@ -193,7 +324,7 @@ mod tests {
         assert_eq!(
             DBImpl::SQLite
                 .create_table("test")
-                .add_column("id", DbType::UInt64, vec![])
+                .add_column(DBImpl::SQLite.create_column("test", "id", DbType::UInt64, vec![]))
                 .build()
                 .unwrap(),
             "CREATE TABLE test (id INTEGER) STRICT;".to_string()
@@ -205,7 +336,12 @@ mod tests {
         assert_eq!(
             DBImpl::SQLite
                 .create_table("test")
-                .add_column("id", DbType::UInt64, vec![Annotation::PrimaryKey])
+                .add_column(DBImpl::SQLite.create_column(
+                    "test",
+                    "id",
+                    DbType::UInt64,
+                    vec![Annotation::PrimaryKey],
+                ))
                 .build()
                 .unwrap(),
             "CREATE TABLE test (id INTEGER PRIMARY KEY) STRICT;"
@@ -217,11 +353,1262 @@ mod tests {
         assert_eq!(
             DBImpl::SQLite
                 .create_table("test")
-                .add_column("id", DbType::UInt64, vec![Annotation::PrimaryKey])
-                .add_column("foo", DbType::VarChar, vec![Annotation::NotNull])
+                .add_column(DBImpl::SQLite.create_column(
+                    "test",
+                    "id",
+                    DbType::UInt64,
+                    vec![Annotation::PrimaryKey],
+                ))
+                .add_column(DBImpl::SQLite.create_column(
+                    "test",
+                    "foo",
+                    DbType::VarChar,
+                    vec![Annotation::NotNull],
+                ))
                 .build()
                 .unwrap(),
             "CREATE TABLE test (id INTEGER PRIMARY KEY,foo TEXT NOT NULL) STRICT;"
         )
     }
+
+    #[test]
+    fn postgres_01() {
+        assert_eq!(
+            DBImpl::Postgres
+                .create_table("test")
+                .add_column(DBImpl::Postgres.create_column(
+                    "test",
+                    "id",
+                    DbType::UInt64,
+                    vec![Annotation::PrimaryKey, Annotation::AutoIncrement],
+                ))
+                .add_column(DBImpl::Postgres.create_column(
+                    "test",
+                    "foo",
+                    DbType::VarChar,
+                    vec![Annotation::MaxLength(255), Annotation::NotNull],
+                ))
+                .build()
+                .unwrap(),
+            "CREATE TABLE test (id BIGSERIAL PRIMARY KEY,foo VARCHAR(255) NOT NULL);"
+        )
+    }
+
+    #[test]
+    fn postgres_02() {
+        assert_eq!(
+            DBImpl::Postgres
+                .alter_index(
+                    "test_foo_index",
+                    SQLAlterIndexOperation::RenameTo {
+                        name: "test_bar_index".to_string()
+                    }
+                )
+                .build()
+                .unwrap(),
+            "ALTER INDEX test_foo_index RENAME TO test_bar_index;"
+        )
+    }
+
+    #[test]
+    fn postgres_extension() {
+        assert_eq!(
+            DBImpl::Postgres
+                .create_extension("uuid-ossp")
+                .if_not_exists()
+                .build()
+                .unwrap(),
+            "CREATE EXTENSION IF NOT EXISTS \"uuid-ossp\";"
+        );
+        assert_eq!(
+            DBImpl::Postgres
+                .drop_extension("pg_trgm")
+                .if_exists()
+                .build()
+                .unwrap(),
+            "DROP EXTENSION IF EXISTS \"pg_trgm\";"
+        );
+        assert!(DBImpl::SQLite.create_extension("pg_trgm").build().is_err());
+        assert!(DBImpl::MySQL.drop_extension("pg_trgm").build().is_err());
+    }
+
+    #[test]
+    fn mysql_01() {
+        assert_eq!(
+            DBImpl::MySQL
+                .create_table("test")
+                .add_column(DBImpl::MySQL.create_column(
+                    "test",
+                    "id",
+                    DbType::UInt64,
+                    vec![Annotation::PrimaryKey, Annotation::AutoIncrement],
+                ))
+                .add_column(DBImpl::MySQL.create_column(
+                    "test",
+                    "foo",
+                    DbType::VarChar,
+                    vec![Annotation::MaxLength(255), Annotation::NotNull],
+                ))
+                .build()
+                .unwrap(),
+            "CREATE TABLE test (id BIGINT UNSIGNED PRIMARY KEY AUTO_INCREMENT,foo VARCHAR(255) NOT NULL) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;"
+        )
+    }
+
+    #[test]
+    fn mysql_02() {
+        assert!(DBImpl::MySQL
+            .create_column("test", "foo", DbType::VarChar, vec![])
+            .build()
+            .is_err())
+    }
+
+    #[test]
+    fn mysql_03() {
+        assert_eq!(
+            DBImpl::MySQL
+                .drop_index("test_foo_index", "test")
+                .build()
+                .unwrap(),
+            "DROP INDEX test_foo_index ON test;"
+        )
+    }
+
+    #[test]
+    fn mysql_04() {
+        for (max_length, expected) in [
+            (16383, "foo VARCHAR(16383)"),
+            (16384, "foo TEXT"),
+            (65535, "foo TEXT"),
+            (65536, "foo MEDIUMTEXT"),
+            (16777215, "foo MEDIUMTEXT"),
+            (16777216, "foo LONGTEXT"),
+        ] {
+            assert_eq!(
+                DBImpl::MySQL
+                    .create_column(
+                        "test",
+                        "foo",
+                        DbType::VarChar,
+                        vec![Annotation::MaxLength(max_length)]
+                    )
+                    .build()
+                    .unwrap()
+                    .0,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn mysql_05() {
+        let table = |max_length| {
+            DBImpl::MySQL
+                .create_table("test")
+                .add_column(DBImpl::MySQL.create_column(
+                    "test",
+                    "foo",
+                    DbType::VarChar,
+                    vec![Annotation::MaxLength(max_length)],
+                ))
+                .add_column(DBImpl::MySQL.create_column(
+                    "test",
+                    "bar",
+                    DbType::VarChar,
+                    vec![Annotation::MaxLength(max_length)],
+                ))
+        };
+
+        assert!(table(255).row_size_warning().is_none());
+        assert!(table(8191).row_size_warning().is_none());
+        assert!(table(8192).row_size_warning().is_some());
+        assert!(table(20000).row_size_warning().is_none());
+        assert!(DBImpl::Postgres
+            .create_table("test")
+            .add_column(DBImpl::Postgres.create_column(
+                "test",
+                "foo",
+                DbType::VarChar,
+                vec![Annotation::MaxLength(100000)],
+            ))
+            .row_size_warning()
+            .is_none());
+    }
+
+    #[test]
+    fn set_sequence() {
+        assert_eq!(
+            DBImpl::SQLite.set_sequence("test", "id", 100).build().unwrap(),
+            "DELETE FROM sqlite_sequence WHERE name = 'test'; INSERT INTO sqlite_sequence (name, seq) VALUES ('test', 99);"
+        );
+        assert_eq!(
+            DBImpl::Postgres
+                .set_sequence("test", "id", 100)
+                .build()
+                .unwrap(),
+            "SELECT setval(pg_get_serial_sequence('test', 'id'), 100, false);"
+        );
+        assert_eq!(
+            DBImpl::MySQL
+                .set_sequence("test", "id", 100)
+                .build()
+                .unwrap(),
+            "ALTER TABLE test AUTO_INCREMENT = 100;"
+        );
+        assert!(DBImpl::MySQL.set_sequence("test", "id", 0).build().is_err());
+    }
+
+    #[test]
+    fn postgres_03() {
+        let table = |dialect: DBImpl| {
+            dialect
+                .create_table("test")
+                .add_column(dialect.create_column("test", "id", DbType::Int64, vec![]))
+                .add_storage_parameter(StorageParameter {
+                    name: "fillfactor".to_string(),
+                    value: "70".to_string(),
+                })
+                .tablespace("fast")
+        };
+
+        assert_eq!(
+            table(DBImpl::Postgres).build().unwrap(),
+            "CREATE TABLE test (id BIGINT) WITH (fillfactor=70) TABLESPACE fast;"
+        );
+        assert!(table(DBImpl::Postgres).ignored_options_warning().is_none());
+        assert_eq!(
+            table(DBImpl::MySQL).build().unwrap(),
+            "CREATE TABLE test (id BIGINT) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;"
+        );
+        assert!(table(DBImpl::MySQL).ignored_options_warning().is_some());
+    }
+
+    #[test]
+    fn sqlite_strict_types() {
+        let types = [
+            (DbType::VarChar, "TEXT"),
+            (DbType::VarBinary, "BLOB"),
+            (DbType::Int8, "INTEGER"),
+            (DbType::Int16, "INTEGER"),
+            (DbType::Int32, "INTEGER"),
+            (DbType::Int64, "INTEGER"),
+            (DbType::UInt8, "INTEGER"),
+            (DbType::UInt16, "INTEGER"),
+            (DbType::UInt32, "INTEGER"),
+            (DbType::UInt64, "INTEGER"),
+            (DbType::Float, "REAL"),
+            (DbType::Double, "REAL"),
+            (DbType::Boolean, "INTEGER"),
+            (DbType::Date, "TEXT"),
+            (DbType::Datetime, "TEXT"),
+            (DbType::Timestamp, "TEXT"),
+            (DbType::Time, "TEXT"),
+            (DbType::Choices, "TEXT"),
+            (DbType::Set, "TEXT"),
+        ];
+
+        for (db_type, expected) in types {
+            assert!(SQLITE_STRICT_TYPES.contains(&expected));
+            assert_eq!(
+                DBImpl::SQLite
+                    .create_table("test")
+                    .add_column(DBImpl::SQLite.create_column("test", "a", db_type, vec![]))
+                    .build()
+                    .unwrap(),
+                format!("CREATE TABLE test (a {}) STRICT;", expected)
+            );
+        }
+    }
+
+    #[test]
+    fn mysql_charset() {
+        let table = |dialect: DBImpl| {
+            dialect
+                .create_table("test")
+                .add_column(dialect.create_column("test", "id", DbType::Int64, vec![]))
+        };
+
+        assert_eq!(
+            table(DBImpl::MySQL).charset("latin1").build().unwrap(),
+            "CREATE TABLE test (id BIGINT) DEFAULT CHARSET=latin1;"
+        );
+        assert_eq!(
+            table(DBImpl::MySQL)
+                .collation("utf8mb4_bin")
+                .build()
+                .unwrap(),
+            "CREATE TABLE test (id BIGINT) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;"
+        );
+        assert_eq!(
+            table(DBImpl::Postgres)
+                .charset("latin1")
+                .collation("latin1_bin")
+                .build()
+                .unwrap(),
+            "CREATE TABLE test (id BIGINT);"
+        );
+        assert!(table(DBImpl::Postgres)
+            .charset("latin1")
+            .ignored_options_warning()
+            .is_none());
+
+        assert_eq!(
+            DBImpl::MySQL
+                .alter_table(
+                    "test",
+                    SQLAlterTableOperation::ConvertCharset {
+                        charset: None,
+                        collation: None,
+                    },
+                )
+                .build()
+                .unwrap(),
+            "ALTER TABLE test CONVERT TO CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci;"
+        );
+        assert!(DBImpl::SQLite
+            .alter_table(
+                "test",
+                SQLAlterTableOperation::ConvertCharset {
+                    charset: Some("utf8mb4".to_string()),
+                    collation: None,
+                },
+            )
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn postgres_04() {
+        assert_eq!(
+            DBImpl::Postgres
+                .alter_table(
+                    "test",
+                    SQLAlterTableOperation::SetStorageParameters {
+                        parameters: vec![StorageParameter {
+                            name: "fillfactor".to_string(),
+                            value: "70".to_string(),
+                        }],
+                    },
+                )
+                .build()
+                .unwrap(),
+            "ALTER TABLE test SET (fillfactor=70);"
+        );
+        assert_eq!(
+            DBImpl::Postgres
+                .alter_table(
+                    "test",
+                    SQLAlterTableOperation::ResetStorageParameters {
+                        names: vec!["fillfactor".to_string()],
+                    },
+                )
+                .build()
+                .unwrap(),
+            "ALTER TABLE test RESET (fillfactor);"
+        );
+        assert_eq!(
+            DBImpl::Postgres
+                .alter_table(
+                    "test",
+                    SQLAlterTableOperation::SetTablespace {
+                        name: "fast".to_string(),
+                    },
+                )
+                .build()
+                .unwrap(),
+            "ALTER TABLE test SET TABLESPACE fast;"
+        );
+        assert!(DBImpl::SQLite
+            .alter_table(
+                "test",
+                SQLAlterTableOperation::SetTablespace {
+                    name: "fast".to_string(),
+                },
+            )
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn postgres_05() {
+        let index = |dialect: DBImpl| {
+            dialect
+                .create_index("test_foo_index", "test")
+                .add_column("foo")
+                .concurrently()
+                .build()
+                .unwrap()
+        };
+
+        assert_eq!(
+            index(DBImpl::Postgres),
+            "CREATE INDEX CONCURRENTLY test_foo_index ON test (foo);"
+        );
+        assert_eq!(
+            index(DBImpl::SQLite),
+            "CREATE INDEX test_foo_index ON test (foo);"
+        );
+    }
+
+    #[test]
+    fn full_text_index() {
+        let index = |dialect: DBImpl, columns: &[&str]| {
+            columns
+                .iter()
+                .fold(dialect.create_index("post_search", "post"), |x, y| {
+                    x.add_column(y)
+                })
+                .full_text()
+                .build()
+        };
+
+        assert_eq!(
+            index(DBImpl::MySQL, &["title", "body"]).unwrap(),
+            "CREATE FULLTEXT INDEX post_search ON post (title,body);"
+        );
+        assert_eq!(
+            index(DBImpl::Postgres, &["title", "body"]).unwrap(),
+            "CREATE INDEX post_search ON post USING GIN (to_tsvector('simple', coalesce(title, '') || ' ' || coalesce(body, '')));"
+        );
+        assert_eq!(
+            index(DBImpl::Postgres, &["to_tsvector('english', title)"]).unwrap(),
+            "CREATE INDEX post_search ON post USING GIN ((to_tsvector('english', title)));"
+        );
+        assert!(index(DBImpl::MySQL, &["lower(title)"]).is_err());
+        assert!(index(DBImpl::SQLite, &["title"]).is_err());
+
+        // Expressions of other indexes are enclosed in parentheses
+        assert_eq!(
+            DBImpl::SQLite
+                .create_index("user_name_index", "user")
+                .add_column("lower(name)")
+                .build()
+                .unwrap(),
+            "CREATE INDEX user_name_index ON user ((lower(name)));"
+        );
+    }
+
+    #[test]
+    fn server_and_client_default() {
+        let column = |annotations| {
+            DBImpl::Postgres
+                .create_column("test", "created", DbType::Datetime, annotations)
+                .build()
+        };
+
+        assert_eq!(
+            column(vec![Annotation::ServerDefault(
+                "CURRENT_TIMESTAMP".to_string()
+            )])
+            .unwrap()
+            .0,
+            "created TIMESTAMP DEFAULT CURRENT_TIMESTAMP"
+        );
+        assert_eq!(
+            column(vec![Annotation::ClientDefault, Annotation::NotNull])
+                .unwrap()
+                .0,
+            "created TIMESTAMP NOT NULL"
+        );
+        assert!(column(vec![
+            Annotation::ServerDefault("now()".to_string()),
+            Annotation::DefaultValue(DefaultValue::String("2022-01-01".to_string())),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn uuid_default() {
+        let column = |dialect: DBImpl, db_type| {
+            dialect
+                .create_column(
+                    "session",
+                    "id",
+                    db_type,
+                    vec![Annotation::PrimaryKey, Annotation::DefaultUuid],
+                )
+                .build()
+        };
+
+        assert_eq!(
+            column(DBImpl::Postgres, DbType::Uuid).unwrap().0,
+            "id UUID PRIMARY KEY DEFAULT gen_random_uuid()"
+        );
+        assert_eq!(
+            column(DBImpl::MySQL, DbType::Uuid).unwrap().0,
+            "id CHAR(36) PRIMARY KEY DEFAULT (UUID())"
+        );
+        assert_eq!(
+            column(DBImpl::SQLite, DbType::Uuid).unwrap().0,
+            format!(
+                "id TEXT PRIMARY KEY DEFAULT {}",
+                crate::create_column::SQLITE_RANDOM_UUID
+            )
+        );
+        assert!(column(DBImpl::Postgres, DbType::VarChar).is_err());
+    }
+
+    #[test]
+    fn citext() {
+        let column = |dialect: DBImpl, annotations| {
+            dialect
+                .create_column("user", "email", DbType::Citext, annotations)
+                .build()
+        };
+
+        assert_eq!(
+            column(DBImpl::Postgres, vec![Annotation::NotNull])
+                .unwrap()
+                .0,
+            "email CITEXT NOT NULL"
+        );
+        assert_eq!(
+            column(DBImpl::SQLite, vec![Annotation::NotNull]).unwrap().0,
+            "email TEXT COLLATE NOCASE NOT NULL"
+        );
+        assert_eq!(
+            column(
+                DBImpl::MySQL,
+                vec![Annotation::MaxLength(255), Annotation::NotNull]
+            )
+            .unwrap()
+            .0,
+            "email VARCHAR(255) COLLATE utf8mb4_general_ci NOT NULL"
+        );
+        // Like varchar, a max_length is required on MySQL
+        assert!(column(DBImpl::MySQL, vec![Annotation::NotNull]).is_err());
+    }
+
+    #[test]
+    fn interval() {
+        let column = |dialect: DBImpl, annotations| {
+            dialect.create_column("task", "timeout", DbType::Interval, annotations)
+        };
+        let default = |value| vec![Annotation::DefaultValue(value), Annotation::NotNull];
+
+        assert_eq!(
+            column(DBImpl::Postgres, vec![Annotation::NotNull])
+                .build()
+                .unwrap()
+                .0,
+            "timeout INTERVAL NOT NULL"
+        );
+        assert!(column(DBImpl::Postgres, vec![])
+            .degraded_type_warning()
+            .is_none());
+        assert_eq!(
+            column(
+                DBImpl::Postgres,
+                default(DefaultValue::String("1 day 2 hours".to_string()))
+            )
+            .build()
+            .unwrap()
+            .0,
+            "timeout INTERVAL DEFAULT INTERVAL '1 day 2 hours' NOT NULL"
+        );
+        assert_eq!(
+            column(DBImpl::Postgres, default(DefaultValue::Integer(1500)))
+                .build()
+                .unwrap()
+                .0,
+            "timeout INTERVAL DEFAULT INTERVAL '1500 microseconds' NOT NULL"
+        );
+
+        // MySQL and SQLite store the number of microseconds
+        assert_eq!(
+            column(DBImpl::MySQL, default(DefaultValue::Integer(1500)))
+                .build()
+                .unwrap()
+                .0,
+            "timeout BIGINT DEFAULT 1500 NOT NULL"
+        );
+        assert_eq!(
+            column(DBImpl::SQLite, default(DefaultValue::Integer(1500)))
+                .build()
+                .unwrap()
+                .0,
+            "timeout INTEGER DEFAULT 1500 NOT NULL"
+        );
+        for dialect in [DBImpl::MySQL, DBImpl::SQLite] {
+            assert!(column(dialect, vec![])
+                .degraded_type_warning()
+                .unwrap()
+                .starts_with("Column task.timeout of type interval is stored as integer"));
+            assert!(
+                column(dialect, default(DefaultValue::String("1 day".to_string())))
+                    .build()
+                    .is_err()
+            );
+        }
+        assert!(
+            column(DBImpl::Postgres, default(DefaultValue::Boolean(true)))
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn boolean_default() {
+        let column = |dialect: DBImpl, default| {
+            dialect
+                .create_column(
+                    "user",
+                    "active",
+                    DbType::Boolean,
+                    vec![Annotation::DefaultValue(default), Annotation::NotNull],
+                )
+                .build()
+                .map(|x| x.0)
+        };
+
+        // Postgres has a native boolean, the others store it as integer
+        for (value, postgres, integer) in [(true, "true", "1"), (false, "false", "0")] {
+            assert_eq!(
+                column(DBImpl::Postgres, DefaultValue::Boolean(value)).unwrap(),
+                format!("active BOOLEAN DEFAULT {} NOT NULL", postgres)
+            );
+            assert_eq!(
+                column(DBImpl::MySQL, DefaultValue::Boolean(value)).unwrap(),
+                format!("active BOOL DEFAULT {} NOT NULL", integer)
+            );
+            assert_eq!(
+                column(DBImpl::SQLite, DefaultValue::Boolean(value)).unwrap(),
+                format!("active INTEGER DEFAULT {} NOT NULL", integer)
+            );
+        }
+
+        // Integers and strings are normalized, so they are valid on Postgres as well
+        assert_eq!(
+            column(DBImpl::Postgres, DefaultValue::Integer(1)).unwrap(),
+            "active BOOLEAN DEFAULT true NOT NULL"
+        );
+        assert_eq!(
+            column(DBImpl::Postgres, DefaultValue::String("f".to_string())).unwrap(),
+            "active BOOLEAN DEFAULT false NOT NULL"
+        );
+        assert_eq!(
+            column(DBImpl::MySQL, DefaultValue::String("true".to_string())).unwrap(),
+            "active BOOL DEFAULT 1 NOT NULL"
+        );
+        for dialect in [DBImpl::Postgres, DBImpl::MySQL, DBImpl::SQLite] {
+            assert!(column(dialect, DefaultValue::Integer(2)).is_err());
+            assert!(column(dialect, DefaultValue::String("yes".to_string())).is_err());
+        }
+
+        // Boolean defaults of other types would only be valid on some dialects
+        assert!(DBImpl::MySQL
+            .create_column(
+                "user",
+                "age",
+                DbType::Int32,
+                vec![Annotation::DefaultValue(DefaultValue::Boolean(true))],
+            )
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn fixed_length() {
+        let column = |dialect: DBImpl, db_type, annotations| {
+            dialect
+                .create_column("country", "code", db_type, annotations)
+                .build()
+                .map(|x| x.0)
+        };
+        let length = |l| vec![Annotation::MaxLength(l), Annotation::NotNull];
+
+        assert_eq!(
+            column(DBImpl::Postgres, DbType::Char, length(2)).unwrap(),
+            "code CHAR(2) NOT NULL"
+        );
+        assert_eq!(
+            column(DBImpl::MySQL, DbType::Char, length(2)).unwrap(),
+            "code CHAR(2) NOT NULL"
+        );
+        assert_eq!(
+            column(DBImpl::SQLite, DbType::Char, length(2)).unwrap(),
+            "code TEXT NOT NULL"
+        );
+
+        assert_eq!(
+            column(DBImpl::MySQL, DbType::Binary, length(16)).unwrap(),
+            "code BINARY(16) NOT NULL"
+        );
+        assert_eq!(
+            column(DBImpl::Postgres, DbType::Binary, length(16)).unwrap(),
+            "code BYTEA NOT NULL"
+        );
+        assert_eq!(
+            column(DBImpl::SQLite, DbType::Binary, length(16)).unwrap(),
+            "code BLOB NOT NULL"
+        );
+        assert!(DBImpl::Postgres
+            .create_column("country", "code", DbType::Binary, length(16))
+            .degraded_type_warning()
+            .unwrap()
+            .contains("without a fixed length"));
+
+        assert_eq!(
+            column(DBImpl::MySQL, DbType::VarBinary, length(64)).unwrap(),
+            "code VARBINARY(64) NOT NULL"
+        );
+        assert_eq!(
+            column(DBImpl::MySQL, DbType::VarBinary, vec![]).unwrap(),
+            "code LONGBLOB"
+        );
+        assert_eq!(
+            column(DBImpl::Postgres, DbType::VarBinary, length(64)).unwrap(),
+            "code BYTEA NOT NULL"
+        );
+
+        // The length is required and limited on MySQL
+        assert!(column(DBImpl::Postgres, DbType::Char, vec![]).is_err());
+        assert!(column(DBImpl::MySQL, DbType::Binary, length(0)).is_err());
+        assert!(column(DBImpl::MySQL, DbType::Char, length(256)).is_err());
+    }
+
+    #[test]
+    fn deferrable_foreign_key() {
+        let foreign_key = |dialect: DBImpl| {
+            dialect.foreign_key(
+                "a_b_fkey",
+                "b",
+                ForeignKey {
+                    table_name: "b".to_string(),
+                    column_name: "id".to_string(),
+                    deferrable: true,
+                    initially_deferred: true,
+                    on_delete: ReferentialAction::NoAction,
+                    on_update: ReferentialAction::NoAction,
+                },
+            )
+        };
+
+        assert_eq!(
+            foreign_key(DBImpl::Postgres).build(),
+            "FOREIGN KEY (b) REFERENCES b (id) DEFERRABLE INITIALLY DEFERRED"
+        );
+        assert!(foreign_key(DBImpl::Postgres)
+            .ignored_options_warning()
+            .is_none());
+        assert_eq!(
+            foreign_key(DBImpl::MySQL).build(),
+            "FOREIGN KEY (b) REFERENCES b (id)"
+        );
+        assert!(foreign_key(DBImpl::MySQL)
+            .ignored_options_warning()
+            .is_some());
+
+        assert_eq!(
+            DBImpl::SQLite
+                .create_table("a")
+                .add_column(DBImpl::SQLite.create_column(
+                    "a",
+                    "b",
+                    DbType::Int64,
+                    vec![],
+                ))
+                .add_foreign_key(foreign_key(DBImpl::SQLite))
+                .build()
+                .unwrap(),
+            "CREATE TABLE a (b INTEGER,CONSTRAINT a_b_fkey FOREIGN KEY (b) REFERENCES b (id) DEFERRABLE INITIALLY DEFERRED) STRICT;"
+        );
+    }
+
+    #[test]
+    fn referential_actions() {
+        let foreign_key = |dialect: DBImpl, on_delete: ReferentialAction| {
+            dialect.foreign_key(
+                "a_b_fkey",
+                "b",
+                ForeignKey {
+                    table_name: "b".to_string(),
+                    column_name: "id".to_string(),
+                    deferrable: false,
+                    initially_deferred: false,
+                    on_delete,
+                    on_update: ReferentialAction::Cascade,
+                },
+            )
+        };
+
+        assert_eq!(
+            foreign_key(DBImpl::Postgres, ReferentialAction::SetNull).build(),
+            "FOREIGN KEY (b) REFERENCES b (id) ON DELETE SET NULL ON UPDATE CASCADE"
+        );
+        assert_eq!(
+            foreign_key(DBImpl::SQLite, ReferentialAction::NoAction).build(),
+            "FOREIGN KEY (b) REFERENCES b (id) ON UPDATE CASCADE"
+        );
+        assert!(foreign_key(DBImpl::MySQL, ReferentialAction::SetDefault)
+            .ignored_options_warning()
+            .is_some());
+
+        assert_eq!(
+            ReferentialAction::from_sql("set default"),
+            Some(ReferentialAction::SetDefault)
+        );
+        assert_eq!(ReferentialAction::from_sql("SET"), None);
+    }
+
+    #[test]
+    fn idempotent_alter() {
+        let drop_column = |dialect: DBImpl| {
+            dialect
+                .alter_table(
+                    "user",
+                    SQLAlterTableOperation::DropColumn {
+                        name: "age".to_string(),
+                        cascade: false,
+                    },
+                )
+                .idempotent()
+                .build()
+                .unwrap()
+        };
+
+        assert_eq!(
+            drop_column(DBImpl::Postgres),
+            "ALTER TABLE user DROP COLUMN IF EXISTS age;"
+        );
+        assert_eq!(
+            drop_column(DBImpl::MySQL),
+            "ALTER TABLE user DROP COLUMN age;"
+        );
+        assert_eq!(
+            DBImpl::Postgres
+                .alter_table(
+                    "user",
+                    SQLAlterTableOperation::RenameTo {
+                        name: "member".to_string(),
+                    },
+                )
+                .idempotent()
+                .build()
+                .unwrap(),
+            "ALTER TABLE IF EXISTS user RENAME TO member;"
+        );
+        assert_eq!(
+            DBImpl::Postgres
+                .alter_index(
+                    "user_age_index",
+                    SQLAlterIndexOperation::RenameTo {
+                        name: "user_years_index".to_string(),
+                    },
+                )
+                .if_exists()
+                .build()
+                .unwrap(),
+            "ALTER INDEX IF EXISTS user_age_index RENAME TO user_years_index;"
+        );
+    }
+
+    #[test]
+    fn truncate() {
+        let truncate = |dialect: DBImpl| dialect.truncate("user").cascade().restart_identity();
+
+        assert_eq!(
+            truncate(DBImpl::Postgres).build(),
+            "TRUNCATE TABLE user RESTART IDENTITY CASCADE;"
+        );
+        assert!(truncate(DBImpl::Postgres)
+            .ignored_options_warning()
+            .is_none());
+        assert_eq!(truncate(DBImpl::MySQL).build(), "TRUNCATE TABLE user;");
+        assert!(truncate(DBImpl::MySQL).ignored_options_warning().is_some());
+        assert_eq!(
+            truncate(DBImpl::SQLite).build(),
+            "DELETE FROM user; DELETE FROM sqlite_sequence WHERE name = 'user';"
+        );
+        assert_eq!(DBImpl::SQLite.truncate("user").build(), "DELETE FROM user;");
+    }
+
+    #[test]
+    fn not_valid_constraint() {
+        let add_check = |dialect: DBImpl| {
+            dialect
+                .alter_table(
+                    "user",
+                    SQLAlterTableOperation::AddConstraint {
+                        name: "user_age_check".to_string(),
+                        definition: "CHECK (age >= 0)".to_string(),
+                        not_valid: true,
+                    },
+                )
+                .build()
+        };
+
+        assert_eq!(
+            add_check(DBImpl::Postgres).unwrap(),
+            "ALTER TABLE user ADD CONSTRAINT user_age_check CHECK (age >= 0) NOT VALID;"
+        );
+        assert_eq!(
+            add_check(DBImpl::MySQL).unwrap(),
+            "ALTER TABLE user ADD CONSTRAINT user_age_check CHECK (age >= 0);"
+        );
+        assert!(add_check(DBImpl::SQLite).is_err());
+
+        let validate = |dialect: DBImpl| {
+            dialect
+                .alter_table(
+                    "user",
+                    SQLAlterTableOperation::ValidateConstraint {
+                        name: "user_age_check".to_string(),
+                    },
+                )
+                .build()
+        };
+        assert_eq!(
+            validate(DBImpl::Postgres).unwrap(),
+            "ALTER TABLE user VALIDATE CONSTRAINT user_age_check;"
+        );
+        assert!(validate(DBImpl::MySQL).is_err());
+        assert!(validate(DBImpl::SQLite).is_err());
+    }
+
+    #[test]
+    fn identity() {
+        let create_table = |dialect: DBImpl, identity| {
+            dialect
+                .create_table("user")
+                .add_column(dialect.create_column(
+                    "user",
+                    "id",
+                    DbType::Int64,
+                    vec![Annotation::PrimaryKey, Annotation::Identity(identity)],
+                ))
+                .build()
+        };
+
+        assert_eq!(
+            create_table(DBImpl::Postgres, Identity::Always).unwrap(),
+            "CREATE TABLE user (id BIGINT PRIMARY KEY GENERATED ALWAYS AS IDENTITY);"
+        );
+        assert_eq!(
+            create_table(DBImpl::Postgres, Identity::ByDefault).unwrap(),
+            "CREATE TABLE user (id BIGINT PRIMARY KEY GENERATED BY DEFAULT AS IDENTITY);"
+        );
+        assert_eq!(
+            create_table(DBImpl::SQLite, Identity::Always).unwrap(),
+            "CREATE TABLE user (id INTEGER PRIMARY KEY AUTOINCREMENT) STRICT;"
+        );
+
+        // Serial types and identity columns can't be combined
+        assert!(DBImpl::Postgres
+            .create_table("user")
+            .add_column(DBImpl::Postgres.create_column(
+                "user",
+                "id",
+                DbType::Int64,
+                vec![
+                    Annotation::AutoIncrement,
+                    Annotation::Identity(Identity::Always)
+                ],
+            ))
+            .build()
+            .is_err());
+        assert!(DBImpl::Postgres
+            .create_table("user")
+            .add_column(DBImpl::Postgres.create_column(
+                "user",
+                "name",
+                DbType::VarChar,
+                vec![Annotation::Identity(Identity::Always)],
+            ))
+            .build()
+            .is_err());
+
+        let alter = |operation| DBImpl::Postgres.alter_table("user", operation).build();
+        assert_eq!(
+            alter(SQLAlterTableOperation::AddIdentity {
+                column: "id".to_string(),
+                identity: Identity::ByDefault,
+            })
+            .unwrap(),
+            "ALTER TABLE user ALTER COLUMN id ADD GENERATED BY DEFAULT AS IDENTITY;"
+        );
+        assert_eq!(
+            alter(SQLAlterTableOperation::SetIdentity {
+                column: "id".to_string(),
+                identity: Identity::Always,
+            })
+            .unwrap(),
+            "ALTER TABLE user ALTER COLUMN id SET GENERATED ALWAYS;"
+        );
+        assert_eq!(
+            DBImpl::Postgres
+                .alter_table(
+                    "user",
+                    SQLAlterTableOperation::DropIdentity {
+                        column: "id".to_string(),
+                    }
+                )
+                .idempotent()
+                .build()
+                .unwrap(),
+            "ALTER TABLE user ALTER COLUMN id DROP IDENTITY IF EXISTS;"
+        );
+        assert!(DBImpl::MySQL
+            .alter_table(
+                "user",
+                SQLAlterTableOperation::DropIdentity {
+                    column: "id".to_string(),
+                }
+            )
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn persistence() {
+        let create_table = |dialect: DBImpl, persistence| {
+            dialect
+                .create_table("cache")
+                .add_column(dialect.create_column("cache", "id", DbType::Int64, vec![]))
+                .persistence(persistence)
+        };
+
+        assert_eq!(
+            create_table(DBImpl::Postgres, Persistence::Unlogged)
+                .build()
+                .unwrap(),
+            "CREATE UNLOGGED TABLE cache (id BIGINT);"
+        );
+        assert_eq!(
+            create_table(DBImpl::Postgres, Persistence::Temporary)
+                .if_not_exists()
+                .build()
+                .unwrap(),
+            "CREATE TEMPORARY TABLE IF NOT EXISTS cache (id BIGINT);"
+        );
+        assert!(create_table(DBImpl::MySQL, Persistence::Unlogged)
+            .ignored_options_warning()
+            .is_some());
+        assert_eq!(
+            create_table(DBImpl::SQLite, Persistence::Temporary)
+                .build()
+                .unwrap(),
+            "CREATE TEMPORARY TABLE cache (id INTEGER) STRICT;"
+        );
+    }
+
+    #[test]
+    fn cascade() {
+        let drop_column = |dialect: DBImpl| {
+            dialect
+                .alter_table(
+                    "user",
+                    SQLAlterTableOperation::DropColumn {
+                        name: "id".to_string(),
+                        cascade: true,
+                    },
+                )
+                .build()
+        };
+
+        assert_eq!(
+            DBImpl::Postgres
+                .drop_table("user")
+                .cascade()
+                .build()
+                .unwrap(),
+            "DROP TABLE user CASCADE;"
+        );
+        assert_eq!(
+            DBImpl::MySQL.drop_table("user").cascade().build().unwrap(),
+            "DROP TABLE user;"
+        );
+        assert!(DBImpl::SQLite.drop_table("user").cascade().build().is_err());
+        assert_eq!(
+            drop_column(DBImpl::Postgres).unwrap(),
+            "ALTER TABLE user DROP COLUMN id CASCADE;"
+        );
+        assert_eq!(
+            drop_column(DBImpl::MySQL).unwrap(),
+            "ALTER TABLE user DROP COLUMN id;"
+        );
+        assert!(drop_column(DBImpl::SQLite).is_err());
+    }
+
+    #[test]
+    fn alter_column_type() {
+        let alter_column = |dialect: DBImpl| {
+            dialect
+                .alter_table(
+                    "user",
+                    SQLAlterTableOperation::AlterColumnType {
+                        operation: dialect.create_column(
+                            "user",
+                            "id",
+                            DbType::Int64,
+                            vec![
+                                Annotation::PrimaryKey,
+                                Annotation::AutoIncrement,
+                                Annotation::NotNull,
+                            ],
+                        ),
+                    },
+                )
+                .build()
+        };
+
+        assert_eq!(
+            alter_column(DBImpl::Postgres).unwrap(),
+            "ALTER TABLE user ALTER COLUMN id TYPE BIGINT;"
+        );
+        assert_eq!(
+            alter_column(DBImpl::MySQL).unwrap(),
+            "ALTER TABLE user MODIFY COLUMN id BIGINT AUTO_INCREMENT NOT NULL;"
+        );
+        assert!(alter_column(DBImpl::SQLite).is_err());
+    }
+
+    #[test]
+    fn check_and_triggers() {
+        let create_table = || {
+            DBImpl::SQLite
+                .create_table("user")
+                .add_column(DBImpl::SQLite.create_column(
+                    "user",
+                    "id",
+                    DbType::Int64,
+                    vec![Annotation::PrimaryKey],
+                ))
+                .add_column(DBImpl::SQLite.create_column(
+                    "user",
+                    "updated",
+                    DbType::Datetime,
+                    vec![Annotation::AutoUpdateTime],
+                ))
+                .add_check("user_id_check", "id > 0")
+        };
+
+        let (table, trigger) = create_table().build_with_triggers().unwrap();
+        assert_eq!(
+            table,
+            "CREATE TABLE user (id INTEGER PRIMARY KEY,updated TEXT,CONSTRAINT user_id_check CHECK (id > 0)) STRICT;"
+        );
+        assert_eq!(trigger.len(), 2);
+        assert_eq!(
+            create_table().build().unwrap(),
+            format!("{}{}", table, trigger.join(" "))
+        );
+    }
+
+    #[test]
+    fn inheritance() {
+        let table = |dialect: DBImpl| {
+            dialect
+                .create_table("employee")
+                .add_column(dialect.create_column("employee", "salary", DbType::Int64, vec![]))
+                .inherit("person")
+        };
+        assert_eq!(
+            table(DBImpl::Postgres).build().unwrap(),
+            "CREATE TABLE employee (salary BIGINT) INHERITS (person);"
+        );
+        assert!(table(DBImpl::SQLite).build().is_err());
+        assert!(table(DBImpl::MySQL).build().is_err());
+        // Partitioned tables can't inherit
+        assert!(table(DBImpl::Postgres)
+            .partition_by(PartitionBy {
+                strategy: PartitionStrategy::Hash,
+                columns: vec!["salary".to_string()],
+            })
+            .build()
+            .is_err());
+
+        let alter = |dialect: DBImpl, operation| dialect.alter_table("employee", operation).build();
+        assert_eq!(
+            alter(
+                DBImpl::Postgres,
+                SQLAlterTableOperation::Inherit {
+                    parent: "person".to_string(),
+                }
+            )
+            .unwrap(),
+            "ALTER TABLE employee INHERIT person;"
+        );
+        assert_eq!(
+            alter(
+                DBImpl::Postgres,
+                SQLAlterTableOperation::NoInherit {
+                    parent: "person".to_string(),
+                }
+            )
+            .unwrap(),
+            "ALTER TABLE employee NO INHERIT person;"
+        );
+        assert!(alter(
+            DBImpl::MySQL,
+            SQLAlterTableOperation::Inherit {
+                parent: "person".to_string(),
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn partitions() {
+        let table = |dialect: DBImpl| {
+            dialect
+                .create_table("event")
+                .add_column(dialect.create_column("event", "at", DbType::Datetime, vec![]))
+                .partition_by(PartitionBy {
+                    strategy: PartitionStrategy::Range,
+                    columns: vec!["at".to_string()],
+                })
+                .tablespace("fast")
+        };
+        assert_eq!(
+            table(DBImpl::Postgres).build().unwrap(),
+            "CREATE TABLE event (at TIMESTAMP) PARTITION BY RANGE (at) TABLESPACE fast;"
+        );
+        assert!(table(DBImpl::SQLite).build().is_err());
+        assert!(table(DBImpl::MySQL).build().is_err());
+
+        assert_eq!(
+            DBImpl::Postgres
+                .create_table("event_2024")
+                .partition_of("event", "FOR VALUES FROM ('2024-01-01') TO ('2025-01-01')")
+                .build()
+                .unwrap(),
+            "CREATE TABLE event_2024 PARTITION OF event FOR VALUES FROM ('2024-01-01') TO ('2025-01-01');"
+        );
+        assert!(DBImpl::SQLite
+            .create_table("event_2024")
+            .partition_of("event", "DEFAULT")
+            .build()
+            .is_err());
+
+        let alter = |dialect: DBImpl, operation| dialect.alter_table("event", operation).build();
+        assert_eq!(
+            alter(
+                DBImpl::Postgres,
+                SQLAlterTableOperation::AttachPartition {
+                    name: "event_old".to_string(),
+                    bound: "DEFAULT".to_string(),
+                }
+            )
+            .unwrap(),
+            "ALTER TABLE event ATTACH PARTITION event_old DEFAULT;"
+        );
+        assert_eq!(
+            alter(
+                DBImpl::Postgres,
+                SQLAlterTableOperation::DetachPartition {
+                    name: "event_old".to_string(),
+                }
+            )
+            .unwrap(),
+            "ALTER TABLE event DETACH PARTITION event_old;"
+        );
+        assert!(alter(
+            DBImpl::MySQL,
+            SQLAlterTableOperation::DetachPartition {
+                name: "event_old".to_string(),
+            }
+        )
+        .is_err());
+    }
 }
@@ -0,0 +1,49 @@
+use anyhow::anyhow;
+
+use crate::DBImpl;
+
+/**
+Representation of setting the next value of an auto increment column
+*/
+pub struct SQLSetSequence {
+    pub(crate) dialect: DBImpl,
+    pub(crate) table_name: String,
+    pub(crate) column_name: String,
+    pub(crate) value: i64,
+}
+
+impl SQLSetSequence {
+    /**
+    This method is used to build the set sequence operation
+    */
+    pub fn build(self) -> anyhow::Result<String> {
+        if self.value < 1 {
+            return Err(anyhow!(
+                "Next value of the sequence of {}.{} must be at least 1, got {}",
+                self.table_name,
+                self.column_name,
+                self.value
+            ));
+        }
+
+        Ok(match self.dialect {
+            // sqlite_sequence stores the last used value and does not contain
+            // a row for the table before the first insert
+            DBImpl::SQLite => format!(
+                "DELETE FROM sqlite_sequence WHERE name = '{}'; INSERT INTO sqlite_sequence (name, seq) VALUES ('{}', {});",
+                self.table_name,
+                self.table_name,
+                self.value - 1
+            ),
+            DBImpl::Postgres => format!(
+                "SELECT setval(pg_get_serial_sequence('{}', '{}'), {}, false);",
+                self.table_name, self.column_name, self.value
+            ),
+            // MySQL has a single auto increment counter per table
+            DBImpl::MySQL => format!(
+                "ALTER TABLE {} AUTO_INCREMENT = {};",
+                self.table_name, self.value
+            ),
+        })
+    }
+}
@@ -14,7 +14,7 @@ impl SQLTransaction {
     */
     pub fn add_statement(mut self, statement: String) -> Self {
         self.statements.push(statement);
-        return self;
+        self
     }
 
     /**
@@ -22,9 +22,12 @@ impl SQLTransaction {
     */
     pub fn finish(self) -> anyhow::Result<String> {
         Ok(match self.dialect {
-            DBImpl::SQLite => {
+            DBImpl::SQLite | DBImpl::Postgres => {
                 format!("BEGIN; {} COMMIT;", self.statements.join(" "))
             }
+            DBImpl::MySQL => {
+                format!("START TRANSACTION; {} COMMIT;", self.statements.join(" "))
+            }
         })
     }
 }
@@ -0,0 +1,81 @@
+use crate::DBImpl;
+
+/**
+Representation of removing all rows of a table
+*/
+pub struct SQLTruncate {
+    pub(crate) dialect: DBImpl,
+    pub(crate) table_name: String,
+    pub(crate) cascade: bool,
+    pub(crate) restart_identity: bool,
+}
+
+impl SQLTruncate {
+    /**
+    Also truncates all tables referencing the table.
+
+    Only supported by postgres, ignored by other dialects.
+    */
+    pub fn cascade(mut self) -> Self {
+        self.cascade = true;
+        self
+    }
+
+    /**
+    Resets the auto increment columns of the table.
+
+    MySQL always resets them.
+    */
+    pub fn restart_identity(mut self) -> Self {
+        self.restart_identity = true;
+        self
+    }
+
+    /**
+    Returns a warning, if options of the truncate are not supported and ignored by the dialect.
+    */
+    pub fn ignored_options_warning(&self) -> Option<String> {
+        match self.dialect {
+            DBImpl::Postgres => None,
+            DBImpl::SQLite | DBImpl::MySQL => self.cascade.then(|| {
+                format!(
+                    "{:?} doesn't support truncating table {} with cascade, referencing tables are not truncated",
+                    self.dialect, self.table_name
+                )
+            }),
+        }
+    }
+
+    /**
+    This method is used to build the truncate operation
+    */
+    pub fn build(self) -> String {
+        match self.dialect {
+            // SQLite has no TRUNCATE, rows are deleted instead
+            DBImpl::SQLite => {
+                let mut sql = format!("DELETE FROM {};", self.table_name);
+                if self.restart_identity {
+                    sql.push_str(
+                        format!(
+                            " DELETE FROM sqlite_sequence WHERE name = '{}';",
+                            self.table_name
+                        )
+                        .as_str(),
+                    );
+                }
+                sql
+            }
+            DBImpl::Postgres => format!(
+                "TRUNCATE TABLE {}{}{};",
+                self.table_name,
+                if self.restart_identity {
+                    " RESTART IDENTITY"
+                } else {
+                    ""
+                },
+                if self.cascade { " CASCADE" } else { "" }
+            ),
+            DBImpl::MySQL => format!("TRUNCATE TABLE {};", self.table_name),
+        }
+    }
+}
@@ -7,6 +7,7 @@ use std::io::Write;
 
 pub mod id;
 pub mod model_def;
+pub mod uuid;
 
 /// This trait maps rust types to database types
 pub trait AsDbType {
@@ -49,15 +50,13 @@ impl_as_db_type!(usize, UInt64);
 impl_as_db_type!(f32, Float);
 impl_as_db_type!(f64, Double);
 impl_as_db_type!(bool, Boolean);
+impl_as_db_type!(std::time::Duration, Interval);
 impl AsDbType for String {
     fn as_db_type(annotations: &[imr::Annotation]) -> imr::DbType {
         let mut choices = false;
         for annotation in annotations.iter() {
-            match annotation {
-                imr::Annotation::Choices(_) => {
-                    choices = true;
-                }
-                _ => {}
+            if let imr::Annotation::Choices(_) = annotation {
+                choices = true;
             }
         }
         if choices {
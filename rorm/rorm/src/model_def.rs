@@ -46,6 +46,7 @@ pub trait Model {
 pub struct ModelDefinition {
     pub name: &'static str,
     pub fields: Vec<Field>,
+    pub annotations: Vec<imr::ModelAnnotation>,
     pub source: Option<imr::Source>,
 }
 
@@ -54,6 +55,7 @@ impl From<ModelDefinition> for imr::Model {
         imr::Model {
             name: model.name.to_string(),
             fields: model.fields.into_iter().map(From::from).collect(),
+            annotations: model.annotations,
             source_defined_at: model.source,
         }
     }
@@ -69,7 +71,7 @@ pub struct Field {
 
 impl From<Field> for imr::Field {
     fn from(field: Field) -> Self {
-        let mut annotations: Vec<_> = field.annotations.into_iter().map(From::from).collect();
+        let mut annotations: Vec<_> = field.annotations.into_iter().collect();
         if !field.nullable {
             annotations.push(imr::Annotation::NotNull);
         }
@@ -0,0 +1,37 @@
+//! A wrapper around the textual representation of a uuid
+
+use crate::AsDbType;
+use rorm_sql::imr::{Annotation, DbType};
+use std::ops::Deref;
+
+/// Uuid stored as native uuid type where supported:
+/// ```ignore
+/// use rorm::{Model, uuid::Uuid};
+///
+/// #[derive(Model)]
+/// struct SomeModel {
+///     #[rorm(primary_key, default_uuid)]
+///     id: Uuid,
+///     ..
+/// }
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Uuid(pub String);
+
+impl AsDbType for Uuid {
+    fn as_db_type(_annotations: &[Annotation]) -> DbType {
+        DbType::Uuid
+    }
+}
+
+impl From<String> for Uuid {
+    fn from(uuid: String) -> Self {
+        Uuid(uuid)
+    }
+}
+
+impl Deref for Uuid {
+    type Target = str;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}